@@ -0,0 +1,55 @@
+" gistit.vim - Send buffers and visual selections with gistit
+"
+"   :Gistit                     Send the whole buffer
+"   :'<,'>Gistit                Send the selected lines
+"   :Gistit -d "a description"  Any other flag of `gistit` is passed along
+"
+" The whole buffer is piped to `gistit --stdin-headless` along with the lines to send as
+" `--range N:M`, gistit cuts them itself and answers with a single JSON line. The link is copied to
+" the clipboard register, or the unnamed one without clipboard support.
+"
+" g:gistit_command  gistit binary to run, 'gistit' by default
+" g:gistit_copy     copy the link once sent, 1 by default
+
+if exists('g:loaded_gistit')
+  finish
+endif
+let g:loaded_gistit = 1
+
+command! -range=% -nargs=* Gistit call s:send(<line1>, <line2>, <q-args>)
+
+function! s:send(line1, line2, args) abort
+  let l:cmd = [get(g:, 'gistit_command', 'gistit'), '--stdin-headless',
+        \ '--range', a:line1 . ':' . a:line2]
+  let l:name = expand('%:t')
+  if !empty(l:name)
+    let l:cmd += ['--file-name', l:name]
+  endif
+
+  let l:output = system(join(map(l:cmd, 'shellescape(v:val)')) . ' ' . a:args, getline(1, '$'))
+  " The result is the last line, anything printed before it went to stderr
+  let l:lines = filter(split(l:output, "\n"), 'v:val =~# "^{"')
+  try
+    let l:result = json_decode(l:lines[-1])
+  catch
+    call s:error(trim(l:output))
+    return
+  endtry
+
+  if !l:result.ok
+    call s:error(l:result.error . (has_key(l:result, 'hint') ? ', ' . l:result.hint : ''))
+    return
+  endif
+
+  let l:link = get(l:result, 'url', l:result.hash)
+  if get(g:, 'gistit_copy', 1)
+    call setreg(has('clipboard') ? '+' : '"', l:link)
+  endif
+  echomsg 'gistit: ' . l:link . (l:result.skipped ? ' (sent before)' : '')
+endfunction
+
+function! s:error(message) abort
+  echohl ErrorMsg
+  echomsg 'gistit: ' . a:message
+  echohl None
+endfunction
@@ -0,0 +1,545 @@
+//! The archive module
+//!
+//! `gistit <dir> --archive tar.zst|zip` packs a directory into a single compressed blob, base64
+//! encoded in the only file of the gistit, so the server and peers treat it as one object. A
+//! [`Manifest`] of the packed files is stored first in the archive, fetching lists it and only
+//! extracts what's under the target directory.
+
+use std::fs;
+use std::io::{Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use gistit_proto::payload::Gistit;
+use gistit_proto::Inner;
+
+use crate::file::{lang_from_path, relative_path, Conflict};
+use crate::sanitize::MAX_DATA_SIZE;
+use crate::{warnln, Error, Result};
+
+/// Language of archive gistits, how fetching tells them apart
+pub const ARCHIVE_LANG: &str = "archive";
+
+/// Name of the manifest inside the archive, never extracted
+pub const MANIFEST_NAME: &str = ".gistit-archive.json";
+
+/// Directories that are never packed
+const SKIPPED_DIRS: &[&str] = &[".git", "target", "node_modules"];
+
+pub const ZSTD_LEVEL: i32 = 19;
+
+/// Entries an archive can hold, the manifest and directories included
+pub const MAX_ENTRIES: usize = 4096;
+
+/// Bytes the files of an archive can add up to once decompressed, each one is at most
+/// [`MAX_DATA_SIZE`]
+pub const MAX_UNPACKED_SIZE: usize = 64 * MAX_DATA_SIZE;
+
+/// How a directory is packed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    TarZst,
+    Zip,
+}
+
+impl Format {
+    /// Parses an `--archive` value
+    ///
+    /// # Errors
+    ///
+    /// Fails if it's not 'tar.zst' or 'zip'
+    pub fn parse(format: &str) -> Result<Self> {
+        match format {
+            "tar.zst" => Ok(Self::TarZst),
+            "zip" => Ok(Self::Zip),
+            _ => Err(Error::Argument(
+                "invalid archive format, expected 'tar.zst' or 'zip'",
+                "--archive",
+            )),
+        }
+    }
+
+    /// The format of `gistit`, if it's an archive
+    #[must_use]
+    pub fn detect(gistit: &Gistit) -> Option<Self> {
+        match gistit.inner.as_slice() {
+            [inner] if inner.lang == ARCHIVE_LANG => {
+                if inner.name.ends_with(".tar.zst") {
+                    Some(Self::TarZst)
+                } else if inner.name.ends_with(".zip") {
+                    Some(Self::Zip)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::TarZst => "tar.zst",
+            Self::Zip => "zip",
+        }
+    }
+}
+
+/// The packed files, stored first in the archive
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    pub files: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Relative to the packed directory, '/' separated
+    pub path: String,
+    pub size: u64,
+}
+
+/// Packs the files under `dir_path` into a base64 encoded archive named after the directory.
+/// Hidden files are packed, symlinks and [`SKIPPED_DIRS`] aren't
+///
+/// # Errors
+///
+/// Fails if the directory can't be read, has no files or more than fetching would unpack, see
+/// [`Budget`]
+pub fn pack(dir_path: &Path, format: Format) -> Result<(String, String)> {
+    let mut paths = Vec::new();
+    walk(dir_path, &mut paths)?;
+    paths.sort();
+    if paths.is_empty() {
+        return Err(Error::Argument("directory has no files to pack", "[FILE]"));
+    }
+
+    let mut budget = Budget::new();
+    // The manifest
+    budget.entry()?;
+    let mut files = Vec::with_capacity(paths.len());
+    let mut manifest = Manifest::default();
+    for path in paths {
+        budget.entry()?;
+        let data = budget.read(fs::File::open(&path)?, &path.to_string_lossy())?;
+        let relative = path
+            .strip_prefix(dir_path)
+            .expect("walked paths to be under the directory")
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        manifest.files.push(ManifestEntry {
+            path: relative.clone(),
+            size: data.len() as u64,
+        });
+        files.push((relative, data));
+    }
+    let manifest = serde_json::to_vec_pretty(&manifest)?;
+
+    let bytes = match format {
+        Format::TarZst => pack_tar_zst(&manifest, &files)?,
+        Format::Zip => pack_zip(&manifest, &files)?,
+    };
+    let dir_name = dir_path.canonicalize()?.file_name().map_or_else(
+        || "gistit".to_owned(),
+        |name| name.to_string_lossy().to_string(),
+    );
+
+    Ok((
+        format!("{}.{}", dir_name, format.extension()),
+        base64::encode(bytes),
+    ))
+}
+
+/// Collects the regular files under `dir_path` into `paths`, leaving out [`SKIPPED_DIRS`]
+///
+/// # Errors
+///
+/// Fails if a directory can't be read
+pub fn walk(dir_path: &Path, paths: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir_path)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            if !SKIPPED_DIRS.contains(&entry.file_name().to_string_lossy().as_ref()) {
+                walk(&entry.path(), paths)?;
+            }
+        } else if file_type.is_file() {
+            paths.push(entry.path());
+        }
+    }
+    Ok(())
+}
+
+/// The manifest first, then the files
+fn entries<'a>(
+    manifest: &'a [u8],
+    files: &'a [(String, Vec<u8>)],
+) -> impl Iterator<Item = (&'a str, &'a [u8])> {
+    std::iter::once((MANIFEST_NAME, manifest)).chain(
+        files
+            .iter()
+            .map(|(path, data)| (path.as_str(), data.as_slice())),
+    )
+}
+
+// Headers are fixed, the same tree always packs to the same archive and hash
+fn pack_tar_zst(manifest: &[u8], files: &[(String, Vec<u8>)]) -> Result<Vec<u8>> {
+    let encoder = zstd::stream::Encoder::new(Vec::new(), ZSTD_LEVEL)?;
+    let mut builder = tar::Builder::new(encoder);
+
+    for (path, data) in entries(manifest, files) {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(0);
+        header.set_entry_type(tar::EntryType::Regular);
+        builder.append_data(&mut header, path, data)?;
+    }
+
+    Ok(builder.into_inner()?.finish()?)
+}
+
+fn pack_zip(manifest: &[u8], files: &[(String, Vec<u8>)]) -> Result<Vec<u8>> {
+    let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let options = zip::write::FileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated)
+        .last_modified_time(zip::DateTime::default())
+        .unix_permissions(0o644);
+
+    for (path, data) in entries(manifest, files) {
+        writer.start_file(path, options).map_err(zip_error)?;
+        writer.write_all(data)?;
+    }
+
+    Ok(writer.finish().map_err(zip_error)?.into_inner())
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn zip_error(err: zip::result::ZipError) -> Error {
+    Error::Archive(err.to_string())
+}
+
+/// Decodes the archive carried by `inner`
+///
+/// # Errors
+///
+/// Fails if it isn't base64
+pub fn decode(inner: &Inner) -> Result<Vec<u8>> {
+    base64::decode(&inner.data).map_err(|err| Error::Archive(err.to_string()))
+}
+
+/// Visits every regular file in the archive, the manifest included, until `visit` returns false
+///
+/// # Errors
+///
+/// Fails if the archive can't be read, goes over the [`Budget`] or `visit` fails
+pub fn for_each_file(
+    bytes: &[u8],
+    format: Format,
+    mut visit: impl FnMut(&str, Vec<u8>) -> Result<bool>,
+) -> Result<()> {
+    let mut budget = Budget::new();
+    match format {
+        Format::TarZst => {
+            let decoder = zstd::stream::Decoder::new(bytes)?;
+            let mut archive = tar::Archive::new(decoder);
+            for entry in archive.entries()? {
+                let mut entry = entry?;
+                budget.entry()?;
+                if !entry.header().entry_type().is_file() {
+                    continue;
+                }
+                let path = entry.path()?.to_string_lossy().to_string();
+                let data = budget.read(entry, &path)?;
+                if !visit(&path, data)? {
+                    break;
+                }
+            }
+        }
+        Format::Zip => {
+            let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).map_err(zip_error)?;
+            for i in 0..archive.len() {
+                budget.entry()?;
+                let mut file = archive.by_index(i).map_err(zip_error)?;
+                if !file.is_file() {
+                    continue;
+                }
+                let path = file.name().to_owned();
+                let data = budget.read(file, &path)?;
+                if !visit(&path, data)? {
+                    break;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// What's left of [`MAX_ENTRIES`] and [`MAX_UNPACKED_SIZE`] while packing or unpacking an
+/// archive, so a small one can't decompress to however many files or bytes
+struct Budget {
+    entries: usize,
+    bytes: usize,
+}
+
+impl Budget {
+    const fn new() -> Self {
+        Self {
+            entries: MAX_ENTRIES,
+            bytes: MAX_UNPACKED_SIZE,
+        }
+    }
+
+    /// Counts one more entry
+    fn entry(&mut self) -> Result<()> {
+        self.entries = self.entries.checked_sub(1).ok_or_else(|| {
+            Error::Archive(format!("archive has more than {} entries", MAX_ENTRIES))
+        })?;
+        Ok(())
+    }
+
+    /// Reads one file, failing as soon as it goes over [`MAX_DATA_SIZE`] or the bytes left rather
+    /// than buffering however much it decompresses to
+    fn read(&mut self, file: impl Read, path: &str) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        file.take(MAX_DATA_SIZE.min(self.bytes) as u64 + 1)
+            .read_to_end(&mut data)?;
+        if data.len() > MAX_DATA_SIZE {
+            return Err(Error::Archive(format!(
+                "'{}' is too large for an archive",
+                path
+            )));
+        }
+        self.bytes = self.bytes.checked_sub(data.len()).ok_or_else(|| {
+            Error::Archive(format!(
+                "archive unpacks to more than {} bytes",
+                MAX_UNPACKED_SIZE
+            ))
+        })?;
+        Ok(data)
+    }
+}
+
+/// Reads the manifest stored in the archive
+///
+/// # Errors
+///
+/// Fails if the archive can't be read or has no manifest
+pub fn manifest(bytes: &[u8], format: Format) -> Result<Manifest> {
+    let mut manifest: Option<Manifest> = None;
+    for_each_file(bytes, format, |path, data| {
+        if path == MANIFEST_NAME {
+            manifest = Some(serde_json::from_slice(&data)?);
+        }
+        Ok(manifest.is_none())
+    })?;
+
+    manifest.ok_or_else(|| Error::Archive("archive has no manifest".to_owned()))
+}
+
+/// The text files in the archive, named after their relative paths, to preview them like a gistit
+/// with many files. Binary files are left out
+///
+/// # Errors
+///
+/// Fails if the archive can't be read
+pub fn unpack(bytes: &[u8], format: Format) -> Result<Vec<Inner>> {
+    let mut files = Vec::new();
+    for_each_file(bytes, format, |path, data| {
+        if path != MANIFEST_NAME {
+            if let Ok(data) = String::from_utf8(data) {
+                files.push(Gistit::new_inner(
+                    path.to_owned(),
+                    lang_from_path(Path::new(path)).to_owned(),
+                    u32::try_from(data.len()).unwrap_or(u32::MAX),
+                    data,
+                ));
+            }
+        }
+        Ok(true)
+    })?;
+    Ok(files)
+}
+
+/// Extracts the archive under `dir_path`, only the file named `only` if given, and returns how
+/// many files were written. Entries that would end up outside of `dir_path` are skipped, files
+/// already there are handled by `conflict`
+///
+/// # Errors
+///
+/// Fails if the archive can't be read, a file can't be written or `conflict` fails
+pub fn extract(
+    bytes: &[u8],
+    format: Format,
+    dir_path: &Path,
+    only: Option<&str>,
+    conflict: Conflict,
+) -> Result<usize> {
+    let mut extracted = 0;
+    for_each_file(bytes, format, |path, data| {
+        if path == MANIFEST_NAME || only.map_or(false, |only| only != path) {
+            return Ok(true);
+        }
+
+        let file_path = match relative_path(path) {
+            Some(relative) => dir_path.join(relative),
+            None => {
+                warnln!("skipping '{}', not a relative path", path);
+                return Ok(true);
+            }
+        };
+        let file_path = match conflict.resolve(&file_path)? {
+            Some(file_path) => file_path,
+            None => return Ok(true),
+        };
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&file_path, data)?;
+        extracted += 1;
+        Ok(true)
+    })?;
+    Ok(extracted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::prelude::*;
+
+    #[test]
+    fn archive_pack_and_extract() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        dir.child("src/main.rs").write_str("fn main() {}").unwrap();
+        dir.child("README.md").write_str("# hello").unwrap();
+        dir.child(".git/HEAD").write_str("ref").unwrap();
+
+        for format in [Format::TarZst, Format::Zip] {
+            let (name, data) = pack(dir.path(), format).unwrap();
+            assert!(name.ends_with(format.extension()));
+            assert_eq!(pack(dir.path(), format).unwrap().1, data);
+
+            let inner = Gistit::new_inner(name, ARCHIVE_LANG.to_owned(), 0, data);
+            let gistit = Gistit::new(
+                String::new(),
+                String::new(),
+                None,
+                String::new(),
+                vec![inner.clone()],
+            );
+            assert_eq!(Format::detect(&gistit), Some(format));
+
+            let bytes = decode(&inner).unwrap();
+            let paths: Vec<_> = manifest(&bytes, format)
+                .unwrap()
+                .files
+                .into_iter()
+                .map(|entry| entry.path)
+                .collect();
+            assert_eq!(paths, ["README.md", "src/main.rs"]);
+
+            let files = unpack(&bytes, format).unwrap();
+            assert_eq!(files[1].name, "src/main.rs");
+            assert_eq!(files[1].lang, "rust");
+
+            let out = assert_fs::TempDir::new().unwrap();
+            assert_eq!(
+                extract(&bytes, format, out.path(), None, Conflict::Overwrite).unwrap(),
+                2
+            );
+            out.child("src/main.rs").assert("fn main() {}");
+            out.child(MANIFEST_NAME).assert(predicates::path::missing());
+
+            let out = assert_fs::TempDir::new().unwrap();
+            assert_eq!(
+                extract(
+                    &bytes,
+                    format,
+                    out.path(),
+                    Some("README.md"),
+                    Conflict::Overwrite
+                )
+                .unwrap(),
+                1
+            );
+            out.child("src").assert(predicates::path::missing());
+        }
+    }
+
+    #[test]
+    fn archive_extract_skips_traversal() {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .start_file("../escaped.txt", zip::write::FileOptions::default())
+            .unwrap();
+        writer.write_all(b"nope").unwrap();
+        let bytes = writer.finish().unwrap().into_inner();
+
+        let root = assert_fs::TempDir::new().unwrap();
+        let out = root.child("out");
+        assert_eq!(
+            extract(&bytes, Format::Zip, out.path(), None, Conflict::Overwrite).unwrap(),
+            0
+        );
+        root.child("escaped.txt")
+            .assert(predicates::path::missing());
+    }
+
+    #[test]
+    fn archive_rejects_oversized_entry() {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .start_file("big.txt", zip::write::FileOptions::default())
+            .unwrap();
+        writer.write_all(&vec![b'a'; MAX_DATA_SIZE + 1]).unwrap();
+        let bytes = writer.finish().unwrap().into_inner();
+        assert!(bytes.len() < MAX_DATA_SIZE);
+
+        let mut visited = false;
+        let result = for_each_file(&bytes, Format::Zip, |_, _| {
+            visited = true;
+            Ok(true)
+        });
+        assert!(matches!(result, Err(Error::Archive(_))));
+        assert!(!visited);
+    }
+
+    #[test]
+    fn archive_rejects_too_many_entries() {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for i in 0..=MAX_ENTRIES {
+            writer
+                .start_file(format!("{}.txt", i), zip::write::FileOptions::default())
+                .unwrap();
+        }
+        let bytes = writer.finish().unwrap().into_inner();
+
+        let result = for_each_file(&bytes, Format::Zip, |_, _| Ok(true));
+        assert!(matches!(result, Err(Error::Archive(_))));
+    }
+
+    #[test]
+    fn archive_budget_bounds_total_size() {
+        let mut budget = Budget::new();
+        let file = vec![b'a'; MAX_DATA_SIZE];
+        for _ in 0..MAX_UNPACKED_SIZE / MAX_DATA_SIZE {
+            assert!(budget.read(file.as_slice(), "a.txt").is_ok());
+        }
+        assert!(budget.read(&b"a"[..], "a.txt").is_err());
+    }
+
+    #[test]
+    fn archive_pack_rejects_oversized_file() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        dir.child("big.txt")
+            .write_binary(&vec![b'a'; MAX_DATA_SIZE + 1])
+            .unwrap();
+
+        assert!(matches!(
+            pack(dir.path(), Format::TarZst),
+            Err(Error::Archive(_))
+        ));
+    }
+}
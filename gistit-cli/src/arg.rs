@@ -5,8 +5,6 @@ use clap::{crate_authors, crate_description, crate_version, Arg, ArgGroup, Comma
 #[allow(clippy::too_many_lines)]
 #[must_use]
 pub fn app() -> Command<'static> {
-    let random_name = Box::leak(Box::new(names::Generator::default().next().unwrap()));
-
     Command::new("gistit-cli")
         .version(crate_version!())
         .about(crate_description!())
@@ -15,6 +13,59 @@ pub fn app() -> Command<'static> {
             "Note: `gistit -h` prints a short and concise overview while `gistit --help` gives all \
                  details.",
         )
+        .after_long_help(
+            "EXAMPLES:
+    gistit main.rs -d \"my snippet\" -c    Send a file and copy the hash to the clipboard
+    cat notes.md | gistit                Send from stdin
+    gistit fetch <HASH> --save           Fetch a gistit and save it locally
+    gistit node --start                  Share through the p2p network instead of the server
+
+EXIT STATUS:
+    1    Failure
+    2    Invalid arguments
+    3    Invalid settings
+    4    Gistit not found
+    5    Unauthorized
+    6    Network or server error
+    7    Gistit node not running",
+        )
+        .arg(
+            Arg::new("profile")
+                .long("profile")
+                .help("Use this settings profile")
+                .long_help(
+                    "Use this settings profile, declared as `[profile.<name>]` in the settings file.
+Can also be selected with the `GISTIT_PROFILE` environment variable.",
+                )
+                .takes_value(true)
+                .value_name("name")
+                .global(true),
+        )
+        .arg(
+            Arg::new("verbose")
+                .long("verbose")
+                .short('v')
+                .help("Print what gistit is doing to stderr, `-vv` for more")
+                .long_help(
+                    "Print tracing spans and events to stderr: the command being run, HTTP requests to the
+server and IPC with gistit-daemon. `-vv` goes down to trace events, the HTTP client's included.",
+                )
+                .multiple_occurrences(true)
+                .global(true),
+        )
+        .arg(
+            Arg::new("timeout")
+                .long("timeout")
+                .help("Give up on network operations after this many seconds")
+                .long_help(
+                    "Give up on network operations after this many seconds: requests to the server,
+round trips to gistit-daemon and fetches from the p2p network. A p2p fetch that runs out of time
+keeps the chunks downloaded so far, fetching again resumes it. Waits as long as it takes by default.",
+                )
+                .takes_value(true)
+                .value_name("secs")
+                .global(true),
+        )
         .arg(
             Arg::new("FILE")
                 .help("File to send/upload.")
@@ -38,9 +89,8 @@ pub fn app() -> Command<'static> {
             Arg::new("author")
                 .long("author")
                 .short('a')
-                .help("With author information. Defaults to a random generated name")
+                .help("With author information. Defaults to the settings file or a random generated name")
                 .takes_value(true)
-                .default_value(random_name)
                 .value_hint(ValueHint::Username),
         )
         .arg(
@@ -56,11 +106,332 @@ This is our best efforts at persisting the hash into the system clipboard after
 ",
                 ),
         )
+        .arg(
+            Arg::new("announce")
+                .long("announce")
+                .help("Announce the hosted gistit on this gossipsub topic (p2p only)")
+                .long_help(
+                    "Announce the hosted gistit on this gossipsub topic, e.g. `gistit/rust`.
+Peers running `gistit subscribe <topic>` are notified with it's hash and metadata.
+Can be repeated. Only used when hosting through the p2p network.",
+                )
+                .takes_value(true)
+                .value_name("topic")
+                .multiple_occurrences(true),
+        )
+        .arg(
+            Arg::new("to-peer")
+                .long("to-peer")
+                .help("Push the gistit straight to this peer, bypassing the server")
+                .long_help(
+                    "Push the gistit straight to this peer through the p2p network, bypassing the
+server entirely. The peer id is shown by `gistit daemon status` on the receiving end,
+which gets notified while running `gistit subscribe`.",
+                )
+                .takes_value(true)
+                .value_name("peer id")
+                .conflicts_with("github"),
+        )
+        .arg(
+            Arg::new("mirror")
+                .long("mirror")
+                .help("Send to every one of these targets at once, e.g. 'server,p2p'")
+                .long_help(
+                    "Send to every one of these targets at once, e.g. 'server,p2p', ignoring the
+backend setting. gistit-daemon is started if needed. Each target prints it's own hash, one failing
+doesn't stop the others but gistit still exits with an error.",
+                )
+                .takes_value(true)
+                .value_name("targets")
+                .use_value_delimiter(true)
+                .possible_values(["server", "p2p"])
+                .conflicts_with_all(&["github", "to-peer", "watch"]),
+        )
+        .arg(
+            Arg::new("parent")
+                .long("parent")
+                .takes_value(true)
+                .value_name("hash")
+                .help("Send as a new revision of this gistit, see `gistit history`")
+                .long_help(
+                    "Send as a new revision of this gistit, listed by `gistit history` and fetched with
+`gistit fetch --rev N`. With `--watch` every save is a revision of the previous one.",
+                ),
+        )
+        .arg(
+            Arg::new("force")
+                .long("force")
+                .help("Send it even if the server or gistit-daemon already has it")
+                .long_help(
+                    "Send it even if the server or gistit-daemon already has it. Gistits are hashed over
+their content, author and description, when one with the same hash was sent before it's link is
+printed instead. Private gistits, ones deleted once read, with a lifespan or a parent are always sent.",
+                ),
+        )
+        .arg(
+            Arg::new("archive")
+                .long("archive")
+                .help("Send a directory packed into a single 'tar.zst' or 'zip' archive")
+                .long_help(
+                    "Send a directory packed into a single 'tar.zst' or 'zip' archive, with a manifest of
+it's files. `gistit fetch` lists and previews them, `--save` extracts them. Symlinks and '.git',
+'target' and 'node_modules' directories are left out.",
+                )
+                .takes_value(true)
+                .value_name("format")
+                .possible_values(["tar.zst", "zip"])
+                .requires("FILE")
+                .conflicts_with_all(&["github", "watch"]),
+        )
+        .arg(
+            Arg::new("watch")
+                .long("watch")
+                .short('w')
+                .help("Keep running and send the file again whenever it changes")
+                .long_help(
+                    "Keep running and send the file again whenever it changes, changes within a second
+are sent once. Each change gets a new hash, pair it with `--name` to keep a name pointing to the
+latest one (p2p only).",
+                )
+                .requires("FILE")
+                .conflicts_with_all(&["github", "to-peer"]),
+        )
+        .arg(
+            Arg::new("name")
+                .long("name")
+                .help("While watching, keep this '@user/name' pointing to the latest hash (p2p only)")
+                .takes_value(true)
+                .value_name("@user/name")
+                .requires("watch"),
+        )
+        .arg(
+            Arg::new("manifest")
+                .long("manifest")
+                .help("Send every file listed in this manifest, writing hashes to a lockfile")
+                .long_help(
+                    "Send every file listed in this toml manifest, each `[[gistit]]` entry with it's own
+`file` and optional `description`, `secret`, `lifespan` and `alias`. The whole manifest is validated
+before anything is sent. Hashes and urls are written to a lockfile next to it, `gistits.lock` for
+`gistits.toml`.",
+                )
+                .takes_value(true)
+                .allow_invalid_utf8(true)
+                .value_name("path")
+                .value_hint(ValueHint::FilePath)
+                .conflicts_with_all(&["FILE", "github", "to-peer", "watch", "description", "mirror"]),
+        )
+        .arg(
+            Arg::new("embed")
+                .long("embed")
+                .help("Also print ready to paste 'markdown', 'html' or 'badge' markup")
+                .long_help(
+                    "Also print ready to paste markup to stdout, for READMEs and issue trackers. 'markdown' is
+a fenced code block followed by a link, 'html' an iframe of the web viewer and 'badge' a markdown
+image linking to it. Only used when sending to the server.",
+                )
+                .takes_value(true)
+                .value_name("format")
+                .possible_values(&["markdown", "html", "badge"])
+                .conflicts_with_all(&["to-peer", "watch", "manifest"]),
+        )
+        .arg(
+            Arg::new("private")
+                .long("private")
+                .help("Only fetchable with the owner token, kept in the OS credential store")
+                .long_help(
+                    "Only fetchable with the owner token. The token is generated, printed and kept in the OS
+credential store, where `gistit fetch` picks it up. Share it with `--token` to let others fetch it.
+Only sent to the server.",
+                )
+                .conflicts_with_all(&["to-peer", "manifest", "visibility"]),
+        )
+        .arg(
+            Arg::new("visibility")
+                .long("visibility")
+                .help("Who can fetch the gistit: 'public', 'unlisted' or 'private'")
+                .long_help(
+                    "Who can fetch the gistit: 'public', 'unlisted' or 'private'. Unlisted gistits are fetchable
+by anyone with the hash but never listed. Defaults to the visibility in the settings file.",
+                )
+                .takes_value(true)
+                .value_name("level")
+                .possible_values(&["public", "unlisted", "private"])
+                .conflicts_with_all(&["to-peer", "manifest"]),
+        )
+        .arg(
+            Arg::new("burn-after-read")
+                .long("burn-after-read")
+                .help("Delete the gistit after it's first fetched")
+                .long_help(
+                    "Delete the gistit after it's first fetched, by the server or by gistit-daemon when hosting.
+Fetching asks before destroying it, unless `gistit fetch --yes`.",
+                )
+                .conflicts_with_all(&["to-peer", "watch", "manifest"]),
+        )
+        .arg(
+            Arg::new("max-reads")
+                .long("max-reads")
+                .help("Delete the gistit after it's fetched this many times")
+                .long_help(
+                    "Delete the gistit after it's fetched this many times, 1 to 1000, by the server or by
+gistit-daemon when hosting. `gistit fetch` tells how many fetches are left.",
+                )
+                .takes_value(true)
+                .value_name("n")
+                .conflicts_with_all(&["burn-after-read", "to-peer", "watch", "manifest"]),
+        )
+        .arg(
+            Arg::new("lifespan")
+                .long("lifespan")
+                .help("How long the server keeps the gistit, e.g. '30m', '12h', '7d' or 'never'")
+                .long_help(
+                    "How long the server keeps the gistit, e.g. '30m', '12h', '7d' or 'never'. The bundled server
+keeps them 1m to 7d, 30m by default, self-hosted ones may allow longer or 'never'.",
+                )
+                .takes_value(true)
+                .value_name("duration")
+                .conflicts_with_all(&["to-peer", "manifest"]),
+        )
+        .arg(
+            Arg::new("gen-secret")
+                .long("gen-secret")
+                .help("Encrypt with a generated secret, printed in a link that opens it")
+                .long_help(
+                    "Encrypt with a generated secret, 32 random letters and digits. Besides the usual url a link
+ending in '#key=<secret>' is printed, `gistit fetch <link>` decrypts it with no more flags. The
+secret only travels in the fragment, the server never sees it. Copied with `--clipboard` instead of
+the hash.",
+                )
+                .conflicts_with_all(&["github", "watch", "mirror", "to-peer", "embed", "manifest"]),
+        )
+        .arg(
+            Arg::new("secret")
+                .long("secret")
+                .short('s')
+                .takes_value(true)
+                .help("Encrypt with this secret before sending, only the ciphertext leaves this machine")
+                .long_help(
+                    "Encrypt with this secret before sending, only the ciphertext leaves this machine. The server
+and peers store it as is, decrypting happens after fetching with `gistit fetch --secret-prompt`
+and a wrong secret is told apart from tampered data by it's authentication tag. Ends up in your
+shell history, prefer `--secret-prompt`, `--secret-file` or `--saved-secret`.",
+                )
+                .conflicts_with_all(&["gen-secret", "github", "embed", "manifest"]),
+        )
+        .arg(
+            Arg::new("secret-prompt")
+                .long("secret-prompt")
+                .conflicts_with_all(&["secret", "secret-file", "gen-secret", "github", "embed", "manifest"])
+                .help("Type the secret at a prompt, twice, keeping it out of your shell history"),
+        )
+        .arg(
+            Arg::new("secret-file")
+                .long("secret-file")
+                .takes_value(true)
+                .value_name("path|/dev/fd/N")
+                .allow_invalid_utf8(true)
+                .value_hint(ValueHint::FilePath)
+                .conflicts_with_all(&["secret", "gen-secret", "github", "embed", "manifest"])
+                .help("Read the secret from this file or file descriptor, without the trailing newline"),
+        )
+        .arg(
+            Arg::new("saved-secret")
+                .long("saved-secret")
+                .takes_value(true)
+                .value_name("name")
+                .conflicts_with_all(&["secret", "secret-file", "secret-prompt", "gen-secret", "github", "embed", "manifest"])
+                .help("Use the secret saved with `gistit auth login secret <name>`"),
+        )
+        .arg(
+            Arg::new("encrypt-metadata")
+                .long("encrypt-metadata")
+                .help("Seal the author, description, file name and language with the secret too")
+                .long_help(
+                    "Seal the author, description, file name and language with the secret too, along with the
+content. The server and peers only see an 'encrypted' gistit of some size, `gistit fetch` puts them
+back once decrypted. Needs a secret, `--gen-secret` or one of the `--secret` flags.",
+                )
+                .conflicts_with_all(&["github", "embed", "manifest"]),
+        )
+        .arg(
+            Arg::new("no-workspace")
+                .long("no-workspace")
+                .help("Send as usual, ignoring the workspace in use")
+                .long_help(
+                    "Send as usual, ignoring the workspace in use, settings `workspace` key. Otherwise gistits
+are encrypted with the workspace key, unless another secret is given, and announced on it's topic.",
+                ),
+        )
+        .arg(
+            Arg::new("dry-run")
+                .long("dry-run")
+                .help("Print the payload, hash, size and targets without sending anything")
+                .long_help(
+                    "Print the payload that would be sent as JSON to stdout, with file data cut short, along
+with it's hash, encoded size after encryption and where it would go. Nothing is sent, gistit-daemon
+isn't spawned and GitHub isn't logged in to. Encrypted gistits are salted, their hash changes on a
+real send.",
+                )
+                .conflicts_with_all(&["watch", "manifest", "clipboard"]),
+        )
+        .arg(
+            Arg::new("tmux-pane")
+                .long("tmux-pane")
+                .help("Send the scrollback of the current tmux pane, or of this target")
+                .long_help(
+                    "Send the scrollback of the current tmux pane, or of this target, e.g. '%3' or
+'session:window.pane'. Wrapped lines are joined back and blank lines at the bottom trimmed, only the
+most recent 50KB are kept. Sent as 'console', great for sharing error output while pairing.",
+                )
+                .takes_value(true)
+                .min_values(0)
+                .max_values(1)
+                .value_name("target")
+                .conflicts_with_all(&["FILE", "file-name", "stdin-headless", "watch", "manifest"]),
+        )
+        .arg(
+            Arg::new("file-name")
+                .long("file-name")
+                .help("Name the gistit sent from stdin, it's language is picked by the extension")
+                .takes_value(true)
+                .value_name("name")
+                .conflicts_with_all(&["FILE", "manifest"]),
+        )
+        .arg(
+            Arg::new("stdin-headless")
+                .long("stdin-headless")
+                .help("Machine mode for editor plugins: send stdin, never prompt, print a JSON line")
+                .long_help(
+                    "Machine mode for editor plugins. The content is read from stdin and everything else from
+flags, nothing is ever asked or printed but a single JSON line to stdout once done:
+    {\"ok\":true,\"hash\":\"...\",\"backend\":\"server\",\"url\":\"...\",\"raw\":\"...\",\"expires\":\"...\",\"skipped\":false}
+    {\"ok\":false,\"code\":\"network\",\"error\":\"...\",\"hint\":\"...\",\"exit_code\":6}
+`backend` is 'server' or 'p2p', hosted gistits only have a hash. `skipped` is true when it was sent
+before, see `--force`. The exit status is the same as without it. Pair it with `--file-name` so the
+language is known and `--range` to send a selection.",
+                )
+                .conflicts_with_all(&["FILE", "github", "watch", "mirror", "manifest", "embed", "to-peer"]),
+        )
+        .arg(
+            Arg::new("range")
+                .long("range")
+                .help("Only send these lines of stdin, e.g. '10:20', '10:', ':20' or '10'")
+                .long_help(
+                    "Only send these lines of stdin, e.g. '10:20', '10:', ':20' or '10', 1 based and inclusive.
+Editors pipe the whole buffer along with the lines of the selection, instead of the selection
+itself, so it's cut the same way whatever the line endings. A range past the end of stdin is
+refused rather than sent short.",
+                )
+                .takes_value(true)
+                .value_name("N:M")
+                .requires("stdin-headless"),
+        )
         .arg(
             Arg::new("list-colorschemes")
                 .long("list-colorschemes")
                 .conflicts_with("FILE")
-                .help("List available colorschemes"),
+                .hide(true)
+                .help("List available colorschemes. Superseded by `gistit themes`"),
         )
         .subcommand(
             Command::new("fetch")
@@ -68,10 +439,37 @@ This is our best efforts at persisting the hash into the system clipboard after
                 .about("Fetch a gistit wherever it is")
                 .arg(
                     Arg::new("HASH")
-                        .help("Fetch a gistit via it's hash")
+                        .help("Fetch a gistit via it's hash, url or a name published with `gistit name`")
+                        .long_help(
+                            "Fetch a gistit via it's hash, url or a name published with `gistit name`.
+Prefix the hash with '#' to only ask the server, or with '@' to only look in the p2p network.
+Web and raw urls of gistits, '.../h/<hash>' and '.../raw/<hash>', only ask the server.
+Names, '@user/name', are always resolved in the p2p network.
+The first 7 or more characters of a hash sent or fetched from this machine are enough.
+Links printed by `--gen-secret`, ending in '#key=<secret>', are decrypted with that secret.",
+                        )
                         .takes_value(true)
                         .required(true),
                 )
+                .arg(
+                    Arg::new("prefer")
+                        .long("prefer")
+                        .takes_value(true)
+                        .value_name("source")
+                        .possible_values(&["p2p", "server", "auto"])
+                        .help("Where to look first, the other source is tried if it's not found")
+                        .long_help(
+                            "Where to look first, the other source is tried if it's not found.
+'p2p' asks gistit-daemon first, 'server' the server first, 'auto' asks gistit-daemon first only if
+it's running. Defaults to the backend in the settings file.",
+                        ),
+                )
+                .arg(
+                    Arg::new("yes")
+                        .long("yes")
+                        .short('y')
+                        .help("Fetch gistits deleted once read without asking"),
+                )
                 .arg(
                     Arg::new("save")
                         .long("save")
@@ -82,6 +480,44 @@ Target directory defaults to 'XDG user directory' on Linux, 'Known Folder' syste
 and 'Standard Directories' on MacOS.",
                         ),
                 )
+                .arg(
+                    Arg::new("force")
+                        .long("force")
+                        .requires("save")
+                        .help("Overwrite files already where the gistit is saved"),
+                )
+                .arg(
+                    Arg::new("rename-on-conflict")
+                        .long("rename-on-conflict")
+                        .requires("save")
+                        .conflicts_with("force")
+                        .help("Save next to files already there, as 'name (1).ext'")
+                        .long_help(
+                            "Save next to files already where the gistit is saved, as 'name (1).ext'.
+Without it or `--force` you're asked what to do with each of them, saving fails when there's no
+terminal to ask in.",
+                        ),
+                )
+                .arg(
+                    Arg::new("rev")
+                        .long("rev")
+                        .takes_value(true)
+                        .value_name("N")
+                        .help("Fetch this revision of the gistit instead, see `gistit history`"),
+                )
+                .arg(
+                    Arg::new("file")
+                        .long("file")
+                        .takes_value(true)
+                        .value_name("name")
+                        .conflicts_with("head")
+                        .help("Only preview, save or print this file of a gistit with many")
+                        .long_help(
+                            "Only preview, save or print this file of a gistit with many, by it's relative path.
+Gistits with many files are previewed one after the other, listed first, and saved restoring their
+directory structure. `--plain` and `--export` handle one file at a time.",
+                        ),
+                )
                 .arg(
                     Arg::new("colorscheme")
                         .long("colorscheme")
@@ -89,10 +525,1096 @@ and 'Standard Directories' on MacOS.",
                         .help("The colorscheme to apply syntax highlighting")
                         .long_help(
                             "The colorscheme to apply syntax highlighting.
-Run `gistit --colorschemes` to list available ones.",
+Defaults to the colorscheme in the settings file.
+Run `gistit themes` to list available ones.",
+                        ),
+                )
+                .arg(
+                    Arg::new("secret")
+                        .long("secret")
+                        .short('s')
+                        .takes_value(true)
+                        .help("Decrypt a gistit hosted with `gistit host --secret`")
+                        .long_help(
+                            "Decrypt a gistit hosted with `gistit host --secret`. Ends up in your shell history,
+prefer `--secret-prompt`, `--secret-file` or the `GISTIT_SECRET` environment variable.",
+                        ),
+                )
+                .arg(
+                    Arg::new("secret-prompt")
+                        .long("secret-prompt")
+                        .conflicts_with_all(&["secret", "secret-file"])
+                        .help("Type the secret at a prompt, keeping it out of your shell history"),
+                )
+                .arg(
+                    Arg::new("secret-file")
+                        .long("secret-file")
+                        .takes_value(true)
+                        .value_name("path|/dev/fd/N")
+                        .allow_invalid_utf8(true)
+                        .value_hint(ValueHint::FilePath)
+                        .conflicts_with("secret")
+                        .help("Read the secret from this file or file descriptor, without the trailing newline"),
+                )
+                .arg(
+                    Arg::new("saved-secret")
+                        .long("saved-secret")
+                        .takes_value(true)
+                        .value_name("name")
+                        .conflicts_with_all(&["secret", "secret-file", "secret-prompt"])
+                        .help("Use the secret saved with `gistit auth login secret <name>`"),
+                )
+                .arg(
+                    Arg::new("token")
+                        .long("token")
+                        .takes_value(true)
+                        .help("Owner token of a private gistit")
+                        .long_help(
+                            "Owner token of a private gistit, only sent to the server.
+Defaults to the token saved when it was sent with `--private` from this machine.",
+                        ),
+                )
+                .arg(
+                    Arg::new("head")
+                        .long("head")
+                        .conflicts_with_all(&["save", "secret", "secret-prompt", "secret-file", "saved-secret"])
+                        .help("Only fetch the metadata, without downloading the content")
+                        .long_help(
+                            "Only fetch the metadata, without downloading the content.
+Prints the name, language, size, author, description and when the server deletes it.",
+                        ),
+                )
+                .arg(
+                    Arg::new("no-pager")
+                        .long("no-pager")
+                        .conflicts_with_all(&["save", "head"])
+                        .help("Print the preview without paging it"),
+                )
+                .arg(
+                    Arg::new("wrap")
+                        .long("wrap")
+                        .takes_value(true)
+                        .value_name("mode")
+                        .possible_values(&["never", "character"])
+                        .conflicts_with_all(&["save", "head", "plain"])
+                        .help("Wrap long lines of the preview at the terminal width, or cut them"),
+                )
+                .arg(
+                    Arg::new("tab-width")
+                        .long("tab-width")
+                        .takes_value(true)
+                        .value_name("N")
+                        .conflicts_with_all(&["save", "head", "plain"])
+                        .help("Expand tabs of the preview to N spaces, 0 leaves them as they are"),
+                )
+                .arg(
+                    Arg::new("no-line-numbers")
+                        .long("no-line-numbers")
+                        .conflicts_with_all(&["save", "head", "plain"])
+                        .help("Preview without line numbers"),
+                )
+                .arg(
+                    Arg::new("no-grid")
+                        .long("no-grid")
+                        .conflicts_with_all(&["save", "head", "plain"])
+                        .help("Preview without the lines around the header and content"),
+                )
+                .arg(
+                    Arg::new("render")
+                        .long("render")
+                        .takes_value(true)
+                        .value_name("mode")
+                        .possible_values(&["auto", "source"])
+                        .conflicts_with_all(&["save", "head", "plain"])
+                        .help("Render markdown files and notebooks, or preview their source"),
+                )
+                .arg(
+                    Arg::new("plain")
+                        .long("plain")
+                        .short('p')
+                        .conflicts_with_all(&["save", "head"])
+                        .help("Print the raw content, without highlighting or decorations")
+                        .long_help(
+                            "Print the raw content to stdout, without highlighting or decorations, for piping
+it elsewhere. Implied when stdout isn't a terminal.",
+                        ),
+                )
+                .arg(
+                    Arg::new("line-range")
+                        .long("line-range")
+                        .short('r')
+                        .takes_value(true)
+                        .value_name("N:M")
+                        .conflicts_with_all(&["save", "head"])
+                        .help("Only print these lines, e.g. '10:20', '10:', ':20' or '10'"),
+                )
+                .arg(
+                    Arg::new("export")
+                        .long("export")
+                        .takes_value(true)
+                        .number_of_values(2)
+                        .value_names(&["format", "path"])
+                        .allow_invalid_utf8(true)
+                        .conflicts_with_all(&["save", "head", "plain", "line-range"])
+                        .help("Render the highlighted preview to a standalone 'html' or 'png' file")
+                        .long_help(
+                            "Render the highlighted preview to a standalone file, e.g. `--export html out.html`,
+using the preview colorscheme. 'png' screenshots it with a headless Chromium and needs gistit built
+with the `png` feature.",
+                        ),
+                )
+                .arg(
+                    Arg::new("comments")
+                        .long("comments")
+                        .conflicts_with_all(&["save", "head", "plain", "export"])
+                        .help("Print the comment thread below the preview, see `gistit comment`"),
+                )
+        )
+        .subcommand(
+            Command::new("verify")
+                .about("Check a gistit's content matches it's hash, without previewing it")
+                .long_about(
+                    "Fetch a gistit and check it's content matches it's hash and the hash asked for,
+without previewing or saving it. Useful after it went through peers or relays you don't trust.
+Encrypted gistits are verified without their secret. Exits with an error if it doesn't match.",
+                )
+                .arg(
+                    Arg::new("HASH")
+                        .help("The gistit hash, url or name, like `gistit fetch`")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("prefer")
+                        .long("prefer")
+                        .takes_value(true)
+                        .value_name("source")
+                        .possible_values(&["p2p", "server", "auto"])
+                        .help("Where to look first, the other source is tried if it's not found"),
+                )
+                .arg(
+                    Arg::new("token")
+                        .long("token")
+                        .takes_value(true)
+                        .help("Owner token of a private gistit"),
+                ),
+        )
+        .subcommand(
+            Command::new("history")
+                .about("List the revisions of a gistit, oldest numbered 1")
+                .long_about(
+                    "List the revisions behind a gistit, following the one it was sent as a revision of
+with `--parent` or `--watch`. Revisions are numbered from the oldest, fetch one with
+`gistit fetch <HASH> --rev N`. Names point to their latest revision.",
+                )
+                .arg(
+                    Arg::new("HASH")
+                        .help("The gistit hash, url or name, like `gistit fetch`")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("prefer")
+                        .long("prefer")
+                        .takes_value(true)
+                        .value_name("source")
+                        .possible_values(&["p2p", "server", "auto"])
+                        .help("Where to look first, like `gistit fetch --prefer`"),
+                )
+                .arg(
+                    Arg::new("token")
+                        .long("token")
+                        .takes_value(true)
+                        .help("Owner token of a private gistit"),
+                ),
+        )
+        .subcommand(
+            Command::new("diff")
+                .about("Show what changed between two gistits, or a gistit and a local file")
+                .long_about(
+                    "Fetch both gistits and print a colorized unified diff, e.g. between revisions sent
+with `--watch`. A side that is an existing file is read from disk. Files of gistits with many, or
+of archives, are paired by name. Colors are left out when piped, so it can go to `git apply`.",
+                )
+                .arg(
+                    Arg::new("OLD")
+                        .help("The gistit hash, url or name, like `gistit fetch`, or a file")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("NEW")
+                        .help("The gistit hash, url or name, like `gistit fetch`, or a file")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("prefer")
+                        .long("prefer")
+                        .takes_value(true)
+                        .value_name("source")
+                        .possible_values(&["p2p", "server", "auto"])
+                        .help("Where to look first, like `gistit fetch --prefer`"),
+                )
+                .arg(
+                    Arg::new("context")
+                        .long("context")
+                        .short('U')
+                        .takes_value(true)
+                        .value_name("lines")
+                        .help("Unchanged lines shown around each change, 3 by default"),
+                ),
+        )
+        .subcommand(
+            Command::new("comment")
+                .about("Comment on a gistit, or list it's comments")
+                .long_about(
+                    "Add a comment to the thread of a gistit, signed with `--author` or the `author`
+setting, and print the thread. Without a message only prints it. Threads are kept by the server,
+gistits only hosted through the p2p network can't be commented on.",
+                )
+                .arg(
+                    Arg::new("HASH")
+                        .help("The gistit hash or url, like `gistit fetch`")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("MESSAGE")
+                        .help("The comment, up to 500 characters")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("author")
+                        .long("author")
+                        .short('a')
+                        .takes_value(true)
+                        .requires("MESSAGE")
+                        .help("With a custom author, defaults to the `author` setting"),
+                )
+                .arg(
+                    Arg::new("token")
+                        .long("token")
+                        .takes_value(true)
+                        .help("Owner token of a private gistit"),
+                ),
+        )
+        .subcommand(
+            Command::new("themes")
+                .about("List, preview and select the colorscheme used to preview gistits")
+                .arg(
+                    Arg::new("COLORSCHEME")
+                        .help("Only list/preview this colorscheme")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("preview")
+                        .long("preview")
+                        .short('p')
+                        .help("Render a sample snippet with each colorscheme"),
+                )
+                .arg(
+                    Arg::new("set")
+                        .long("set")
+                        .requires("COLORSCHEME")
+                        .help("Persist the colorscheme into the settings file")
+                        .long_help(
+                            "Persist the colorscheme into the settings file.
+It will be used by `gistit fetch` whenever `--colorscheme` is not provided.",
                         ),
                 )
         )
+        .subcommand(
+            Command::new("config")
+                .about("Create, edit and validate the settings file")
+                .subcommand_required(true)
+                .arg_required_else_help(true)
+                .subcommand(
+                    Command::new("init")
+                        .about("Write a commented default settings file")
+                        .arg(
+                            Arg::new("force")
+                                .long("force")
+                                .short('f')
+                                .help("Overwrite the settings file if it already exists"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("edit")
+                        .about("Open the settings file with $VISUAL or $EDITOR")
+                )
+                .subcommand(
+                    Command::new("doctor")
+                        .about("Validate settings, project directories and server reachability")
+                )
+        )
+        .subcommand(
+            Command::new("completions")
+                .about("Generate shell completion scripts")
+                .long_about(
+                    "Generate shell completion scripts and print them to stdout.
+Bash, zsh and fish scripts also complete `gistit fetch` with hashes you recently sent or fetched.
+
+Example: gistit completions zsh > ~/.zfunc/_gistit",
+                )
+                .arg(
+                    Arg::new("SHELL")
+                        .help("The shell to generate completions for")
+                        .possible_values(&["bash", "zsh", "fish", "powershell"])
+                        .required_unless_present("hashes")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("hashes")
+                        .long("hashes")
+                        .help("Print hashes from the local cache index, used by completion scripts")
+                        .hide(true),
+                )
+        )
+        .subcommand(
+            Command::new("daemon")
+                .about("Manage the gistit-daemon background process")
+                .long_about(
+                    "Manage the gistit-daemon background process.
+The process id is recorded in a pidfile inside the runtime directory, and the process log is
+written next to it.",
+                )
+                .subcommand_required(true)
+                .arg_required_else_help(true)
+                .subcommand(
+                    Command::new("start")
+                        .about("Spawn the daemon in the background, if not running")
+                        .args(&daemon_listen_args()),
+                )
+                .subcommand(Command::new("stop").about("Gracefully stop the daemon"))
+                .subcommand(
+                    Command::new("status")
+                        .about("Display the status of the running daemon")
+                        .arg(
+                            Arg::new("peers")
+                                .long("peers")
+                                .help("Also list the best scored providers, picked first when fetching"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("doctor")
+                        .about("Check the running daemon can reach and be reached by the network"),
+                )
+                .subcommand(
+                    Command::new("restart")
+                        .about("Stop the daemon, if running, and start it again")
+                        .args(&daemon_listen_args()),
+                )
+                .subcommand(
+                    Command::new("logs")
+                        .about("Print the daemon log")
+                        .arg(
+                            Arg::new("follow")
+                                .long("follow")
+                                .short('f')
+                                .help("Keep printing new log lines as they are written"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("revoke")
+                        .about("Stop hosting a gistit in the p2p network")
+                        .arg(
+                            Arg::new("HASH")
+                                .help("The hash of the hosted gistit")
+                                .takes_value(true)
+                                .required(true),
+                        ),
+                )
+                .subcommand(
+                    Command::new("bootstrap")
+                        .about("Manage the bootstrap peers the daemon dials on startup")
+                        .long_about(
+                            "Manage the bootstrap peers the daemon dials on startup, retrying with backoff
+until they connect. The list is kept in the settings file and replaces the default libp2p
+bootstrap nodes when not empty.",
+                        )
+                        .subcommand_required(true)
+                        .arg_required_else_help(true)
+                        .subcommand(
+                            Command::new("add").about("Add a bootstrap peer").arg(
+                                Arg::new("MULTIADDR")
+                                    .help("The peer address, ending with '/p2p/<peer id>'")
+                                    .takes_value(true)
+                                    .required(true),
+                            ),
+                        )
+                        .subcommand(
+                            Command::new("remove").about("Remove a bootstrap peer").arg(
+                                Arg::new("MULTIADDR")
+                                    .help("The peer address, as listed by `ls`")
+                                    .takes_value(true)
+                                    .required(true),
+                            ),
+                        )
+                        .subcommand(Command::new("ls").about("List the bootstrap peers in effect")),
+                )
+                .subcommand(
+                    Command::new("identity")
+                        .about("Show, rotate, export or import the daemon identity")
+                        .long_about(
+                            "Show, rotate, export or import the daemon identity. The keypair behind the peer id
+is generated on first run and stored encrypted in the config directory, with a key derived from
+`GISTIT_IDENTITY_PASSPHRASE` when set. Exports can be imported on another machine to keep the
+same peer id. Changes apply on the next daemon start.",
+                        )
+                        .subcommand_required(true)
+                        .arg_required_else_help(true)
+                        .subcommand(Command::new("show").about("Print the peer id"))
+                        .subcommand(
+                            Command::new("rotate")
+                                .about("Replace the keypair with a new one, changing the peer id"),
+                        )
+                        .subcommand(
+                            Command::new("export")
+                                .about("Print the keypair, keep it secret")
+                                .arg(
+                                    Arg::new("out")
+                                        .long("out")
+                                        .short('o')
+                                        .help("Write it to this file instead, readable by you only")
+                                        .takes_value(true)
+                                        .value_name("file")
+                                        .value_hint(ValueHint::FilePath),
+                                ),
+                        )
+                        .subcommand(
+                            Command::new("import")
+                                .about("Replace the keypair with an exported one")
+                                .arg(
+                                    Arg::new("FILE")
+                                        .help("A file written by `export`, or an IPFS config file")
+                                        .takes_value(true)
+                                        .required(true)
+                                        .value_hint(ValueHint::FilePath),
+                                ),
+                        ),
+                )
+                .subcommand(
+                    Command::new("acl")
+                        .about("Restrict which peers may connect and fetch from the running daemon")
+                        .long_about(
+                            "Add or remove connection gating rules of the running daemon. Deny rules always
+win, any allow rule turns the list into an allowlist. Denied peer ids are disconnected right away,
+ip rules and allowlists apply to new connections. Rules are listed by `gistit daemon status`,
+keep them across restarts with the `acl` list in the settings file.",
+                        )
+                        .subcommand_required(true)
+                        .arg_required_else_help(true)
+                        .subcommand(Command::new("add").about("Add a rule").args(acl_rule_args()))
+                        .subcommand(
+                            Command::new("remove").about("Remove a rule").args(acl_rule_args()),
+                        ),
+                )
+                .subcommand(
+                    Command::new("limits")
+                        .about("Change bandwidth caps and the per peer request rate at runtime")
+                        .long_about(
+                            "Change bandwidth caps and the per peer request rate of the running daemon,
+without a restart. Limits not given as flags are taken from the settings `[limits]` table,
+so running it with no flags applies an edited settings file. Zero means unlimited.",
+                        )
+                        .arg(
+                            Arg::new("upload-rate")
+                                .long("upload-rate")
+                                .help("Upload cap in bytes per second")
+                                .takes_value(true)
+                                .value_name("bytes"),
+                        )
+                        .arg(
+                            Arg::new("download-rate")
+                                .long("download-rate")
+                                .help("Download cap in bytes per second")
+                                .takes_value(true)
+                                .value_name("bytes"),
+                        )
+                        .arg(
+                            Arg::new("peer-request-rate")
+                                .long("peer-request-rate")
+                                .help("Requests per minute a single peer can make")
+                                .takes_value(true)
+                                .value_name("requests"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("reload")
+                        .about("Apply an edited daemon.toml without a restart")
+                        .long_about(
+                            "Make the running daemon read `daemon.toml` in the config directory again, same as
+sending it SIGHUP. Listen addresses, bootstrap peers, acl rules, limits, the storage quota,
+notifications and the log level apply right away, the relay mode and storage path after a
+restart. Nothing is applied if the file is invalid, the errors are listed instead.",
+                        ),
+                )
+                .subcommand(
+                    Command::new("log-level")
+                        .about("Show or change the daemon log level at runtime")
+                        .long_about(
+                            "Show or change the log level of the running daemon, e.g. turn on `debug` while
+diagnosing connectivity without a restart losing the DHT routing table. The change lasts until the
+daemon restarts or the `log_level` of `daemon.toml` is changed and reloaded. It can't be changed if
+the daemon was started with `RUST_LOG`.",
+                        )
+                        .arg(
+                            Arg::new("LEVEL")
+                                .help("The new level, prints the current one if omitted")
+                                .takes_value(true)
+                                .possible_values(&["off", "error", "warn", "info", "debug", "trace"]),
+                        ),
+                )
+                .subcommand(
+                    Command::new("install-service")
+                        .about("Install a systemd user unit (or launchd agent on macOS) for the daemon")
+                        .long_about(
+                            "Install a systemd user unit (or launchd agent on macOS) running the daemon,
+so the p2p node is started on login and survives reboots.",
+                        )
+                        .arg(
+                            Arg::new("force")
+                                .long("force")
+                                .short('f')
+                                .help("Overwrite the service file if it already exists"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("uninstall-service")
+                        .about("Remove the service installed with `install-service`"),
+                )
+        )
+        .subcommand(
+            Command::new("subscribe")
+                .about("Stream announcements on gossipsub topics and gistits pushed to you")
+                .long_about(
+                    "Stream announcements of newly hosted gistits on gossipsub topics, as sent with
+`gistit <FILE> --announce <topic>`, and gistits pushed to you with `--to-peer`.
+Needs the p2p network, runs until interrupted.",
+                )
+                .arg(
+                    Arg::new("TOPIC")
+                        .help("The topics to subscribe to, e.g. 'gistit/rust', the workspace one if none")
+                        .takes_value(true)
+                        .multiple_values(true),
+                ),
+        )
+        .subcommand(
+            Command::new("name")
+                .about("Publish and resolve human readable names for gistits")
+                .long_about(
+                    "Publish and resolve human readable names, '@user/name', pointing to the latest
+hash of a gistit. Names are signed by your node identity and stored in the DHT, so only your node
+can update the names it published. Needs the p2p network.
+
+Example: gistit name publish @alice/dotfiles <HASH> && gistit fetch @alice/dotfiles",
+                )
+                .subcommand_required(true)
+                .arg_required_else_help(true)
+                .subcommand(
+                    Command::new("publish")
+                        .about("Point a name to a hash, publishing again updates it")
+                        .arg(
+                            Arg::new("NAME")
+                                .help("The name, written as '@user/name'")
+                                .takes_value(true)
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::new("HASH")
+                                .help("The gistit hash the name points to")
+                                .takes_value(true)
+                                .required(true),
+                        ),
+                )
+                .subcommand(
+                    Command::new("resolve")
+                        .about("Print the hash a name points to and who published it")
+                        .arg(
+                            Arg::new("NAME")
+                                .help("The name, written as '@user/name'")
+                                .takes_value(true)
+                                .required(true),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("host")
+                .about("Host a file on the p2p network, optionally encrypted with a secret")
+                .long_about(
+                    "Host a file on the p2p network through gistit-daemon, starting it if needed.
+With `--secret` the file is encrypted before leaving this machine, peers serving it never see it's
+contents. Prints an '@hash' id, fetch it with `gistit fetch @hash [--secret-prompt]`.",
+                )
+                .arg(
+                    Arg::new("FILE")
+                        .help("File to host")
+                        .allow_invalid_utf8(true)
+                        .takes_value(true)
+                        .required(true)
+                        .value_hint(ValueHint::FilePath),
+                )
+                .arg(
+                    Arg::new("secret")
+                        .long("secret")
+                        .short('s')
+                        .help("Encrypt the file with this secret")
+                        .long_help(
+                            "Encrypt the file with this secret. Ends up in your shell history, prefer
+`--secret-prompt`, `--secret-file` or the `GISTIT_SECRET` environment variable.",
+                        )
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("secret-prompt")
+                        .long("secret-prompt")
+                        .conflicts_with_all(&["secret", "secret-file"])
+                        .help("Type the secret at a prompt, twice, keeping it out of your shell history"),
+                )
+                .arg(
+                    Arg::new("secret-file")
+                        .long("secret-file")
+                        .takes_value(true)
+                        .value_name("path|/dev/fd/N")
+                        .allow_invalid_utf8(true)
+                        .value_hint(ValueHint::FilePath)
+                        .conflicts_with("secret")
+                        .help("Read the secret from this file or file descriptor, without the trailing newline"),
+                )
+                .arg(
+                    Arg::new("saved-secret")
+                        .long("saved-secret")
+                        .takes_value(true)
+                        .value_name("name")
+                        .conflicts_with_all(&["secret", "secret-file", "secret-prompt"])
+                        .help("Use the secret saved with `gistit auth login secret <name>`"),
+                )
+                .arg(
+                    Arg::new("description")
+                        .long("description")
+                        .short('d')
+                        .help("With a description")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("author")
+                        .long("author")
+                        .short('a')
+                        .help("With author information. Defaults to the settings file or a random generated name")
+                        .takes_value(true)
+                        .value_hint(ValueHint::Username),
+                )
+                .arg(
+                    Arg::new("announce")
+                        .long("announce")
+                        .help("Announce the hosted gistit on this gossipsub topic")
+                        .takes_value(true)
+                        .value_name("topic")
+                        .multiple_occurrences(true),
+                )
+                .arg(
+                    Arg::new("clipboard")
+                        .long("clipboard")
+                        .short('c')
+                        .help("Copies the '@hash' id to the system clipboard"),
+                ),
+        )
+        .subcommand(
+            Command::new("tui")
+                .about("Browse cached, stored and searched gistits in a terminal UI")
+                .long_about(
+                    "Browse the gistits in the local cache index and in gistit-daemon storage, or search
+both. Selecting one previews it with syntax highlighting, from there it can be saved, it's hash
+copied to the clipboard or it can be removed (from the index, or unpinned from the daemon).",
+                ),
+        )
+        .subcommand(
+            Command::new("watch-clipboard")
+                .about("Offer to send whatever you copy to the server, replacing it with the share url")
+                .long_about(
+                    "Watch the system clipboard and, whenever at least `--min-lines` lines are copied, offer to
+send them to the server as a gistit. The clipboard is then replaced with the share url. Needs a
+clipboard binary able to read the clipboard back, e.g. `xclip`, `xsel` or `wl-paste`.",
+                )
+                .arg(
+                    Arg::new("min-lines")
+                        .long("min-lines")
+                        .short('n')
+                        .takes_value(true)
+                        .value_name("lines")
+                        .help("Only offer copies with at least this many lines [default: 5]"),
+                )
+                .arg(
+                    Arg::new("auto")
+                        .long("auto")
+                        .help("Send without asking"),
+                )
+                .arg(
+                    Arg::new("author")
+                        .long("author")
+                        .short('a')
+                        .help("With author information. Defaults to the settings file or a random generated name")
+                        .takes_value(true)
+                        .value_hint(ValueHint::Username),
+                ),
+        )
+        .subcommand(
+            Command::new("auth")
+                .about("Keep tokens and secrets in the OS credential store")
+                .long_about(
+                    "Keep tokens and secrets in the OS credential store (Secret Service, Keychain or
+Credential Manager) instead of files or your shell history. 'github' is the token used by
+`--github`, 'identity' the passphrase protecting the gistit-daemon identity, handed to it when
+it's started, and 'secret <name>' a gistit secret used with `--saved-secret <name>`.",
+                )
+                .subcommand_required(true)
+                .arg_required_else_help(true)
+                .subcommand(
+                    Command::new("login")
+                        .about("Authorize with GitHub, or save a passphrase or secret typed at a prompt")
+                        .arg(
+                            Arg::new("KIND")
+                                .help("What to save")
+                                .takes_value(true)
+                                .required(true)
+                                .possible_values(&["github", "identity", "secret"]),
+                        )
+                        .arg(
+                            Arg::new("NAME")
+                                .help("The secret name, for 'secret'")
+                                .takes_value(true)
+                                .required_if_eq("KIND", "secret"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("logout")
+                        .about("Remove a saved token, passphrase or secret")
+                        .arg(
+                            Arg::new("KIND")
+                                .help("What to remove")
+                                .takes_value(true)
+                                .required(true)
+                                .possible_values(&["github", "identity", "secret"]),
+                        )
+                        .arg(
+                            Arg::new("NAME")
+                                .help("The secret name, for 'secret'")
+                                .takes_value(true)
+                                .required_if_eq("KIND", "secret"),
+                        ),
+                )
+                .subcommand(Command::new("status").about("Show what's saved, without the values")),
+        )
+        .subcommand(
+            Command::new("telemetry")
+                .about("Opt in or out of anonymous usage reporting, off by default")
+                .long_about(
+                    "Opt in or out of anonymous usage reporting, off by default. Only command names,
+error codes, the gistit version and the OS are collected, never arguments, hashes, file names or
+contents. Records are kept in the data directory and sent to the server in batches. `DO_NOT_TRACK=1`
+turns it off regardless of the settings.",
+                )
+                .subcommand_required(true)
+                .arg_required_else_help(true)
+                .subcommand(Command::new("status").about("Show whether it's on and what's pending"))
+                .subcommand(Command::new("on").about("Start reporting"))
+                .subcommand(Command::new("off").about("Stop reporting and remove pending records")),
+        )
+        .subcommand(
+            Command::new("cache")
+                .about("Inspect the local cache of gistits and server responses")
+                .long_about(
+                    "Inspect the local cache, kept in the data directory: the index of gistits sent and
+fetched from this machine, and server responses that rarely change, so sending in a loop doesn't ask
+the server the same thing every time.",
+                )
+                .subcommand_required(true)
+                .arg_required_else_help(true)
+                .subcommand(
+                    Command::new("stats")
+                        .about("Show what's cached and how often server responses came from it"),
+                ),
+        )
+        .subcommand(
+            Command::new("log")
+                .about("Show the audit log of gistits sent, fetched, hosted and deleted from here")
+                .long_about(
+                    "Show the audit log kept in the data directory, one line per gistit sent, fetched,
+hosted or deleted from this machine: when, it's hash, where it went or came from and whether it
+worked. It's append-only and never leaves this machine, check where a snippet holding a secret went
+with `gistit log --hash <hash>`.",
+                )
+                .arg(
+                    Arg::new("since")
+                        .long("since")
+                        .help("Only the last '30m', '12h', '7d'...")
+                        .takes_value(true)
+                        .value_name("duration"),
+                )
+                .arg(
+                    Arg::new("failed")
+                        .long("failed")
+                        .help("Only what failed"),
+                )
+                .arg(
+                    Arg::new("hash")
+                        .long("hash")
+                        .help("Only this gistit, the start of the hash is enough")
+                        .takes_value(true)
+                        .value_name("hash"),
+                )
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .help("Print the matching lines as they're stored, JSON lines"),
+                ),
+        )
+        .subcommand(
+            Command::new("export-state")
+                .about("Bundle settings, keys, snippets, caches, pins and the daemon identity in a file")
+                .long_about(
+                    "Bundle what gistit keeps on this machine in a single archive, to move to another
+machine or keep a backup: the global settings, the snippet library, published names, the cache
+index, the audit log, pinned gistits and the daemon identity. Saved credentials and the key of the
+identity are sealed with a passphrase, asked twice. Restore it with `gistit import-state`.",
+                )
+                .args(state_args()),
+        )
+        .subcommand(
+            Command::new("import-state")
+                .about("Restore a file written by `gistit export-state`")
+                .long_about(
+                    "Restore a file written by `gistit export-state`. What's already on this machine is
+kept unless `--force` is given, sealed keys are opened with the passphrase they were exported with.
+gistit-daemon must be stopped, it rewrites it's identity and storage.",
+                )
+                .args(state_args()),
+        )
+        .subcommand(
+            Command::new("serve-json")
+                .about("Answer JSON-RPC requests on stdin, for editor extensions")
+                .long_about(
+                    "Answer newline delimited JSON-RPC 2.0 requests read from stdin, one line each on stdout,
+until stdin is closed. Editor extensions keep it running instead of spawning gistit for every action.
+Requests are answered one at a time, in order.
+
+METHODS:
+    send      {data, name?, lang?, author?, description?, secret?, backend?} -> {hash, url?, backend}
+    fetch     {hash, secret?, token?, prefer?, burn_after_read?}
+              -> {hash, author, description?, source, files: [{name, lang, data}]}
+    list      {origin?, limit?} -> {entries}, gistits sent and fetched, most recent first
+    status    -> {backend, running, ...}, with the swarm status when gistit-daemon is running
+
+Errors of gistit itself have code -32000, their `data` holds the error `code`, `hint` and `exit_code`.",
+                ),
+        )
+        .subcommand(
+            Command::new("workspace")
+                .about("Share a key and a topic with your team, used by every send")
+                .long_about(
+                    "Share a key and a topic with your team. `create` makes a workspace and prints a join token
+to pass around privately, `join` takes it. While a workspace is in use gistits sent are encrypted
+with it's key and announced on it's topic, `gistit fetch` decrypts with it when no other secret is
+given and `gistit subscribe` follows it's topic. Join tokens are kept in the OS credential store.",
+                )
+                .subcommand_required(true)
+                .arg_required_else_help(true)
+                .subcommand(
+                    Command::new("create")
+                        .about("Create a workspace with a new key and use it")
+                        .arg(
+                            Arg::new("NAME")
+                                .help("The workspace name")
+                                .takes_value(true)
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::new("topic")
+                                .long("topic")
+                                .help("The topic to announce on, a hard to guess one is generated otherwise")
+                                .takes_value(true)
+                                .value_name("topic"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("join")
+                        .about("Join a workspace with the token printed by `create` and use it")
+                        .arg(
+                            Arg::new("TOKEN")
+                                .help("The join token, 'gistit-workspace:...'")
+                                .takes_value(true)
+                                .required(true),
+                        ),
+                )
+                .subcommand(Command::new("ls").about("List joined workspaces and the one in use"))
+                .subcommand(
+                    Command::new("use")
+                        .about("Use a joined workspace for the next sends")
+                        .arg(
+                            Arg::new("NAME")
+                                .help("The workspace name")
+                                .takes_value(true)
+                                .required(true),
+                        ),
+                )
+                .subcommand(
+                    Command::new("leave")
+                        .about("Forget a workspace and it's key")
+                        .arg(
+                            Arg::new("NAME")
+                                .help("The workspace name")
+                                .takes_value(true)
+                                .required(true),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("snippets")
+                .about("Keep a local library of frequently shared snippets")
+                .long_about(
+                    "Keep a local library of frequently shared snippets, stored in the config directory
+along with the description and lifespan to send them with. `use` sends a stored snippet in one go.
+
+Example: gistit snippets add serve serve.sh --lifespan 1d && gistit snippets use serve",
+                )
+                .subcommand_required(true)
+                .arg_required_else_help(true)
+                .subcommand(
+                    Command::new("add")
+                        .about("Store a copy of a file as a snippet")
+                        .arg(
+                            Arg::new("NAME")
+                                .help("The snippet name")
+                                .takes_value(true)
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::new("FILE")
+                                .help("The file to store, later changes to it aren't picked up")
+                                .allow_invalid_utf8(true)
+                                .takes_value(true)
+                                .required(true)
+                                .value_hint(ValueHint::FilePath),
+                        )
+                        .arg(
+                            Arg::new("description")
+                                .long("description")
+                                .short('d')
+                                .help("Send it with this description")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::new("lifespan")
+                                .long("lifespan")
+                                .help("How long the server keeps it, e.g. '30m', '2d' or 'never'")
+                                .takes_value(true)
+                                .value_name("duration"),
+                        )
+                        .arg(
+                            Arg::new("force")
+                                .long("force")
+                                .short('f')
+                                .help("Replace the snippet with the same name"),
+                        ),
+                )
+                .subcommand(Command::new("ls").about("List stored snippets"))
+                .subcommand(
+                    Command::new("use")
+                        .about("Send a stored snippet with it's description and lifespan")
+                        .arg(
+                            Arg::new("NAME")
+                                .help("The snippet name")
+                                .takes_value(true)
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::new("author")
+                                .long("author")
+                                .short('a')
+                                .help("With author information. Defaults to the settings file or a random generated name")
+                                .takes_value(true)
+                                .value_hint(ValueHint::Username),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("inbox")
+                .about("Accept or reject gistits other peers pushed to you")
+                .long_about(
+                    "Accept or reject gistits other peers pushed to you with `--to-peer`. Pushes wait
+in gistit-daemon memory until accepted, nothing is written to disk or served before that. Rejected
+ones are dropped. Needs the p2p network.",
+                )
+                .subcommand_required(true)
+                .arg_required_else_help(true)
+                .subcommand(Command::new("ls").about("List pushed gistits waiting for approval"))
+                .subcommand(
+                    Command::new("accept")
+                        .about("Store a pushed gistit, it can then be fetched by hash")
+                        .arg(
+                            Arg::new("ID")
+                                .help("The inbox id, listed by `gistit inbox ls`")
+                                .takes_value(true)
+                                .required(true),
+                        ),
+                )
+                .subcommand(
+                    Command::new("reject")
+                        .about("Drop a pushed gistit")
+                        .arg(
+                            Arg::new("ID")
+                                .help("The inbox id, listed by `gistit inbox ls`")
+                                .takes_value(true)
+                                .required(true),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("pin")
+                .about("Keep a gistit stored and served by gistit-daemon until unpinned")
+                .long_about(
+                    "Keep a gistit stored and served by gistit-daemon until unpinned, fetching it
+first if needed. Pinned gistits count towards the storage quota, settings `[storage]` table, but are
+never evicted. Needs the p2p network.",
+                )
+                .args_conflicts_with_subcommands(true)
+                .subcommand_negates_reqs(true)
+                .arg(
+                    Arg::new("HASH")
+                        .help("The gistit hash to pin")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .subcommand(
+                    Command::new("ls")
+                        .about("List pinned gistits, their size and last access, and storage usage"),
+                ),
+        )
+        .subcommand(
+            Command::new("unpin")
+                .about("Let a pinned gistit be evicted once the storage quota is reached")
+                .arg(
+                    Arg::new("HASH")
+                        .help("The gistit hash to unpin")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            Command::new("gen-man")
+                .about("Generate roff man pages for every subcommand")
+                .hide(true)
+                .arg(
+                    Arg::new("out-dir")
+                        .long("out-dir")
+                        .short('o')
+                        .help("Directory to write the pages to, defaults to the current directory")
+                        .takes_value(true)
+                        .value_name("dir")
+                        .value_hint(ValueHint::DirPath),
+                )
+        )
         .subcommand(
             Command::new("node")
                 .alias("n")
@@ -159,3 +1681,65 @@ to peer file sharing.")
                     )
         )
 }
+
+fn state_args() -> [Arg<'static>; 5] {
+    [
+        Arg::new("FILE")
+            .help("The state archive")
+            .takes_value(true)
+            .required(true)
+            .allow_invalid_utf8(true)
+            .value_hint(ValueHint::FilePath),
+        Arg::new("no-keys")
+            .long("no-keys")
+            .help("Leave saved credentials and the identity key out"),
+        Arg::new("force")
+            .long("force")
+            .help("Replace what's already there"),
+        Arg::new("secret-file")
+            .long("secret-file")
+            .takes_value(true)
+            .value_name("path|/dev/fd/N")
+            .allow_invalid_utf8(true)
+            .value_hint(ValueHint::FilePath)
+            .conflicts_with("no-keys")
+            .help("Read the passphrase sealing the keys from this file instead of asking it"),
+        Arg::new("saved-secret")
+            .long("saved-secret")
+            .takes_value(true)
+            .value_name("name")
+            .conflicts_with_all(&["secret-file", "no-keys"])
+            .help("Use a secret saved with `gistit auth login secret <name>` as the passphrase"),
+    ]
+}
+
+fn daemon_listen_args() -> [Arg<'static>; 2] {
+    [
+        Arg::new("host")
+            .long("host")
+            .help("Local host address to listen for connection")
+            .takes_value(true)
+            .value_name("ipv4")
+            .default_value("0.0.0.0"),
+        Arg::new("port")
+            .long("port")
+            .help("Local port to listen for connection")
+            .takes_value(true)
+            .value_name("port")
+            .default_value("0"),
+    ]
+}
+
+fn acl_rule_args() -> [Arg<'static>; 2] {
+    [
+        Arg::new("ACTION")
+            .help("Whether to allow or deny the target")
+            .takes_value(true)
+            .possible_values(["allow", "deny"])
+            .required(true),
+        Arg::new("TARGET")
+            .help("A peer id or an ip address with an optional prefix, e.g. '10.0.0.0/8'")
+            .takes_value(true)
+            .required(true),
+    ]
+}
@@ -0,0 +1,362 @@
+//! The audit module
+//!
+//! An append-only log of every gistit sent, fetched, hosted or deleted (unpinned, or removed in
+//! `gistit tui`) from this machine, kept as JSON lines in the data directory. Each line has the
+//! time, the hash, where it went or came from (a server url, `p2p` or `peer:<id>`) and whether it
+//! worked, with the error code when it didn't. It never leaves this machine and lines are never
+//! rewritten, so it tells where a snippet holding a secret ended up.
+//!
+//! Commands note the hashes they act on with [`note`] as they go, once they're known, and the
+//! outcome of the command is written next to them by [`record`]. `gistit log` reads it back.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use clap::ArgMatches;
+use console::style;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+
+use gistit_project::path;
+
+use crate::cache;
+use crate::dispatch::Dispatch;
+use crate::param::check;
+use crate::pin::fmt_ago;
+use crate::{finish, Error, Result};
+
+/// The log, inside the data directory
+pub const LOG_FILE_NAME: &str = "audit.jsonl";
+
+/// Target of gistits hosted on or fetched from the p2p network
+pub const P2P: &str = "p2p";
+
+/// Hashes are cut to this many characters when listed
+const SHORT_HASH_LENGTH: usize = 12;
+
+lazy_static! {
+    /// Hashes and where they went or came from, noted by the running command
+    static ref NOTED: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+}
+
+/// One line of the log
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Record {
+    /// Seconds since unix epoch
+    pub time: u64,
+    /// `send`, `fetch`, `host` or `delete`
+    pub action: String,
+    /// Unknown when it failed before the gistit was read or located
+    pub hash: Option<String>,
+    /// A server url, `p2p` or `peer:<id>`
+    pub target: Option<String>,
+    pub ok: bool,
+    /// Code of the error it failed with, see [`Error::code`]
+    pub error: Option<String>,
+}
+
+/// What's logged of a command, `None` for the ones that are left out
+fn action(command: &str) -> Option<&'static str> {
+    match command {
+        "send" => Some("send"),
+        "fetch" => Some("fetch"),
+        "host" => Some("host"),
+        "unpin" | "tui" => Some("delete"),
+        _ => None,
+    }
+}
+
+/// Notes that the running command acts on `hash` at `target`, logged once it's done
+pub fn note(hash: &str, target: &str) {
+    NOTED
+        .lock()
+        .unwrap()
+        .push((hash.to_owned(), target.to_owned()));
+}
+
+/// Target of gistits pushed to `peer_id`
+#[must_use]
+pub fn peer(peer_id: &str) -> String {
+    format!("peer:{}", peer_id)
+}
+
+fn log_path() -> Result<PathBuf> {
+    Ok(path::data()?.join(LOG_FILE_NAME))
+}
+
+/// The records of the hashes noted by `command` with it's outcome, or a single one without a hash
+/// if it failed before noting any
+fn records(
+    command: &str,
+    noted: Vec<(String, String)>,
+    error: Option<&Error>,
+    now: u64,
+) -> Vec<Record> {
+    let action = match action(command) {
+        Some(action) => action,
+        None => return Vec::new(),
+    };
+    let record = |hash: Option<String>, target: Option<String>| Record {
+        time: now,
+        action: action.to_owned(),
+        hash,
+        target,
+        ok: error.is_none(),
+        error: error.map(|err| err.code().to_owned()),
+    };
+
+    if noted.is_empty() {
+        // Nothing happened to a gistit if it worked, e.g. browsing `gistit tui`
+        return error.map(|_| vec![record(None, None)]).unwrap_or_default();
+    }
+    noted
+        .into_iter()
+        .map(|(hash, target)| record(Some(hash), Some(target)))
+        .collect()
+}
+
+/// Logs the outcome of `command` along with what it noted so far. Commands sending more than once,
+/// like `--watch`, log each one as it's done. Never fails the command
+pub fn record(command: &str, error: Option<&Error>) {
+    let noted = std::mem::take(&mut *NOTED.lock().unwrap());
+    let records = records(command, noted, error, cache::now());
+    if records.is_empty() {
+        return;
+    }
+
+    let append = || -> Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(log_path()?)?;
+        for record in &records {
+            writeln!(file, "{}", serde_json::to_string(record)?)?;
+        }
+        Ok(())
+    };
+
+    if let Err(err) = append() {
+        tracing::debug!("failed to write the audit log: {}", err);
+    }
+}
+
+fn read_records(log_path: &Path) -> Vec<Record> {
+    fs::read_to_string(log_path)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+/// Which records `gistit log` lists
+#[derive(Debug, Clone, Copy, Default)]
+struct Filter<'a> {
+    /// Only records from this time on
+    after: Option<u64>,
+    failed: bool,
+    /// Only records of hashes starting with this
+    hash: Option<&'a str>,
+}
+
+impl Filter<'_> {
+    fn matches(&self, record: &Record) -> bool {
+        self.after.map_or(true, |after| record.time >= after)
+            && (!self.failed || !record.ok)
+            && self.hash.map_or(true, |hash| {
+                record
+                    .hash
+                    .as_deref()
+                    .map_or(false, |other| other.starts_with(hash))
+            })
+    }
+}
+
+fn format_records(records: &[&Record], now: u64) -> String {
+    if records.is_empty() {
+        return "\n    nothing logged\n\n".to_owned();
+    }
+
+    let mut lines = String::from("\n");
+    for record in records {
+        let hash = record
+            .hash
+            .as_deref()
+            .map_or("-", |hash| &hash[..hash.len().min(SHORT_HASH_LENGTH)]);
+        let outcome = match (record.ok, &record.error) {
+            (true, _) => style("ok".to_owned()).green(),
+            (false, Some(code)) => style(format!("failed, {}", code)).red(),
+            (false, None) => style("failed".to_owned()).red(),
+        };
+        lines.push_str(&format!(
+            "    {:<9} {:<6} {:<14} {:<14} {}\n",
+            fmt_ago(now.saturating_sub(record.time)),
+            record.action,
+            hash,
+            outcome,
+            style(record.target.as_deref().unwrap_or("-")).dim()
+        ));
+    }
+    lines.push('\n');
+    lines
+}
+
+#[derive(Debug, Clone)]
+pub struct Action<'a> {
+    pub since: Option<&'a str>,
+    pub failed: bool,
+    pub hash: Option<&'a str>,
+    pub json: bool,
+}
+
+impl<'a> Action<'a> {
+    pub fn from_args(
+        args: &'a ArgMatches,
+    ) -> Result<Box<dyn Dispatch<InnerData = Config<'a>> + Send + Sync + 'a>> {
+        Ok(Box::new(Self {
+            since: args.value_of("since"),
+            failed: args.is_present("failed"),
+            hash: args.value_of("hash"),
+            json: args.is_present("json"),
+        }))
+    }
+}
+
+pub struct Config<'a> {
+    filter: Filter<'a>,
+    json: bool,
+    log_path: PathBuf,
+}
+
+#[async_trait]
+impl<'a> Dispatch for Action<'a> {
+    type InnerData = Config<'a>;
+
+    async fn prepare(&self) -> Result<Self::InnerData> {
+        let after = self
+            .since
+            .map(check::since)
+            .transpose()?
+            .map(|since| cache::now().saturating_sub(since));
+
+        Ok(Config {
+            filter: Filter {
+                after,
+                failed: self.failed,
+                hash: self.hash,
+            },
+            json: self.json,
+            log_path: log_path()?,
+        })
+    }
+
+    async fn dispatch(&self, config: Self::InnerData) -> Result<()> {
+        let records = read_records(&config.log_path);
+        let records: Vec<&Record> = records
+            .iter()
+            .filter(|record| config.filter.matches(record))
+            .collect();
+
+        if config.json {
+            for record in records {
+                println!("{}", serde_json::to_string(record)?);
+            }
+        } else {
+            finish!(format_records(&records, cache::now()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn noted(hash: &str, target: &str) -> (String, String) {
+        (hash.to_owned(), target.to_owned())
+    }
+
+    #[test]
+    fn audit_records_outcome() {
+        let sent = records("send", vec![noted("hash", "p2p")], None, 10);
+        assert_eq!(
+            sent,
+            vec![Record {
+                time: 10,
+                action: "send".to_owned(),
+                hash: Some("hash".to_owned()),
+                target: Some("p2p".to_owned()),
+                ok: true,
+                error: None,
+            }]
+        );
+
+        let err = Error::Daemon("gistit node is not running");
+        let failed = records("unpin", vec![], Some(&err), 10);
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].action, "delete");
+        assert_eq!(failed[0].hash, None);
+        assert_eq!(failed[0].error.as_deref(), Some("daemon"));
+
+        assert!(records("tui", vec![], None, 10).is_empty());
+        assert!(records("config", vec![noted("hash", "p2p")], None, 10).is_empty());
+        assert_eq!(
+            records(
+                "fetch",
+                vec![noted("a", "p2p"), noted("b", "p2p")],
+                None,
+                10
+            )
+            .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn audit_filter() {
+        let record = |time: u64, hash: Option<&str>, ok: bool| Record {
+            time,
+            action: "send".to_owned(),
+            hash: hash.map(ToOwned::to_owned),
+            target: None,
+            ok,
+            error: None,
+        };
+
+        let filter = Filter {
+            after: Some(100),
+            ..Filter::default()
+        };
+        assert!(filter.matches(&record(100, None, true)));
+        assert!(!filter.matches(&record(99, None, true)));
+
+        let filter = Filter {
+            failed: true,
+            ..Filter::default()
+        };
+        assert!(filter.matches(&record(0, None, false)));
+        assert!(!filter.matches(&record(0, None, true)));
+
+        let filter = Filter {
+            hash: Some("ab"),
+            ..Filter::default()
+        };
+        assert!(filter.matches(&record(0, Some("abcdef"), true)));
+        assert!(!filter.matches(&record(0, Some("bcdef"), true)));
+        assert!(!filter.matches(&record(0, None, false)));
+    }
+
+    #[test]
+    fn audit_read_records() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let log = dir.path().join(LOG_FILE_NAME);
+        let line = serde_json::to_string(&records("host", vec![noted("hash", "p2p")], None, 1)[0])
+            .unwrap();
+        fs::write(&log, format!("{}\nnot json\n{}\n", line, line)).unwrap();
+
+        assert_eq!(read_records(&log).len(), 2);
+        assert!(read_records(&dir.path().join("missing")).is_empty());
+    }
+}
@@ -0,0 +1,172 @@
+//! The auth module
+//!
+//! `gistit auth login|logout|status` manages what's kept in the OS credential store, see
+//! [`crate::credentials`]: the GitHub token used by `--github`, the passphrase protecting the
+//! daemon identity and gistit secrets saved by name, used with `--saved-secret <name>`.
+
+use std::fs;
+
+use async_trait::async_trait;
+use clap::ArgMatches;
+use console::style;
+
+use gistit_project::path;
+
+use crate::credentials::{self, Credential};
+use crate::dispatch::Dispatch;
+use crate::github::{self, GITHUB_TOKEN_FILE_NAME};
+use crate::param::check;
+use crate::passphrase;
+use crate::{finish, progress, updateln, Error, Result};
+
+#[derive(Debug, Clone)]
+pub enum Action<'a> {
+    Login(Kind<'a>),
+    Logout(Kind<'a>),
+    Status,
+}
+
+/// What to log in or out of
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Kind<'a> {
+    Github,
+    Identity,
+    Secret(&'a str),
+}
+
+impl<'a> Kind<'a> {
+    fn from_args(args: &'a ArgMatches) -> Result<Self> {
+        match args.value_of("KIND") {
+            Some("github") => Ok(Self::Github),
+            Some("identity") => Ok(Self::Identity),
+            Some("secret") => Ok(Self::Secret(
+                args.value_of("NAME")
+                    .ok_or(Error::Argument("secrets are saved by name", "[NAME]"))?,
+            )),
+            _ => Err(Error::Argument(
+                "expected 'github', 'identity' or 'secret'",
+                "[KIND]",
+            )),
+        }
+    }
+
+    fn credential(&self) -> Credential {
+        match self {
+            Self::Github => Credential::GithubToken,
+            Self::Identity => Credential::IdentityPassphrase,
+            Self::Secret(name) => Credential::Secret((*name).to_owned()),
+        }
+    }
+}
+
+impl<'a> Action<'a> {
+    pub fn from_args(
+        args: &'a ArgMatches,
+    ) -> Result<Box<dyn Dispatch<InnerData = Config> + Send + Sync + 'a>> {
+        let action = match args.subcommand() {
+            Some(("login", args)) => Self::Login(Kind::from_args(args)?),
+            Some(("logout", args)) => Self::Logout(Kind::from_args(args)?),
+            Some(("status", _)) => Self::Status,
+            _ => return Err(Error::Argument("missing subcommand", "auth")),
+        };
+        Ok(Box::new(action))
+    }
+}
+
+pub struct Config;
+
+#[async_trait]
+impl<'a> Dispatch for Action<'a> {
+    type InnerData = Config;
+
+    async fn prepare(&self) -> Result<Self::InnerData> {
+        if let Self::Login(Kind::Secret(name)) | Self::Logout(Kind::Secret(name)) = self {
+            // Same rules as snippet names
+            check::snippet(name).map_err(|_| {
+                Error::Argument(
+                    "invalid secret name, expected letters, digits, '-', '_' or '.'",
+                    "[NAME]",
+                )
+            })?;
+        }
+        Ok(Config)
+    }
+
+    async fn dispatch(&self, _config: Self::InnerData) -> Result<()> {
+        match self {
+            Self::Login(Kind::Github) => {
+                progress!("Authorizing");
+                let mut oauth = github::Oauth::new()?;
+                oauth.login().await?;
+                updateln!("Authorized");
+                finish!("\n    github: logged in\n\n");
+            }
+            Self::Login(kind) => {
+                let value = match kind {
+                    Kind::Identity => passphrase::ask("identity passphrase", true).await?,
+                    _ => passphrase::Source::Prompt.read(true).await?,
+                };
+                kind.credential().set(&value)?;
+                finish!(format!("\n    {}: saved\n\n", style(label(kind)).bold()));
+            }
+            Self::Logout(kind) => {
+                let mut deleted = kind.credential().delete()?;
+                if *kind == Kind::Github {
+                    let token_path = path::config()?.join(GITHUB_TOKEN_FILE_NAME);
+                    if fs::metadata(&token_path).is_ok() {
+                        fs::remove_file(&token_path)?;
+                        deleted = true;
+                    }
+                }
+                let state = if deleted { "removed" } else { "wasn't saved" };
+                finish!(format!(
+                    "\n    {}: {}\n\n",
+                    style(label(kind)).bold(),
+                    state
+                ));
+            }
+            Self::Status => {
+                finish!(format_status()?);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn label(kind: &Kind) -> String {
+    match kind {
+        Kind::Github => "github".to_owned(),
+        Kind::Identity => "identity".to_owned(),
+        Kind::Secret(name) => format!("secret '{}'", name),
+    }
+}
+
+fn format_status() -> Result<String> {
+    let saved = |saved: bool| {
+        if saved {
+            style("saved").green().to_string()
+        } else {
+            style("not saved").dim().to_string()
+        }
+    };
+
+    let github = if Credential::GithubToken.get()?.is_some() {
+        saved(true)
+    } else if fs::metadata(path::config()?.join(GITHUB_TOKEN_FILE_NAME)).is_ok() {
+        format!("{}, in the config directory", style("saved").yellow())
+    } else {
+        saved(false)
+    };
+    let identity = saved(Credential::IdentityPassphrase.get()?.is_some());
+    let secrets = credentials::secret_names()?;
+    let secrets = if secrets.is_empty() {
+        style("none").dim().to_string()
+    } else {
+        secrets.join(", ")
+    };
+
+    Ok(format!(
+        "\n    github: {}\n    identity: {}\n    secrets: {}\n\n",
+        github, identity, secrets
+    ))
+}
@@ -0,0 +1,202 @@
+//! The binary module
+//!
+//! Files that aren't text are sent base64 encoded and flagged `binary`, see
+//! [`crate::file::is_binary`]. They're never printed to the terminal: previews describe them, with
+//! their dimensions if they're images or a hexdump of their start otherwise, and fetching saves
+//! them unless they're piped somewhere.
+
+use std::borrow::Cow;
+use std::fmt::Write;
+
+use indicatif::HumanBytes;
+
+use gistit_proto::Inner;
+
+use crate::{Error, Result};
+
+/// Bytes of a binary file shown in it's preview
+const HEXDUMP_LEN: usize = 256;
+
+/// Bytes shown on every line of a hexdump
+const HEXDUMP_WIDTH: usize = 16;
+
+/// The content of `inner`, decoded if it's binary
+///
+/// # Errors
+///
+/// Fails if it's binary but not base64
+pub fn content(inner: &Inner) -> Result<Cow<'_, [u8]>> {
+    if inner.binary {
+        base64::decode(&inner.data)
+            .map(Cow::Owned)
+            .map_err(|err| Error::Binary(err.to_string()))
+    } else {
+        Ok(Cow::Borrowed(inner.data.as_bytes()))
+    }
+}
+
+/// One line about `bytes`, their format and dimensions if they're an image
+#[must_use]
+pub fn describe(bytes: &[u8]) -> String {
+    let size = HumanBytes(bytes.len() as u64);
+    match image_size(bytes) {
+        Some((format, width, height)) => {
+            format!("{} image, {}x{}, {}", format, width, height, size)
+        }
+        None => format!("binary, {}", size),
+    }
+}
+
+/// What previews show instead of `bytes`, [`describe`] and a hexdump of their start if they aren't
+/// an image
+#[must_use]
+pub fn preview(bytes: &[u8]) -> String {
+    let mut preview = describe(bytes);
+    if image_size(bytes).is_none() {
+        preview.push_str("\n\n");
+        preview.push_str(&hexdump(&bytes[..bytes.len().min(HEXDUMP_LEN)]));
+        if bytes.len() > HEXDUMP_LEN {
+            preview.push_str("...\n");
+        }
+    }
+    preview
+}
+
+/// `bytes` like `hexdump -C` prints them, offsets, hex and printable ASCII
+#[must_use]
+pub fn hexdump(bytes: &[u8]) -> String {
+    let mut dump = String::new();
+    for (line, chunk) in bytes.chunks(HEXDUMP_WIDTH).enumerate() {
+        let _ = write!(dump, "{:08x} ", line * HEXDUMP_WIDTH);
+        for index in 0..HEXDUMP_WIDTH {
+            if index % 8 == 0 {
+                dump.push(' ');
+            }
+            match chunk.get(index) {
+                Some(byte) => {
+                    let _ = write!(dump, "{:02x} ", byte);
+                }
+                None => dump.push_str("   "),
+            }
+        }
+
+        dump.push_str(" |");
+        dump.extend(chunk.iter().map(|byte| {
+            if byte.is_ascii_graphic() || *byte == b' ' {
+                char::from(*byte)
+            } else {
+                '.'
+            }
+        }));
+        dump.push_str("|\n");
+    }
+    dump
+}
+
+/// Format, width and height of `bytes` if they're a PNG, GIF, JPEG or BMP image
+#[must_use]
+pub fn image_size(bytes: &[u8]) -> Option<(&'static str, u32, u32)> {
+    let big16 = |at: usize| Some(u16::from_be_bytes(bytes.get(at..at + 2)?.try_into().ok()?));
+    let little16 = |at: usize| Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?));
+    let big32 = |at: usize| Some(u32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?));
+    let signed32 = |at: usize| Some(i32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?));
+
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some(("PNG", big32(16)?, big32(20)?))
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Some(("GIF", little16(6)?.into(), little16(8)?.into()))
+    } else if bytes.starts_with(b"BM") {
+        // Bottom-up bitmaps have a negative height
+        Some((
+            "BMP",
+            signed32(18)?.unsigned_abs(),
+            signed32(22)?.unsigned_abs(),
+        ))
+    } else if bytes.starts_with(b"\xff\xd8") {
+        // Segments follow the start marker, the first start of frame one has the dimensions
+        let mut at = 2;
+        loop {
+            let marker = *bytes.get(at + 1)?;
+            if bytes[at] != 0xff {
+                return None;
+            }
+            if matches!(marker, 0xc0..=0xcf) && !matches!(marker, 0xc4 | 0xc8 | 0xcc) {
+                return Some(("JPEG", big16(at + 7)?.into(), big16(at + 5)?.into()));
+            }
+            at += 2 + usize::from(big16(at + 2)?);
+        }
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+        png.extend(width.to_be_bytes());
+        png.extend(height.to_be_bytes());
+        png.extend([8, 6, 0, 0, 0]);
+        png
+    }
+
+    #[test]
+    fn binary_image_size() {
+        assert_eq!(image_size(&png(640, 480)), Some(("PNG", 640, 480)));
+        assert_eq!(
+            image_size(b"GIF89a\x20\x03\x58\x02\0\0"),
+            Some(("GIF", 800, 600))
+        );
+
+        let mut jpeg = b"\xff\xd8\xff\xe0\0\x04\0\0".to_vec();
+        jpeg.extend(b"\xff\xc0\0\x11\x08\x01\xe0\x02\x80");
+        assert_eq!(image_size(&jpeg), Some(("JPEG", 640, 480)));
+
+        let mut bmp = vec![0; 26];
+        bmp[..2].copy_from_slice(b"BM");
+        bmp[18..22].copy_from_slice(&16_i32.to_le_bytes());
+        bmp[22..26].copy_from_slice(&(-9_i32).to_le_bytes());
+        assert_eq!(image_size(&bmp), Some(("BMP", 16, 9)));
+
+        assert_eq!(image_size(b"\x89PNG\r\n\x1a\n"), None);
+        assert_eq!(image_size(b"\xff\xd8\xff\xe0\0\x04"), None);
+        assert_eq!(image_size(b"\x7fELF"), None);
+    }
+
+    #[test]
+    fn binary_preview() {
+        assert!(describe(&png(2, 3)).starts_with("PNG image, 2x3, "));
+        assert!(!preview(&png(2, 3)).contains('|'));
+
+        assert_eq!(
+            hexdump(b"\x7fELF\x02\x01\x01\0hello, world!\n"),
+            "00000000  7f 45 4c 46 02 01 01 00  68 65 6c 6c 6f 2c 20 77  |.ELF....hello, w|\n\
+             00000010  6f 72 6c 64 21 0a                                 |orld!.|\n"
+        );
+
+        let elf = [0x7f; HEXDUMP_LEN + 1];
+        let preview = preview(&elf);
+        assert!(preview.starts_with("binary, 257 B\n\n00000000  "));
+        assert_eq!(preview.lines().count(), 2 + HEXDUMP_LEN / HEXDUMP_WIDTH + 1);
+        assert!(preview.ends_with("...\n"));
+    }
+
+    #[test]
+    fn binary_content() {
+        let mut inner = Inner {
+            data: "f0VMRgAB".to_owned(),
+            binary: true,
+            ..Inner::default()
+        };
+        assert_eq!(&*content(&inner).unwrap(), b"\x7fELF\0\x01");
+
+        inner.binary = false;
+        assert_eq!(&*content(&inner).unwrap(), b"f0VMRgAB");
+
+        inner.binary = true;
+        inner.data = "not base64!".to_owned();
+        assert!(content(&inner).is_err());
+    }
+}
@@ -0,0 +1,600 @@
+//! The cache module
+//!
+//! Keeps a small index of the gistits sent and fetched from this machine inside the project data
+//! directory. It's a quality of life feature (shell completion, history), so callers should not
+//! fail an operation because the index couldn't be written.
+//!
+//! Server responses that rarely change are cached next to it for a while, so sending in a loop
+//! (`--watch`) doesn't ask the server the same thing every time: it's capabilities, revalidated
+//! with their `ETag` once expired, and whether it has a hash, which is content addressed. `gistit cache
+//! stats` shows how often they're answered from here.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use clap::ArgMatches;
+use console::style;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use gistit_project::path;
+
+use crate::dispatch::Dispatch;
+use crate::{finish, warnln, Error, Result};
+
+/// The index file name inside the data directory
+pub const INDEX_FILE_NAME: &str = "index.json";
+
+/// Oldest entries are dropped past this size
+const INDEX_MAX_ENTRIES: usize = 500;
+
+/// The cached server responses file name inside the data directory
+pub const RESPONSES_FILE_NAME: &str = "responses.json";
+
+/// Responses expiring first are dropped past this size
+const RESPONSES_MAX_ENTRIES: usize = 500;
+
+/// Seconds since unix epoch
+#[must_use]
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Check your system time")
+        .as_secs()
+}
+
+/// How the gistit got into the index
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Origin {
+    Sent,
+    Hosted,
+    Fetched,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub hash: String,
+    pub name: String,
+    pub author: String,
+    pub origin: Origin,
+    /// Seconds since unix epoch
+    pub timestamp: u64,
+}
+
+impl Entry {
+    #[must_use]
+    pub fn new(hash: &str, name: &str, author: &str, origin: Origin) -> Self {
+        Self {
+            hash: hash.to_owned(),
+            name: name.to_owned(),
+            author: author.to_owned(),
+            origin,
+            timestamp: now(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Index {
+    pub entries: Vec<Entry>,
+}
+
+impl Index {
+    /// Path of the index file
+    ///
+    /// # Errors
+    ///
+    /// Fails if the system doesn't have a HOME directory
+    pub fn path() -> Result<PathBuf> {
+        Ok(path::data()?.join(INDEX_FILE_NAME))
+    }
+
+    /// Reads the index file, empty if it doesn't exist
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file exists but can't be read or decoded
+    pub fn load() -> Result<Self> {
+        let index_path = Self::path()?;
+
+        if fs::metadata(&index_path).is_ok() {
+            Ok(serde_json::from_str(&fs::read_to_string(&index_path)?)?)
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    /// Writes the index file, overwriting it
+    ///
+    /// # Errors
+    ///
+    /// Fails if can't write to the data directory
+    pub fn save(&self) -> Result<()> {
+        fs::write(Self::path()?, serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+
+    /// Adds an entry, replacing previous ones with the same hash and origin
+    pub fn insert(&mut self, entry: Entry) {
+        self.entries
+            .retain(|other| other.hash != entry.hash || other.origin != entry.origin);
+        self.entries.push(entry);
+
+        if self.entries.len() > INDEX_MAX_ENTRIES {
+            let overflow = self.entries.len() - INDEX_MAX_ENTRIES;
+            self.entries.drain(..overflow);
+        }
+    }
+
+    /// Removes every entry of `hash`, returning whether there was any
+    pub fn remove(&mut self, hash: &str) -> bool {
+        let len = self.entries.len();
+        self.entries.retain(|entry| entry.hash != hash);
+        self.entries.len() != len
+    }
+
+    /// Unique hashes, most recent first
+    #[must_use]
+    pub fn hashes(&self) -> Vec<&str> {
+        let mut hashes: Vec<&str> = Vec::with_capacity(self.entries.len());
+        for entry in self.entries.iter().rev() {
+            if !hashes.contains(&entry.hash.as_str()) {
+                hashes.push(&entry.hash);
+            }
+        }
+        hashes
+    }
+
+    /// The hash starting with `short`, `None` if there's none
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`Error::Ambiguous`] if more than one hash starts with it
+    pub fn expand(&self, short: &str) -> Result<Option<&str>> {
+        let mut matching: Vec<&str> = self
+            .hashes()
+            .into_iter()
+            .filter(|hash| hash.starts_with(short))
+            .collect();
+
+        match matching.len() {
+            0 | 1 => Ok(matching.pop()),
+            _ => Err(Error::Ambiguous(
+                matching.into_iter().map(ToOwned::to_owned).collect(),
+            )),
+        }
+    }
+}
+
+/// Loads the index, inserts the entry and saves it back
+///
+/// # Errors
+///
+/// Inherits errors of [`Index::load`] and [`Index::save`]
+pub fn record(entry: Entry) -> Result<()> {
+    let mut index = Index::load()?;
+    index.insert(entry);
+    index.save()
+}
+
+/// Same as [`record`] but only warns on failure, the index must never fail an operation
+pub fn record_or_warn(entry: Entry) {
+    if let Err(err) = record(entry) {
+        warnln!("failed to update the local cache index: {}", err);
+    }
+}
+
+/// Server responses that are cached
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResponseKind {
+    /// What the server supports, it's limits included. They only change when it's redeployed
+    Capabilities,
+    /// Whether the server has a hash. Gistits expire, so it's not trusted for long
+    Exists,
+}
+
+impl ResponseKind {
+    const ALL: [Self; 2] = [Self::Capabilities, Self::Exists];
+
+    const fn ttl_secs(self) -> u64 {
+        match self {
+            Self::Capabilities => 60 * 60,
+            Self::Exists => 5 * 60,
+        }
+    }
+
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Capabilities => "capabilities",
+            Self::Exists => "exists",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Cached {
+    kind: ResponseKind,
+    value: serde_json::Value,
+    /// Sent back once it's expired, the server answers `304` if it's still current
+    etag: Option<String>,
+    /// Seconds since unix epoch
+    expires_at: u64,
+}
+
+/// How lookups of a kind of response went
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Stats {
+    /// Answered from the cache
+    pub hits: u64,
+    /// Expired, but the server confirmed it's still current
+    pub revalidated: u64,
+    /// Asked the server
+    pub misses: u64,
+}
+
+impl Stats {
+    /// Share of lookups the server didn't have to answer again, `None` before the first
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn hit_rate(&self) -> Option<f64> {
+        let total = self.hits + self.revalidated + self.misses;
+        (total > 0).then(|| (self.hits + self.revalidated) as f64 / total as f64)
+    }
+}
+
+/// A cached response, as found by [`Responses::lookup`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Lookup<T> {
+    Fresh(T),
+    /// Expired, but it may still be current. Ask the server with it's `etag`
+    Stale {
+        value: T,
+        etag: String,
+    },
+    Missing,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Responses {
+    /// By the url asked, and the hash for content addressed responses
+    entries: BTreeMap<String, Cached>,
+    pub stats: BTreeMap<ResponseKind, Stats>,
+}
+
+impl Responses {
+    /// Path of the cached responses file
+    ///
+    /// # Errors
+    ///
+    /// Fails if the system doesn't have a HOME directory
+    pub fn path() -> Result<PathBuf> {
+        Ok(path::data()?.join(RESPONSES_FILE_NAME))
+    }
+
+    /// Reads the cached responses file, empty if it doesn't exist or can't be decoded
+    #[must_use]
+    pub fn load() -> Self {
+        Self::path()
+            .ok()
+            .and_then(|responses_path| fs::read(responses_path).ok())
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+
+    /// Writes the cached responses file, overwriting it
+    ///
+    /// # Errors
+    ///
+    /// Fails if can't write to the data directory
+    pub fn save(&self) -> Result<()> {
+        fs::write(Self::path()?, serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+
+    /// The response cached under `key`. Counts a hit if it's fresh and a miss otherwise, stale
+    /// ones until they're revalidated
+    pub fn lookup<T: DeserializeOwned>(
+        &mut self,
+        kind: ResponseKind,
+        key: &str,
+        now: u64,
+    ) -> Lookup<T> {
+        let lookup = self
+            .entries
+            .get(key)
+            .filter(|cached| cached.kind == kind)
+            .map_or(Lookup::Missing, |cached| {
+                match serde_json::from_value(cached.value.clone()) {
+                    Ok(value) if cached.expires_at > now => Lookup::Fresh(value),
+                    Ok(value) => cached
+                        .etag
+                        .clone()
+                        .map_or(Lookup::Missing, |etag| Lookup::Stale { value, etag }),
+                    Err(_) => Lookup::Missing,
+                }
+            });
+
+        let stats = self.stats.entry(kind).or_default();
+        match lookup {
+            Lookup::Fresh(_) => stats.hits += 1,
+            Lookup::Stale { .. } | Lookup::Missing => stats.misses += 1,
+        }
+        lookup
+    }
+
+    /// Caches `value` under `key` for as long as responses of `kind` are trusted
+    pub fn insert<T: Serialize>(
+        &mut self,
+        kind: ResponseKind,
+        key: &str,
+        value: &T,
+        etag: Option<String>,
+        now: u64,
+    ) {
+        let value = match serde_json::to_value(value) {
+            Ok(value) => value,
+            Err(_) => return,
+        };
+        self.entries.insert(
+            key.to_owned(),
+            Cached {
+                kind,
+                value,
+                etag,
+                expires_at: now + kind.ttl_secs(),
+            },
+        );
+
+        if self.entries.len() > RESPONSES_MAX_ENTRIES {
+            let overflow = self.entries.len() - RESPONSES_MAX_ENTRIES;
+            let mut expiring: Vec<(u64, String)> = self
+                .entries
+                .iter()
+                .map(|(key, cached)| (cached.expires_at, key.clone()))
+                .collect();
+            expiring.sort_unstable();
+            for (_, key) in expiring.iter().take(overflow) {
+                self.entries.remove(key);
+            }
+        }
+    }
+
+    /// Trusts the stale response under `key` for longer, the server said it's still current
+    pub fn revalidate(&mut self, kind: ResponseKind, key: &str, now: u64) {
+        if let Some(cached) = self.entries.get_mut(key) {
+            cached.expires_at = now + kind.ttl_secs();
+        }
+        let stats = self.stats.entry(kind).or_default();
+        stats.misses = stats.misses.saturating_sub(1);
+        stats.revalidated += 1;
+    }
+
+    /// Responses of `kind` cached, fresh or not
+    #[must_use]
+    pub fn len(&self, kind: ResponseKind) -> usize {
+        self.entries
+            .values()
+            .filter(|cached| cached.kind == kind)
+            .count()
+    }
+}
+
+/// Loads the cached responses, hands them to `f` and saves them back. Never fails, the cache only
+/// spares requests
+pub fn responses<T>(f: impl FnOnce(&mut Responses) -> T) -> T {
+    let mut responses = Responses::load();
+    let out = f(&mut responses);
+    if let Err(err) = responses.save() {
+        tracing::debug!("failed to save cached server responses: {}", err);
+    }
+    out
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Stats,
+}
+
+impl Action {
+    pub fn from_args(
+        args: &ArgMatches,
+    ) -> Result<Box<dyn Dispatch<InnerData = Config> + Send + Sync + 'static>> {
+        let action = match args.subcommand() {
+            Some(("stats", _)) => Self::Stats,
+            _ => return Err(Error::Argument("missing subcommand", "cache")),
+        };
+        Ok(Box::new(action))
+    }
+}
+
+pub struct Config {
+    index: Index,
+    responses: Responses,
+}
+
+#[async_trait]
+impl Dispatch for Action {
+    type InnerData = Config;
+
+    async fn prepare(&self) -> Result<Self::InnerData> {
+        Ok(Config {
+            index: Index::load()?,
+            responses: Responses::load(),
+        })
+    }
+
+    async fn dispatch(&self, config: Self::InnerData) -> Result<()> {
+        match self {
+            Self::Stats => {
+                finish!(format!(
+                    "\n    index: {} gistits in '{}'\n    responses: '{}'\n{}\n",
+                    config.index.hashes().len(),
+                    Index::path()?.to_string_lossy(),
+                    Responses::path()?.to_string_lossy(),
+                    format_stats(&config.responses)
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A line for every kind of cached response, with it's hit rate
+fn format_stats(responses: &Responses) -> String {
+    ResponseKind::ALL
+        .into_iter()
+        .map(|kind| {
+            let stats = responses.stats.get(&kind).copied().unwrap_or_default();
+            let hit_rate = stats.hit_rate().map_or_else(
+                || style("no lookups yet").dim().to_string(),
+                |rate| format!("{:.0}% hit rate", rate * 100.0),
+            );
+            format!(
+                "      {}: {} cached, {} hits, {} revalidated, {} misses, {}\n",
+                kind.name(),
+                responses.len(kind),
+                stats.hits,
+                stats.revalidated,
+                stats.misses,
+                hit_rate
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_index_insert_dedup_and_order() {
+        let mut index = Index::default();
+        index.insert(Entry::new("aaa", "foo.rs", "me", Origin::Sent));
+        index.insert(Entry::new("bbb", "bar.rs", "me", Origin::Fetched));
+        index.insert(Entry::new("aaa", "foo.rs", "me", Origin::Fetched));
+        index.insert(Entry::new("aaa", "foo.rs", "me", Origin::Fetched));
+
+        assert_eq!(index.entries.len(), 3);
+        assert_eq!(index.hashes(), vec!["aaa", "bbb"]);
+    }
+
+    #[test]
+    fn cache_index_remove() {
+        let mut index = Index::default();
+        index.insert(Entry::new("aaa", "foo.rs", "me", Origin::Sent));
+        index.insert(Entry::new("aaa", "foo.rs", "me", Origin::Fetched));
+        index.insert(Entry::new("bbb", "bar.rs", "me", Origin::Fetched));
+
+        assert!(index.remove("aaa"));
+        assert!(!index.remove("aaa"));
+        assert_eq!(index.hashes(), vec!["bbb"]);
+    }
+
+    #[test]
+    fn cache_index_expand_short_hash() {
+        let mut index = Index::default();
+        index.insert(Entry::new("abc1234ff", "foo.rs", "me", Origin::Sent));
+        index.insert(Entry::new("abc1234ee", "bar.rs", "me", Origin::Fetched));
+        index.insert(Entry::new("abc1234ee", "bar.rs", "me", Origin::Sent));
+
+        assert_eq!(index.expand("abc1234f").unwrap(), Some("abc1234ff"));
+        assert_eq!(index.expand("abc1234e").unwrap(), Some("abc1234ee"));
+        assert_eq!(index.expand("def").unwrap(), None);
+        assert!(matches!(
+            index.expand("abc1234"),
+            Err(Error::Ambiguous(candidates)) if candidates.len() == 2
+        ));
+    }
+
+    #[test]
+    fn cache_index_bounded() {
+        let mut index = Index::default();
+        for n in 0..INDEX_MAX_ENTRIES + 10 {
+            index.insert(Entry::new(&n.to_string(), "foo.rs", "me", Origin::Sent));
+        }
+
+        assert_eq!(index.entries.len(), INDEX_MAX_ENTRIES);
+        assert_eq!(index.entries.first().unwrap().hash, "10");
+    }
+
+    #[test]
+    fn cache_responses_lookup() {
+        let mut responses = Responses::default();
+        let key = "https://server/capabilities";
+        assert_eq!(
+            responses.lookup::<u32>(ResponseKind::Capabilities, key, 0),
+            Lookup::Missing
+        );
+
+        responses.insert(
+            ResponseKind::Capabilities,
+            key,
+            &7_u32,
+            Some("\"v1\"".to_owned()),
+            0,
+        );
+        assert_eq!(
+            responses.lookup::<u32>(ResponseKind::Capabilities, key, 60),
+            Lookup::Fresh(7)
+        );
+        // Same key, other kind
+        assert_eq!(
+            responses.lookup::<u32>(ResponseKind::Exists, key, 60),
+            Lookup::Missing
+        );
+
+        let expired = ResponseKind::Capabilities.ttl_secs();
+        assert_eq!(
+            responses.lookup::<u32>(ResponseKind::Capabilities, key, expired),
+            Lookup::Stale {
+                value: 7,
+                etag: "\"v1\"".to_owned()
+            }
+        );
+        responses.revalidate(ResponseKind::Capabilities, key, expired);
+        assert_eq!(
+            responses.lookup::<u32>(ResponseKind::Capabilities, key, expired),
+            Lookup::Fresh(7)
+        );
+
+        let stats = responses.stats[&ResponseKind::Capabilities];
+        assert_eq!((stats.hits, stats.revalidated, stats.misses), (2, 1, 1));
+        assert_eq!(stats.hit_rate(), Some(0.75));
+        assert_eq!(Stats::default().hit_rate(), None);
+    }
+
+    #[test]
+    fn cache_responses_without_etag_expire() {
+        let mut responses = Responses::default();
+        responses.insert(ResponseKind::Exists, "hash", &true, None, 0);
+
+        let expired = ResponseKind::Exists.ttl_secs();
+        assert_eq!(
+            responses.lookup::<bool>(ResponseKind::Exists, "hash", expired),
+            Lookup::Missing
+        );
+    }
+
+    #[test]
+    fn cache_responses_bounded() {
+        let mut responses = Responses::default();
+        for n in 0..RESPONSES_MAX_ENTRIES + 10 {
+            responses.insert(ResponseKind::Exists, &n.to_string(), &true, None, n as u64);
+        }
+
+        assert_eq!(responses.len(ResponseKind::Exists), RESPONSES_MAX_ENTRIES);
+        assert_eq!(
+            responses.lookup::<bool>(ResponseKind::Exists, "9", 0),
+            Lookup::Missing
+        );
+        assert_eq!(
+            responses.lookup::<bool>(ResponseKind::Exists, "10", 0),
+            Lookup::Fresh(true)
+        );
+    }
+}
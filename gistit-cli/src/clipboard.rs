@@ -5,13 +5,17 @@
 //! after the program exists we have to rely on not so reliable methods to achieve this behaviour.
 //!
 //! Here we do our best efforts look for the most common clipboard binaries, spawn a child process, and pipe the
-//! contents into it's 'stdin'. If no binary was found we'll fallback to OSC52 escape sequence.
+//! contents into it's 'stdin'. Every provider that fits the environment is tried in order, so
+//! copying degrades gracefully: clipboard binaries first, then the OSC52 escape sequence if
+//! there's a terminal to write it to, and at last a file in the data directory.
 //! [OSC52](https://www.reddit.com/r/vim/comments/k1ydpn/a_guide_on_how_to_copy_text_from_anywhere/)
 //!
 //! credits: this implementation is heavily inspired on
 //! [copypasta](https://docs.rs/copypasta/0.7.1/copypasta/)
 //!
-//! **note** we're not interested in 'paste' functionallity
+//! Reading the clipboard back, used by `gistit watch-clipboard`, only works with the binary
+//! approach. Each copy program is paired with it's paste counterpart (`xclip -o`, `xsel -o`,
+//! `wl-paste`, `pbpaste` and `powershell.exe Get-Clipboard`), OSC52 can't be read from.
 //!
 //! # Linux/BSD
 //!
@@ -28,17 +32,17 @@
 //!
 //! ## Wayland
 //!
-//! Will look for `wl-copy` binary.
+//! Will look for `wl-copy` binary, then the X11 ones in case XWayland is running.
 //!
 //! ## Tty (SSH session)
 //!
 //! Under this condition we'll do a couple of extra checks to ensure X11 Passthrough is
-//! working, otherwise clipboard usage is unlikely to succeed (?).
+//! working, otherwise X11 clipboard binaries are skipped.
 //!
 //! 1. checks for `xauth` binary, utility to manage X11 session cookies.
 //! 2. reads `DISPLAY` env variable to ensure it's set with 'localhost:' something something.
 //!
-//! If the above are ok we check for X11 clipboard binaries to use.
+//! Most of the time OSC52 is what ends up working, the local terminal sets the clipboard.
 //!
 //! # Mac OS
 //!
@@ -47,17 +51,23 @@
 //!
 //! # Windows
 //!
-//! Doesn't make sense to check for `clip.exe` because it's default installation. Anyhow, we're
-//! not using it under this platform. This can change in the future
+//! `clip.exe` comes with every installation, the clipboard is read back with `powershell.exe`.
 use std::env;
 use std::ffi::OsString;
-use std::io::Write;
+use std::fs;
+use std::io::{self, Write};
 use std::path::PathBuf;
 use std::process::{Command, Stdio};
 
+use console::Term;
 use which::which;
 
-use crate::{error, Result};
+use gistit_project::path;
+
+use crate::{error, warnln, Result};
+
+/// Where the content goes when no other provider works, inside the data directory
+pub const CLIPBOARD_FILE_NAME: &str = "clipboard.txt";
 
 /// The clipboard structure, holds the content string
 #[derive(Clone, Debug)]
@@ -86,7 +96,19 @@ pub struct EscapeSequence {
     selected: Selected,
 }
 
-/// The display server type
+/// The clipboard of last resort, a file in the data directory
+#[derive(Clone, Debug)]
+pub struct File {
+    selected: Selected,
+}
+
+/// Every provider fitting the environment, tried in order until one succeeds
+pub struct Chain {
+    providers: Vec<Box<dyn Provider>>,
+}
+
+/// The display server type, Linux/BSD ones are never selected elsewhere
+#[cfg_attr(any(target_os = "macos", target_os = "windows"), allow(dead_code))]
 #[derive(Clone, Debug)]
 #[cfg_attr(test, derive(Eq, PartialEq))]
 enum DisplayKind {
@@ -95,20 +117,27 @@ enum DisplayKind {
     Wsl,
     SshTty,
     Unknown,
-    #[cfg(target_is = "macos")]
+    #[cfg(target_os = "macos")]
     MacOs,
     #[cfg(target_os = "windows")]
     Windows,
 }
 
 /// Returns the current display server
-fn select_display() -> DisplayKind {
-    #[cfg(target_os = "windows")]
-    return DisplayKind::Windows;
+#[cfg(target_os = "windows")]
+const fn select_display() -> DisplayKind {
+    DisplayKind::Windows
+}
 
-    #[cfg(target_is = "macos")]
-    return DisplayKind::MacOs;
+/// Returns the current display server
+#[cfg(target_os = "macos")]
+const fn select_display() -> DisplayKind {
+    DisplayKind::MacOs
+}
 
+/// Returns the current display server
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+fn select_display() -> DisplayKind {
     // Linux/BSD only
     if is_wsl() {
         DisplayKind::Wsl
@@ -124,7 +153,7 @@ fn select_display() -> DisplayKind {
 }
 
 /// Checks whether we're under windows subsystem for linux
-#[cfg(target_family = "unix")]
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
 fn is_wsl() -> bool {
     env::var("WSL_DISTRO_NAME").is_ok()
         || env::var("WT_SESSION").is_ok()
@@ -135,10 +164,7 @@ fn is_wsl() -> bool {
 /// This function is avaiable only under Linux/BSD environment so no extra checks are needed.
 /// **note** that this is best to run before checking for X11 because `DISPLAY` var can also be set
 /// under Wayland.
-#[cfg(all(
-    target_family = "unix",
-    not(all(target_os = "macos", target_os = "ios", target_os = "android"))
-))]
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
 fn is_wayland() -> bool {
     let mut score = 0;
     match env::var("XDG_SESSION_TYPE").ok().as_deref() {
@@ -153,10 +179,7 @@ fn is_wayland() -> bool {
 
 /// Check whether or not in X11
 /// This function is avaiable only under Linux/BSD environment so no extra checks are needed.
-#[cfg(all(
-    target_family = "unix",
-    not(all(target_os = "macos", target_os = "ios", target_os = "android"))
-))]
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
 fn is_x11() -> bool {
     let mut score = 0;
     match env::var("XDG_SESSION_TYPE").ok().as_deref() {
@@ -172,10 +195,7 @@ fn is_x11() -> bool {
 /// Checks whether or not in TTY.
 /// The default session type under SSH is `tty` so we make sure to assert both things
 /// since we're not supporting clipboard under raw tty sessions.
-#[cfg(all(
-    target_family = "unix",
-    not(all(target_os = "macos", target_os = "ios", target_os = "android"))
-))]
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
 fn is_ssh_tty() -> bool {
     let tty = env::var("XDG_SESSION_TYPE").as_deref() == Ok("tty");
     let ssh = env::var("SSH_CLIENT").is_ok();
@@ -191,18 +211,12 @@ impl Clipboard {
         }
     }
 
-    /// Tries to select the current display
-    ///
-    /// # Errors
-    ///
-    /// Fails with [`ClipboardError`] error
-    pub fn try_into_selected(self) -> Result<Selected> {
-        match select_display() {
-            DisplayKind::Unknown => Err(error::Clipboard::UnsupportedPlatform.into()),
-            valid => Ok(Selected {
-                display: valid,
-                content: self.content,
-            }),
+    /// Selects the current display, an unknown one still has OSC52 and the file to fall back to
+    #[must_use]
+    pub fn into_selected(self) -> Selected {
+        Selected {
+            display: select_display(),
+            content: self.content,
         }
     }
 }
@@ -213,8 +227,15 @@ pub trait Provider {
     ///
     /// # Errors
     ///
-    /// Fails with [`ClipboardError`]
+    /// Fails with [`error::Clipboard`]
     fn set_contents(&self) -> Result<()>;
+
+    /// Attempt to read the system clipboard contents
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`error::Clipboard`]
+    fn get_contents(&self) -> Result<String>;
 }
 
 impl Provider for Binary {
@@ -227,7 +248,9 @@ impl Provider for Binary {
             ClipboardBinProgram::Xsel => {
                 command.arg("--clipboard");
             }
-            ClipboardBinProgram::WlCopy | ClipboardBinProgram::ClipExe => (),
+            ClipboardBinProgram::WlCopy
+            | ClipboardBinProgram::ClipExe
+            | ClipboardBinProgram::PbCopy => (),
         };
         let mut process = command
             .stdin(Stdio::piped())
@@ -241,150 +264,204 @@ impl Provider for Binary {
             .expect("to access stdin")
             .write_all(self.selected.content.as_bytes())?;
 
-        let _status = process.wait()?;
+        // `xclip` without a reachable display, for one, fails right away
+        if process.wait()?.success() {
+            Ok(())
+        } else {
+            Err(error::Clipboard::ProgramFailed.into())
+        }
+    }
+
+    fn get_contents(&self) -> Result<String> {
+        let mut command = match self.program {
+            ClipboardBinProgram::Xclip => {
+                let mut command = Command::new(&self.bin);
+                command.arg("-sel").arg("clip").arg("-o");
+                command
+            }
+            ClipboardBinProgram::Xsel => {
+                let mut command = Command::new(&self.bin);
+                command.arg("--clipboard").arg("--output");
+                command
+            }
+            ClipboardBinProgram::WlCopy => {
+                let mut command = Command::new(which("wl-paste")?);
+                command.arg("--no-newline");
+                command
+            }
+            ClipboardBinProgram::ClipExe => {
+                let mut command = Command::new("powershell.exe");
+                command
+                    .arg("-NoProfile")
+                    .arg("-Command")
+                    .arg("Get-Clipboard");
+                command
+            }
+            ClipboardBinProgram::PbCopy => Command::new(which("pbpaste")?),
+        };
+        let output = command
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()?;
 
-        Ok(())
+        if output.status.success() {
+            Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+        } else {
+            Err(error::Clipboard::ProgramFailed.into())
+        }
+    }
+}
+
+impl EscapeSequence {
+    /// Terminals read the sequence from whatever they display, stderr stays one when stdout is
+    /// piped somewhere
+    fn supported() -> bool {
+        Term::stderr().is_term()
     }
 }
 
 impl Provider for EscapeSequence {
     fn set_contents(&self) -> Result<()> {
-        print!("\x1B]52;c;{}\x07", base64::encode(&self.selected.content));
+        let sequence = format!("\x1B]52;c;{}\x07", base64::encode(&self.selected.content));
+        let sequence = if env::var_os("TMUX").is_some() {
+            // Passed through tmux to the terminal it runs in
+            format!("\x1BPtmux;{}\x1B\\", sequence.replace('\x1B', "\x1B\x1B"))
+        } else {
+            sequence
+        };
+
+        let mut stderr = io::stderr();
+        stderr.write_all(sequence.as_bytes())?;
+        stderr.flush()?;
         Ok(())
     }
+
+    fn get_contents(&self) -> Result<String> {
+        Err(error::Clipboard::ReadUnsupported.into())
+    }
+}
+
+impl Provider for File {
+    fn set_contents(&self) -> Result<()> {
+        let file_path = path::data()?.join(CLIPBOARD_FILE_NAME);
+        fs::write(&file_path, &self.selected.content)?;
+        warnln!(format!(
+            "no clipboard reachable, copied to '{}' instead",
+            file_path.to_string_lossy()
+        ));
+        Ok(())
+    }
+
+    fn get_contents(&self) -> Result<String> {
+        Err(error::Clipboard::ReadUnsupported.into())
+    }
+}
+
+impl Provider for Chain {
+    fn set_contents(&self) -> Result<()> {
+        let mut last_err = None;
+        for provider in &self.providers {
+            match provider.set_contents() {
+                Ok(()) => return Ok(()),
+                Err(err) => {
+                    tracing::debug!("clipboard provider failed: {}", err);
+                    last_err = Some(err);
+                }
+            }
+        }
+        Err(last_err.unwrap_or_else(|| error::Clipboard::ProgramFailed.into()))
+    }
+
+    fn get_contents(&self) -> Result<String> {
+        let mut last_err = None;
+        for provider in &self.providers {
+            match provider.get_contents() {
+                Ok(content) => return Ok(content),
+                Err(err) => last_err = Some(err),
+            }
+        }
+        Err(last_err.unwrap_or_else(|| error::Clipboard::ReadUnsupported.into()))
+    }
 }
 
 impl Selected {
     /// Transforms this clipboard into a ready-to-use kind
-    /// First checks for binaries and fallbacks to the ANSI escape sequence approach.
+    /// The clipboard binaries found for the display server first, then the ANSI escape sequence
+    /// approach and the file in the data directory.
     #[must_use]
     pub fn into_provider(self) -> Box<dyn Provider> {
-        match self.try_into_bin() {
-            Ok(bin_clipboard) => {
-                return Box::new(bin_clipboard);
+        let mut providers: Vec<Box<dyn Provider>> = self
+            .binaries()
+            .into_iter()
+            .map(|(bin, program)| {
+                Box::new(Binary {
+                    bin: bin.into_os_string(),
+                    selected: self.clone(),
+                    program,
+                }) as Box<dyn Provider>
+            })
+            .collect();
+
+        if EscapeSequence::supported() {
+            providers.push(Box::new(EscapeSequence {
+                selected: self.clone(),
+            }));
+        }
+        providers.push(Box::new(File { selected: self }));
+
+        Box::new(Chain { providers })
+    }
+
+    /// Supported clipboard binaries of the display server that are installed, in order of
+    /// preference
+    fn binaries(&self) -> Vec<(PathBuf, ClipboardBinProgram)> {
+        let x11 = [
+            ("xclip", ClipboardBinProgram::Xclip),
+            ("xsel", ClipboardBinProgram::Xsel),
+        ];
+        let candidates: Vec<(&str, ClipboardBinProgram)> = match self.display {
+            DisplayKind::X11 => x11.to_vec(),
+            DisplayKind::Wayland => {
+                let mut candidates = vec![("wl-copy", ClipboardBinProgram::WlCopy)];
+                candidates.extend(x11);
+                candidates
             }
-            Err(err) => {
-                println!("{:?}", err);
+            DisplayKind::SshTty => {
+                // `xauth` missing or `DISPLAY` not set most likely mean display passthrough isn't
+                // working
+                if which("xauth").is_ok() && env::var_os("DISPLAY").is_some() {
+                    x11.to_vec()
+                } else {
+                    Vec::new()
+                }
             }
-        }
-        Box::new(EscapeSequence { selected: self })
+            DisplayKind::Wsl => vec![("clip.exe", ClipboardBinProgram::ClipExe)],
+            DisplayKind::Unknown => Vec::new(),
+            #[cfg(target_os = "macos")]
+            DisplayKind::MacOs => vec![("pbcopy", ClipboardBinProgram::PbCopy)],
+            #[cfg(target_os = "windows")]
+            DisplayKind::Windows => vec![("clip.exe", ClipboardBinProgram::ClipExe)],
+        };
+
+        candidates
+            .into_iter()
+            .filter_map(|(name, program)| which(name).ok().map(|bin| (bin, program)))
+            .collect()
     }
 }
 
 /// Currently supported clipboard programs
 #[non_exhaustive]
-#[derive(Clone, Debug)]
+#[derive(Clone, Copy, Debug)]
 enum ClipboardBinProgram {
     Xclip,
     Xsel,
     ClipExe,
     WlCopy,
-    #[cfg(all(target_os = "macos", target_os = "ios"))]
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     PbCopy,
 }
 
-#[cfg(all(
-    target_family = "unix",
-    not(all(target_os = "macos", target_os = "ios", target_os = "android"))
-))]
-impl Selected {
-    /// Checks for supported clipboard binaries and attempts to convert the selected clipboard into
-    /// the binary implementation.
-    ///
-    /// # Errors
-    ///
-    /// Will fail with [`ClipboardError`] when any matched display server misses it's supported
-    /// clipboard binaries.
-    fn try_into_bin(&self) -> Result<Binary> {
-        let (bin, program) = match self.display {
-            DisplayKind::X11 => {
-                let mut binaries = [
-                    (which("xclip"), ClipboardBinProgram::Xclip),
-                    (which("xsel"), ClipboardBinProgram::Xsel),
-                    // TODO: Add more supported clipboard programs here
-                ]
-                .into_iter();
-
-                let (bin, program) = binaries
-                    .find(|(bin, _)| bin.is_ok())
-                    .ok_or(error::Clipboard::MissingBinary)?;
-                // Safe to unwrap since we previously checked `bin.is_ok()`
-                (bin.unwrap(), program)
-            }
-            DisplayKind::Wayland => {
-                let bin = which("wl-copy")?;
-                let program = ClipboardBinProgram::WlCopy;
-                (bin, program)
-            }
-            DisplayKind::SshTty => {
-                //`xauth` missing most likely mean display passthrough isn't working
-                let _xauth = which("xauth")?;
-
-                // DISPALY variable different than `localhost:...` is a bad sign as well
-                env::var("DISPLAY").map_err(|_| error::Clipboard::DisplayNotSet)?;
-
-                let mut binaries = [
-                    (which("xclip"), ClipboardBinProgram::Xclip),
-                    (which("xsel"), ClipboardBinProgram::Xsel),
-                    // TODO: Add more supported clipboard programs here
-                ]
-                .into_iter();
-
-                let (bin, program) = binaries
-                    .find(|(bin, _)| bin.is_ok())
-                    .ok_or(error::Clipboard::MissingBinary)?;
-                // Safe to unwrap since we previously checked `bin.is_ok()`
-                (bin.unwrap(), program)
-            }
-            DisplayKind::Wsl => {
-                let bin = PathBuf::from("clip.exe");
-                let program = ClipboardBinProgram::ClipExe;
-                (bin, program)
-            }
-            DisplayKind::Unknown => panic!("clipboard feature not supported"),
-        };
-        Ok(Binary {
-            bin: bin.as_os_str().to_owned(),
-            selected: self.clone(),
-            program,
-        })
-    }
-}
-
-#[cfg(all(target_os = "macos", target_os = "ios"))]
-impl Selected {
-    /// Checks for supported clipboard binaries and attempts to convert the selected clipboard into
-    /// the binary implementation.
-    ///
-    /// # Errors
-    ///
-    /// Will fail with [`ClipboardError`] when any matched display server misses it's supported
-    /// clipboard binaries.
-    fn try_into_bin(&self) -> Result<Binary> {
-        let bin = match self.display {
-            DisplayKind::MacOs => which("pbcopy")
-                .ok()
-                .map(|t| t.as_os_str().to_owned())
-                .ok_or(ErrorKind::MissingClipboardBinary)?,
-            DisplayKind::Unknown => panic!("clipboard feature not supported"),
-        };
-        let program = ClipboardBinProgram::PbCopy;
-        Ok(Binary {
-            bin,
-            program,
-            selected: self.clone(),
-        })
-    }
-}
-
-/// Not supported
-#[cfg(target_os = "windows")]
-impl Selected {
-    fn try_into_bin(&self) -> Result<Binary> {
-        Err(())
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -399,16 +476,20 @@ mod tests {
         env::remove_var("WT_SESSION");
         env::remove_var("WSL_INTEROP");
 
+        let clip0 = Clipboard::new("qux").into_selected();
+        assert_eq!(clip0.display, DisplayKind::Unknown);
+        assert!(clip0.binaries().is_empty());
+
         env::set_var("DISPLAY", "localhost");
-        let clip1 = Clipboard::new("foo").try_into_selected().unwrap();
+        let clip1 = Clipboard::new("foo").into_selected();
         assert_eq!(clip1.display, DisplayKind::X11);
 
         env::set_var("WAYLAND_DISPLAY", "wayland");
-        let clip2 = Clipboard::new("bar").try_into_selected().unwrap();
+        let clip2 = Clipboard::new("bar").into_selected();
         assert_eq!(clip2.display, DisplayKind::Wayland);
 
         env::set_var("WSL_DISTRO_NAME", "hanna_montana_linux");
-        let clip3 = Clipboard::new("baz").try_into_selected().unwrap();
+        let clip3 = Clipboard::new("baz").into_selected();
         assert_eq!(clip3.display, DisplayKind::Wsl);
     }
 }
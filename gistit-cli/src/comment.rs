@@ -0,0 +1,207 @@
+//! The comment module
+//!
+//! `gistit comment <hash> "message"` adds a comment to the thread of a gistit, signed with
+//! `--author` or the `author` setting, and `gistit comment <hash>` lists the thread. Threads are
+//! kept by the server only, `gistit fetch --comments` prints them below the preview.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use clap::ArgMatches;
+use console::style;
+
+use gistit_core::comment::{comment_server, comments_server, new_comment};
+use gistit_proto::Comment;
+
+use gistit_project::path;
+
+use crate::credentials;
+use crate::dispatch::Dispatch;
+use crate::fetch::{self, Source};
+use crate::param::{check, Identifier};
+use crate::pin::fmt_ago;
+use crate::send::random_author;
+use crate::server::{self, SERVER_URL_BASE};
+use crate::settings::get_runtime_settings;
+use crate::{finish, progress, updateln, Error, Result};
+
+#[derive(Debug, Clone)]
+pub struct Action<'a> {
+    pub hash: &'a str,
+    /// Lists the thread instead when missing
+    pub message: Option<&'a str>,
+    pub author: Option<&'a str>,
+    pub token: Option<&'a str>,
+}
+
+impl<'a> Action<'a> {
+    pub fn from_args(
+        args: &'a ArgMatches,
+    ) -> Result<Box<dyn Dispatch<InnerData = Config> + Send + Sync + 'a>> {
+        Ok(Box::new(Self {
+            hash: args
+                .value_of("HASH")
+                .ok_or(Error::Argument("missing argument", "[HASH]"))?,
+            message: args.value_of("MESSAGE"),
+            author: args.value_of("author"),
+            token: args.value_of("token"),
+        }))
+    }
+}
+
+pub struct Config {
+    hash: String,
+    /// The comment to post, `None` to list the thread
+    comment: Option<Comment>,
+    token: Option<String>,
+}
+
+#[async_trait]
+impl<'a> Dispatch for Action<'a> {
+    type InnerData = Config;
+
+    async fn prepare(&self) -> Result<Self::InnerData> {
+        progress!("Preparing");
+        let hash = server_hash(check::identifier(self.hash)?).await?;
+        let token = self
+            .token
+            .map(check::token)
+            .transpose()?
+            .map(ToOwned::to_owned)
+            .or_else(|| credentials::owner_token(&hash));
+
+        let comment = match self.message {
+            Some(message) => {
+                let settings = get_runtime_settings()?;
+                let author = match (self.author, settings.author.as_deref()) {
+                    (Some(author), _) | (None, Some(author)) => check::author(author)?.to_owned(),
+                    (None, None) => random_author(),
+                };
+                Some(new_comment(
+                    &hash,
+                    &author,
+                    check::comment(message)?,
+                    token.as_deref(),
+                ))
+            }
+            None => None,
+        };
+        updateln!("Prepared");
+
+        Ok(Config {
+            hash,
+            comment,
+            token,
+        })
+    }
+
+    async fn dispatch(&self, config: Self::InnerData) -> Result<()> {
+        if let Some(ref comment) = config.comment {
+            progress!("Commenting");
+            server::retry_rate_limited(|| comment_server(&SERVER_URL_BASE, comment)).await?;
+            updateln!("Commented");
+        }
+
+        let comments = thread(&config.hash, config.token.as_deref()).await?;
+        finish!(format_comments(&comments, now()));
+        Ok(())
+    }
+}
+
+/// The hash `identifier` points to, threads are only kept by the server so gistits only known to
+/// the p2p network can't be commented on
+async fn server_hash(identifier: Identifier<'_>) -> Result<String> {
+    match identifier {
+        Identifier::Hash(hash) | Identifier::Server(hash) => Ok(hash.to_owned()),
+        Identifier::Short(short) | Identifier::ServerShort(short) => {
+            let mut bridge = gistit_ipc::client(&path::runtime_dir()?)?;
+            fetch::expand(&mut bridge, short, &[Source::Server]).await
+        }
+        Identifier::Peer(_) | Identifier::Name(_) => Err(Error::Argument(
+            "comments are kept by the server, p2p gistits can't have them",
+            "[HASH]",
+        )),
+    }
+}
+
+/// The comments on `hash`, oldest first
+///
+/// # Errors
+///
+/// Fails with [`gistit_core::Error::NotFound`] if the server doesn't have the gistit or `token`
+/// can't read it
+pub async fn thread(hash: &str, token: Option<&str>) -> Result<Vec<Comment>> {
+    progress!("Fetching comments");
+    let comments =
+        server::retry_rate_limited(|| comments_server(&SERVER_URL_BASE, hash, token)).await?;
+    updateln!("Fetched comments");
+    Ok(comments)
+}
+
+/// Seconds since the epoch, to tell how long ago comments were posted
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Check your system time")
+        .as_secs()
+}
+
+pub fn format_comments(comments: &[Comment], now: u64) -> String {
+    if comments.is_empty() {
+        return "\n    no comments yet, add one with `gistit comment <HASH> \"message\"`\n\n"
+            .to_owned();
+    }
+
+    let lines: Vec<String> = comments
+        .iter()
+        .map(|comment| {
+            let posted = comment.timestamp.parse::<u64>().map_or_else(
+                |_| comment.timestamp.clone(),
+                |millis| fmt_ago(now.saturating_sub(millis / 1000)),
+            );
+            let body: Vec<String> = comment
+                .body
+                .lines()
+                .map(|line| format!("      {}", line))
+                .collect();
+            format!(
+                "    {}, {}\n{}",
+                style(&comment.author).blue().bold(),
+                style(posted).dim(),
+                body.join("\n")
+            )
+        })
+        .collect();
+
+    format!("\n{}\n\n", lines.join("\n\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comment_format_thread() {
+        console::set_colors_enabled(false);
+        let mut first = new_comment("hash", "alice", "looks good\nship it", None);
+        first.timestamp = "3600000".to_owned();
+        let mut second = new_comment("hash", "bob", "thanks", None);
+        second.timestamp = "7200000".to_owned();
+
+        assert_eq!(
+            format_comments(&[first, second], 7200),
+            "\n    alice, 1h ago\n      looks good\n      ship it\n\n    bob, just now\n      thanks\n\n"
+        );
+        assert!(format_comments(&[], 0).contains("no comments yet"));
+    }
+
+    #[tokio::test]
+    async fn comment_server_hash_only() {
+        let hash = "a".repeat(64);
+        assert_eq!(server_hash(Identifier::Hash(&hash)).await.unwrap(), hash);
+        assert!(server_hash(Identifier::Peer(&hash)).await.is_err());
+        assert!(server_hash(Identifier::Name("@alice/snippet"))
+            .await
+            .is_err());
+    }
+}
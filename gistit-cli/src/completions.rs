@@ -0,0 +1,112 @@
+use std::io::{self, Write};
+
+use async_trait::async_trait;
+use clap::ArgMatches;
+use clap_complete::{generate, Shell};
+
+use crate::arg::app;
+use crate::cache::Index;
+use crate::dispatch::Dispatch;
+use crate::{Error, Result};
+
+const BIN_NAME: &str = "gistit";
+
+/// Completes `gistit fetch <HASH>` with hashes from the local cache index
+const BASH_DYNAMIC: &str = r#"
+_gistit_dynamic() {
+    local cur="${COMP_WORDS[COMP_CWORD]}"
+    if [[ ${COMP_CWORD} -ge 2 && ( "${COMP_WORDS[1]}" == "fetch" || "${COMP_WORDS[1]}" == "f" ) && "${cur}" != -* ]]; then
+        COMPREPLY=( $(compgen -W "$(gistit completions --hashes 2>/dev/null)" -- "${cur}") )
+        return 0
+    fi
+    _gistit "$@"
+}
+
+complete -F _gistit_dynamic -o bashdefault -o default gistit
+"#;
+
+const ZSH_DYNAMIC: &str = r#"
+_gistit_dynamic() {
+    if (( CURRENT > 2 )) && [[ ${words[2]} == (fetch|f) && ${words[CURRENT]} != -* ]]; then
+        local -a hashes
+        hashes=(${(f)"$(gistit completions --hashes 2>/dev/null)"})
+        compadd -a hashes
+    fi
+    _gistit "$@"
+}
+
+compdef _gistit_dynamic gistit
+"#;
+
+const FISH_DYNAMIC: &str = r#"
+complete -c gistit -n "__fish_seen_subcommand_from fetch f" -f -a "(gistit completions --hashes 2>/dev/null)"
+"#;
+
+#[derive(Debug, Clone)]
+pub struct Action<'a> {
+    pub shell: Option<&'a str>,
+    pub hashes: bool,
+}
+
+impl<'a> Action<'a> {
+    pub fn from_args(
+        args: &'a ArgMatches,
+    ) -> Result<Box<dyn Dispatch<InnerData = Config> + Send + Sync + 'a>> {
+        Ok(Box::new(Self {
+            shell: args.value_of("SHELL"),
+            hashes: args.is_present("hashes"),
+        }))
+    }
+}
+
+pub enum Config {
+    Script(Shell),
+    Hashes(Index),
+}
+
+#[async_trait]
+impl<'a> Dispatch for Action<'a> {
+    type InnerData = Config;
+
+    async fn prepare(&self) -> Result<Self::InnerData> {
+        if self.hashes {
+            // Completion must never fail loudly, an unreadable index completes nothing
+            return Ok(Config::Hashes(Index::load().unwrap_or_default()));
+        }
+
+        let shell = match self.shell {
+            Some("bash") => Shell::Bash,
+            Some("zsh") => Shell::Zsh,
+            Some("fish") => Shell::Fish,
+            Some("powershell") => Shell::PowerShell,
+            _ => return Err(Error::Argument("unsupported shell", "[SHELL]")),
+        };
+
+        Ok(Config::Script(shell))
+    }
+
+    async fn dispatch(&self, config: Self::InnerData) -> Result<()> {
+        let mut stdout = io::stdout();
+
+        match config {
+            Config::Script(shell) => {
+                generate(shell, &mut app(), BIN_NAME, &mut stdout);
+
+                let dynamic = match shell {
+                    Shell::Bash => BASH_DYNAMIC,
+                    Shell::Zsh => ZSH_DYNAMIC,
+                    Shell::Fish => FISH_DYNAMIC,
+                    _ => "",
+                };
+                stdout.write_all(dynamic.as_bytes())?;
+            }
+            Config::Hashes(index) => {
+                for hash in index.hashes() {
+                    writeln!(stdout, "{}", hash)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
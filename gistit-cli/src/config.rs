@@ -0,0 +1,441 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use clap::ArgMatches;
+use console::{style, Emoji};
+use url::Url;
+
+use gistit_project::path;
+
+use crate::dispatch::Dispatch;
+use crate::error::exit;
+use crate::param::check;
+use crate::server::SERVER_URL_GET;
+use crate::settings::{project_path, Settings, PROFILE_KEYS, SETTINGS_KEYS};
+use crate::{errorln, finish, interruptln, progress, updateln, warnln, Error, Result};
+
+/// Written by `gistit config init`. Keys are commented out so defaults keep applying until the
+/// user opts in.
+const SETTINGS_TEMPLATE: &str = r#"# Gistit settings
+#
+# Every key is optional. Commented out keys fall back to the built-in defaults.
+# Run `gistit config doctor` after editing to validate this file.
+
+# Colorscheme used to preview gistits. Run `gistit themes` to list available ones.
+# colorscheme = "Monokai Extended Origin"
+
+# Author attached to sent gistits. Defaults to a random generated name.
+# author = "Matthew McConaughey"
+
+# Where gistits are sent to and fetched from: "auto", "server" or "p2p".
+# "auto" uses the p2p network whenever `gistit-daemon` is running.
+# "p2p" starts `gistit-daemon` on demand if it isn't running.
+# backend = "auto"
+
+# Gistit server base url. `GISTIT_SERVER_URL` takes precedence over it.
+# server_url = "https://us-central1-gistit-base.cloudfunctions.net/"
+
+# Bootstrap peers of `gistit-daemon` when started by gistit, replacing the default libp2p ones.
+# Manage this list with `gistit daemon bootstrap add|remove|ls`.
+# bootstrap = ["/ip4/10.0.0.1/tcp/4001/p2p/12D3KooWDpJ7As7BWAwRMfu1VU2WCqNjvq387JEYKDBj4kx6nXTN"]
+
+# Connection gating rules of `gistit-daemon` when started by gistit, `allow|deny <peer id|ip[/prefix]>`.
+# Deny rules always win. Any allow rule turns the list into an allowlist, cutting off unlisted peers.
+# Edit them at runtime with `gistit daemon acl add|remove`.
+# acl = ["deny 10.0.0.0/8", "deny 12D3KooWDpJ7As7BWAwRMfu1VU2WCqNjvq387JEYKDBj4kx6nXTN"]
+
+# Relay traffic for peers behind NATs when `gistit-daemon` is started by gistit.
+# Only useful on machines with a public address. Unset quotas use the daemon defaults.
+#
+# [relay]
+# enabled = true
+# max_reservations = 128
+# max_circuits = 16
+# max_circuit_duration = 120 # seconds
+# max_circuit_bytes = 131072
+
+# Transfer limits of `gistit-daemon` when started by gistit, unset ones are unlimited.
+# Run `gistit daemon limits` after editing to apply them without a restart.
+#
+# [limits]
+# upload_rate = 1048576 # bytes per second
+# download_rate = 1048576 # bytes per second
+# peer_request_rate = 30 # requests per minute
+
+# Disk storage of `gistit-daemon` when started by gistit. Fetched and pinned gistits are kept and
+# served, the least recently used unpinned ones are evicted past the quota. Defaults to 256MiB.
+#
+# [storage]
+# quota = 268435456 # bytes
+
+# Extra listen addresses of `gistit-daemon` when started by gistit, per transport. It always
+# listens on TCP and QUIC at its host and port, QUIC addresses of a peer are dialed first. TCP and
+# WebSocket connections are secured with "noise" or "tls", peers need the same one. QUIC always
+# uses TLS.
+#
+# [transport]
+# tcp = ["/ip4/0.0.0.0/tcp/4001"]
+# websocket = ["/ip4/0.0.0.0/tcp/4002/ws"]
+# quic = ["/ip4/0.0.0.0/udp/4001/quic-v1"]
+# security = "noise"
+
+# Desktop notifications from `gistit-daemon` when started by gistit, when a hosted gistit is
+# fetched, a push arrives in the inbox or the node loses reachability.
+# notify = true
+
+# Visibility of gistits sent to the server: "public", "unlisted" or "private".
+# Private gistits are only fetchable with their owner token, kept in the OS credential store.
+# visibility = "unlisted"
+
+# Lifespan of gistits sent to the server, like `--lifespan`: "30m", "12h", "2d" or "never" if the
+# server keeps gistits forever. Defaults to the server's own default, a project settings file can
+# pick one for a repository.
+# lifespan = "2d"
+
+# Times `gistit fetch` asks again for a secret typed at `--secret-prompt` that doesn't decrypt the
+# gistit. 0 fails right away.
+# secret_retries = 2
+
+# Anonymous usage reporting: command names, error codes, the gistit version and the OS, never
+# arguments or contents. Off unless turned on here or with `gistit telemetry on`.
+# telemetry = false
+
+# Gistits sent without `--description` are described by their module header, first doc comment or
+# first function signature, for languages gistit knows the comments of.
+# auto_description = true
+
+# Workspace gistits are sent to: encrypted with it's shared key, announced on it's topic and
+# decrypted with it when fetching. Set by `gistit workspace create|join|use`, `--no-workspace`
+# skips it for a single send. A project settings file can pick a workspace for a repository.
+# workspace = "team"
+
+# Directory `gistit fetch --save` writes gistits fetched from the p2p network to, apart from the
+# ones fetched from the server. Only read from this file, never from a project settings file.
+# quarantine = "/home/me/gistit-quarantine"
+
+# Command previewing fetched gistits instead of the bundled bat, run with the path of every file
+# appended. Only read from this file, never from a project settings file.
+# previewer = "delta"
+
+# Keypair `gistit-daemon` runs with when started by gistit, written by `gistit daemon identity export`
+# or an IPFS config file. It's the node peer id and signs published names, the stored identity is
+# used if unset. Only read from this file, never from a project settings file.
+# signing_key = "/home/me/.gistit/personal.key"
+
+# Named profiles override any of the keys above. Select one with `--profile <name>` or the
+# `GISTIT_PROFILE` environment variable.
+#
+# [profile.work]
+# author = "Matthew McConaughey"
+# server_url = "https://gistit.mycompany.com/"
+# lifespan = "7d"
+# signing_key = "/home/me/.gistit/work.key"
+"#;
+
+const FALLBACK_EDITOR: &str = "vi";
+
+const SERVER_REACHABILITY_TIMEOUT_SECS: u64 = 5;
+
+#[derive(Debug, Clone)]
+pub struct Action {
+    pub command: SettingsCommand,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SettingsCommand {
+    Init { force: bool },
+    Edit,
+    Doctor,
+}
+
+impl Action {
+    pub fn from_args(
+        args: &ArgMatches,
+    ) -> Result<Box<dyn Dispatch<InnerData = Config> + Send + Sync + 'static>> {
+        let command = match args.subcommand() {
+            Some(("init", args)) => SettingsCommand::Init {
+                force: args.is_present("force"),
+            },
+            Some(("edit", _)) => SettingsCommand::Edit,
+            Some(("doctor", _)) => SettingsCommand::Doctor,
+            _ => return Err(Error::Argument("missing subcommand", "config")),
+        };
+
+        Ok(Box::new(Self { command }))
+    }
+}
+
+pub struct Config {
+    settings_path: PathBuf,
+}
+
+#[async_trait]
+impl Dispatch for Action {
+    type InnerData = Config;
+
+    async fn prepare(&self) -> Result<Self::InnerData> {
+        Ok(Config {
+            settings_path: Settings::path()?,
+        })
+    }
+
+    async fn dispatch(&self, config: Self::InnerData) -> Result<()> {
+        let settings_path = &config.settings_path;
+
+        match self.command {
+            SettingsCommand::Init { force } => {
+                progress!("Writing settings");
+                if fs::metadata(settings_path).is_ok() && !force {
+                    interruptln!();
+                    errorln!(
+                        "settings file already exists at '{}', use `--force` to overwrite it",
+                        settings_path.to_string_lossy()
+                    );
+                    std::process::exit(exit::USAGE);
+                }
+
+                fs::write(settings_path, SETTINGS_TEMPLATE)?;
+                updateln!("Settings written");
+                finish!(format!(
+                    "\n    path: '{}'\n\n",
+                    style(settings_path.to_string_lossy()).bold()
+                ));
+            }
+            SettingsCommand::Edit => {
+                if fs::metadata(settings_path).is_err() {
+                    fs::write(settings_path, SETTINGS_TEMPLATE)?;
+                }
+
+                let editor = env::var("VISUAL")
+                    .or_else(|_| env::var("EDITOR"))
+                    .unwrap_or_else(|_| FALLBACK_EDITOR.to_owned());
+                let status = Command::new(&editor).arg(settings_path).status()?;
+
+                if !status.success() {
+                    warnln!("'{}' exited with {}", editor, status);
+                }
+
+                if let Err(err) = Settings::load() {
+                    err.report();
+                    warnln!("settings file is invalid, run `gistit config edit` to fix it");
+                    std::process::exit(exit::SETTINGS);
+                }
+            }
+            SettingsCommand::Doctor => {
+                let mut checks = vec![check_settings(settings_path)];
+                if let Some(project_path) = project_path()? {
+                    checks.push(check_settings(&project_path));
+                }
+                checks.extend([
+                    check_directory("config directory", &path::config()?),
+                    check_directory("data directory", &path::data()?),
+                    check_directory("daemon socket directory", &path::runtime_dir()?),
+                    check_server().await,
+                ]);
+                let failed = checks.iter().filter(|check| check.is_err()).count();
+
+                for check in checks {
+                    match check {
+                        Ok(ok) => println!("{} {}", style(Emoji("✔️ ", "> ")).green(), ok),
+                        Err((fail, hint)) => {
+                            println!("{} {}", style(Emoji("❌", "x ")).red(), fail);
+                            println!("    {}", style(hint).dim());
+                        }
+                    }
+                }
+
+                if failed > 0 {
+                    println!();
+                    errorln!("{} check(s) failed", failed);
+                    std::process::exit(exit::FAILURE);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A doctor check outcome. Failures carry an actionable hint
+type Check = std::result::Result<String, (String, String)>;
+
+fn check_settings(settings_path: &Path) -> Check {
+    let display = settings_path.to_string_lossy();
+
+    let content = fs::read_to_string(settings_path).map_err(|_| {
+        (
+            format!("settings file not found at '{}'", display),
+            "run `gistit config init` to create one".to_owned(),
+        )
+    })?;
+
+    let table: toml::value::Table = toml::from_str(&content).map_err(|err| {
+        (
+            format!("settings file is not valid toml: {}", err),
+            "run `gistit config edit` to fix it".to_owned(),
+        )
+    })?;
+
+    if let Some(key) = table
+        .keys()
+        .find(|key| !SETTINGS_KEYS.contains(&key.as_str()))
+    {
+        return Err((
+            format!("unknown settings key '{}'", key),
+            format!("supported keys are: {}", SETTINGS_KEYS.join(", ")),
+        ));
+    }
+
+    if let Some(profiles) = table.get("profile").and_then(toml::Value::as_table) {
+        for (name, profile) in profiles {
+            let unknown = profile.as_table().and_then(|profile| {
+                profile
+                    .keys()
+                    .find(|key| !PROFILE_KEYS.contains(&key.as_str()))
+            });
+
+            if let Some(key) = unknown {
+                return Err((
+                    format!("unknown key '{}' in profile '{}'", key, name),
+                    format!("supported profile keys are: {}", PROFILE_KEYS.join(", ")),
+                ));
+            }
+        }
+    }
+
+    let settings: Settings = toml::from_str(&content).map_err(|err| {
+        (
+            format!("invalid settings value: {}", err),
+            "run `gistit config edit` to fix it".to_owned(),
+        )
+    })?;
+
+    let profiles = settings.profile.values().cloned().map(Settings::from);
+    for settings in std::iter::once(settings.clone()).chain(profiles) {
+        check_settings_values(&settings)?;
+    }
+
+    Ok(format!("settings file is valid '{}'", display))
+}
+
+fn check_settings_values(settings: &Settings) -> std::result::Result<(), (String, String)> {
+    if let Some(ref colorscheme) = settings.colorscheme {
+        check::colorscheme(colorscheme).map_err(|_| {
+            (
+                format!("unknown colorscheme '{}'", colorscheme),
+                "run `gistit themes` to list available ones".to_owned(),
+            )
+        })?;
+    }
+
+    if let Some(ref author) = settings.author {
+        check::author(author).map_err(|_| {
+            (
+                format!("invalid author '{}'", author),
+                "author must be between 3 and 30 characters long".to_owned(),
+            )
+        })?;
+    }
+
+    if let Some(ref workspace) = settings.workspace {
+        check::snippet(workspace).map_err(|_| {
+            (
+                format!("invalid workspace '{}'", workspace),
+                "list the joined ones with `gistit workspace ls`".to_owned(),
+            )
+        })?;
+    }
+
+    if let Some(ref lifespan) = settings.lifespan {
+        if lifespan != "never" && check::duration(lifespan).is_none() {
+            return Err((
+                format!("invalid lifespan '{}'", lifespan),
+                "use a duration like '30m', '12h', '2d' or 'never'".to_owned(),
+            ));
+        }
+    }
+
+    if let Some(ref signing_key) = settings.signing_key {
+        if !signing_key.is_file() {
+            return Err((
+                format!("signing key '{}' doesn't exist", signing_key.display()),
+                "write one with `gistit daemon identity export --out <file>`".to_owned(),
+            ));
+        }
+    }
+
+    if let Some(ref server_url) = settings.server_url {
+        Url::parse(server_url).map_err(|err| {
+            (
+                format!("invalid server url '{}': {}", server_url, err),
+                "use an absolute url like 'https://example.com/'".to_owned(),
+            )
+        })?;
+    }
+
+    if let Some(ref transport) = settings.transport {
+        for (key, address) in transport.listen_addrs() {
+            check::listen_addr(address, key).map_err(|err| {
+                (
+                    format!("invalid {} listen address '{}': {}", key, address, err),
+                    "tcp addresses end with '/tcp/<port>', websocket ones with '/tcp/<port>/ws' \
+                     and quic ones with '/udp/<port>/quic-v1'"
+                        .to_owned(),
+                )
+            })?;
+        }
+        if let Some(ref security) = transport.security {
+            check::security(security).map_err(|err| {
+                (
+                    format!("invalid transport security '{}': {}", security, err),
+                    "use 'noise' or 'tls'".to_owned(),
+                )
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+fn check_directory(name: &str, dir: &Path) -> Check {
+    let display = dir.to_string_lossy();
+
+    let attr = fs::metadata(dir).map_err(|_| {
+        (
+            format!("{} is missing '{}'", name, display),
+            "run any gistit command to create it".to_owned(),
+        )
+    })?;
+
+    if attr.permissions().readonly() {
+        return Err((
+            format!("{} is read only '{}'", name, display),
+            "check the directory permissions".to_owned(),
+        ));
+    }
+
+    Ok(format!("{} is writable '{}'", name, display))
+}
+
+async fn check_server() -> Check {
+    let url = SERVER_URL_GET.to_string();
+
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(SERVER_REACHABILITY_TIMEOUT_SECS))
+        .build()
+        .map_err(|err| (err.to_string(), "check your tls setup".to_owned()))?;
+
+    // Any response is fine here, we only care about reaching the server
+    client.get(&url).send().await.map_err(|err| {
+        (
+            format!("server is unreachable '{}': {}", url, err),
+            "check your internet connection and the `GISTIT_SERVER_URL` variable".to_owned(),
+        )
+    })?;
+
+    Ok(format!("server is reachable '{}'", url))
+}
@@ -0,0 +1,172 @@
+//! The credentials module
+//!
+//! Keeps the GitHub token, the daemon identity passphrase and named gistit secrets in the OS
+//! credential store (Secret Service, Keychain or Credential Manager), managed with
+//! `gistit auth login|logout|status`. Owner tokens of private gistits are saved here by
+//! `gistit <file> --private` and picked up by `gistit fetch`, workspaces by
+//! `gistit workspace create|join`. Nothing here is ever written to disk by gistit itself.
+//!
+//! The credential store can't list what it holds, so the names of saved secrets and workspaces
+//! are kept in credentials of their own.
+
+use std::env;
+
+use keyring::Entry;
+
+use gistit_project::env::GISTIT_IDENTITY_PASSPHRASE_VAR;
+
+use crate::Result;
+
+/// Service every credential is stored under
+const SERVICE: &str = "gistit";
+
+/// Accounts listing the names of saved secrets and workspaces
+const SECRET_NAMES: &str = "secret-names";
+const WORKSPACE_NAMES: &str = "workspace-names";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Credential {
+    GithubToken,
+    IdentityPassphrase,
+    /// A gistit secret saved under this name
+    Secret(String),
+    /// Owner token of the private gistit with this hash
+    OwnerToken(String),
+    /// Join token of the workspace with this name, holding it's key
+    Workspace(String),
+}
+
+impl Credential {
+    fn account(&self) -> String {
+        match self {
+            Self::GithubToken => "github-token".to_owned(),
+            Self::IdentityPassphrase => "identity-passphrase".to_owned(),
+            Self::Secret(name) => format!("secret:{}", name),
+            Self::OwnerToken(hash) => format!("owner-token:{}", hash),
+            Self::Workspace(name) => format!("workspace:{}", name),
+        }
+    }
+
+    /// Account listing the names of this kind of credential, and this one's name
+    fn listed(&self) -> Option<(&'static str, &str)> {
+        match self {
+            Self::Secret(name) => Some((SECRET_NAMES, name)),
+            Self::Workspace(name) => Some((WORKSPACE_NAMES, name)),
+            _ => None,
+        }
+    }
+
+    fn entry(&self) -> Entry {
+        Entry::new(SERVICE, &self.account())
+    }
+
+    /// The stored value, `None` if there's none
+    ///
+    /// # Errors
+    ///
+    /// Fails if the credential store can't be reached
+    pub fn get(&self) -> Result<Option<String>> {
+        match self.entry().get_password() {
+            Ok(value) => Ok(Some(value)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Stores `value`, replacing the previous one
+    ///
+    /// # Errors
+    ///
+    /// Fails if the credential store can't be reached
+    pub fn set(&self, value: &str) -> Result<()> {
+        self.entry().set_password(value)?;
+        if let Some((account, name)) = self.listed() {
+            let mut names = names(account)?;
+            if !names.iter().any(|other| other == name) {
+                names.push(name.to_owned());
+                names.sort();
+                save_names(account, &names)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the stored value, returning whether there was one
+    ///
+    /// # Errors
+    ///
+    /// Fails if the credential store can't be reached
+    pub fn delete(&self) -> Result<bool> {
+        let deleted = match self.entry().delete_password() {
+            Ok(()) => true,
+            Err(keyring::Error::NoEntry) => false,
+            Err(err) => return Err(err.into()),
+        };
+        if let Some((account, name)) = self.listed() {
+            let mut names = names(account)?;
+            names.retain(|other| other != name);
+            save_names(account, &names)?;
+        }
+        Ok(deleted)
+    }
+}
+
+/// Names of the saved gistit secrets
+///
+/// # Errors
+///
+/// Fails if the credential store can't be reached
+pub fn secret_names() -> Result<Vec<String>> {
+    names(SECRET_NAMES)
+}
+
+/// Names of the joined workspaces
+///
+/// # Errors
+///
+/// Fails if the credential store can't be reached
+pub fn workspace_names() -> Result<Vec<String>> {
+    names(WORKSPACE_NAMES)
+}
+
+fn names(account: &str) -> Result<Vec<String>> {
+    let names = Entry::new(SERVICE, account);
+    match names.get_password() {
+        Ok(names) => Ok(names.lines().map(ToOwned::to_owned).collect()),
+        Err(keyring::Error::NoEntry) => Ok(Vec::new()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn save_names(account: &str, names: &[String]) -> Result<()> {
+    let entry = Entry::new(SERVICE, account);
+    if names.is_empty() {
+        match entry.delete_password() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    } else {
+        Ok(entry.set_password(&names.join("\n"))?)
+    }
+}
+
+/// The stored identity passphrase as an environment variable for `gistit-daemon`, unless one is
+/// already set. A missing credential store is the same as no passphrase
+#[must_use]
+pub fn identity_passphrase_env() -> Option<(&'static str, String)> {
+    if env::var_os(GISTIT_IDENTITY_PASSPHRASE_VAR).is_some() {
+        return None;
+    }
+    Credential::IdentityPassphrase
+        .get()
+        .ok()
+        .flatten()
+        .map(|passphrase| (GISTIT_IDENTITY_PASSPHRASE_VAR, passphrase))
+}
+
+/// The saved owner token of the private gistit `hash`. A missing credential store is the same as
+/// no token, public gistits don't need one
+#[must_use]
+pub fn owner_token(hash: &str) -> Option<String> {
+    Credential::OwnerToken(hash.to_owned()).get().ok().flatten()
+}
@@ -0,0 +1,1132 @@
+//! The daemon module
+//!
+//! Manages the `gistit-daemon` background process lifecycle. The process id is kept in a pidfile
+//! inside the runtime directory, while liveness and status are always checked through the IPC
+//! bridge, since a pidfile can outlive the process it points to.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use clap::ArgMatches;
+use console::{style, Emoji};
+use serde::Deserialize;
+use tokio::time::sleep;
+
+use gistit_ipc::{Bridge, Client};
+use gistit_project::path;
+use gistit_proto::ipc::instruction::diagnostic_check::Verdict;
+use gistit_proto::{ipc, Instruction};
+
+use crate::credentials;
+use crate::dispatch::Dispatch;
+use crate::error::exit;
+use crate::param::check;
+use crate::service::Service;
+use crate::settings::{get_global_settings, get_runtime_settings, Backend, Limits, Settings};
+use crate::{cleanln, errorln, finish, progress, updateln, warnln, Error, Result};
+
+/// The pidfile name inside the runtime directory
+pub const PIDFILE_NAME: &str = "gistit.pid";
+
+/// The daemon log file name inside the runtime directory
+pub const LOGFILE_NAME: &str = "gistit.log";
+
+/// The `gistit-daemon` config file name inside the config directory
+const DAEMON_CONFIG_FILE_NAME: &str = "daemon.toml";
+
+/// The daemon binary, expected to be in `PATH`
+const DAEMON_BIN: &str = "gistit-daemon";
+
+/// How long to wait for the daemon to go away after a shutdown request
+const STOP_TIMEOUT_SECS: u64 = 5;
+
+/// How long to wait for an auto spawned daemon to bind it's socket
+const SPAWN_TIMEOUT_SECS: u64 = 10;
+
+/// Interval between log requests when following
+const LOGS_POLL_MILLIS: u64 = 500;
+
+/// Listen address used when the daemon is spawned on demand
+const DEFAULT_HOST: &str = "0.0.0.0";
+const DEFAULT_PORT: &str = "0";
+
+#[derive(Debug, Clone)]
+pub struct Action<'a> {
+    pub command: &'a str,
+    pub force: bool,
+    pub follow: bool,
+    pub peers: bool,
+    pub hash: Option<&'a str>,
+    pub bootstrap: Option<(&'a str, Option<&'a str>)>,
+    pub identity: Option<(&'a str, Option<&'a str>)>,
+    pub acl: Option<(&'a str, Option<&'a str>, Option<&'a str>)>,
+    pub limits: [Option<&'a str>; 3],
+    pub log_level: Option<&'a str>,
+    host: &'a str,
+    port: &'a str,
+}
+
+impl<'a> Action<'a> {
+    pub fn from_args(
+        args: &'a ArgMatches,
+    ) -> Result<Box<dyn Dispatch<InnerData = Config<'a>> + Send + Sync + 'a>> {
+        let (command, sub_args) = args
+            .subcommand()
+            .ok_or(Error::Argument("missing subcommand", "daemon"))?;
+
+        // Only `start` and `restart` take listen arguments
+        let (host, port) = match command {
+            "start" | "restart" => (
+                sub_args
+                    .value_of("host")
+                    .ok_or(Error::Argument("missing argument", "--host"))?,
+                sub_args
+                    .value_of("port")
+                    .ok_or(Error::Argument("missing argument", "--port"))?,
+            ),
+            _ => (DEFAULT_HOST, DEFAULT_PORT),
+        };
+
+        Ok(Box::new(Self {
+            command,
+            force: command == "install-service" && sub_args.is_present("force"),
+            follow: command == "logs" && sub_args.is_present("follow"),
+            peers: command == "status" && sub_args.is_present("peers"),
+            hash: if command == "revoke" {
+                sub_args.value_of("HASH")
+            } else {
+                None
+            },
+            bootstrap: if command == "bootstrap" {
+                sub_args.subcommand().map(|(bootstrap, bootstrap_args)| {
+                    (bootstrap, bootstrap_args.value_of("MULTIADDR"))
+                })
+            } else {
+                None
+            },
+            identity: if command == "identity" {
+                sub_args.subcommand().map(|(identity, identity_args)| {
+                    (
+                        identity,
+                        identity_args
+                            .value_of("out")
+                            .or_else(|| identity_args.value_of("FILE")),
+                    )
+                })
+            } else {
+                None
+            },
+            acl: if command == "acl" {
+                sub_args.subcommand().map(|(acl, acl_args)| {
+                    (
+                        acl,
+                        acl_args.value_of("ACTION"),
+                        acl_args.value_of("TARGET"),
+                    )
+                })
+            } else {
+                None
+            },
+            limits: if command == "limits" {
+                [
+                    sub_args.value_of("upload-rate"),
+                    sub_args.value_of("download-rate"),
+                    sub_args.value_of("peer-request-rate"),
+                ]
+            } else {
+                [None; 3]
+            },
+            log_level: if command == "log-level" {
+                sub_args.value_of("LEVEL")
+            } else {
+                None
+            },
+            host,
+            port,
+        }))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DaemonCommand<'a> {
+    Start,
+    Stop,
+    Status,
+    Doctor,
+    Restart,
+    Logs,
+    Revoke(&'a str),
+    Bootstrap(BootstrapCommand<'a>),
+    Identity(IdentityCommand<'a>),
+    /// Add (or remove) an `allow|deny <target>` rule
+    Acl(bool, &'a str, &'a str),
+    Limits(Limits),
+    Reload,
+    /// Set the log level, or only show it
+    LogLevel(Option<&'a str>),
+    InstallService,
+    UninstallService,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BootstrapCommand<'a> {
+    Add(&'a str),
+    Remove(&'a str),
+    Ls,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum IdentityCommand<'a> {
+    Show,
+    Rotate,
+    /// Export, to a file if given
+    Export(Option<&'a str>),
+    Import(&'a str),
+}
+
+pub struct Config<'a> {
+    command: DaemonCommand<'a>,
+    host: &'a str,
+    port: &'a str,
+    runtime_path: PathBuf,
+    config_path: PathBuf,
+}
+
+#[async_trait]
+impl<'a> Dispatch for Action<'a> {
+    type InnerData = Config<'a>;
+
+    async fn prepare(&self) -> Result<Self::InnerData> {
+        let command = match self.command {
+            "start" => DaemonCommand::Start,
+            "stop" => DaemonCommand::Stop,
+            "status" => DaemonCommand::Status,
+            "doctor" => DaemonCommand::Doctor,
+            "restart" => DaemonCommand::Restart,
+            "logs" => DaemonCommand::Logs,
+            "revoke" => DaemonCommand::Revoke(check::hash(
+                self.hash
+                    .ok_or(Error::Argument("missing argument", "[HASH]"))?,
+            )?),
+            "bootstrap" => DaemonCommand::Bootstrap(match self.bootstrap {
+                Some(("add", Some(address))) => {
+                    BootstrapCommand::Add(check::bootstrap_addr(address)?)
+                }
+                Some(("remove", Some(address))) => BootstrapCommand::Remove(address),
+                Some(("ls", _)) => BootstrapCommand::Ls,
+                _ => return Err(Error::Argument("missing subcommand", "bootstrap")),
+            }),
+            "identity" => DaemonCommand::Identity(match self.identity {
+                Some(("show", _)) => IdentityCommand::Show,
+                Some(("rotate", _)) => IdentityCommand::Rotate,
+                Some(("export", out)) => IdentityCommand::Export(out),
+                Some(("import", Some(file))) => IdentityCommand::Import(file),
+                _ => return Err(Error::Argument("missing subcommand", "identity")),
+            }),
+            "acl" => match self.acl {
+                Some((command @ ("add" | "remove"), Some(action), Some(target))) => {
+                    DaemonCommand::Acl(command == "add", action, check::acl_target(target)?)
+                }
+                _ => return Err(Error::Argument("missing subcommand", "acl")),
+            },
+            "limits" => {
+                let [upload_rate, download_rate, peer_request_rate] = self.limits;
+                let parse = |value: Option<&str>, arg| {
+                    value
+                        .map(|value| {
+                            value
+                                .parse()
+                                .map_err(|_| Error::Argument("invalid limit", arg))
+                        })
+                        .transpose()
+                };
+                // Flags take precedence over the settings file
+                let settings = get_runtime_settings()?.limits.unwrap_or_default();
+
+                DaemonCommand::Limits(Limits {
+                    upload_rate: parse(upload_rate, "--upload-rate")?.or(settings.upload_rate),
+                    download_rate: parse(download_rate, "--download-rate")?
+                        .or(settings.download_rate),
+                    peer_request_rate: parse(peer_request_rate, "--peer-request-rate")?
+                        .map(|rate: u64| u32::try_from(rate).unwrap_or(u32::MAX))
+                        .or(settings.peer_request_rate),
+                })
+            }
+            "reload" => DaemonCommand::Reload,
+            "log-level" => DaemonCommand::LogLevel(self.log_level),
+            "install-service" => DaemonCommand::InstallService,
+            "uninstall-service" => DaemonCommand::UninstallService,
+            _ => return Err(Error::Argument("unknown subcommand", "daemon")),
+        };
+        let (host, port) = check::host_port(self.host, self.port)?;
+
+        Ok(Config {
+            command,
+            host,
+            port,
+            runtime_path: path::runtime_dir()?,
+            config_path: path::config()?,
+        })
+    }
+
+    async fn dispatch(&self, config: Self::InnerData) -> Result<()> {
+        let mut bridge = gistit_ipc::client(&config.runtime_path)?;
+
+        match config.command {
+            DaemonCommand::Start => {
+                if bridge.alive() {
+                    warnln!("gistit node is already running");
+                    print_status(&mut bridge, &config.runtime_path, false).await?;
+                    return Ok(());
+                }
+                start(&mut bridge, &config).await?;
+            }
+            DaemonCommand::Stop => {
+                progress!("Stopping");
+                if !bridge.alive() {
+                    remove_pid(&config.runtime_path)?;
+                    return Err(Error::Daemon("gistit node is not running"));
+                }
+                stop(&mut bridge, &config.runtime_path).await?;
+                updateln!("Stopped");
+                finish!("");
+            }
+            DaemonCommand::Status => {
+                progress!("Requesting status");
+                if !bridge.alive() {
+                    remove_pid(&config.runtime_path)?;
+                    return Err(Error::Daemon("gistit node is not running"));
+                }
+                print_status(&mut bridge, &config.runtime_path, self.peers).await?;
+            }
+            DaemonCommand::Doctor => {
+                progress!("Running diagnostics");
+                if !bridge.alive() {
+                    return Err(Error::Daemon("gistit node is not running"));
+                }
+                doctor(&mut bridge).await?;
+            }
+            DaemonCommand::Restart => {
+                if bridge.alive() {
+                    progress!("Stopping");
+                    stop(&mut bridge, &config.runtime_path).await?;
+                    updateln!("Stopped");
+                }
+                start(&mut bridge, &config).await?;
+            }
+            DaemonCommand::Logs => {
+                if !bridge.alive() {
+                    return Err(Error::Daemon("gistit node is not running"));
+                }
+                tail_logs(&mut bridge, self.follow).await?;
+            }
+            DaemonCommand::Revoke(hash) => {
+                progress!("Revoking");
+                if !bridge.alive() {
+                    return Err(Error::Daemon("gistit node is not running"));
+                }
+                bridge.connect_blocking()?;
+                bridge
+                    .send(Instruction::request_revoke(hash.to_owned()))
+                    .await?;
+
+                if let ipc::instruction::Kind::RevokeResponse(ipc::instruction::RevokeResponse {
+                    revoked,
+                }) = bridge.recv().await?.expect_response()?
+                {
+                    if !revoked {
+                        return Err(Error::Daemon("gistit node is not hosting this hash"));
+                    }
+                    updateln!("Revoked");
+                    finish!(format!(
+                        "\n    hash: '{}'\n    peers forget it once their provider record expires\n\n",
+                        style(hash).bold()
+                    ));
+                }
+            }
+            DaemonCommand::Bootstrap(command) => edit_bootstrap(command, bridge.alive())?,
+            DaemonCommand::Identity(command) => {
+                manage_identity(command, &config.config_path, bridge.alive())?;
+            }
+            DaemonCommand::Acl(add, action, target) => {
+                progress!("Updating acl");
+                if !bridge.alive() {
+                    return Err(Error::Daemon("gistit node is not running"));
+                }
+                let rule = format!("{} {}", action, target);
+                bridge.connect_blocking()?;
+                bridge
+                    .send(Instruction::request_acl(add, rule.clone()))
+                    .await?;
+
+                if let ipc::instruction::Kind::AclResponse(ipc::instruction::AclResponse {
+                    changed,
+                }) = bridge.recv().await?.expect_response()?
+                {
+                    if !changed {
+                        return Err(Error::Daemon(if add {
+                            "acl rule is invalid or already present"
+                        } else {
+                            "acl rule not found"
+                        }));
+                    }
+                    updateln!("{} acl rule", if add { "Added" } else { "Removed" });
+                    finish!(format!(
+                        "\n    rule: '{}'\n    add it to the settings `acl` list to keep it across restarts\n\n",
+                        style(rule).bold()
+                    ));
+                }
+            }
+            DaemonCommand::Limits(limits) => {
+                progress!("Applying limits");
+                if !bridge.alive() {
+                    return Err(Error::Daemon("gistit node is not running"));
+                }
+                bridge.connect_blocking()?;
+                bridge
+                    .send(Instruction::request_limits(
+                        limits.upload_rate,
+                        limits.download_rate,
+                        limits.peer_request_rate,
+                    ))
+                    .await?;
+
+                if let ipc::instruction::Kind::LimitsResponse(ipc::instruction::LimitsResponse {
+                    limits: Some(limits),
+                }) = bridge.recv().await?.expect_response()?
+                {
+                    updateln!("Applied limits");
+                    finish!(format!("{}\n", format_limits(&limits)));
+                }
+            }
+            DaemonCommand::Reload => {
+                progress!("Reloading config");
+                if !bridge.alive() {
+                    return Err(Error::Daemon("gistit node is not running"));
+                }
+                bridge.connect_blocking()?;
+                bridge.send(Instruction::request_reload_config()).await?;
+
+                if let ipc::instruction::Kind::ReloadConfigResponse(
+                    ipc::instruction::ReloadConfigResponse {
+                        errors,
+                        needs_restart,
+                    },
+                ) = bridge.recv().await?.expect_response()?
+                {
+                    if !errors.is_empty() {
+                        for error in errors {
+                            warnln!(error);
+                        }
+                        return Err(Error::Daemon("invalid daemon config, nothing was reloaded"));
+                    }
+                    updateln!("Reloaded config");
+                    finish!(format_reloaded(&config.config_path, &needs_restart));
+                }
+            }
+            DaemonCommand::LogLevel(level) => {
+                progress!(if level.is_some() {
+                    "Changing log level"
+                } else {
+                    "Reading log level"
+                });
+                if !bridge.alive() {
+                    return Err(Error::Daemon("gistit node is not running"));
+                }
+                bridge.connect_blocking()?;
+                bridge
+                    .send(Instruction::request_log_level(level.map(ToOwned::to_owned)))
+                    .await?;
+
+                if let ipc::instruction::Kind::LogLevelResponse(
+                    ipc::instruction::LogLevelResponse {
+                        level: current,
+                        from_env,
+                    },
+                ) = bridge.recv().await?.expect_response()?
+                {
+                    if level.is_some() && from_env {
+                        return Err(Error::Daemon(
+                            "log level set by RUST_LOG, restart the daemon without it to change it",
+                        ));
+                    }
+                    updateln!(if level.is_some() {
+                        "Changed log level"
+                    } else {
+                        "Log level"
+                    });
+                    finish!(format!("\n    level: {}\n\n", style(current).bold()));
+                }
+            }
+            DaemonCommand::InstallService => {
+                progress!("Installing service");
+                let daemon_bin = which::which(DAEMON_BIN)?;
+                let service = Service::for_current_platform(
+                    &daemon_bin,
+                    &config.runtime_path,
+                    &config.config_path,
+                )?;
+                service.install(self.force)?;
+                updateln!("Installed service");
+                finish!(format!(
+                    "\n    file: '{}'\n    enable it with: {}\n\n",
+                    style(service.path.to_string_lossy()).bold(),
+                    style(service.enable_hint).blue()
+                ));
+            }
+            DaemonCommand::UninstallService => {
+                progress!("Uninstalling service");
+                // The binary path doesn't matter to locate the service file
+                let service = Service::for_current_platform(
+                    Path::new(DAEMON_BIN),
+                    &config.runtime_path,
+                    &config.config_path,
+                )?;
+                service.uninstall()?;
+                updateln!("Uninstalled service");
+                finish!(format!(
+                    "\n    removed: '{}'\n    if it's still loaded, run: {}\n\n",
+                    style(service.path.to_string_lossy()).bold(),
+                    style(service.disable_hint).blue()
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+async fn start(bridge: &mut Bridge<Client>, config: &Config) -> Result<()> {
+    progress!("Starting gistit node");
+    let pid = spawn(
+        &config.runtime_path,
+        &config.config_path,
+        config.host,
+        config.port,
+    )?;
+    updateln!("Gistit node started, pid: {}", style(pid).blue());
+
+    bridge.connect_blocking()?;
+    bridge.send(Instruction::request_status()).await?;
+
+    if let ipc::instruction::Kind::StatusResponse(ipc::instruction::StatusResponse {
+        peer_id,
+        ..
+    }) = bridge.recv().await?.expect_response()?
+    {
+        cleanln!(format!("\n    peer id: '{}'\n\n", style(peer_id).bold()));
+    }
+
+    Ok(())
+}
+
+async fn stop(bridge: &mut Bridge<Client>, runtime_path: &Path) -> Result<()> {
+    bridge.connect_blocking()?;
+    bridge.send(Instruction::request_shutdown()).await?;
+
+    let earlier = Instant::now();
+    while bridge.alive() {
+        if earlier.elapsed() > Duration::from_secs(STOP_TIMEOUT_SECS) {
+            return Err(Error::Daemon("gistit node didn't stop in time"));
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+
+    remove_pid(runtime_path)?;
+    let log_path = runtime_path.join(LOGFILE_NAME);
+    if fs::metadata(&log_path).is_ok() {
+        fs::remove_file(log_path)?;
+    }
+
+    Ok(())
+}
+
+/// Prints the daemon status, with the provider scores if `peers` is set
+async fn print_status(bridge: &mut Bridge<Client>, runtime_path: &Path, peers: bool) -> Result<()> {
+    bridge.connect_blocking()?;
+    bridge
+        .send(if peers {
+            Instruction::request_status_peers()
+        } else {
+            Instruction::request_status()
+        })
+        .await?;
+
+    if let ipc::instruction::Kind::StatusResponse(response) =
+        bridge.recv().await?.expect_response()?
+    {
+        format_daemon_status(&response, read_pid(runtime_path));
+    }
+
+    Ok(())
+}
+
+/// Prints the swarm diagnostics, exiting with an error if any check failed
+async fn doctor(bridge: &mut Bridge<Client>) -> Result<()> {
+    bridge.connect_blocking()?;
+    bridge.send(Instruction::request_diagnostics()).await?;
+
+    if let ipc::instruction::Kind::DiagnosticsResponse(ipc::instruction::DiagnosticsResponse {
+        checks,
+    }) = bridge.recv().await?.expect_response()?
+    {
+        updateln!("Diagnostics complete");
+        let failed = checks
+            .iter()
+            .filter(|check| check.verdict() == Verdict::Fail)
+            .count();
+        finish!(format_diagnostics(&checks));
+
+        if failed > 0 {
+            errorln!("{} check(s) failed", failed);
+            std::process::exit(exit::FAILURE);
+        }
+    }
+
+    Ok(())
+}
+
+fn format_diagnostics(checks: &[ipc::instruction::DiagnosticCheck]) -> String {
+    let mut lines = vec![String::new()];
+    for check in checks {
+        let symbol = match check.verdict() {
+            Verdict::Pass => style(Emoji("✔️ ", "> ")).green(),
+            Verdict::Fail => style(Emoji("❌", "x ")).red(),
+            Verdict::Skip => style(Emoji("➖", "- ")).dim(),
+        };
+        lines.push(format!("{} {}: {}", symbol, check.name, check.detail));
+        if !check.hint.is_empty() {
+            lines.push(format!("    {}", style(&check.hint).dim()));
+        }
+    }
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+/// Prints the daemon structured log, polling for new lines if `follow` is set
+async fn tail_logs(bridge: &mut Bridge<Client>, follow: bool) -> Result<()> {
+    let mut offset = None;
+    bridge.connect_blocking()?;
+
+    loop {
+        bridge.send(Instruction::request_tail_logs(offset)).await?;
+
+        if let ipc::instruction::Kind::LogChunkResponse(ipc::instruction::LogChunkResponse {
+            data,
+            offset: next,
+        }) = bridge.recv().await?.expect_response()?
+        {
+            for line in String::from_utf8_lossy(&data).lines() {
+                println!("{}", format_log_line(line));
+            }
+
+            // Keep reading right away while there's backlog
+            if !data.is_empty() && offset.is_some() {
+                offset = Some(next);
+                continue;
+            }
+            offset = Some(next);
+        }
+
+        if !follow {
+            return Ok(());
+        }
+        sleep(Duration::from_millis(LOGS_POLL_MILLIS)).await;
+    }
+}
+
+/// Edits the bootstrap list in the global settings file, `ls` prints the one in effect
+fn edit_bootstrap(command: BootstrapCommand, running: bool) -> Result<()> {
+    let mut settings = Settings::load()?;
+    let mut peers = settings.bootstrap.take().unwrap_or_default();
+
+    match command {
+        BootstrapCommand::Ls => {
+            match get_runtime_settings()?.bootstrap {
+                Some(peers) if !peers.is_empty() => {
+                    for peer in peers {
+                        println!("{}", peer);
+                    }
+                }
+                _ => warnln!("no bootstrap peers set, the default libp2p bootstrap nodes are used"),
+            }
+            return Ok(());
+        }
+        BootstrapCommand::Add(address) => {
+            progress!("Adding bootstrap peer");
+            if peers.iter().any(|peer| peer == address) {
+                return Err(Error::Argument(
+                    "bootstrap peer already added",
+                    "[MULTIADDR]",
+                ));
+            }
+            peers.push(address.to_owned());
+            updateln!("Added bootstrap peer");
+        }
+        BootstrapCommand::Remove(address) => {
+            progress!("Removing bootstrap peer");
+            let len = peers.len();
+            peers.retain(|peer| peer != address);
+            if peers.len() == len {
+                return Err(Error::Argument("unknown bootstrap peer", "[MULTIADDR]"));
+            }
+            updateln!("Removed bootstrap peer");
+        }
+    }
+
+    // An empty list falls back to the default bootstrap nodes
+    settings.bootstrap = (!peers.is_empty()).then(|| peers);
+    settings.save()?;
+
+    if running {
+        finish!(format!(
+            "\n    run {} to apply it\n\n",
+            style("gistit daemon restart").blue()
+        ));
+    } else {
+        finish!("");
+    }
+    Ok(())
+}
+
+/// Runs `gistit-daemon identity`, which owns the encrypted keypair
+fn manage_identity(command: IdentityCommand, config_path: &Path, running: bool) -> Result<()> {
+    let subcommand = match command {
+        IdentityCommand::Show => vec!["show"],
+        IdentityCommand::Rotate => vec!["rotate"],
+        IdentityCommand::Export(_) => vec!["export"],
+        IdentityCommand::Import(file) => vec!["import", file],
+    };
+
+    let output = Command::new(DAEMON_BIN)
+        .args(&["--config-path", &*config_path.to_string_lossy()])
+        .arg("identity")
+        .args(&subcommand)
+        .envs(credentials::identity_passphrase_env())
+        .stdin(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        return Err(Error::Identity(
+            String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        ));
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    match command {
+        IdentityCommand::Show => println!("{}", stdout.trim()),
+        IdentityCommand::Export(None) => print!("{}", stdout),
+        IdentityCommand::Export(Some(file)) => {
+            write_private(Path::new(file), stdout.as_bytes())?;
+            finish!(format!("\n    exported to: '{}'\n\n", style(file).bold()));
+        }
+        IdentityCommand::Rotate | IdentityCommand::Import(_) => {
+            updateln!("New peer id: '{}'", style(stdout.trim()).bold());
+            if running {
+                finish!(format!(
+                    "\n    run {} to apply it\n\n",
+                    style("gistit daemon restart").blue()
+                ));
+            } else {
+                finish!("");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes a file only the owner can read
+///
+/// # Errors
+///
+/// Fails if the file can't be written
+pub fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    options.open(path)?.write_all(contents)?;
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+struct LogLine {
+    ts: u64,
+    level: String,
+    target: String,
+    msg: String,
+}
+
+/// Formats a JSON log line as `HH:MM:SS LEVEL target: message` (UTC), falling back to the raw line
+fn format_log_line(line: &str) -> String {
+    serde_json::from_str::<LogLine>(line).map_or_else(
+        |_| line.to_owned(),
+        |LogLine {
+             ts,
+             level,
+             target,
+             msg,
+         }| {
+            let secs = ts / 1000 % 86_400;
+            let level = match level.as_str() {
+                "ERROR" => style(level).red().bold(),
+                "WARN" => style(level).yellow().bold(),
+                "INFO" => style(level).green(),
+                _ => style(level).dim(),
+            };
+
+            format!(
+                "{:02}:{:02}:{:02} {:>5} {}: {}",
+                secs / 3600,
+                secs % 3600 / 60,
+                secs % 60,
+                level,
+                style(target).dim(),
+                msg
+            )
+        },
+    )
+}
+
+/// Spawns `gistit-daemon` in the background, with it's stderr redirected to the log file, and
+/// records it's pid
+///
+/// # Errors
+///
+/// Fails if the binary can't be found or the runtime directory isn't writable
+pub fn spawn(runtime_path: &Path, config_path: &Path, host: &str, port: &str) -> Result<u32> {
+    let stderr = fs::File::create(runtime_path.join(LOGFILE_NAME))?;
+    let settings = get_runtime_settings()?;
+    let relay_args = settings
+        .relay
+        .map(|relay| relay.daemon_args())
+        .unwrap_or_default();
+    let bootstrap_args = bootstrap_daemon_args(&settings.bootstrap.unwrap_or_default());
+    let limits_args = settings
+        .limits
+        .map(|limits| limits.daemon_args())
+        .unwrap_or_default();
+    let storage_args = settings
+        .storage
+        .map(|storage| storage.daemon_args())
+        .unwrap_or_default();
+    let transport_args = settings
+        .transport
+        .map(|transport| transport.daemon_args())
+        .unwrap_or_default();
+    let notify_args = if settings.notify.unwrap_or(false) {
+        vec!["--notify"]
+    } else {
+        Vec::new()
+    };
+    // A project settings file mustn't pick the key the node signs with
+    let signing_key_args = get_global_settings()?
+        .signing_key
+        .map(|key| {
+            vec![
+                "--config-file".to_owned(),
+                key.to_string_lossy().into_owned(),
+            ]
+        })
+        .unwrap_or_default();
+    let acl_args: Vec<String> = settings
+        .acl
+        .unwrap_or_default()
+        .into_iter()
+        .flat_map(|rule| ["--acl".to_owned(), rule])
+        .collect();
+
+    let pid = Command::new(DAEMON_BIN)
+        .args(&["--host", host])
+        .args(&["--port", port])
+        .args(&["--runtime-path", &*runtime_path.to_string_lossy()])
+        .args(&["--config-path", &*config_path.to_string_lossy()])
+        .args(&bootstrap_args)
+        .args(&acl_args)
+        .args(&limits_args)
+        .args(&storage_args)
+        .args(&transport_args)
+        .args(&notify_args)
+        .args(&relay_args)
+        .args(&signing_key_args)
+        .envs(credentials::identity_passphrase_env())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(stderr)
+        .spawn()?
+        .id();
+
+    write_pid(runtime_path, pid)?;
+    Ok(pid)
+}
+
+fn format_reloaded(config_path: &Path, needs_restart: &[String]) -> String {
+    let mut text = format!(
+        "\n    file: '{}'\n",
+        style(config_path.join(DAEMON_CONFIG_FILE_NAME).to_string_lossy()).bold()
+    );
+    if !needs_restart.is_empty() {
+        text.push_str(&format!(
+            "    restart to apply: {}\n",
+            style(needs_restart.join(", ")).yellow()
+        ));
+    }
+    text.push('\n');
+    text
+}
+
+/// The `gistit-daemon` arguments bootstrapping from `peers`, or from the default nodes if empty
+fn bootstrap_daemon_args(peers: &[String]) -> Vec<String> {
+    if peers.is_empty() {
+        return vec!["--bootstrap".to_owned()];
+    }
+
+    peers
+        .iter()
+        .flat_map(|peer| ["--bootstrap-peer".to_owned(), peer.clone()])
+        .collect()
+}
+
+/// Spawns the daemon when `backend` requires it and it isn't running yet, then waits for it's
+/// socket to come up. Any other case is a no-op
+///
+/// # Errors
+///
+/// Fails if the daemon can't be spawned or doesn't come up in time
+pub async fn spawn_if_required(
+    backend: Backend,
+    bridge: &Bridge<Client>,
+    runtime_path: &Path,
+    config_path: &Path,
+) -> Result<()> {
+    if backend != Backend::P2p || bridge.alive() {
+        return Ok(());
+    }
+
+    progress!("Starting gistit node");
+    let pid = spawn(runtime_path, config_path, DEFAULT_HOST, DEFAULT_PORT)?;
+
+    let earlier = Instant::now();
+    while !bridge.alive() {
+        if earlier.elapsed() > Duration::from_secs(SPAWN_TIMEOUT_SECS) {
+            return Err(Error::Daemon(
+                "gistit node didn't come up in time, check `gistit node --attach`",
+            ));
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    updateln!("Gistit node started, pid: {}", style(pid).blue());
+
+    Ok(())
+}
+
+/// Reads the recorded daemon pid, if any
+#[must_use]
+pub fn read_pid(runtime_path: &Path) -> Option<u32> {
+    fs::read_to_string(runtime_path.join(PIDFILE_NAME))
+        .ok()
+        .and_then(|pid| pid.trim().parse().ok())
+}
+
+/// Records the daemon pid
+///
+/// # Errors
+///
+/// Fails if the runtime directory isn't writable
+pub fn write_pid(runtime_path: &Path, pid: u32) -> Result<()> {
+    fs::write(runtime_path.join(PIDFILE_NAME), pid.to_string())?;
+    Ok(())
+}
+
+/// Removes the pidfile, if present
+///
+/// # Errors
+///
+/// Fails if the pidfile exists but can't be removed
+pub fn remove_pid(runtime_path: &Path) -> Result<()> {
+    let pidfile = runtime_path.join(PIDFILE_NAME);
+    if fs::metadata(&pidfile).is_ok() {
+        fs::remove_file(pidfile)?;
+    }
+    Ok(())
+}
+
+fn format_limits(limits: &ipc::instruction::Limits) -> String {
+    let rate = |value: u64, unit: &str| {
+        if value == 0 {
+            "unlimited".to_owned()
+        } else {
+            format!("{} {}", value, unit)
+        }
+    };
+
+    format!(
+        "\n    upload: {}\n    download: {}\n    per peer: {}",
+        rate(limits.upload_rate, "bytes/s"),
+        rate(limits.download_rate, "bytes/s"),
+        rate(u64::from(limits.peer_request_rate), "requests/min")
+    )
+}
+
+pub fn format_daemon_status(response: &ipc::instruction::StatusResponse, pid: Option<u32>) {
+    let ipc::instruction::StatusResponse {
+        peer_id,
+        peer_count,
+        pending_connections,
+        hosting,
+        reachability,
+        acl,
+        limits,
+        bytes_sent,
+        bytes_received,
+        rate_limited,
+        outbox_depth,
+        outbox_capacity,
+        events_dropped,
+        peers,
+    } = response;
+    let pid = pid.map_or_else(|| "unknown".to_owned(), |pid| pid.to_string());
+    let acl = if acl.is_empty() {
+        "none".to_owned()
+    } else {
+        acl.iter()
+            .map(|rule| format!("\n      {}", rule))
+            .collect::<String>()
+    };
+
+    updateln!("Running status");
+    finish!(format!(
+        r#"
+    pid: {}
+    peer id: '{}'
+    hosting: {} gistit
+    peers: {}
+    pending connections: {}
+    reachability: {}
+    acl: {}
+    sent: {} bytes, received: {} bytes, rate limited: {} requests
+    outbox: {}/{} queued, {} events dropped{}{}
+        "#,
+        style(pid).blue(),
+        style(peer_id).bold(),
+        hosting,
+        style(peer_count).blue(),
+        pending_connections,
+        reachability,
+        acl,
+        bytes_sent,
+        bytes_received,
+        rate_limited,
+        outbox_depth,
+        outbox_capacity,
+        events_dropped,
+        limits.as_ref().map(format_limits).unwrap_or_default(),
+        format_peer_scores(peers),
+    ));
+}
+
+/// Provider scores, best first, empty if there are none
+fn format_peer_scores(peers: &[ipc::instruction::PeerScore]) -> String {
+    let measured = |value: u64, unit: &str| {
+        if value == 0 {
+            "unknown".to_owned()
+        } else {
+            format!("{} {}", value, unit)
+        }
+    };
+
+    peers
+        .iter()
+        .map(|peer| {
+            format!(
+                "\n    provider {}\n      score: {:.2}, {} ok, {} failed, latency: {}, bandwidth: {}",
+                peer.peer_id,
+                peer.score,
+                peer.successes,
+                peer.failures,
+                measured(peer.latency, "ms"),
+                measured(peer.bandwidth, "bytes/s"),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::prelude::*;
+
+    #[test]
+    fn daemon_pidfile_roundtrip() {
+        let tmp = assert_fs::TempDir::new().unwrap();
+        assert_eq!(read_pid(tmp.path()), None);
+
+        write_pid(tmp.path(), 4242).unwrap();
+        assert_eq!(read_pid(tmp.path()), Some(4242));
+
+        remove_pid(tmp.path()).unwrap();
+        assert_eq!(read_pid(tmp.path()), None);
+        remove_pid(tmp.path()).unwrap();
+    }
+
+    #[test]
+    fn daemon_format_log_line() {
+        console::set_colors_enabled(false);
+        let line =
+            r#"{"ts":3723000,"level":"INFO","target":"gistit_daemon::node","msg":"Listening"}"#;
+
+        assert_eq!(
+            format_log_line(line),
+            "01:02:03  INFO gistit_daemon::node: Listening"
+        );
+        assert_eq!(format_log_line("not json"), "not json");
+    }
+
+    #[test]
+    fn daemon_pidfile_garbage_is_ignored() {
+        let tmp = assert_fs::TempDir::new().unwrap();
+        tmp.child(PIDFILE_NAME).write_str("not a pid").unwrap();
+
+        assert_eq!(read_pid(tmp.path()), None);
+    }
+
+    #[test]
+    fn daemon_format_peer_scores() {
+        assert_eq!(format_peer_scores(&[]), "");
+
+        let peers = [ipc::instruction::PeerScore {
+            peer_id: "12D3KooWDpJ7As7BWAwRMfu1VU2WCqNjvq387JEYKDBj4kx6nXTN".to_owned(),
+            score: 1.5,
+            successes: 12,
+            failures: 1,
+            latency: 120,
+            bandwidth: 0,
+        }];
+        assert_eq!(
+            format_peer_scores(&peers),
+            "\n    provider 12D3KooWDpJ7As7BWAwRMfu1VU2WCqNjvq387JEYKDBj4kx6nXTN\n      score: 1.50, 12 ok, 1 failed, latency: 120 ms, bandwidth: unknown"
+        );
+    }
+
+    #[test]
+    fn daemon_bootstrap_args() {
+        assert_eq!(bootstrap_daemon_args(&[]), vec!["--bootstrap"]);
+
+        let peer =
+            "/ip4/10.0.0.1/tcp/4001/p2p/12D3KooWDpJ7As7BWAwRMfu1VU2WCqNjvq387JEYKDBj4kx6nXTN";
+        assert!(check::bootstrap_addr(peer).is_ok());
+        assert!(check::bootstrap_addr("/ip4/10.0.0.1/tcp/4001").is_err());
+        assert!(check::bootstrap_addr("/ip4/10.0.0.1/tcp/4001/p2p/").is_err());
+        assert_eq!(
+            bootstrap_daemon_args(&[peer.to_owned()]),
+            vec!["--bootstrap-peer", peer]
+        );
+    }
+}
@@ -0,0 +1,371 @@
+//! The describe module
+//!
+//! Descriptions for gistits sent without `--description`, taken from the file itself: it's module
+//! header or first doc comment, or else the signature of it's first top-level function. A language
+//! only needs it's comment markers and the keywords starting a function to be described, others
+//! are left without one. Turned off with `auto_description = false` in the settings.
+
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+/// Only the head of a file is looked at
+const DESCRIBE_READ_LIMIT: u64 = 16 * 1024;
+
+/// Shorter descriptions say nothing, the server refuses them too
+const MIN_LENGTH: usize = 10;
+
+/// Longer descriptions are cut at a word, the server refuses them otherwise
+const MAX_LENGTH: usize = 100;
+
+/// How a language writes comments and starts functions
+struct Syntax {
+    /// Line comment markers, longer first so `///` isn't read as `//`
+    line: &'static [&'static str],
+    /// Block comment delimiters, docstrings included
+    block: &'static [(&'static str, &'static str)],
+    /// Markers of comments documenting the item below, picked even past the module header
+    doc: &'static [&'static str],
+    /// Starts of a top-level function definition
+    signature: &'static [&'static str],
+}
+
+const C_BLOCK: &[(&str, &str)] = &[("/**", "*/"), ("/*!", "*/"), ("/*", "*/")];
+const C_DOC: &[&str] = &["/**", "///"];
+
+const RUST: Syntax = Syntax {
+    line: &["//!", "///", "//"],
+    block: C_BLOCK,
+    doc: &["//!", "///", "/**", "/*!"],
+    signature: &[
+        "pub fn ",
+        "pub async fn ",
+        "pub const fn ",
+        "pub(crate) fn ",
+        "async fn ",
+        "const fn ",
+        "fn ",
+    ],
+};
+
+const PYTHON: Syntax = Syntax {
+    line: &["#"],
+    block: &[("\"\"\"", "\"\"\""), ("'''", "'''")],
+    doc: &["\"\"\"", "'''"],
+    signature: &["async def ", "def ", "class "],
+};
+
+const JAVASCRIPT: Syntax = Syntax {
+    line: &["//"],
+    block: C_BLOCK,
+    doc: C_DOC,
+    signature: &[
+        "export default function ",
+        "export async function ",
+        "export function ",
+        "async function ",
+        "function ",
+    ],
+};
+
+const GO: Syntax = Syntax {
+    line: &["//"],
+    block: C_BLOCK,
+    doc: &[],
+    signature: &["func "],
+};
+
+const C_LIKE: Syntax = Syntax {
+    line: &["///", "//"],
+    block: C_BLOCK,
+    doc: C_DOC,
+    signature: &[],
+};
+
+const SHELL: Syntax = Syntax {
+    line: &["#"],
+    block: &[],
+    doc: &[],
+    signature: &["function "],
+};
+
+const RUBY: Syntax = Syntax {
+    line: &["#"],
+    block: &[("=begin", "=end")],
+    doc: &[],
+    signature: &["def "],
+};
+
+const LUA: Syntax = Syntax {
+    line: &["---", "--"],
+    block: &[("--[[", "]]")],
+    doc: &["---"],
+    signature: &["local function ", "function "],
+};
+
+const SQL: Syntax = Syntax {
+    line: &["--"],
+    block: C_BLOCK,
+    doc: &[],
+    signature: &[],
+};
+
+fn syntax(lang: &str) -> Option<Syntax> {
+    Some(match lang {
+        "rust" => RUST,
+        "python" => PYTHON,
+        "javascript" | "typescript" | "jsx" | "tsx" => JAVASCRIPT,
+        "go" => GO,
+        "c" | "cpp" | "csharp" | "java" | "kotlin" | "swift" | "scala" | "dart" | "php"
+        | "objectivec" | "zig" | "solidity" => C_LIKE,
+        "bash" | "powershell" | "perl" | "r" | "elixir" | "nix" | "makefile" | "toml" | "yaml"
+        | "docker" | "cmake" => SHELL,
+        "ruby" | "crystal" => RUBY,
+        "lua" => LUA,
+        "sql" | "plsql" | "haskell" | "elm" | "ada" => SQL,
+        _ => return None,
+    })
+}
+
+/// Describes the file at `path` written in `lang`, `None` if nothing in it fits
+#[must_use]
+pub fn from_path(path: &Path, lang: &str) -> Option<String> {
+    let mut head = Vec::new();
+    fs::File::open(path)
+        .ok()?
+        .take(DESCRIBE_READ_LIMIT)
+        .read_to_end(&mut head)
+        .ok()?;
+
+    describe(&String::from_utf8_lossy(&head), lang)
+}
+
+/// Describes `data` written in `lang`, `None` if nothing in it fits
+#[must_use]
+pub fn describe(data: &str, lang: &str) -> Option<String> {
+    let syntax = syntax(lang)?;
+    let lines: Vec<&str> = data.lines().collect();
+
+    let mut leading = true;
+    let mut signature = None;
+    let mut at = usize::from(lines.first().map_or(false, |line| line.starts_with("#!")));
+    while at < lines.len() {
+        let line = lines[at];
+        if line.trim().is_empty() {
+            at += 1;
+            continue;
+        }
+
+        if let Some((text, next)) = comment(&syntax, &lines, at) {
+            let doc = leading || syntax.doc.iter().any(|marker| line.starts_with(marker));
+            if doc && !is_license(&text) {
+                if let Some(description) = summarize(&text) {
+                    return Some(description);
+                }
+            }
+            at = next;
+            continue;
+        }
+
+        leading = false;
+        if signature.is_none() && syntax.signature.iter().any(|start| line.starts_with(start)) {
+            signature = Some(signature_at(&lines[at..]));
+        }
+        at += 1;
+    }
+
+    signature.and_then(|signature| clip(&signature))
+}
+
+/// The text of the comment starting at line `start`, and the line after it ends
+fn comment(syntax: &Syntax, lines: &[&str], start: usize) -> Option<(String, usize)> {
+    let first = lines[start].trim_start();
+    let mut text = String::new();
+
+    if let Some((open, close)) = syntax
+        .block
+        .iter()
+        .find(|(open, _)| first.starts_with(open))
+    {
+        let mut at = start;
+        let mut rest = &first[open.len()..];
+        loop {
+            if let Some(end) = rest.find(close) {
+                push_line(&mut text, &rest[..end]);
+                return Some((text, at + 1));
+            }
+            push_line(&mut text, rest);
+
+            at += 1;
+            let line = lines.get(at)?.trim_start();
+            // Leading `*` of javadoc style blocks, not the closing `*/`
+            rest = line
+                .strip_prefix('*')
+                .filter(|rest| !rest.starts_with('/'))
+                .unwrap_or(line);
+        }
+    }
+
+    let marker = syntax
+        .line
+        .iter()
+        .find(|marker| first.starts_with(*marker))?;
+    let mut at = start;
+    while let Some(rest) = lines
+        .get(at)
+        .and_then(|line| line.trim_start().strip_prefix(marker))
+    {
+        push_line(&mut text, rest);
+        at += 1;
+    }
+    Some((text, at))
+}
+
+fn push_line(text: &mut String, line: &str) {
+    text.push_str(line.trim());
+    text.push('\n');
+}
+
+fn is_license(text: &str) -> bool {
+    let lower = text.to_lowercase();
+    ["copyright", "spdx-license", "licensed under"]
+        .iter()
+        .any(|word| lower.contains(word))
+}
+
+/// The first sentence of the first paragraph of `text`, or the whole paragraph if that's too short
+fn summarize(text: &str) -> Option<String> {
+    let paragraph: Vec<&str> = text
+        .lines()
+        .map(str::trim)
+        .skip_while(|line| line.is_empty())
+        .take_while(|line| !line.is_empty())
+        .collect();
+    let paragraph = paragraph.join(" ");
+    // Markdown headers of doc comments
+    let paragraph = paragraph.trim_start_matches('#').trim();
+
+    let sentence = paragraph
+        .find(". ")
+        .map(|end| &paragraph[..end])
+        .filter(|sentence| sentence.len() >= MIN_LENGTH)
+        .unwrap_or(paragraph);
+    clip(sentence.trim_end_matches('.'))
+}
+
+/// The function definition starting `lines`, over as many lines as it's parameters take
+fn signature_at(lines: &[&str]) -> String {
+    let mut signature = String::new();
+    let mut depth = 0_i32;
+    for line in lines {
+        signature.push(' ');
+        signature.push_str(line.trim());
+
+        for c in line.chars() {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                _ => (),
+            }
+        }
+        if depth <= 0 {
+            break;
+        }
+    }
+
+    signature
+        .trim_end_matches(|c: char| c == '{' || c == ':' || c.is_whitespace())
+        .to_owned()
+}
+
+/// `text` with whitespace collapsed, cut at a word to fit [`MAX_LENGTH`]. `None` if it's shorter
+/// than [`MIN_LENGTH`]
+fn clip(text: &str) -> Option<String> {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.len() < MIN_LENGTH {
+        return None;
+    }
+    if text.len() <= MAX_LENGTH {
+        return Some(text);
+    }
+
+    let mut end = MAX_LENGTH - "...".len();
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    let cut = text[..end]
+        .rfind(' ')
+        .filter(|space| *space >= MIN_LENGTH)
+        .unwrap_or(end);
+    Some(format!("{}...", text[..cut].trim_end()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describe_module_header() {
+        let rust =
+            "//! The file module\n//!\n//! Here we define file structures.\n\nuse std::fs;\n";
+        assert_eq!(describe(rust, "rust").unwrap(), "The file module");
+
+        let python = "#!/usr/bin/env python\n\"\"\"Resize images in bulk. Keeps ratios.\"\"\"\n";
+        assert_eq!(describe(python, "python").unwrap(), "Resize images in bulk");
+
+        let bash = "#!/bin/bash\n# Backs up the home directory\n# every night\nset -e\n";
+        assert_eq!(
+            describe(bash, "bash").unwrap(),
+            "Backs up the home directory every night"
+        );
+    }
+
+    #[test]
+    fn describe_skips_licenses() {
+        let js = "/*\n * Copyright 2022 Someone\n */\n\n/**\n * Debounces a callback by a delay\n \
+                  * @param fn the callback\n */\nexport function debounce(fn, delay) {}\n";
+        assert_eq!(
+            describe(js, "javascript").unwrap(),
+            "Debounces a callback by a delay @param fn the callback"
+        );
+    }
+
+    #[test]
+    fn describe_doc_comment_past_code() {
+        let rust = "use std::fs;\n\n// not a doc\nconst A: u8 = 1;\n\n/// Parses the settings file\nfn parse() {}\n";
+        assert_eq!(describe(rust, "rust").unwrap(), "Parses the settings file");
+    }
+
+    #[test]
+    fn describe_signature() {
+        let rust = "use std::fs;\n\npub fn copy_all(\n    from: &Path,\n    to: &Path,\n) -> Result<()> {\n}\n";
+        assert_eq!(
+            describe(rust, "rust").unwrap(),
+            "pub fn copy_all( from: &Path, to: &Path, ) -> Result<()>"
+        );
+
+        let python = "import os\n\n\nclass ImageResizer:\n    def bar(self):\n        pass\n";
+        assert_eq!(describe(python, "python").unwrap(), "class ImageResizer");
+
+        let go = "package main\n\nfunc main() {\n}\n";
+        assert_eq!(describe(go, "go").unwrap(), "func main()");
+    }
+
+    #[test]
+    fn describe_unknown_or_empty() {
+        assert_eq!(describe("# Title\n\nSome text", "markdown"), None);
+        assert_eq!(describe("", "rust"), None);
+        assert_eq!(describe("// short\n", "rust"), None);
+    }
+
+    #[test]
+    fn describe_clip() {
+        let long = "word ".repeat(40);
+        let clipped = clip(&long).unwrap();
+        assert!(clipped.len() <= MAX_LENGTH);
+        assert!(clipped.ends_with("word..."));
+
+        let wide = "é".repeat(80);
+        assert!(clip(&wide).unwrap().len() <= MAX_LENGTH);
+    }
+}
@@ -0,0 +1,292 @@
+//! The diff module
+//!
+//! `gistit diff <hash> <hash|file>` fetches both sides and prints a unified diff, to compare
+//! revisions of a snippet, e.g. the ones sent by `--watch`. A side that is an existing path is read
+//! from disk. Files of gistits with many, or of archives, are paired by name.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use clap::ArgMatches;
+use console::style;
+use similar::{ChangeTag, TextDiff};
+
+use gistit_core::secret;
+use gistit_ipc::{Bridge, Client};
+use gistit_project::path;
+use gistit_proto::{Gistit, Inner};
+
+use crate::archive;
+use crate::daemon;
+use crate::dispatch::Dispatch;
+use crate::fetch::{self, Prefer};
+use crate::file::name_from_path;
+use crate::fmt::PROGRESS;
+use crate::param::check;
+use crate::settings::{get_runtime_settings, Backend};
+use crate::{finish, progress, updateln, Error, Result};
+
+/// Unchanged lines shown around each change, unless `--context` is given
+const DEFAULT_CONTEXT: usize = 3;
+
+#[derive(Debug, Clone)]
+pub struct Action<'a> {
+    pub old: &'a str,
+    pub new: &'a str,
+    pub prefer: Option<&'a str>,
+    pub context: Option<&'a str>,
+}
+
+impl<'a> Action<'a> {
+    pub fn from_args(
+        args: &'a ArgMatches,
+    ) -> Result<Box<dyn Dispatch<InnerData = Config<'a>> + Send + Sync + 'a>> {
+        Ok(Box::new(Self {
+            old: args
+                .value_of("OLD")
+                .ok_or(Error::Argument("missing argument", "[OLD]"))?,
+            new: args
+                .value_of("NEW")
+                .ok_or(Error::Argument("missing argument", "[NEW]"))?,
+            prefer: args.value_of("prefer"),
+            context: args.value_of("context"),
+        }))
+    }
+}
+
+/// One side of the diff
+enum Side<'a> {
+    Gistit(&'a str),
+    Local(PathBuf),
+}
+
+impl<'a> Side<'a> {
+    /// Existing paths are read from disk, anything else is fetched
+    fn parse(value: &'a str) -> Result<Self> {
+        let path = Path::new(value);
+        if path.is_file() {
+            Ok(Self::Local(path.to_owned()))
+        } else {
+            check::identifier(value)?;
+            Ok(Self::Gistit(value))
+        }
+    }
+}
+
+pub struct Config<'a> {
+    old: Side<'a>,
+    new: Side<'a>,
+    prefer: Prefer,
+    context: usize,
+    backend: Backend,
+    runtime_path: PathBuf,
+    config_path: PathBuf,
+}
+
+#[async_trait]
+impl<'a> Dispatch for Action<'a> {
+    type InnerData = Config<'a>;
+
+    async fn prepare(&self) -> Result<Self::InnerData> {
+        progress!("Preparing");
+        let settings = get_runtime_settings()?;
+        let config = Config {
+            old: Side::parse(self.old)?,
+            new: Side::parse(self.new)?,
+            prefer: self.prefer.map_or_else(
+                || Ok(Prefer::from_backend(settings.backend())),
+                Prefer::parse,
+            )?,
+            context: self
+                .context
+                .map(|context| {
+                    context
+                        .parse()
+                        .map_err(|_| Error::Argument("expected a number of lines", "--context"))
+                })
+                .transpose()?
+                .unwrap_or(DEFAULT_CONTEXT),
+            backend: settings.backend(),
+            runtime_path: path::runtime_dir()?,
+            config_path: path::config()?,
+        };
+        updateln!("Prepared");
+        Ok(config)
+    }
+
+    async fn dispatch(&self, config: Self::InnerData) -> Result<()> {
+        let mut bridge = gistit_ipc::client(&config.runtime_path)?;
+        daemon::spawn_if_required(
+            config.backend,
+            &bridge,
+            &config.runtime_path,
+            &config.config_path,
+        )
+        .await?;
+
+        let old = files(&mut bridge, &config.old, config.prefer).await?;
+        let new = files(&mut bridge, &config.new, config.prefer).await?;
+
+        let rendered: String = pair(&old, &new)
+            .into_iter()
+            .map(|(old, new)| render(old, new, config.context))
+            .collect();
+        if rendered.is_empty() {
+            finish!("\n    no differences\n\n");
+        } else {
+            PROGRESS.finish_and_clear();
+            std::io::stdout().write_all(rendered.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+/// The files of `side`, archives unpacked
+async fn files(bridge: &mut Bridge<Client>, side: &Side<'_>, prefer: Prefer) -> Result<Vec<Inner>> {
+    let value = match side {
+        Side::Local(file_path) => {
+            let data = fs::read_to_string(file_path)?;
+            return Ok(vec![Gistit::new_inner(
+                name_from_path(file_path),
+                String::new(),
+                u32::try_from(data.len()).unwrap_or(u32::MAX),
+                data,
+            )]);
+        }
+        Side::Gistit(value) => value,
+    };
+
+    let identifier = check::identifier(value)?;
+    let (hash, sources) = fetch::locate(bridge, identifier, prefer).await?;
+    let token = fetch::owner_token(None, &hash, &sources);
+    let (gistit, _) = match fetch::fetch_any(bridge, &hash, sources, token.as_deref()).await {
+        Err(Error::Core(gistit_core::Error::BurnAfterRead)) => {
+            return Err(Error::Argument(
+                "gistit is deleted once read, diffing it would delete it",
+                "[OLD]/[NEW]",
+            ));
+        }
+        fetched => fetched?,
+    };
+    if secret::is_encrypted(&gistit) {
+        return Err(Error::Secret(
+            "encrypted gistits can't be diffed, fetch them with `--secret-prompt`",
+        ));
+    }
+
+    match archive::Format::detect(&gistit) {
+        Some(format) => {
+            let bytes = archive::decode(&gistit.inner[0])?;
+            archive::unpack(&bytes, format)
+        }
+        None => Ok(gistit.inner),
+    }
+}
+
+/// Pairs the files of both sides, by name unless both have a single one. A file missing on one
+/// side is diffed against nothing
+fn pair<'f>(old: &'f [Inner], new: &'f [Inner]) -> Vec<(Option<&'f Inner>, Option<&'f Inner>)> {
+    if let ([old], [new]) = (old, new) {
+        return vec![(Some(old), Some(new))];
+    }
+
+    let names: BTreeSet<&str> = old
+        .iter()
+        .chain(new)
+        .map(|inner| inner.name.as_str())
+        .collect();
+    names
+        .into_iter()
+        .map(|name| {
+            (
+                old.iter().find(|inner| inner.name == name),
+                new.iter().find(|inner| inner.name == name),
+            )
+        })
+        .collect()
+}
+
+/// A colorized unified diff of `old` and `new`, empty if they're the same. Colors are left out
+/// when stdout isn't a terminal, so it can be piped to `git apply`
+fn render(old: Option<&Inner>, new: Option<&Inner>, context: usize) -> String {
+    let old_data = old.map_or("", |inner| inner.data.as_str());
+    let new_data = new.map_or("", |inner| inner.data.as_str());
+    if old_data == new_data {
+        return String::new();
+    }
+    let diff = TextDiff::from_lines(old_data, new_data);
+
+    let old_name = old.map_or_else(
+        || "/dev/null".to_owned(),
+        |inner| format!("a/{}", inner.name),
+    );
+    let new_name = new.map_or_else(
+        || "/dev/null".to_owned(),
+        |inner| format!("b/{}", inner.name),
+    );
+    let mut rendered = format!(
+        "{}\n{}\n",
+        style(format!("--- {}", old_name)).bold(),
+        style(format!("+++ {}", new_name)).bold()
+    );
+
+    for hunk in diff.unified_diff().context_radius(context).iter_hunks() {
+        rendered.push_str(&format!("{}\n", style(hunk.header()).cyan()));
+        for change in hunk.iter_changes() {
+            let line = match change.tag() {
+                ChangeTag::Delete => style(format!("-{}", change.value())).red(),
+                ChangeTag::Insert => style(format!("+{}", change.value())).green(),
+                ChangeTag::Equal => style(format!(" {}", change.value())),
+            };
+            rendered.push_str(&line.to_string());
+            if change.missing_newline() {
+                rendered.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+    }
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inner(name: &str, data: &str) -> Inner {
+        Gistit::new_inner(name.to_owned(), String::new(), 0, data.to_owned())
+    }
+
+    #[test]
+    fn diff_render_unified() {
+        console::set_colors_enabled(false);
+        let old = inner("main.rs", "fn main() {\n    one();\n}\n");
+        let new = inner("main.rs", "fn main() {\n    two();\n}\n");
+
+        let rendered = render(Some(&old), Some(&new), DEFAULT_CONTEXT);
+        assert_eq!(
+            rendered,
+            "--- a/main.rs\n+++ b/main.rs\n@@ -1,3 +1,3 @@\n fn main() {\n-    one();\n+    two();\n }\n"
+        );
+        assert!(render(Some(&old), Some(&old), DEFAULT_CONTEXT).is_empty());
+
+        let rendered = render(None, Some(&new), DEFAULT_CONTEXT);
+        assert!(rendered.starts_with("--- /dev/null\n+++ b/main.rs\n"));
+    }
+
+    #[test]
+    fn diff_pair_files() {
+        let old = [inner("a.rs", ""), inner("b.rs", "")];
+        let new = [inner("b.rs", ""), inner("c.rs", "")];
+
+        let pairs = pair(&old, &new);
+        assert_eq!(pairs.len(), 3);
+        assert!(pairs[0].1.is_none());
+        assert!(pairs[1].0.is_some() && pairs[1].1.is_some());
+        assert!(pairs[2].0.is_none());
+
+        // Renamed single files are still compared
+        assert_eq!(pair(&old[..1], &new[..1]).len(), 1);
+    }
+}
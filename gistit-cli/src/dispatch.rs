@@ -1,4 +1,5 @@
 use async_trait::async_trait;
+use tracing::{debug_span, info_span, Instrument};
 
 use crate::Result;
 
@@ -10,3 +11,23 @@ pub trait Dispatch {
 
     async fn dispatch(&self, payload: Self::InnerData) -> Result<()>;
 }
+
+/// Prepares and dispatches `action`, traced under a span named after the `command`
+///
+/// # Errors
+///
+/// Inherits the errors of the action
+pub async fn run<T>(
+    command: &str,
+    action: Box<dyn Dispatch<InnerData = T> + Send + Sync + '_>,
+) -> Result<()> {
+    async move {
+        let payload = action.prepare().instrument(debug_span!("prepare")).await?;
+        action
+            .dispatch(payload)
+            .instrument(debug_span!("dispatch"))
+            .await
+    }
+    .instrument(info_span!("command", name = command))
+    .await
+}
@@ -0,0 +1,354 @@
+//! The document module
+//!
+//! Markdown files and Jupyter notebooks read poorly as their source, a notebook being a JSON
+//! document with it's cells as arrays of lines. [`markdown`] renders markdown for the terminal,
+//! styling headings, emphasis, code and links, and [`notebook`] renders the cells of a notebook one
+//! after the other, code cells followed by their outputs.
+
+use std::fmt::Write;
+
+use console::style;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::Result;
+
+/// Width of horizontal rules
+const RULE_WIDTH: usize = 40;
+
+/// Indentation of code blocks and cell outputs
+const INDENT: &str = "    ";
+
+/// `text` rendered for the terminal. Block elements are handled line by line, tables and html are
+/// left as they are
+#[must_use]
+pub fn markdown(text: &str) -> String {
+    let mut rendered = String::new();
+    // The marker the open code block was started with
+    let mut fence: Option<&str> = None;
+
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        if let Some(marker) = fence {
+            if trimmed.starts_with(marker) {
+                fence = None;
+            } else {
+                rendered.push_str(&code(line));
+            }
+            continue;
+        }
+
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            fence = Some(&trimmed[..3]);
+            let lang = trimmed[3..].trim();
+            if !lang.is_empty() {
+                let _ = writeln!(rendered, "{}{}", INDENT, style(lang).dim());
+            }
+            continue;
+        }
+        rendered.push_str(&block(line));
+        rendered.push('\n');
+    }
+    rendered
+}
+
+fn code(line: &str) -> String {
+    format!("{}{}\n", INDENT, style(line).cyan())
+}
+
+/// A line outside of code blocks
+fn block(line: &str) -> String {
+    let indent = &line[..line.len() - line.trim_start().len()];
+    let trimmed = line.trim();
+
+    let level = trimmed.chars().take_while(|c| *c == '#').count();
+    if (1..=6).contains(&level) && trimmed[level..].starts_with(' ') {
+        let heading = inline(trimmed[level..].trim_end_matches('#').trim());
+        return match level {
+            1 => style(heading).bold().underlined().to_string(),
+            2 => style(heading).bold().to_string(),
+            _ => style(heading).bold().dim().to_string(),
+        };
+    }
+
+    let marks: String = trimmed.chars().filter(|c| !c.is_whitespace()).collect();
+    if marks.len() >= 3
+        && ['-', '*', '_']
+            .iter()
+            .any(|mark| marks.chars().all(|c| c == *mark))
+    {
+        return style("─".repeat(RULE_WIDTH)).dim().to_string();
+    }
+
+    if let Some(quote) = trimmed.strip_prefix('>') {
+        return format!(
+            "{}{} {}",
+            indent,
+            style("│").dim(),
+            style(inline(quote.trim_start())).italic()
+        );
+    }
+
+    for marker in ["- ", "* ", "+ "] {
+        if let Some(item) = trimmed.strip_prefix(marker) {
+            return format!("{}• {}", indent, inline(item));
+        }
+    }
+    format!("{}{}", indent, inline(trimmed))
+}
+
+/// `text` with it's emphasis, code spans, links and images styled
+fn inline(text: &str) -> String {
+    let mut rendered = String::new();
+    let mut rest = text;
+    let mut previous = ' ';
+
+    while let Some(c) = rest.chars().next() {
+        let after = &rest[c.len_utf8()..];
+
+        if c == '\\' {
+            if let Some(escaped) = after.chars().next() {
+                rendered.push(escaped);
+                rest = &after[escaped.len_utf8()..];
+                continue;
+            }
+        } else if c == '`' {
+            if let Some(end) = after.find('`') {
+                rendered.push_str(&style(&after[..end]).cyan().to_string());
+                rest = &after[end + 1..];
+                previous = '`';
+                continue;
+            }
+        } else if rest.starts_with("**") || rest.starts_with("__") {
+            let marker = &rest[..2];
+            if let Some(end) = rest[2..].find(marker).filter(|end| *end > 0) {
+                rendered.push_str(&style(inline(&rest[2..2 + end])).bold().to_string());
+                rest = &rest[end + 4..];
+                previous = '*';
+                continue;
+            }
+        } else if (c == '*' || c == '_')
+            && !previous.is_alphanumeric()
+            && !after.starts_with(char::is_whitespace)
+        {
+            if let Some(end) = after.find(c).filter(|end| *end > 0) {
+                rendered.push_str(&style(inline(&after[..end])).italic().to_string());
+                rest = &after[end + 1..];
+                previous = c;
+                continue;
+            }
+        } else if let Some((image, label, url, length)) = link(rest) {
+            let label = if image {
+                style(format!("[image: {}]", label)).dim().to_string()
+            } else {
+                style(inline(label)).underlined().to_string()
+            };
+            let _ = write!(rendered, "{} {}", label, style(format!("({})", url)).dim());
+            rest = &rest[length..];
+            previous = ')';
+            continue;
+        }
+
+        rendered.push(c);
+        rest = after;
+        previous = c;
+    }
+    rendered
+}
+
+/// Whether `text` starts with an image or a link, it's label, url and length
+fn link(text: &str) -> Option<(bool, &str, &str, usize)> {
+    let image = text.starts_with("![");
+    let start = if image {
+        2
+    } else if text.starts_with('[') {
+        1
+    } else {
+        return None;
+    };
+
+    let close = start + text[start..].find(']')?;
+    if !text[close + 1..].starts_with('(') {
+        return None;
+    }
+    let url_end = close + 2 + text[close + 2..].find(')')?;
+
+    Some((
+        image,
+        &text[start..close],
+        &text[close + 2..url_end],
+        url_end + 1,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+struct Notebook {
+    cells: Vec<Cell>,
+    #[serde(default)]
+    metadata: Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct Cell {
+    #[serde(rename = "cell_type")]
+    kind: String,
+    /// A string or an array of lines
+    #[serde(default)]
+    source: Value,
+    #[serde(default)]
+    outputs: Vec<Value>,
+    #[serde(default)]
+    execution_count: Option<u64>,
+}
+
+/// A multiline string of a notebook, a string or an array of lines with their line breaks
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Array(lines) => lines.iter().filter_map(Value::as_str).collect(),
+        _ => String::new(),
+    }
+}
+
+/// The cells of the Jupyter notebook `json` rendered one after the other, markdown ones with
+/// [`markdown`] and code ones followed by their outputs
+///
+/// # Errors
+///
+/// Fails if it isn't a notebook
+pub fn notebook(json: &str) -> Result<String> {
+    let notebook: Notebook = serde_json::from_str(json)?;
+    let lang = notebook.metadata["kernelspec"]["language"]
+        .as_str()
+        .or_else(|| notebook.metadata["language_info"]["name"].as_str())
+        .unwrap_or_default();
+
+    let mut rendered = String::new();
+    for cell in &notebook.cells {
+        let source = text(&cell.source);
+        match cell.kind.as_str() {
+            "markdown" => rendered.push_str(&markdown(&source)),
+            "code" => {
+                let count = cell
+                    .execution_count
+                    .map_or_else(|| " ".to_owned(), |count| count.to_string());
+                let _ = writeln!(
+                    rendered,
+                    "{} {}",
+                    style(format!("In [{}]:", count)).green(),
+                    style(lang).dim()
+                );
+                for line in source.lines() {
+                    rendered.push_str(&code(line));
+                }
+                for output in &cell.outputs {
+                    rendered.push_str(&cell_output(output, &count));
+                }
+            }
+            _ => {
+                rendered.push_str(&source);
+                rendered.push('\n');
+            }
+        }
+        rendered.push('\n');
+    }
+    Ok(rendered)
+}
+
+/// An output of a code cell, images are only named
+fn cell_output(output: &Value, count: &str) -> String {
+    let (label, body) = match output["output_type"].as_str() {
+        Some("stream") => (None, text(&output["text"])),
+        Some("error") => {
+            let error = format!(
+                "{}: {}",
+                output["ename"].as_str().unwrap_or_default(),
+                output["evalue"].as_str().unwrap_or_default()
+            );
+            return format!("{}{}\n", INDENT, style(error).red());
+        }
+        Some(kind) => {
+            let data = output["data"].as_object();
+            let image = data.and_then(|data| data.keys().find(|mime| mime.starts_with("image/")));
+            let body = image.map_or_else(
+                || text(&output["data"]["text/plain"]),
+                |mime| format!("[{} output]", mime),
+            );
+            let label = (kind == "execute_result").then(|| format!("Out[{}]:", count));
+            (label, body)
+        }
+        None => return String::new(),
+    };
+
+    let mut rendered = String::new();
+    if let Some(label) = label {
+        let _ = writeln!(rendered, "{}", style(label).red());
+    }
+    for line in body.lines() {
+        let _ = writeln!(rendered, "{}{}", INDENT, style(line).dim());
+    }
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn document_markdown() {
+        console::set_colors_enabled(false);
+
+        assert_eq!(
+            markdown("# Title #\n\nSome **bold** and *italic* `code`, snake_case_name\n"),
+            "Title\n\nSome bold and italic code, snake_case_name\n"
+        );
+        assert_eq!(
+            markdown("- one\n  * two\n> quoted\n***\n1. first"),
+            format!(
+                "• one\n  • two\n│ quoted\n{}\n1. first\n",
+                "─".repeat(RULE_WIDTH)
+            )
+        );
+        assert_eq!(
+            markdown("See [the docs](https://gistit.vercel.app) ![logo](logo.png) \\*not\\*"),
+            "See the docs (https://gistit.vercel.app) [image: logo] (logo.png) *not*\n"
+        );
+        assert_eq!(
+            markdown("```rust\nfn main() {}\n\n# not a heading\n```\nafter"),
+            "    rust\n    fn main() {}\n    \n    # not a heading\nafter\n"
+        );
+        assert_eq!(markdown("#hashtag, 2 * 3 * 4"), "#hashtag, 2 * 3 * 4\n");
+    }
+
+    #[test]
+    fn document_notebook() {
+        console::set_colors_enabled(false);
+
+        let json = r##"{
+            "cells": [
+                { "cell_type": "markdown", "source": ["# Plot\n", "Some *data*"] },
+                {
+                    "cell_type": "code",
+                    "execution_count": 3,
+                    "source": "print(1)\n1 + 1",
+                    "outputs": [
+                        { "output_type": "stream", "name": "stdout", "text": ["1\n"] },
+                        { "output_type": "execute_result", "data": { "text/plain": ["2"] } },
+                        { "output_type": "display_data", "data": { "image/png": "iVBOR" } },
+                        { "output_type": "error", "ename": "ValueError", "evalue": "bad" }
+                    ]
+                }
+            ],
+            "metadata": { "kernelspec": { "language": "python" } },
+            "nbformat": 4
+        }"##;
+
+        assert_eq!(
+            notebook(json).unwrap(),
+            "Plot\nSome data\n\n\
+             In [3]: python\n    print(1)\n    1 + 1\n    1\nOut[3]:\n    2\n    [image/png output]\n    ValueError: bad\n\n"
+        );
+        assert!(notebook("{}").is_err());
+        assert!(notebook("# not json").is_err());
+    }
+}
@@ -0,0 +1,157 @@
+//! The embed module
+//!
+//! Ready to paste markup for a gistit sent to the server, printed to stdout by
+//! `gistit <file> --embed markdown|html|badge`. Markdown is a fenced code block followed by a link,
+//! html an iframe of the web viewer and badge a markdown image linking to it.
+
+use gistit_proto::payload::Gistit;
+
+use crate::{Error, Result};
+
+/// Iframe heights are guessed from the line count, up to this
+const MAX_IFRAME_HEIGHT: usize = 600;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Embed {
+    Markdown,
+    Html,
+    Badge,
+}
+
+impl Embed {
+    pub fn parse(embed: &str) -> Result<Self> {
+        match embed {
+            "markdown" => Ok(Self::Markdown),
+            "html" => Ok(Self::Html),
+            "badge" => Ok(Self::Badge),
+            _ => Err(Error::Argument(
+                "invalid embed format, expected 'markdown', 'html' or 'badge'",
+                "--embed",
+            )),
+        }
+    }
+
+    /// Markup of `gistit`, linking to `url`
+    #[must_use]
+    pub fn render(self, gistit: &Gistit, url: &str) -> String {
+        // NOTE: Currently we support one file
+        let (name, lang, data) = gistit.inner.first().map_or(("", "", ""), |inner| {
+            (
+                inner.name.as_str(),
+                inner.lang.as_str(),
+                inner.data.as_str(),
+            )
+        });
+
+        match self {
+            Self::Markdown => {
+                let fence = fence_for(data);
+                let newline = if data.ends_with('\n') { "" } else { "\n" };
+                format!(
+                    "{fence}{lang}\n{data}{newline}{fence}\n\n[{name}]({url}) by {author}",
+                    fence = fence,
+                    lang = lang,
+                    data = data,
+                    newline = newline,
+                    name = name,
+                    url = url,
+                    author = gistit.author
+                )
+            }
+            Self::Html => {
+                let height = (data.lines().count() * 20 + 80).min(MAX_IFRAME_HEIGHT);
+                format!(
+                    r#"<iframe src="{}" title="{}" width="100%" height="{}" frameborder="0"></iframe>"#,
+                    url,
+                    name.replace('"', "&quot;"),
+                    height
+                )
+            }
+            Self::Badge => format!(
+                "[![gistit](https://img.shields.io/badge/gistit-{}-blue)]({})",
+                badge_text(name),
+                url
+            ),
+        }
+    }
+}
+
+/// A backtick fence longer than any run of backticks inside `data`
+fn fence_for(data: &str) -> String {
+    let longest = data
+        .split(|c: char| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or_default();
+
+    "`".repeat(longest.max(2) + 1)
+}
+
+/// Escapes `text` for a shields.io path, where dashes and underscores are separators
+fn badge_text(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '-' => "--".to_owned(),
+            '_' => "__".to_owned(),
+            ' ' => "%20".to_owned(),
+            c if c.is_ascii_alphanumeric() || c == '.' => c.to_string(),
+            c => {
+                let mut buf = [0; 4];
+                c.encode_utf8(&mut buf)
+                    .bytes()
+                    .map(|b| format!("%{:02X}", b))
+                    .collect()
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gistit(name: &str, data: &str) -> Gistit {
+        Gistit::new(
+            "hash".to_owned(),
+            "alice".to_owned(),
+            None,
+            "0".to_owned(),
+            vec![Gistit::new_inner(
+                name.to_owned(),
+                "rust".to_owned(),
+                0,
+                data.to_owned(),
+            )],
+        )
+    }
+
+    #[test]
+    fn embed_markdown_fence_outgrows_content() {
+        let url = "https://gistit.vercel.app/h/hash";
+
+        let markdown = Embed::Markdown.render(&gistit("main.rs", "fn main() {}"), url);
+        assert_eq!(
+            markdown,
+            "```rust\nfn main() {}\n```\n\n[main.rs](https://gistit.vercel.app/h/hash) by alice"
+        );
+
+        let markdown = Embed::Markdown.render(&gistit("README.md", "````\ncode\n````\n"), url);
+        assert!(markdown.starts_with("`````rust\n````\ncode\n````\n`````\n"));
+    }
+
+    #[test]
+    fn embed_html_and_badge() {
+        let url = "https://gistit.vercel.app/h/hash";
+        let gistit = gistit("my-file_v2.rs", "a\nb\nc");
+
+        assert_eq!(
+            Embed::Html.render(&gistit, url),
+            r#"<iframe src="https://gistit.vercel.app/h/hash" title="my-file_v2.rs" width="100%" height="140" frameborder="0"></iframe>"#
+        );
+        assert_eq!(
+            Embed::Badge.render(&gistit, url),
+            "[![gistit](https://img.shields.io/badge/gistit-my--file__v2.rs-blue)](https://gistit.vercel.app/h/hash)"
+        );
+        assert!(Embed::parse("bbcode").is_err());
+    }
+}
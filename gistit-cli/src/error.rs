@@ -1,5 +1,25 @@
 use console::style;
 
+/// Exit statuses, a contract scripts can branch on. Errors not listed exit with [`exit::FAILURE`]
+pub mod exit {
+    pub const FAILURE: i32 = 1;
+    /// Invalid arguments, also used by clap
+    pub const USAGE: i32 = 2;
+    /// Invalid settings file
+    pub const SETTINGS: i32 = 3;
+    pub const NOT_FOUND: i32 = 4;
+    /// Missing or wrong secret, passphrase or login
+    pub const UNAUTHORIZED: i32 = 5;
+    /// The server can't be reached or answers unexpectedly
+    pub const NETWORK: i32 = 6;
+    /// `gistit-daemon` isn't running or can't be reached
+    pub const DAEMON: i32 = 7;
+    /// Stopped by SIGINT (Ctrl-C)
+    pub const INTERRUPTED: i32 = 130;
+    /// Stopped by SIGTERM
+    pub const TERMINATED: i32 = 143;
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("{0}")]
@@ -29,15 +49,66 @@ pub enum Error {
     #[error("{0}")]
     Proto(#[from] gistit_proto::Error),
 
+    #[error("{0}")]
+    Core(#[from] gistit_core::Error),
+
     #[error("{0}")]
     Tui(#[from] bat::error::Error),
 
     #[error("{0}")]
     Other(#[from] which::Error),
 
+    #[error("{0}")]
+    SettingsParse(#[from] toml::de::Error),
+
+    #[error("{0}")]
+    SettingsWrite(#[from] toml::ser::Error),
+
+    #[error("{0}")]
+    Watch(#[from] notify::Error),
+
+    #[error("{0}")]
+    Keyring(#[from] keyring::Error),
+
     #[error("{0}")]
     Server(&'static str),
 
+    #[error("{0}")]
+    Daemon(&'static str),
+
+    #[error("{0}")]
+    Identity(String),
+
+    #[error("{0}")]
+    Secret(&'static str),
+
+    #[error("invalid manifest, {0}")]
+    Manifest(String),
+
+    #[error("failed to export, {0}")]
+    Export(String),
+
+    #[error("failed to mirror, {0}")]
+    Mirror(String),
+
+    #[error("invalid archive, {0}")]
+    Archive(String),
+
+    #[error("invalid binary file, {0}")]
+    Binary(String),
+
+    #[error("gistit doesn't match it's hash, it was altered on the way")]
+    Integrity,
+
+    #[error("refusing to save, {0}")]
+    Unsafe(String),
+
+    #[error("not supported by the server, {0}")]
+    Unsupported(String),
+
+    #[error("interrupted by {0}")]
+    Interrupted(crate::signal::Signal),
+
     /// (Reason, Param)
     #[error("{}", fmt_subcat("PARAM", .0, .1))]
     Argument(&'static str, &'static str),
@@ -45,11 +116,164 @@ pub enum Error {
     #[error("{}", fmt_suggest("invalid colorscheme parameter", .0.clone()))]
     Colorscheme(String),
 
+    #[error("no file '{0}' in this gistit, it has {1}")]
+    MissingFile(String, String),
+
+    /// Hashes starting with the short hash given
+    #[error("ambiguous short hash, it could be any of:\n{}", fmt_candidates(.0))]
+    Ambiguous(Vec<String>),
+
     #[error("{0}")]
     OAuth(String),
 
-    #[error("unknown error")]
-    Unknown,
+    #[error("{0}")]
+    Unknown(String),
+}
+
+impl Error {
+    /// Stable, machine readable code, printed as `error[<code>]`
+    #[must_use]
+    pub const fn code(&self) -> &'static str {
+        match self {
+            Self::IO(_) => "io",
+            Self::Request(_) => "network",
+            Self::Clipboard(_) => "clipboard",
+            Self::Utf8(_) => "encoding",
+            Self::UrlParse(_) => "url",
+            Self::JsonParse(_) => "json",
+            Self::Ipc(_) => "ipc",
+            Self::Project(_) => "project",
+            Self::Proto(_) => "protocol",
+            Self::Core(err) => core_code(err),
+            Self::Tui(_) => "preview",
+            Self::Other(_) => "missing-binary",
+            Self::SettingsParse(_) | Self::SettingsWrite(_) => "settings",
+            Self::Watch(_) => "watch",
+            Self::Keyring(_) => "credential-store",
+            Self::Server(_) => "server",
+            Self::Daemon(_) => "daemon",
+            Self::Identity(_) => "identity",
+            Self::Secret(_) => "secret",
+            Self::Manifest(_) => "manifest",
+            Self::Export(_) => "export",
+            Self::Mirror(_) => "mirror",
+            Self::Archive(_) => "archive",
+            Self::Binary(_) => "binary",
+            Self::Integrity => "integrity",
+            Self::Unsafe(_) => "unsafe-content",
+            Self::Unsupported(_) => "unsupported",
+            Self::Interrupted(_) => "interrupted",
+            Self::Argument(..) | Self::Colorscheme(_) | Self::MissingFile(..) => "argument",
+            Self::Ambiguous(_) => "ambiguous-hash",
+            Self::OAuth(_) => "github-auth",
+            Self::Unknown(_) => "unknown",
+        }
+    }
+
+    /// Status to exit with, see [`exit`]
+    #[must_use]
+    pub const fn exit_code(&self) -> i32 {
+        match self {
+            Self::Argument(..)
+            | Self::Colorscheme(_)
+            | Self::MissingFile(..)
+            | Self::Ambiguous(_)
+            | Self::Manifest(_)
+            | Self::Unsupported(_)
+            | Self::UrlParse(_) => exit::USAGE,
+            Self::SettingsParse(_) | Self::SettingsWrite(_) => exit::SETTINGS,
+            Self::Core(gistit_core::Error::NotFound) => exit::NOT_FOUND,
+            Self::Secret(_)
+            | Self::Identity(_)
+            | Self::OAuth(_)
+            | Self::Core(gistit_core::Error::Secret(_) | gistit_core::Error::WrongSecret) => {
+                exit::UNAUTHORIZED
+            }
+            Self::Request(_)
+            | Self::Server(_)
+            | Self::Core(
+                gistit_core::Error::Request(_)
+                | gistit_core::Error::Server(_)
+                | gistit_core::Error::RateLimited(_),
+            ) => exit::NETWORK,
+            Self::Ipc(_)
+            | Self::Daemon(_)
+            | Self::Core(gistit_core::Error::Ipc(_) | gistit_core::Error::Daemon(_)) => {
+                exit::DAEMON
+            }
+            Self::Interrupted(signal) => signal.exit_code(),
+            _ => exit::FAILURE,
+        }
+    }
+
+    /// What to try next, if there's something to try
+    #[must_use]
+    pub const fn hint(&self) -> Option<&'static str> {
+        match self {
+            Self::Request(_) | Self::Core(gistit_core::Error::Request(_)) => {
+                Some("check your connection and the server with `gistit config doctor`")
+            }
+            Self::Clipboard(_) => {
+                Some("install xclip, xsel or wl-clipboard, copying falls back to OSC52 or a file without them")
+            }
+            Self::Ipc(_) | Self::Daemon(_) | Self::Core(gistit_core::Error::Daemon(_)) => {
+                Some("check gistit-daemon with `gistit daemon status` and `gistit daemon logs`")
+            }
+            Self::SettingsParse(_) | Self::SettingsWrite(_) => {
+                Some("check the settings file with `gistit config doctor`")
+            }
+            Self::Keyring(_) => {
+                Some("make sure the OS credential store is unlocked, see `gistit auth status`")
+            }
+            Self::OAuth(_) => Some("log in again with `gistit auth login github`"),
+            Self::Core(gistit_core::Error::NotFound) => {
+                Some("check the hash, gistits on the server expire")
+            }
+            Self::Core(gistit_core::Error::WrongSecret) => {
+                Some("secrets are case sensitive, `--secret-prompt` asks again when mistyped")
+            }
+            Self::Integrity => Some("fetch it from another source with `--prefer`"),
+            Self::Ambiguous(_) => Some("give a few more characters of the hash"),
+            Self::Unsupported(_) => {
+                Some("use the p2p network, or another server with `server_url` in the settings")
+            }
+            Self::Core(gistit_core::Error::RateLimited(_)) => {
+                Some("the server limits how often it's asked, wait a little and try again")
+            }
+            Self::Core(gistit_core::Error::BurnAfterRead) => {
+                Some("fetch it with `--yes` to delete it as it's read")
+            }
+            _ => None,
+        }
+    }
+
+    /// Prints `error[<code>]: <message>` to stderr, followed by the hint if there's one
+    pub fn report(&self) {
+        eprintln!(
+            "{}: {}",
+            style(format!("error[{}]", self.code())).red().bold(),
+            self
+        );
+        if let Some(hint) = self.hint() {
+            eprintln!("{}: {}", style("hint").cyan().bold(), hint);
+        }
+    }
+}
+
+const fn core_code(err: &gistit_core::Error) -> &'static str {
+    match err {
+        gistit_core::Error::Request(_) => "network",
+        gistit_core::Error::UrlParse(_) => "url",
+        gistit_core::Error::Ipc(_) => "ipc",
+        gistit_core::Error::Proto(_) => "protocol",
+        gistit_core::Error::Server(_) => "server",
+        gistit_core::Error::Daemon(_) => "daemon",
+        gistit_core::Error::Secret(_) => "secret",
+        gistit_core::Error::WrongSecret => "wrong-secret",
+        gistit_core::Error::NotFound => "not-found",
+        gistit_core::Error::RateLimited(_) => "rate-limited",
+        gistit_core::Error::BurnAfterRead => "burn-after-read",
+    }
 }
 
 fn fmt_suggest(cause: &'static str, suggest: String) -> String {
@@ -63,6 +287,14 @@ Did you mean: '{}'?
     )
 }
 
+fn fmt_candidates(candidates: &[String]) -> String {
+    candidates
+        .iter()
+        .map(|hash| format!("    {}", style(hash).bold()))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
 fn fmt_subcat(subcat: &'static str, cause: &'static str, param: &'static str) -> String {
     format!(
         r#"{}
@@ -78,16 +310,58 @@ fn fmt_subcat(subcat: &'static str, cause: &'static str, param: &'static str) ->
 
 #[derive(thiserror::Error, Debug)]
 pub enum Clipboard {
-    #[error("this platform is not supported")]
-    UnsupportedPlatform,
-    #[error("couldn't find any supported clipboard binaries")]
-    MissingBinary,
-    #[error("the environment variable `DISPLAY` is not set")]
-    DisplayNotSet,
+    #[error("the clipboard program exited with an error")]
+    ProgramFailed,
+    #[error("reading the clipboard needs a clipboard binary, OSC52 can't be read from")]
+    ReadUnsupported,
 }
 
 impl From<String> for Error {
-    fn from(_: String) -> Self {
-        Self::Unknown
+    fn from(err: String) -> Self {
+        Self::Unknown(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_codes_and_hints() {
+        let err = Error::Argument("invalid gistit hash format.", "--hash");
+        assert_eq!(err.code(), "argument");
+        assert!(err.hint().is_none());
+
+        let err = Error::Core(gistit_core::Error::BurnAfterRead);
+        assert_eq!(err.code(), "burn-after-read");
+        assert!(err.hint().unwrap().contains("--yes"));
+
+        assert_eq!(Error::from("lost".to_owned()).to_string(), "lost");
+
+        let err = Error::Ambiguous(vec!["abcd01".to_owned(), "abcd02".to_owned()]);
+        assert_eq!(err.code(), "ambiguous-hash");
+        assert_eq!(err.exit_code(), exit::USAGE);
+        assert!(err.to_string().contains("abcd01"));
+        assert!(err.to_string().contains("abcd02"));
+    }
+
+    #[test]
+    fn error_exit_codes() {
+        assert_eq!(Error::Argument("", "--hash").exit_code(), exit::USAGE);
+        assert_eq!(
+            Error::Core(gistit_core::Error::NotFound).exit_code(),
+            exit::NOT_FOUND
+        );
+        assert_eq!(Error::Secret("").exit_code(), exit::UNAUTHORIZED);
+        assert_eq!(Error::Server("").exit_code(), exit::NETWORK);
+        assert_eq!(Error::Daemon("").exit_code(), exit::DAEMON);
+        assert_eq!(
+            Error::Interrupted(crate::signal::Signal::Interrupt).exit_code(),
+            exit::INTERRUPTED
+        );
+        assert_eq!(
+            Error::IO(std::io::ErrorKind::Other.into()).exit_code(),
+            exit::FAILURE
+        );
     }
 }
@@ -0,0 +1,206 @@
+//! The export module
+//!
+//! Renders a fetched gistit, syntax highlighted with the preview colorscheme, to a standalone file
+//! that can be embedded in wikis and slides: `gistit fetch <hash> --export html out.html`.
+//!
+//! PNG exports, `--export png out.png`, take a screenshot of the html with a headless Chromium and
+//! are only available when built with the `png` feature.
+
+use std::fs;
+use std::path::Path;
+
+use bat::assets::HighlightingAssets;
+use syntect::html::highlighted_html_for_string;
+
+use gistit_proto::payload::Gistit;
+
+use crate::{Error, Result};
+
+/// Headless capable browsers, tried in order
+#[cfg(feature = "png")]
+const BROWSER_BINARIES: [&str; 4] = [
+    "chromium",
+    "chromium-browser",
+    "google-chrome",
+    "google-chrome-stable",
+];
+
+/// Screenshot width, the height is guessed from the line count
+#[cfg(feature = "png")]
+const PNG_WIDTH: usize = 1200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Html,
+    Png,
+}
+
+impl Format {
+    pub fn parse(format: &str) -> Result<Self> {
+        match format {
+            "html" => Ok(Self::Html),
+            "png" if cfg!(feature = "png") => Ok(Self::Png),
+            "png" => Err(Error::Argument(
+                "png exports need gistit built with the `png` feature",
+                "--export",
+            )),
+            _ => Err(Error::Argument(
+                "invalid export format, expected 'html' or 'png'",
+                "--export",
+            )),
+        }
+    }
+}
+
+/// Renders `gistit` to `out_path`
+///
+/// # Errors
+///
+/// Fails if the bundled assets can't be loaded, the file can't be written or, for png, no headless
+/// browser could take the screenshot
+pub fn export(gistit: &Gistit, format: Format, out_path: &Path, colorscheme: &str) -> Result<()> {
+    let html = render_html(gistit, colorscheme)?;
+
+    match format {
+        Format::Html => fs::write(out_path, html)?,
+        Format::Png => screenshot(gistit, &html, out_path)?,
+    }
+    Ok(())
+}
+
+/// A standalone html document with the first file highlighted, titled like the preview header
+fn render_html(gistit: &Gistit, colorscheme: &str) -> Result<String> {
+    // NOTE: Currently we support one file
+    let inner = gistit.inner.first().expect("to have at least one file");
+    let assets = HighlightingAssets::from_binary();
+    let syntaxes = assets.get_syntax_set()?;
+    let syntax = Path::new(&inner.name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .and_then(|ext| syntaxes.find_syntax_by_extension(ext))
+        .unwrap_or_else(|| syntaxes.find_syntax_plain_text());
+    let code =
+        highlighted_html_for_string(&inner.data, syntaxes, syntax, assets.get_theme(colorscheme));
+
+    let mut header = format!(
+        "<strong>{}</strong> | {}",
+        escape(&inner.name),
+        escape(&gistit.author)
+    );
+    if let Some(ref description) = gistit.description {
+        header.push_str(&format!(" | <em>{}</em>", escape(description)));
+    }
+
+    Ok(format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<style>
+body {{ margin: 0; font-family: monospace; }}
+header {{ padding: 8px 16px; }}
+pre {{ margin: 0; padding: 16px; font-size: 14px; line-height: 20px; }}
+</style>
+</head>
+<body>
+<header>{header}</header>
+{code}
+</body>
+</html>
+"#,
+        title = escape(&inner.name),
+        header = header,
+        code = code
+    ))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(feature = "png")]
+fn screenshot(gistit: &Gistit, html: &str, out_path: &Path) -> Result<()> {
+    use std::process::Command;
+
+    let browser = BROWSER_BINARIES
+        .iter()
+        .find_map(|binary| which::which(binary).ok())
+        .ok_or_else(|| {
+            Error::Export("couldn't find chromium or google-chrome for the screenshot".to_owned())
+        })?;
+    let lines = gistit
+        .inner
+        .first()
+        .map_or(0, |inner| inner.data.lines().count());
+    let height = lines * 20 + 80;
+
+    let html_path = std::env::temp_dir().join(format!("gistit-export-{}.html", gistit.hash));
+    fs::write(&html_path, html)?;
+    let out_path = std::env::current_dir()?.join(out_path);
+    let status = Command::new(browser)
+        .arg("--headless")
+        .arg("--disable-gpu")
+        .arg("--hide-scrollbars")
+        .arg(format!("--window-size={},{}", PNG_WIDTH, height))
+        .arg(format!("--screenshot={}", out_path.to_string_lossy()))
+        .arg(format!("file://{}", html_path.to_string_lossy()))
+        .output()?
+        .status;
+    let _ = fs::remove_file(&html_path);
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(Error::Export(format!(
+            "headless browser exited with {}",
+            status
+        )))
+    }
+}
+
+#[cfg(not(feature = "png"))]
+fn screenshot(_gistit: &Gistit, _html: &str, _out_path: &Path) -> Result<()> {
+    Err(Error::Argument(
+        "png exports need gistit built with the `png` feature",
+        "--export",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn export_html_escapes_and_highlights() {
+        let gistit = Gistit::new(
+            "hash".to_owned(),
+            "alice".to_owned(),
+            Some("<b>bold</b> claims".to_owned()),
+            "0".to_owned(),
+            vec![Gistit::new_inner(
+                "main.rs".to_owned(),
+                "rust".to_owned(),
+                12,
+                "fn main() {}".to_owned(),
+            )],
+        );
+
+        let html = render_html(&gistit, "Monokai Extended Origin").unwrap();
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<title>main.rs</title>"));
+        assert!(html.contains("&lt;b&gt;bold&lt;/b&gt; claims"));
+        assert!(html.contains("<pre style=\"background-color:"));
+        assert!(!html.contains("<b>bold</b>"));
+    }
+
+    #[test]
+    fn export_format_parse() {
+        assert_eq!(Format::parse("html").unwrap(), Format::Html);
+        assert!(Format::parse("pdf").is_err());
+        assert_eq!(Format::parse("png").is_ok(), cfg!(feature = "png"));
+    }
+}
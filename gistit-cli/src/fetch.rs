@@ -1,168 +1,1667 @@
-use std::path::PathBuf;
+use std::borrow::Cow;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::future::Future;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use async_trait::async_trait;
+use bat::line_range::{LineRange, LineRanges};
 use clap::ArgMatches;
-use console::style;
-use reqwest::StatusCode;
+use console::{style, Term};
+use indicatif::HumanBytes;
 use serde::Serialize;
+use tokio::time::timeout;
 
+use gistit_core::secret;
+use gistit_ipc::{Bridge, Client};
+
+use gistit_proto::ipc::instruction::fetch_progress_response::Stage;
+use gistit_proto::ipc::instruction::FetchProgressResponse;
 use gistit_proto::ipc::{self, Instruction};
-use gistit_proto::payload::Gistit;
-use gistit_proto::prost::Message;
+use gistit_proto::payload::{Gistit, Head};
+use gistit_proto::Inner;
 
 use gistit_project::path;
 
+use crate::archive;
+use crate::audit;
+use crate::binary;
+use crate::cache::{self, Entry, Index, Origin};
+use crate::comment;
+use crate::credentials;
+use crate::daemon;
 use crate::dispatch::Dispatch;
-use crate::file::File;
-use crate::param::check;
-use crate::server::SERVER_URL_GET;
-use crate::{errorln, finish, interruptln, progress, updateln, warnln, Error, Result};
+use crate::document;
+use crate::export::{self, Format};
+use crate::file::{self, relative_path, Conflict, File};
+use crate::fmt::PROGRESS;
+use crate::history;
+use crate::name;
+use crate::param::{check, Identifier};
+use crate::passphrase;
+use crate::sanitize;
+use crate::server::{self, Feature, SERVER_URL_BASE};
+use crate::settings::{get_runtime_settings, Backend, Settings};
+use crate::signal;
+use crate::workspace::Workspace;
+use crate::{cleanln, finish, progress, updateln, warnln, Error, Result};
+
+/// How long to wait for `gistit-daemon` to report progress before trying the next source
+const P2P_FETCH_TIMEOUT: Duration = Duration::from_secs(30);
 
 #[derive(Debug, Clone)]
-pub struct Action {
-    pub hash: &'static str,
-    pub colorscheme: &'static str,
+pub struct Action<'a> {
+    pub hash: &'a str,
+    pub colorscheme: Option<&'a str>,
+    pub prefer: Option<&'a str>,
+    pub secret: Option<passphrase::Source<'a>>,
+    pub token: Option<&'a str>,
     pub save: bool,
+    /// Overwrite files already where it's saved
+    pub force: bool,
+    /// Save next to files already where it's saved
+    pub rename_on_conflict: bool,
+    /// Only this file of a gistit with many
+    pub file: Option<&'a str>,
+    /// Revision to fetch, numbered from the oldest
+    pub rev: Option<&'a str>,
+    /// Agree upfront to fetching gistits deleted once read
+    pub yes: bool,
+    pub head: bool,
+    pub no_pager: bool,
+    /// `never` or `character`
+    pub wrap: Option<&'a str>,
+    pub tab_width: Option<&'a str>,
+    pub no_line_numbers: bool,
+    pub no_grid: bool,
+    /// `auto` or `source`
+    pub render: Option<&'a str>,
+    pub plain: bool,
+    pub line_range: Option<&'a str>,
+    /// Format and output path
+    pub export: Option<(&'a OsStr, &'a OsStr)>,
+    /// Print the comment thread below the preview
+    pub comments: bool,
 }
 
-impl Action {
+impl<'a> Action<'a> {
     pub fn from_args(
-        args: &'static ArgMatches,
-    ) -> Result<Box<dyn Dispatch<InnerData = Config> + Send + Sync + 'static>> {
+        args: &'a ArgMatches,
+    ) -> Result<Box<dyn Dispatch<InnerData = Config<'a>> + Send + Sync + 'a>> {
         Ok(Box::new(Self {
             hash: args
                 .value_of("HASH")
                 .ok_or(Error::Argument("missing arugment", "--hash"))?,
-            colorscheme: args
-                .value_of("colorscheme")
-                .unwrap_or("Monokai Extended Origin"), // This is the most decent looking
+            colorscheme: args.value_of("colorscheme"),
+            prefer: args.value_of("prefer"),
+            secret: passphrase::Source::from_args(args),
+            token: args.value_of("token"),
             save: args.is_present("save"),
+            force: args.is_present("force"),
+            rename_on_conflict: args.is_present("rename-on-conflict"),
+            file: args.value_of("file"),
+            rev: args.value_of("rev"),
+            yes: args.is_present("yes"),
+            head: args.is_present("head"),
+            no_pager: args.is_present("no-pager"),
+            wrap: args.value_of("wrap"),
+            tab_width: args.value_of("tab-width"),
+            no_line_numbers: args.is_present("no-line-numbers"),
+            no_grid: args.is_present("no-grid"),
+            render: args.value_of("render"),
+            plain: args.is_present("plain"),
+            line_range: args.value_of("line-range"),
+            export: args.values_of_os("export").and_then(|mut values| {
+                let format = values.next()?;
+                Some((format, values.next()?))
+            }),
+            comments: args.is_present("comments"),
         }))
     }
 }
 
 #[derive(Debug, Serialize)]
-pub struct Config {
-    hash: &'static str,
-    colorscheme: &'static str,
+pub struct Config<'a> {
+    #[serde(skip)]
+    identifier: Identifier<'a>,
+    colorscheme: String,
     save: bool,
+    /// What `--save` does with files already there
+    #[serde(skip)]
+    conflict: Conflict,
+    /// Only this file of a gistit with many
+    file: Option<&'a str>,
+    /// Revision to fetch instead, numbered from the oldest
+    rev: Option<usize>,
+    /// Raw content to stdout, without highlighting or decorations
+    plain: bool,
+    paging: bool,
+    /// Wrap long lines or cut them, the pager decides if unset
+    wrap: Option<bool>,
+    /// Spaces tabs are expanded to, `0` leaves them
+    tab_width: Option<usize>,
+    line_numbers: bool,
+    grid: bool,
+    /// Render markdown files and notebooks instead of highlighting their source
+    rich: bool,
+    /// Command previewing instead of bat, from the global settings
+    previewer: Option<String>,
+    line_range: Option<(usize, usize)>,
+    #[serde(skip)]
+    export: Option<(Format, PathBuf)>,
+    #[serde(skip)]
+    backend: Backend,
+    #[serde(skip)]
+    prefer: Prefer,
+    #[serde(skip)]
+    secret: Option<String>,
+    /// The secret came from the `#key=` fragment of the link
+    secret_in_link: bool,
+    /// Times to ask again for a mistyped secret, when it's typed at the prompt
+    #[serde(skip)]
+    secret_retries: u8,
+    /// Owner token of a private gistit, the saved one is looked up once the hash is known
+    #[serde(skip)]
+    token: Option<&'a str>,
     runtime_path: PathBuf,
     config_path: PathBuf,
     data_path: PathBuf,
-}
-
-impl TryFrom<&Config> for Gistit {
-    type Error = Error;
-
-    fn try_from(value: &Config) -> std::result::Result<Self, Self::Error> {
-        Ok(Self {
-            hash: value.hash.to_owned(),
-            ..Self::default()
-        })
-    }
+    /// Where `--save` writes gistits fetched from peers, the data directory if unset
+    quarantine: Option<PathBuf>,
 }
 
 #[async_trait]
-impl Dispatch for Action {
-    type InnerData = Config;
+impl<'a> Dispatch for Action<'a> {
+    type InnerData = Config<'a>;
 
     async fn prepare(&self) -> Result<Self::InnerData> {
         progress!("Preparing");
-        let hash = check::hash(self.hash)?;
-        let colorscheme = check::colorscheme(self.colorscheme)?;
+        let identifier = check::identifier(self.hash)?;
+        let (_, key) = check::key_link(self.hash)?;
+        let settings = get_runtime_settings()?;
+        // A project settings file could point these anywhere
+        let global = Settings::load()?;
+        let prefer = self.prefer.map_or_else(
+            || Ok(Prefer::from_backend(settings.backend())),
+            Prefer::parse,
+        )?;
+        let colorscheme =
+            check::colorscheme(self.colorscheme.unwrap_or_else(|| settings.colorscheme()))?
+                .to_owned();
+
+        // Comments are only kept by the server, as everything else when only it is looked up.
+        // It's asked upfront whether it supports what's needed
+        let server_only = matches!(
+            identifier,
+            Identifier::Server(_) | Identifier::ServerShort(_)
+        );
+        let required: Vec<Feature> = [
+            (self.comments, Feature::Comments),
+            (server_only && self.rev.is_some(), Feature::Revisions),
+            (
+                matches!(identifier, Identifier::ServerShort(_)),
+                Feature::Resolve,
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(needed, feature)| needed.then_some(feature))
+        .collect();
+        if !required.is_empty() {
+            let capabilities = server::capabilities().await;
+            for feature in required {
+                capabilities.require(feature)?;
+            }
+        }
         updateln!("Prepared");
 
         Ok(Config {
-            hash,
+            identifier,
             colorscheme,
             save: self.save,
-            runtime_path: path::runtime()?,
+            conflict: Conflict::from_flags(self.force, self.rename_on_conflict),
+            file: self.file,
+            rev: self.rev.map(check::revision).transpose()?,
+            // Piped somewhere, decorations would only get in the way
+            plain: self.plain || !Term::stdout().is_term(),
+            // The thread goes below the preview, not after quitting the pager
+            paging: !self.no_pager && !self.comments,
+            wrap: self.wrap.map(|mode| mode == "character"),
+            tab_width: self.tab_width.map(check::tab_width).transpose()?,
+            line_numbers: !self.no_line_numbers,
+            grid: !self.no_grid,
+            // Rendered lines aren't the source lines a range is given in
+            rich: self.render != Some("source") && self.line_range.is_none(),
+            previewer: global.previewer,
+            line_range: self.line_range.map(check::line_range).transpose()?,
+            export: self
+                .export
+                .map(|(format, out_path)| {
+                    let format = format.to_str().ok_or(Error::Argument(
+                        "invalid export format, expected 'html' or 'png'",
+                        "--export",
+                    ))?;
+                    Ok::<_, Error>((Format::parse(format)?, Path::new(out_path).to_owned()))
+                })
+                .transpose()?,
+            backend: settings.backend(),
+            prefer,
+            secret_in_link: key.is_some()
+                && matches!(self.secret, None | Some(passphrase::Source::Env)),
+            // One given with a flag wins over the link's, the environment's and the workspace key
+            // are only fallbacks
+            secret: match (self.secret, key) {
+                (None | Some(passphrase::Source::Env), Some(key)) => Some(key.to_owned()),
+                (Some(source), _) => Some(source.read(false).await?),
+                (None, None) => Workspace::active(&settings)?.map(|workspace| workspace.key),
+            },
+            secret_retries: settings.secret_retries(),
+            token: self.token.map(check::token).transpose()?,
+            runtime_path: path::runtime_dir()?,
             config_path: path::config()?,
             data_path: path::data()?,
+            quarantine: global.quarantine,
         })
     }
 
     async fn dispatch(&self, config: Self::InnerData) -> Result<()> {
-        progress!("Fetching");
         let mut bridge = gistit_ipc::client(&config.runtime_path)?;
+        daemon::spawn_if_required(
+            config.backend,
+            &bridge,
+            &config.runtime_path,
+            &config.config_path,
+        )
+        .await?;
 
-        if bridge.alive() {
-            warnln!("gistit-daemon running, looking in the DHT");
-            bridge.connect_blocking()?;
-            bridge
-                .send(Instruction::request_fetch(self.hash.to_owned()))
-                .await?;
-
-            if let ipc::instruction::Kind::FetchResponse(ipc::instruction::FetchResponse {
-                gistit: Some(gistit),
-            }) = bridge.recv().await?.expect_response()?
-            {
-                preview_or_save(&gistit, self.save, &config)?;
-            } else {
-                interruptln!();
-                errorln!("gistit hash not found");
+        let (hash, sources) = locate(&mut bridge, config.identifier, config.prefer).await?;
+        let hash = match config.rev {
+            Some(rev) => {
+                let token = owner_token(config.token, &hash, &sources);
+                history::revision(&mut bridge, &hash, sources.clone(), token.as_deref(), rev)
+                    .await?
             }
-        } else {
-            let gistit: Gistit = (&config).try_into()?;
-
-            let response = reqwest::Client::new()
-                .post(SERVER_URL_GET.to_string())
-                .header("content-type", "application/x-protobuf")
-                .body(gistit.encode_to_vec())
-                .send()
-                .await?;
-            updateln!("Fetched");
-
-            match response.status() {
-                StatusCode::OK => {
-                    let gistit = Gistit::from_bytes(response.bytes().await?)?;
-                    preview_or_save(&gistit, self.save, &config)?;
+            None => hash,
+        };
+        let token = owner_token(config.token, &hash, &sources);
+
+        if self.head {
+            return print_head(&mut bridge, &hash, sources, token.as_deref()).await;
+        }
+
+        let last = sources.len() - 1;
+        for (i, source) in sources.into_iter().enumerate() {
+            let mut fetched =
+                fetch_from(&mut bridge, source, &hash, token.as_deref(), self.yes).await;
+            if matches!(fetched, Err(Error::Core(gistit_core::Error::BurnAfterRead))) {
+                // Found, but it's gone once fetched
+                if !confirm_burn().await? {
+                    return Err(Error::Argument(
+                        "gistit is deleted once read, nothing was fetched",
+                        "--yes",
+                    ));
+                }
+                fetched = fetch_from(&mut bridge, source, &hash, token.as_deref(), true).await;
+            }
+
+            match fetched {
+                Ok(Some(mut gistit)) => {
+                    audit::note(&hash, source.audit_target());
+                    if let Some(reads_left) = gistit.reads_left {
+                        warnln!("{}", fmt_reads_left(reads_left));
+                    }
+                    if secret::is_encrypted(&gistit) {
+                        decrypt(&mut gistit, &config, self.secret).await?;
+                    }
+                    preview_or_save(&gistit, source, self.save, &config)?;
+                    if self.comments {
+                        print_comments(&hash, token.as_deref()).await?;
+                    }
+                    return Ok(());
                 }
-                StatusCode::NOT_FOUND => {
-                    return Err(Error::Server("gistit hash not found"));
+                Ok(None) if i < last => warnln!("gistit not found {}, trying next", source),
+                Err(err) if i < last => warnln!("{}, trying next", err),
+                Ok(None) => break,
+                Err(err) => return Err(err),
+            }
+        }
+
+        Err(gistit_core::Error::NotFound.into())
+    }
+}
+
+/// The hash `identifier` points to and the sources to look for it in, in order
+///
+/// # Errors
+///
+/// Fails if a name can't be resolved or a short hash expanded, see [`expand`]
+pub async fn locate(
+    bridge: &mut Bridge<Client>,
+    identifier: Identifier<'_>,
+    prefer: Prefer,
+) -> Result<(String, Vec<Source>)> {
+    Ok(match identifier {
+        Identifier::Server(hash) => (hash.to_owned(), vec![Source::Server]),
+        Identifier::Peer(hash) => (hash.to_owned(), vec![Source::Daemon]),
+        Identifier::Name(name) => {
+            // Names only live in the DHT
+            let (hash, _) = name::resolve(bridge, name).await?;
+            (hash, vec![Source::Daemon])
+        }
+        Identifier::Hash(hash) => (hash.to_owned(), prefer.sources(bridge.alive())),
+        Identifier::Short(short) => {
+            let sources = prefer.sources(bridge.alive());
+            (expand(bridge, short, &sources).await?, sources)
+        }
+        Identifier::ServerShort(short) => (
+            expand(bridge, short, &[Source::Server]).await?,
+            vec![Source::Server],
+        ),
+    })
+}
+
+/// The hash starting with `short`, like git short SHAs. Hashes sent or fetched from this machine
+/// are looked up first, then `sources` are asked: `gistit-daemon` for the ones it provides or
+/// received, the server for public ones
+///
+/// # Errors
+///
+/// Fails with [`Error::Ambiguous`] listing the candidates if more than one hash starts with it,
+/// or with [`gistit_core::Error::NotFound`] if none does
+pub async fn expand(
+    bridge: &mut Bridge<Client>,
+    short: &str,
+    sources: &[Source],
+) -> Result<String> {
+    if let Some(hash) = Index::load()?.expand(short)? {
+        return Ok(hash.to_owned());
+    }
+
+    progress!("Expanding short hash");
+    let mut candidates: Vec<String> = Vec::new();
+    for source in sources {
+        let found = match source {
+            Source::Daemon if !bridge.alive() => continue,
+            Source::Daemon => resolve_p2p(bridge, short).await,
+            Source::Server => server::retry_rate_limited(|| {
+                gistit_core::fetch::resolve_server(&SERVER_URL_BASE, short)
+            })
+            .await
+            .map_err(Error::from),
+        };
+
+        match found {
+            Ok(hashes) => {
+                for hash in hashes {
+                    if hash.starts_with(short) && !candidates.contains(&hash) {
+                        candidates.push(hash);
+                    }
+                }
+            }
+            Err(err) => warnln!("{}, can't look for the short hash {}", err, source),
+        }
+    }
+
+    match candidates.len() {
+        0 => Err(gistit_core::Error::NotFound.into()),
+        1 => Ok(candidates.remove(0)),
+        _ => Err(Error::Ambiguous(candidates)),
+    }
+}
+
+/// Hashes `gistit-daemon` provides or received starting with `short`, it can't search the DHT
+async fn resolve_p2p(bridge: &mut Bridge<Client>, short: &str) -> Result<Vec<String>> {
+    bridge.connect_blocking()?;
+    bridge
+        .send(Instruction::request_resolve_prefix(short.to_owned()))
+        .await?;
+
+    match bridge.recv().await?.expect_response()? {
+        ipc::instruction::Kind::ResolvePrefixResponse(
+            ipc::instruction::ResolvePrefixResponse { hashes },
+        ) => Ok(hashes),
+        _ => Err(Error::Daemon(
+            "unexpected response, check gistit-daemon logs",
+        )),
+    }
+}
+
+/// The owner token to fetch `hash` with, the saved one unless `token` is given. Only the server
+/// knows about private gistits
+#[must_use]
+pub fn owner_token(token: Option<&str>, hash: &str, sources: &[Source]) -> Option<String> {
+    match token {
+        Some(token) => Some(token.to_owned()),
+        None if sources.contains(&Source::Server) => credentials::owner_token(hash),
+        None => None,
+    }
+}
+
+/// Decrypts `gistit` in place. A secret typed at the prompt that doesn't decrypt it is asked
+/// again, up to `config.secret_retries` times
+async fn decrypt(
+    gistit: &mut Gistit,
+    config: &Config,
+    source: Option<passphrase::Source>,
+) -> Result<()> {
+    let secret = config.secret.clone().ok_or(Error::Secret(
+        "gistit is encrypted, pass it's secret with `--secret-prompt`",
+    ))?;
+    let retries = match source {
+        Some(passphrase::Source::Prompt) => config.secret_retries,
+        _ => 0,
+    };
+
+    open_retrying(gistit, secret, retries, config.secret_in_link, || {
+        passphrase::Source::Prompt.read(false)
+    })
+    .await
+}
+
+/// Decrypts `gistit` in place with `secret`, asking for another one with `ask_again` while it's
+/// the wrong one, up to `retries_left` times
+async fn open_retrying<F>(
+    gistit: &mut Gistit,
+    mut secret: String,
+    mut retries_left: u8,
+    secret_in_link: bool,
+    mut ask_again: impl FnMut() -> F,
+) -> Result<()>
+where
+    F: Future<Output = Result<String>>,
+{
+    loop {
+        progress!("Decrypting");
+        // Opened on a copy, a failed attempt must not leave it half decrypted
+        let mut opened = gistit.clone();
+        match secret::open(&mut opened, &secret) {
+            Ok(()) => {
+                *gistit = opened;
+                updateln!("Decrypted");
+                return Ok(());
+            }
+            Err(gistit_core::Error::WrongSecret) if secret_in_link => {
+                return Err(Error::Secret(
+                    "the key in the link doesn't open this gistit, check it wasn't cut short",
+                ));
+            }
+            Err(gistit_core::Error::WrongSecret) if retries_left > 0 => {
+                warnln!(
+                    "wrong secret, {} {} left",
+                    retries_left,
+                    if retries_left == 1 { "try" } else { "tries" }
+                );
+                retries_left -= 1;
+                secret = ask_again().await?;
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+/// Where to look for a gistit first, the other source is tried when it's not found there
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Prefer {
+    /// The p2p network, then the server
+    P2p,
+    /// The server, then the p2p network if `gistit-daemon` is running
+    Server,
+    /// The p2p network if `gistit-daemon` is running, then the server
+    Auto,
+}
+
+impl Prefer {
+    /// Parses a `--prefer` value
+    ///
+    /// # Errors
+    ///
+    /// Fails if it's not 'p2p', 'server' or 'auto'
+    pub fn parse(prefer: &str) -> Result<Self> {
+        match prefer {
+            "p2p" => Ok(Self::P2p),
+            "server" => Ok(Self::Server),
+            "auto" => Ok(Self::Auto),
+            _ => Err(Error::Argument(
+                "invalid source, expected 'p2p', 'server' or 'auto'",
+                "--prefer",
+            )),
+        }
+    }
+
+    #[must_use]
+    pub const fn from_backend(backend: Backend) -> Self {
+        match backend {
+            Backend::Auto => Self::Auto,
+            Backend::Server => Self::Server,
+            Backend::P2p => Self::P2p,
+        }
+    }
+
+    /// Sources to try in order
+    #[must_use]
+    pub fn sources(self, daemon_alive: bool) -> Vec<Source> {
+        match self {
+            Self::P2p => vec![Source::Daemon, Source::Server],
+            Self::Server if daemon_alive => vec![Source::Server, Source::Daemon],
+            Self::Auto if daemon_alive => vec![Source::Daemon, Source::Server],
+            Self::Server | Self::Auto => vec![Source::Server],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Daemon,
+    Server,
+}
+
+impl Source {
+    /// Where it came from in the audit log
+    #[must_use]
+    pub fn audit_target(self) -> &'static str {
+        match self {
+            Self::Daemon => audit::P2P,
+            Self::Server => SERVER_URL_BASE.as_str(),
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Daemon => write!(f, "in the DHT"),
+            Self::Server => write!(f, "on the server"),
+        }
+    }
+}
+
+/// Fetches `hash` from `source`, `None` if it doesn't have it
+///
+/// # Errors
+///
+/// Fails if `source` can't be reached, or if the gistit is deleted once read and
+/// `burn_after_read` is unset
+pub async fn fetch_from(
+    bridge: &mut Bridge<Client>,
+    source: Source,
+    hash: &str,
+    token: Option<&str>,
+    burn_after_read: bool,
+) -> Result<Option<Gistit>> {
+    match source {
+        Source::Daemon => fetch_p2p(bridge, hash, burn_after_read).await,
+        Source::Server => fetch_server(hash, token, burn_after_read).await,
+    }
+}
+
+/// Fetches `hash` from the first of `sources` that has it, never agreeing to burn it. For commands
+/// that only look at a gistit, like `verify` and `diff`
+///
+/// # Errors
+///
+/// Fails with [`gistit_core::Error::NotFound`] if no source has it, with the last source error if
+/// one failed, or with [`gistit_core::Error::BurnAfterRead`] if it's deleted once read
+pub async fn fetch_any(
+    bridge: &mut Bridge<Client>,
+    hash: &str,
+    sources: Vec<Source>,
+    token: Option<&str>,
+) -> Result<(Gistit, Source)> {
+    let last = sources.len() - 1;
+    for (i, source) in sources.into_iter().enumerate() {
+        match fetch_from(bridge, source, hash, token, false).await {
+            Ok(Some(gistit)) => return Ok((gistit, source)),
+            Err(Error::Core(gistit_core::Error::BurnAfterRead)) => {
+                return Err(gistit_core::Error::BurnAfterRead.into());
+            }
+            Ok(None) if i < last => warnln!("gistit not found {}, trying next", source),
+            Err(err) if i < last => warnln!("{}, trying next", err),
+            Ok(None) => break,
+            Err(err) => return Err(err),
+        }
+    }
+
+    Err(gistit_core::Error::NotFound.into())
+}
+
+/// Asks whether to fetch a gistit deleted once read. Without a terminal to ask, `--yes` is needed
+async fn confirm_burn() -> Result<bool> {
+    if !Term::stderr().is_term() {
+        return Ok(false);
+    }
+
+    cleanln!(format!(
+        "this gistit is deleted once read, nobody can fetch it after you. Fetch it? {}",
+        style("[y/N]").dim()
+    ));
+    let answer = tokio::task::spawn_blocking(|| Term::stderr().read_char())
+        .await
+        .expect("prompt to not panic")?;
+
+    Ok(matches!(answer, 'y' | 'Y'))
+}
+
+/// Fetches `hash` through `gistit-daemon`, giving up when it stays silent for
+/// [`P2P_FETCH_TIMEOUT`]. Gistits deleted once read are only fetched if `burn_after_read`
+pub async fn fetch_p2p(
+    bridge: &mut Bridge<Client>,
+    hash: &str,
+    burn_after_read: bool,
+) -> Result<Option<Gistit>> {
+    progress!("Fetching");
+    if !bridge.alive() {
+        return Err(Error::Daemon("gistit node is not running"));
+    }
+
+    warnln!("gistit-daemon running, looking in the DHT");
+    let _pending = signal::pending(hash);
+    bridge.connect_blocking()?;
+    bridge
+        .send(Instruction::request_fetch(hash.to_owned(), burn_after_read))
+        .await?;
+
+    // Progress is streamed before the final response
+    let fetch = async {
+        loop {
+            let instruction = timeout(P2P_FETCH_TIMEOUT, bridge.recv())
+                .await
+                .map_err(|_| Error::Daemon("timed out waiting for gistit-daemon"))??;
+
+            match instruction.expect_response()? {
+                ipc::instruction::Kind::FetchProgressResponse(progress) => report(&progress),
+                ipc::instruction::Kind::FetchResponse(ipc::instruction::FetchResponse {
+                    gistit: Some(gistit),
+                    ..
+                }) => {
+                    updateln!("Fetched");
+                    return Ok(Some(gistit));
                 }
-                _ => return Err(Error::Server("unexpected response")),
+                ipc::instruction::Kind::FetchResponse(ipc::instruction::FetchResponse {
+                    burn_after_read: true,
+                    ..
+                }) => return Err(gistit_core::Error::BurnAfterRead.into()),
+                _ => return Ok(None),
             }
         }
+    };
+
+    if let Some(limit) = gistit_core::timeout() {
+        if let Ok(fetched) = timeout(limit, fetch).await {
+            return fetched;
+        }
+        // The daemon keeps the chunks downloaded so far, fetching again resumes
+        bridge
+            .send(Instruction::request_cancel(hash.to_owned()))
+            .await?;
+        return Err(Error::Daemon(
+            "timed out fetching from the p2p network, fetch again to resume",
+        ));
+    }
+    fetch.await
+}
 
-        Ok(())
+/// Prints the comment thread of `hash` below the preview. Only the server keeps threads, a gistit
+/// fetched through the p2p network may have none
+async fn print_comments(hash: &str, token: Option<&str>) -> Result<()> {
+    match comment::thread(hash, token).await {
+        Ok(comments) => {
+            PROGRESS.finish_and_clear();
+            std::io::stdout()
+                .write_all(comment::format_comments(&comments, comment::now()).as_bytes())?;
+        }
+        Err(err) => warnln!("comments unavailable: {}", err),
     }
+    Ok(())
 }
 
-pub fn preview_or_save(gistit: &Gistit, save: bool, config: &Config) -> Result<()> {
-    // NOTE: Currently we support one file
-    let inner = gistit.inner.first().expect("to have at least one file");
-    let mut file = File::from_data(&inner.data, &inner.name)?;
-    let save_location = &config.data_path;
+/// Prints the metadata of `hash` from the first source that has it
+async fn print_head(
+    bridge: &mut Bridge<Client>,
+    hash: &str,
+    sources: Vec<Source>,
+    token: Option<&str>,
+) -> Result<()> {
+    let last = sources.len() - 1;
+    for (i, source) in sources.into_iter().enumerate() {
+        let head = match source {
+            Source::Daemon => head_p2p(bridge, hash).await,
+            Source::Server => head_server(hash, token).await,
+        };
 
+        match head {
+            Ok(Some(head)) => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .expect("Check your system time")
+                    .as_secs();
+                finish!(format_head(&head, now));
+                return Ok(());
+            }
+            Ok(None) if i < last => warnln!("gistit not found {}, trying next", source),
+            Err(err) if i < last => warnln!("{}, trying next", err),
+            Ok(None) => break,
+            Err(err) => return Err(err),
+        }
+    }
+
+    Err(gistit_core::Error::NotFound.into())
+}
+
+/// Asks `gistit-daemon` for the metadata of `hash`. Providers are asked for it directly, it's
+/// content isn't downloaded
+pub async fn head_p2p(bridge: &mut Bridge<Client>, hash: &str) -> Result<Option<Head>> {
+    progress!("Fetching metadata");
+    if !bridge.alive() {
+        return Err(Error::Daemon("gistit node is not running"));
+    }
+
+    warnln!("gistit-daemon running, looking in the DHT");
+    let _pending = signal::pending(hash);
+    bridge.connect_blocking()?;
+    bridge
+        .send(Instruction::request_head(hash.to_owned()))
+        .await?;
+
+    let instruction = timeout(P2P_FETCH_TIMEOUT, bridge.recv())
+        .await
+        .map_err(|_| Error::Daemon("timed out waiting for gistit-daemon"))??;
+    match instruction.expect_response()? {
+        ipc::instruction::Kind::HeadResponse(ipc::instruction::HeadResponse { head }) => {
+            updateln!("Fetched metadata");
+            Ok(head)
+        }
+        _ => Ok(None),
+    }
+}
+
+async fn head_server(hash: &str, token: Option<&str>) -> Result<Option<Head>> {
+    progress!("Fetching metadata");
+    let head = server::retry_rate_limited(|| {
+        gistit_core::fetch::head_server(&SERVER_URL_BASE, hash, token)
+    })
+    .await?;
+    if head.is_some() {
+        updateln!("Fetched metadata");
+    }
+    Ok(head)
+}
+
+fn format_head(head: &Head, now: u64) -> String {
+    let mut lines = vec![
+        format!("    hash: '{}'", style(&head.hash).bold()),
+        format!(
+            "    name: '{}' ({}, {})",
+            style(&head.name).green(),
+            head.lang,
+            HumanBytes(u64::from(head.size))
+        ),
+        format!("    author: '{}'", style(&head.author).blue()),
+    ];
+    if let Some(ref description) = head.description {
+        lines.push(format!(
+            "    description: '{}'",
+            style(description).italic()
+        ));
+    }
+    if head.encrypted {
+        lines.push("    encrypted, fetch it with `--secret-prompt`".to_owned());
+    }
+    if head.burn_after_read {
+        lines.push("    deleted once fetched".to_owned());
+    }
+    if let Some(reads_left) = head.reads_left {
+        lines.push(format!("    reads left: {}", reads_left));
+    }
+    lines.push(head.expires_at.map_or_else(
+        || "    expires: never".to_owned(),
+        |expires_at| {
+            format!(
+                "    expires: {}",
+                fmt_in((expires_at / 1000).saturating_sub(now))
+            )
+        },
+    ));
+
+    format!("\n{}\n\n", lines.join("\n"))
+}
+
+fn fmt_reads_left(reads_left: u32) -> String {
+    match reads_left {
+        0 => "that was the last read, the gistit is deleted now".to_owned(),
+        1 => "1 read left before the gistit is deleted".to_owned(),
+        n => format!("{} reads left before the gistit is deleted", n),
+    }
+}
+
+fn fmt_in(secs: u64) -> String {
+    match secs {
+        0..=59 => "any moment now".to_owned(),
+        60..=3599 => format!("in {}m", secs / 60),
+        3600..=86_399 => format!("in {}h", secs / 3600),
+        _ => format!("in {}d", secs / 86_400),
+    }
+}
+
+/// Renders a progress update streamed by `gistit-daemon`
+fn report(progress: &FetchProgressResponse) {
+    match progress.stage() {
+        Stage::Searching => progress!("Looking for providers in the DHT"),
+        Stage::ProvidersFound => updateln!("Found {} providers", progress.providers),
+        Stage::Connecting => progress!("Connecting to {}", style(&progress.peer_id).dim()),
+        Stage::Downloading => progress!(
+            "{} {}/{} chunks, {} from {}",
+            progress_bar(progress.chunks_done, progress.chunks_total),
+            progress.chunks_done,
+            progress.chunks_total,
+            HumanBytes(progress.bytes_done),
+            style(&progress.peer_id).dim()
+        ),
+    }
+}
+
+fn progress_bar(done: u32, total: u32) -> String {
+    const WIDTH: u32 = 20;
+    let filled = if total == 0 {
+        0
+    } else {
+        (done.min(total) * WIDTH / total) as usize
+    };
+
+    format!(
+        "{}{}",
+        style("█".repeat(filled)).blue(),
+        style("░".repeat(WIDTH as usize - filled)).dim()
+    )
+}
+
+/// Asks the server for `hash`, private gistits are only answered with their owner `token` and
+/// gistits deleted once read only if `burn_after_read`
+pub async fn fetch_server(
+    hash: &str,
+    token: Option<&str>,
+    burn_after_read: bool,
+) -> Result<Option<Gistit>> {
+    progress!("Fetching");
+    let gistit = server::retry_rate_limited(|| {
+        gistit_core::fetch::fetch_server(&SERVER_URL_BASE, hash, token, burn_after_read)
+    })
+    .await?;
+    if gistit.is_some() {
+        updateln!("Fetched");
+    }
+    Ok(gistit)
+}
+
+/// Previews, saves, exports or prints `gistit`. Gistits with many files are previewed one after
+/// the other and saved restoring their directory structure, `--file` picks one of them. Archives
+/// are opened the same way, see [`open_archive`]. Nothing is done with a gistit failing
+/// [`sanitize::check`], gistits from peers are saved to the quarantine directory if there's one.
+/// Binary files are described instead of previewed, saved if there's nothing else to preview
+pub fn preview_or_save(gistit: &Gistit, source: Source, save: bool, config: &Config) -> Result<()> {
+    sanitize::check(gistit)?;
+    for inner in &gistit.inner {
+        if let Some(what) = sanitize::sniff(inner) {
+            warnln!("'{}' looks like {}, not {}", inner.name, what, inner.lang);
+        }
+    }
+
+    let first = gistit
+        .inner
+        .first()
+        .ok_or_else(|| Error::Unsafe("it has no files".to_owned()))?;
+    // Printing binaries to the terminal would only garble it, they're saved unless piped somewhere
+    let save = save
+        || (!config.plain
+            && config.export.is_none()
+            && gistit.inner.iter().all(|inner| inner.binary));
+    let save_location = match (source, &config.quarantine) {
+        (Source::Daemon, Some(quarantine)) => quarantine,
+        _ => &config.data_path,
+    };
     if save {
-        let file_path = save_location.join(file.name());
-        file.save_as(&file_path)?;
+        fs::create_dir_all(save_location)?;
+    }
+    cache::record_or_warn(Entry::new(
+        &gistit.hash,
+        &first.name,
+        &gistit.author,
+        Origin::Fetched,
+    ));
+
+    if let Some(format) = archive::Format::detect(gistit) {
+        return open_archive(gistit, format, save, save_location, config);
+    }
+
+    let files = select_files(gistit, config.file)?;
+
+    if let Some((format, ref out_path)) = config.export {
+        let inner = single(&files, "--export")?;
+        if inner.binary {
+            return Err(Error::Argument(
+                "binary files can't be exported, save them with `--save`",
+                "--export",
+            ));
+        }
+        // Exporting renders the first file, the picked one goes first
+        let selected = Gistit {
+            inner: vec![inner.clone()],
+            ..gistit.clone()
+        };
+
+        progress!("Exporting");
+        export::export(&selected, format, out_path, &config.colorscheme)?;
+        updateln!("Exported");
+        finish!(format!(
+            "\n    exported: '{}'\n\n",
+            style(out_path.to_string_lossy()).bold()
+        ));
+    } else if save && gistit.inner.len() > 1 {
+        let dir_path = save_location.join(&gistit.hash);
+        let saved = save_files(&dir_path, &files, config.conflict)?;
+
+        warnln!("gistit saved at: `{}`", dir_path.to_string_lossy());
+        finish!(format!(
+            "💾  Saved {} of {} files",
+            saved,
+            gistit.inner.len()
+        ));
+    } else if save {
+        // Saved flat, without the directories of it's name
+        let name = file::name_from_path(Path::new(&first.name));
+        let file_path = match config.conflict.resolve(&save_location.join(&name))? {
+            Some(file_path) => file_path,
+            None => {
+                finish!("💾  Nothing saved");
+                return Ok(());
+            }
+        };
+        let content = binary::content(first)?;
+        fs::write(&file_path, &content)?;
 
         warnln!("gistit saved at: `{}`", file_path.to_string_lossy());
-        finish!("💾  Saved");
+        if first.binary {
+            finish!(format!("💾  Saved {}", binary::describe(&content)));
+        } else {
+            finish!("💾  Saved");
+        }
+    } else if config.plain {
+        print_plain(single(&files, "--plain")?, config)?;
+    } else if files.len() > 1 {
+        preview(
+            gistit,
+            &files,
+            format!("👀  Preview\n{}", format_files(&files)),
+            config,
+        )?;
+    } else {
+        preview(gistit, &files, "👀  Preview".to_owned(), config)?;
+    }
+    Ok(())
+}
+
+/// Lists the files of an archive gistit, previews the text ones and extracts them with `--save`.
+/// `--plain` prints the archive itself, to pipe it elsewhere, unless `--file` picks a file
+fn open_archive(
+    gistit: &Gistit,
+    format: archive::Format,
+    save: bool,
+    save_location: &Path,
+    config: &Config,
+) -> Result<()> {
+    if config.export.is_some() {
+        return Err(Error::Argument(
+            "archives can't be exported, extract them with `--save`",
+            "--export",
+        ));
+    }
+
+    let first = gistit
+        .inner
+        .first()
+        .ok_or_else(|| Error::Unsafe("it has no files".to_owned()))?;
+    let bytes = archive::decode(first)?;
+
+    if save {
+        progress!("Extracting");
+        let dir_path = save_location.join(&gistit.hash);
+        let extracted = archive::extract(&bytes, format, &dir_path, config.file, config.conflict)?;
+        if extracted == 0 && config.file.is_some() {
+            return Err(Error::Argument("no such file in the archive", "--file"));
+        }
+        updateln!("Extracted");
+
+        warnln!("gistit extracted at: `{}`", dir_path.to_string_lossy());
+        finish!(format!("💾  Extracted {} files", extracted));
+        return Ok(());
+    }
+    if config.plain && config.file.is_none() {
+        PROGRESS.finish_and_clear();
+        std::io::stdout().write_all(&bytes)?;
+        return Ok(());
+    }
+
+    progress!("Unpacking");
+    let manifest = archive::manifest(&bytes, format)?;
+    let unpacked = Gistit {
+        inner: archive::unpack(&bytes, format)?,
+        ..gistit.clone()
+    };
+    updateln!("Unpacked");
+
+    let files = select_files(&unpacked, config.file)?;
+    if config.plain {
+        print_plain(single(&files, "--plain")?, config)
     } else {
-        finish!("👀  Preview");
-        let mut header_string = style(&inner.name).green().to_string();
-        header_string.push_str(&format!(" | {}", style(&gistit.author).blue().bold()));
+        let heading = format!(
+            "👀  Preview of '{}'\n{}",
+            first.name,
+            format_manifest(&manifest, &unpacked)
+        );
+        preview(&unpacked, &files, heading, config)
+    }
+}
 
-        if let Some(ref description) = gistit.description {
-            header_string.push_str(&format!(" | {}", style(description).italic()));
+/// Prints the content of `inner`, the bytes of binary ones unless stdout is a terminal
+fn print_plain(inner: &Inner, config: &Config) -> Result<()> {
+    if inner.binary {
+        if Term::stdout().is_term() {
+            return Err(Error::Argument(
+                "binary files aren't printed to the terminal, pipe them or `--save` them",
+                "--plain",
+            ));
         }
+        PROGRESS.finish_and_clear();
+        std::io::stdout().write_all(&binary::content(inner)?)?;
+        return Ok(());
+    }
 
-        let input = bat::Input::from_reader(&*file)
+    PROGRESS.finish_and_clear();
+    std::io::stdout().write_all(select_lines(&inner.data, config.line_range).as_bytes())?;
+    Ok(())
+}
+
+/// How a file is previewed, see [`Render::of`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Render {
+    /// Highlighted by bat
+    Source,
+    /// Described, see [`binary::preview`]
+    Binary,
+    /// Rendered for the terminal, see [`document::markdown`]
+    Markdown,
+    /// Rendered cell by cell, see [`document::notebook`]
+    Notebook,
+}
+
+impl Render {
+    /// How `inner` is previewed, markdown files and notebooks are only rendered if `rich`
+    fn of(inner: &Inner, rich: bool) -> Self {
+        if inner.binary {
+            return Self::Binary;
+        }
+        let extension = Path::new(&inner.name)
+            .extension()
+            .and_then(OsStr::to_str)
+            .map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("md" | "markdown") if rich => Self::Markdown,
+            Some("ipynb") if rich => Self::Notebook,
+            _ => Self::Source,
+        }
+    }
+
+    /// What's previewed of `inner`
+    fn content(self, inner: &Inner) -> Result<Cow<'_, str>> {
+        Ok(match self {
+            Self::Source => Cow::Borrowed(inner.data.as_str()),
+            Self::Binary => Cow::Owned(binary::preview(&binary::content(inner)?)),
+            Self::Markdown => Cow::Owned(document::markdown(&inner.data)),
+            Self::Notebook => Cow::Owned(document::notebook(&inner.data)?),
+        })
+    }
+
+    /// Printed as it is, not through bat
+    const fn is_rendered(self) -> bool {
+        matches!(self, Self::Markdown | Self::Notebook)
+    }
+}
+
+/// Previews `files` of `gistit` one after the other, below `heading`, with the previewer in the
+/// settings if there's one. Rendered files are printed between bat runs of the others, without
+/// paging
+fn preview(gistit: &Gistit, files: &[&Inner], heading: String, config: &Config) -> Result<()> {
+    finish!(heading);
+    if files.is_empty() {
+        return Ok(());
+    }
+    if let Some(ref previewer) = config.previewer {
+        return preview_external(previewer, files, config);
+    }
+
+    let mut highlighted = Vec::new();
+    for inner in files {
+        let render = Render::of(inner, config.rich);
+        let (render, content) = match render.content(inner) {
+            Err(err) if render == Render::Notebook => {
+                warnln!("'{}' isn't a notebook, {}", inner.name, err);
+                (Render::Source, Render::Source.content(inner)?)
+            }
+            content => (render, content?),
+        };
+
+        if render.is_rendered() {
+            highlight(gistit, &highlighted, config)?;
+            highlighted.clear();
+            println!("{}\n\n{}", header(gistit, inner), content);
+        } else {
+            highlighted.push((*inner, content));
+        }
+    }
+    highlight(gistit, &highlighted, config)
+}
+
+/// The name of `inner`, the author and description of `gistit`
+fn header(gistit: &Gistit, inner: &Inner) -> String {
+    let mut header_string = style(&inner.name).green().to_string();
+    header_string.push_str(&format!(" | {}", style(&gistit.author).blue().bold()));
+
+    if let Some(ref description) = gistit.description {
+        header_string.push_str(&format!(" | {}", style(description).italic()));
+    }
+    header_string
+}
+
+/// Prints `files` of `gistit` with bat, highlighted and paged
+fn highlight(gistit: &Gistit, files: &[(&Inner, Cow<str>)], config: &Config) -> Result<()> {
+    if files.is_empty() {
+        return Ok(());
+    }
+
+    let inputs = files.iter().map(|(inner, content)| {
+        // Named after the file, bat picks the syntax of each from it
+        bat::Input::from_bytes(content.as_bytes())
             .name(&inner.name)
-            .title(header_string);
-
-        bat::PrettyPrinter::new()
-            .header(true)
-            .grid(true)
-            .input(input)
-            .line_numbers(true)
-            .theme(config.colorscheme)
-            .use_italics(true)
-            .paging_mode(bat::PagingMode::QuitIfOneScreen)
-            .print()?;
+            .title(header(gistit, inner))
+    });
+    let paging_mode = if config.paging {
+        bat::PagingMode::QuitIfOneScreen
+    } else {
+        bat::PagingMode::Never
+    };
+
+    let mut printer = bat::PrettyPrinter::new();
+    printer
+        .header(true)
+        .grid(config.grid)
+        .inputs(inputs)
+        .line_numbers(config.line_numbers)
+        .theme(&config.colorscheme)
+        .use_italics(true)
+        .tab_width(config.tab_width)
+        .paging_mode(paging_mode);
+    if let Some((start, end)) = config.line_range {
+        printer.line_ranges(LineRanges::from(vec![LineRange::new(start, end)]));
+    }
+    if let Some(wrap) = config.wrap {
+        printer.wrapping_mode(if wrap {
+            bat::WrappingMode::Character
+        } else {
+            bat::WrappingMode::NoWrapping(true)
+        });
     }
+    printer.print()?;
     Ok(())
 }
+
+/// Runs `previewer` with `files` appended, written to temporary files named after them so it can
+/// pick their syntax. Only the lines of `--line-range` of text files are written
+fn preview_external(previewer: &str, files: &[&Inner], config: &Config) -> Result<()> {
+    let mut words = previewer.split_whitespace();
+    let program = words.next().ok_or(Error::Argument(
+        "the settings `previewer` is empty",
+        "previewer",
+    ))?;
+
+    let temp_files = files
+        .iter()
+        .map(|inner| {
+            let name = file::name_from_path(Path::new(&inner.name));
+            if inner.binary {
+                File::from_data(binary::content(inner)?, &name)
+            } else {
+                File::from_data(select_lines(&inner.data, config.line_range), &name)
+            }
+        })
+        .collect::<Result<Vec<File>>>()?;
+    let status = Command::new(which::which(program)?)
+        .args(words)
+        .args(temp_files.iter().map(File::path))
+        .status();
+    for temp_file in &temp_files {
+        let _ = fs::remove_file(temp_file.path());
+    }
+
+    let status = status?;
+    if !status.success() {
+        warnln!("'{}' exited with {}", previewer, status);
+    }
+    Ok(())
+}
+
+/// Every file of `gistit`, or only the one named `file`
+fn select_files<'g>(gistit: &'g Gistit, file: Option<&str>) -> Result<Vec<&'g Inner>> {
+    match file {
+        Some(name) => gistit.file(name).map(|inner| vec![inner]).ok_or_else(|| {
+            Error::MissingFile(
+                name.to_owned(),
+                gistit
+                    .inner
+                    .iter()
+                    .map(|inner| format!("'{}'", inner.name))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            )
+        }),
+        None => Ok(gistit.inner.iter().collect()),
+    }
+}
+
+/// The only file in `files`, `arg` handles one at a time
+fn single<'g>(files: &[&'g Inner], arg: &'static str) -> Result<&'g Inner> {
+    match files {
+        &[inner] => Ok(inner),
+        _ => Err(Error::Argument(
+            "gistit has many files, pick one with `--file`",
+            arg,
+        )),
+    }
+}
+
+/// Writes `files` under `dir_path` at their relative paths, returns how many were written. Names
+/// that would end up outside of it are skipped, files already there are handled by `conflict`
+fn save_files(dir_path: &Path, files: &[&Inner], conflict: Conflict) -> Result<usize> {
+    let mut saved = 0;
+    for inner in files {
+        let file_path = match relative_path(&inner.name) {
+            Some(relative) => dir_path.join(relative),
+            None => {
+                warnln!("skipping '{}', not a relative path", inner.name);
+                continue;
+            }
+        };
+        let file_path = match conflict.resolve(&file_path)? {
+            Some(file_path) => file_path,
+            None => continue,
+        };
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&file_path, binary::content(inner)?)?;
+        saved += 1;
+    }
+    Ok(saved)
+}
+
+/// Every packed file, binary ones can't be previewed
+fn format_manifest(manifest: &archive::Manifest, unpacked: &Gistit) -> String {
+    manifest
+        .files
+        .iter()
+        .map(|entry| {
+            let note = if unpacked.file(&entry.path).is_some() {
+                String::new()
+            } else {
+                format!(", {}", style("binary").dim())
+            };
+            format!(
+                "    {} ({}{})",
+                style(&entry.path).green(),
+                HumanBytes(entry.size),
+                note
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn format_files(files: &[&Inner]) -> String {
+    files
+        .iter()
+        .map(|inner| {
+            format!(
+                "    {} ({}, {})",
+                style(&inner.name).green(),
+                inner.lang,
+                HumanBytes(u64::from(inner.size))
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Lines `start..=end` of `data`, 1 based, keeping line endings
+fn select_lines(data: &str, range: Option<(usize, usize)>) -> String {
+    match range {
+        Some((start, end)) => data
+            .split_inclusive('\n')
+            .skip(start - 1)
+            .take(end - start + 1)
+            .collect(),
+        None => data.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::DEFAULT_SECRET_RETRIES;
+
+    fn sealed(secret: &str) -> Gistit {
+        let mut gistit = Gistit::new(
+            "hash".to_owned(),
+            "me".to_owned(),
+            None,
+            String::new(),
+            vec![Gistit::new_inner(
+                "main.rs".to_owned(),
+                "rust".to_owned(),
+                12,
+                "fn main() {}".to_owned(),
+            )],
+        );
+        secret::seal(&mut gistit, secret).unwrap();
+        gistit
+    }
+
+    #[tokio::test]
+    async fn fetch_decrypt_after_wrong_secrets() {
+        let mut gistit = sealed("hunter2");
+        let mut typed = vec!["hunter2", "hunter4"];
+        let mut asked = 0;
+
+        open_retrying(
+            &mut gistit,
+            "hunter3".to_owned(),
+            DEFAULT_SECRET_RETRIES,
+            false,
+            || {
+                asked += 1;
+                let secret = typed.pop().unwrap().to_owned();
+                async move { Ok(secret) }
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(asked, 2);
+        assert_eq!(gistit.inner[0].data, "fn main() {}");
+    }
+
+    #[tokio::test]
+    async fn fetch_decrypt_out_of_retries() {
+        let mut gistit = sealed("hunter2");
+        let mut asked = 0;
+
+        let result = open_retrying(
+            &mut gistit,
+            "hunter3".to_owned(),
+            DEFAULT_SECRET_RETRIES,
+            false,
+            || {
+                asked += 1;
+                async { Ok("hunter3".to_owned()) }
+            },
+        )
+        .await;
+
+        assert!(matches!(
+            result,
+            Err(Error::Core(gistit_core::Error::WrongSecret))
+        ));
+        assert_eq!(asked, DEFAULT_SECRET_RETRIES);
+        assert!(secret::is_encrypted(&gistit));
+
+        // The key of a link is never asked again
+        let result = open_retrying(
+            &mut gistit,
+            "hunter3".to_owned(),
+            DEFAULT_SECRET_RETRIES,
+            true,
+            || async { Ok("hunter2".to_owned()) },
+        )
+        .await;
+        assert!(matches!(result, Err(Error::Secret(_))));
+    }
+
+    #[test]
+    fn fetch_format_head() {
+        let head = Head {
+            hash: "hash".to_owned(),
+            author: "alice".to_owned(),
+            description: Some("a description".to_owned()),
+            name: "main.rs".to_owned(),
+            lang: "rust".to_owned(),
+            size: 2048,
+            expires_at: Some(7_200_000),
+            ..Head::default()
+        };
+
+        let formatted = format_head(&head, 0);
+        assert!(formatted.contains("main.rs"));
+        assert!(formatted.contains("2.00 KiB"));
+        assert!(formatted.contains("a description"));
+        assert!(formatted.contains("expires: in 2h"));
+        assert!(!formatted.contains("encrypted"));
+        assert!(!formatted.contains("reads left"));
+
+        let head = Head {
+            encrypted: true,
+            expires_at: None,
+            burn_after_read: true,
+            reads_left: Some(3),
+            ..head
+        };
+        let formatted = format_head(&head, 0);
+        assert!(formatted.contains("encrypted"));
+        assert!(formatted.contains("expires: never"));
+        assert!(formatted.contains("deleted once fetched"));
+        assert!(formatted.contains("reads left: 3"));
+        assert_eq!(
+            fmt_reads_left(0),
+            "that was the last read, the gistit is deleted now"
+        );
+        assert_eq!(
+            fmt_reads_left(2),
+            "2 reads left before the gistit is deleted"
+        );
+    }
+
+    #[test]
+    fn fetch_select_files() {
+        let gistit = Gistit::new(
+            "hash".to_owned(),
+            "alice".to_owned(),
+            None,
+            "0".to_owned(),
+            vec![
+                Gistit::new_inner(
+                    "src/main.rs".to_owned(),
+                    "rust".to_owned(),
+                    12,
+                    String::new(),
+                ),
+                Gistit::new_inner(
+                    "README.md".to_owned(),
+                    "markdown".to_owned(),
+                    8,
+                    String::new(),
+                ),
+            ],
+        );
+
+        assert_eq!(select_files(&gistit, None).unwrap().len(), 2);
+        let files = select_files(&gistit, Some("README.md")).unwrap();
+        assert_eq!(files[0].lang, "markdown");
+        assert!(single(&files, "--plain").is_ok());
+        assert!(single(&select_files(&gistit, None).unwrap(), "--plain").is_err());
+        assert!(matches!(
+            select_files(&gistit, Some("main.rs")),
+            Err(Error::MissingFile(_, names)) if names == "'src/main.rs', 'README.md'"
+        ));
+        assert!(format_files(&files).contains("README.md"));
+    }
+
+    #[test]
+    fn fetch_render() {
+        let inner = |name: &str, data: &str| {
+            Gistit::new_inner(
+                name.to_owned(),
+                "text".to_owned(),
+                data.len() as u32,
+                data.to_owned(),
+            )
+        };
+
+        assert_eq!(Render::of(&inner("main.rs", ""), true), Render::Source);
+        assert_eq!(Render::of(&inner("README.md", ""), true), Render::Markdown);
+        assert_eq!(
+            Render::of(&inner("NOTES.Markdown", ""), true),
+            Render::Markdown
+        );
+        assert_eq!(Render::of(&inner("plot.ipynb", ""), true), Render::Notebook);
+        assert_eq!(Render::of(&inner("README.md", ""), false), Render::Source);
+
+        let mut binary = inner("plot.ipynb", "");
+        binary.binary = true;
+        assert_eq!(Render::of(&binary, true), Render::Binary);
+
+        let notebook = inner("plot.ipynb", r#"{ "cells": [] }"#);
+        assert!(Render::Notebook.content(&notebook).is_ok());
+        assert!(Render::Notebook
+            .content(&inner("plot.ipynb", "[]"))
+            .is_err());
+        assert!(matches!(
+            Render::Source.content(&notebook),
+            Ok(Cow::Borrowed(data)) if data == notebook.data
+        ));
+    }
+
+    #[test]
+    fn fetch_select_lines() {
+        let data = "one\ntwo\nthree\nfour";
+
+        assert_eq!(select_lines(data, None), data);
+        assert_eq!(select_lines(data, Some((2, 3))), "two\nthree\n");
+        assert_eq!(select_lines(data, Some((3, usize::MAX))), "three\nfour");
+        assert_eq!(select_lines(data, Some((9, 9))), "");
+        assert_eq!(check::line_range("2:3").unwrap(), (2, 3));
+        assert_eq!(check::line_range(":3").unwrap(), (1, 3));
+        assert_eq!(check::line_range("4").unwrap(), (4, 4));
+        assert!(check::line_range("0:3").is_err());
+        assert!(check::line_range("3:2").is_err());
+        assert!(check::line_range("").is_err());
+    }
+
+    #[test]
+    fn fetch_timeout_flag() {
+        assert_eq!(check::timeout("30").unwrap(), Duration::from_secs(30));
+        assert!(check::timeout("0").is_err());
+        assert!(check::timeout("-1").is_err());
+        assert!(check::timeout("30s").is_err());
+    }
+
+    #[test]
+    fn fetch_tab_width_flag() {
+        assert_eq!(check::tab_width("4").unwrap(), 4);
+        assert_eq!(check::tab_width("0").unwrap(), 0);
+        assert!(check::tab_width("-1").is_err());
+        assert!(check::tab_width("four").is_err());
+    }
+
+    #[test]
+    fn fetch_short_identifiers() {
+        assert_eq!(
+            check::identifier("3fa9").unwrap(),
+            Identifier::Short("3fa9")
+        );
+        assert_eq!(
+            check::identifier("#3fa9").unwrap(),
+            Identifier::ServerShort("3fa9")
+        );
+        assert_eq!(
+            check::identifier(&format!("#{}", "a".repeat(64))).unwrap(),
+            Identifier::Server(&"a".repeat(64))
+        );
+        assert!(check::identifier("3fa").is_err());
+        assert!(check::identifier("#3fa").is_err());
+        assert!(check::identifier("#zzzz").is_err());
+        assert!(check::identifier(&format!("@{}", "a".repeat(8))).is_err());
+    }
+
+    #[test]
+    fn fetch_key_link() {
+        let hash = "a".repeat(64);
+        let url = format!("https://gistit.vercel.app/h/{}", hash);
+
+        let link = format!("{}#key=s3cretKey", url);
+        assert!(matches!(
+            check::identifier(&link),
+            Ok(Identifier::Server(found)) if found == hash
+        ));
+        assert_eq!(
+            check::key_link(&link).unwrap(),
+            (url.as_str(), Some("s3cretKey"))
+        );
+
+        let link = format!("{}#key=s3cretKey", hash);
+        assert!(matches!(
+            check::identifier(&link),
+            Ok(Identifier::Hash(found)) if found == hash
+        ));
+        assert_eq!(
+            check::key_link(&link).unwrap(),
+            (hash.as_str(), Some("s3cretKey"))
+        );
+        assert_eq!(check::key_link(&url).unwrap(), (url.as_str(), None));
+        assert!(check::key_link(&format!("{}#key=", url)).is_err());
+    }
+
+    #[tokio::test]
+    async fn fetch_any_from_daemon() {
+        let tmp = assert_fs::TempDir::new().unwrap();
+        let daemon = gistit_testkit::FakeDaemon::start(&tmp).unwrap();
+        let gistit = gistit_core::new_gistit(
+            "main.rs",
+            "rust",
+            "fn main() {}".to_owned(),
+            "alice".to_owned(),
+            None,
+        );
+        daemon.insert(gistit.clone());
+
+        let mut bridge = gistit_ipc::client(&tmp).unwrap();
+        let (fetched, source) = fetch_any(&mut bridge, &gistit.hash, vec![Source::Daemon], None)
+            .await
+            .unwrap();
+        assert_eq!(fetched, gistit);
+        assert_eq!(source, Source::Daemon);
+
+        assert!(matches!(
+            fetch_any(&mut bridge, &"0".repeat(64), vec![Source::Daemon], None).await,
+            Err(Error::Core(gistit_core::Error::NotFound))
+        ));
+
+        let mut burnt = gistit;
+        burnt.hash = "1".repeat(64);
+        burnt.burn_after_read = true;
+        daemon.insert(burnt.clone());
+        assert!(matches!(
+            fetch_any(&mut bridge, &burnt.hash, vec![Source::Daemon], None).await,
+            Err(Error::Core(gistit_core::Error::BurnAfterRead))
+        ));
+        assert!(daemon.gistit(&burnt.hash).is_some());
+    }
+}
@@ -5,17 +5,22 @@
 
 use std::env::temp_dir;
 use std::ffi::OsStr;
+use std::fmt;
 use std::fs::{self, write};
-use std::io::{BufReader, Read, Seek, Write};
-use std::path::{Path, PathBuf};
+use std::io::{self, Read, Seek, Write};
+use std::ops::Deref;
+use std::path::{Component, Path, PathBuf};
 use std::str;
 
+use console::{style, Term};
+use memmap2::Mmap;
 use phf::{phf_map, Map};
 use rand::{distributions::Alphanumeric, Rng};
 
-use gistit_project::var::GISTIT_MAX_SIZE;
+use crate::{cleanln, Error, Result};
 
-use crate::Result;
+/// Files this big or bigger are memory mapped instead of read into a buffer
+pub const MMAP_THRESHOLD: usize = 1024 * 1024;
 
 /// Supported file extensions
 /// This is a compile time built hashmap to check incomming file extensions against.
@@ -589,6 +594,53 @@ pub const EXTENSION_TO_LANG_MAPPING: Map<&'static str, &'static str> = phf_map!
     "" => "text",
 };
 
+/// How the contents of a [`File`] are read, chosen by it's size
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadStrategy {
+    /// Read into a buffer sized after the file
+    Buffered,
+    /// Mapped into memory, the kernel pages it in as it's read and nothing is copied
+    Mapped,
+}
+
+impl ReadStrategy {
+    #[must_use]
+    pub const fn for_size(size: usize) -> Self {
+        if size >= MMAP_THRESHOLD {
+            Self::Mapped
+        } else {
+            Self::Buffered
+        }
+    }
+}
+
+impl fmt::Display for ReadStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Buffered => write!(f, "buffered"),
+            Self::Mapped => write!(f, "memory mapped"),
+        }
+    }
+}
+
+/// The raw contents of a [`File`]
+#[derive(Debug)]
+pub enum Contents {
+    Buffered(Vec<u8>),
+    Mapped(Mmap),
+}
+
+impl Deref for Contents {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Self::Buffered(buf) => buf,
+            Self::Mapped(map) => map,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct File {
     handler: fs::File,
@@ -618,6 +670,138 @@ pub fn name_from_path(path: &Path) -> String {
         .to_string()
 }
 
+/// The language of a file, by it's extension
+#[must_use]
+pub fn lang_from_path(path: &Path) -> &'static str {
+    path.extension().map_or("text", |ext| {
+        let ext_str = OsStr::to_str(ext).expect("file to contain valid utf8 extension");
+        EXTENSION_TO_LANG_MAPPING.get(ext_str).unwrap_or(&"text")
+    })
+}
+
+/// Language of files that aren't text, sent base64 encoded
+pub const BINARY_LANG: &str = "binary";
+
+/// Bytes looked at to tell text from binary content, as many as git does
+const BINARY_SNIFF_LEN: usize = 8000;
+
+/// Whether `bytes` aren't text, their start has a NUL byte or isn't utf8. A character cut at the
+/// end of it doesn't count
+#[must_use]
+pub fn is_binary(bytes: &[u8]) -> bool {
+    let start = &bytes[..bytes.len().min(BINARY_SNIFF_LEN)];
+    start.contains(&0) || matches!(str::from_utf8(start), Err(err) if err.error_len().is_some())
+}
+
+/// Whether the file at `path` isn't text, see [`is_binary`]
+///
+/// # Errors
+///
+/// Fails if the file can't be read
+pub fn is_binary_file(path: &Path) -> Result<bool> {
+    let mut start = Vec::with_capacity(BINARY_SNIFF_LEN);
+    fs::File::open(path)?
+        .take(BINARY_SNIFF_LEN as u64)
+        .read_to_end(&mut start)?;
+    Ok(is_binary(&start))
+}
+
+/// `name` as a path relative to the gistit root. Only plain components are kept, a gistit can't
+/// write outside of where it's saved
+#[must_use]
+pub fn relative_path(name: &str) -> Option<PathBuf> {
+    let path = Path::new(name);
+    if path.components().any(|component| {
+        matches!(
+            component,
+            Component::ParentDir | Component::RootDir | Component::Prefix(_)
+        )
+    }) {
+        return None;
+    }
+
+    let relative: PathBuf = path
+        .components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .collect();
+    (!relative.as_os_str().is_empty()).then(|| relative)
+}
+
+/// What saving a file does when there's one at it's path already
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Conflict {
+    Fail,
+    Overwrite,
+    /// Save next to it, see [`free_path`]
+    Rename,
+    /// Ask for every file, to overwrite, rename or skip it
+    Prompt,
+}
+
+impl Conflict {
+    /// `--force` overwrites and `--rename-on-conflict` renames, otherwise the user is asked if
+    /// there's a terminal to ask in
+    #[must_use]
+    pub fn from_flags(force: bool, rename: bool) -> Self {
+        if force {
+            Self::Overwrite
+        } else if rename {
+            Self::Rename
+        } else if Term::stderr().is_term() {
+            Self::Prompt
+        } else {
+            Self::Fail
+        }
+    }
+
+    /// Where to write a file meant for `path`, `None` to skip it
+    ///
+    /// # Errors
+    ///
+    /// Fails if there's a file at `path` and this is [`Conflict::Fail`], or reading the answer to
+    /// the prompt fails
+    pub fn resolve(self, path: &Path) -> Result<Option<PathBuf>> {
+        if fs::symlink_metadata(path).is_err() {
+            return Ok(Some(path.to_owned()));
+        }
+
+        match self {
+            Self::Overwrite => Ok(Some(path.to_owned())),
+            Self::Rename => Ok(Some(free_path(path))),
+            Self::Fail => Err(Error::Argument(
+                "file exists, pass `--force` to overwrite it or `--rename-on-conflict` to keep both",
+                "--save",
+            )),
+            Self::Prompt => {
+                cleanln!(format!(
+                    "'{}' exists. Overwrite, rename or skip it? {}",
+                    path.to_string_lossy(),
+                    style("[o/r/S]").dim()
+                ));
+                match Term::stderr().read_char()? {
+                    'o' | 'O' => Ok(Some(path.to_owned())),
+                    'r' | 'R' => Ok(Some(free_path(path))),
+                    _ => Ok(None),
+                }
+            }
+        }
+    }
+}
+
+/// The first of `name (1).ext`, `name (2).ext`... next to `path` with nothing at it
+#[must_use]
+pub fn free_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path
+        .extension()
+        .map_or_else(String::new, |ext| format!(".{}", ext.to_string_lossy()));
+
+    (1..)
+        .map(|n| path.with_file_name(format!("{} ({}){}", stem, n, extension)))
+        .find(|candidate| fs::symlink_metadata(candidate).is_err())
+        .expect("a free name")
+}
+
 impl File {
     /// Create file from a given path
     ///
@@ -641,7 +825,7 @@ impl File {
     /// # Errors
     ///
     /// Fails with [`std::io::Error`]
-    pub fn from_data(data: impl AsRef<str>, name: &str) -> Result<Self> {
+    pub fn from_data(data: impl AsRef<[u8]>, name: &str) -> Result<Self> {
         let data = data.as_ref();
 
         let (handler, path) = {
@@ -663,7 +847,7 @@ impl File {
                 .truncate(true)
                 .open(&path)?;
 
-            handler.write_all(data.as_bytes())?;
+            handler.write_all(data)?;
             handler.rewind()?;
 
             (handler, path)
@@ -688,10 +872,7 @@ impl File {
 
     #[must_use]
     pub fn lang(&self) -> &str {
-        self.path.extension().map_or("text", |ext| {
-            let ext_str = OsStr::to_str(ext).expect("file to contain valid utf8 extension");
-            EXTENSION_TO_LANG_MAPPING.get(ext_str).unwrap_or(&"text")
-        })
+        lang_from_path(&self.path)
     }
 
     #[must_use]
@@ -699,34 +880,74 @@ impl File {
         self.size
     }
 
-    /// Reads the file using [`BufReader`] and returns contents as string
+    /// How [`Self::contents`] reads this file, see [`MMAP_THRESHOLD`]
+    #[must_use]
+    pub const fn strategy(&self) -> ReadStrategy {
+        ReadStrategy::for_size(self.size)
+    }
+
+    /// The raw contents of the file, memory mapped if it's big so it isn't buffered twice
     ///
     /// # Errors
     ///
-    /// Fails if can't read the file
+    /// Fails if can't read or map the file
+    pub fn contents(&self) -> Result<Contents> {
+        let strategy = self.strategy();
+        tracing::debug!(path = ?self.path, size = self.size, %strategy, "reading file");
+
+        match strategy {
+            ReadStrategy::Buffered => {
+                let mut buf = Vec::with_capacity(self.size);
+                (&self.handler).read_to_end(&mut buf)?;
+                Ok(Contents::Buffered(buf))
+            }
+            // SAFETY: the map is read only. Were the file truncated by someone else while it's
+            // mapped, reads past it's new end fault, the risk any reader of mapped files takes
+            ReadStrategy::Mapped => Ok(Contents::Mapped(unsafe { Mmap::map(&self.handler)? })),
+        }
+    }
+
+    /// Reads the file and returns contents as string
+    ///
+    /// # Errors
+    ///
+    /// Fails if can't read the file or it isn't valid utf8
     pub fn read(&self) -> Result<String> {
-        let mut buf = String::with_capacity(GISTIT_MAX_SIZE);
-        let mut reader = BufReader::new(&**self);
-        reader.read_to_string(&mut buf)?;
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "file is not valid utf8");
 
-        Ok(buf)
+        Ok(match self.contents()? {
+            Contents::Buffered(buf) => String::from_utf8(buf).map_err(|_| invalid())?,
+            Contents::Mapped(map) => str::from_utf8(&map).map_err(|_| invalid())?.to_owned(),
+        })
     }
 
-    /// Reads the contents and save the file to given path
+    /// Reads the file as text, base64 encoding it's bytes if it's binary. The flag tells which
+    ///
+    /// # Errors
+    ///
+    /// Fails if can't read the file
+    pub fn read_encoded(&self) -> Result<(String, bool)> {
+        let contents = self.contents()?;
+        Ok(match str::from_utf8(&contents) {
+            Ok(text) if !is_binary(&contents) => (text.to_owned(), false),
+            _ => (base64::encode(&*contents), true),
+        })
+    }
+
+    /// Writes the contents of the file to given path
     ///
     /// # Errors
     ///
     /// Fails with [`std::io::Error`]
     pub fn save_as(&mut self, path: &Path) -> Result<()> {
-        let content = self.read()?;
-        Ok(write(path, content)?)
+        let contents = self.contents()?;
+        Ok(write(path, &*contents)?)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::Error;
     use assert_fs::prelude::*;
     use predicates::prelude::*;
 
@@ -747,6 +968,76 @@ mod tests {
         assert_eq!(n6, "😁");
     }
 
+    #[test]
+    fn file_relative_path() {
+        assert_eq!(
+            relative_path("src/main.rs"),
+            Some(PathBuf::from("src").join("main.rs"))
+        );
+        assert_eq!(relative_path("./main.rs"), Some(PathBuf::from("main.rs")));
+        assert_eq!(relative_path("../main.rs"), None);
+        assert_eq!(relative_path("/etc/passwd"), None);
+        assert_eq!(relative_path(""), None);
+        assert_eq!(lang_from_path(Path::new("src/main.rs")), "rust");
+        assert_eq!(lang_from_path(Path::new("Makefile")), "text");
+    }
+
+    #[test]
+    fn file_conflict_resolve() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let path = dir.child("main.rs");
+
+        assert_eq!(
+            Conflict::Fail.resolve(path.path()).unwrap(),
+            Some(path.to_path_buf())
+        );
+
+        path.write_str("fn main() {}").unwrap();
+        assert!(matches!(
+            Conflict::Fail.resolve(path.path()),
+            Err(Error::Argument(..))
+        ));
+        assert_eq!(
+            Conflict::Overwrite.resolve(path.path()).unwrap(),
+            Some(path.to_path_buf())
+        );
+        assert_eq!(
+            Conflict::Rename.resolve(path.path()).unwrap(),
+            Some(dir.path().join("main (1).rs"))
+        );
+
+        dir.child("main (1).rs").touch().unwrap();
+        assert_eq!(free_path(path.path()), dir.path().join("main (2).rs"));
+        assert_eq!(
+            free_path(&dir.path().join("Makefile")),
+            dir.path().join("Makefile (1)")
+        );
+    }
+
+    #[test]
+    fn file_binary_detection() {
+        assert!(!is_binary(b"fn main() {}"));
+        assert!(!is_binary("caf\u{e9}".as_bytes()));
+        assert!(!is_binary(&"\u{e9}".as_bytes()[..1]));
+        assert!(!is_binary(b""));
+        assert!(is_binary(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"));
+        assert!(is_binary(b"\xff\xfe text"));
+
+        let text = File::from_data("fn main() {}", "main.rs").unwrap();
+        assert_eq!(
+            text.read_encoded().unwrap(),
+            ("fn main() {}".to_owned(), false)
+        );
+        assert!(!is_binary_file(text.path()).unwrap());
+
+        let binary = File::from_data([0x7f, b'E', b'L', b'F', 0, 1], "main").unwrap();
+        assert_eq!(
+            binary.read_encoded().unwrap(),
+            ("f0VMRgAB".to_owned(), true)
+        );
+        assert!(is_binary_file(binary.path()).unwrap());
+    }
+
     #[test]
     fn file_spawn_random_and_write() {
         let data: String = rand::thread_rng()
@@ -855,5 +1146,26 @@ mod tests {
 
         assert_eq!(file.name(), "foo");
         assert_eq!(file.size(), 512);
+        assert_eq!(file.strategy(), ReadStrategy::Buffered);
+    }
+
+    #[test]
+    fn file_structure_mapped_when_big() {
+        let data = "gistit\n".repeat(MMAP_THRESHOLD / 7 + 1);
+        let tmp = assert_fs::TempDir::new().unwrap();
+        let tmp_file = tmp.child("big.log");
+        tmp_file.write_binary(data.as_bytes()).unwrap();
+
+        let mut file = File::from_path(&tmp_file).unwrap();
+        assert_eq!(file.strategy(), ReadStrategy::Mapped);
+        assert!(matches!(file.contents().unwrap(), Contents::Mapped(_)));
+        assert_eq!(file.read().unwrap(), data);
+
+        file.save_as(&tmp.join("copy.log")).unwrap();
+        assert_eq!(fs::read(tmp.child("copy.log")).unwrap(), data.as_bytes());
+
+        tmp_file.write_binary(&vec![0xff; MMAP_THRESHOLD]).unwrap();
+        let file = File::from_path(&tmp_file).unwrap();
+        assert!(matches!(file.read(), Err(Error::IO(_))));
     }
 }
@@ -54,8 +54,9 @@ macro_rules! progress {
     ($msg:expr) => {{
         use crate::fmt::{PROGRESS, STATUS};
         let mut status = STATUS.lock().unwrap();
-        PROGRESS.set_message($msg);
-        *status = Box::leak(Box::new($msg));
+        let msg = $msg.to_string();
+        PROGRESS.set_message(msg.clone());
+        *status = msg;
     }};
 
     ($msg:literal, $($rest:expr),* $(,)*) => {{
@@ -63,7 +64,7 @@ macro_rules! progress {
         let mut status = STATUS.lock().unwrap();
         let msg = format!($msg, $($rest,)*);
         PROGRESS.set_message(msg.clone());
-        *status = Box::leak(Box::new(msg));
+        *status = msg;
     }};
 }
 
@@ -134,5 +135,5 @@ lazy_static::lazy_static! {
         pb
     };
 
-    pub static ref STATUS: Arc<Mutex<&'static str>> = Arc::new(Mutex::new(""));
+    pub static ref STATUS: Arc<Mutex<String>> = Arc::new(Mutex::new(String::new()));
 }
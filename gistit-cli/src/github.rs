@@ -4,19 +4,24 @@ use std::io::Write;
 use std::thread;
 use std::time::Duration;
 
+use console::style;
 use rand::{distributions::Alphanumeric, Rng};
-use reqwest::StatusCode;
+use reqwest::{header::RETRY_AFTER, StatusCode};
 use serde::{Deserialize, Serialize};
 use url::Url;
 
+use crate::credentials::Credential;
 use crate::patch::webbrowser::{self, BrowserOptions};
-use crate::server::SERVER_URL_TOKEN;
-use crate::{Error, Result};
+use crate::server::{RATE_LIMIT_MAX_WAIT_SECS, SERVER_URL_TOKEN};
+use crate::{warnln, Error, Result};
 
 pub const GITHUB_OAUTH_CLIENT_ID: &str = "265cd618948a2e58042e";
 pub const GITHUB_OAUTH_BASE_URL: &str = "https://github.com/login/oauth/authorize";
 pub const GITHUB_GISTS_API_URL: &str = "https://api.github.com/gists";
 
+/// Where the token is kept when the OS credential store isn't available
+pub const GITHUB_TOKEN_FILE_NAME: &str = "github";
+
 #[derive(Clone, Debug, Serialize)]
 pub struct Oauth {
     pub state: String,
@@ -48,18 +53,20 @@ pub struct CreateResponse {
 }
 
 impl Oauth {
-    /// Looks for token in project config dir and initializes state.
-    /// Will not fail if token file is missing.
+    /// Looks for token in the OS credential store, then in project config dir, and initializes
+    /// state. Will not fail if the token is missing.
     ///
     /// # Errors
     ///
     /// Fails if cannot read token file
     pub fn new() -> Result<Self> {
         let config = gistit_project::path::config()?;
-        let token_path = config.join("github");
+        let token_path = config.join(GITHUB_TOKEN_FILE_NAME);
         let state = unguessable_state();
 
-        let token = if fs::metadata(&token_path).is_ok() {
+        let token = if let Ok(Some(token)) = Credential::GithubToken.get() {
+            Some(serde_json::from_str(&token)?)
+        } else if fs::metadata(&token_path).is_ok() {
             Some(serde_json::from_str(&fs::read_to_string(&token_path)?)?)
         } else {
             None
@@ -106,7 +113,7 @@ impl Oauth {
     pub async fn poll_token(&mut self) -> Result<()> {
         let mut retry = 0_usize;
         let token: Token = loop {
-            let response = reqwest::Client::new()
+            let response = gistit_core::http_client()
                 .post(SERVER_URL_TOKEN.to_string())
                 .json(self)
                 .send()
@@ -124,24 +131,80 @@ impl Oauth {
                 StatusCode::OK => {
                     break response.json().await?;
                 }
+                StatusCode::TOO_MANY_REQUESTS if retry < 7 => {
+                    let wait = response
+                        .headers()
+                        .get(RETRY_AFTER)
+                        .and_then(|value| value.to_str().ok())
+                        .and_then(gistit_core::parse_retry_after)
+                        .map_or(Duration::from_secs(3), |wait| {
+                            wait.min(Duration::from_secs(RATE_LIMIT_MAX_WAIT_SECS))
+                        });
+                    warnln!("rate limited, retrying in {}s", wait.as_secs().max(1));
+                    tokio::time::sleep(wait).await;
+                    retry += 1;
+                }
                 _ => return Err(Error::Server("unexpected response")),
             }
         };
 
-        let config = gistit_project::path::config()?;
-        fs::File::create(config.join("github"))?.write_all(&serde_json::to_vec(&token)?)?;
-
+        store_token(&token)?;
         self.token = Some(token);
 
         Ok(())
     }
 
+    /// Authorizes in the web browser, or asks to do it manually when it can't be opened, and
+    /// waits for the token
+    ///
+    /// # Errors
+    ///
+    /// Inherits errors of [`Self::poll_token`]
+    pub async fn login(&mut self) -> Result<()> {
+        if let Err(url) = self.authorize() {
+            warnln!(
+                "failed to open your web browser. \n\nAuthorize manually: '{}'",
+                style(url).cyan()
+            );
+        }
+        self.poll_token().await
+    }
+
     #[must_use]
     pub const fn token(&self) -> Option<&Token> {
         self.token.as_ref()
     }
 }
 
+/// Stores `token` in the OS credential store, or in project config dir if it isn't available
+///
+/// # Errors
+///
+/// Fails if the token file can't be written
+pub fn store_token(token: &Token) -> Result<()> {
+    let encoded = serde_json::to_string(token)?;
+    let config = gistit_project::path::config()?;
+    let token_path = config.join(GITHUB_TOKEN_FILE_NAME);
+
+    match Credential::GithubToken.set(&encoded) {
+        Ok(()) => {
+            // Don't leave a stale copy behind
+            if fs::metadata(&token_path).is_ok() {
+                fs::remove_file(&token_path)?;
+            }
+        }
+        Err(err) => {
+            warnln!(
+                "{}, storing the github token at: '{}'",
+                err,
+                token_path.to_string_lossy()
+            );
+            fs::File::create(&token_path)?.write_all(encoded.as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
 #[must_use]
 pub fn unguessable_state() -> String {
     rand::thread_rng()
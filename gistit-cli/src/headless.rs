@@ -0,0 +1,192 @@
+//! The headless module
+//!
+//! `gistit --stdin-headless` is a machine mode for editor plugins. The content is read from stdin
+//! and everything else from flags, nothing is ever asked and the only thing written to stdout is a
+//! single JSON line once the gistit is sent, or once it failed:
+//!
+//! ```text
+//! {"ok":true,"hash":"...","backend":"server","url":"...","raw":"...","expires":"...","skipped":false}
+//! {"ok":false,"code":"network","error":"...","hint":"...","exit_code":6}
+//! ```
+//!
+//! `--range N:M` sends lines `N` to `M` of stdin, 1 based and inclusive like `gistit fetch
+//! --line-range`. Editors pipe the whole buffer with the lines of a selection instead of the
+//! selection itself, so it's cut here the same way whatever line endings or trailing newline the
+//! buffer has. A range past the end of stdin is refused rather than sent short.
+
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use indicatif::ProgressDrawTarget;
+use serde::Serialize;
+
+use crate::fmt::PROGRESS;
+use crate::param::check;
+use crate::{Error, Result};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Switches to the machine mode, progress and colors are turned off for good
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+    console::set_colors_enabled(false);
+    console::set_colors_enabled_stderr(false);
+    PROGRESS.set_draw_target(ProgressDrawTarget::hidden());
+}
+
+#[must_use]
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Reads stdin to it's end without a word, keeping only the lines in `range` if given
+///
+/// # Errors
+///
+/// Fails if stdin can't be read, isn't utf8, `range` is invalid or goes past it's end
+pub fn read_stdin(range: Option<&str>) -> Result<String> {
+    let mut data = String::new();
+    io::stdin().read_to_string(&mut data)?;
+
+    match range {
+        Some(range) => select(&data, range),
+        None => Ok(data),
+    }
+}
+
+/// Lines of `data` within `range`, with their line endings
+fn select(data: &str, range: &str) -> Result<String> {
+    let (start, end) = check::line_range(range).map_err(|_| {
+        Error::Argument(
+            "invalid range, expected 'N:M', 'N:', ':M' or 'N'",
+            "--range",
+        )
+    })?;
+
+    let lines: Vec<&str> = data.split_inclusive('\n').collect();
+    let end = if end == usize::MAX { lines.len() } else { end };
+    if start > lines.len() || end > lines.len() {
+        return Err(Error::Argument(
+            "range goes past the end of stdin",
+            "--range",
+        ));
+    }
+
+    Ok(lines[start - 1..end].concat())
+}
+
+/// Where a gistit ended up, printed once it's sent
+#[derive(Debug, Default, Serialize)]
+pub struct Sent {
+    pub hash: String,
+    /// `server` or `p2p`
+    pub backend: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires: Option<String>,
+    /// Owner token of private gistits
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    /// Generated with `--gen-secret`, it's opened by the link `<url or hash>#key=<secret>`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    /// Already sent or hosted before, see `--force`
+    pub skipped: bool,
+}
+
+#[derive(Serialize)]
+struct Report<'a, T> {
+    ok: bool,
+    #[serde(flatten)]
+    inner: &'a T,
+}
+
+#[derive(Serialize)]
+struct Failure {
+    code: &'static str,
+    error: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hint: Option<&'static str>,
+    exit_code: i32,
+}
+
+fn format_sent(sent: &Sent) -> String {
+    serde_json::to_string(&Report {
+        ok: true,
+        inner: sent,
+    })
+    .expect("to serialize")
+}
+
+fn format_failure(err: &Error) -> String {
+    serde_json::to_string(&Report {
+        ok: false,
+        inner: &Failure {
+            code: err.code(),
+            error: err.to_string(),
+            hint: err.hint(),
+            exit_code: err.exit_code(),
+        },
+    })
+    .expect("to serialize")
+}
+
+/// Prints the result line of a sent gistit
+pub fn report_sent(sent: &Sent) {
+    println!("{}", format_sent(sent));
+}
+
+/// Prints the result line of a failure, in place of [`Error::report`]
+pub fn report_failure(err: &Error) {
+    println!("{}", format_failure(err));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headless_select_range() {
+        let data = "one\r\ntwo\nthree\nfour";
+        assert_eq!(select(data, "2:3").unwrap(), "two\nthree\n");
+        assert_eq!(select(data, "1").unwrap(), "one\r\n");
+        assert_eq!(select(data, "3:").unwrap(), "three\nfour");
+        assert_eq!(select(data, ":4").unwrap(), data);
+        assert!(select(data, "4:5").is_err());
+        assert!(select(data, "5").is_err());
+        assert!(select(data, "3:2").is_err());
+        assert!(select("", "1").is_err());
+    }
+
+    #[test]
+    fn headless_format_sent() {
+        let sent = Sent {
+            hash: "hash".to_owned(),
+            backend: "server",
+            url: Some("https://gistit.vercel.app/h/hash".to_owned()),
+            skipped: true,
+            ..Sent::default()
+        };
+        assert_eq!(
+            format_sent(&sent),
+            r#"{"ok":true,"hash":"hash","backend":"server","url":"https://gistit.vercel.app/h/hash","skipped":true}"#
+        );
+    }
+
+    #[test]
+    fn headless_format_failure() {
+        let err = Error::Daemon("gistit node is not running");
+        let line = format_failure(&err);
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+
+        assert_eq!(value["ok"], false);
+        assert_eq!(value["code"], "daemon");
+        assert_eq!(value["error"], "gistit node is not running");
+        assert_eq!(value["exit_code"], 7);
+        assert!(value["hint"].is_string());
+        assert!(!line.contains('\n'));
+    }
+}
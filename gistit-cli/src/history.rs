@@ -0,0 +1,241 @@
+//! The history module
+//!
+//! Gistits sent as a new revision of another one, e.g. every save with `--watch` or `--parent
+//! <hash>`, point to it with their `parent` hash. `gistit history <hash|@alias>` lists the
+//! revisions behind one, numbered from the oldest, and `gistit fetch --rev N` fetches one of them.
+
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use clap::ArgMatches;
+use console::style;
+
+use gistit_ipc::{Bridge, Client};
+use gistit_project::path;
+use gistit_proto::Head;
+
+use crate::daemon;
+use crate::dispatch::Dispatch;
+use crate::fetch::{self, Prefer, Source};
+use crate::param::{check, Identifier};
+use crate::pin::fmt_ago;
+use crate::server::{self, SERVER_URL_BASE};
+use crate::settings::{get_runtime_settings, Backend};
+use crate::{finish, progress, updateln, warnln, Error, Result};
+
+/// Parents followed at most through the p2p network, the server answers as many
+const MAX_REVISIONS: usize = 50;
+
+#[derive(Debug, Clone)]
+pub struct Action<'a> {
+    pub hash: &'a str,
+    pub prefer: Option<&'a str>,
+    pub token: Option<&'a str>,
+}
+
+impl<'a> Action<'a> {
+    pub fn from_args(
+        args: &'a ArgMatches,
+    ) -> Result<Box<dyn Dispatch<InnerData = Config<'a>> + Send + Sync + 'a>> {
+        Ok(Box::new(Self {
+            hash: args
+                .value_of("HASH")
+                .ok_or(Error::Argument("missing argument", "[HASH]"))?,
+            prefer: args.value_of("prefer"),
+            token: args.value_of("token"),
+        }))
+    }
+}
+
+pub struct Config<'a> {
+    identifier: Identifier<'a>,
+    prefer: Prefer,
+    backend: Backend,
+    token: Option<&'a str>,
+    runtime_path: PathBuf,
+    config_path: PathBuf,
+}
+
+#[async_trait]
+impl<'a> Dispatch for Action<'a> {
+    type InnerData = Config<'a>;
+
+    async fn prepare(&self) -> Result<Self::InnerData> {
+        progress!("Preparing");
+        let settings = get_runtime_settings()?;
+        let config = Config {
+            identifier: check::identifier(self.hash)?,
+            prefer: self.prefer.map_or_else(
+                || Ok(Prefer::from_backend(settings.backend())),
+                Prefer::parse,
+            )?,
+            backend: settings.backend(),
+            token: self.token.map(check::token).transpose()?,
+            runtime_path: path::runtime_dir()?,
+            config_path: path::config()?,
+        };
+        updateln!("Prepared");
+        Ok(config)
+    }
+
+    async fn dispatch(&self, config: Self::InnerData) -> Result<()> {
+        let mut bridge = gistit_ipc::client(&config.runtime_path)?;
+        daemon::spawn_if_required(
+            config.backend,
+            &bridge,
+            &config.runtime_path,
+            &config.config_path,
+        )
+        .await?;
+
+        let (hash, sources) = fetch::locate(&mut bridge, config.identifier, config.prefer).await?;
+        let token = fetch::owner_token(config.token, &hash, &sources);
+        let revisions = revisions(&mut bridge, &hash, sources, token.as_deref()).await?;
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Check your system time")
+            .as_secs();
+        finish!(format_revisions(&revisions, now));
+        Ok(())
+    }
+}
+
+/// Revisions of `hash`, newest first, from the first of `sources` that has it. The server answers
+/// them at once, through the p2p network parents are followed one at a time
+///
+/// # Errors
+///
+/// Fails with [`gistit_core::Error::NotFound`] if no source has it, or with the last source error
+/// if one failed
+pub async fn revisions(
+    bridge: &mut Bridge<Client>,
+    hash: &str,
+    sources: Vec<Source>,
+    token: Option<&str>,
+) -> Result<Vec<Head>> {
+    let last = sources.len() - 1;
+    for (i, source) in sources.into_iter().enumerate() {
+        let revisions = match source {
+            Source::Daemon => revisions_p2p(bridge, hash).await,
+            Source::Server => revisions_server(hash, token).await,
+        };
+
+        match revisions {
+            Ok(revisions) if !revisions.is_empty() => return Ok(revisions),
+            Ok(_) if i < last => warnln!("gistit not found {}, trying next", source),
+            Err(err) if i < last => warnln!("{}, trying next", err),
+            Ok(_) => break,
+            Err(err) => return Err(err),
+        }
+    }
+
+    Err(gistit_core::Error::NotFound.into())
+}
+
+async fn revisions_server(hash: &str, token: Option<&str>) -> Result<Vec<Head>> {
+    progress!("Fetching revisions");
+    let history = server::retry_rate_limited(|| {
+        gistit_core::fetch::history_server(&SERVER_URL_BASE, hash, token)
+    })
+    .await?;
+    updateln!("Fetched revisions");
+    Ok(history.map(|history| history.revisions).unwrap_or_default())
+}
+
+async fn revisions_p2p(bridge: &mut Bridge<Client>, hash: &str) -> Result<Vec<Head>> {
+    let mut revisions: Vec<Head> = Vec::new();
+    let mut next = Some(hash.to_owned());
+    while let Some(hash) = next.take() {
+        if revisions.len() == MAX_REVISIONS || revisions.iter().any(|head| head.hash == hash) {
+            break;
+        }
+        match fetch::head_p2p(bridge, &hash).await? {
+            Some(head) => {
+                next = head.parent.clone();
+                revisions.push(head);
+            }
+            // Older revisions may not be hosted anymore
+            None => break,
+        }
+    }
+    Ok(revisions)
+}
+
+/// The hash of revision `rev` of `hash`, numbered from the oldest one
+///
+/// # Errors
+///
+/// Fails if the revisions can't be fetched or there's no such revision
+pub async fn revision(
+    bridge: &mut Bridge<Client>,
+    hash: &str,
+    sources: Vec<Source>,
+    token: Option<&str>,
+    rev: usize,
+) -> Result<String> {
+    let revisions = revisions(bridge, hash, sources, token).await?;
+    nth_oldest(revisions, rev).ok_or(Error::Argument(
+        "no such revision, list them with `gistit history`",
+        "--rev",
+    ))
+}
+
+fn nth_oldest(revisions: Vec<Head>, rev: usize) -> Option<String> {
+    let index = revisions.len().checked_sub(rev)?;
+    revisions.into_iter().nth(index).map(|head| head.hash)
+}
+
+fn format_revisions(revisions: &[Head], now: u64) -> String {
+    let lines: Vec<String> = revisions
+        .iter()
+        .enumerate()
+        .map(|(i, head)| {
+            let sent = head.timestamp.parse::<u64>().map_or_else(
+                |_| head.timestamp.clone(),
+                |millis| fmt_ago(now.saturating_sub(millis / 1000)),
+            );
+            format!(
+                "    {:>3}  {}  '{}' by '{}', {}",
+                revisions.len() - i,
+                style(&head.hash).bold(),
+                style(&head.name).green(),
+                style(&head.author).blue(),
+                style(sent).dim()
+            )
+        })
+        .collect();
+
+    format!(
+        "\n{}\n\n    fetch one with `gistit fetch <HASH> --rev N`\n\n",
+        lines.join("\n")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn history_numbered_from_oldest() {
+        let head = |hash: &str, parent: Option<&str>| Head {
+            hash: hash.to_owned(),
+            author: "alice".to_owned(),
+            name: "main.rs".to_owned(),
+            timestamp: "3600000".to_owned(),
+            parent: parent.map(ToOwned::to_owned),
+            ..Head::default()
+        };
+        let revisions = vec![head("c", Some("b")), head("b", Some("a")), head("a", None)];
+
+        assert_eq!(nth_oldest(revisions.clone(), 1).as_deref(), Some("a"));
+        assert_eq!(nth_oldest(revisions.clone(), 3).as_deref(), Some("c"));
+        assert_eq!(nth_oldest(revisions.clone(), 4), None);
+
+        console::set_colors_enabled(false);
+        let formatted = format_revisions(&revisions, 7200);
+        assert!(formatted.contains("      3  c  'main.rs' by 'alice', 1h ago"));
+        assert!(formatted.contains("      1  a  "));
+    }
+}
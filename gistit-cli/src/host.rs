@@ -0,0 +1,173 @@
+//! The host module
+//!
+//! Hosts a file on the p2p network through `gistit-daemon`, optionally encrypted with a secret,
+//! and prints an `@hash` identifier. `gistit fetch @hash` only looks for it in the p2p network.
+
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use clap::ArgMatches;
+use console::style;
+
+use gistit_core::secret;
+use gistit_project::path;
+use gistit_proto::{ipc, Instruction};
+
+use crate::audit;
+use crate::cache::{self, Entry, Origin};
+use crate::clipboard::Clipboard;
+use crate::daemon;
+use crate::dispatch::Dispatch;
+use crate::file::File;
+use crate::param::check;
+use crate::passphrase;
+use crate::send::{first_file_name, new_gistit, random_author};
+use crate::settings::{get_runtime_settings, Backend};
+use crate::{finish, progress, updateln, Error, Result};
+
+#[derive(Debug, Clone)]
+pub struct Action<'a> {
+    pub file_path: &'a OsStr,
+    pub description: Option<&'a str>,
+    pub author: Option<&'a str>,
+    pub secret: Option<passphrase::Source<'a>>,
+    pub clipboard: bool,
+    pub announce: Vec<&'a str>,
+}
+
+impl<'a> Action<'a> {
+    pub fn from_args(
+        args: &'a ArgMatches,
+    ) -> Result<Box<dyn Dispatch<InnerData = Config<'a>> + Send + Sync + 'a>> {
+        Ok(Box::new(Self {
+            file_path: args
+                .value_of_os("FILE")
+                .ok_or(Error::Argument("missing argument", "[FILE]"))?,
+            description: args.value_of("description"),
+            author: args.value_of("author"),
+            secret: passphrase::Source::from_args(args),
+            clipboard: args.is_present("clipboard"),
+            announce: args
+                .values_of("announce")
+                .map(Iterator::collect)
+                .unwrap_or_default(),
+        }))
+    }
+}
+
+pub struct Config<'a> {
+    file: File,
+    author: String,
+    description: Option<&'a str>,
+    secret: Option<String>,
+    topics: Vec<String>,
+    runtime_path: PathBuf,
+    config_path: PathBuf,
+}
+
+#[async_trait]
+impl<'a> Dispatch for Action<'a> {
+    type InnerData = Config<'a>;
+
+    async fn prepare(&self) -> Result<Self::InnerData> {
+        progress!("Preparing");
+        let path = Path::new(self.file_path);
+        check::metadata(&fs::metadata(&path)?)?;
+        check::extension(path.extension())?;
+
+        let settings = get_runtime_settings()?;
+        let author = match (self.author, settings.author.as_deref()) {
+            (Some(author), _) | (None, Some(author)) => check::author(author)?.to_owned(),
+            (None, None) => random_author(),
+        };
+        let topics = self
+            .announce
+            .iter()
+            .map(|topic| check::topic(topic).map(ToOwned::to_owned))
+            .collect::<Result<Vec<String>>>()?;
+        updateln!("Prepared");
+
+        Ok(Config {
+            file: File::from_path(path)?,
+            author,
+            description: self.description.map(check::description).transpose()?,
+            secret: match self.secret {
+                Some(source) => Some(source.read(true).await?),
+                None => None,
+            },
+            topics,
+            runtime_path: path::runtime_dir()?,
+            config_path: path::config()?,
+        })
+    }
+
+    async fn dispatch(&self, config: Self::InnerData) -> Result<()> {
+        let mut bridge = gistit_ipc::client(&config.runtime_path)?;
+        daemon::spawn_if_required(
+            Backend::P2p,
+            &bridge,
+            &config.runtime_path,
+            &config.config_path,
+        )
+        .await?;
+
+        let mut gistit = new_gistit(&config.file, config.author, config.description)?;
+        if let Some(ref secret) = config.secret {
+            progress!("Encrypting");
+            secret::seal(&mut gistit, secret)?;
+            updateln!("Encrypted");
+        }
+        let name = first_file_name(&gistit);
+        let author = gistit.author.clone();
+        audit::note(&gistit.hash, audit::P2P);
+
+        progress!("Hosting");
+        if !bridge.alive() {
+            return Err(Error::Daemon("gistit node is not running"));
+        }
+        bridge.connect_blocking()?;
+        bridge
+            .send(Instruction::request_provide(gistit, config.topics))
+            .await?;
+
+        match bridge.recv().await?.expect_response()? {
+            ipc::instruction::Kind::ProvideResponse(ipc::instruction::ProvideResponse {
+                hash: Some(hash),
+            }) => {
+                cache::record_or_warn(Entry::new(&hash, &name, &author, Origin::Hosted));
+                let id = format!("@{}", hash);
+
+                if self.clipboard {
+                    Clipboard::new(&id)
+                        .into_selected()
+                        .into_provider()
+                        .set_contents()?;
+                }
+                let clipboard_msg = if self.clipboard {
+                    style("(copied to clipboard)").italic().dim().to_string()
+                } else {
+                    String::new()
+                };
+                let fetch_hint = if config.secret.is_some() {
+                    format!("gistit fetch {} --secret-prompt", id)
+                } else {
+                    format!("gistit fetch {}", id)
+                };
+
+                updateln!("Hosted");
+                finish!(format!(
+                    "\n    id: '{}' {}\n    fetch it with `{}`\n\n",
+                    style(&id).bold(),
+                    clipboard_msg,
+                    fetch_hint
+                ));
+                Ok(())
+            }
+            _ => Err(Error::Daemon(
+                "failed to provide gistit, check gistit-daemon logs",
+            )),
+        }
+    }
+}
@@ -0,0 +1,170 @@
+//! The inbox module
+//!
+//! Gistits other peers push to us wait in the `gistit-daemon` inbox, in memory, until accepted or
+//! rejected. Accepted ones are stored and fetched with `gistit fetch <hash>` as usual, rejected ones
+//! are dropped. `gistit subscribe` prints a line as pushes come in.
+
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use clap::ArgMatches;
+use console::style;
+use indicatif::HumanBytes;
+
+use gistit_project::path;
+use gistit_proto::{ipc, Instruction};
+
+use crate::daemon;
+use crate::dispatch::Dispatch;
+use crate::param::check;
+use crate::pin::fmt_ago;
+use crate::settings::{get_runtime_settings, Backend};
+use crate::{finish, progress, updateln, Error, Result};
+
+#[derive(Debug, Clone)]
+pub enum Action<'a> {
+    List,
+    Accept(&'a str),
+    Reject(&'a str),
+}
+
+impl<'a> Action<'a> {
+    pub fn from_args(
+        args: &'a ArgMatches,
+    ) -> Result<Box<dyn Dispatch<InnerData = Config> + Send + Sync + 'a>> {
+        let id = |args: &'a ArgMatches| {
+            args.value_of("ID")
+                .ok_or(Error::Argument("missing argument", "[ID]"))
+        };
+
+        let action = match args.subcommand() {
+            Some(("ls", _)) => Self::List,
+            Some(("accept", args)) => Self::Accept(id(args)?),
+            Some(("reject", args)) => Self::Reject(id(args)?),
+            _ => return Err(Error::Argument("missing subcommand", "inbox")),
+        };
+        Ok(Box::new(action))
+    }
+}
+
+pub struct Config {
+    /// The inbox id to accept or reject
+    id: Option<u32>,
+    backend: Backend,
+    runtime_path: PathBuf,
+    config_path: PathBuf,
+}
+
+#[async_trait]
+impl<'a> Dispatch for Action<'a> {
+    type InnerData = Config;
+
+    async fn prepare(&self) -> Result<Self::InnerData> {
+        let id = match self {
+            Self::Accept(id) | Self::Reject(id) => Some(check::inbox_id(id)?),
+            Self::List => None,
+        };
+
+        Ok(Config {
+            id,
+            backend: get_runtime_settings()?.backend(),
+            runtime_path: path::runtime_dir()?,
+            config_path: path::config()?,
+        })
+    }
+
+    async fn dispatch(&self, config: Self::InnerData) -> Result<()> {
+        let mut bridge = gistit_ipc::client(&config.runtime_path)?;
+        daemon::spawn_if_required(
+            config.backend,
+            &bridge,
+            &config.runtime_path,
+            &config.config_path,
+        )
+        .await?;
+
+        if !bridge.alive() {
+            return Err(Error::Daemon("gistit node is not running"));
+        }
+        bridge.connect_blocking()?;
+
+        match (self, config.id) {
+            (Self::Accept(_) | Self::Reject(_), Some(id)) => {
+                let accept = matches!(self, Self::Accept(_));
+                progress!(if accept { "Accepting" } else { "Rejecting" });
+                bridge
+                    .send(Instruction::request_inbox_decision(id, accept))
+                    .await?;
+
+                if let ipc::instruction::Kind::InboxDecisionResponse(
+                    ipc::instruction::InboxDecisionResponse { hash },
+                ) = bridge.recv().await?.expect_response()?
+                {
+                    let hash = hash.ok_or(Error::Daemon("no pushed gistit with this id"))?;
+                    if accept {
+                        updateln!("Accepted");
+                        finish!(format!(
+                            "\n    hash: '{}'\n    fetch it with `gistit fetch {}`\n\n",
+                            style(&hash).bold(),
+                            hash
+                        ));
+                    } else {
+                        updateln!("Rejected");
+                        finish!(format!("\n    hash: '{}'\n\n", style(hash).bold()));
+                    }
+                }
+            }
+            _ => {
+                progress!("Listing inbox");
+                bridge.send(Instruction::request_inbox()).await?;
+
+                if let ipc::instruction::Kind::InboxResponse(ipc::instruction::InboxResponse {
+                    items,
+                }) = bridge.recv().await?.expect_response()?
+                {
+                    finish!(format_items(&items));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn format_items(items: &[ipc::instruction::InboxItem]) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Check your system time")
+        .as_secs();
+
+    let mut lines: Vec<String> = items
+        .iter()
+        .map(|item| {
+            let description = item
+                .description
+                .as_deref()
+                .map_or_else(String::new, |description| {
+                    format!("\n        {}", description)
+                });
+            format!(
+                "    [{}] {}  {:>10}  by {} from {}, {}{}",
+                style(item.id).green(),
+                style(&item.hash).bold(),
+                HumanBytes(item.size).to_string(),
+                item.author,
+                style(&item.peer_id).blue(),
+                style(fmt_ago(now.saturating_sub(item.received_at))).dim(),
+                style(description).dim()
+            )
+        })
+        .collect();
+    if lines.is_empty() {
+        lines.push("    inbox is empty".to_owned());
+    } else {
+        lines.push(String::new());
+        lines.push("    accept or reject them with `gistit inbox accept|reject <id>`".to_owned());
+    }
+
+    format!("\n{}\n", lines.join("\n"))
+}
@@ -21,14 +21,55 @@
     )
 )]
 
+mod archive;
 mod arg;
+mod audit;
+mod auth;
+mod binary;
+mod cache;
+mod comment;
+mod completions;
+mod config;
+mod credentials;
+mod daemon;
+mod describe;
+mod diff;
 mod dispatch;
+mod document;
+mod embed;
+mod export;
 mod fetch;
 mod fmt;
+mod headless;
+mod history;
+mod host;
+mod inbox;
+mod man;
+mod manifest;
+mod mirror;
+mod name;
 mod node;
 mod param;
+mod passphrase;
+mod pin;
+mod sanitize;
 mod send;
+mod serve_json;
+mod service;
+mod settings;
+mod signal;
+mod snippets;
+mod state;
 mod stdin;
+mod subscribe;
+mod telemetry;
+mod themes;
+mod tmux;
+mod trace;
+mod tui;
+mod verify;
+mod watch_clipboard;
+mod workspace;
 
 pub mod clipboard;
 pub mod error;
@@ -37,6 +78,8 @@ pub mod github;
 pub mod patch;
 pub mod server;
 
+use clap::ArgMatches;
+
 pub use error::Error;
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -44,15 +87,19 @@ pub type Result<T> = std::result::Result<T, Error>;
 async fn main() -> Result<()> {
     if let Err(err) = run().await {
         interruptln!();
-        errorln!(err);
+        if headless::enabled() {
+            headless::report_failure(&err);
+        } else {
+            err.report();
+        }
+        std::process::exit(err.exit_code());
     };
 
     Ok(())
 }
 
-#[allow(clippy::single_match_else)]
 async fn run() -> Result<()> {
-    let matches = Box::leak(Box::new(arg::app().get_matches()));
+    let matches = arg::app().get_matches();
     gistit_project::path::init()?;
 
     let (cmd, args) = if let Some((cmd, args)) = matches.subcommand() {
@@ -61,53 +108,125 @@ async fn run() -> Result<()> {
         ("", None)
     };
 
+    // Only JSON is written to stdout
+    if matches.is_present("stdin-headless") || cmd == "serve-json" {
+        headless::enable();
+    }
+
+    if let Some(profile) = matches
+        .value_of("profile")
+        .or_else(|| args.and_then(|args| args.value_of("profile")))
+    {
+        settings::select_profile(profile);
+    }
+
+    trace::init(trace::verbosity(&matches));
+
+    if let Some(timeout) = matches
+        .value_of("timeout")
+        .or_else(|| args.and_then(|args| args.value_of("timeout")))
+    {
+        gistit_core::set_timeout(Some(param::check::timeout(timeout)?));
+    }
+
     if matches.is_present("list-colorschemes") {
-        list_bat_colorschemes();
+        let settings = settings::get_runtime_settings()?;
+        themes::list(&param::SUPPORTED_COLORSCHEMES, settings.colorscheme());
         std::process::exit(0);
     }
 
+    // Opting out with `gistit telemetry off` shouldn't send anything on the way
+    let flush = if cmd == "telemetry" {
+        None
+    } else {
+        telemetry::flush()
+    };
+    let command = match cmd {
+        "" if matches.is_present("manifest") => "manifest",
+        "" => "send",
+        cmd => cmd,
+    };
+
+    // Kept alive while cleaning up after a signal, so fetches it's running are still known
+    let execution = execute(&matches, cmd, args);
+    tokio::pin!(execution);
+    let result = tokio::select! {
+        // A command that finished while blocked, e.g. in the pager, isn't reported as interrupted
+        biased;
+        result = &mut execution => result,
+        signal = signal::received() => {
+            signal::cleanup().await;
+            Err(Error::Interrupted(signal))
+        }
+    };
+    audit::record(command, result.as_ref().err());
+    telemetry::record(command, result.as_ref().err());
+    telemetry::join(flush).await;
+    result
+}
+
+#[allow(clippy::single_match_else)]
+async fn execute(matches: &ArgMatches, cmd: &str, args: Option<&ArgMatches>) -> Result<()> {
     match (cmd, args) {
-        ("fetch", Some(args)) => {
-            let action = fetch::Action::from_args(args)?;
-            let payload = action.prepare().await?;
-            action.dispatch(payload).await?;
+        ("fetch", Some(args)) => dispatch::run(cmd, fetch::Action::from_args(args)?).await?,
+        ("themes", Some(args)) => dispatch::run(cmd, themes::Action::from_args(args)?).await?,
+        ("config", Some(args)) => dispatch::run(cmd, config::Action::from_args(args)?).await?,
+        ("completions", Some(args)) => {
+            dispatch::run(cmd, completions::Action::from_args(args)?).await?;
+        }
+        ("daemon", Some(args)) => dispatch::run(cmd, daemon::Action::from_args(args)?).await?,
+        ("subscribe", Some(args)) => {
+            dispatch::run(cmd, subscribe::Action::from_args(args)?).await?;
+        }
+        ("gen-man", Some(args)) => dispatch::run(cmd, man::Action::from_args(args)?).await?,
+        ("name", Some(args)) => dispatch::run(cmd, name::Action::from_args(args)?).await?,
+        ("host", Some(args)) => dispatch::run(cmd, host::Action::from_args(args)?).await?,
+        ("inbox", Some(args)) => dispatch::run(cmd, inbox::Action::from_args(args)?).await?,
+        ("pin" | "unpin", Some(args)) => {
+            dispatch::run(cmd, pin::Action::from_args(cmd, args)?).await?;
+        }
+        ("tui", Some(args)) => dispatch::run(cmd, tui::Action::from_args(args)?).await?,
+        ("watch-clipboard", Some(args)) => {
+            dispatch::run(cmd, watch_clipboard::Action::from_args(args)?).await?;
+        }
+        ("auth", Some(args)) => dispatch::run(cmd, auth::Action::from_args(args)?).await?,
+        ("snippets", Some(args)) => dispatch::run(cmd, snippets::Action::from_args(args)?).await?,
+        ("node", Some(args)) => dispatch::run(cmd, node::Action::from_args(args)?).await?,
+        ("verify", Some(args)) => dispatch::run(cmd, verify::Action::from_args(args)?).await?,
+        ("diff", Some(args)) => dispatch::run(cmd, diff::Action::from_args(args)?).await?,
+        ("history", Some(args)) => dispatch::run(cmd, history::Action::from_args(args)?).await?,
+        ("comment", Some(args)) => dispatch::run(cmd, comment::Action::from_args(args)?).await?,
+        ("telemetry", Some(args)) => {
+            dispatch::run(cmd, telemetry::Action::from_args(args)?).await?;
         }
-        ("node", Some(args)) => {
-            let action = node::Action::from_args(args)?;
-            let payload = action.prepare().await?;
-            action.dispatch(payload).await?;
+        ("cache", Some(args)) => dispatch::run(cmd, cache::Action::from_args(args)?).await?,
+        ("serve-json", Some(args)) => {
+            dispatch::run(cmd, serve_json::Action::from_args(args)?).await?;
+        }
+        ("export-state" | "import-state", Some(args)) => {
+            dispatch::run(cmd, state::Action::from_args(cmd, args)?).await?;
+        }
+        ("log", Some(args)) => dispatch::run(cmd, audit::Action::from_args(args)?).await?,
+        ("workspace", Some(args)) => {
+            dispatch::run(cmd, workspace::Action::from_args(args)?).await?;
+        }
+        _ if matches.is_present("manifest") => {
+            dispatch::run("manifest", manifest::Action::from_args(matches)?).await?;
         }
         _ => {
-            let default_action = if matches.is_present("FILE") {
+            let default_action = if matches.is_present("FILE") || matches.is_present("tmux-pane") {
                 send::Action::from_args(matches, None)?
+            } else if headless::enabled() {
+                let stdin = headless::read_stdin(matches.value_of("range"))?;
+                send::Action::from_args(matches, Some(stdin))?
             } else {
                 let stdin = stdin::read_to_end();
                 send::Action::from_args(matches, Some(stdin))?
             };
 
-            let payload = default_action.prepare().await?;
-            default_action.dispatch(payload).await?;
+            dispatch::run("send", default_action).await?;
         }
     };
 
     Ok(())
 }
-
-fn list_bat_colorschemes() {
-    println!(
-        "{}",
-        console::style("Supported colorschemes: \n").green().bold()
-    );
-    for c in param::SUPPORTED_COLORSCHEMES {
-        println!("    {}", c);
-    }
-    println!(
-        r#"
-This application uses '{}' to view gistits inside your terminal.
-For more information please visit:
-{}
-        "#,
-        console::style("bat").bold().blue(),
-        "https://github.com/sharkdp/bat"
-    );
-}
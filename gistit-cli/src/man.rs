@@ -0,0 +1,78 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use clap::{ArgMatches, Command};
+use clap_mangen::Man;
+use console::style;
+
+use crate::arg::app;
+use crate::dispatch::Dispatch;
+use crate::{finish, progress, updateln, Result};
+
+const BIN_NAME: &str = "gistit";
+
+#[derive(Debug, Clone)]
+pub struct Action<'a> {
+    pub out_dir: &'a str,
+}
+
+impl<'a> Action<'a> {
+    pub fn from_args(
+        args: &'a ArgMatches,
+    ) -> Result<Box<dyn Dispatch<InnerData = Config> + Send + Sync + 'a>> {
+        Ok(Box::new(Self {
+            out_dir: args.value_of("out-dir").unwrap_or("."),
+        }))
+    }
+}
+
+pub struct Config {
+    out_dir: PathBuf,
+}
+
+#[async_trait]
+impl<'a> Dispatch for Action<'a> {
+    type InnerData = Config;
+
+    async fn prepare(&self) -> Result<Self::InnerData> {
+        let out_dir = PathBuf::from(self.out_dir);
+        fs::create_dir_all(&out_dir)?;
+
+        Ok(Config { out_dir })
+    }
+
+    async fn dispatch(&self, config: Self::InnerData) -> Result<()> {
+        progress!("Generating man pages");
+        let count = render_recursive(app().name(BIN_NAME), &config.out_dir)?;
+        updateln!("Generated {} man pages", count);
+        finish!(format!(
+            "\n    directory: '{}'\n\n",
+            style(config.out_dir.to_string_lossy()).bold()
+        ));
+
+        Ok(())
+    }
+}
+
+/// Renders a page for `command` and each of it's visible subcommands, named after the full
+/// command path (e.g. `gistit-config-init.1`). Returns the amount of pages written
+fn render_recursive(command: Command<'static>, out_dir: &Path) -> Result<usize> {
+    let name = command.get_name().to_owned();
+    let mut count = 0;
+
+    for subcommand in command.get_subcommands() {
+        if subcommand.is_hide_set() {
+            continue;
+        }
+
+        let full_name = format!("{}-{}", name, subcommand.get_name());
+        count += render_recursive(subcommand.clone().name(full_name), out_dir)?;
+    }
+
+    let mut buf: Vec<u8> = Vec::new();
+    Man::new(command).render(&mut buf)?;
+    fs::write(out_dir.join(format!("{}.1", name)), buf)?;
+
+    Ok(count + 1)
+}
@@ -0,0 +1,492 @@
+//! The manifest module
+//!
+//! Sends every file listed in a manifest with `gistit --manifest gistits.toml`. The whole manifest
+//! is validated before anything is sent. Entries are then sent to the server concurrently, or
+//! hosted one after the other through `gistit-daemon`. Resulting hashes and urls are written to a
+//! lockfile next to the manifest, `gistits.lock` for `gistits.toml`.
+//!
+//! ```toml
+//! # Optional, defaults to `--author`, the settings file or a random name
+//! author = "alice"
+//!
+//! [[gistit]]
+//! file = "src/main.rs"
+//! description = "The entry point"
+//! secret = "hunter22"
+//! lifespan = "2d" # or "never", if the server allows it
+//! alias = "@alice/main"
+//! ```
+//!
+//! Files are relative to the manifest. Aliases are names published in the p2p network and
+//! lifespans are only used by the server.
+
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use clap::ArgMatches;
+use console::style;
+use serde::{Deserialize, Serialize};
+
+use gistit_core::secret;
+use gistit_project::path;
+use gistit_proto::payload::Gistit;
+
+use crate::cache::{self, Entry, Origin};
+use crate::daemon;
+use crate::dispatch::Dispatch;
+use crate::file::File;
+use crate::name;
+use crate::param::check;
+use crate::send::{first_file_name, new_gistit, provide, random_author, raw_url, share_url};
+use crate::server::{self, Limits, LIFESPAN_NEVER, SERVER_URL_BASE};
+use crate::settings::{get_runtime_settings, Backend};
+use crate::{finish, progress, updateln, warnln, Error, Result};
+
+/// Written on top of lockfiles
+const LOCKFILE_HEADER: &str = "# Written by `gistit --manifest`, do not edit\n\n";
+
+#[derive(Debug, Clone)]
+pub struct Action<'a> {
+    pub manifest_path: &'a OsStr,
+    pub author: Option<&'a str>,
+}
+
+impl<'a> Action<'a> {
+    pub fn from_args(
+        args: &'a ArgMatches,
+    ) -> Result<Box<dyn Dispatch<InnerData = Config> + Send + Sync + 'a>> {
+        Ok(Box::new(Self {
+            manifest_path: args
+                .value_of_os("manifest")
+                .ok_or(Error::Argument("missing argument", "--manifest"))?,
+            author: args.value_of("author"),
+        }))
+    }
+}
+
+pub struct Config {
+    items: Vec<Item>,
+    lock_path: PathBuf,
+    limits: Limits,
+    backend: Backend,
+    runtime_path: PathBuf,
+    config_path: PathBuf,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    author: Option<String>,
+    #[serde(default)]
+    gistit: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ManifestEntry {
+    file: String,
+    description: Option<String>,
+    secret: Option<String>,
+    lifespan: Option<String>,
+    alias: Option<String>,
+}
+
+/// A validated manifest entry, ready to be sent
+#[derive(Debug)]
+struct Item {
+    /// As written in the manifest
+    file: String,
+    gistit: Gistit,
+    alias: Option<String>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lock {
+    pub gistit: Vec<Locked>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Locked {
+    pub file: String,
+    pub hash: String,
+    pub url: Option<String>,
+    pub raw_url: Option<String>,
+    pub alias: Option<String>,
+    /// Unix timestamp, in seconds, of when the server deletes it. Unset if it never does
+    pub expires_at: Option<u64>,
+}
+
+#[async_trait]
+impl<'a> Dispatch for Action<'a> {
+    type InnerData = Config;
+
+    async fn prepare(&self) -> Result<Self::InnerData> {
+        progress!("Validating manifest");
+        let manifest_path = Path::new(self.manifest_path);
+        let manifest: Manifest = toml::from_str(&fs::read_to_string(manifest_path)?)?;
+
+        let settings = get_runtime_settings()?;
+        let author = match (
+            self.author,
+            manifest.author.as_deref(),
+            settings.author.as_deref(),
+        ) {
+            (Some(author), _, _) | (None, Some(author), _) | (None, None, Some(author)) => {
+                check::author(author)?.to_owned()
+            }
+            (None, None, None) => random_author(),
+        };
+        let base = manifest_path.parent().unwrap_or_else(|| Path::new(""));
+        let limits = server::limits().await;
+        let items = validate(manifest.gistit, base, &author, &limits)?;
+        updateln!("Validated {} entries", items.len());
+
+        Ok(Config {
+            items,
+            lock_path: lock_path(manifest_path),
+            limits,
+            backend: settings.backend(),
+            runtime_path: path::runtime_dir()?,
+            config_path: path::config()?,
+        })
+    }
+
+    async fn dispatch(&self, config: Self::InnerData) -> Result<()> {
+        let mut bridge = gistit_ipc::client(&config.runtime_path)?;
+        daemon::spawn_if_required(
+            config.backend,
+            &bridge,
+            &config.runtime_path,
+            &config.config_path,
+        )
+        .await?;
+        let use_daemon = config.backend.use_daemon(bridge.alive())?;
+
+        if !use_daemon && config.items.iter().any(|item| item.alias.is_some()) {
+            return Err(Error::Manifest(
+                "aliases are published in the p2p network, they need gistit-daemon".to_owned(),
+            ));
+        }
+        if use_daemon
+            && config
+                .items
+                .iter()
+                .any(|item| item.gistit.lifespan.is_some())
+        {
+            warnln!("lifespans are only used by the server, hosted gistits don't expire");
+        }
+
+        let total = config.items.len();
+        let mut lock = Lock::default();
+        let mut failed = 0;
+
+        if use_daemon {
+            progress!("Hosting {} gistits", total);
+            for item in config.items {
+                let name = first_file_name(&item.gistit);
+                let author = item.gistit.author.clone();
+                let hosted = match provide(&mut bridge, item.gistit, Vec::new()).await {
+                    Ok(hash) => match item.alias {
+                        Some(ref alias) => name::publish_hash(&mut bridge, alias, &hash)
+                            .await
+                            .map(|_| hash),
+                        None => Ok(hash),
+                    },
+                    Err(err) => Err(err),
+                };
+
+                match hosted {
+                    Ok(hash) => {
+                        cache::record_or_warn(Entry::new(&hash, &name, &author, Origin::Hosted));
+                        updateln!("Hosted '{}'", item.file);
+                        lock.gistit.push(Locked {
+                            file: item.file,
+                            hash,
+                            url: None,
+                            raw_url: None,
+                            alias: item.alias,
+                            expires_at: None,
+                        });
+                    }
+                    Err(err) => {
+                        warnln!("failed to host '{}': {}", item.file, err);
+                        failed += 1;
+                    }
+                }
+            }
+        } else {
+            progress!("Sending {} gistits", total);
+            let limits = config.limits;
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Check your system time")
+                .as_secs();
+
+            let sending: Vec<_> = config
+                .items
+                .into_iter()
+                .map(|item| {
+                    let gistit = item.gistit.clone();
+                    let handle = tokio::spawn(async move {
+                        server::retry_rate_limited(|| {
+                            gistit_core::send::send_server(&SERVER_URL_BASE, &gistit)
+                        })
+                        .await
+                    });
+                    (item, handle)
+                })
+                .collect();
+
+            for (item, handle) in sending {
+                match handle.await.expect("send task to not panic") {
+                    Ok(hash) => {
+                        cache::record_or_warn(Entry::new(
+                            &hash,
+                            &first_file_name(&item.gistit),
+                            &item.gistit.author,
+                            Origin::Sent,
+                        ));
+                        updateln!("Sent '{}'", item.file);
+                        lock.gistit.push(Locked {
+                            file: item.file,
+                            url: Some(share_url(&hash)),
+                            raw_url: Some(raw_url(&hash)),
+                            hash,
+                            alias: None,
+                            expires_at: match item.gistit.lifespan {
+                                Some(LIFESPAN_NEVER) => None,
+                                lifespan => Some(now + lifespan.unwrap_or(limits.default_lifespan)),
+                            },
+                        });
+                    }
+                    Err(err) => {
+                        warnln!("failed to send '{}': {}", item.file, err);
+                        failed += 1;
+                    }
+                }
+            }
+        }
+
+        fs::write(&config.lock_path, render_lock(&lock)?)?;
+        finish!(format!(
+            "\n    sent: {} of {}\n    lockfile: '{}'\n\n",
+            style(total - failed).bold(),
+            total,
+            config.lock_path.to_string_lossy()
+        ));
+
+        if failed > 0 {
+            return Err(Error::Manifest(format!(
+                "{} of {} entries failed to send",
+                failed, total
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Checks and builds every entry, reporting all invalid ones at once
+fn validate(
+    entries: Vec<ManifestEntry>,
+    base: &Path,
+    author: &str,
+    limits: &Limits,
+) -> Result<Vec<Item>> {
+    if entries.is_empty() {
+        return Err(Error::Manifest("no `[[gistit]]` entries".to_owned()));
+    }
+
+    let mut problems = Vec::new();
+    let mut aliases = HashSet::new();
+    let mut items = Vec::new();
+
+    for (i, entry) in entries.into_iter().enumerate() {
+        if let Some(ref alias) = entry.alias {
+            if !aliases.insert(alias.clone()) {
+                problems.push(format!("entry {} '{}': duplicate alias", i + 1, entry.file));
+                continue;
+            }
+        }
+
+        let file = entry.file.clone();
+        match prepare_entry(entry, base, author, limits) {
+            Ok(item) => items.push(item),
+            Err(err) => problems.push(format!("entry {} '{}': {}", i + 1, file, reason(&err))),
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(items)
+    } else {
+        Err(Error::Manifest(format!(
+            "\n    {}",
+            problems.join("\n    ")
+        )))
+    }
+}
+
+fn prepare_entry(entry: ManifestEntry, base: &Path, author: &str, limits: &Limits) -> Result<Item> {
+    let path = base.join(&entry.file);
+    check::metadata(&fs::metadata(&path)?)?;
+    check::extension(path.extension())?;
+
+    let description = entry
+        .description
+        .as_deref()
+        .map(check::description)
+        .transpose()?;
+    let secret = entry.secret.as_deref().map(check::secret).transpose()?;
+    let lifespan = entry
+        .lifespan
+        .as_deref()
+        .map(|lifespan| check::lifespan(lifespan, limits))
+        .transpose()?;
+    let alias = entry.alias.as_deref().map(check::name).transpose()?;
+
+    let mut gistit = new_gistit(&File::from_path(&path)?, author.to_owned(), description)?;
+    if let Some(secret) = secret {
+        secret::seal(&mut gistit, secret)?;
+    }
+    gistit.lifespan = lifespan;
+
+    Ok(Item {
+        file: entry.file,
+        gistit,
+        alias: alias.map(ToOwned::to_owned),
+    })
+}
+
+/// The short reason of a validation error, without the argument hint
+fn reason(err: &Error) -> String {
+    match err {
+        Error::Argument(reason, _) => (*reason).to_owned(),
+        err => err.to_string(),
+    }
+}
+
+/// `gistits.lock` next to `gistits.toml`
+fn lock_path(manifest_path: &Path) -> PathBuf {
+    manifest_path.with_extension("lock")
+}
+
+fn render_lock(lock: &Lock) -> Result<String> {
+    Ok(format!("{}{}", LOCKFILE_HEADER, toml::to_string(lock)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifest_parse() {
+        let manifest: Manifest = toml::from_str(
+            r#"
+            author = "alice"
+
+            [[gistit]]
+            file = "src/main.rs"
+            description = "The entry point"
+            lifespan = "2d"
+
+            [[gistit]]
+            file = "notes.md"
+            alias = "@alice/notes"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(manifest.author.as_deref(), Some("alice"));
+        assert_eq!(manifest.gistit.len(), 2);
+        assert_eq!(manifest.gistit[0].lifespan.as_deref(), Some("2d"));
+        assert_eq!(manifest.gistit[1].alias.as_deref(), Some("@alice/notes"));
+    }
+
+    #[test]
+    fn manifest_unknown_field() {
+        assert!(toml::from_str::<Manifest>("[[gistit]]\nfile = \"a.rs\"\nttl = \"2d\"\n").is_err());
+    }
+
+    #[test]
+    fn manifest_validate_reports_every_entry() {
+        let dir = std::env::temp_dir().join("gistit-manifest-test");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("ok.rs"), "fn main() { println!(\"hello\"); }").unwrap();
+
+        let entries = vec![
+            ManifestEntry {
+                file: "ok.rs".to_owned(),
+                description: None,
+                secret: None,
+                lifespan: Some("1h".to_owned()),
+                alias: None,
+            },
+            ManifestEntry {
+                file: "ok.rs".to_owned(),
+                description: None,
+                secret: None,
+                lifespan: Some("1y".to_owned()),
+                alias: None,
+            },
+            ManifestEntry {
+                file: "missing.rs".to_owned(),
+                description: None,
+                secret: None,
+                lifespan: None,
+                alias: None,
+            },
+        ];
+
+        let err = validate(entries, &dir, "alice", &Limits::default())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("entry 2 'ok.rs': invalid lifespan"));
+        assert!(err.contains("entry 3 'missing.rs'"));
+        assert!(!err.contains("entry 1"));
+    }
+
+    #[test]
+    fn manifest_lifespan() {
+        let limits = Limits::default();
+        assert_eq!(check::lifespan("30m", &limits).unwrap(), 30 * 60);
+        assert_eq!(check::lifespan("7d", &limits).unwrap(), 7 * 24 * 60 * 60);
+        assert!(check::lifespan("30s", &limits).is_err());
+        assert!(check::lifespan("8d", &limits).is_err());
+        assert!(check::lifespan("d", &limits).is_err());
+        assert!(check::lifespan("", &limits).is_err());
+        assert!(check::lifespan("never", &limits).is_err());
+
+        let limits = Limits {
+            max_lifespan: 30 * 24 * 60 * 60,
+            never_expire: true,
+            ..limits
+        };
+        assert_eq!(check::lifespan("8d", &limits).unwrap(), 8 * 24 * 60 * 60);
+        assert_eq!(check::lifespan("never", &limits).unwrap(), LIFESPAN_NEVER);
+    }
+
+    #[test]
+    fn manifest_lock_path_and_render() {
+        assert_eq!(
+            lock_path(Path::new("dir/gistits.toml")),
+            PathBuf::from("dir/gistits.lock")
+        );
+
+        let lock = Lock {
+            gistit: vec![Locked {
+                file: "src/main.rs".to_owned(),
+                hash: "hash".to_owned(),
+                url: Some("https://gistit.vercel.app/h/hash".to_owned()),
+                raw_url: None,
+                alias: None,
+                expires_at: Some(42),
+            }],
+        };
+        let rendered = render_lock(&lock).unwrap();
+        assert!(rendered.starts_with(LOCKFILE_HEADER));
+        assert_eq!(toml::from_str::<Lock>(&rendered).unwrap(), lock);
+    }
+}
@@ -0,0 +1,111 @@
+//! The mirror module
+//!
+//! `gistit <file> --mirror server,p2p` sends the same gistit to the server and hosts it through
+//! `gistit-daemon` at once. Every target is sent to concurrently and reports it's own outcome, one
+//! failing doesn't stop the others.
+
+use std::fmt;
+use std::path::Path;
+
+use gistit_ipc::{Bridge, Client};
+use gistit_proto::payload::Gistit;
+
+use crate::daemon;
+use crate::send::{provide, send_server};
+use crate::settings::Backend;
+use crate::{Error, Result};
+
+/// Where a mirrored gistit goes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Server,
+    P2p,
+}
+
+impl Target {
+    /// Parses a `--mirror` value
+    ///
+    /// # Errors
+    ///
+    /// Fails if it's not 'server' or 'p2p'
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "server" => Ok(Self::Server),
+            "p2p" => Ok(Self::P2p),
+            _ => Err(Error::Argument("expected 'server' or 'p2p'", "--mirror")),
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Server => write!(f, "server"),
+            Self::P2p => write!(f, "p2p"),
+        }
+    }
+}
+
+/// The hash a target stored the gistit under, or why it didn't
+pub struct Outcome {
+    pub target: Target,
+    pub hash: Result<String>,
+}
+
+/// Sends `gistit` to every target concurrently, starting `gistit-daemon` first if it's a target
+/// and isn't running. Outcomes are in the order of `targets`
+pub async fn send(
+    bridge: &mut Bridge<Client>,
+    gistit: &Gistit,
+    topics: Vec<String>,
+    targets: &[Target],
+    runtime_path: &Path,
+    config_path: &Path,
+) -> Vec<Outcome> {
+    let to_server = targets.contains(&Target::Server);
+    let to_p2p = targets.contains(&Target::P2p);
+
+    let server = async {
+        if to_server {
+            Some(send_server(gistit).await)
+        } else {
+            None
+        }
+    };
+    let p2p = async {
+        if to_p2p {
+            // The server upload doesn't wait for the daemon to come up
+            let spawned =
+                daemon::spawn_if_required(Backend::P2p, bridge, runtime_path, config_path).await;
+            Some(host(bridge, gistit.clone(), topics, spawned).await)
+        } else {
+            None
+        }
+    };
+    let (mut server, mut p2p) = tokio::join!(server, p2p);
+
+    targets
+        .iter()
+        .filter_map(|&target| {
+            let hash = match target {
+                Target::Server => server.take(),
+                Target::P2p => p2p.take(),
+            }?;
+            Some(Outcome { target, hash })
+        })
+        .collect()
+}
+
+async fn host(
+    bridge: &mut Bridge<Client>,
+    gistit: Gistit,
+    topics: Vec<String>,
+    spawned: Result<()>,
+) -> Result<String> {
+    spawned?;
+    if !bridge.alive() {
+        return Err(Error::Daemon("gistit node is not running"));
+    }
+    bridge.connect_blocking()?;
+    provide(bridge, gistit, topics).await
+}
@@ -0,0 +1,165 @@
+//! The name module
+//!
+//! Human readable names, `@user/name`, pointing to the latest hash of a gistit. Names live in the
+//! DHT as records signed by the publishing node, so publishing and resolving needs `gistit-daemon`.
+//! Publish again to point a name to a newer hash.
+
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use clap::ArgMatches;
+use console::style;
+
+use gistit_ipc::{Bridge, Client};
+use gistit_project::path;
+use gistit_proto::{ipc, Instruction};
+
+use crate::daemon;
+use crate::dispatch::Dispatch;
+use crate::param::check;
+use crate::settings::{get_runtime_settings, Backend};
+use crate::{finish, progress, updateln, Error, Result};
+
+#[derive(Debug, Clone)]
+pub enum Action<'a> {
+    Publish { name: &'a str, hash: &'a str },
+    Resolve { name: &'a str },
+}
+
+impl<'a> Action<'a> {
+    pub fn from_args(
+        args: &'a ArgMatches,
+    ) -> Result<Box<dyn Dispatch<InnerData = Config> + Send + Sync + 'a>> {
+        let name = |args: &'a ArgMatches| {
+            args.value_of("NAME")
+                .ok_or(Error::Argument("missing argument", "[NAME]"))
+        };
+
+        let action = match args.subcommand() {
+            Some(("publish", args)) => Self::Publish {
+                name: name(args)?,
+                hash: args
+                    .value_of("HASH")
+                    .ok_or(Error::Argument("missing argument", "[HASH]"))?,
+            },
+            Some(("resolve", args)) => Self::Resolve { name: name(args)? },
+            _ => return Err(Error::Argument("missing subcommand", "name")),
+        };
+        Ok(Box::new(action))
+    }
+}
+
+pub struct Config {
+    backend: Backend,
+    runtime_path: PathBuf,
+    config_path: PathBuf,
+}
+
+#[async_trait]
+impl<'a> Dispatch for Action<'a> {
+    type InnerData = Config;
+
+    async fn prepare(&self) -> Result<Self::InnerData> {
+        match self {
+            Self::Publish { name, hash } => {
+                check::name(name)?;
+                check::hash(hash)?;
+            }
+            Self::Resolve { name } => {
+                check::name(name)?;
+            }
+        }
+
+        Ok(Config {
+            backend: get_runtime_settings()?.backend(),
+            runtime_path: path::runtime_dir()?,
+            config_path: path::config()?,
+        })
+    }
+
+    async fn dispatch(&self, config: Self::InnerData) -> Result<()> {
+        let mut bridge = gistit_ipc::client(&config.runtime_path)?;
+        daemon::spawn_if_required(
+            config.backend,
+            &bridge,
+            &config.runtime_path,
+            &config.config_path,
+        )
+        .await?;
+
+        match self {
+            Self::Publish { name, hash } => publish(&mut bridge, name, hash).await,
+            Self::Resolve { name } => {
+                let (hash, publisher) = resolve(&mut bridge, name).await?;
+                finish!(format!(
+                    "\n    name: '{}'\n    hash: '{}'\n    publisher: '{}'\n\n",
+                    style(name).bold(),
+                    style(hash).bold(),
+                    style(publisher).blue()
+                ));
+                Ok(())
+            }
+        }
+    }
+}
+
+async fn publish(bridge: &mut Bridge<Client>, name: &str, hash: &str) -> Result<()> {
+    progress!("Publishing");
+    publish_hash(bridge, name, hash).await?;
+
+    updateln!("Published");
+    finish!(format!(
+        "\n    name: '{}'\n    hash: '{}'\n\n",
+        style(name).bold(),
+        style(hash).bold()
+    ));
+    Ok(())
+}
+
+/// Points `name` to `hash` in the DHT, signed by our node
+pub async fn publish_hash(bridge: &mut Bridge<Client>, name: &str, hash: &str) -> Result<()> {
+    if !bridge.alive() {
+        return Err(Error::Daemon("gistit node is not running"));
+    }
+
+    bridge.connect_blocking()?;
+    bridge
+        .send(Instruction::request_publish_name(
+            name.to_owned(),
+            hash.to_owned(),
+        ))
+        .await?;
+
+    match bridge.recv().await?.expect_response()? {
+        ipc::instruction::Kind::PublishNameResponse(ipc::instruction::PublishNameResponse {
+            published: true,
+        }) => Ok(()),
+        _ => Err(Error::Daemon(
+            "failed to publish name, check gistit-daemon logs",
+        )),
+    }
+}
+
+/// Looks `name` up in the DHT, returning the hash it points to and the peer id of it's publisher
+pub async fn resolve(bridge: &mut Bridge<Client>, name: &str) -> Result<(String, String)> {
+    progress!("Resolving {}", name);
+    if !bridge.alive() {
+        return Err(Error::Daemon("gistit node is not running"));
+    }
+
+    bridge.connect_blocking()?;
+    bridge
+        .send(Instruction::request_resolve_name(name.to_owned()))
+        .await?;
+
+    match bridge.recv().await?.expect_response()? {
+        ipc::instruction::Kind::ResolveNameResponse(ipc::instruction::ResolveNameResponse {
+            hash: Some(hash),
+            peer_id,
+        }) => {
+            updateln!("Resolved {}", style(name).bold());
+            Ok((hash, peer_id))
+        }
+        _ => Err(Error::Daemon("name not found")),
+    }
+}
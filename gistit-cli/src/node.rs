@@ -1,7 +1,6 @@
 use std::fs;
 use std::io::{BufRead, BufReader};
 use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
 use std::thread::sleep;
 use std::time::Duration;
 
@@ -13,27 +12,29 @@ use gistit_project::path;
 use gistit_proto::{ipc, Instruction};
 
 use crate::arg::app;
+use crate::daemon::{self, format_daemon_status, LOGFILE_NAME};
 use crate::dispatch::Dispatch;
+use crate::error::exit;
 use crate::param::check;
-use crate::{cleanln, errorln, finish, interruptln, progress, updateln, Error, Result};
+use crate::{cleanln, finish, progress, updateln, Error, Result};
 
 #[derive(Debug, Clone)]
 #[allow(clippy::struct_excessive_bools)]
-pub struct Action {
+pub struct Action<'a> {
     pub start: bool,
     pub stop: bool,
     pub status: bool,
     pub attach: bool,
     // Hidden args
-    dial: Option<&'static str>,
-    host: &'static str,
-    port: &'static str,
+    dial: Option<&'a str>,
+    host: &'a str,
+    port: &'a str,
 }
 
-impl Action {
+impl<'a> Action<'a> {
     pub fn from_args(
-        args: &'static ArgMatches,
-    ) -> Result<Box<dyn Dispatch<InnerData = Config> + Send + Sync + 'static>> {
+        args: &'a ArgMatches,
+    ) -> Result<Box<dyn Dispatch<InnerData = Config<'a>> + Send + Sync + 'a>> {
         Ok(Box::new(Self {
             start: args.is_present("start"),
             stop: args.is_present("stop"),
@@ -51,29 +52,29 @@ impl Action {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
-enum ProcessCommand {
+enum ProcessCommand<'a> {
     Start,
     Status,
     Stop,
     Attach,
-    Dial(&'static str),
+    Dial(&'a str),
 }
 
-pub struct Config {
-    commands: Vec<ProcessCommand>,
-    host: &'static str,
-    port: &'static str,
+pub struct Config<'a> {
+    commands: Vec<ProcessCommand<'a>>,
+    host: &'a str,
+    port: &'a str,
     runtime_path: PathBuf,
     config_path: PathBuf,
 }
 
 #[async_trait]
-impl Dispatch for Action {
-    type InnerData = Config;
+impl<'a> Dispatch for Action<'a> {
+    type InnerData = Config<'a>;
 
     async fn prepare(&self) -> Result<Self::InnerData> {
         progress!("Preparing");
-        let mut commands: Vec<ProcessCommand> = Vec::new();
+        let mut commands: Vec<ProcessCommand<'a>> = Vec::new();
         match (self.start, self.stop, self.status, self.attach, self.dial) {
             // Matching:
             // - start
@@ -133,7 +134,7 @@ impl Dispatch for Action {
             // No match. Clap should not let this branch happen
             (_, _, _, _, _) => {
                 app().print_help()?;
-                std::process::exit(1);
+                std::process::exit(exit::USAGE);
             }
         };
 
@@ -142,7 +143,7 @@ impl Dispatch for Action {
             commands,
             host,
             port,
-            runtime_path: path::runtime()?,
+            runtime_path: path::runtime_dir()?,
             config_path: path::config()?,
         };
         updateln!("Prepared");
@@ -164,29 +165,20 @@ impl Dispatch for Action {
                         if let ipc::instruction::Kind::StatusResponse(response) =
                             bridge.recv().await?.expect_response()?
                         {
-                            format_daemon_status(&response);
+                            let pid = daemon::read_pid(&config.runtime_path);
+                            format_daemon_status(&response, pid);
                         }
 
                         continue;
                     }
 
                     progress!("Starting gistit node");
-                    let pid = {
-                        let stdout = fs::File::create(config.runtime_path.join("gistit.log"))?;
-                        // FIXME: Fix this before release
-                        let daemon = "gistit-daemon";
-
-                        Command::new(daemon)
-                            .args(&["--host", config.host])
-                            .args(&["--port", config.port])
-                            .args(&["--runtime-path", &*config.runtime_path.to_string_lossy()])
-                            .args(&["--config-path", &*config.config_path.to_string_lossy()])
-                            .arg("--bootstrap")
-                            .stderr(stdout)
-                            .stdout(Stdio::null())
-                            .spawn()?
-                            .id()
-                    };
+                    let pid = daemon::spawn(
+                        &config.runtime_path,
+                        &config.config_path,
+                        config.host,
+                        config.port,
+                    )?;
 
                     updateln!("Gistit node started, pid: {}", style(pid).blue());
                     bridge.connect_blocking()?;
@@ -203,16 +195,15 @@ impl Dispatch for Action {
                 ProcessCommand::Stop => {
                     progress!("Stopping");
                     if bridge.alive() {
-                        fs::remove_file(config.runtime_path.join("gistit.log"))?;
+                        fs::remove_file(config.runtime_path.join(LOGFILE_NAME))?;
+                        daemon::remove_pid(&config.runtime_path)?;
 
                         bridge.connect_blocking()?;
                         bridge.send(Instruction::request_shutdown()).await?;
                         updateln!("Stopped");
                         finish!("");
                     } else {
-                        interruptln!();
-                        errorln!("gistit node is not running");
-                        std::process::exit(1);
+                        return Err(Error::Daemon("gistit node is not running"));
                     }
                 }
 
@@ -225,12 +216,11 @@ impl Dispatch for Action {
                         if let ipc::instruction::Kind::StatusResponse(response) =
                             bridge.recv().await?.expect_response()?
                         {
-                            format_daemon_status(&response);
+                            let pid = daemon::read_pid(&config.runtime_path);
+                            format_daemon_status(&response, pid);
                         }
                     } else {
-                        interruptln!();
-                        errorln!("gistit node is not running");
-                        std::process::exit(1);
+                        return Err(Error::Daemon("gistit node is not running"));
                     }
                 }
 
@@ -243,9 +233,7 @@ impl Dispatch for Action {
                             .await?;
                         updateln!("Dialed");
                     } else {
-                        interruptln!();
-                        errorln!("gistit node is not running");
-                        std::process::exit(1);
+                        return Err(Error::Daemon("gistit node is not running"));
                     }
                 }
 
@@ -265,58 +253,29 @@ impl Dispatch for Action {
     }
 }
 
-fn format_daemon_status(response: &ipc::instruction::StatusResponse) {
-    let ipc::instruction::StatusResponse {
-        peer_id,
-        peer_count,
-        pending_connections,
-        hosting,
-    } = response;
-
-    updateln!("Running status");
-    finish!(format!(
-        r#"
-    peer id: '{}'
-    hosting: {} gistit
-    peers: {}
-    pending connections: {}
-        "#,
-        style(peer_id).bold(),
-        hosting,
-        style(peer_count).blue(),
-        pending_connections,
-    ));
-}
-
 fn attach_to_log(runtime_path: &Path, linked: bool) -> Result<()> {
-    let log_path = runtime_path.join("gistit.log");
-
-    if let Ok(log) = fs::File::open(&log_path) {
-        let mut reader = BufReader::new(&log);
-        let mut buf = String::new();
+    let log_path = runtime_path.join(LOGFILE_NAME);
+    let log = fs::File::open(&log_path)
+        .map_err(|_| Error::Daemon("can't attach to log file, is it running?"))?;
+    let mut reader = BufReader::new(&log);
+    let mut buf = String::new();
+
+    if linked {
+        progress!(
+            "Executing {}",
+            style("(CTRL-C exits the process)").italic().dim()
+        );
+    } else {
+        finish!("");
+    }
 
-        if linked {
-            progress!(
-                "Executing {}",
-                style("(CTRL-C exits the process)").italic().dim()
-            );
+    loop {
+        let bytes = reader.read_line(&mut buf)?;
+        if bytes > 0 {
+            cleanln!(buf);
+            buf = String::new();
         } else {
-            finish!("");
-        }
-
-        loop {
-            let bytes = reader.read_line(&mut buf)?;
-            if bytes > 0 {
-                cleanln!(buf);
-                buf = String::new();
-            } else {
-                sleep(Duration::from_millis(500));
-            }
+            sleep(Duration::from_millis(500));
         }
-    } else {
-        interruptln!();
-        errorln!("can't attach to log file, is it running?");
     }
-
-    Ok(())
 }
@@ -28,6 +28,25 @@ pub const SUPPORTED_COLORSCHEMES: [&str; 24] = [
     "zenburn",
 ];
 
+/// What points at a gistit, see [`check::identifier`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Identifier<'a> {
+    /// A bare hash, looked up wherever `--prefer` says
+    Hash(&'a str),
+    /// `#<hash>` or the web or raw url of a gistit, only the server knows about it
+    Server(&'a str),
+    /// `@<hash>`, only looked up in the p2p network
+    Peer(&'a str),
+    /// `@user/name` published with `gistit name`, resolved in the p2p network
+    Name(&'a str),
+    /// The first characters of a hash, like git short SHAs. Expanded with the local index, then
+    /// with the hashes `gistit-daemon` holds and the public ones on the server
+    Short(&'a str),
+    /// `#` followed by the first characters of a hash, only expanded with the local index and the
+    /// server
+    ServerShort(&'a str),
+}
+
 lazy_static! {
     static ref FUZZY_MATCH: Corpus = SUPPORTED_COLORSCHEMES.iter().fold(
         CorpusBuilder::new().arity(2).pad_full(Pad::Auto).finish(),
@@ -39,14 +58,17 @@ lazy_static! {
 }
 
 pub mod check {
-    use super::{FUZZY_MATCH, SUPPORTED_COLORSCHEMES};
+    use super::{Identifier, FUZZY_MATCH, SUPPORTED_COLORSCHEMES};
 
     use std::ffi::OsStr;
     use std::fs;
-    use std::net::Ipv4Addr;
+    use std::net::{IpAddr, Ipv4Addr};
     use std::ops::RangeInclusive;
+    use std::time::Duration;
 
     use crate::file::EXTENSION_TO_LANG_MAPPING;
+    use crate::server::{Limits, LIFESPAN_NEVER};
+    use crate::settings::Visibility;
     use crate::{Error, Result};
 
     const ALLOWED_FILE_SIZE_RANGE: RangeInclusive<u64> = 20..=50_000;
@@ -55,8 +77,36 @@ pub mod check {
 
     const ALLOWED_AUTHOR_CHAR_LENGTH_RANGE: RangeInclusive<usize> = 3..=30;
 
+    /// Same bounds as the server
+    const ALLOWED_COMMENT_CHAR_LENGTH_RANGE: RangeInclusive<usize> = 1..=500;
+
+    const ALLOWED_SECRET_CHAR_LENGTH_RANGE: RangeInclusive<usize> = 5..=50;
+
     const GISTIT_HASH_CHAR_LENGTH: usize = 64;
 
+    /// Short hashes, like git's. Same bounds as the server
+    const SHORT_HASH_CHAR_LENGTH_RANGE: RangeInclusive<usize> = 4..=63;
+
+    /// Path segments before the hash in web and raw urls
+    const URL_HASH_SEGMENTS: [&str; 2] = ["h", "raw"];
+
+    /// Same bounds as the server
+    const ALLOWED_TOKEN_CHAR_LENGTH_RANGE: RangeInclusive<usize> = 32..=128;
+
+    /// Same bounds as the server
+    const ALLOWED_MAX_READS_RANGE: RangeInclusive<u32> = 1..=1000;
+
+    const MAX_TOPIC_CHAR_LENGTH: usize = 64;
+
+    const PEER_ID_CHAR_LENGTH_RANGE: RangeInclusive<usize> = 46..=64;
+
+    const MAX_NAME_PART_CHAR_LENGTH: usize = 32;
+
+    const MAX_FILE_NAME_CHAR_LENGTH: usize = 255;
+
+    /// Fragment of links carrying the secret of a gistit, `<link>#key=<secret>`
+    pub const KEY_FRAGMENT: &str = "#key=";
+
     pub fn description(description: &str) -> Result<&str> {
         if ALLOWED_DESCRIPTION_CHAR_LENGHT_RANGE.contains(&description.len()) {
             Ok(description)
@@ -68,6 +118,21 @@ pub mod check {
         }
     }
 
+    /// Name given to content sent from stdin, a plain file name
+    pub fn file_name(name: &str) -> Result<&str> {
+        if !name.is_empty()
+            && name.len() <= MAX_FILE_NAME_CHAR_LENGTH
+            && !name.contains(['/', '\\'])
+        {
+            Ok(name)
+        } else {
+            Err(Error::Argument(
+                "invalid file name, expected no directories",
+                "--file-name",
+            ))
+        }
+    }
+
     pub fn author(author: &str) -> Result<&str> {
         if ALLOWED_AUTHOR_CHAR_LENGTH_RANGE.contains(&author.len()) {
             Ok(author)
@@ -79,6 +144,18 @@ pub mod check {
         }
     }
 
+    pub fn comment(comment: &str) -> Result<&str> {
+        if !comment.trim().is_empty() && ALLOWED_COMMENT_CHAR_LENGTH_RANGE.contains(&comment.len())
+        {
+            Ok(comment)
+        } else {
+            Err(Error::Argument(
+                "invalid comment character length.",
+                "[MESSAGE]",
+            ))
+        }
+    }
+
     pub fn metadata(attr: &fs::Metadata) -> Result<()> {
         let size_allowed = ALLOWED_FILE_SIZE_RANGE.contains(&attr.len());
 
@@ -89,6 +166,65 @@ pub mod check {
         }
     }
 
+    /// Same size limits as files, for content that isn't read from one (e.g. the clipboard)
+    pub fn content_size(content: &str) -> Result<&str> {
+        if ALLOWED_FILE_SIZE_RANGE.contains(&(content.len() as u64)) {
+            Ok(content)
+        } else {
+            Err(Error::Argument("content size not allowed", "[CLIPBOARD]"))
+        }
+    }
+
+    /// Same size limits as files, for the base64 encoded content of a binary file
+    pub fn binary_size(binary: &str) -> Result<&str> {
+        if ALLOWED_FILE_SIZE_RANGE.contains(&(binary.len() as u64)) {
+            Ok(binary)
+        } else {
+            Err(Error::Argument(
+                "binary file size not allowed once encoded",
+                "[FILE]",
+            ))
+        }
+    }
+
+    /// Same size limits as files, for the encoded archive of a directory
+    pub fn archive_size(archive: &str) -> Result<&str> {
+        if ALLOWED_FILE_SIZE_RANGE.contains(&(archive.len() as u64)) {
+            Ok(archive)
+        } else {
+            Err(Error::Argument("archive size not allowed", "--archive"))
+        }
+    }
+
+    pub fn min_lines(lines: &str) -> Result<usize> {
+        match lines.parse() {
+            Ok(lines) if lines > 0 => Ok(lines),
+            _ => Err(Error::Argument(
+                "invalid line count, expected a positive number",
+                "--min-lines",
+            )),
+        }
+    }
+
+    pub fn revision(rev: &str) -> Result<usize> {
+        match rev.parse() {
+            Ok(rev) if rev > 0 => Ok(rev),
+            _ => Err(Error::Argument(
+                "invalid revision, expected a positive number",
+                "--rev",
+            )),
+        }
+    }
+
+    pub fn tab_width(width: &str) -> Result<usize> {
+        width.parse().map_err(|_| {
+            Error::Argument(
+                "invalid tab width, expected a number of spaces",
+                "--tab-width",
+            )
+        })
+    }
+
     pub fn extension(ext: Option<&OsStr>) -> Result<()> {
         let ext = ext
             .and_then(OsStr::to_str)
@@ -115,6 +251,59 @@ pub mod check {
         }
     }
 
+    pub fn secret(secret: &str) -> Result<&str> {
+        if ALLOWED_SECRET_CHAR_LENGTH_RANGE.contains(&secret.len()) {
+            Ok(secret)
+        } else {
+            Err(Error::Argument(
+                "invalid secret character length, expected 5 to 50",
+                "--secret",
+            ))
+        }
+    }
+
+    /// Splits the `#key=<secret>` fragment off links to encrypted gistits, returning the link
+    /// without it and the secret
+    pub fn key_link(identifier: &str) -> Result<(&str, Option<&str>)> {
+        match identifier.split_once(KEY_FRAGMENT) {
+            Some((link, key)) => {
+                let key = secret(key).map_err(|_| {
+                    Error::Argument(
+                        "invalid key in the gistit link, expected 5 to 50 characters",
+                        "[HASH]",
+                    )
+                })?;
+                Ok((link, Some(key)))
+            }
+            None => Ok((identifier, None)),
+        }
+    }
+
+    pub fn visibility(visibility: &str) -> Result<Visibility> {
+        match visibility {
+            "public" => Ok(Visibility::Public),
+            "unlisted" => Ok(Visibility::Unlisted),
+            "private" => Ok(Visibility::Private),
+            _ => Err(Error::Argument(
+                "invalid visibility, expected 'public', 'unlisted' or 'private'",
+                "--visibility",
+            )),
+        }
+    }
+
+    pub fn token(token: &str) -> Result<&str> {
+        if ALLOWED_TOKEN_CHAR_LENGTH_RANGE.contains(&token.len())
+            && token.chars().all(|c| c.is_ascii_alphanumeric())
+        {
+            Ok(token)
+        } else {
+            Err(Error::Argument(
+                "invalid owner token, expected 32 to 128 letters or digits",
+                "--token",
+            ))
+        }
+    }
+
     pub const fn hash(hash: &str) -> Result<&str> {
         if hash.len() == GISTIT_HASH_CHAR_LENGTH {
             Ok(hash)
@@ -123,6 +312,91 @@ pub mod check {
         }
     }
 
+    /// Bare hashes, `#<hash>`, `@<hash>`, `@user/name`, web or raw urls like
+    /// `https://gistit.vercel.app/h/<hash>` and short hashes, bare or after `#`. A `#key=<secret>`
+    /// fragment is left out, see [`key_link`]
+    pub fn identifier(identifier: &str) -> Result<Identifier> {
+        let identifier = identifier
+            .split_once(KEY_FRAGMENT)
+            .map_or(identifier, |(link, _)| link);
+
+        if let Some(rest) = identifier.strip_prefix('#') {
+            if is_short(rest) {
+                Ok(Identifier::ServerShort(rest))
+            } else {
+                Ok(Identifier::Server(hash(rest)?))
+            }
+        } else if identifier.starts_with('@') && identifier.contains('/') {
+            Ok(Identifier::Name(name(identifier)?))
+        } else if let Some(rest) = identifier.strip_prefix('@') {
+            Ok(Identifier::Peer(hash(rest)?))
+        } else if identifier.contains("://") {
+            let path = identifier.split(&['?', '#'][..]).next().unwrap_or_default();
+            let mut segments = path.trim_end_matches('/').rsplit('/');
+            match (segments.next(), segments.next()) {
+                (Some(rest), Some(segment)) if URL_HASH_SEGMENTS.contains(&segment) => {
+                    Ok(Identifier::Server(hash(rest)?))
+                }
+                _ => Err(Error::Argument(
+                    "invalid gistit url, expected '.../h/<hash>' or '.../raw/<hash>'",
+                    "[HASH]",
+                )),
+            }
+        } else if identifier.len() == GISTIT_HASH_CHAR_LENGTH {
+            Ok(Identifier::Hash(identifier))
+        } else if is_short(identifier) {
+            Ok(Identifier::Short(identifier))
+        } else {
+            Err(Error::Argument(
+                "invalid gistit, expected a hash, '@user/name' or a gistit url",
+                "[HASH]",
+            ))
+        }
+    }
+
+    fn is_short(identifier: &str) -> bool {
+        SHORT_HASH_CHAR_LENGTH_RANGE.contains(&identifier.len())
+            && identifier.chars().all(|c| c.is_ascii_hexdigit())
+    }
+
+    /// Names are written as `@user/name`, both parts up to 32 alphanumeric, `-`, `_` or `.`
+    pub fn name(name: &str) -> Result<&str> {
+        let valid_part = |part: &str| {
+            !part.is_empty()
+                && part.len() <= MAX_NAME_PART_CHAR_LENGTH
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        };
+
+        match name.strip_prefix('@').and_then(|name| name.split_once('/')) {
+            Some((user, rest)) if valid_part(user) && valid_part(rest) => Ok(name),
+            _ => Err(Error::Argument(
+                "invalid name, expected '@user/name'",
+                "[NAME]",
+            )),
+        }
+    }
+
+    /// A snippet name, like the parts of `@user/name`. It names a directory so it can't start
+    /// with a dot
+    pub fn snippet(name: &str) -> Result<&str> {
+        if !name.is_empty()
+            && !name.starts_with('.')
+            && name.len() <= MAX_NAME_PART_CHAR_LENGTH
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        {
+            Ok(name)
+        } else {
+            Err(Error::Argument(
+                "invalid snippet name, expected letters, digits, '-', '_' or '.'",
+                "[NAME]",
+            ))
+        }
+    }
+
     pub fn host_port<'a, 'b>(host: &'a str, port: &'b str) -> Result<(&'a str, &'b str)> {
         let _host: Ipv4Addr = host
             .parse()
@@ -132,4 +406,206 @@ pub mod check {
             .map_err(|_| Error::Argument("invalid port", "--port"))?;
         Ok((host, port))
     }
+
+    /// Loose check of a bootstrap peer address, `gistit-daemon` does the full parsing
+    pub fn bootstrap_addr(address: &str) -> Result<&str> {
+        let peer_id = address.rsplit_once("/p2p/").map(|(_, peer_id)| peer_id);
+
+        match peer_id {
+            Some(peer_id)
+                if address.starts_with('/') && !peer_id.is_empty() && !peer_id.contains('/') =>
+            {
+                Ok(address)
+            }
+            _ => Err(Error::Argument(
+                "invalid bootstrap address, expected '/.../p2p/<peer id>'",
+                "[MULTIADDR]",
+            )),
+        }
+    }
+
+    /// Loose check of a listen address of the given transport, `gistit-daemon` does the full
+    /// parsing
+    pub fn listen_addr<'a>(address: &'a str, transport: &str) -> Result<&'a str> {
+        let is_websocket = address.ends_with("/ws") || address.ends_with("/wss");
+        let is_quic = address.contains("/udp/") && address.ends_with("/quic-v1");
+        let is_tcp = address.contains("/tcp/") && !is_websocket;
+
+        let matches = match transport {
+            "websocket" => is_websocket && address.contains("/tcp/"),
+            "quic" => is_quic,
+            _ => is_tcp,
+        };
+        if address.starts_with('/') && !address.contains("/p2p/") && matches {
+            return Ok(address);
+        }
+
+        Err(Error::Argument(
+            match transport {
+                "websocket" => "invalid websocket listen address, expected '/.../tcp/<port>/ws'",
+                "quic" => "invalid quic listen address, expected '/.../udp/<port>/quic-v1'",
+                _ => "invalid tcp listen address, expected '/.../tcp/<port>'",
+            },
+            "[MULTIADDR]",
+        ))
+    }
+
+    /// What `gistit-daemon` secures TCP and WebSocket connections with
+    pub fn security(security: &str) -> Result<&str> {
+        if matches!(security, "noise" | "tls") {
+            Ok(security)
+        } else {
+            Err(Error::Argument(
+                "invalid security, expected 'noise' or 'tls'",
+                "[SECURITY]",
+            ))
+        }
+    }
+
+    /// A lifespan like `30m`, `12h`, `7d` or `never`, in seconds, within the server `limits`.
+    /// Never expiring is [`LIFESPAN_NEVER`]
+    pub fn lifespan(lifespan: &str, limits: &Limits) -> Result<u64> {
+        if lifespan == "never" {
+            return if limits.never_expire {
+                Ok(LIFESPAN_NEVER)
+            } else {
+                Err(Error::Argument(
+                    "the server doesn't keep gistits forever, expected a lifespan like '2d'",
+                    "lifespan",
+                ))
+            };
+        }
+
+        match duration(lifespan) {
+            Some(secs) if secs < limits.min_lifespan => Err(Error::Argument(
+                "lifespan is shorter than the server allows",
+                "lifespan",
+            )),
+            Some(secs) if secs > limits.max_lifespan => Err(Error::Argument(
+                "lifespan is longer than the server allows",
+                "lifespan",
+            )),
+            Some(secs) => Ok(secs),
+            None => Err(Error::Argument(
+                "invalid lifespan, expected e.g. '30m', '12h', '2d' or 'never'",
+                "lifespan",
+            )),
+        }
+    }
+
+    /// How far back to look, like `30m`, `12h` or `7d`, in seconds
+    pub fn since(since: &str) -> Result<u64> {
+        duration(since).ok_or(Error::Argument(
+            "invalid duration, expected e.g. '30m', '12h' or '7d'",
+            "--since",
+        ))
+    }
+
+    /// A count of seconds, minutes, hours or days like `30m`, in seconds
+    pub fn duration(duration: &str) -> Option<u64> {
+        let (count, unit) = duration.split_at(duration.len().saturating_sub(1));
+        let unit_secs = match unit {
+            "s" => 1,
+            "m" => 60,
+            "h" => 60 * 60,
+            "d" => 24 * 60 * 60,
+            _ => return None,
+        };
+
+        count
+            .parse::<u64>()
+            .ok()
+            .and_then(|n| n.checked_mul(unit_secs))
+    }
+
+    /// Seconds network operations are given, see `--timeout`
+    pub fn timeout(timeout: &str) -> Result<Duration> {
+        match timeout.parse::<u64>() {
+            Ok(secs) if secs > 0 => Ok(Duration::from_secs(secs)),
+            _ => Err(Error::Argument(
+                "invalid timeout, expected a number of seconds",
+                "--timeout",
+            )),
+        }
+    }
+
+    pub fn max_reads(max_reads: &str) -> Result<u32> {
+        match max_reads.parse::<u32>() {
+            Ok(n) if ALLOWED_MAX_READS_RANGE.contains(&n) => Ok(n),
+            _ => Err(Error::Argument(
+                "invalid max reads, expected 1 to 1000",
+                "--max-reads",
+            )),
+        }
+    }
+
+    /// Lines to print, 1 based and inclusive, like bat's `N:M`, `N:`, `:M` or `N`
+    pub fn line_range(range: &str) -> Result<(usize, usize)> {
+        let parse = |n: &str, default: usize| {
+            if n.is_empty() {
+                Some(default)
+            } else {
+                n.parse::<usize>().ok()
+            }
+        };
+        let bounds = match range.split_once(':') {
+            Some((start, end)) => parse(start, 1).zip(parse(end, usize::MAX)),
+            None => range.parse::<usize>().ok().map(|n| (n, n)),
+        };
+
+        match bounds {
+            Some((start, end)) if start >= 1 && start <= end => Ok((start, end)),
+            _ => Err(Error::Argument(
+                "invalid line range, expected 'N:M', 'N:', ':M' or 'N'",
+                "--line-range",
+            )),
+        }
+    }
+
+    pub fn inbox_id(id: &str) -> Result<u32> {
+        id.parse()
+            .map_err(|_| Error::Argument("invalid inbox id, expected a number", "[ID]"))
+    }
+
+    pub fn topic(topic: &str) -> Result<&str> {
+        if topic.is_empty()
+            || topic.len() > MAX_TOPIC_CHAR_LENGTH
+            || topic.contains(char::is_whitespace)
+        {
+            Err(Error::Argument(
+                "invalid topic, expected no whitespace and up to 64 characters",
+                "[TOPIC]",
+            ))
+        } else {
+            Ok(topic)
+        }
+    }
+
+    /// Loose check of a base58 peer id, `gistit-daemon` does the full parsing
+    pub fn peer_id(peer_id: &str) -> Result<&str> {
+        const BASE58_ALPHABET: &str = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+        if PEER_ID_CHAR_LENGTH_RANGE.contains(&peer_id.len())
+            && peer_id.chars().all(|c| BASE58_ALPHABET.contains(c))
+        {
+            Ok(peer_id)
+        } else {
+            Err(Error::Argument("invalid peer id", "--to-peer"))
+        }
+    }
+
+    /// Loose check of an acl rule target, a peer id or an ip address with an optional prefix.
+    /// `gistit-daemon` does the full parsing
+    pub fn acl_target(target: &str) -> Result<&str> {
+        let ip = target.split_once('/').map_or(target, |(ip, _)| ip);
+
+        if ip.parse::<IpAddr>().is_ok() || peer_id(target).is_ok() {
+            Ok(target)
+        } else {
+            Err(Error::Argument(
+                "invalid acl target, expected a peer id or 'ip[/prefix]'",
+                "[TARGET]",
+            ))
+        }
+    }
 }
@@ -0,0 +1,128 @@
+//! The passphrase module
+//!
+//! Where the secret of `gistit host` and `gistit fetch` comes from. Passing `--secret <SECRET>`
+//! leaks it into the shell history and `ps`, so it can also be typed at a prompt with
+//! `--secret-prompt`, read from a file or file descriptor with `--secret-file <path|/dev/fd/N>`,
+//! taken from the OS credential store with `--saved-secret <name>`, or from [`GISTIT_SECRET_VAR`]
+//! when none of those is given.
+
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+
+use clap::ArgMatches;
+use console::Term;
+
+use gistit_project::env::GISTIT_SECRET_VAR;
+
+use crate::credentials::Credential;
+use crate::fmt::PROGRESS;
+use crate::param::check;
+use crate::{Error, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source<'a> {
+    Arg(&'a str),
+    File(&'a OsStr),
+    /// Saved with `gistit auth login secret <name>`
+    Saved(&'a str),
+    Prompt,
+    Env,
+}
+
+impl<'a> Source<'a> {
+    /// The source picked in `args`, [`GISTIT_SECRET_VAR`] if it's set and none was
+    #[must_use]
+    pub fn from_args(args: &'a ArgMatches) -> Option<Self> {
+        if let Some(secret) = args.value_of("secret") {
+            Some(Self::Arg(secret))
+        } else if let Some(path) = args.value_of_os("secret-file") {
+            Some(Self::File(path))
+        } else if let Some(name) = args.value_of("saved-secret") {
+            Some(Self::Saved(name))
+        } else if args.is_present("secret-prompt") {
+            Some(Self::Prompt)
+        } else {
+            env::var_os(GISTIT_SECRET_VAR).map(|_| Self::Env)
+        }
+    }
+
+    /// Reads and checks the secret. Prompts ask twice when `confirm`, a typo while encrypting
+    /// would lock the gistit for good
+    ///
+    /// # Errors
+    ///
+    /// Fails if the secret can't be read or is invalid
+    pub async fn read(self, confirm: bool) -> Result<String> {
+        let secret = match self {
+            Self::Arg(secret) => secret.to_owned(),
+            Self::File(path) => trim_newline(fs::read_to_string(path)?),
+            Self::Saved(name) => {
+                Credential::Secret(name.to_owned())
+                    .get()?
+                    .ok_or(Error::Secret(
+                        "no secret saved under this name, see `gistit auth status`",
+                    ))?
+            }
+            Self::Env => env::var(GISTIT_SECRET_VAR)
+                .map_err(|_| Error::Secret("GISTIT_SECRET is not valid unicode"))?,
+            Self::Prompt => ask("secret", confirm).await?,
+        };
+
+        check::secret(&secret)?;
+        Ok(secret)
+    }
+}
+
+/// Asks for `what` without echoing it, twice when `confirm`
+///
+/// # Errors
+///
+/// Fails if the terminal can't be read or the answers don't match
+pub async fn ask(what: &'static str, confirm: bool) -> Result<String> {
+    tokio::task::spawn_blocking(move || PROGRESS.suspend(|| prompt(what, confirm)))
+        .await
+        .expect("prompt to not panic")
+}
+
+fn prompt(what: &str, confirm: bool) -> Result<String> {
+    let term = Term::stderr();
+    term.write_str(&format!("{}: ", what))?;
+    let answer = term.read_secure_line()?;
+
+    if confirm {
+        term.write_str(&format!("{} again: ", what))?;
+        if term.read_secure_line()? != answer {
+            return Err(Error::Secret("answers don't match"));
+        }
+    }
+    Ok(answer)
+}
+
+/// Files usually end with a newline that isn't part of the secret
+fn trim_newline(mut secret: String) -> String {
+    let len = secret.trim_end_matches(&['\n', '\r'][..]).len();
+    secret.truncate(len);
+    secret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passphrase_trim_newline() {
+        assert_eq!(trim_newline("hunter22\n".to_owned()), "hunter22");
+        assert_eq!(trim_newline("hunter22\r\n".to_owned()), "hunter22");
+        assert_eq!(trim_newline("hunter 22 ".to_owned()), "hunter 22 ");
+    }
+
+    #[tokio::test]
+    async fn passphrase_checks_secret() {
+        assert!(Source::Arg("abc").read(false).await.is_err());
+        assert_eq!(
+            Source::Arg("hunter22").read(false).await.unwrap(),
+            "hunter22"
+        );
+    }
+}
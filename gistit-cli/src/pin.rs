@@ -0,0 +1,169 @@
+//! The pin module
+//!
+//! Pinned gistits are fetched by `gistit-daemon` and kept on disk, served to other peers until
+//! unpinned. Unpinned gistits the daemon fetched are cached too, but evicted least recently used
+//! first once the storage quota, settings `[storage]` table, is reached.
+
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use clap::ArgMatches;
+use console::style;
+use indicatif::HumanBytes;
+
+use gistit_project::path;
+use gistit_proto::{ipc, Instruction};
+
+use crate::audit;
+use crate::daemon;
+use crate::dispatch::Dispatch;
+use crate::param::check;
+use crate::settings::{get_runtime_settings, Backend};
+use crate::{finish, progress, updateln, Error, Result};
+
+#[derive(Debug, Clone)]
+pub enum Action<'a> {
+    Pin(&'a str),
+    Unpin(&'a str),
+    List,
+}
+
+impl<'a> Action<'a> {
+    pub fn from_args(
+        cmd: &str,
+        args: &'a ArgMatches,
+    ) -> Result<Box<dyn Dispatch<InnerData = Config> + Send + Sync + 'a>> {
+        let hash = || {
+            args.value_of("HASH")
+                .ok_or(Error::Argument("missing argument", "[HASH]"))
+        };
+
+        let action = match (cmd, args.subcommand()) {
+            ("pin", Some(("ls", _))) => Self::List,
+            ("pin", _) => Self::Pin(hash()?),
+            _ => Self::Unpin(hash()?),
+        };
+        Ok(Box::new(action))
+    }
+}
+
+pub struct Config {
+    backend: Backend,
+    runtime_path: PathBuf,
+    config_path: PathBuf,
+}
+
+#[async_trait]
+impl<'a> Dispatch for Action<'a> {
+    type InnerData = Config;
+
+    async fn prepare(&self) -> Result<Self::InnerData> {
+        if let Self::Pin(hash) | Self::Unpin(hash) = self {
+            check::hash(hash)?;
+        }
+
+        Ok(Config {
+            backend: get_runtime_settings()?.backend(),
+            runtime_path: path::runtime_dir()?,
+            config_path: path::config()?,
+        })
+    }
+
+    async fn dispatch(&self, config: Self::InnerData) -> Result<()> {
+        let mut bridge = gistit_ipc::client(&config.runtime_path)?;
+        daemon::spawn_if_required(
+            config.backend,
+            &bridge,
+            &config.runtime_path,
+            &config.config_path,
+        )
+        .await?;
+
+        if !bridge.alive() {
+            return Err(Error::Daemon("gistit node is not running"));
+        }
+        bridge.connect_blocking()?;
+
+        match self {
+            Self::Pin(hash) | Self::Unpin(hash) => {
+                let pin = matches!(self, Self::Pin(_));
+                progress!(if pin { "Pinning" } else { "Unpinning" });
+                if !pin {
+                    audit::note(hash, audit::P2P);
+                }
+                bridge
+                    .send(Instruction::request_pin((*hash).to_owned(), pin))
+                    .await?;
+
+                if let ipc::instruction::Kind::PinResponse(ipc::instruction::PinResponse {
+                    changed,
+                }) = bridge.recv().await?.expect_response()?
+                {
+                    match (changed, pin) {
+                        (true, true) => updateln!("Pinned"),
+                        (true, false) => updateln!("Unpinned"),
+                        (false, true) => {
+                            return Err(Error::Daemon(
+                                "failed to fetch or store gistit, check gistit-daemon logs",
+                            ))
+                        }
+                        (false, false) => return Err(Error::Daemon("gistit is not pinned")),
+                    }
+                    finish!(format!("\n    hash: '{}'\n\n", style(hash).bold()));
+                }
+            }
+            Self::List => {
+                progress!("Listing pins");
+                bridge.send(Instruction::request_list_pins()).await?;
+
+                if let ipc::instruction::Kind::ListPinsResponse(
+                    ipc::instruction::ListPinsResponse { pins, used, quota },
+                ) = bridge.recv().await?.expect_response()?
+                {
+                    finish!(format_pins(&pins, used, quota));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn format_pins(pins: &[ipc::instruction::Pin], used: u64, quota: u64) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Check your system time")
+        .as_secs();
+
+    let mut lines: Vec<String> = pins
+        .iter()
+        .map(|pin| {
+            format!(
+                "    {}  {:>10}  {}",
+                style(&pin.hash).bold(),
+                HumanBytes(pin.size).to_string(),
+                style(fmt_ago(now.saturating_sub(pin.last_access))).dim()
+            )
+        })
+        .collect();
+    if lines.is_empty() {
+        lines.push("    no pinned gistits".to_owned());
+    }
+
+    format!(
+        "\n{}\n\n    storage: {} of {}\n",
+        lines.join("\n"),
+        HumanBytes(used),
+        HumanBytes(quota)
+    )
+}
+
+pub fn fmt_ago(secs: u64) -> String {
+    match secs {
+        0..=59 => "just now".to_owned(),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86_399 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86_400),
+    }
+}
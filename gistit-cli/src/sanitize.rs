@@ -0,0 +1,224 @@
+//! The sanitize module
+//!
+//! Fetched gistits are whatever their sender made them. Before one is previewed or saved, it's file
+//! names are checked to be plain relative paths and it's sizes to be within what gistit sends, see
+//! [`check`]. Content that doesn't look like the language it declares is only warned about, see
+//! [`sniff`].
+
+use std::path::Path;
+
+use gistit_project::var;
+use gistit_proto::payload::Gistit;
+use gistit_proto::Inner;
+
+use crate::archive::ARCHIVE_LANG;
+use crate::{Error, Result};
+
+/// Longest file name, or path component, most file systems don't take more
+const MAX_NAME_LENGTH: usize = 255;
+
+/// Most bytes of file data. Encrypted and archived files take more than what was sent, never twice
+/// as much
+pub const MAX_DATA_SIZE: usize = 2 * var::GISTIT_MAX_SIZE;
+
+/// Starts of content worth a warning, what it is and it's language. Content that isn't source code
+/// has none, it's warned about whatever the language declared
+const SIGNATURES: [(&str, &str, Option<&str>); 4] = [
+    ("\u{7f}ELF", "an executable", None),
+    ("%PDF-", "a pdf document", None),
+    ("<!DOCTYPE html", "an html page", Some("html")),
+    ("<?php", "a php script", Some("php")),
+];
+
+/// Checks `name`, a path relative to where the gistit is saved, is made of plain components
+///
+/// # Errors
+///
+/// Fails naming the first problem found
+pub fn name(name: &str) -> Result<()> {
+    let unsafe_name = |reason: &str| Err(Error::Unsafe(format!("file {:?} {}", name, reason)));
+
+    if name.is_empty() {
+        return unsafe_name("has no name");
+    }
+    if name.chars().any(char::is_control) {
+        return unsafe_name("has control characters in it's name");
+    }
+    if name.starts_with('/') || name.contains('\\') || Path::new(name).has_root() {
+        return unsafe_name("has an absolute path or a backslash");
+    }
+    for component in name.split('/') {
+        if component == ".." {
+            return unsafe_name("points outside of where it's saved");
+        }
+        if component.len() > MAX_NAME_LENGTH {
+            return unsafe_name("has a name too long");
+        }
+    }
+    // `C:foo` is relative to the current directory of drive `C` on Windows
+    if name.contains(':') && cfg!(windows) {
+        return unsafe_name("has a drive in it's name");
+    }
+    Ok(())
+}
+
+/// Checks the names and sizes of every file of `gistit`
+///
+/// # Errors
+///
+/// Fails naming the first problem found
+pub fn check(gistit: &Gistit) -> Result<()> {
+    if gistit.inner.is_empty() {
+        return Err(Error::Unsafe("it has no files".to_owned()));
+    }
+
+    let mut total = 0;
+    for inner in &gistit.inner {
+        name(&inner.name)?;
+        if inner.size as usize > MAX_DATA_SIZE {
+            return Err(Error::Unsafe(format!(
+                "file {:?} declares a size of {} bytes",
+                inner.name, inner.size
+            )));
+        }
+        total += inner.data.len();
+    }
+
+    if total > MAX_DATA_SIZE {
+        return Err(Error::Unsafe(format!(
+            "it holds {} bytes, gistits are at most {}",
+            total,
+            var::GISTIT_MAX_SIZE
+        )));
+    }
+    Ok(())
+}
+
+/// What the content of `inner` looks like, if it isn't the language it declares. Encrypted and
+/// binary files and archives are skipped
+#[must_use]
+pub fn sniff(inner: &Inner) -> Option<String> {
+    if inner.encrypted || inner.binary || inner.lang == ARCHIVE_LANG {
+        return None;
+    }
+
+    // Plain text could be anything, source declared as another language is suspicious
+    let mismatch = |lang: &str| inner.lang != "text" && inner.lang != lang;
+
+    let start = inner.data.trim_start();
+    if let Some((_, what, lang)) = SIGNATURES
+        .iter()
+        .find(|(signature, ..)| start.starts_with(signature))
+    {
+        return lang.map_or(true, mismatch).then(|| (*what).to_owned());
+    }
+
+    let interpreter = interpreter(inner.data.lines().next()?)?;
+    let lang = match interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.') {
+        "sh" | "bash" | "zsh" | "ksh" | "dash" => "bash",
+        "python" => "python",
+        "node" => "javascript",
+        "perl" => "perl",
+        "ruby" => "ruby",
+        "pwsh" => "powershell",
+        _ => return None,
+    };
+    mismatch(lang).then(|| format!("a {} script", interpreter))
+}
+
+/// The program of a `#!` line, `/usr/bin/env python3` gives `python3`
+fn interpreter(line: &str) -> Option<&str> {
+    let mut words = line.strip_prefix("#!")?.split_whitespace();
+    let program = words.next()?.rsplit('/').next()?;
+
+    if program == "env" {
+        words.find(|word| !word.starts_with('-'))
+    } else {
+        Some(program)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inner(name: &str, lang: &str, data: &str) -> Inner {
+        Gistit::new_inner(
+            name.to_owned(),
+            lang.to_owned(),
+            data.len() as u32,
+            data.to_owned(),
+        )
+    }
+
+    #[test]
+    fn sanitize_names() {
+        assert!(name("main.rs").is_ok());
+        assert!(name("src/main.rs").is_ok());
+        assert!(name("./main.rs").is_ok());
+        assert!(name(".gitignore").is_ok());
+
+        assert!(name("").is_err());
+        assert!(name("../main.rs").is_err());
+        assert!(name("src/../../main.rs").is_err());
+        assert!(name("/etc/passwd").is_err());
+        assert!(name("..\\main.rs").is_err());
+        assert!(name("main\u{1b}[2J.rs").is_err());
+        assert!(name("main\n.rs").is_err());
+        assert!(name(&"a".repeat(MAX_NAME_LENGTH + 1)).is_err());
+    }
+
+    #[test]
+    fn sanitize_no_files() {
+        assert!(matches!(check(&Gistit::default()), Err(Error::Unsafe(_))));
+    }
+
+    #[test]
+    fn sanitize_sizes() {
+        let mut gistit = Gistit::default();
+        gistit.inner.push(inner("main.rs", "rust", "fn main() {}"));
+        assert!(check(&gistit).is_ok());
+
+        gistit.inner[0].size = u32::MAX;
+        assert!(check(&gistit).is_err());
+
+        gistit.inner[0] = inner("big.txt", "text", &"a".repeat(MAX_DATA_SIZE + 1));
+        assert!(check(&gistit).is_err());
+
+        gistit.inner[0] = inner("../main.rs", "rust", "fn main() {}");
+        assert!(check(&gistit).is_err());
+    }
+
+    #[test]
+    fn sanitize_sniff() {
+        assert_eq!(sniff(&inner("main.rs", "rust", "fn main() {}")), None);
+        assert_eq!(
+            sniff(&inner("main.rs", "rust", "\u{7f}ELF\u{2}\u{1}")).as_deref(),
+            Some("an executable")
+        );
+        assert_eq!(
+            sniff(&inner("notes.md", "markdown", "\n<!DOCTYPE html><html>")).as_deref(),
+            Some("an html page")
+        );
+        assert_eq!(sniff(&inner("index.php", "php", "<?php echo 1;")), None);
+        assert_eq!(
+            sniff(&inner("main.rs", "rust", "<?php echo 1;")).as_deref(),
+            Some("a php script")
+        );
+        assert_eq!(
+            sniff(&inner("notes.txt", "text", "%PDF-1.4")).as_deref(),
+            Some("a pdf document")
+        );
+
+        assert_eq!(sniff(&inner("run.sh", "bash", "#!/bin/sh\necho")), None);
+        assert_eq!(sniff(&inner("run", "text", "#!/bin/sh\necho")), None);
+        assert_eq!(
+            sniff(&inner("main.rs", "rust", "#!/usr/bin/env python3\nprint()")).as_deref(),
+            Some("a python3 script")
+        );
+        assert_eq!(
+            sniff(&inner("lib.py", "python", "#!/usr/bin/env -S python3.10")),
+            None
+        );
+    }
+}
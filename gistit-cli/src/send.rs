@@ -2,142 +2,421 @@ use std::ffi::OsStr;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::str;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use async_trait::async_trait;
 use clap::ArgMatches;
 use console::style;
+use indicatif::HumanBytes;
+use notify::{watcher, DebouncedEvent, RecursiveMode, Watcher};
+use rand::{distributions::Alphanumeric, Rng};
 use reqwest::StatusCode;
 
-use gistit_proto::payload::{hash, Gistit};
+use gistit_proto::payload::Gistit;
 use gistit_proto::prost::Message;
 use gistit_proto::{ipc, Instruction};
 
+use gistit_core::{secret, SHARE_URL_BASE};
+use gistit_ipc::{Bridge, Client};
 use gistit_project::path;
 
+use crate::archive::{self, ARCHIVE_LANG};
+use crate::audit;
+use crate::cache::{self, Entry, Lookup, Origin, ResponseKind};
 use crate::clipboard::Clipboard;
+use crate::credentials::Credential;
+use crate::daemon;
+use crate::describe;
 use crate::dispatch::Dispatch;
-use crate::file::File;
+use crate::embed::Embed;
+use crate::file::{self, File, BINARY_LANG};
 use crate::github::{self, CreateResponse, GITHUB_GISTS_API_URL};
+use crate::headless::{self, Sent};
+use crate::mirror::{self, Target};
+use crate::name;
 use crate::param::check;
-use crate::server::SERVER_URL_LOAD;
-use crate::{errorln, finish, interruptln, progress, updateln, warnln, Error, Result};
+use crate::passphrase;
+use crate::server::{self, Feature, Limits, SERVER_URL_BASE};
+use crate::settings::{get_runtime_settings, Backend, Visibility};
+use crate::tmux;
+use crate::workspace::Workspace;
+use crate::{finish, progress, updateln, warnln, Error, Result};
+
+/// File changes within this interval are sent once
+const WATCH_DEBOUNCE: Duration = Duration::from_secs(1);
+
+/// Length of the owner token generated for private gistits
+const OWNER_TOKEN_LENGTH: usize = 48;
+
+/// Characters of file data printed with `--dry-run`
+const DRY_RUN_PREVIEW_LENGTH: usize = 80;
+
+/// Length of the secret generated with `--gen-secret`, alphanumeric so it fits a link as is
+const GENERATED_SECRET_LENGTH: usize = 32;
 
 #[derive(Debug, Clone)]
-pub struct Action {
-    pub file_path: Option<&'static OsStr>,
+pub struct Action<'a> {
+    pub file_path: Option<&'a OsStr>,
     pub maybe_stdin: Option<String>,
-    pub description: Option<&'static str>,
-    pub author: &'static str,
+    /// Name of the content sent from stdin
+    pub file_name: Option<&'a str>,
+    /// Scrollback of this tmux pane is sent, `Some(None)` for the current one
+    pub tmux_pane: Option<Option<&'a str>>,
+    pub description: Option<&'a str>,
+    pub author: Option<&'a str>,
     pub clipboard: bool,
     pub github: bool,
+    pub announce: Vec<&'a str>,
+    pub to_peer: Option<&'a str>,
+    pub watch: bool,
+    pub name: Option<&'a str>,
+    pub embed: Option<&'a str>,
+    pub private: bool,
+    pub visibility: Option<&'a str>,
+    pub burn_after_read: bool,
+    pub max_reads: Option<&'a str>,
+    pub lifespan: Option<&'a str>,
+    pub mirror: Vec<&'a str>,
+    pub archive: Option<&'a str>,
+    pub parent: Option<&'a str>,
+    pub force: bool,
+    pub secret: Option<passphrase::Source<'a>>,
+    pub gen_secret: bool,
+    pub encrypt_metadata: bool,
+    pub no_workspace: bool,
+    pub dry_run: bool,
 }
 
-impl Action {
+impl<'a> Action<'a> {
     pub fn from_args(
-        args: &'static ArgMatches,
+        args: &'a ArgMatches,
         maybe_stdin: Option<String>,
-    ) -> Result<Box<dyn Dispatch<InnerData = Config> + Send + Sync + 'static>> {
+    ) -> Result<Box<dyn Dispatch<InnerData = Config<'a>> + Send + Sync + 'a>> {
         Ok(Box::new(Self {
             file_path: args.value_of_os("FILE"),
             maybe_stdin,
+            file_name: args.value_of("file-name"),
+            tmux_pane: args
+                .is_present("tmux-pane")
+                .then(|| args.value_of("tmux-pane")),
             description: args.value_of("description"),
-            author: args
-                .value_of("author")
-                .ok_or(Error::Argument("missing argument", "--author"))?,
+            author: args.value_of("author"),
             clipboard: args.is_present("clipboard"),
             github: args.is_present("github"),
+            announce: args
+                .values_of("announce")
+                .map(Iterator::collect)
+                .unwrap_or_default(),
+            to_peer: args.value_of("to-peer"),
+            watch: args.is_present("watch"),
+            name: args.value_of("name"),
+            embed: args.value_of("embed"),
+            private: args.is_present("private"),
+            visibility: args.value_of("visibility"),
+            burn_after_read: args.is_present("burn-after-read"),
+            max_reads: args.value_of("max-reads"),
+            lifespan: args.value_of("lifespan"),
+            mirror: args
+                .values_of("mirror")
+                .map(Iterator::collect)
+                .unwrap_or_default(),
+            archive: args.value_of("archive"),
+            parent: args.value_of("parent"),
+            force: args.is_present("force"),
+            // `GISTIT_SECRET` is meant for fetching, it would encrypt every gistit sent otherwise
+            secret: passphrase::Source::from_args(args)
+                .filter(|source| *source != passphrase::Source::Env),
+            gen_secret: args.is_present("gen-secret"),
+            encrypt_metadata: args.is_present("encrypt-metadata"),
+            no_workspace: args.is_present("no-workspace"),
+            dry_run: args.is_present("dry-run"),
         }))
     }
 }
 
 #[derive(Debug)]
-pub struct Config {
+pub struct Config<'a> {
     file: File,
-    author: &'static str,
-    description: Option<&'static str>,
+    /// Name of the archive a directory was packed into, `file` holds it encoded
+    archive_name: Option<String>,
+    /// Language sent instead of the one picked by the file extension
+    lang: Option<&'static str>,
+    author: String,
+    /// Given with `--description` or taken from the file
+    description: Option<String>,
     clipboard: bool,
     github_token: Option<github::Token>,
+    topics: Vec<String>,
+    /// Topic of the workspace in use, announced on along with `topics`
+    workspace_topic: Option<String>,
+    to_peer: Option<&'a str>,
+    /// The file to send again whenever it changes
+    watch_path: Option<PathBuf>,
+    name: Option<&'a str>,
+    embed: Option<Embed>,
+    visibility: Visibility,
+    /// Generated for private gistits, the server only answers them with it
+    owner_token: Option<String>,
+    /// Deleted by the server or `gistit-daemon` once fetched
+    burn_after_read: bool,
+    /// Deleted by the server or `gistit-daemon` after this many fetches
+    max_reads: Option<u32>,
+    /// Seconds the server keeps it, it's default lifespan if unset
+    lifespan: Option<u64>,
+    /// Fetched from the server to check `lifespan`
+    limits: Option<Limits>,
+    /// Targets to send to at once, ignoring the backend
+    mirror: Vec<Target>,
+    /// Hash of the revision this one replaces
+    parent: Option<&'a str>,
+    /// Sends it even if the server or `gistit-daemon` has it already
+    force: bool,
+    /// Encrypts it before it leaves this machine, the server or peers only get the ciphertext
+    secret: Option<String>,
+    /// `secret` was generated with `--gen-secret`, it's printed in the fragment of the link
+    gen_secret: bool,
+    /// Seals the author, description and file name with `secret` too
+    encrypt_metadata: bool,
+    backend: Backend,
     runtime_path: PathBuf,
+    config_path: PathBuf,
 }
 
-impl TryFrom<Config> for Gistit {
+impl TryFrom<Config<'_>> for Gistit {
     type Error = Error;
 
-    #[allow(clippy::cast_possible_truncation)]
-    fn try_from(value: Config) -> std::result::Result<Self, Self::Error> {
-        let data = value.file.read()?;
-        let hash = hash(value.author, value.description, &data);
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("Check your system time")
-            .as_millis()
-            .to_string();
-
-        let inner = Self::new_inner(
-            value.file.name(),
-            value.file.lang().to_owned(),
-            value.file.size() as u32,
-            data,
-        );
+    fn try_from(value: Config<'_>) -> std::result::Result<Self, Self::Error> {
+        let mut gistit = new_gistit(&value.file, value.author, value.description.as_deref())?;
+        // Not part of the hash, only tells fetching how to open it
+        if let (Some(name), Some(inner)) = (value.archive_name, gistit.inner.first_mut()) {
+            inner.name = name;
+            inner.lang = ARCHIVE_LANG.to_owned();
+        }
+        if let (Some(lang), Some(inner)) = (value.lang, gistit.inner.first_mut()) {
+            inner.lang = lang.to_owned();
+        }
+        gistit.set_visibility(value.visibility.into());
+        gistit.token = value.owner_token;
+        gistit.burn_after_read = value.burn_after_read;
+        gistit.max_reads = value.max_reads;
+        gistit.lifespan = value.lifespan;
+        gistit.parent = value.parent.map(ToOwned::to_owned);
+        if let Some(ref secret) = value.secret {
+            progress!("Encrypting");
+            secret::seal(&mut gistit, secret)?;
+            if value.encrypt_metadata {
+                secret::seal_metadata(&mut gistit, secret)?;
+            }
+            updateln!("Encrypted");
+        }
+        Ok(gistit)
+    }
+}
 
-        let gistit = Self::new(
-            hash,
-            value.author.to_owned(),
-            value.description.map(ToOwned::to_owned),
-            now,
-            vec![inner],
-        );
+/// A gistit of `file`, hashed over it's content, author and description. Binary files are sent
+/// base64 encoded
+pub fn new_gistit(file: &File, author: String, description: Option<&str>) -> Result<Gistit> {
+    let (data, binary) = file.read_encoded()?;
+    let lang = if binary {
+        check::binary_size(&data)?;
+        BINARY_LANG
+    } else {
+        file.lang()
+    };
 
-        Ok(gistit)
+    let mut gistit = gistit_core::new_gistit(
+        &file.name(),
+        lang,
+        data,
+        author,
+        description.map(ToOwned::to_owned),
+    );
+    // Not part of the hash, only tells fetching how to open it
+    if let Some(inner) = gistit.inner.first_mut() {
+        inner.binary = binary;
     }
+    Ok(gistit)
 }
 
 #[async_trait]
-impl Dispatch for Action {
-    type InnerData = Config;
+impl<'a> Dispatch for Action<'a> {
+    type InnerData = Config<'a>;
 
     async fn prepare(&self) -> Result<Self::InnerData> {
         progress!("Preparing");
+        let mut archive_name = None;
+        let mut lang = None;
         let file = if let Some(file_ostr) = self.file_path {
             let path = Path::new(file_ostr);
             let attr = fs::metadata(&path)?;
-            let maybe_extension = path.extension();
 
-            check::metadata(&attr)?;
-            check::extension(maybe_extension)?;
+            match (attr.is_dir(), self.archive) {
+                (true, Some(format)) => {
+                    let (name, data) = archive::pack(path, archive::Format::parse(format)?)?;
+                    let file = File::from_data(check::archive_size(&data)?, &name)?;
+                    archive_name = Some(name);
+                    file
+                }
+                (true, None) => {
+                    return Err(Error::Argument(
+                        "directories are sent packed, pick a format with `--archive`",
+                        "[FILE]",
+                    ))
+                }
+                (false, Some(_)) => {
+                    return Err(Error::Argument(
+                        "only directories are archived",
+                        "--archive",
+                    ))
+                }
+                (false, None) => {
+                    let maybe_extension = path.extension();
+
+                    check::metadata(&attr)?;
+                    // Binary files are sent whatever their extension
+                    if !file::is_binary_file(path)? {
+                        check::extension(maybe_extension)?;
+                    }
 
-            File::from_path(path)?
+                    File::from_path(path)?
+                }
+            }
+        } else if let Some(target) = self.tmux_pane {
+            progress!("Capturing tmux pane");
+            let scrollback = tmux::capture(target)?;
+            lang = Some(tmux::CONSOLE_LANG);
+            File::from_data(scrollback, tmux::CAPTURE_NAME)?
         } else if let Some(ref stdin) = self.maybe_stdin {
-            File::from_data(stdin, "stdin")?
+            let name = self.file_name.map(check::file_name).transpose()?;
+            File::from_data(stdin, name.unwrap_or("stdin"))?
         } else {
             return Err(Error::Argument("missing file input", "[FILE]/[STDIN]"));
         };
 
-        let author = check::author(self.author)?;
+        let settings = get_runtime_settings()?;
+        let author = match (self.author, settings.author.as_deref()) {
+            (Some(author), _) | (None, Some(author)) => check::author(author)?.to_owned(),
+            (None, None) => random_author(),
+        };
         let description = if let Some(value) = self.description {
-            Some(check::description(value)?)
+            Some(check::description(value)?.to_owned())
+        } else if settings.auto_description() && archive_name.is_none() {
+            let described = describe::from_path(file.path(), file.lang());
+            tracing::debug!(?described, "description left out");
+            described
         } else {
             None
         };
         updateln!("Prepared");
 
-        let github_token = if self.github {
+        // Gistits for GitHub aren't encrypted
+        let workspace = if self.no_workspace || self.github {
+            None
+        } else {
+            Workspace::active(&settings)?
+        };
+
+        let mut topics = self
+            .announce
+            .iter()
+            .map(|topic| check::topic(topic).map(ToOwned::to_owned))
+            .collect::<Result<Vec<String>>>()?;
+        let workspace_topic = workspace.as_ref().map(|workspace| workspace.topic.clone());
+        if let Some(ref topic) = workspace_topic {
+            if !topics.contains(topic) {
+                topics.push(topic.clone());
+            }
+        }
+
+        let watch_path = match (self.watch, self.file_path) {
+            (true, Some(file_ostr)) => Some(PathBuf::from(file_ostr)),
+            (true, None) => return Err(Error::Argument("can't watch stdin", "--watch")),
+            (false, _) => None,
+        };
+
+        let visibility = match (self.private, self.visibility) {
+            (true, _) => Visibility::Private,
+            (false, Some(visibility)) => check::visibility(visibility)?,
+            (false, None) => settings.visibility(),
+        };
+        let owner_token = (visibility == Visibility::Private).then(new_owner_token);
+
+        let secret = match (self.gen_secret, self.secret) {
+            (true, _) => Some(new_secret()),
+            (false, Some(source)) => Some(source.read(true).await?),
+            // A secret given explicitly wins over the workspace key
+            (false, None) => workspace.map(|workspace| workspace.key),
+        };
+        if self.encrypt_metadata && secret.is_none() {
+            return Err(Error::Argument(
+                "metadata is sealed with the secret, pass one with `--secret-prompt` or `--gen-secret`",
+                "--encrypt-metadata",
+            ));
+        }
+
+        let mut mirror = Vec::new();
+        for target in &self.mirror {
+            let target = Target::parse(target)?;
+            if !mirror.contains(&target) {
+                mirror.push(target);
+            }
+        }
+        if mirror.contains(&Target::P2p) && visibility == Visibility::Private {
+            return Err(Error::Argument(
+                "private gistits are only sent to the server, they can't be mirrored to the p2p network",
+                "--mirror",
+            ));
+        }
+
+        // Gistits for the server are checked against what it supports before anything is sent,
+        // it's only asked otherwise when there's a lifespan to check
+        let to_server = match (self.to_peer, mirror.is_empty()) {
+            (Some(_), _) => false,
+            (None, false) => mirror.contains(&Target::Server),
+            (None, true) => {
+                let daemon_alive = gistit_ipc::client(&path::runtime_dir()?)?.alive();
+                !settings.backend().use_daemon(daemon_alive).unwrap_or(true)
+            }
+        };
+        // Hosted gistits don't expire, the settings lifespan is only for the server
+        let lifespan = self
+            .lifespan
+            .or_else(|| settings.lifespan.as_deref().filter(|_| to_server));
+        let capabilities = if to_server || lifespan.is_some() {
+            Some(server::capabilities().await)
+        } else {
+            None
+        };
+        if let (true, Some(capabilities)) = (to_server, &capabilities) {
+            capabilities.check_send(file.size())?;
+            if visibility == Visibility::Private {
+                capabilities.require(Feature::Private)?;
+            }
+            if self.burn_after_read || self.max_reads.is_some() {
+                capabilities.require(Feature::ReadLimit)?;
+            }
+            if self.parent.is_some() {
+                capabilities.require(Feature::Revisions)?;
+            }
+            if file::is_binary_file(file.path())? {
+                capabilities.require(Feature::Binary)?;
+            }
+        }
+        let limits = capabilities.map(|capabilities| capabilities.limits);
+        let lifespan = lifespan
+            .zip(limits)
+            .map(|(lifespan, limits)| check::lifespan(lifespan, &limits))
+            .transpose()?;
+
+        // Nothing is posted on a dry run, there's no need to log in
+        let github_token = if self.github && !self.dry_run {
             progress!("Authorizing");
             let mut oauth = github::Oauth::new()?;
 
             if oauth.token().is_none() {
-                if let Err(url) = oauth.authorize() {
-                    warnln!(
-                        "failed to open your web browser. \n\nAuthorize manually: '{}'",
-                        style(url).cyan()
-                    );
-                }
-                oauth.poll_token().await?;
-                warnln!(
-                    "storing github token at: '{}'",
-                    gistit_project::path::config()?.to_string_lossy()
-                );
+                oauth.login().await?;
             }
             updateln!("Authorized");
             oauth.token
@@ -147,34 +426,120 @@ impl Dispatch for Action {
 
         Ok(Config {
             file,
+            archive_name,
+            lang,
             description,
             author,
             clipboard: self.clipboard,
             github_token,
-            runtime_path: path::runtime()?,
+            topics,
+            workspace_topic,
+            to_peer: self.to_peer.map(check::peer_id).transpose()?,
+            watch_path,
+            name: self.name.map(check::name).transpose()?,
+            embed: self.embed.map(Embed::parse).transpose()?,
+            visibility,
+            owner_token,
+            burn_after_read: self.burn_after_read,
+            max_reads: self.max_reads.map(check::max_reads).transpose()?,
+            lifespan,
+            limits,
+            mirror,
+            parent: self.parent.map(check::hash).transpose()?,
+            force: self.force,
+            secret,
+            gen_secret: self.gen_secret,
+            encrypt_metadata: self.encrypt_metadata,
+            backend: settings.backend(),
+            runtime_path: path::runtime_dir()?,
+            config_path: path::config()?,
         })
     }
 
     #[allow(clippy::too_many_lines)]
     async fn dispatch(&self, config: Self::InnerData) -> Result<()> {
         let clipboard = config.clipboard;
+        let embed = config.embed;
+        // Only a generated secret is printed, a given one is for the sender to share
+        let secret = config.secret.clone().filter(|_| config.gen_secret);
+
+        // Pushing to a peer always goes through the p2p network
+        let backend = if config.to_peer.is_some() {
+            Backend::P2p
+        } else {
+            config.backend
+        };
 
         let mut bridge = gistit_ipc::client(&config.runtime_path)?;
-        if bridge.alive() {
+        if self.dry_run {
+            // `gistit-daemon` would be spawned if it's required and isn't running
+            let use_daemon = backend.use_daemon(bridge.alive()).unwrap_or(true);
+            return dry_run(config, use_daemon, self.github);
+        }
+        daemon::spawn_if_required(backend, &bridge, &config.runtime_path, &config.config_path)
+            .await?;
+
+        if let Some(path) = config.watch_path.clone() {
+            return watch(&mut bridge, config, &path).await;
+        }
+
+        if let Some(peer_id) = config.to_peer {
+            check_hostable(config.visibility)?;
+            let gistit: Gistit = config.try_into()?;
+            audit::note(&gistit.hash, &audit::peer(peer_id));
+            return push(&mut bridge, gistit, peer_id).await;
+        }
+
+        if !config.mirror.is_empty() {
+            return mirror(&mut bridge, config).await;
+        }
+
+        if config.backend.use_daemon(bridge.alive())? {
             // Daemon is running, hosting with p2p
+            check_hostable(config.visibility)?;
+            if config.lifespan.is_some() {
+                warnln!("lifespans are only used by the server, hosted gistits don't expire");
+            }
+            if embed.is_some() {
+                warnln!(
+                    "`--embed` links to the web viewer, it's only used when sending to the server"
+                );
+            }
             progress!("Hosting");
+            let topics = config.topics.clone();
+            let force = config.force;
             let gistit: Gistit = config.try_into()?;
+            let name = first_file_name(&gistit);
+            let author = gistit.author.clone();
+            audit::note(&gistit.hash, audit::P2P);
 
             bridge.connect_blocking()?;
-            bridge.send(Instruction::request_provide(gistit)).await?;
+            let hosted = !force
+                && topics.is_empty()
+                && dedupable(&gistit)
+                && hosting(&mut bridge, &gistit.hash).await?;
+            let maybe_hash = if hosted {
+                Some(gistit.hash)
+            } else {
+                bridge
+                    .send(Instruction::request_provide(gistit, topics))
+                    .await?;
+
+                match bridge.recv().await?.expect_response()? {
+                    ipc::instruction::Kind::ProvideResponse(
+                        ipc::instruction::ProvideResponse { hash },
+                    ) => hash,
+                    _ => None,
+                }
+            };
+
+            if let Some(hash) = maybe_hash {
+                cache::record_or_warn(Entry::new(&hash, &name, &author, Origin::Hosted));
+                let link = secret.as_deref().map(|secret| key_link(&hash, secret));
 
-            if let ipc::instruction::Kind::ProvideResponse(ipc::instruction::ProvideResponse {
-                hash: Some(hash),
-            }) = bridge.recv().await?.expect_response()?
-            {
                 if clipboard {
-                    Clipboard::new(&hash)
-                        .try_into_selected()?
+                    Clipboard::new(link.as_deref().unwrap_or(&hash))
+                        .into_selected()
                         .into_provider()
                         .set_contents()?;
                 }
@@ -185,20 +550,61 @@ impl Dispatch for Action {
                     "".to_string()
                 };
 
-                updateln!("Hosted");
+                if headless::enabled() {
+                    headless::report_sent(&Sent {
+                        hash,
+                        backend: "p2p",
+                        secret,
+                        skipped: hosted,
+                        ..Sent::default()
+                    });
+                    return Ok(());
+                }
+
+                updateln!(if hosted {
+                    "Already hosted, skipped providing it again"
+                } else {
+                    "Hosted"
+                });
+                let link = link.map_or_else(String::new, |link| {
+                    format!("    link: '{}'\n", style(link).bold())
+                });
                 finish!(format!(
-                    "\n    hash: '{}' {}\n\n",
+                    "\n    hash: '{}' {}\n{}\n",
                     style(hash).bold(),
-                    style(clipboard_msg).italic().dim()
+                    style(clipboard_msg).italic().dim(),
+                    link
                 ));
             } else {
-                interruptln!();
-                errorln!("failed to provide gistit, check gistit-daemon logs");
+                return Err(Error::Daemon(
+                    "failed to provide gistit, check gistit-daemon logs",
+                ));
             }
         } else {
+            // The workspace topic isn't asked for, nothing to warn about
+            if config
+                .topics
+                .iter()
+                .any(|topic| Some(topic) != config.workspace_topic.as_ref())
+            {
+                warnln!("`--announce` is only used when hosting through the p2p network");
+            }
+
             progress!("Sending");
+            let limits = match config.limits {
+                Some(limits) => limits,
+                None => server::limits().await,
+            };
             let maybe_github_token = config.github_token.as_ref().map(Clone::clone);
+            let force = config.force;
             let gistit: Gistit = config.try_into()?;
+            audit::note(&gistit.hash, SERVER_URL_BASE.as_str());
+            if maybe_github_token.is_some() && gistit.token.is_some() {
+                warnln!("github gists are public, only the gistit itself is private");
+            }
+            if maybe_github_token.is_some() && gistit.read_limit().is_some() {
+                warnln!("github gists are kept, only the gistit itself is deleted after reading");
+            }
 
             let maybe_gist = if let Some(token) = maybe_github_token {
                 // Github flag was provided, sending to Github Gists
@@ -207,7 +613,7 @@ impl Dispatch for Action {
                 let name = &inner.name;
                 let description = gistit.description.as_deref().unwrap_or("");
 
-                let response = reqwest::Client::new()
+                let response = gistit_core::http_client()
                     .post(GITHUB_GISTS_API_URL)
                     .header("user-agent", "gistit")
                     .header("authorization", format!("token {}", token.access_token))
@@ -227,6 +633,7 @@ impl Dispatch for Action {
                 match response.status() {
                     StatusCode::CREATED => {
                         let data: CreateResponse = response.json().await?;
+                        audit::note(&gistit.hash, &data.url);
                         Some(data.url)
                     }
                     StatusCode::FORBIDDEN | StatusCode::UNPROCESSABLE_ENTITY => {
@@ -245,50 +652,637 @@ impl Dispatch for Action {
                 None
             };
 
-            let response = reqwest::Client::new()
-                .post(SERVER_URL_LOAD.to_string())
-                .header("content-type", "application/x-protobuf")
-                .body(gistit.encode_to_vec())
-                .send()
-                .await?;
-
-            match response.status() {
-                StatusCode::OK => {
-                    let server_hash = Gistit::from_bytes(response.bytes().await?)?.hash;
-
-                    if clipboard {
-                        Clipboard::new(&server_hash)
-                            .try_into_selected()?
-                            .into_provider()
-                            .set_contents()?;
-                    }
-                    updateln!("Sent");
-
-                    let clipboard_msg = if self.clipboard {
-                        style("(copied to clipboard)").italic().dim().to_string()
-                    } else {
-                        "".to_string()
-                    };
-
-                    let gist = maybe_gist.map_or_else(
-                        || "".to_string(),
-                        |gist_url| format!("github gist: '{}'\n", gist_url),
-                    );
-
-                    finish!(format!(
-                        "\n    hash: '{}' {} \n    url: 'https://gistit.vercel.app/h/{}' \n    {}\n\n",
-                        style(&server_hash).bold(),
-                        clipboard_msg,
-                        style(&server_hash).bold(),
-                        gist
-                    ));
+            let shared = !force && dedupable(&gistit) && exists_server(&gistit.hash).await;
+            let server_hash = if shared {
+                gistit.hash.clone()
+            } else {
+                send_server(&gistit).await?
+            };
+            if dedupable(&gistit) {
+                record_exists(&server_hash);
+            }
+            cache::record_or_warn(Entry::new(
+                &server_hash,
+                &first_file_name(&gistit),
+                &gistit.author,
+                Origin::Sent,
+            ));
+            let token = gistit.token.as_deref().map_or_else(String::new, |token| {
+                save_owner_token(&server_hash, token);
+                format!("token: '{}'\n    ", style(token).bold())
+            });
+
+            let link = secret
+                .as_deref()
+                .map(|secret| key_link(&share_url(&server_hash), secret));
+
+            if clipboard {
+                Clipboard::new(link.as_deref().unwrap_or(&server_hash))
+                    .into_selected()
+                    .into_provider()
+                    .set_contents()?;
+            }
+            updateln!(if shared {
+                "Already on the server, skipped sending it again"
+            } else {
+                "Sent"
+            });
+
+            let clipboard_msg = if self.clipboard {
+                style("(copied to clipboard)").italic().dim().to_string()
+            } else {
+                "".to_string()
+            };
+
+            let gist = maybe_gist.map_or_else(
+                || "".to_string(),
+                |gist_url| format!("github gist: '{}'\n", gist_url),
+            );
+
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Check your system time")
+                .as_secs();
+            let expires = if shared {
+                "as when it was first sent".to_owned()
+            } else {
+                server::fmt_expiry(gistit.lifespan, &limits, now)
+            };
+
+            if headless::enabled() {
+                headless::report_sent(&Sent {
+                    url: Some(share_url(&server_hash)),
+                    raw: Some(raw_url(&server_hash)),
+                    expires: Some(expires),
+                    token: gistit.token.clone(),
+                    secret,
+                    skipped: shared,
+                    backend: "server",
+                    hash: server_hash,
+                });
+                return Ok(());
+            }
+
+            let link = link.map_or_else(String::new, |link| {
+                format!("link: '{}'\n    ", style(link).bold())
+            });
+
+            finish!(format!(
+                "\n    hash: '{}' {} \n    url: '{}' \n    raw: '{}' \n    expires: {}\n    {}{}{}\n\n",
+                style(&server_hash).bold(),
+                clipboard_msg,
+                style(share_url(&server_hash)).bold(),
+                raw_url(&server_hash),
+                expires,
+                link,
+                token,
+                gist
+            ));
+
+            if let Some(embed) = embed {
+                println!("{}", embed.render(&gistit, &share_url(&server_hash)));
+            }
+        };
+        Ok(())
+    }
+}
+
+/// Prints where the gistit would go, it's hash and size and the payload itself with the file data
+/// cut short, without sending anything
+fn dry_run(config: Config<'_>, use_daemon: bool, github: bool) -> Result<()> {
+    let mut targets = Vec::new();
+    if let Some(peer_id) = config.to_peer {
+        check_hostable(config.visibility)?;
+        targets.push(format!("{}, through gistit-daemon", audit::peer(peer_id)));
+    } else if !config.mirror.is_empty() {
+        for target in &config.mirror {
+            targets.push(match target {
+                Target::Server => format!("server: {}load", *SERVER_URL_BASE),
+                Target::P2p => "p2p: through gistit-daemon".to_owned(),
+            });
+        }
+    } else if use_daemon {
+        check_hostable(config.visibility)?;
+        targets.push("p2p: through gistit-daemon".to_owned());
+    } else {
+        targets.push(format!("server: {}load", *SERVER_URL_BASE));
+        if github {
+            targets.push(format!("github: {}", GITHUB_GISTS_API_URL));
+        }
+    }
+    // Only announced when hosting through the p2p network
+    if use_daemon || config.mirror.contains(&Target::P2p) {
+        for topic in &config.topics {
+            targets.push(format!("announced on: {}", topic));
+        }
+    }
+
+    let gistit: Gistit = config.try_into()?;
+    let size = gistit.encoded_len();
+    // Salted, a real send gets another hash
+    let hash_note = if secret::is_encrypted(&gistit) {
+        style(" (encrypted, changes on every send)")
+            .dim()
+            .to_string()
+    } else {
+        String::new()
+    };
+
+    updateln!("Dry run, nothing was sent");
+    let targets: String = targets
+        .iter()
+        .map(|target| format!("\n      {}", target))
+        .collect();
+    finish!(format!(
+        "\n    hash: '{}'{}\n    size: {}\n    to:{}\n\n",
+        style(&gistit.hash).bold(),
+        hash_note,
+        HumanBytes(size as u64),
+        targets
+    ));
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&dry_run_payload(&gistit))?
+    );
+    Ok(())
+}
+
+/// `gistit` as sent, with file data and sealed metadata cut short
+fn dry_run_payload(gistit: &Gistit) -> serde_json::Value {
+    let inner: Vec<serde_json::Value> = gistit
+        .inner
+        .iter()
+        .map(|inner| {
+            serde_json::json!({
+                "name": inner.name,
+                "lang": inner.lang,
+                "size": inner.size,
+                "data": elide(&inner.data),
+                "encrypted": inner.encrypted,
+            })
+        })
+        .collect();
+
+    serde_json::json!({
+        "hash": gistit.hash,
+        "author": gistit.author,
+        "description": gistit.description,
+        "timestamp": gistit.timestamp,
+        "inner": inner,
+        "lifespan": gistit.lifespan,
+        "visibility": format!("{:?}", gistit.visibility()).to_lowercase(),
+        // A new one is generated on every send
+        "token": gistit.token.as_ref().map(|_| "<owner token>"),
+        "burn_after_read": gistit.burn_after_read,
+        "max_reads": gistit.max_reads,
+        "parent": gistit.parent,
+        "metadata": gistit.metadata.as_deref().map(elide),
+    })
+}
+
+/// The start of `text`, followed by how much was left out
+fn elide(text: &str) -> String {
+    match text.char_indices().nth(DRY_RUN_PREVIEW_LENGTH) {
+        Some((end, _)) => format!("{}... ({} more bytes)", &text[..end], text.len() - end),
+        None => text.to_owned(),
+    }
+}
+
+/// Pushes `gistit` straight to `peer_id` through the daemon
+async fn push(bridge: &mut Bridge<Client>, gistit: Gistit, peer_id: &str) -> Result<()> {
+    progress!("Pushing");
+    if !bridge.alive() {
+        return Err(Error::Daemon("gistit node is not running"));
+    }
+    let hash = gistit.hash.clone();
+
+    bridge.connect_blocking()?;
+    bridge
+        .send(Instruction::request_push(gistit, peer_id.to_owned()))
+        .await?;
+
+    if let ipc::instruction::Kind::PushResponse(ipc::instruction::PushResponse { delivered }) =
+        bridge.recv().await?.expect_response()?
+    {
+        if !delivered {
+            return Err(Error::Daemon(
+                "failed to reach peer, check gistit-daemon logs",
+            ));
+        }
+
+        updateln!("Pushed");
+        finish!(format!(
+            "\n    hash: '{}'\n    peer: '{}'\n\n",
+            style(hash).bold(),
+            style(peer_id).blue()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Sends the gistit to every `--mirror` target at once, reporting each of them
+async fn mirror(bridge: &mut Bridge<Client>, config: Config<'_>) -> Result<()> {
+    progress!("Sending");
+    let clipboard = config.clipboard;
+    let embed = config.embed;
+    let limits = match config.limits {
+        Some(limits) => limits,
+        None => server::limits().await,
+    };
+    let topics = config.topics.clone();
+    let targets = config.mirror.clone();
+    let runtime_path = config.runtime_path.clone();
+    let config_path = config.config_path.clone();
+    let gistit: Gistit = config.try_into()?;
+    let name = first_file_name(&gistit);
+
+    let outcomes = mirror::send(
+        bridge,
+        &gistit,
+        topics,
+        &targets,
+        &runtime_path,
+        &config_path,
+    )
+    .await;
+
+    let mut report = String::new();
+    let mut failed = 0;
+    let mut copied = false;
+    for outcome in outcomes {
+        let target = match outcome.target {
+            Target::Server => SERVER_URL_BASE.as_str(),
+            Target::P2p => audit::P2P,
+        };
+        // Each target has it's own outcome
+        audit::note(outcome.hash.as_ref().unwrap_or(&gistit.hash), target);
+        audit::record("send", outcome.hash.as_ref().err());
+
+        let hash = match outcome.hash {
+            Ok(hash) => hash,
+            Err(err) => {
+                warnln!("failed to send to the {}: {}", outcome.target, err);
+                report.push_str(&format!(
+                    "\n    {}: {}",
+                    outcome.target,
+                    style("failed").red()
+                ));
+                failed += 1;
+                continue;
+            }
+        };
+
+        if clipboard && !copied {
+            Clipboard::new(&hash)
+                .into_selected()
+                .into_provider()
+                .set_contents()?;
+            copied = true;
+        }
+
+        match outcome.target {
+            Target::Server => {
+                cache::record_or_warn(Entry::new(&hash, &name, &gistit.author, Origin::Sent));
+                if let Some(ref token) = gistit.token {
+                    save_owner_token(&hash, token);
                 }
-                StatusCode::UNPROCESSABLE_ENTITY | StatusCode::BAD_REQUEST => {
-                    return Err(Error::Server("invalid gistit payload"));
+                updateln!("Sent to the server");
+
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .expect("Check your system time")
+                    .as_secs();
+                report.push_str(&format!(
+                    "\n    server: '{}'\n      url: '{}'\n      raw: '{}'\n      expires: {}",
+                    style(&hash).bold(),
+                    style(share_url(&hash)).bold(),
+                    raw_url(&hash),
+                    server::fmt_expiry(gistit.lifespan, &limits, now),
+                ));
+                if let Some(ref embed) = embed {
+                    println!("{}", embed.render(&gistit, &share_url(&hash)));
                 }
-                _ => return Err(Error::Server("invalid server response")),
             }
-        };
+            Target::P2p => {
+                cache::record_or_warn(Entry::new(&hash, &name, &gistit.author, Origin::Hosted));
+                updateln!("Hosted on the p2p network");
+                report.push_str(&format!("\n    p2p: '{}'", style(&hash).bold()));
+            }
+        }
+    }
+
+    let clipboard_msg = if copied {
+        style("\n    (copied to clipboard)")
+            .italic()
+            .dim()
+            .to_string()
+    } else {
+        String::new()
+    };
+    finish!(format!("{}{}\n\n", report, clipboard_msg));
+
+    if failed > 0 {
+        return Err(Error::Mirror(format!(
+            "{} of {} targets failed",
+            failed,
+            targets.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Sends the file at `path` again whenever it changes, until interrupted. With `--name` the name is
+/// published again to point to each new hash
+async fn watch(bridge: &mut Bridge<Client>, config: Config, path: &Path) -> Result<()> {
+    let use_daemon = config.backend.use_daemon(bridge.alive())?;
+    if config.name.is_some() && !use_daemon {
+        return Err(Error::Argument(
+            "names are only published through the p2p network",
+            "--name",
+        ));
+    }
+    if use_daemon {
+        check_hostable(config.visibility)?;
+        bridge.connect_blocking()?;
+    }
+
+    // Editors often save by replacing the file, the parent directory is watched instead
+    let (event_tx, event_rx) = mpsc::channel();
+    let mut watcher = watcher(event_tx, WATCH_DEBOUNCE)?;
+    let parent = match path.parent() {
+        Some(parent) if parent != Path::new("") => parent,
+        _ => Path::new("."),
+    };
+    watcher.watch(parent, RecursiveMode::NonRecursive)?;
+    let file_name = path.file_name().map(ToOwned::to_owned);
+
+    let (changed_tx, mut changed_rx) = tokio::sync::mpsc::unbounded_channel();
+    thread::spawn(move || {
+        for event in event_rx {
+            let changed = match event {
+                DebouncedEvent::Create(changed)
+                | DebouncedEvent::Write(changed)
+                | DebouncedEvent::Rename(_, changed) => changed,
+                _ => continue,
+            };
+            if changed.file_name() == file_name.as_deref() && changed_tx.send(()).is_err() {
+                break;
+            }
+        }
+    });
+
+    let published_name = config.name;
+    let topics = config.topics.clone();
+    let description = config.description.clone();
+    let author = config.author.clone();
+    let visibility = config.visibility;
+    let owner_token = config.owner_token.clone();
+    let lifespan = config.lifespan;
+    let secret = config.secret.clone();
+    let encrypt_metadata = config.encrypt_metadata;
+    let mut gistit: Gistit = config.try_into()?;
+    let mut last_hash = None;
+
+    loop {
+        if last_hash.as_ref() != Some(&gistit.hash) {
+            progress!("Sending");
+            let target = if use_daemon {
+                audit::P2P
+            } else {
+                SERVER_URL_BASE.as_str()
+            };
+            audit::note(&gistit.hash, target);
+            let hash = if use_daemon {
+                provide(bridge, gistit.clone(), topics.clone()).await?
+            } else {
+                let hash = send_server(&gistit).await?;
+                if let Some(ref token) = owner_token {
+                    save_owner_token(&hash, token);
+                }
+                hash
+            };
+            let origin = if use_daemon {
+                Origin::Hosted
+            } else {
+                Origin::Sent
+            };
+            cache::record_or_warn(Entry::new(
+                &hash,
+                &first_file_name(&gistit),
+                &gistit.author,
+                origin,
+            ));
+
+            if let Some(name) = published_name {
+                name::publish_hash(bridge, name, &hash).await?;
+            }
+            let location = if use_daemon {
+                published_name.map_or_else(|| format!("@{}", hash), ToOwned::to_owned)
+            } else {
+                share_url(&hash)
+            };
+            updateln!("Sent '{}'", style(location).bold());
+            audit::record("send", None);
+            last_hash = Some(hash);
+        }
+
+        progress!("Watching for changes, press Ctrl-C to stop");
+        if changed_rx.recv().await.is_none() {
+            return Err(Error::Argument("stopped watching the file", "--watch"));
+        }
+
+        let changed = fs::metadata(path)
+            .map_err(Error::from)
+            .and_then(|attr| check::metadata(&attr))
+            .and_then(|_| File::from_path(path))
+            .and_then(|file| new_gistit(&file, author.clone(), description.as_deref()))
+            .and_then(|mut changed| {
+                if let Some(ref secret) = secret {
+                    secret::seal(&mut changed, secret)?;
+                    if encrypt_metadata {
+                        secret::seal_metadata(&mut changed, secret)?;
+                    }
+                }
+                Ok(changed)
+            });
+        match changed {
+            Ok(mut changed) => {
+                changed.set_visibility(visibility.into());
+                changed.token = owner_token.clone();
+                changed.lifespan = lifespan;
+                // Each save is a revision of the last one sent
+                changed.parent = last_hash.clone();
+                gistit = changed;
+            }
+            Err(err) => warnln!("{}, not sending this change", err),
+        }
+    }
+}
+
+/// Hosts `gistit` through the daemon, returning it's hash
+pub async fn provide(
+    bridge: &mut Bridge<Client>,
+    gistit: Gistit,
+    topics: Vec<String>,
+) -> Result<String> {
+    bridge
+        .send(Instruction::request_provide(gistit, topics))
+        .await?;
+
+    match bridge.recv().await?.expect_response()? {
+        ipc::instruction::Kind::ProvideResponse(ipc::instruction::ProvideResponse {
+            hash: Some(hash),
+        }) => Ok(hash),
+        _ => Err(Error::Daemon(
+            "failed to provide gistit, check gistit-daemon logs",
+        )),
+    }
+}
+
+/// Sends `gistit` to the server, returning the hash it was stored under
+pub async fn send_server(gistit: &Gistit) -> Result<String> {
+    server::retry_rate_limited(|| gistit_core::send::send_server(&SERVER_URL_BASE, gistit)).await
+}
+
+/// Whether the server already has `hash` to link to, cached for a while. Sent anyway if it can't
+/// tell
+pub async fn exists_server(hash: &str) -> bool {
+    let key = exists_key(hash);
+    let now = cache::now();
+    if let Lookup::Fresh(exists) =
+        cache::responses(|responses| responses.lookup(ResponseKind::Exists, &key, now))
+    {
+        tracing::debug!(exists, "server answer cached");
+        return exists;
+    }
+
+    match server::retry_rate_limited(|| gistit_core::send::exists_server(&SERVER_URL_BASE, hash))
+        .await
+    {
+        Ok(exists) => {
+            cache::responses(|responses| {
+                responses.insert(ResponseKind::Exists, &key, &exists, None, now);
+            });
+            exists
+        }
+        Err(err) => {
+            tracing::debug!("can't tell if the server has {}: {}", hash, err);
+            false
+        }
+    }
+}
+
+/// Remembers the server has `hash` now, sending it again in a loop doesn't have to ask
+fn record_exists(hash: &str) {
+    let key = exists_key(hash);
+    cache::responses(|responses| {
+        responses.insert(ResponseKind::Exists, &key, &true, None, cache::now());
+    });
+}
+
+/// Responses of the server about `hash` are content addressed, cached by the hash
+fn exists_key(hash: &str) -> String {
+    format!("{}exists/{}", *SERVER_URL_BASE, hash)
+}
+
+/// Whether `hash` is hosted by `gistit-daemon` already
+async fn hosting(bridge: &mut Bridge<Client>, hash: &str) -> Result<bool> {
+    bridge
+        .send(Instruction::request_exists(hash.to_owned()))
+        .await?;
+
+    match bridge.recv().await?.expect_response()? {
+        ipc::instruction::Kind::ExistsResponse(ipc::instruction::ExistsResponse { exists }) => {
+            Ok(exists)
+        }
+        _ => Err(Error::Daemon(
+            "unexpected response, check gistit-daemon logs",
+        )),
+    }
+}
+
+/// Whether a copy of `gistit` sent before can stand in for it. It's hash only covers the content,
+/// author and description: private gistits, ones deleted once read, with a lifespan or a parent are
+/// always sent. Encrypted ones never repeat, they're salted
+#[must_use]
+pub fn dedupable(gistit: &Gistit) -> bool {
+    !secret::is_encrypted(gistit)
+        && gistit.token.is_none()
+        && gistit.read_limit().is_none()
+        && gistit.lifespan.is_none()
+        && gistit.parent.is_none()
+}
+
+/// A random owner token for a private gistit
+fn new_owner_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(OWNER_TOKEN_LENGTH)
+        .map(char::from)
+        .collect()
+}
+
+/// A random secret, for `--gen-secret` and workspace keys
+pub fn new_secret() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(GENERATED_SECRET_LENGTH)
+        .map(char::from)
+        .collect()
+}
+
+/// `link` carrying the secret that opens it in it's fragment, never sent along when it's opened
+pub fn key_link(link: &str, secret: &str) -> String {
+    format!("{}{}{}", link, check::KEY_FRAGMENT, secret)
+}
+
+/// Keeps the owner token of `hash` for `gistit fetch`, warning with the token if it can't
+fn save_owner_token(hash: &str, token: &str) {
+    if let Err(err) = Credential::OwnerToken(hash.to_owned()).set(token) {
+        warnln!(
+            "failed to save the owner token '{}', keep it to fetch this gistit: {}",
+            token,
+            err
+        );
+    }
+}
+
+/// Private gistits rely on the server checking the owner token, peers can't
+fn check_hostable(visibility: Visibility) -> Result<()> {
+    if visibility == Visibility::Private {
+        Err(Error::Argument(
+            "private gistits are only sent to the server, stop gistit-daemon or use the server backend",
+            "--private",
+        ))
+    } else {
         Ok(())
     }
 }
+
+/// The web page of a gistit sent to the server
+pub fn share_url(hash: &str) -> String {
+    format!("{}{}", SHARE_URL_BASE, hash)
+}
+
+/// Plain text content of a gistit sent to the server, for piping it with `curl`
+pub fn raw_url(hash: &str) -> String {
+    format!("{}raw/{}", *SERVER_URL_BASE, hash)
+}
+
+/// Name of the first inner file
+// NOTE: Currently we only support one file
+pub fn first_file_name(gistit: &Gistit) -> String {
+    gistit
+        .inner
+        .first()
+        .map(|inner| inner.name.clone())
+        .unwrap_or_default()
+}
+
+/// A random generated author name, used when none was given
+pub fn random_author() -> String {
+    names::Generator::default()
+        .next()
+        .expect("to generate a random name")
+}
@@ -0,0 +1,512 @@
+//! The serve-json module
+//!
+//! `gistit serve-json` keeps a single process around for editor extensions, instead of spawning
+//! the CLI for every action. It answers newline delimited [JSON-RPC 2.0](https://www.jsonrpc.org/specification)
+//! requests read from stdin with a single line each on stdout, until stdin is closed. Requests are
+//! answered one at a time in the order they came, `gistit-daemon` only has a single client socket.
+//!
+//! - `send` `{data, name?, lang?, author?, description?, secret?, backend?}` answers
+//!   `{hash, url?, backend}`
+//! - `fetch` `{hash, secret?, token?, prefer?, burn_after_read?}` answers
+//!   `{hash, author, description?, source, files: [{name, lang, data}]}`, a hash ending in
+//!   `#key=<secret>` is decrypted with it when there's no `secret`
+//! - `list` `{origin?, limit?}` answers `{entries}`, the local cache index most recent first
+//! - `status` answers `{backend, running}`, plus the swarm status when `gistit-daemon` is running
+//!
+//! Sending and fetching go through `gistit-core`, defaults come from the settings like the other
+//! commands. Failures of gistit itself are answered with the `-32000` error code, it's `data`
+//! holding the same `code`, `hint` and `exit_code` `--stdin-headless` prints.
+
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use clap::ArgMatches;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::io::{self, AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+use gistit_core::{Destination, FetchRequest, SendRequest};
+use gistit_project::path;
+use gistit_proto::{ipc, Instruction};
+
+use crate::audit;
+use crate::cache::{self, Entry, Index, Origin};
+use crate::daemon;
+use crate::dispatch::Dispatch;
+use crate::fetch::Prefer;
+use crate::file::lang_from_path;
+use crate::param::check;
+use crate::send::random_author;
+use crate::server::SERVER_URL_BASE;
+use crate::settings::{get_runtime_settings, Backend};
+use crate::{Error, Result};
+
+const JSONRPC_VERSION: &str = "2.0";
+
+/// Name of gistits sent without one
+const DEFAULT_NAME: &str = "untitled";
+
+/// JSON-RPC error codes
+mod code {
+    pub const PARSE_ERROR: i64 = -32700;
+    pub const INVALID_REQUEST: i64 = -32600;
+    pub const METHOD_NOT_FOUND: i64 = -32601;
+    pub const INVALID_PARAMS: i64 = -32602;
+    /// Errors of gistit itself, detailed in `data`
+    pub const GISTIT: i64 = -32000;
+}
+
+#[derive(Debug, Clone)]
+pub struct Action;
+
+impl Action {
+    pub fn from_args(
+        _args: &ArgMatches,
+    ) -> Result<Box<dyn Dispatch<InnerData = Config> + Send + Sync + '_>> {
+        Ok(Box::new(Self))
+    }
+}
+
+pub struct Config {
+    runtime_path: PathBuf,
+    config_path: PathBuf,
+}
+
+#[async_trait]
+impl Dispatch for Action {
+    type InnerData = Config;
+
+    async fn prepare(&self) -> Result<Self::InnerData> {
+        Ok(Config {
+            runtime_path: path::runtime_dir()?,
+            config_path: path::config()?,
+        })
+    }
+
+    async fn dispatch(&self, config: Self::InnerData) -> Result<()> {
+        let mut lines = BufReader::new(io::stdin()).lines();
+        let mut stdout = io::stdout();
+
+        while let Some(line) = lines.next_line().await? {
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Some(response) = handle(&config, &line).await {
+                let mut encoded = serde_json::to_vec(&response)?;
+                encoded.push(b'\n');
+                stdout.write_all(&encoded).await?;
+                stdout.flush().await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    jsonrpc: String,
+    /// Notifications have none and aren't answered
+    #[serde(default)]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Debug, Serialize)]
+struct Response {
+    jsonrpc: &'static str,
+    id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<RpcError>,
+}
+
+impl Response {
+    fn new(id: Value, outcome: std::result::Result<Value, RpcError>) -> Self {
+        let (result, error) = match outcome {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error)),
+        };
+        Self {
+            jsonrpc: JSONRPC_VERSION,
+            id,
+            result,
+            error,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<Value>,
+}
+
+impl RpcError {
+    fn new(code: i64, message: String) -> Self {
+        Self {
+            code,
+            message,
+            data: None,
+        }
+    }
+}
+
+impl From<Error> for RpcError {
+    fn from(err: Error) -> Self {
+        Self {
+            code: code::GISTIT,
+            message: err.to_string(),
+            data: Some(json!({
+                "code": err.code(),
+                "hint": err.hint(),
+                "exit_code": err.exit_code(),
+            })),
+        }
+    }
+}
+
+/// Answers a single request line, `None` for notifications
+async fn handle(config: &Config, line: &str) -> Option<Response> {
+    let value: Value = match serde_json::from_str(line) {
+        Ok(value) => value,
+        Err(err) => {
+            return Some(Response::new(
+                Value::Null,
+                Err(RpcError::new(code::PARSE_ERROR, err.to_string())),
+            ))
+        }
+    };
+    // The id is answered even when the rest of the request is invalid
+    let id = value.get("id").cloned().unwrap_or(Value::Null);
+
+    let request = match serde_json::from_value::<Request>(value) {
+        Ok(request) if request.jsonrpc == JSONRPC_VERSION => request,
+        Ok(_) => {
+            return Some(Response::new(
+                id,
+                Err(RpcError::new(
+                    code::INVALID_REQUEST,
+                    "expected jsonrpc '2.0'".to_owned(),
+                )),
+            ))
+        }
+        Err(err) => {
+            return Some(Response::new(
+                id,
+                Err(RpcError::new(code::INVALID_REQUEST, err.to_string())),
+            ))
+        }
+    };
+
+    tracing::debug!(method = %request.method, "request");
+    let outcome = call(config, &request.method, request.params).await;
+    request.id.map(|id| Response::new(id, outcome))
+}
+
+async fn call(
+    config: &Config,
+    method: &str,
+    params: Value,
+) -> std::result::Result<Value, RpcError> {
+    match method {
+        "send" => Ok(audited("send", send(config, parse(params)?).await)?),
+        "fetch" => Ok(audited("fetch", fetch(config, parse(params)?).await)?),
+        "list" => Ok(list(&parse(params)?)?),
+        "status" => Ok(status(config).await?),
+        _ => Err(RpcError::new(
+            code::METHOD_NOT_FOUND,
+            format!(
+                "no method '{}', expected 'send', 'fetch', 'list' or 'status'",
+                method
+            ),
+        )),
+    }
+}
+
+/// Logs a send or fetch in the audit log, each request on it's own
+fn audited(method: &str, result: Result<Value>) -> Result<Value> {
+    audit::record(method, result.as_ref().err());
+    result
+}
+
+/// Params of a method, left out ones are the same as `{}`
+fn parse<T: DeserializeOwned>(params: Value) -> std::result::Result<T, RpcError> {
+    let params = if params.is_null() { json!({}) } else { params };
+    serde_json::from_value(params)
+        .map_err(|err| RpcError::new(code::INVALID_PARAMS, err.to_string()))
+}
+
+#[derive(Debug, Deserialize)]
+struct SendParams {
+    data: String,
+    name: Option<String>,
+    /// Picked by the extension of `name` if left out
+    lang: Option<String>,
+    author: Option<String>,
+    description: Option<String>,
+    secret: Option<String>,
+    backend: Option<Backend>,
+}
+
+async fn send(config: &Config, params: SendParams) -> Result<Value> {
+    let settings = get_runtime_settings()?;
+    let name = params
+        .name
+        .as_deref()
+        .map(check::file_name)
+        .transpose()?
+        .unwrap_or(DEFAULT_NAME);
+    let lang = params
+        .lang
+        .unwrap_or_else(|| lang_from_path(Path::new(name)).to_owned());
+    let author = match params.author.or_else(|| settings.author.clone()) {
+        Some(author) => check::author(&author)?.to_owned(),
+        None => random_author(),
+    };
+    let description = params
+        .description
+        .as_deref()
+        .map(check::description)
+        .transpose()?
+        .map(ToOwned::to_owned);
+    let secret = params
+        .secret
+        .as_deref()
+        .map(check::secret)
+        .transpose()?
+        .map(ToOwned::to_owned);
+
+    let backend = params.backend.unwrap_or_else(|| settings.backend());
+    let hosted = use_daemon(config, backend).await?;
+    let destination = if hosted {
+        Destination::Daemon {
+            runtime_path: config.runtime_path.clone(),
+            topics: Vec::new(),
+        }
+    } else {
+        Destination::Server(SERVER_URL_BASE.clone())
+    };
+
+    let receipt = gistit_core::send(SendRequest {
+        name: name.to_owned(),
+        data: params.data,
+        author: author.clone(),
+        lang,
+        description,
+        secret,
+        destination,
+    })
+    .await?;
+
+    let origin = if hosted { Origin::Hosted } else { Origin::Sent };
+    cache::record_or_warn(Entry::new(&receipt.hash, name, &author, origin));
+    let target = if hosted {
+        audit::P2P
+    } else {
+        SERVER_URL_BASE.as_str()
+    };
+    audit::note(&receipt.hash, target);
+
+    Ok(json!({
+        "hash": receipt.hash,
+        "url": receipt.url,
+        "backend": if hosted { "p2p" } else { "server" },
+    }))
+}
+
+/// Whether to go through `gistit-daemon`, spawned first if `backend` requires it
+async fn use_daemon(config: &Config, backend: Backend) -> Result<bool> {
+    let bridge = gistit_ipc::client(&config.runtime_path)?;
+    daemon::spawn_if_required(backend, &bridge, &config.runtime_path, &config.config_path).await?;
+    backend.use_daemon(bridge.alive())
+}
+
+#[derive(Debug, Deserialize)]
+struct FetchParams {
+    hash: String,
+    secret: Option<String>,
+    /// Owner token of a private gistit
+    token: Option<String>,
+    /// 'p2p', 'server' or 'auto', defaults to the backend setting
+    prefer: Option<String>,
+    /// Agrees to fetch gistits deleted once read
+    #[serde(default)]
+    burn_after_read: bool,
+}
+
+async fn fetch(config: &Config, params: FetchParams) -> Result<Value> {
+    let settings = get_runtime_settings()?;
+    let (hash, key) = check::key_link(&params.hash)?;
+    let hash = check::hash(hash)?;
+    let prefer = params.prefer.as_deref().map_or_else(
+        || Ok(Prefer::from_backend(settings.backend())),
+        Prefer::parse,
+    )?;
+
+    let daemon_alive = gistit_ipc::client(&config.runtime_path)?.alive();
+    let sources = prefer
+        .sources(daemon_alive)
+        .into_iter()
+        .map(|source| match source {
+            crate::fetch::Source::Server => gistit_core::Source::Server(SERVER_URL_BASE.clone()),
+            crate::fetch::Source::Daemon => {
+                gistit_core::Source::Daemon(config.runtime_path.clone())
+            }
+        })
+        .collect();
+
+    let fetched = gistit_core::fetch(FetchRequest {
+        hash: hash.to_owned(),
+        secret: params.secret.or_else(|| key.map(ToOwned::to_owned)),
+        token: params.token,
+        burn_after_read: params.burn_after_read,
+        sources,
+    })
+    .await?;
+    cache::record_or_warn(Entry::new(
+        hash,
+        fetched.name(),
+        fetched.author(),
+        Origin::Fetched,
+    ));
+
+    let source = match fetched.source {
+        gistit_core::Source::Server(_) => "server",
+        gistit_core::Source::Daemon(_) => "p2p",
+    };
+    let target = match fetched.source {
+        gistit_core::Source::Server(ref url) => url.as_str(),
+        gistit_core::Source::Daemon(_) => audit::P2P,
+    };
+    audit::note(hash, target);
+    let files: Vec<Value> = fetched
+        .files()
+        .iter()
+        .map(|inner| json!({ "name": inner.name, "lang": inner.lang, "data": inner.data }))
+        .collect();
+    Ok(json!({
+        "hash": fetched.gistit.hash,
+        "author": fetched.gistit.author,
+        "description": fetched.gistit.description,
+        "source": source,
+        "files": files,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct ListParams {
+    /// Only entries that were 'sent', 'hosted' or 'fetched'
+    origin: Option<Origin>,
+    limit: Option<usize>,
+}
+
+fn list(params: &ListParams) -> Result<Value> {
+    let index = Index::load()?;
+    let entries: Vec<&Entry> = index
+        .entries
+        .iter()
+        .rev()
+        .filter(|entry| params.origin.map_or(true, |origin| entry.origin == origin))
+        .take(params.limit.unwrap_or(usize::MAX))
+        .collect();
+
+    Ok(json!({ "entries": entries }))
+}
+
+async fn status(config: &Config) -> Result<Value> {
+    let backend = get_runtime_settings()?.backend();
+    let mut bridge = gistit_ipc::client(&config.runtime_path)?;
+    if !bridge.alive() {
+        return Ok(json!({ "backend": backend, "running": false }));
+    }
+
+    bridge.connect_blocking()?;
+    bridge.send(Instruction::request_status()).await?;
+    match bridge.recv().await?.expect_response()? {
+        ipc::instruction::Kind::StatusResponse(response) => Ok(json!({
+            "backend": backend,
+            "running": true,
+            "pid": daemon::read_pid(&config.runtime_path),
+            "peer_id": response.peer_id,
+            "peer_count": response.peer_count,
+            "pending_connections": response.pending_connections,
+            "hosting": response.hosting,
+            "reachability": response.reachability,
+            "bytes_sent": response.bytes_sent,
+            "bytes_received": response.bytes_received,
+        })),
+        _ => Err(Error::Daemon("unexpected response to a status request")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        Config {
+            runtime_path: PathBuf::from("/nonexistent"),
+            config_path: PathBuf::from("/nonexistent"),
+        }
+    }
+
+    async fn answer(line: &str) -> Value {
+        let response = handle(&config(), line).await.unwrap();
+        serde_json::to_value(response).unwrap()
+    }
+
+    #[tokio::test]
+    async fn serve_json_invalid_requests() {
+        let parse_error = answer("{not json").await;
+        assert_eq!(parse_error["id"], Value::Null);
+        assert_eq!(parse_error["error"]["code"], code::PARSE_ERROR);
+
+        let wrong_version = answer(r#"{"jsonrpc":"1.0","id":1,"method":"status"}"#).await;
+        assert_eq!(wrong_version["id"], 1);
+        assert_eq!(wrong_version["error"]["code"], code::INVALID_REQUEST);
+
+        let no_method = answer(r#"{"jsonrpc":"2.0","id":"a"}"#).await;
+        assert_eq!(no_method["id"], "a");
+        assert_eq!(no_method["error"]["code"], code::INVALID_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn serve_json_method_and_params() {
+        let unknown = answer(r#"{"jsonrpc":"2.0","id":1,"method":"delete"}"#).await;
+        assert_eq!(unknown["error"]["code"], code::METHOD_NOT_FOUND);
+        assert!(unknown.get("result").is_none());
+
+        let missing_data = answer(r#"{"jsonrpc":"2.0","id":2,"method":"send","params":{}}"#).await;
+        assert_eq!(missing_data["error"]["code"], code::INVALID_PARAMS);
+
+        let bad_backend = answer(
+            r#"{"jsonrpc":"2.0","id":3,"method":"send","params":{"data":"x","backend":"ftp"}}"#,
+        )
+        .await;
+        assert_eq!(bad_backend["error"]["code"], code::INVALID_PARAMS);
+    }
+
+    #[tokio::test]
+    async fn serve_json_notifications_unanswered() {
+        assert!(handle(&config(), r#"{"jsonrpc":"2.0","method":"delete"}"#)
+            .await
+            .is_none());
+    }
+
+    #[test]
+    fn serve_json_gistit_errors() {
+        let error = RpcError::from(Error::Argument("invalid gistit hash format.", "--hash"));
+        let value = serde_json::to_value(error).unwrap();
+
+        assert_eq!(value["code"], code::GISTIT);
+        assert_eq!(value["data"]["code"], "argument");
+        assert_eq!(value["data"]["exit_code"], 2);
+    }
+}
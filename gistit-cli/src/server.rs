@@ -1,36 +1,396 @@
+use std::future::Future;
+use std::time::Duration;
+
+use indicatif::HumanBytes;
+use reqwest::header::{ETAG, IF_NONE_MATCH};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 use url::Url;
 
 use gistit_project::{env, var};
 use lazy_static::lazy_static;
 
-lazy_static! {
-    static ref SERVER_URL_BASE: Url = Url::parse(var::GISTIT_SERVER_URL_BASE).unwrap();
-}
+use crate::cache::{self, Lookup, ResponseKind};
+use crate::settings::get_runtime_settings;
+use crate::{warnln, Error, Result};
 
 const SERVER_SUBPATH_GET: &str = "get";
-const SERVER_SUBPATH_LOAD: &str = "load";
 const SERVER_SUBPATH_TOKEN: &str = "token";
+const SERVER_SUBPATH_CAPABILITIES: &str = "capabilities";
+const SERVER_SUBPATH_TELEMETRY: &str = "telemetry";
+
+const SERVER_CAPABILITIES_TIMEOUT_SECS: u64 = 5;
+
+/// Times a rate limited request is sent again
+pub const SERVER_RATE_LIMIT_RETRIES: u32 = 3;
+
+/// First wait of a rate limited request when the server doesn't say how long, doubled every time
+const RATE_LIMIT_BACKOFF_SECS: u64 = 2;
+
+/// Longer waits asked by the server aren't worth it, the request fails instead
+pub const RATE_LIMIT_MAX_WAIT_SECS: u64 = 60;
+
+/// Lifespan of gistits the server keeps forever, if it allows that
+pub const LIFESPAN_NEVER: u64 = 0;
+
+/// What gistit hashes are, see [`gistit_proto::payload::hash`]
+const HASH_ALGORITHM: &str = "sha256";
 
 lazy_static! {
-    pub static ref SERVER_URL_GET: Url = Url::parse(
-        &std::env::var(env::GISTIT_SERVER_URL)
-            .unwrap_or_else(|_| var::GISTIT_SERVER_URL_BASE.to_owned())
-    )
-    .expect("invalid `GISTIT_SERVER_URL` variable")
-    .join(SERVER_SUBPATH_GET)
-    .unwrap();
-    pub static ref SERVER_URL_LOAD: Url = Url::parse(
+    /// `GISTIT_SERVER_URL` takes precedence over the settings file (and it's selected profile)
+    pub static ref SERVER_URL_BASE: Url = Url::parse(
         &std::env::var(env::GISTIT_SERVER_URL)
-            .unwrap_or_else(|_| var::GISTIT_SERVER_URL_BASE.to_owned())
+            .ok()
+            .or_else(|| get_runtime_settings().ok().and_then(|settings| settings.server_url))
+            .unwrap_or_else(|| var::GISTIT_SERVER_URL_BASE.to_owned())
     )
-    .expect("invalid `GISTIT_SERVER_URL` variable")
-    .join(SERVER_SUBPATH_LOAD)
-    .unwrap();
-    pub static ref SERVER_URL_TOKEN: Url = Url::parse(
-        &std::env::var(env::GISTIT_SERVER_URL)
-            .unwrap_or_else(|_| var::GISTIT_SERVER_URL_BASE.to_owned())
+    .expect("invalid server url, check `GISTIT_SERVER_URL` variable and `server_url` setting");
+    pub static ref SERVER_URL_GET: Url = SERVER_URL_BASE.join(SERVER_SUBPATH_GET).unwrap();
+    pub static ref SERVER_URL_TOKEN: Url = SERVER_URL_BASE.join(SERVER_SUBPATH_TOKEN).unwrap();
+    pub static ref SERVER_URL_CAPABILITIES: Url =
+        SERVER_URL_BASE.join(SERVER_SUBPATH_CAPABILITIES).unwrap();
+    pub static ref SERVER_URL_TELEMETRY: Url =
+        SERVER_URL_BASE.join(SERVER_SUBPATH_TELEMETRY).unwrap();
+}
+
+/// Lifespans the server accepts, in seconds. Self-hosted servers may keep gistits longer or forever
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Limits {
+    pub min_lifespan: u64,
+    pub max_lifespan: u64,
+    /// Used when a gistit is sent without one
+    pub default_lifespan: u64,
+    /// Whether `never` is accepted
+    pub never_expire: bool,
+}
+
+impl Default for Limits {
+    /// The bundled server's
+    fn default() -> Self {
+        Self {
+            min_lifespan: 60,
+            max_lifespan: 7 * 24 * 60 * 60,
+            default_lifespan: 30 * 60,
+            never_expire: false,
+        }
+    }
+}
+
+/// Everything the server advertises, so arguments can be checked before anything is sent
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Capabilities {
+    #[serde(flatten)]
+    pub limits: Limits,
+    /// Most bytes of a file
+    pub max_size: u64,
+    /// Algorithms hashes are accepted in
+    pub hash_algorithms: Vec<String>,
+    /// Optional features it has, see [`Feature`]
+    pub features: Vec<String>,
+}
+
+impl Default for Capabilities {
+    /// The bundled server's
+    fn default() -> Self {
+        Self {
+            limits: Limits::default(),
+            max_size: var::GISTIT_MAX_SIZE as u64,
+            hash_algorithms: vec![HASH_ALGORITHM.to_owned()],
+            features: Feature::ALL
+                .iter()
+                .map(|feature| feature.name().to_owned())
+                .collect(),
+        }
+    }
+}
+
+impl Capabilities {
+    /// Checks the server has `feature`
+    ///
+    /// # Errors
+    ///
+    /// Fails naming it if it doesn't
+    pub fn require(&self, feature: Feature) -> Result<()> {
+        if self.features.iter().any(|name| name == feature.name()) {
+            Ok(())
+        } else {
+            Err(Error::Unsupported(feature.description().to_owned()))
+        }
+    }
+
+    /// Checks the server takes a file of `size` bytes, hashed like gistit hashes them
+    ///
+    /// # Errors
+    ///
+    /// Fails if it's too big or the hash algorithm isn't accepted
+    pub fn check_send(&self, size: usize) -> Result<()> {
+        if !self
+            .hash_algorithms
+            .iter()
+            .any(|algorithm| algorithm == HASH_ALGORITHM)
+        {
+            return Err(Error::Unsupported(format!(
+                "{} hashes, it takes {}",
+                HASH_ALGORITHM,
+                self.hash_algorithms.join(", ")
+            )));
+        }
+        if size as u64 > self.max_size {
+            return Err(Error::Unsupported(format!(
+                "files over {}, this one is {}",
+                HumanBytes(self.max_size),
+                HumanBytes(size as u64)
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Optional features of the server, older or self-hosted ones may lack some
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    /// Gistits only answered with their owner token
+    Private,
+    /// Gistits deleted once fetched, or after some fetches
+    ReadLimit,
+    /// Gistits replacing others, listed by `gistit history`
+    Revisions,
+    Comments,
+    /// Short hashes expanded to the public gistits they start
+    Resolve,
+    /// Base64 encoded files
+    Binary,
+}
+
+impl Feature {
+    const ALL: [Self; 6] = [
+        Self::Private,
+        Self::ReadLimit,
+        Self::Revisions,
+        Self::Comments,
+        Self::Resolve,
+        Self::Binary,
+    ];
+
+    /// Name the server advertises it with
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Private => "private",
+            Self::ReadLimit => "read-limit",
+            Self::Revisions => "revisions",
+            Self::Comments => "comments",
+            Self::Resolve => "resolve",
+            Self::Binary => "binary",
+        }
+    }
+
+    const fn description(self) -> &'static str {
+        match self {
+            Self::Private => "private gistits",
+            Self::ReadLimit => "gistits deleted once read",
+            Self::Revisions => "revisions",
+            Self::Comments => "comments",
+            Self::Resolve => "short hashes",
+            Self::Binary => "binary files",
+        }
+    }
+}
+
+/// The limits advertised by the server, part of it's [`capabilities`]
+pub async fn limits() -> Limits {
+    capabilities().await.limits
+}
+
+/// The capabilities advertised by the server, the bundled server's if it doesn't advertise them or
+/// can't be reached. Sending fails later in that case anyway
+#[tracing::instrument(level = "debug")]
+pub async fn capabilities() -> Capabilities {
+    cached(ResponseKind::Capabilities, &SERVER_URL_CAPABILITIES)
+        .await
+        .unwrap_or_default()
+}
+
+/// The json answered by `url`, cached as `kind`. Once expired the server is asked whether it
+/// changed with it's `ETag`. `None` if it couldn't be reached or didn't answer a success
+async fn cached<T>(kind: ResponseKind, url: &Url) -> Option<T>
+where
+    T: Serialize + DeserializeOwned,
+{
+    let key = url.as_str();
+    let now = cache::now();
+    let stale = match cache::responses(|responses| responses.lookup(kind, key, now)) {
+        Lookup::Fresh(value) => {
+            tracing::debug!(?kind, "cached");
+            return Some(value);
+        }
+        Lookup::Stale { value, etag } => Some((value, etag)),
+        Lookup::Missing => None,
+    };
+
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(SERVER_CAPABILITIES_TIMEOUT_SECS))
+        .build()
+        .ok()?;
+
+    let mut request = client.get(key);
+    if let Some((_, etag)) = &stale {
+        request = request.header(IF_NONE_MATCH, etag.as_str());
+    }
+    let response = request.send().await;
+    tracing::debug!(status = ?response.as_ref().map(reqwest::Response::status), "server answered");
+    match (response, stale) {
+        (Ok(response), Some((value, _))) if response.status() == StatusCode::NOT_MODIFIED => {
+            cache::responses(|responses| responses.revalidate(kind, key, now));
+            Some(value)
+        }
+        (Ok(response), _) if response.status().is_success() => {
+            let etag = response
+                .headers()
+                .get(ETAG)
+                .and_then(|etag| etag.to_str().ok())
+                .map(ToOwned::to_owned);
+            let value = response.json().await.ok()?;
+            cache::responses(|responses| responses.insert(kind, key, &value, etag, now));
+            Some(value)
+        }
+        _ => None,
+    }
+}
+
+/// Sends `request` again while the server rate limits it, waiting as long as it asks to or backing
+/// off exponentially, at most [`SERVER_RATE_LIMIT_RETRIES`] times
+///
+/// # Errors
+///
+/// Inherits errors of `request`, rate limited ones once retries are exhausted or the server asks
+/// to wait for longer than [`RATE_LIMIT_MAX_WAIT_SECS`]
+pub async fn retry_rate_limited<T, F, Fut>(mut request: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = gistit_core::Result<T>>,
+{
+    let mut retries = 0;
+    loop {
+        match request().await {
+            Err(gistit_core::Error::RateLimited(retry_after))
+                if retries < SERVER_RATE_LIMIT_RETRIES =>
+            {
+                let wait = retry_after
+                    .unwrap_or_else(|| Duration::from_secs(RATE_LIMIT_BACKOFF_SECS << retries));
+                if wait > Duration::from_secs(RATE_LIMIT_MAX_WAIT_SECS) {
+                    return Err(gistit_core::Error::RateLimited(Some(wait)).into());
+                }
+
+                retries += 1;
+                warnln!("rate limited, retrying in {}s", wait.as_secs().max(1));
+                tokio::time::sleep(wait).await;
+            }
+            result => return Ok(result?),
+        }
+    }
+}
+
+/// When a gistit sent with `lifespan`, or the server's default, `now` expires: a UTC date like
+/// `2022-02-03 18:30 UTC` or `never`
+#[must_use]
+pub fn fmt_expiry(lifespan: Option<u64>, limits: &Limits, now: u64) -> String {
+    match lifespan.unwrap_or(limits.default_lifespan) {
+        LIFESPAN_NEVER => "never".to_owned(),
+        lifespan => fmt_utc(now + lifespan),
+    }
+}
+
+/// Seconds since unix epoch as `YYYY-MM-DD HH:MM UTC`
+fn fmt_utc(secs: u64) -> String {
+    let days = secs / 86_400;
+    let secs = secs % 86_400;
+
+    // Days to a civil date, from Howard Hinnant's `civil_from_days`
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60
     )
-    .expect("invalid `GISTIT_SERVER_URL` variable")
-    .join(SERVER_SUBPATH_TOKEN)
-    .unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn server_fmt_expiry() {
+        let limits = Limits::default();
+
+        assert_eq!(fmt_utc(0), "1970-01-01 00:00 UTC");
+        assert_eq!(fmt_utc(1_643_913_000), "2022-02-03 18:30 UTC");
+        assert_eq!(fmt_utc(951_782_400), "2000-02-29 00:00 UTC");
+        assert_eq!(
+            fmt_expiry(Some(3600), &limits, 1_643_913_000),
+            "2022-02-03 19:30 UTC"
+        );
+        assert_eq!(
+            fmt_expiry(None, &limits, 1_643_913_000),
+            "2022-02-03 19:00 UTC"
+        );
+        assert_eq!(fmt_expiry(Some(LIFESPAN_NEVER), &limits, 0), "never");
+    }
+
+    #[test]
+    fn server_capabilities() {
+        let capabilities: Capabilities = serde_json::from_str(
+            r#"{
+                "minLifespan": 60,
+                "maxLifespan": 3600,
+                "neverExpire": true,
+                "maxSize": 100,
+                "hashAlgorithms": ["sha256"],
+                "features": ["private", "comments"]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(capabilities.limits.max_lifespan, 3600);
+        assert!(capabilities.limits.never_expire);
+        assert_eq!(
+            capabilities.limits.default_lifespan,
+            Limits::default().default_lifespan
+        );
+
+        assert!(capabilities.require(Feature::Private).is_ok());
+        assert!(matches!(
+            capabilities.require(Feature::Revisions),
+            Err(Error::Unsupported(what)) if what == "revisions"
+        ));
+        assert!(capabilities.check_send(100).is_ok());
+        assert!(capabilities.check_send(101).is_err());
+
+        let md5 = Capabilities {
+            hash_algorithms: vec!["md5".to_owned()],
+            ..Capabilities::default()
+        };
+        assert!(md5.check_send(1).is_err());
+
+        // Servers advertising nothing are the bundled one
+        let bundled: Capabilities = serde_json::from_str("{}").unwrap();
+        assert_eq!(bundled, Capabilities::default());
+        assert!(Feature::ALL
+            .iter()
+            .all(|feature| bundled.require(*feature).is_ok()));
+    }
 }
@@ -0,0 +1,214 @@
+//! The service module
+//!
+//! Writes a systemd user unit (linux) or a launchd agent (macOS) running `gistit-daemon` in the
+//! foreground, so the p2p node is brought up on login and survives reboots.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::daemon::LOGFILE_NAME;
+use crate::{Error, Result};
+
+/// The systemd unit file name
+pub const SYSTEMD_UNIT_NAME: &str = "gistit-daemon.service";
+
+/// The launchd agent label, also used as the plist file name
+pub const LAUNCHD_LABEL: &str = "io.demfabris.gistit-daemon";
+
+/// A service definition ready to be written to disk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Service {
+    pub path: PathBuf,
+    pub contents: String,
+    /// Command the user should run to load the service
+    pub enable_hint: String,
+    /// Command the user should run to unload the service
+    pub disable_hint: String,
+}
+
+impl Service {
+    /// Builds the service definition for the current platform
+    ///
+    /// # Errors
+    ///
+    /// Fails if the platform has no supported service manager or the HOME directory is unknown
+    pub fn for_current_platform(
+        daemon_bin: &Path,
+        runtime_path: &Path,
+        config_path: &Path,
+    ) -> Result<Self> {
+        let home = env::var_os("HOME")
+            .map(PathBuf::from)
+            .ok_or(Error::Daemon("can't find the HOME directory"))?;
+
+        if cfg!(target_os = "macos") {
+            let path = home
+                .join("Library/LaunchAgents")
+                .join(format!("{}.plist", LAUNCHD_LABEL));
+
+            Ok(Self {
+                enable_hint: format!("launchctl load -w {}", path.to_string_lossy()),
+                disable_hint: format!("launchctl unload -w {}", path.to_string_lossy()),
+                contents: launchd_plist(daemon_bin, runtime_path, config_path),
+                path,
+            })
+        } else if cfg!(target_os = "linux") {
+            let config_home =
+                env::var_os("XDG_CONFIG_HOME").map_or_else(|| home.join(".config"), PathBuf::from);
+
+            Ok(Self {
+                path: config_home.join("systemd/user").join(SYSTEMD_UNIT_NAME),
+                contents: systemd_unit(daemon_bin, runtime_path, config_path),
+                enable_hint: format!("systemctl --user enable --now {}", SYSTEMD_UNIT_NAME),
+                disable_hint: format!("systemctl --user disable --now {}", SYSTEMD_UNIT_NAME),
+            })
+        } else {
+            Err(Error::Daemon(
+                "service installation is only supported on linux (systemd) and macOS (launchd)",
+            ))
+        }
+    }
+
+    /// Writes the service file, creating parent directories
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file exists and `force` isn't set, or if it can't be written
+    pub fn install(&self, force: bool) -> Result<()> {
+        if fs::metadata(&self.path).is_ok() && !force {
+            return Err(Error::Argument(
+                "service file already exists, use --force to overwrite it",
+                "--force",
+            ));
+        }
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.path, &self.contents)?;
+        Ok(())
+    }
+
+    /// Removes the service file
+    ///
+    /// # Errors
+    ///
+    /// Fails if the service isn't installed or the file can't be removed
+    pub fn uninstall(&self) -> Result<()> {
+        if fs::metadata(&self.path).is_err() {
+            return Err(Error::Daemon("gistit service is not installed"));
+        }
+
+        fs::remove_file(&self.path)?;
+        Ok(())
+    }
+}
+
+fn systemd_unit(daemon_bin: &Path, runtime_path: &Path, config_path: &Path) -> String {
+    format!(
+        r#"[Unit]
+Description=Gistit p2p node
+After=network-online.target
+Wants=network-online.target
+
+[Service]
+ExecStart="{}" --runtime-path "{}" --config-path "{}" --bootstrap
+Restart=on-failure
+RestartSec=5
+
+[Install]
+WantedBy=default.target
+"#,
+        daemon_bin.to_string_lossy(),
+        runtime_path.to_string_lossy(),
+        config_path.to_string_lossy(),
+    )
+}
+
+fn launchd_plist(daemon_bin: &Path, runtime_path: &Path, config_path: &Path) -> String {
+    let log_path = runtime_path.join(LOGFILE_NAME);
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{}</string>
+    <key>ProgramArguments</key>
+    <array>
+        <string>{}</string>
+        <string>--runtime-path</string>
+        <string>{}</string>
+        <string>--config-path</string>
+        <string>{}</string>
+        <string>--bootstrap</string>
+    </array>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <true/>
+    <key>StandardErrorPath</key>
+    <string>{}</string>
+</dict>
+</plist>
+"#,
+        LAUNCHD_LABEL,
+        daemon_bin.to_string_lossy(),
+        runtime_path.to_string_lossy(),
+        config_path.to_string_lossy(),
+        log_path.to_string_lossy(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn service_systemd_unit_points_at_daemon() {
+        let unit = systemd_unit(
+            Path::new("/usr/bin/gistit-daemon"),
+            Path::new("/run/user/1000"),
+            Path::new("/home/me/.config/gistit"),
+        );
+
+        assert!(unit.contains(
+            r#"ExecStart="/usr/bin/gistit-daemon" --runtime-path "/run/user/1000" --config-path "/home/me/.config/gistit" --bootstrap"#
+        ));
+        assert!(unit.contains("WantedBy=default.target"));
+    }
+
+    #[test]
+    fn service_launchd_plist_points_at_daemon() {
+        let plist = launchd_plist(
+            Path::new("/usr/local/bin/gistit-daemon"),
+            Path::new("/tmp"),
+            Path::new("/Users/me/Library/Application Support/gistit"),
+        );
+
+        assert!(plist.contains("<string>/usr/local/bin/gistit-daemon</string>"));
+        assert!(plist.contains("<string>/Users/me/Library/Application Support/gistit</string>"));
+        assert!(plist.contains(LAUNCHD_LABEL));
+    }
+
+    #[test]
+    fn service_install_and_uninstall() {
+        let tmp = assert_fs::TempDir::new().unwrap();
+        let service = Service {
+            path: tmp.path().join("systemd/user").join(SYSTEMD_UNIT_NAME),
+            contents: "[Unit]".to_owned(),
+            enable_hint: String::new(),
+            disable_hint: String::new(),
+        };
+
+        service.install(false).unwrap();
+        assert_eq!(fs::read_to_string(&service.path).unwrap(), "[Unit]");
+        assert!(service.install(false).is_err());
+        service.install(true).unwrap();
+
+        service.uninstall().unwrap();
+        assert!(service.uninstall().is_err());
+    }
+}
@@ -0,0 +1,837 @@
+//! The settings module
+//!
+//! User settings live in a TOML file inside the project config directory. Every field is optional
+//! so a missing or partially written file falls back to the built-in defaults.
+//!
+//! A repository can also ship a [`PROJECT_SETTINGS_FILE_NAME`] file, discovered by walking up from
+//! the current directory, to override the global settings for everyone working on it. The
+//! resolution order is: command line arguments, project settings, global settings, defaults.
+//!
+//! Both files may declare named profiles (`[profile.work]`, `[profile.personal]`). The profile
+//! selected with `--profile` or [`GISTIT_PROFILE_VAR`] is applied on top of each file before they
+//! are merged.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+
+use gistit_project::env::GISTIT_PROFILE_VAR;
+use gistit_project::path;
+use gistit_proto::payload::gistit;
+
+use crate::{Error, Result};
+
+/// The settings file name inside the config directory
+pub const SETTINGS_FILE_NAME: &str = "settings.toml";
+
+/// The per-project settings file name
+pub const PROJECT_SETTINGS_FILE_NAME: &str = ".gistit.toml";
+
+/// Keys accepted in the settings file
+pub const SETTINGS_KEYS: [&str; 21] = [
+    "colorscheme",
+    "author",
+    "backend",
+    "server_url",
+    "bootstrap",
+    "acl",
+    "relay",
+    "limits",
+    "storage",
+    "transport",
+    "notify",
+    "visibility",
+    "lifespan",
+    "secret_retries",
+    "telemetry",
+    "auto_description",
+    "workspace",
+    "quarantine",
+    "previewer",
+    "signing_key",
+    "profile",
+];
+
+/// Keys accepted inside a profile table
+pub const PROFILE_KEYS: [&str; 6] = [
+    "colorscheme",
+    "author",
+    "backend",
+    "server_url",
+    "lifespan",
+    "signing_key",
+];
+
+/// The colorscheme used when neither the arguments nor the settings specify one.
+/// This is the most decent looking
+pub const DEFAULT_COLORSCHEME: &str = "Monokai Extended Origin";
+
+/// Times `gistit fetch` asks again for a mistyped secret when the settings don't specify it
+pub const DEFAULT_SECRET_RETRIES: u8 = 2;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Colorscheme used to preview gistits
+    pub colorscheme: Option<String>,
+
+    /// Author attached to sent gistits. Defaults to a random generated name
+    pub author: Option<String>,
+
+    /// Where gistits are sent to and fetched from
+    pub backend: Option<Backend>,
+
+    /// Gistit server base url
+    pub server_url: Option<String>,
+
+    /// Bootstrap peer multiaddrs of the spawned `gistit-daemon`, replacing the default ones
+    pub bootstrap: Option<Vec<String>>,
+
+    /// Connection gating rules of the spawned `gistit-daemon`, `allow|deny <peer id|ip[/prefix]>`
+    pub acl: Option<Vec<String>>,
+
+    /// Desktop notifications of the spawned `gistit-daemon`, when a hosted gistit is fetched, a
+    /// push arrives or the node loses reachability
+    pub notify: Option<bool>,
+
+    /// Visibility of gistits sent to the server, `--private` overrides it
+    pub visibility: Option<Visibility>,
+
+    /// Lifespan of gistits sent to the server, like `--lifespan`. The server's default if unset
+    pub lifespan: Option<String>,
+
+    /// Times `gistit fetch` asks again for a secret typed at `--secret-prompt` that fails to
+    /// decrypt the gistit
+    pub secret_retries: Option<u8>,
+
+    /// Anonymous usage reporting, off by default. Only read from the global settings file
+    pub telemetry: Option<bool>,
+
+    /// Describe gistits sent without `--description` from their doc comments or signatures, on by
+    /// default
+    pub auto_description: Option<bool>,
+
+    /// Workspace gistits are sent to, encrypted with it's key and announced on it's topic. Set by
+    /// `gistit workspace create|join|use`
+    pub workspace: Option<String>,
+
+    /// Where `gistit fetch --save` writes gistits fetched from the p2p network, instead of the data
+    /// directory. Only read from the global settings file
+    pub quarantine: Option<PathBuf>,
+
+    /// Command previewing fetched gistits instead of the bundled bat, given the path of every file.
+    /// Only read from the global settings file
+    pub previewer: Option<String>,
+
+    /// Keypair of the spawned `gistit-daemon`, written by `gistit daemon identity export` or an
+    /// IPFS config file. It's the node peer id and signs published names, the stored identity is
+    /// used if unset. Only read from the global settings file
+    pub signing_key: Option<PathBuf>,
+
+    // Tables go last, toml can't serialize plain values after them
+    /// Relay server mode of the spawned `gistit-daemon`
+    pub relay: Option<Relay>,
+
+    /// Transfer limits of the spawned `gistit-daemon`, applied at runtime by `gistit daemon limits`
+    pub limits: Option<Limits>,
+
+    /// Disk storage of the spawned `gistit-daemon`, for fetched and pinned gistits
+    pub storage: Option<Storage>,
+
+    /// Listen addresses of the spawned `gistit-daemon`, per transport
+    pub transport: Option<Transport>,
+
+    /// Named profiles overriding the fields above
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub profile: BTreeMap<String, Profile>,
+}
+
+/// A named set of overrides, selected with `--profile`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    pub colorscheme: Option<String>,
+    pub author: Option<String>,
+    pub backend: Option<Backend>,
+    pub server_url: Option<String>,
+    pub lifespan: Option<String>,
+    pub signing_key: Option<PathBuf>,
+}
+
+impl From<Profile> for Settings {
+    fn from(profile: Profile) -> Self {
+        Self {
+            colorscheme: profile.colorscheme,
+            author: profile.author,
+            backend: profile.backend,
+            server_url: profile.server_url,
+            bootstrap: None,
+            acl: None,
+            relay: None,
+            limits: None,
+            storage: None,
+            transport: None,
+            notify: None,
+            visibility: None,
+            lifespan: profile.lifespan,
+            secret_retries: None,
+            telemetry: None,
+            auto_description: None,
+            workspace: None,
+            quarantine: None,
+            previewer: None,
+            signing_key: profile.signing_key,
+            profile: BTreeMap::default(),
+        }
+    }
+}
+
+lazy_static! {
+    /// The profile in use, `--profile` overrides [`GISTIT_PROFILE_VAR`]
+    static ref PROFILE: Mutex<Option<String>> = Mutex::new(env::var(GISTIT_PROFILE_VAR).ok());
+}
+
+/// Selects the profile applied by [`get_runtime_settings`]
+pub fn select_profile(name: &str) {
+    *PROFILE.lock().unwrap() = Some(name.to_owned());
+}
+
+/// The backend used to send and fetch gistits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// Use the p2p network if `gistit-daemon` is running, the server otherwise
+    Auto,
+    /// Always use the server
+    Server,
+    /// Always use the p2p network, `gistit-daemon` is spawned on demand
+    P2p,
+}
+
+impl Default for Backend {
+    fn default() -> Self {
+        Self::Auto
+    }
+}
+
+impl Backend {
+    /// Whether to go through `gistit-daemon`, given it's running or not
+    ///
+    /// # Errors
+    ///
+    /// Fails if this backend requires the daemon and it isn't running
+    pub const fn use_daemon(self, daemon_alive: bool) -> Result<bool> {
+        match self {
+            Self::Auto => Ok(daemon_alive),
+            Self::Server => Ok(false),
+            Self::P2p if daemon_alive => Ok(true),
+            Self::P2p => Err(Error::Daemon(
+                "gistit node is not running, start it with `gistit node --start`",
+            )),
+        }
+    }
+}
+
+/// Who can fetch a gistit sent to the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Visibility {
+    Public,
+    /// Fetchable by anyone with the hash, never listed
+    Unlisted,
+    /// Only fetchable with the owner token, kept in the OS credential store
+    Private,
+}
+
+impl Default for Visibility {
+    fn default() -> Self {
+        Self::Public
+    }
+}
+
+impl From<Visibility> for gistit::Visibility {
+    fn from(visibility: Visibility) -> Self {
+        match visibility {
+            Visibility::Public => Self::Public,
+            Visibility::Unlisted => Self::Unlisted,
+            Visibility::Private => Self::Private,
+        }
+    }
+}
+
+/// Relay server quotas, unset ones use the daemon defaults. Only takes effect for public nodes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Relay {
+    pub enabled: bool,
+    pub max_reservations: Option<usize>,
+    pub max_circuits: Option<usize>,
+    /// Seconds
+    pub max_circuit_duration: Option<u64>,
+    pub max_circuit_bytes: Option<u64>,
+}
+
+impl Relay {
+    /// The `gistit-daemon` arguments enabling relay server mode, empty if disabled
+    #[must_use]
+    pub fn daemon_args(&self) -> Vec<String> {
+        if !self.enabled {
+            return Vec::new();
+        }
+
+        let mut args = vec!["--relay-server".to_owned()];
+        let quotas = [
+            (
+                "--relay-max-reservations",
+                self.max_reservations.map(|n| n as u64),
+            ),
+            ("--relay-max-circuits", self.max_circuits.map(|n| n as u64)),
+            ("--relay-max-circuit-duration", self.max_circuit_duration),
+            ("--relay-max-circuit-bytes", self.max_circuit_bytes),
+        ];
+        for (flag, value) in quotas {
+            if let Some(value) = value {
+                args.push(flag.to_owned());
+                args.push(value.to_string());
+            }
+        }
+        args
+    }
+}
+
+/// Transfer limits, unset or zero ones are unlimited
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Limits {
+    /// Bytes per second
+    pub upload_rate: Option<u64>,
+    pub download_rate: Option<u64>,
+    /// Requests per minute a single peer can make
+    pub peer_request_rate: Option<u32>,
+}
+
+impl Limits {
+    /// The `gistit-daemon` arguments applying these limits
+    #[must_use]
+    pub fn daemon_args(&self) -> Vec<String> {
+        let limits = [
+            ("--upload-rate", self.upload_rate),
+            ("--download-rate", self.download_rate),
+            ("--peer-request-rate", self.peer_request_rate.map(u64::from)),
+        ];
+
+        limits
+            .into_iter()
+            .filter_map(|(flag, value)| value.map(|value| [flag.to_owned(), value.to_string()]))
+            .flatten()
+            .collect()
+    }
+}
+
+/// Disk storage of fetched and pinned gistits
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Storage {
+    /// Bytes, least recently used unpinned gistits are evicted past it
+    pub quota: Option<u64>,
+}
+
+impl Storage {
+    /// The `gistit-daemon` arguments applying this storage quota
+    #[must_use]
+    pub fn daemon_args(&self) -> Vec<String> {
+        self.quota
+            .map(|quota| vec!["--storage-quota".to_owned(), quota.to_string()])
+            .unwrap_or_default()
+    }
+}
+
+/// Extra listen multiaddrs per transport, on top of the TCP and QUIC ones of `--host` and
+/// `--port`, and what connections are secured with
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Transport {
+    /// Plain TCP, `/ip4/0.0.0.0/tcp/4001`
+    pub tcp: Option<Vec<String>>,
+    /// WebSocket over TCP, `/ip4/0.0.0.0/tcp/4002/ws`
+    pub websocket: Option<Vec<String>>,
+    /// QUIC, `/ip4/0.0.0.0/udp/4001/quic-v1`. Dialed first when a peer has both
+    pub quic: Option<Vec<String>>,
+    /// `noise` or `tls`, for TCP and WebSocket connections. QUIC always uses TLS
+    pub security: Option<String>,
+}
+
+impl Transport {
+    /// Every listen address, with the transport key it's under
+    pub fn listen_addrs(&self) -> impl Iterator<Item = (&'static str, &String)> {
+        let tcp = self.tcp.iter().flatten().map(|address| ("tcp", address));
+        let websocket = self.websocket.iter().flatten();
+        let quic = self.quic.iter().flatten();
+
+        tcp.chain(websocket.map(|address| ("websocket", address)))
+            .chain(quic.map(|address| ("quic", address)))
+    }
+
+    /// The `gistit-daemon` arguments listening on every address
+    #[must_use]
+    pub fn daemon_args(&self) -> Vec<String> {
+        let mut args: Vec<String> = self
+            .listen_addrs()
+            .flat_map(|(_, address)| ["--listen".to_owned(), address.clone()])
+            .collect();
+        if let Some(ref security) = self.security {
+            args.extend(["--security".to_owned(), security.clone()]);
+        }
+        args
+    }
+}
+
+/// Settings that can be layered on top of each other
+pub trait Mergeable {
+    /// Fills every field unset in `self` with the one from `other`
+    #[must_use]
+    fn merge(self, other: Self) -> Self;
+}
+
+impl Mergeable for Settings {
+    fn merge(self, other: Self) -> Self {
+        let mut profile = other.profile;
+        profile.extend(self.profile);
+
+        Self {
+            colorscheme: self.colorscheme.or(other.colorscheme),
+            author: self.author.or(other.author),
+            backend: self.backend.or(other.backend),
+            server_url: self.server_url.or(other.server_url),
+            bootstrap: self.bootstrap.or(other.bootstrap),
+            acl: self.acl.or(other.acl),
+            relay: self.relay.or(other.relay),
+            limits: self.limits.or(other.limits),
+            storage: self.storage.or(other.storage),
+            transport: self.transport.or(other.transport),
+            notify: self.notify.or(other.notify),
+            visibility: self.visibility.or(other.visibility),
+            lifespan: self.lifespan.or(other.lifespan),
+            secret_retries: self.secret_retries.or(other.secret_retries),
+            telemetry: self.telemetry.or(other.telemetry),
+            auto_description: self.auto_description.or(other.auto_description),
+            workspace: self.workspace.or(other.workspace),
+            quarantine: self.quarantine.or(other.quarantine),
+            previewer: self.previewer.or(other.previewer),
+            signing_key: self.signing_key.or(other.signing_key),
+            profile,
+        }
+    }
+}
+
+impl Settings {
+    /// Path of the settings file
+    ///
+    /// # Errors
+    ///
+    /// Fails if the system doesn't have a HOME directory
+    pub fn path() -> Result<PathBuf> {
+        Ok(path::config()?.join(SETTINGS_FILE_NAME))
+    }
+
+    /// Reads the settings file, defaulting every field if it doesn't exist
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file exists but can't be read or isn't valid TOML
+    pub fn load() -> Result<Self> {
+        Self::load_from(&Self::path()?)
+    }
+
+    /// Reads the closest project settings file, defaulting every field if there is none
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file exists but can't be read or isn't valid TOML
+    pub fn load_project() -> Result<Self> {
+        project_path()?.map_or_else(|| Ok(Self::default()), |path| Self::load_from(&path))
+    }
+
+    /// Reads the settings file at `settings_path`, defaulting every field if it doesn't exist
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file exists but can't be read or isn't valid TOML
+    pub fn load_from(settings_path: &Path) -> Result<Self> {
+        if fs::metadata(settings_path).is_ok() {
+            Ok(toml::from_str(&fs::read_to_string(settings_path)?)?)
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    /// Writes these settings to the settings file, overwriting it
+    ///
+    /// # Errors
+    ///
+    /// Fails if can't serialize or write to the config directory
+    pub fn save(&self) -> Result<()> {
+        self.save_to(&Self::path()?)
+    }
+
+    /// Writes these settings to `settings_path`, overwriting it
+    ///
+    /// # Errors
+    ///
+    /// Fails if can't serialize or write to `settings_path`
+    pub fn save_to(&self, settings_path: &Path) -> Result<()> {
+        fs::write(settings_path, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Applies the named profile on top of these settings, if declared
+    #[must_use]
+    pub fn with_profile(mut self, name: &str) -> Self {
+        match self.profile.remove(name) {
+            Some(profile) => Self::from(profile).merge(self),
+            None => self,
+        }
+    }
+
+    /// The colorscheme to use, falling back to [`DEFAULT_COLORSCHEME`]
+    #[must_use]
+    pub fn colorscheme(&self) -> &str {
+        self.colorscheme.as_deref().unwrap_or(DEFAULT_COLORSCHEME)
+    }
+
+    /// The backend to use, falling back to [`Backend::Auto`]
+    #[must_use]
+    pub fn backend(&self) -> Backend {
+        self.backend.unwrap_or_default()
+    }
+
+    /// The visibility of sent gistits, falling back to [`Visibility::Public`]
+    #[must_use]
+    pub fn visibility(&self) -> Visibility {
+        self.visibility.unwrap_or_default()
+    }
+
+    /// Times to ask again for a mistyped secret, falling back to [`DEFAULT_SECRET_RETRIES`]
+    #[must_use]
+    pub fn secret_retries(&self) -> u8 {
+        self.secret_retries.unwrap_or(DEFAULT_SECRET_RETRIES)
+    }
+
+    /// Whether anonymous usage reporting is on, off unless opted in
+    #[must_use]
+    pub fn telemetry(&self) -> bool {
+        self.telemetry.unwrap_or(false)
+    }
+
+    /// Whether gistits sent without a description get one from their content, on unless opted out
+    #[must_use]
+    pub fn auto_description(&self) -> bool {
+        self.auto_description.unwrap_or(true)
+    }
+}
+
+/// Walks up from the current directory looking for [`PROJECT_SETTINGS_FILE_NAME`]
+///
+/// # Errors
+///
+/// Fails if the current directory is invalid
+pub fn project_path() -> Result<Option<PathBuf>> {
+    let cwd = env::current_dir()?;
+    Ok(find_project_file(&cwd))
+}
+
+fn find_project_file(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .map(|dir| dir.join(PROJECT_SETTINGS_FILE_NAME))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Returns the settings in effect for this run, project settings taking precedence and the
+/// selected profile applied to each of them
+///
+/// # Errors
+///
+/// Inherits errors of [`Settings::load`] and [`Settings::load_project`]. Fails if the selected
+/// profile isn't declared in any settings file
+pub fn get_runtime_settings() -> Result<Settings> {
+    let project = Settings::load_project()?;
+    let global = Settings::load()?;
+    let selected = PROFILE.lock().unwrap().clone();
+
+    resolve(project, global, selected.as_deref())
+}
+
+/// Returns the global settings file alone with the selected profile applied, for the settings a
+/// project settings file mustn't override
+///
+/// # Errors
+///
+/// Inherits errors of [`Settings::load`]
+pub fn get_global_settings() -> Result<Settings> {
+    let global = Settings::load()?;
+    let selected = PROFILE.lock().unwrap().clone();
+
+    Ok(match selected {
+        Some(name) => global.with_profile(&name),
+        None => global,
+    })
+}
+
+fn resolve(project: Settings, global: Settings, profile: Option<&str>) -> Result<Settings> {
+    match profile {
+        Some(name) => {
+            if !project.profile.contains_key(name) && !global.profile.contains_key(name) {
+                return Err(Error::Argument("unknown settings profile", "--profile"));
+            }
+            Ok(project.with_profile(name).merge(global.with_profile(name)))
+        }
+        None => Ok(project.merge(global)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::prelude::*;
+
+    #[test]
+    fn settings_missing_fields_default() {
+        let settings: Settings = toml::from_str("").unwrap();
+        assert_eq!(settings, Settings::default());
+        assert_eq!(settings.colorscheme(), DEFAULT_COLORSCHEME);
+        assert_eq!(settings.backend(), Backend::Auto);
+        assert_eq!(settings.visibility(), Visibility::Public);
+        assert_eq!(settings.secret_retries(), DEFAULT_SECRET_RETRIES);
+        assert!(!settings.telemetry());
+        assert!(settings.auto_description());
+    }
+
+    #[test]
+    fn settings_serialize_roundtrip() {
+        let settings = Settings {
+            colorscheme: Some("Dracula".to_owned()),
+            author: Some("Matthew McConaughey".to_owned()),
+            backend: Some(Backend::P2p),
+            server_url: None,
+            bootstrap: Some(vec![
+                "/ip4/10.0.0.1/tcp/4001/p2p/12D3KooWDpJ7As7BWAwRMfu1VU2WCqNjvq387JEYKDBj4kx6nXTN"
+                    .to_owned(),
+            ]),
+            acl: Some(vec!["deny 10.0.0.0/8".to_owned()]),
+            relay: Some(Relay {
+                enabled: true,
+                max_circuits: Some(8),
+                ..Relay::default()
+            }),
+            limits: Some(Limits {
+                upload_rate: Some(1_048_576),
+                ..Limits::default()
+            }),
+            storage: Some(Storage {
+                quota: Some(1_048_576),
+            }),
+            transport: Some(Transport {
+                tcp: Some(vec!["/ip4/0.0.0.0/tcp/4001".to_owned()]),
+                websocket: None,
+                quic: Some(vec!["/ip4/0.0.0.0/udp/4001/quic-v1".to_owned()]),
+                security: Some("noise".to_owned()),
+            }),
+            notify: Some(true),
+            visibility: Some(Visibility::Unlisted),
+            lifespan: Some("2d".to_owned()),
+            secret_retries: Some(0),
+            telemetry: Some(true),
+            auto_description: Some(false),
+            workspace: Some("team".to_owned()),
+            quarantine: Some(PathBuf::from("/tmp/gistit-quarantine")),
+            previewer: Some("delta --paging never".to_owned()),
+            signing_key: Some(PathBuf::from("/home/me/.gistit/personal.key")),
+            profile: BTreeMap::default(),
+        };
+        let encoded = toml::to_string_pretty(&settings).unwrap();
+        let decoded: Settings = toml::from_str(&encoded).unwrap();
+
+        assert_eq!(decoded, settings);
+        assert_eq!(decoded.colorscheme(), "Dracula");
+    }
+
+    #[test]
+    fn settings_merge_precedence() {
+        let project: Settings = toml::from_str(
+            r#"
+author = "team"
+lifespan = "1d"
+"#,
+        )
+        .unwrap();
+        let global: Settings = toml::from_str(
+            r#"
+author = "me"
+colorscheme = "Nord"
+backend = "server"
+lifespan = "30m"
+"#,
+        )
+        .unwrap();
+        let merged = project.merge(global);
+
+        assert_eq!(merged.author.as_deref(), Some("team"));
+        assert_eq!(merged.colorscheme(), "Nord");
+        assert_eq!(merged.backend(), Backend::Server);
+        assert_eq!(merged.lifespan.as_deref(), Some("1d"));
+    }
+
+    #[test]
+    fn settings_profile_aware_merge() {
+        let project: Settings = toml::from_str(
+            r#"
+author = "team"
+
+[profile.personal]
+backend = "p2p"
+"#,
+        )
+        .unwrap();
+        let global: Settings = toml::from_str(
+            r#"
+author = "me"
+server_url = "https://example.com/"
+
+[profile.work]
+author = "me at work"
+server_url = "https://gistit.work.com/"
+lifespan = "7d"
+signing_key = "/home/me/.gistit/work.key"
+
+[profile.personal]
+colorscheme = "Dracula"
+"#,
+        )
+        .unwrap();
+
+        let work = resolve(project.clone(), global.clone(), Some("work")).unwrap();
+        assert_eq!(work.author.as_deref(), Some("team"));
+        assert_eq!(work.server_url.as_deref(), Some("https://gistit.work.com/"));
+        assert_eq!(work.lifespan.as_deref(), Some("7d"));
+        assert_eq!(
+            work.signing_key,
+            Some(PathBuf::from("/home/me/.gistit/work.key"))
+        );
+
+        let personal = resolve(project.clone(), global.clone(), Some("personal")).unwrap();
+        assert_eq!(personal.backend(), Backend::P2p);
+        assert_eq!(personal.colorscheme(), "Dracula");
+        assert_eq!(personal.server_url.as_deref(), Some("https://example.com/"));
+        assert_eq!(personal.signing_key, None);
+
+        let none = resolve(project.clone(), global.clone(), None).unwrap();
+        assert_eq!(none.server_url.as_deref(), Some("https://example.com/"));
+        assert_eq!(none.backend(), Backend::Auto);
+        assert_eq!(none.lifespan, None);
+
+        assert!(resolve(project, global, Some("missing")).is_err());
+    }
+
+    #[test]
+    fn settings_backend_use_daemon() {
+        assert!(Backend::Auto.use_daemon(true).unwrap());
+        assert!(!Backend::Auto.use_daemon(false).unwrap());
+        assert!(!Backend::Server.use_daemon(true).unwrap());
+        assert!(Backend::P2p.use_daemon(true).unwrap());
+        assert!(Backend::P2p.use_daemon(false).is_err());
+    }
+
+    #[test]
+    fn settings_limits_daemon_args() {
+        assert!(Limits::default().daemon_args().is_empty());
+
+        let limits: Limits = toml::from_str("upload_rate = 1024\npeer_request_rate = 30").unwrap();
+        assert_eq!(
+            limits.daemon_args(),
+            vec!["--upload-rate", "1024", "--peer-request-rate", "30"]
+        );
+    }
+
+    #[test]
+    fn settings_storage_daemon_args() {
+        assert!(Storage::default().daemon_args().is_empty());
+
+        let storage: Storage = toml::from_str("quota = 1024").unwrap();
+        assert_eq!(storage.daemon_args(), vec!["--storage-quota", "1024"]);
+    }
+
+    #[test]
+    fn settings_transport_daemon_args() {
+        assert!(Transport::default().daemon_args().is_empty());
+
+        let transport: Transport = toml::from_str(
+            r#"
+tcp = ["/ip4/0.0.0.0/tcp/4001"]
+websocket = ["/ip4/0.0.0.0/tcp/4002/ws"]
+quic = ["/ip4/0.0.0.0/udp/4001/quic-v1"]
+security = "tls"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            transport.daemon_args(),
+            vec![
+                "--listen",
+                "/ip4/0.0.0.0/tcp/4001",
+                "--listen",
+                "/ip4/0.0.0.0/tcp/4002/ws",
+                "--listen",
+                "/ip4/0.0.0.0/udp/4001/quic-v1",
+                "--security",
+                "tls"
+            ]
+        );
+    }
+
+    #[test]
+    fn settings_relay_daemon_args() {
+        assert!(Relay::default().daemon_args().is_empty());
+
+        let relay: Relay = toml::from_str(
+            r#"
+enabled = true
+max_circuits = 16
+max_circuit_duration = 120
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            relay.daemon_args(),
+            vec![
+                "--relay-server",
+                "--relay-max-circuits",
+                "16",
+                "--relay-max-circuit-duration",
+                "120"
+            ]
+        );
+    }
+
+    #[test]
+    fn settings_find_project_file_walking_up() {
+        let tmp = assert_fs::TempDir::new().unwrap();
+        let nested = tmp.child("foo/bar/baz");
+        nested.create_dir_all().unwrap();
+
+        assert_eq!(find_project_file(&nested), None);
+
+        let project_file = tmp.child("foo").child(PROJECT_SETTINGS_FILE_NAME);
+        project_file.touch().unwrap();
+
+        assert_eq!(
+            find_project_file(&nested),
+            Some(project_file.path().to_path_buf())
+        );
+    }
+}
@@ -0,0 +1,125 @@
+//! The signal module
+//!
+//! SIGINT (Ctrl-C) and SIGTERM end the running command gracefully instead of killing it halfway:
+//! requests in flight are dropped, fetches `gistit-daemon` runs for us are canceled so it doesn't
+//! keep downloading, and the terminal is given back as it was found.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::io;
+use std::sync::Mutex;
+
+use console::Term;
+use crossterm::execute;
+use crossterm::terminal::{disable_raw_mode, is_raw_mode_enabled, LeaveAlternateScreen};
+use lazy_static::lazy_static;
+
+use gistit_project::path;
+use gistit_proto::Instruction;
+
+use crate::error::exit;
+
+lazy_static! {
+    /// Hashes `gistit-daemon` is fetching, or fetching the head of, for this command
+    static ref PENDING: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    Interrupt,
+    Terminate,
+}
+
+impl Signal {
+    /// Status to exit with, `128 + signal number` like shells do
+    #[must_use]
+    pub const fn exit_code(self) -> i32 {
+        match self {
+            Self::Interrupt => exit::INTERRUPTED,
+            Self::Terminate => exit::TERMINATED,
+        }
+    }
+}
+
+impl fmt::Display for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Interrupt => write!(f, "SIGINT"),
+            Self::Terminate => write!(f, "SIGTERM"),
+        }
+    }
+}
+
+/// Resolves with the first SIGINT or SIGTERM received, or never if they can't be listened to
+pub async fn received() -> Signal {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        if let Ok(mut terminate) = signal(SignalKind::terminate()) {
+            return tokio::select! {
+                _ = interrupt() => Signal::Interrupt,
+                _ = terminate.recv() => Signal::Terminate,
+            };
+        }
+    }
+
+    interrupt().await;
+    Signal::Interrupt
+}
+
+/// Ctrl-C, on every platform
+async fn interrupt() {
+    if tokio::signal::ctrl_c().await.is_err() {
+        std::future::pending::<()>().await;
+    }
+}
+
+/// A fetch `gistit-daemon` runs for this command, canceled by [`cleanup`] until dropped
+#[derive(Debug)]
+pub struct Pending(String);
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        PENDING.lock().unwrap().remove(&self.0);
+    }
+}
+
+/// Marks `hash` as being fetched through `gistit-daemon` while the returned guard lives
+#[must_use]
+pub fn pending(hash: &str) -> Pending {
+    PENDING.lock().unwrap().insert(hash.to_owned());
+    Pending(hash.to_owned())
+}
+
+/// Cancels the fetches `gistit-daemon` runs for this command and restores the terminal, best
+/// effort since it's on the way out
+pub async fn cleanup() {
+    let pending: Vec<String> = PENDING.lock().unwrap().iter().cloned().collect();
+    if !pending.is_empty() {
+        if let Err(err) = cancel(pending).await {
+            tracing::debug!("failed to cancel daemon fetches: {}", err);
+        }
+    }
+
+    // Left in raw mode and the alternate screen by `gistit tui`
+    if is_raw_mode_enabled().unwrap_or(false) {
+        let _ = disable_raw_mode();
+        let _ = execute!(io::stdout(), LeaveAlternateScreen);
+    }
+    let _ = Term::stderr().show_cursor();
+}
+
+async fn cancel(hashes: Vec<String>) -> crate::Result<()> {
+    let mut bridge = gistit_ipc::client(&path::runtime_dir()?)?;
+    if !bridge.alive() {
+        return Ok(());
+    }
+
+    bridge.connect_blocking()?;
+    for hash in hashes {
+        tracing::debug!(%hash, "canceling daemon fetch");
+        bridge.send(Instruction::request_cancel(hash)).await?;
+    }
+    Ok(())
+}
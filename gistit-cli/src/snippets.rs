@@ -0,0 +1,415 @@
+//! The snippets module
+//!
+//! A local library of frequently shared snippets, kept in the project config directory. Each
+//! snippet is a copy of the file taken with `gistit snippets add <name> <file>`, along with the
+//! description and lifespan to send it with. `gistit snippets use <name>` sends it again in one go.
+
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use clap::ArgMatches;
+use console::style;
+use serde::{Deserialize, Serialize};
+
+use gistit_project::path;
+
+use crate::cache::{self, Entry, Origin};
+use crate::daemon;
+use crate::dispatch::Dispatch;
+use crate::file::File;
+use crate::param::check;
+use crate::pin::fmt_ago;
+use crate::send::{first_file_name, new_gistit, provide, random_author, send_server, share_url};
+use crate::server::{self, LIFESPAN_NEVER};
+use crate::settings::{get_runtime_settings, Backend};
+use crate::{finish, progress, updateln, warnln, Error, Result};
+
+/// The library directory inside the config directory
+pub const SNIPPETS_DIR_NAME: &str = "snippets";
+
+/// The metadata file inside the library directory
+const LIBRARY_FILE_NAME: &str = "library.toml";
+
+#[derive(Debug, Clone)]
+pub enum Action<'a> {
+    Add {
+        name: &'a str,
+        file_path: &'a OsStr,
+        description: Option<&'a str>,
+        lifespan: Option<&'a str>,
+        force: bool,
+    },
+    List,
+    Use {
+        name: &'a str,
+        author: Option<&'a str>,
+    },
+}
+
+impl<'a> Action<'a> {
+    pub fn from_args(
+        args: &'a ArgMatches,
+    ) -> Result<Box<dyn Dispatch<InnerData = Config> + Send + Sync + 'a>> {
+        let name = |args: &'a ArgMatches| {
+            args.value_of("NAME")
+                .ok_or(Error::Argument("missing argument", "[NAME]"))
+        };
+
+        let action = match args.subcommand() {
+            Some(("add", args)) => Self::Add {
+                name: name(args)?,
+                file_path: args
+                    .value_of_os("FILE")
+                    .ok_or(Error::Argument("missing argument", "[FILE]"))?,
+                description: args.value_of("description"),
+                lifespan: args.value_of("lifespan"),
+                force: args.is_present("force"),
+            },
+            Some(("ls", _)) => Self::List,
+            Some(("use", args)) => Self::Use {
+                name: name(args)?,
+                author: args.value_of("author"),
+            },
+            _ => return Err(Error::Argument("missing subcommand", "snippets")),
+        };
+        Ok(Box::new(action))
+    }
+}
+
+pub struct Config {
+    library: Library,
+    backend: Backend,
+    author: Option<String>,
+    /// Of the snippet being added, in seconds
+    lifespan: Option<u64>,
+    runtime_path: PathBuf,
+    config_path: PathBuf,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snippet {
+    pub name: String,
+    /// File name of the stored copy
+    pub file: String,
+    pub description: Option<String>,
+    /// Seconds the server keeps it, forever if [`LIFESPAN_NEVER`]
+    pub lifespan: Option<u64>,
+    /// Seconds since unix epoch
+    pub added: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Library {
+    #[serde(default)]
+    pub snippet: Vec<Snippet>,
+}
+
+impl Library {
+    /// Path of the library directory
+    ///
+    /// # Errors
+    ///
+    /// Fails if the system doesn't have a HOME directory
+    pub fn dir() -> Result<PathBuf> {
+        Ok(path::config()?.join(SNIPPETS_DIR_NAME))
+    }
+
+    /// Reads the library, empty if it doesn't exist
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file exists but can't be read or decoded
+    pub fn load() -> Result<Self> {
+        let library_path = Self::dir()?.join(LIBRARY_FILE_NAME);
+
+        if fs::metadata(&library_path).is_ok() {
+            Ok(toml::from_str(&fs::read_to_string(&library_path)?)?)
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    /// Writes the library, overwriting it
+    ///
+    /// # Errors
+    ///
+    /// Fails if can't write to the config directory
+    pub fn save(&self) -> Result<()> {
+        let dir = Self::dir()?;
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join(LIBRARY_FILE_NAME), toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&Snippet> {
+        self.snippet.iter().find(|snippet| snippet.name == name)
+    }
+
+    /// Adds a snippet, replacing the one with the same name
+    pub fn insert(&mut self, snippet: Snippet) {
+        self.snippet.retain(|other| other.name != snippet.name);
+        self.snippet.push(snippet);
+        self.snippet.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+
+    /// Where the copy of `snippet` is stored
+    ///
+    /// # Errors
+    ///
+    /// Fails if the system doesn't have a HOME directory
+    pub fn path_of(snippet: &Snippet) -> Result<PathBuf> {
+        Ok(Self::dir()?.join(&snippet.name).join(&snippet.file))
+    }
+}
+
+#[async_trait]
+impl<'a> Dispatch for Action<'a> {
+    type InnerData = Config;
+
+    async fn prepare(&self) -> Result<Self::InnerData> {
+        let settings = get_runtime_settings()?;
+        let mut parsed_lifespan = None;
+        let author = match self {
+            Self::Add {
+                name,
+                file_path,
+                description,
+                lifespan,
+                ..
+            } => {
+                check::snippet(name)?;
+                let path = Path::new(file_path);
+                check::metadata(&fs::metadata(&path)?)?;
+                check::extension(path.extension())?;
+                description.map(check::description).transpose()?;
+                if let Some(lifespan) = lifespan {
+                    parsed_lifespan = Some(check::lifespan(lifespan, &server::limits().await)?);
+                }
+                None
+            }
+            Self::List => None,
+            Self::Use { name, author } => {
+                check::snippet(name)?;
+                let author = match (*author, settings.author.as_deref()) {
+                    (Some(author), _) | (None, Some(author)) => check::author(author)?.to_owned(),
+                    (None, None) => random_author(),
+                };
+                Some(author)
+            }
+        };
+
+        Ok(Config {
+            library: Library::load()?,
+            backend: settings.backend(),
+            author,
+            lifespan: parsed_lifespan,
+            runtime_path: path::runtime_dir()?,
+            config_path: path::config()?,
+        })
+    }
+
+    async fn dispatch(&self, config: Self::InnerData) -> Result<()> {
+        let mut library = config.library;
+
+        match self {
+            Self::Add {
+                name,
+                file_path,
+                description,
+                force,
+                ..
+            } => {
+                if library.get(name).is_some() && !force {
+                    return Err(Error::Argument(
+                        "snippet already exists, replace it with `--force`",
+                        "[NAME]",
+                    ));
+                }
+                let file = File::from_path(Path::new(file_path))?;
+                let snippet = Snippet {
+                    name: (*name).to_owned(),
+                    file: file.name(),
+                    description: description.map(ToOwned::to_owned),
+                    lifespan: config.lifespan,
+                    added: now(),
+                };
+
+                let dir = Library::dir()?.join(name);
+                if fs::metadata(&dir).is_ok() {
+                    fs::remove_dir_all(&dir)?;
+                }
+                fs::create_dir_all(&dir)?;
+                fs::write(Library::path_of(&snippet)?, &*file.contents()?)?;
+                library.insert(snippet);
+                library.save()?;
+
+                finish!(format!(
+                    "\n    snippet: '{}'\n    send it with `gistit snippets use {}`\n\n",
+                    style(name).bold(),
+                    name
+                ));
+            }
+            Self::List => {
+                finish!(format_library(&library, now()));
+            }
+            Self::Use { name, .. } => {
+                let snippet = library.get(name).ok_or(Error::Argument(
+                    "no such snippet, see `gistit snippets ls`",
+                    "[NAME]",
+                ))?;
+                let file = File::from_path(&Library::path_of(snippet)?)?;
+                let author = config.author.unwrap_or_else(random_author);
+                let mut gistit = new_gistit(&file, author, snippet.description.as_deref())?;
+                gistit.lifespan = snippet.lifespan;
+
+                let mut bridge = gistit_ipc::client(&config.runtime_path)?;
+                daemon::spawn_if_required(
+                    config.backend,
+                    &bridge,
+                    &config.runtime_path,
+                    &config.config_path,
+                )
+                .await?;
+                let use_daemon = config.backend.use_daemon(bridge.alive())?;
+                let name = first_file_name(&gistit);
+                let author = gistit.author.clone();
+
+                if use_daemon {
+                    if snippet.lifespan.is_some() {
+                        warnln!(
+                            "lifespans are only used by the server, hosted gistits don't expire"
+                        );
+                    }
+                    progress!("Hosting");
+                    bridge.connect_blocking()?;
+                    let hash = provide(&mut bridge, gistit, Vec::new()).await?;
+                    cache::record_or_warn(Entry::new(&hash, &name, &author, Origin::Hosted));
+
+                    updateln!("Hosted");
+                    finish!(format!(
+                        "\n    id: '{}'\n    fetch it with `gistit fetch @{}`\n\n",
+                        style(format!("@{}", hash)).bold(),
+                        hash
+                    ));
+                } else {
+                    progress!("Sending");
+                    let hash = send_server(&gistit).await?;
+                    cache::record_or_warn(Entry::new(&hash, &name, &author, Origin::Sent));
+
+                    updateln!("Sent");
+                    finish!(format!(
+                        "\n    hash: '{}'\n    url: '{}'\n\n",
+                        style(&hash).bold(),
+                        style(share_url(&hash)).blue()
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Check your system time")
+        .as_secs()
+}
+
+fn format_library(library: &Library, now: u64) -> String {
+    let mut lines: Vec<String> = library
+        .snippet
+        .iter()
+        .map(|snippet| {
+            let mut line = format!(
+                "    {}  {}  {}",
+                style(&snippet.name).bold(),
+                snippet.file,
+                style(fmt_ago(now.saturating_sub(snippet.added))).dim()
+            );
+            if let Some(lifespan) = snippet.lifespan {
+                line.push_str(&format!("  lives {}", fmt_lifespan(lifespan)));
+            }
+            if let Some(ref description) = snippet.description {
+                line.push_str(&format!("\n      {}", style(description).italic()));
+            }
+            line
+        })
+        .collect();
+    if lines.is_empty() {
+        lines.push("    no snippets, add one with `gistit snippets add <name> <file>`".to_owned());
+    }
+
+    format!("\n{}\n\n", lines.join("\n"))
+}
+
+/// The largest whole unit, as accepted by `--lifespan`
+fn fmt_lifespan(secs: u64) -> String {
+    match secs {
+        LIFESPAN_NEVER => "never".to_owned(),
+        secs if secs % 86_400 == 0 => format!("{}d", secs / 86_400),
+        secs if secs % 3600 == 0 => format!("{}h", secs / 3600),
+        secs if secs % 60 == 0 => format!("{}m", secs / 60),
+        secs => format!("{}s", secs),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snippet(name: &str) -> Snippet {
+        Snippet {
+            name: name.to_owned(),
+            file: "main.rs".to_owned(),
+            description: None,
+            lifespan: None,
+            added: 0,
+        }
+    }
+
+    #[test]
+    fn snippets_library_insert_replaces_and_sorts() {
+        let mut library = Library::default();
+        library.insert(snippet("serve"));
+        library.insert(snippet("deploy"));
+        library.insert(Snippet {
+            lifespan: Some(3600),
+            ..snippet("serve")
+        });
+
+        assert_eq!(library.snippet.len(), 2);
+        assert_eq!(library.snippet[0].name, "deploy");
+        assert_eq!(library.get("serve").unwrap().lifespan, Some(3600));
+        assert!(library.get("missing").is_none());
+    }
+
+    #[test]
+    fn snippets_library_roundtrip() {
+        let mut library = Library::default();
+        library.insert(Snippet {
+            description: Some("Spins a dev server".to_owned()),
+            lifespan: Some(2 * 86_400),
+            ..snippet("serve")
+        });
+        library.insert(snippet("deploy"));
+
+        let encoded = toml::to_string(&library).unwrap();
+        assert_eq!(toml::from_str::<Library>(&encoded).unwrap(), library);
+        assert_eq!(toml::from_str::<Library>("").unwrap(), Library::default());
+    }
+
+    #[test]
+    fn snippets_fmt_lifespan() {
+        assert_eq!(fmt_lifespan(2 * 86_400), "2d");
+        assert_eq!(fmt_lifespan(36 * 3600), "36h");
+        assert_eq!(fmt_lifespan(90 * 60), "90m");
+        assert_eq!(fmt_lifespan(61), "61s");
+        assert_eq!(fmt_lifespan(LIFESPAN_NEVER), "never");
+    }
+}
@@ -0,0 +1,626 @@
+//! The state module
+//!
+//! `gistit export-state <file>` bundles what gistit keeps on this machine into a single tar.zst
+//! archive and `gistit import-state <file>` puts it back, to move to another machine or keep a
+//! backup: the global settings, the snippet library, names published by the daemon, the cache
+//! index, the audit log, pinned gistits and the daemon identity.
+//!
+//! Saved credentials (GitHub token, identity passphrase, secrets, workspaces and owner tokens) and
+//! the key encrypting the daemon identity are sealed with a passphrase into [`KEYS_NAME`], the rest
+//! is stored as is. `--no-keys` leaves them out. Importing never replaces what's already here
+//! unless `--force` is given.
+
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use clap::ArgMatches;
+use console::style;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use gistit_core::secret;
+use gistit_project::path;
+
+use crate::archive::{self, Format, ZSTD_LEVEL};
+use crate::audit;
+use crate::cache::{self, Index, INDEX_FILE_NAME};
+use crate::credentials::{self, Credential};
+use crate::daemon::write_private;
+use crate::dispatch::Dispatch;
+use crate::file::relative_path;
+use crate::passphrase;
+use crate::settings::SETTINGS_FILE_NAME;
+use crate::snippets::SNIPPETS_DIR_NAME;
+use crate::{finish, progress, updateln, warnln, Error, Result};
+
+/// Bumped when the layout of the archive changes, newer archives are refused
+const FORMAT_VERSION: u32 = 1;
+
+/// Stored first, tells a state archive apart from any other tar.zst
+const MANIFEST_NAME: &str = "gistit-state.json";
+
+/// The sealed [`Keys`]
+const KEYS_NAME: &str = "keys.sealed";
+
+/// Archive paths under these go to the config and data directories
+const CONFIG_PREFIX: &str = "config/";
+const DATA_PREFIX: &str = "data/";
+
+/// Written by gistit-daemon in the config directory, the encrypted identity and published names
+const DAEMON_CONFIG_FILE_NAMES: [&str; 2] = ["identity", "names.json"];
+
+/// Key the daemon identity is encrypted with when there's no passphrase, same as in gistit-daemon
+const IDENTITY_KEY_FILE_NAME: &str = "identity.key";
+
+/// Where gistit-daemon stores gistits in the data directory, and it's index
+const STORE_DIR_NAME: &str = "store";
+const STORE_INDEX_FILE_NAME: &str = "index.json";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Manifest {
+    version: u32,
+    /// Seconds since unix epoch
+    created: u64,
+    files: Vec<String>,
+}
+
+/// Credentials and the identity key, never stored unsealed
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct Keys {
+    github_token: Option<String>,
+    identity_passphrase: Option<String>,
+    #[serde(default)]
+    secrets: BTreeMap<String, String>,
+    #[serde(default)]
+    workspaces: BTreeMap<String, String>,
+    /// Owner tokens of the private gistits in the cache index, by hash
+    #[serde(default)]
+    owner_tokens: BTreeMap<String, String>,
+    /// Base64 encoded
+    identity_key: Option<String>,
+}
+
+impl Keys {
+    fn load(config_path: &Path) -> Result<Self> {
+        let named = |names: Vec<String>, credential: fn(String) -> Credential| {
+            let mut values = BTreeMap::new();
+            for name in names {
+                if let Some(value) = credential(name.clone()).get()? {
+                    values.insert(name, value);
+                }
+            }
+            Ok::<_, Error>(values)
+        };
+        // The credential store can't list them, only the ones of gistits known here are found
+        let hashes = Index::load()?
+            .hashes()
+            .into_iter()
+            .map(ToOwned::to_owned)
+            .collect();
+
+        Ok(Self {
+            github_token: Credential::GithubToken.get()?,
+            identity_passphrase: Credential::IdentityPassphrase.get()?,
+            secrets: named(credentials::secret_names()?, Credential::Secret)?,
+            workspaces: named(credentials::workspace_names()?, Credential::Workspace)?,
+            owner_tokens: named(hashes, Credential::OwnerToken)?,
+            identity_key: fs::read(config_path.join(IDENTITY_KEY_FILE_NAME))
+                .ok()
+                .map(base64::encode),
+        })
+    }
+
+    fn len(&self) -> usize {
+        usize::from(self.github_token.is_some())
+            + usize::from(self.identity_passphrase.is_some())
+            + self.secrets.len()
+            + self.workspaces.len()
+            + self.owner_tokens.len()
+            + usize::from(self.identity_key.is_some())
+    }
+
+    /// Saves the keys missing here, all of them with `force`, returning how many were kept
+    fn save(self, config_path: &Path, force: bool) -> Result<usize> {
+        let mut kept = 0;
+        let mut save = |credential: Credential, value: String| -> Result<()> {
+            if !force && credential.get()?.is_some() {
+                kept += 1;
+                return Ok(());
+            }
+            credential.set(&value)
+        };
+
+        if let Some(token) = self.github_token {
+            save(Credential::GithubToken, token)?;
+        }
+        if let Some(passphrase) = self.identity_passphrase {
+            save(Credential::IdentityPassphrase, passphrase)?;
+        }
+        for (name, value) in self.secrets {
+            save(Credential::Secret(name), value)?;
+        }
+        for (name, token) in self.workspaces {
+            save(Credential::Workspace(name), token)?;
+        }
+        for (hash, token) in self.owner_tokens {
+            save(Credential::OwnerToken(hash), token)?;
+        }
+
+        if let Some(key) = self.identity_key {
+            let key_path = config_path.join(IDENTITY_KEY_FILE_NAME);
+            if !force && fs::metadata(&key_path).is_ok() {
+                kept += 1;
+            } else {
+                let key = base64::decode(key).map_err(|err| Error::Archive(err.to_string()))?;
+                write_private(&key_path, &key)?;
+            }
+        }
+        Ok(kept)
+    }
+}
+
+/// `path` relative to `base`, '/' separated
+fn archive_path(prefix: &str, base: &Path, path: &Path) -> String {
+    let relative = path
+        .strip_prefix(base)
+        .expect("collected paths to be under their directory")
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    format!("{}{}", prefix, relative)
+}
+
+/// The files of the state, by archive path. Missing ones are left out
+fn collect(config_path: &Path, data_path: &Path) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut paths = vec![(CONFIG_PREFIX, config_path.join(SETTINGS_FILE_NAME))];
+    for name in DAEMON_CONFIG_FILE_NAMES {
+        paths.push((CONFIG_PREFIX, config_path.join(name)));
+    }
+    let snippets_path = config_path.join(SNIPPETS_DIR_NAME);
+    if snippets_path.is_dir() {
+        let mut snippets = Vec::new();
+        archive::walk(&snippets_path, &mut snippets)?;
+        paths.extend(snippets.into_iter().map(|path| (CONFIG_PREFIX, path)));
+    }
+    paths.push((DATA_PREFIX, data_path.join(INDEX_FILE_NAME)));
+    paths.push((DATA_PREFIX, data_path.join(audit::LOG_FILE_NAME)));
+
+    let mut files = Vec::new();
+    for (prefix, path) in paths {
+        if !path.is_file() {
+            continue;
+        }
+        let base = if prefix == CONFIG_PREFIX {
+            config_path
+        } else {
+            data_path
+        };
+        files.push((archive_path(prefix, base, &path), fs::read(&path)?));
+    }
+    files.extend(pinned(data_path)?);
+    Ok(files)
+}
+
+/// Pinned gistits in the daemon storage and an index of only them. Unpinned ones are a cache
+fn pinned(data_path: &Path) -> Result<Vec<(String, Vec<u8>)>> {
+    let store_path = data_path.join(STORE_DIR_NAME);
+    let index: BTreeMap<String, Value> =
+        match fs::read_to_string(store_path.join(STORE_INDEX_FILE_NAME)) {
+            Ok(index) => serde_json::from_str(&index)?,
+            Err(_) => return Ok(Vec::new()),
+        };
+    let index: BTreeMap<String, Value> = index
+        .into_iter()
+        .filter(|(_, entry)| entry["pinned"] == true)
+        .collect();
+    if index.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let store_prefix = format!("{}{}/", DATA_PREFIX, STORE_DIR_NAME);
+    let mut files = Vec::with_capacity(index.len() + 1);
+    for hash in index.keys() {
+        files.push((
+            format!("{}{}", store_prefix, hash),
+            fs::read(store_path.join(hash))?,
+        ));
+    }
+    files.push((
+        format!("{}{}", store_prefix, STORE_INDEX_FILE_NAME),
+        serde_json::to_vec(&index)?,
+    ));
+    Ok(files)
+}
+
+/// Adds the pinned gistits in `imported` to the storage index
+fn merge_store_index(data_path: &Path, imported: &[u8]) -> Result<()> {
+    let index_path = data_path.join(STORE_DIR_NAME).join(STORE_INDEX_FILE_NAME);
+    let mut index: BTreeMap<String, Value> = fs::read_to_string(&index_path)
+        .ok()
+        .and_then(|index| serde_json::from_str(&index).ok())
+        .unwrap_or_default();
+    index.extend(serde_json::from_slice::<BTreeMap<String, Value>>(imported)?);
+
+    fs::write(index_path, serde_json::to_vec(&index)?)?;
+    Ok(())
+}
+
+/// Where the file at archive `path` goes, `None` if it would end up elsewhere
+fn resolve(path: &str, config_path: &Path, data_path: &Path) -> Option<PathBuf> {
+    let (base, rest) = if let Some(rest) = path.strip_prefix(CONFIG_PREFIX) {
+        (config_path, rest)
+    } else if let Some(rest) = path.strip_prefix(DATA_PREFIX) {
+        (data_path, rest)
+    } else {
+        return None;
+    };
+    relative_path(rest).map(|relative| base.join(relative))
+}
+
+fn pack(manifest: &Manifest, files: &[(String, Vec<u8>)], keys: Option<&str>) -> Result<Vec<u8>> {
+    let encoder = zstd::stream::Encoder::new(Vec::new(), ZSTD_LEVEL)?;
+    let mut builder = tar::Builder::new(encoder);
+    let manifest = serde_json::to_vec_pretty(manifest)?;
+
+    let entries = std::iter::once((MANIFEST_NAME, manifest.as_slice()))
+        .chain(keys.map(|keys| (KEYS_NAME, keys.as_bytes())))
+        .chain(
+            files
+                .iter()
+                .map(|(path, data)| (path.as_str(), data.as_slice())),
+        );
+    for (path, data) in entries {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        // Secrets aside, settings and the identity aren't for everyone either
+        header.set_mode(0o600);
+        // The manifest has the export time
+        header.set_mtime(0);
+        header.set_entry_type(tar::EntryType::Regular);
+        builder.append_data(&mut header, path, data)?;
+    }
+
+    Ok(builder.into_inner()?.finish()?)
+}
+
+#[derive(Debug, Clone)]
+pub enum Action<'a> {
+    Export {
+        file: &'a OsStr,
+        no_keys: bool,
+        force: bool,
+        secret: Option<passphrase::Source<'a>>,
+    },
+    Import {
+        file: &'a OsStr,
+        no_keys: bool,
+        force: bool,
+        secret: Option<passphrase::Source<'a>>,
+    },
+}
+
+impl<'a> Action<'a> {
+    pub fn from_args(
+        command: &str,
+        args: &'a ArgMatches,
+    ) -> Result<Box<dyn Dispatch<InnerData = Config> + Send + Sync + 'a>> {
+        let file = args
+            .value_of_os("FILE")
+            .ok_or(Error::Argument("missing argument", "[FILE]"))?;
+        let no_keys = args.is_present("no-keys");
+        let force = args.is_present("force");
+        // Asked at a prompt otherwise
+        let secret = match args.value_of_os("secret-file") {
+            Some(path) => Some(passphrase::Source::File(path)),
+            None => args.value_of("saved-secret").map(passphrase::Source::Saved),
+        };
+
+        Ok(Box::new(if command == "export-state" {
+            Self::Export {
+                file,
+                no_keys,
+                force,
+                secret,
+            }
+        } else {
+            Self::Import {
+                file,
+                no_keys,
+                force,
+                secret,
+            }
+        }))
+    }
+}
+
+pub struct Config {
+    file_path: PathBuf,
+    config_path: PathBuf,
+    data_path: PathBuf,
+    runtime_path: PathBuf,
+}
+
+#[async_trait]
+impl<'a> Dispatch for Action<'a> {
+    type InnerData = Config;
+
+    async fn prepare(&self) -> Result<Self::InnerData> {
+        let (Self::Export { file, force, .. } | Self::Import { file, force, .. }) = self;
+        let file_path = PathBuf::from(file);
+        match self {
+            Self::Export { .. } if !force && fs::metadata(&file_path).is_ok() => {
+                return Err(Error::Argument(
+                    "file exists, pass `--force` to overwrite it",
+                    "[FILE]",
+                ));
+            }
+            Self::Import { .. } if !file_path.is_file() => {
+                return Err(Error::Argument("no state archive at this path", "[FILE]"));
+            }
+            _ => (),
+        }
+
+        Ok(Config {
+            file_path,
+            config_path: path::config()?,
+            data_path: path::data()?,
+            runtime_path: path::runtime_dir()?,
+        })
+    }
+
+    async fn dispatch(&self, config: Self::InnerData) -> Result<()> {
+        match *self {
+            Self::Export {
+                no_keys, secret, ..
+            } => export(&config, no_keys, secret).await,
+            Self::Import {
+                no_keys,
+                force,
+                secret,
+                ..
+            } => import(&config, no_keys, force, secret).await,
+        }
+    }
+}
+
+async fn export(
+    config: &Config,
+    no_keys: bool,
+    secret: Option<passphrase::Source<'_>>,
+) -> Result<()> {
+    progress!("Collecting");
+    let files = collect(&config.config_path, &config.data_path)?;
+    let keys = if no_keys {
+        Keys::default()
+    } else {
+        Keys::load(&config.config_path)?
+    };
+    updateln!("Collected");
+
+    let sealed = if keys.len() > 0 {
+        let passphrase = secret
+            .unwrap_or(passphrase::Source::Prompt)
+            .read(true)
+            .await?;
+        progress!("Sealing keys");
+        let sealed = secret::encrypt(&serde_json::to_string(&keys)?, &passphrase)?;
+        updateln!("Sealed keys");
+        Some(sealed)
+    } else {
+        None
+    };
+
+    progress!("Packing");
+    let manifest = Manifest {
+        version: FORMAT_VERSION,
+        created: cache::now(),
+        files: files.iter().map(|(path, _)| path.clone()).collect(),
+    };
+    write_private(
+        &config.file_path,
+        &pack(&manifest, &files, sealed.as_deref())?,
+    )?;
+    updateln!("Packed");
+
+    finish!(format!(
+        "\n    exported: '{}'\n    files: {}\n    keys: {}\n\n",
+        style(config.file_path.to_string_lossy()).bold(),
+        files.len(),
+        if sealed.is_some() {
+            format!("{}, sealed with your passphrase", keys.len())
+        } else {
+            "none".to_owned()
+        }
+    ));
+    Ok(())
+}
+
+async fn import(
+    config: &Config,
+    no_keys: bool,
+    force: bool,
+    secret: Option<passphrase::Source<'_>>,
+) -> Result<()> {
+    // It rewrites it's identity and storage index on the way out
+    if gistit_ipc::client(&config.runtime_path)?.alive() {
+        return Err(Error::Daemon(
+            "gistit-daemon is running, stop it first with `gistit daemon stop`",
+        ));
+    }
+
+    progress!("Unpacking");
+    let bytes = fs::read(&config.file_path)?;
+    let mut manifest: Option<Manifest> = None;
+    let mut sealed = None;
+    let mut store_index = None;
+    let (mut written, mut kept) = (0, 0);
+
+    archive::for_each_file(&bytes, Format::TarZst, |path, data| {
+        if manifest.is_none() {
+            // Nothing is written from an archive that isn't a state archive
+            let parsed: Manifest = (path == MANIFEST_NAME)
+                .then(|| serde_json::from_slice(&data).ok())
+                .flatten()
+                .ok_or_else(|| Error::Archive("not a gistit state archive".to_owned()))?;
+            if parsed.version > FORMAT_VERSION {
+                return Err(Error::Archive(
+                    "state archive from a newer gistit, update it first".to_owned(),
+                ));
+            }
+            manifest = Some(parsed);
+            return Ok(true);
+        }
+
+        if path == KEYS_NAME {
+            sealed = Some(std::str::from_utf8(&data)?.to_owned());
+            return Ok(true);
+        }
+        if path
+            == format!(
+                "{}{}/{}",
+                DATA_PREFIX, STORE_DIR_NAME, STORE_INDEX_FILE_NAME
+            )
+        {
+            store_index = Some(data);
+            return Ok(true);
+        }
+
+        let file_path = match resolve(path, &config.config_path, &config.data_path) {
+            Some(file_path) => file_path,
+            None => {
+                warnln!("skipping '{}', not part of the gistit state", path);
+                return Ok(true);
+            }
+        };
+        if !force && fs::metadata(&file_path).is_ok() {
+            kept += 1;
+            return Ok(true);
+        }
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        write_private(&file_path, &data)?;
+        written += 1;
+        Ok(true)
+    })?;
+
+    if manifest.is_none() {
+        return Err(Error::Archive("not a gistit state archive".to_owned()));
+    }
+    if let Some(store_index) = store_index {
+        merge_store_index(&config.data_path, &store_index)?;
+    }
+    updateln!("Unpacked");
+
+    let mut imported_keys = 0;
+    if let (false, Some(sealed)) = (no_keys, sealed) {
+        let passphrase = secret
+            .unwrap_or(passphrase::Source::Prompt)
+            .read(false)
+            .await?;
+        progress!("Opening keys");
+        let opened: Keys = match secret::decrypt(&sealed, &passphrase) {
+            Ok(opened) => serde_json::from_str(&opened)?,
+            Err(gistit_core::Error::WrongSecret) => {
+                return Err(Error::Secret(
+                    "wrong passphrase, the keys in this archive are sealed with another one",
+                ))
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let keys = opened.len();
+        let kept_keys = opened.save(&config.config_path, force)?;
+        imported_keys = keys - kept_keys;
+        kept += kept_keys;
+        updateln!("Opened keys");
+    }
+
+    let kept = if kept > 0 {
+        format!(
+            "\n    kept: {} already here, pass `--force` to replace them",
+            kept
+        )
+    } else {
+        String::new()
+    };
+    finish!(format!(
+        "\n    imported: {} files, {} keys{}\n\n",
+        written, imported_keys, kept
+    ));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn state_resolve() {
+        let config = Path::new("/config");
+        let data = Path::new("/data");
+
+        assert_eq!(
+            resolve("config/settings.toml", config, data),
+            Some(config.join("settings.toml"))
+        );
+        assert_eq!(
+            resolve("data/store/hash", config, data),
+            Some(data.join("store").join("hash"))
+        );
+        assert_eq!(resolve("config/../../etc/passwd", config, data), None);
+        assert_eq!(resolve("/etc/passwd", config, data), None);
+        assert_eq!(resolve("other/file", config, data), None);
+        assert_eq!(resolve("config/", config, data), None);
+    }
+
+    #[test]
+    fn state_pack_and_pinned() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let store = dir.path().join(STORE_DIR_NAME);
+        fs::create_dir_all(&store).unwrap();
+        fs::write(store.join("pinned"), "kept").unwrap();
+        fs::write(store.join("cached"), "left out").unwrap();
+        fs::write(
+            store.join(STORE_INDEX_FILE_NAME),
+            r#"{"pinned":{"size":4,"pinned":true,"last_access":1},"cached":{"size":8,"pinned":false,"last_access":1}}"#,
+        )
+        .unwrap();
+        fs::write(dir.path().join(INDEX_FILE_NAME), "[]").unwrap();
+
+        let files = collect(&dir.path().join("config"), dir.path()).unwrap();
+        let paths: Vec<&str> = files.iter().map(|(path, _)| path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "data/index.json",
+                "data/store/pinned",
+                "data/store/index.json"
+            ]
+        );
+
+        let manifest = Manifest {
+            version: FORMAT_VERSION,
+            created: 1,
+            files: Vec::new(),
+        };
+        let bytes = pack(&manifest, &files, Some("sealed")).unwrap();
+        let mut unpacked = Vec::new();
+        archive::for_each_file(&bytes, Format::TarZst, |path, data| {
+            unpacked.push((path.to_owned(), data));
+            Ok(true)
+        })
+        .unwrap();
+
+        assert_eq!(unpacked[0].0, MANIFEST_NAME);
+        assert_eq!(unpacked[1], (KEYS_NAME.to_owned(), b"sealed".to_vec()));
+        assert_eq!(
+            unpacked[3],
+            ("data/store/pinned".to_owned(), b"kept".to_vec())
+        );
+        assert_eq!(unpacked.len(), 5);
+    }
+}
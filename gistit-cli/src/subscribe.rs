@@ -0,0 +1,139 @@
+//! The subscribe module
+//!
+//! Streams gossipsub announcements of newly provided gistits from `gistit-daemon`, along with
+//! gistits other peers push to our inbox. Announcements only carry metadata, the content is fetched with
+//! `gistit fetch <hash>` as usual. With no topic given the one of the workspace in use is followed.
+
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use clap::ArgMatches;
+use console::style;
+
+use gistit_project::path;
+use gistit_proto::{ipc, Announcement, Gistit, Instruction};
+
+use crate::daemon;
+use crate::dispatch::Dispatch;
+use crate::param::check;
+use crate::settings::{get_runtime_settings, Backend};
+use crate::workspace::Workspace;
+use crate::{cleanln, progress, updateln, Error, Result};
+
+#[derive(Debug, Clone)]
+pub struct Action<'a> {
+    pub topics: Vec<&'a str>,
+}
+
+impl<'a> Action<'a> {
+    pub fn from_args(
+        args: &'a ArgMatches,
+    ) -> Result<Box<dyn Dispatch<InnerData = Config> + Send + Sync + 'a>> {
+        Ok(Box::new(Self {
+            topics: args
+                .values_of("TOPIC")
+                .map(Iterator::collect)
+                .unwrap_or_default(),
+        }))
+    }
+}
+
+pub struct Config {
+    topics: Vec<String>,
+    backend: Backend,
+    runtime_path: PathBuf,
+    config_path: PathBuf,
+}
+
+#[async_trait]
+impl<'a> Dispatch for Action<'a> {
+    type InnerData = Config;
+
+    async fn prepare(&self) -> Result<Self::InnerData> {
+        let settings = get_runtime_settings()?;
+        let mut topics = self
+            .topics
+            .iter()
+            .map(|topic| check::topic(topic).map(ToOwned::to_owned))
+            .collect::<Result<Vec<String>>>()?;
+        // With no topic given, the one of the workspace in use
+        if topics.is_empty() {
+            topics.extend(Workspace::active(&settings)?.map(|workspace| workspace.topic));
+        }
+
+        Ok(Config {
+            topics,
+            backend: settings.backend(),
+            runtime_path: path::runtime_dir()?,
+            config_path: path::config()?,
+        })
+    }
+
+    async fn dispatch(&self, config: Self::InnerData) -> Result<()> {
+        let mut bridge = gistit_ipc::client(&config.runtime_path)?;
+        daemon::spawn_if_required(
+            config.backend,
+            &bridge,
+            &config.runtime_path,
+            &config.config_path,
+        )
+        .await?;
+
+        progress!("Subscribing");
+        if !bridge.alive() {
+            return Err(Error::Daemon("gistit node is not running"));
+        }
+
+        bridge.connect_blocking()?;
+        bridge
+            .send(Instruction::request_subscribe(config.topics.clone()))
+            .await?;
+        if !config.topics.is_empty() {
+            updateln!("Subscribed to {}", style(config.topics.join(", ")).bold());
+        }
+        progress!("Waiting for announcements and pushes, press Ctrl-C to stop");
+
+        loop {
+            match bridge.listen().await?.expect_response()? {
+                ipc::instruction::Kind::EventResponse(ipc::instruction::EventResponse {
+                    topic,
+                    announcement: Some(announcement),
+                }) => cleanln!(format_announcement(&topic, &announcement)),
+                ipc::instruction::Kind::IncomingResponse(ipc::instruction::IncomingResponse {
+                    gistit: Some(gistit),
+                    peer_id,
+                    inbox_id,
+                }) => cleanln!(format_incoming(&gistit, &peer_id, inbox_id)),
+                _ => (),
+            }
+        }
+    }
+}
+
+fn format_announcement(topic: &str, announcement: &Announcement) -> String {
+    let description = announcement
+        .description
+        .as_deref()
+        .map_or_else(String::new, |description| format!("\n    {}", description));
+
+    format!(
+        "[{}] {} {} ({}, {} bytes) by {}{}",
+        style(topic).blue(),
+        style(&announcement.hash).bold(),
+        announcement.name,
+        announcement.lang,
+        announcement.size,
+        announcement.author,
+        style(description).dim()
+    )
+}
+
+fn format_incoming(gistit: &Gistit, peer_id: &str, inbox_id: u32) -> String {
+    format!(
+        "[{}] {} pushed by {}, accept it with `gistit inbox accept {}`",
+        style("incoming").green(),
+        style(&gistit.hash).bold(),
+        style(peer_id).blue(),
+        inbox_id
+    )
+}
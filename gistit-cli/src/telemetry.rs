@@ -0,0 +1,289 @@
+//! The telemetry module
+//!
+//! Anonymous usage reporting, off unless `telemetry = true` is set in the global settings file,
+//! with `gistit telemetry on`. Project settings can't turn it on for you. `DO_NOT_TRACK=1` turns it
+//! off regardless of the settings.
+//!
+//! Only the command name and the error code ([`crate::Error::code`]) of each run are recorded,
+//! never arguments, hashes, file names or contents. Records are appended to a spool file in the
+//! data directory and sent to the server as counts, along with the gistit version and the OS, once
+//! there are [`FLUSH_THRESHOLD`] of them. Sending happens in the background of a later command
+//! and never fails it.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use clap::ArgMatches;
+use console::style;
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+
+use gistit_project::path;
+
+use crate::dispatch::Dispatch;
+use crate::server::SERVER_URL_TELEMETRY;
+use crate::settings::Settings;
+use crate::{finish, Error, Result};
+
+/// Records waiting to be sent, inside the data directory
+pub const SPOOL_FILE_NAME: &str = "telemetry.jsonl";
+
+/// Records being sent, kept until the server accepts them
+const SENDING_FILE_NAME: &str = "telemetry.sending.jsonl";
+
+/// Records spooled before they're sent
+pub const FLUSH_THRESHOLD: usize = 20;
+
+/// Time a command waits on it's way out for the records to be sent
+const FLUSH_TIMEOUT_SECS: u64 = 2;
+
+/// <https://consoledonottrack.com>
+const DO_NOT_TRACK_VAR: &str = "DO_NOT_TRACK";
+
+/// What's recorded of a single run
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Record {
+    command: String,
+    error: Option<String>,
+}
+
+/// What's sent to the server, records counted by command and error code
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct Report {
+    version: &'static str,
+    os: &'static str,
+    commands: BTreeMap<String, u32>,
+    errors: BTreeMap<String, u32>,
+}
+
+impl Report {
+    fn new(records: &[Record]) -> Self {
+        let mut commands = BTreeMap::new();
+        let mut errors = BTreeMap::new();
+        for record in records {
+            *commands.entry(record.command.clone()).or_insert(0) += 1;
+            if let Some(error) = &record.error {
+                *errors.entry(error.clone()).or_insert(0) += 1;
+            }
+        }
+
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            os: env::consts::OS,
+            commands,
+            errors,
+        }
+    }
+}
+
+fn do_not_track() -> bool {
+    env::var(DO_NOT_TRACK_VAR).map_or(false, |value| !value.is_empty() && value != "0")
+}
+
+/// Whether the user opted in, only the global settings file counts
+#[must_use]
+pub fn enabled() -> bool {
+    !do_not_track() && Settings::load().map_or(false, |settings| settings.telemetry())
+}
+
+fn spool_path() -> Result<PathBuf> {
+    Ok(path::data()?.join(SPOOL_FILE_NAME))
+}
+
+fn sending_path() -> Result<PathBuf> {
+    Ok(path::data()?.join(SENDING_FILE_NAME))
+}
+
+fn read_records(records_path: &Path) -> Vec<Record> {
+    fs::read_to_string(records_path)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+/// Spools the outcome of `command`, if enabled. Never fails the command
+pub fn record(command: &str, error: Option<&Error>) {
+    if !enabled() {
+        return;
+    }
+
+    let record = Record {
+        command: command.to_owned(),
+        error: error.map(|err| err.code().to_owned()),
+    };
+    let spool = || -> Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(spool_path()?)?;
+        writeln!(file, "{}", serde_json::to_string(&record)?)?;
+        Ok(())
+    };
+
+    if let Err(err) = spool() {
+        tracing::debug!("failed to spool telemetry: {}", err);
+    }
+}
+
+/// Sends the spooled records in the background, if enabled and there are enough of them
+#[must_use]
+pub fn flush() -> Option<JoinHandle<()>> {
+    if !enabled() {
+        return None;
+    }
+
+    let sending = sending_path().ok()?;
+    // Records that failed to send last time go first, new ones keep piling up meanwhile
+    if fs::metadata(&sending).is_err() {
+        let spool = spool_path().ok()?;
+        if read_records(&spool).len() < FLUSH_THRESHOLD {
+            return None;
+        }
+        fs::rename(&spool, &sending).ok()?;
+    }
+
+    Some(tokio::spawn(async move {
+        if let Err(err) = send(&sending).await {
+            tracing::debug!("failed to send telemetry: {}", err);
+        }
+    }))
+}
+
+async fn send(sending: &Path) -> Result<()> {
+    let report = Report::new(&read_records(sending));
+    let response = gistit_core::http_client()
+        .post(SERVER_URL_TELEMETRY.as_str())
+        .json(&report)
+        .send()
+        .await?;
+
+    // Rejected records would be rejected again, they're dropped too
+    if response.status().is_success() || response.status().is_client_error() {
+        fs::remove_file(sending)?;
+    }
+    response.error_for_status()?;
+    Ok(())
+}
+
+/// Waits a little for [`flush`] to finish, it's retried by a later command otherwise
+pub async fn join(flush: Option<JoinHandle<()>>) {
+    if let Some(handle) = flush {
+        let timeout = Duration::from_secs(FLUSH_TIMEOUT_SECS);
+        if tokio::time::timeout(timeout, handle).await.is_err() {
+            tracing::debug!("telemetry still sending, left for a later command");
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Status,
+    On,
+    Off,
+}
+
+impl Action {
+    pub fn from_args(
+        args: &ArgMatches,
+    ) -> Result<Box<dyn Dispatch<InnerData = Config> + Send + Sync + 'static>> {
+        let action = match args.subcommand() {
+            Some(("status", _)) => Self::Status,
+            Some(("on", _)) => Self::On,
+            Some(("off", _)) => Self::Off,
+            _ => return Err(Error::Argument("missing subcommand", "telemetry")),
+        };
+        Ok(Box::new(action))
+    }
+}
+
+pub struct Config {
+    settings: Settings,
+}
+
+#[async_trait]
+impl Dispatch for Action {
+    type InnerData = Config;
+
+    async fn prepare(&self) -> Result<Self::InnerData> {
+        Ok(Config {
+            settings: Settings::load()?,
+        })
+    }
+
+    async fn dispatch(&self, config: Self::InnerData) -> Result<()> {
+        let mut settings = config.settings;
+        match self {
+            Self::Status => {
+                let state = if do_not_track() {
+                    format!("{}, `{}` is set", style("off").dim(), DO_NOT_TRACK_VAR)
+                } else if settings.telemetry() {
+                    style("on").green().to_string()
+                } else {
+                    style("off").dim().to_string()
+                };
+                let pending =
+                    read_records(&spool_path()?).len() + read_records(&sending_path()?).len();
+
+                finish!(format!(
+                    "\n    telemetry: {}\n    pending: {} records in '{}'\n    collected: command \
+                     names and error codes, the gistit version and the OS\n\n",
+                    state,
+                    pending,
+                    spool_path()?.to_string_lossy()
+                ));
+            }
+            Self::On => {
+                settings.telemetry = Some(true);
+                settings.save()?;
+                finish!("\n    telemetry: on, thank you!\n\n");
+            }
+            Self::Off => {
+                settings.telemetry = Some(false);
+                settings.save()?;
+                // Nothing recorded is sent after opting out
+                for records_path in [spool_path()?, sending_path()?] {
+                    if fs::metadata(&records_path).is_ok() {
+                        fs::remove_file(&records_path)?;
+                    }
+                }
+                finish!("\n    telemetry: off, pending records removed\n\n");
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn telemetry_report_counts_records() {
+        let record = |command: &str, error: Option<&str>| Record {
+            command: command.to_owned(),
+            error: error.map(ToOwned::to_owned),
+        };
+        let report = Report::new(&[
+            record("send", None),
+            record("send", Some("network")),
+            record("fetch", Some("not-found")),
+            record("fetch", Some("network")),
+        ]);
+
+        assert_eq!(report.commands.get("send"), Some(&2));
+        assert_eq!(report.commands.get("fetch"), Some(&2));
+        assert_eq!(report.errors.get("network"), Some(&2));
+        assert_eq!(report.errors.get("not-found"), Some(&1));
+
+        let encoded = serde_json::to_value(&report).unwrap();
+        assert!(encoded.get("version").is_some());
+        assert!(encoded.get("os").is_some());
+        assert_eq!(encoded.as_object().unwrap().len(), 4);
+    }
+}
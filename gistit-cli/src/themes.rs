@@ -0,0 +1,193 @@
+use std::path::Path;
+
+use async_trait::async_trait;
+use clap::ArgMatches;
+use console::style;
+
+use crate::dispatch::Dispatch;
+use crate::param::{check, SUPPORTED_COLORSCHEMES};
+use crate::settings::{get_runtime_settings, Settings};
+use crate::{finish, progress, updateln, Result};
+
+/// Snippet rendered when previewing colorschemes
+const PREVIEW_SAMPLE: &str = r#"use std::collections::HashMap;
+
+/// Counts words in a text
+fn count(text: &str) -> HashMap<&str, usize> {
+    let mut map = HashMap::new();
+    for word in text.split_whitespace() {
+        *map.entry(word).or_insert(0) += 1;
+    }
+    map
+}
+"#;
+
+#[derive(Debug, Clone)]
+pub struct Action<'a> {
+    pub colorscheme: Option<&'a str>,
+    pub preview: bool,
+    pub set: bool,
+}
+
+impl<'a> Action<'a> {
+    pub fn from_args(
+        args: &'a ArgMatches,
+    ) -> Result<Box<dyn Dispatch<InnerData = Config> + Send + Sync + 'a>> {
+        Ok(Box::new(Self {
+            colorscheme: args.value_of("COLORSCHEME"),
+            preview: args.is_present("preview"),
+            set: args.is_present("set"),
+        }))
+    }
+}
+
+pub struct Config {
+    colorschemes: Vec<&'static str>,
+    settings: Settings,
+}
+
+#[async_trait]
+impl<'a> Dispatch for Action<'a> {
+    type InnerData = Config;
+
+    async fn prepare(&self) -> Result<Self::InnerData> {
+        let colorschemes = if let Some(colorscheme) = self.colorscheme {
+            vec![check::colorscheme(colorscheme)?]
+        } else {
+            SUPPORTED_COLORSCHEMES.to_vec()
+        };
+
+        Ok(Config {
+            colorschemes,
+            settings: get_runtime_settings()?,
+        })
+    }
+
+    async fn dispatch(&self, config: Self::InnerData) -> Result<()> {
+        let Config {
+            colorschemes,
+            settings,
+        } = config;
+
+        if self.preview {
+            for colorscheme in &colorschemes {
+                preview(colorscheme)?;
+            }
+        } else {
+            list(&colorschemes, settings.colorscheme());
+        }
+
+        if self.set {
+            // Clap requires `COLORSCHEME` with `--set` so there is exactly one
+            let colorscheme = colorschemes.first().expect("to have a colorscheme");
+
+            progress!("Saving");
+            save_colorscheme(&Settings::path()?, colorscheme)?;
+            updateln!("Saved");
+            finish!(format!(
+                "\n    colorscheme: '{}'\n\n",
+                style(colorscheme).bold()
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Sets `colorscheme` in the global settings file, read on it's own so the project settings file
+/// and the selected profile aren't written into it
+fn save_colorscheme(settings_path: &Path, colorscheme: &str) -> Result<()> {
+    let mut settings = Settings::load_from(settings_path)?;
+    settings.colorscheme = Some(colorscheme.to_owned());
+    settings.save_to(settings_path)
+}
+
+/// Prints the colorscheme names highlighting the one currently in use
+pub fn list(colorschemes: &[&str], current: &str) {
+    println!("{}", style("Supported colorschemes: \n").green().bold());
+    for colorscheme in colorschemes {
+        if *colorscheme == current {
+            println!(
+                "    {} {}",
+                style(colorscheme).bold(),
+                style("(current)").italic().dim()
+            );
+        } else {
+            println!("    {}", colorscheme);
+        }
+    }
+    println!(
+        r#"
+This application uses '{}' to view gistits inside your terminal.
+For more information please visit:
+{}
+        "#,
+        style("bat").bold().blue(),
+        "https://github.com/sharkdp/bat"
+    );
+}
+
+/// Renders [`PREVIEW_SAMPLE`] with the given colorscheme
+fn preview(colorscheme: &str) -> Result<()> {
+    let input = bat::Input::from_bytes(PREVIEW_SAMPLE.as_bytes())
+        .name("sample.rs")
+        .title(style(colorscheme).green().bold().to_string());
+
+    bat::PrettyPrinter::new()
+        .header(true)
+        .grid(true)
+        .input(input)
+        .line_numbers(true)
+        .theme(colorscheme)
+        .use_italics(true)
+        .paging_mode(bat::PagingMode::Never)
+        .print()?;
+    println!();
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::{Mergeable, PROJECT_SETTINGS_FILE_NAME, SETTINGS_FILE_NAME};
+    use assert_fs::prelude::*;
+
+    #[test]
+    fn themes_set_keeps_project_overrides_out_of_global_settings() {
+        let tmp = assert_fs::TempDir::new().unwrap();
+        let global = tmp.child(SETTINGS_FILE_NAME);
+        global
+            .write_str(
+                r#"
+author = "me"
+
+[profile.work]
+lifespan = "7d"
+"#,
+            )
+            .unwrap();
+        let project = tmp.child("repo").child(PROJECT_SETTINGS_FILE_NAME);
+        project
+            .write_str(
+                r#"
+author = "team"
+lifespan = "1h"
+"#,
+            )
+            .unwrap();
+
+        let runtime = Settings::load_from(project.path())
+            .unwrap()
+            .merge(Settings::load_from(global.path()).unwrap());
+        assert_eq!(runtime.author.as_deref(), Some("team"));
+
+        save_colorscheme(global.path(), "Dracula").unwrap();
+
+        let saved = Settings::load_from(global.path()).unwrap();
+        assert_eq!(saved.colorscheme(), "Dracula");
+        assert_eq!(saved.author.as_deref(), Some("me"));
+        assert_eq!(saved.lifespan, None);
+        assert_eq!(saved.profile["work"].lifespan.as_deref(), Some("7d"));
+    }
+}
@@ -0,0 +1,113 @@
+//! The tmux module
+//!
+//! `--tmux-pane` sends the scrollback of a tmux pane, the one gistit runs in or any other given as
+//! a tmux target (`%3`, `session:window.pane`). Wrapped lines are joined back and blank lines at
+//! the bottom, usually the prompt waiting below the output, are trimmed. Only the most recent
+//! [`MAX_CAPTURE_BYTES`] are kept, errors are at the end of the output.
+
+use std::env;
+use std::process::{Command, Stdio};
+
+use which::which;
+
+use crate::{Error, Result};
+
+/// Language of captured panes
+pub const CONSOLE_LANG: &str = "console";
+
+/// Name of captured panes
+pub const CAPTURE_NAME: &str = "tmux";
+
+/// Set by tmux in the shells it runs
+const TMUX_VAR: &str = "TMUX";
+
+/// Same as the biggest file sent
+const MAX_CAPTURE_BYTES: usize = 50_000;
+
+/// Captures the scrollback of the tmux pane `target`, or of the current pane
+///
+/// # Errors
+///
+/// Fails if tmux isn't installed, the current pane is asked for outside of tmux or tmux can't
+/// capture the pane
+pub fn capture(target: Option<&str>) -> Result<String> {
+    if target.is_none() && env::var_os(TMUX_VAR).is_none() {
+        return Err(Error::Argument(
+            "not running inside tmux, pick a pane like `--tmux-pane %3`",
+            "--tmux-pane",
+        ));
+    }
+
+    let mut command = Command::new(which("tmux")?);
+    // Printed, from the start of the history and with wrapped lines joined
+    command.args(["capture-pane", "-p", "-J", "-S", "-"]);
+    if let Some(target) = target {
+        command.arg("-t").arg(target);
+    }
+    tracing::debug!(?target, "capturing tmux pane");
+
+    let output = command.stdin(Stdio::null()).output()?;
+    if !output.status.success() {
+        tracing::debug!(stderr = %String::from_utf8_lossy(&output.stderr), "tmux failed");
+        return Err(Error::Argument(
+            "tmux can't capture that pane, list them with `tmux list-panes -a`",
+            "--tmux-pane",
+        ));
+    }
+
+    Ok(tail(&trim(&String::from_utf8_lossy(&output.stdout))).to_owned())
+}
+
+/// `scrollback` without trailing whitespace or blank lines at the bottom
+fn trim(scrollback: &str) -> String {
+    let mut lines: Vec<&str> = scrollback.lines().map(str::trim_end).collect();
+    while lines.last() == Some(&"") {
+        lines.pop();
+    }
+
+    let mut trimmed = lines.join("\n");
+    if !trimmed.is_empty() {
+        trimmed.push('\n');
+    }
+    trimmed
+}
+
+/// The most recent lines of `scrollback` that fit [`MAX_CAPTURE_BYTES`]
+fn tail(scrollback: &str) -> &str {
+    if scrollback.len() <= MAX_CAPTURE_BYTES {
+        return scrollback;
+    }
+
+    let start = scrollback.len() - MAX_CAPTURE_BYTES;
+    let start = scrollback[start..]
+        .find('\n')
+        .map_or(scrollback.len(), |newline| start + newline + 1);
+    &scrollback[start..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tmux_trim() {
+        let scrollback = "$ cargo build\nerror[E0308]: mismatched types   \n\n$ \n\n\n";
+        assert_eq!(
+            trim(scrollback),
+            "$ cargo build\nerror[E0308]: mismatched types\n\n$\n"
+        );
+        assert_eq!(trim("\n\n  \n"), "");
+    }
+
+    #[test]
+    fn tmux_tail() {
+        assert_eq!(tail("short\n"), "short\n");
+
+        let line = format!("{}\n", "x".repeat(99));
+        let scrollback = format!("first\n{}", line.repeat(MAX_CAPTURE_BYTES / line.len()));
+        let kept = tail(&scrollback);
+        assert!(kept.len() <= MAX_CAPTURE_BYTES);
+        assert!(kept.starts_with('x'));
+        assert!(kept.ends_with("x\n"));
+    }
+}
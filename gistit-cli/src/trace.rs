@@ -0,0 +1,73 @@
+//! The trace module
+//!
+//! `-v` and `-vv` print tracing spans and events to stderr while a command runs: the command being
+//! prepared and dispatched, HTTP requests to the server and IPC with `gistit-daemon`. `-v` shows
+//! gistit's own debug events and how long each span took, `-vv` goes down to trace events, the
+//! HTTP client's included.
+
+use clap::ArgMatches;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::EnvFilter;
+
+/// Gistit crates, as tracing targets
+const TARGETS: [&str; 3] = ["gistit", "gistit_core", "gistit_ipc"];
+
+/// Prints spans and events for `verbosity`, the number of `-v` given. Nothing is printed with 0
+pub fn init(verbosity: u64) {
+    let filter = match verbosity {
+        0 => return,
+        1 => directives("debug"),
+        _ => format!("{},reqwest=trace,hyper=debug", directives("trace")),
+    };
+
+    // Fails only if a subscriber is already set, which is fine
+    let _ = tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::new(filter))
+        .with_span_events(FmtSpan::CLOSE)
+        .with_writer(std::io::stderr)
+        .try_init();
+}
+
+/// The number of `-v` given. The flag is global and clap copies its count into every subcommand's
+/// matches, so it's the highest count on the way down rather than the sum
+pub fn verbosity(matches: &ArgMatches) -> u64 {
+    let mut count = matches.occurrences_of("verbose");
+    let mut matches = matches;
+    while let Some((_, subcommand)) = matches.subcommand() {
+        count = count.max(subcommand.occurrences_of("verbose"));
+        matches = subcommand;
+    }
+    count
+}
+
+fn directives(level: &str) -> String {
+    TARGETS
+        .iter()
+        .map(|target| format!("{}={}", target, level))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trace_directives() {
+        assert_eq!(
+            directives("debug"),
+            "gistit=debug,gistit_core=debug,gistit_ipc=debug"
+        );
+    }
+
+    #[test]
+    fn trace_verbosity_global_flag() {
+        let verbosity = |args: &[&str]| verbosity(&crate::arg::app().get_matches_from(args));
+
+        assert_eq!(verbosity(&["gistit", "fetch", "abc1234"]), 0);
+        assert_eq!(verbosity(&["gistit", "-v", "fetch", "abc1234"]), 1);
+        assert_eq!(verbosity(&["gistit", "fetch", "-v", "abc1234"]), 1);
+        assert_eq!(verbosity(&["gistit", "fetch", "-vv", "abc1234"]), 2);
+        assert_eq!(verbosity(&["gistit", "-vv"]), 2);
+    }
+}
@@ -0,0 +1,635 @@
+//! The tui module
+//!
+//! `gistit tui` browses the gistits this machine knows about: the local cache index, the ones kept
+//! in `gistit-daemon` storage and a search over both. Selecting one fetches it and previews it with
+//! syntax highlighting, from there it can be saved, it's hash copied or it can be removed (from the
+//! index, or unpinned from the daemon).
+
+use std::borrow::Cow;
+use std::io::{self, Stdout};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use clap::ArgMatches;
+use crossterm::event::{self, Event, KeyCode, KeyEvent};
+use crossterm::execute;
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use indicatif::{HumanBytes, ProgressDrawTarget};
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Span, Spans};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Tabs};
+use ratatui::{Frame, Terminal};
+use syntect::easy::HighlightLines;
+use syntect::highlighting::ThemeSet;
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+
+use gistit_core::secret;
+use gistit_ipc::{Bridge, Client};
+use gistit_project::path;
+use gistit_proto::{ipc, Gistit, Instruction};
+
+use crate::audit;
+use crate::binary;
+use crate::cache::{Index, Origin};
+use crate::clipboard::Clipboard;
+use crate::credentials;
+use crate::daemon;
+use crate::dispatch::Dispatch;
+use crate::fetch::{fetch_p2p, fetch_server};
+use crate::file::File;
+use crate::fmt::PROGRESS;
+use crate::pin::fmt_ago;
+use crate::settings::{get_runtime_settings, Backend};
+use crate::{Error, Result};
+
+/// Used when the colorscheme in the settings file isn't one of syntect's defaults
+const FALLBACK_THEME: &str = "base16-ocean.dark";
+
+const HELP: &str =
+    "tab: switch pane  /: search  enter: preview  s: save  c: copy hash  d: delete  q: quit";
+
+#[derive(Debug, Clone)]
+pub struct Action;
+
+impl Action {
+    pub fn from_args(
+        _args: &ArgMatches,
+    ) -> Result<Box<dyn Dispatch<InnerData = Config> + Send + Sync + 'static>> {
+        Ok(Box::new(Self))
+    }
+}
+
+pub struct Config {
+    colorscheme: String,
+    backend: Backend,
+    runtime_path: PathBuf,
+    config_path: PathBuf,
+    data_path: PathBuf,
+}
+
+#[async_trait]
+impl Dispatch for Action {
+    type InnerData = Config;
+
+    async fn prepare(&self) -> Result<Self::InnerData> {
+        let settings = get_runtime_settings()?;
+
+        Ok(Config {
+            colorscheme: settings.colorscheme().to_owned(),
+            backend: settings.backend(),
+            runtime_path: path::runtime_dir()?,
+            config_path: path::config()?,
+            data_path: path::data()?,
+        })
+    }
+
+    async fn dispatch(&self, config: Self::InnerData) -> Result<()> {
+        let mut bridge = gistit_ipc::client(&config.runtime_path)?;
+        daemon::spawn_if_required(
+            config.backend,
+            &bridge,
+            &config.runtime_path,
+            &config.config_path,
+        )
+        .await?;
+        // The spinner would draw over the alternate screen
+        PROGRESS.set_draw_target(ProgressDrawTarget::hidden());
+
+        let mut app = App::new(&config);
+        app.load(&mut bridge).await;
+
+        enable_raw_mode()?;
+        let mut stdout = io::stdout();
+        execute!(stdout, EnterAlternateScreen)?;
+        let mut terminal = Terminal::new(CrosstermBackend::new(stdout))?;
+
+        let result = run(&mut terminal, &mut app, &mut bridge).await;
+
+        disable_raw_mode()?;
+        execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+        terminal.show_cursor()?;
+        result
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pane {
+    Cache,
+    Daemon,
+    Search,
+}
+
+impl Pane {
+    const ALL: [Self; 3] = [Self::Cache, Self::Daemon, Self::Search];
+
+    const fn title(self) -> &'static str {
+        match self {
+            Self::Cache => "Cache",
+            Self::Daemon => "Daemon",
+            Self::Search => "Search",
+        }
+    }
+
+    fn index(self) -> usize {
+        Self::ALL.iter().position(|pane| *pane == self).unwrap_or(0)
+    }
+
+    fn next(self) -> Self {
+        Self::ALL[(self.index() + 1) % Self::ALL.len()]
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Item {
+    hash: String,
+    name: String,
+    detail: String,
+    /// Kept in the daemon storage rather than only in the cache index
+    stored: bool,
+    /// Only found in the p2p network
+    p2p: bool,
+}
+
+impl Item {
+    fn matches(&self, query: &str) -> bool {
+        let query = query.to_lowercase();
+        self.hash.contains(&query)
+            || self.name.to_lowercase().contains(&query)
+            || self.detail.to_lowercase().contains(&query)
+    }
+}
+
+struct Preview {
+    gistit: Gistit,
+    lines: Vec<Spans<'static>>,
+    scroll: u16,
+}
+
+struct App {
+    pane: Pane,
+    cache: Vec<Item>,
+    daemon: Vec<Item>,
+    query: String,
+    typing: bool,
+    list: ListState,
+    preview: Option<Preview>,
+    status: String,
+    syntaxes: SyntaxSet,
+    themes: ThemeSet,
+    colorscheme: String,
+    data_path: PathBuf,
+}
+
+impl App {
+    fn new(config: &Config) -> Self {
+        let mut list = ListState::default();
+        list.select(Some(0));
+
+        Self {
+            pane: Pane::Cache,
+            cache: Vec::new(),
+            daemon: Vec::new(),
+            query: String::new(),
+            typing: false,
+            list,
+            preview: None,
+            status: HELP.to_owned(),
+            syntaxes: SyntaxSet::load_defaults_newlines(),
+            themes: ThemeSet::load_defaults(),
+            colorscheme: config.colorscheme.clone(),
+            data_path: config.data_path.clone(),
+        }
+    }
+
+    /// Reads the cache index and lists the daemon storage, if it's running
+    async fn load(&mut self, bridge: &mut Bridge<Client>) {
+        let now = now();
+
+        self.cache = Index::load()
+            .map(|index| {
+                index
+                    .entries
+                    .iter()
+                    .rev()
+                    .map(|entry| Item {
+                        hash: entry.hash.clone(),
+                        name: entry.name.clone(),
+                        detail: format!(
+                            "{} by {}, {}",
+                            origin(entry.origin),
+                            entry.author,
+                            fmt_ago(now.saturating_sub(entry.timestamp))
+                        ),
+                        stored: false,
+                        p2p: entry.origin == Origin::Hosted,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        match list_pins(bridge).await {
+            Ok(pins) => {
+                self.daemon = pins
+                    .iter()
+                    .map(|pin| Item {
+                        hash: pin.hash.clone(),
+                        name: self.name_of(&pin.hash),
+                        detail: format!(
+                            "{}, {}",
+                            HumanBytes(pin.size),
+                            fmt_ago(now.saturating_sub(pin.last_access))
+                        ),
+                        stored: true,
+                        p2p: true,
+                    })
+                    .collect();
+            }
+            Err(err) => self.status = format!("daemon storage unavailable: {}", err),
+        }
+    }
+
+    /// The file name the cache index knows `hash` by, if any
+    fn name_of(&self, hash: &str) -> String {
+        self.cache
+            .iter()
+            .find(|item| item.hash == hash)
+            .map(|item| item.name.clone())
+            .unwrap_or_default()
+    }
+
+    fn items(&self) -> Vec<&Item> {
+        match self.pane {
+            Pane::Cache => self.cache.iter().collect(),
+            Pane::Daemon => self.daemon.iter().collect(),
+            Pane::Search if self.query.is_empty() => Vec::new(),
+            Pane::Search => self
+                .cache
+                .iter()
+                .chain(self.daemon.iter())
+                .filter(|item| item.matches(&self.query))
+                .collect(),
+        }
+    }
+
+    fn selected(&self) -> Option<Item> {
+        let items = self.items();
+        self.list
+            .selected()
+            .and_then(|i| items.get(i).map(|item| (*item).clone()))
+    }
+
+    /// Moves the selection, kept within the listed items
+    fn select(&mut self, step: fn(usize) -> usize) {
+        let len = self.items().len();
+        if len == 0 {
+            self.list.select(None);
+            return;
+        }
+        let current = self.list.selected().unwrap_or(0);
+        self.list.select(Some(step(current).min(len - 1)));
+    }
+
+    fn switch(&mut self, pane: Pane) {
+        self.pane = pane;
+        self.preview = None;
+        self.list.select(Some(0));
+    }
+
+    fn highlight(&self, gistit: &Gistit) -> Vec<Spans<'static>> {
+        // NOTE: Currently we support one file
+        let inner = match gistit.inner.first() {
+            Some(inner) => inner,
+            None => return Vec::new(),
+        };
+        let theme = self
+            .themes
+            .themes
+            .get(&self.colorscheme)
+            .or_else(|| self.themes.themes.get(FALLBACK_THEME))
+            .expect("fallback theme to be bundled");
+        // Binary files are described, see [`binary::preview`]
+        let (data, syntax) = if inner.binary {
+            let preview = binary::content(inner)
+                .map_or_else(|err| err.to_string(), |bytes| binary::preview(&bytes));
+            (Cow::Owned(preview), self.syntaxes.find_syntax_plain_text())
+        } else {
+            let syntax = Path::new(&inner.name)
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .and_then(|ext| self.syntaxes.find_syntax_by_extension(ext))
+                .unwrap_or_else(|| self.syntaxes.find_syntax_plain_text());
+            (Cow::Borrowed(inner.data.as_str()), syntax)
+        };
+
+        let mut highlighter = HighlightLines::new(syntax, theme);
+        LinesWithEndings::from(&data)
+            .map(|line| {
+                let spans: Vec<Span<'static>> = highlighter
+                    .highlight(line, &self.syntaxes)
+                    .into_iter()
+                    .map(|(style, text)| {
+                        let fg = style.foreground;
+                        Span::styled(
+                            text.trim_end_matches(&['\r', '\n'][..]).to_owned(),
+                            Style::default().fg(Color::Rgb(fg.r, fg.g, fg.b)),
+                        )
+                    })
+                    .collect();
+                Spans::from(spans)
+            })
+            .collect()
+    }
+}
+
+async fn run(
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    app: &mut App,
+    bridge: &mut Bridge<Client>,
+) -> Result<()> {
+    loop {
+        terminal.draw(|frame| draw(frame, app))?;
+
+        let key = match event::read()? {
+            Event::Key(key) => key,
+            _ => continue,
+        };
+        if app.typing {
+            type_query(app, key);
+            continue;
+        }
+
+        match key.code {
+            KeyCode::Char('q') => return Ok(()),
+            KeyCode::Esc if app.preview.is_some() => app.preview = None,
+            KeyCode::Esc => return Ok(()),
+            KeyCode::Tab | KeyCode::Right => app.switch(app.pane.next()),
+            KeyCode::Char('/') => {
+                app.switch(Pane::Search);
+                app.typing = true;
+            }
+            KeyCode::Down | KeyCode::Char('j') => match app.preview {
+                Some(ref mut preview) => preview.scroll = preview.scroll.saturating_add(1),
+                None => app.select(|i| i + 1),
+            },
+            KeyCode::Up | KeyCode::Char('k') => match app.preview {
+                Some(ref mut preview) => preview.scroll = preview.scroll.saturating_sub(1),
+                None => app.select(|i| i.saturating_sub(1)),
+            },
+            KeyCode::Enter => {
+                if let Some(item) = app.selected() {
+                    app.status = format!("fetching {}", item.hash);
+                    terminal.draw(|frame| draw(frame, app))?;
+                    preview(app, bridge, &item).await;
+                }
+            }
+            KeyCode::Char('s') => {
+                if let Some(item) = app.selected() {
+                    if app.preview.is_none() {
+                        preview(app, bridge, &item).await;
+                    }
+                    app.status = match save(app) {
+                        Ok(path) => format!("saved at {}", path.to_string_lossy()),
+                        Err(err) => format!("failed to save: {}", err),
+                    };
+                }
+            }
+            KeyCode::Char('c') => {
+                if let Some(item) = app.selected() {
+                    app.status = match copy(&item.hash) {
+                        Ok(()) => format!("copied {}", item.hash),
+                        Err(err) => format!("failed to copy: {}", err),
+                    };
+                }
+            }
+            KeyCode::Char('d') => {
+                if let Some(item) = app.selected() {
+                    app.status = match delete(app, bridge, &item).await {
+                        Ok(()) => format!("removed {}", item.hash),
+                        Err(err) => format!("failed to remove: {}", err),
+                    };
+                    app.preview = None;
+                    app.select(|i| i);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+fn type_query(app: &mut App, key: KeyEvent) {
+    match key.code {
+        KeyCode::Char(c) => app.query.push(c),
+        KeyCode::Backspace => {
+            app.query.pop();
+        }
+        KeyCode::Enter | KeyCode::Esc => app.typing = false,
+        _ => (),
+    }
+    app.list.select(Some(0));
+}
+
+fn draw(frame: &mut Frame<CrosstermBackend<Stdout>>, app: &mut App) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3),
+            Constraint::Min(1),
+            Constraint::Length(1),
+        ])
+        .split(frame.size());
+
+    let titles = Pane::ALL
+        .iter()
+        .map(|pane| Spans::from(pane.title()))
+        .collect();
+    let tabs = Tabs::new(titles)
+        .select(app.pane.index())
+        .block(Block::default().borders(Borders::ALL).title("gistit"))
+        .highlight_style(
+            Style::default()
+                .fg(Color::Blue)
+                .add_modifier(Modifier::BOLD),
+        );
+    frame.render_widget(tabs, chunks[0]);
+
+    let body = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(40), Constraint::Percentage(60)])
+        .split(chunks[1]);
+
+    let title = match app.pane {
+        Pane::Search if app.typing => format!("Search: {}_", app.query),
+        Pane::Search => format!("Search: {}", app.query),
+        pane => pane.title().to_owned(),
+    };
+    let items: Vec<ListItem> = app
+        .items()
+        .iter()
+        .map(|item| {
+            let name = if item.name.is_empty() {
+                "-"
+            } else {
+                &item.name
+            };
+            ListItem::new(vec![
+                Spans::from(vec![
+                    Span::styled(
+                        item.hash.get(..12).unwrap_or(&item.hash).to_owned(),
+                        Style::default().add_modifier(Modifier::BOLD),
+                    ),
+                    Span::raw(format!(" {}", name)),
+                ]),
+                Spans::from(Span::styled(
+                    format!("  {}", item.detail),
+                    Style::default().fg(Color::DarkGray),
+                )),
+            ])
+        })
+        .collect();
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(Style::default().fg(Color::Green))
+        .highlight_symbol("> ");
+    frame.render_stateful_widget(list, body[0], &mut app.list);
+
+    let preview = match app.preview {
+        Some(ref preview) => {
+            let header = format!(
+                "{} | {}",
+                preview
+                    .gistit
+                    .inner
+                    .first()
+                    .map_or("", |inner| inner.name.as_str()),
+                preview.gistit.author
+            );
+            Paragraph::new(preview.lines.clone())
+                .block(Block::default().borders(Borders::ALL).title(header))
+                .scroll((preview.scroll, 0))
+        }
+        None => Paragraph::new("press enter to preview")
+            .block(Block::default().borders(Borders::ALL).title("Preview")),
+    };
+    frame.render_widget(preview, body[1]);
+
+    frame.render_widget(
+        Paragraph::new(app.status.as_str()).style(Style::default().fg(Color::DarkGray)),
+        chunks[2],
+    );
+}
+
+/// Fetches `item`, from the p2p network first when the daemon is running
+async fn preview(app: &mut App, bridge: &mut Bridge<Client>, item: &Item) {
+    let token = credentials::owner_token(&item.hash);
+    let fetched = if bridge.alive() {
+        match fetch_p2p(bridge, &item.hash, false).await {
+            Ok(Some(gistit)) => Ok(Some(gistit)),
+            _ if item.p2p => Ok(None),
+            _ => fetch_server(&item.hash, token.as_deref(), false).await,
+        }
+    } else if item.p2p {
+        Err(Error::Daemon("gistit node is not running"))
+    } else {
+        fetch_server(&item.hash, token.as_deref(), false).await
+    };
+
+    match fetched {
+        Ok(Some(gistit)) if secret::is_encrypted(&gistit) => {
+            app.status = "gistit is encrypted, fetch it with `gistit fetch --secret`".to_owned();
+        }
+        Ok(Some(gistit)) => {
+            app.preview = Some(Preview {
+                lines: app.highlight(&gistit),
+                gistit,
+                scroll: 0,
+            });
+            app.status = HELP.to_owned();
+        }
+        Ok(None) => app.status = format!("{} not found", item.hash),
+        Err(err) => app.status = format!("failed to fetch: {}", err),
+    }
+}
+
+/// Saves the previewed gistit in the data directory, like `gistit fetch --save`
+fn save(app: &App) -> Result<PathBuf> {
+    let inner = app
+        .preview
+        .as_ref()
+        .and_then(|preview| preview.gistit.inner.first())
+        .ok_or(Error::Server("nothing to save"))?;
+
+    let mut file = File::from_data(binary::content(inner)?, &inner.name)?;
+    let file_path = app.data_path.join(file.name());
+    file.save_as(&file_path)?;
+    Ok(file_path)
+}
+
+fn copy(hash: &str) -> Result<()> {
+    Clipboard::new(hash)
+        .into_selected()
+        .into_provider()
+        .set_contents()
+}
+
+/// Unpins gistits in the daemon storage, removes the others from the cache index
+async fn delete(app: &mut App, bridge: &mut Bridge<Client>, item: &Item) -> Result<()> {
+    if item.stored {
+        bridge.connect_blocking()?;
+        bridge
+            .send(Instruction::request_pin(item.hash.clone(), false))
+            .await?;
+
+        match bridge.recv().await?.expect_response()? {
+            ipc::instruction::Kind::PinResponse(ipc::instruction::PinResponse {
+                changed: true,
+            }) => {
+                audit::note(&item.hash, audit::P2P);
+                app.daemon.retain(|other| other.hash != item.hash);
+            }
+            _ => return Err(Error::Daemon("gistit is not pinned")),
+        }
+    } else {
+        let mut index = Index::load()?;
+        index.remove(&item.hash);
+        index.save()?;
+        app.cache.retain(|other| other.hash != item.hash);
+    }
+    Ok(())
+}
+
+async fn list_pins(bridge: &mut Bridge<Client>) -> Result<Vec<ipc::instruction::Pin>> {
+    if !bridge.alive() {
+        return Err(Error::Daemon("gistit node is not running"));
+    }
+    bridge.connect_blocking()?;
+    bridge.send(Instruction::request_list_pins()).await?;
+
+    match bridge.recv().await?.expect_response()? {
+        ipc::instruction::Kind::ListPinsResponse(ipc::instruction::ListPinsResponse {
+            pins,
+            ..
+        }) => Ok(pins),
+        _ => Err(Error::Daemon("unexpected response from gistit-daemon")),
+    }
+}
+
+const fn origin(origin: Origin) -> &'static str {
+    match origin {
+        Origin::Sent => "sent",
+        Origin::Hosted => "hosted",
+        Origin::Fetched => "fetched",
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Check your system time")
+        .as_secs()
+}
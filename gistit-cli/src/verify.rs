@@ -0,0 +1,138 @@
+//! The verify module
+//!
+//! `gistit verify <hash>` fetches a gistit without previewing or saving it and checks it's content
+//! against it's hash, see [`gistit_core::verify`]. Useful after it went through peers or relays you
+//! don't trust.
+
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use clap::ArgMatches;
+use console::style;
+
+use gistit_core::Integrity;
+use gistit_project::path;
+
+use crate::daemon;
+use crate::dispatch::Dispatch;
+use crate::fetch::{self, Prefer};
+use crate::param::{check, Identifier};
+use crate::settings::{get_runtime_settings, Backend};
+use crate::{finish, progress, updateln, Error, Result};
+
+#[derive(Debug, Clone)]
+pub struct Action<'a> {
+    pub hash: &'a str,
+    pub prefer: Option<&'a str>,
+    pub token: Option<&'a str>,
+}
+
+impl<'a> Action<'a> {
+    pub fn from_args(
+        args: &'a ArgMatches,
+    ) -> Result<Box<dyn Dispatch<InnerData = Config<'a>> + Send + Sync + 'a>> {
+        Ok(Box::new(Self {
+            hash: args
+                .value_of("HASH")
+                .ok_or(Error::Argument("missing argument", "[HASH]"))?,
+            prefer: args.value_of("prefer"),
+            token: args.value_of("token"),
+        }))
+    }
+}
+
+pub struct Config<'a> {
+    identifier: Identifier<'a>,
+    prefer: Prefer,
+    backend: Backend,
+    token: Option<&'a str>,
+    runtime_path: PathBuf,
+    config_path: PathBuf,
+}
+
+#[async_trait]
+impl<'a> Dispatch for Action<'a> {
+    type InnerData = Config<'a>;
+
+    async fn prepare(&self) -> Result<Self::InnerData> {
+        progress!("Preparing");
+        let settings = get_runtime_settings()?;
+        let config = Config {
+            identifier: check::identifier(self.hash)?,
+            prefer: self.prefer.map_or_else(
+                || Ok(Prefer::from_backend(settings.backend())),
+                Prefer::parse,
+            )?,
+            backend: settings.backend(),
+            token: self.token.map(check::token).transpose()?,
+            runtime_path: path::runtime_dir()?,
+            config_path: path::config()?,
+        };
+        updateln!("Prepared");
+        Ok(config)
+    }
+
+    async fn dispatch(&self, config: Self::InnerData) -> Result<()> {
+        let mut bridge = gistit_ipc::client(&config.runtime_path)?;
+        daemon::spawn_if_required(
+            config.backend,
+            &bridge,
+            &config.runtime_path,
+            &config.config_path,
+        )
+        .await?;
+
+        let (hash, sources) = fetch::locate(&mut bridge, config.identifier, config.prefer).await?;
+        let token = fetch::owner_token(config.token, &hash, &sources);
+
+        // Never agreeing to burn it, verifying would leave nothing to fetch
+        let (gistit, source) =
+            match fetch::fetch_any(&mut bridge, &hash, sources, token.as_deref()).await {
+                Err(Error::Core(gistit_core::Error::BurnAfterRead)) => {
+                    return Err(Error::Argument(
+                        "gistit is deleted once read, verifying it would delete it",
+                        "[HASH]",
+                    ));
+                }
+                fetched => fetched?,
+            };
+
+        progress!("Verifying");
+        let integrity = gistit_core::verify(&gistit, &hash);
+        updateln!("Verified");
+        finish!(format_integrity(&integrity, &hash, &gistit.hash, source));
+
+        if integrity.ok() {
+            Ok(())
+        } else {
+            Err(Error::Integrity)
+        }
+    }
+}
+
+fn format_integrity(
+    integrity: &Integrity,
+    requested: &str,
+    carried: &str,
+    source: fetch::Source,
+) -> String {
+    let check = |ok: bool, failed: String| {
+        if ok {
+            style("ok").green().to_string()
+        } else {
+            style(failed).red().to_string()
+        }
+    };
+
+    format!(
+        "\n    hash: '{}'\n    found: {}\n    content: {}\n    requested: {}\n    sizes: {}\n\n",
+        style(requested).bold(),
+        source,
+        check(
+            integrity.hash,
+            format!("hashes to '{}'", integrity.computed)
+        ),
+        check(integrity.requested, format!("got '{}' instead", carried)),
+        check(integrity.sizes, "don't match the content".to_owned()),
+    )
+}
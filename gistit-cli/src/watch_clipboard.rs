@@ -0,0 +1,144 @@
+//! The watch clipboard module
+//!
+//! Polls the system clipboard and, whenever something with at least `--min-lines` lines is copied,
+//! offers to send it to the server as a gistit. The clipboard contents are then replaced with the
+//! share url. With `--auto` it doesn't ask. Reading the clipboard needs one of the binaries listed in
+//! [`crate::clipboard`], OSC52 only works one way.
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use clap::ArgMatches;
+use console::{style, Term};
+
+use crate::cache::{self, Entry, Origin};
+use crate::clipboard::{Clipboard, Provider};
+use crate::dispatch::Dispatch;
+use crate::file::File;
+use crate::param::check;
+use crate::send::{first_file_name, new_gistit, random_author, send_server, share_url};
+use crate::settings::get_runtime_settings;
+use crate::{cleanln, progress, updateln, warnln, Result};
+
+const CLIPBOARD_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+const DEFAULT_MIN_LINES: usize = 5;
+
+#[derive(Debug, Clone)]
+pub struct Action<'a> {
+    pub min_lines: Option<&'a str>,
+    pub author: Option<&'a str>,
+    pub auto: bool,
+}
+
+impl<'a> Action<'a> {
+    pub fn from_args(
+        args: &'a ArgMatches,
+    ) -> Result<Box<dyn Dispatch<InnerData = Config> + Send + Sync + 'a>> {
+        Ok(Box::new(Self {
+            min_lines: args.value_of("min-lines"),
+            author: args.value_of("author"),
+            auto: args.is_present("auto"),
+        }))
+    }
+}
+
+pub struct Config {
+    min_lines: usize,
+    author: String,
+}
+
+#[async_trait]
+impl<'a> Dispatch for Action<'a> {
+    type InnerData = Config;
+
+    async fn prepare(&self) -> Result<Self::InnerData> {
+        let settings = get_runtime_settings()?;
+        let author = match (self.author, settings.author.as_deref()) {
+            (Some(author), _) | (None, Some(author)) => check::author(author)?.to_owned(),
+            (None, None) => random_author(),
+        };
+
+        Ok(Config {
+            min_lines: self
+                .min_lines
+                .map_or(Ok(DEFAULT_MIN_LINES), check::min_lines)?,
+            author,
+        })
+    }
+
+    async fn dispatch(&self, config: Self::InnerData) -> Result<()> {
+        let provider = Clipboard::new("").into_selected().into_provider();
+        // Whatever was copied before we started isn't offered
+        let mut last = provider.get_contents()?;
+
+        progress!("Watching the clipboard, press Ctrl-C to stop");
+        loop {
+            tokio::time::sleep(CLIPBOARD_POLL_INTERVAL).await;
+
+            let content = match provider.get_contents() {
+                Ok(content) => content,
+                Err(err) => {
+                    warnln!("failed to read the clipboard: {}", err);
+                    continue;
+                }
+            };
+            if content == last {
+                continue;
+            }
+            last = content.clone();
+
+            let lines = content.lines().count();
+            if lines < config.min_lines || check::content_size(&content).is_err() {
+                continue;
+            }
+            if !self.auto && !confirm(lines).await? {
+                continue;
+            }
+
+            progress!("Sending");
+            match share(&content, &config.author).await {
+                Ok(url) => {
+                    Clipboard::new(&url)
+                        .into_selected()
+                        .into_provider()
+                        .set_contents()?;
+                    // Our own url isn't offered back
+                    last = url.clone();
+                    updateln!("Sent {} lines, copied '{}'", lines, style(url).bold());
+                }
+                Err(err) => warnln!("{}, clipboard left untouched", err),
+            }
+            progress!("Watching the clipboard, press Ctrl-C to stop");
+        }
+    }
+}
+
+/// Asks whether to share what was just copied
+async fn confirm(lines: usize) -> Result<bool> {
+    cleanln!(format!(
+        "copied {} lines, send them as a gistit? {}",
+        lines,
+        style("[y/N]").dim()
+    ));
+    let answer = tokio::task::spawn_blocking(|| Term::stderr().read_char())
+        .await
+        .expect("prompt to not panic")?;
+
+    Ok(matches!(answer, 'y' | 'Y'))
+}
+
+/// Sends `content` to the server, returning it's share url
+async fn share(content: &str, author: &str) -> Result<String> {
+    let file = File::from_data(content, "clipboard")?;
+    let gistit = new_gistit(&file, author.to_owned(), None)?;
+    let hash = send_server(&gistit).await?;
+    cache::record_or_warn(Entry::new(
+        &hash,
+        &first_file_name(&gistit),
+        &gistit.author,
+        Origin::Sent,
+    ));
+
+    Ok(share_url(&hash))
+}
@@ -0,0 +1,337 @@
+//! The workspace module
+//!
+//! A workspace is a shared secret and a gossipsub topic a team passes around as a join token,
+//! made with `gistit workspace create <name>` and joined with `gistit workspace join <token>`.
+//! While one is in use, `workspace = "<name>"` in the settings, gistits sent are encrypted with
+//! it's key and announced on it's topic, fetching decrypts with it when no other secret is given
+//! and `gistit subscribe` follows it's topic when none is given. A private snippet channel with no
+//! flags to remember.
+//!
+//! Join tokens hold the key, they're kept in the OS credential store, see [`crate::credentials`].
+
+use async_trait::async_trait;
+use clap::ArgMatches;
+use console::style;
+
+use crate::credentials::{self, Credential};
+use crate::dispatch::Dispatch;
+use crate::param::check;
+use crate::send;
+use crate::settings::{get_runtime_settings, Settings};
+use crate::{finish, Error, Result};
+
+/// Starts every join token, so a token pasted in the wrong place is told apart
+const TOKEN_PREFIX: &str = "gistit-workspace:";
+
+/// Random characters ending generated topics, so outsiders don't stumble on them
+const TOPIC_SUFFIX_LENGTH: usize = 12;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Workspace {
+    pub name: String,
+    /// Gossipsub topic gistits sent are announced on
+    pub topic: String,
+    /// Secret gistits sent are encrypted with
+    pub key: String,
+}
+
+impl Workspace {
+    /// A workspace with a new key, announcing on `topic` or a generated one
+    fn new(name: &str, topic: Option<&str>) -> Self {
+        let key = send::new_secret();
+        let topic = match topic {
+            Some(topic) => topic.to_owned(),
+            None => {
+                let suffix = &send::new_secret()[..TOPIC_SUFFIX_LENGTH];
+                format!("workspace/{}/{}", name, suffix)
+            }
+        };
+
+        Self {
+            name: name.to_owned(),
+            topic,
+            key,
+        }
+    }
+
+    /// The token others join it with, holding the key
+    #[must_use]
+    pub fn token(&self) -> String {
+        let encoded = base64::encode_config(
+            format!("{}\n{}\n{}", self.name, self.topic, self.key),
+            base64::URL_SAFE_NO_PAD,
+        );
+        format!("{}{}", TOKEN_PREFIX, encoded)
+    }
+
+    /// Reads a join token
+    ///
+    /// # Errors
+    ///
+    /// Fails if `token` isn't a join token or what it holds is invalid
+    pub fn from_token(token: &str) -> Result<Self> {
+        let invalid = || Error::Argument("invalid workspace join token", "[TOKEN]");
+        let encoded = token
+            .trim()
+            .strip_prefix(TOKEN_PREFIX)
+            .ok_or_else(invalid)?;
+        let decoded = base64::decode_config(encoded, base64::URL_SAFE_NO_PAD)
+            .ok()
+            .and_then(|decoded| String::from_utf8(decoded).ok())
+            .ok_or_else(invalid)?;
+
+        let mut parts = decoded.split('\n');
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(name), Some(topic), Some(key), None)
+                if check::snippet(name).is_ok()
+                    && check::topic(topic).is_ok()
+                    && check::secret(key).is_ok() =>
+            {
+                Ok(Self {
+                    name: name.to_owned(),
+                    topic: topic.to_owned(),
+                    key: key.to_owned(),
+                })
+            }
+            _ => Err(invalid()),
+        }
+    }
+
+    /// The joined workspace `name`, `None` if it isn't
+    ///
+    /// # Errors
+    ///
+    /// Fails if the credential store can't be reached or holds an invalid token
+    pub fn load(name: &str) -> Result<Option<Self>> {
+        Credential::Workspace(name.to_owned())
+            .get()?
+            .map(|token| Self::from_token(&token))
+            .transpose()
+    }
+
+    /// The workspace in use according to `settings`, if any
+    ///
+    /// # Errors
+    ///
+    /// Fails if it isn't joined or the credential store can't be reached
+    pub fn active(settings: &Settings) -> Result<Option<Self>> {
+        match settings.workspace {
+            Some(ref name) => Self::load(name)?.map(Some).ok_or(Error::Secret(
+                "the workspace in the settings isn't joined, see `gistit workspace ls`",
+            )),
+            None => Ok(None),
+        }
+    }
+
+    fn save(&self) -> Result<()> {
+        Credential::Workspace(self.name.clone()).set(&self.token())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Action<'a> {
+    Create {
+        name: &'a str,
+        topic: Option<&'a str>,
+    },
+    Join(&'a str),
+    Ls,
+    Use(&'a str),
+    Leave(&'a str),
+}
+
+impl<'a> Action<'a> {
+    pub fn from_args(
+        args: &'a ArgMatches,
+    ) -> Result<Box<dyn Dispatch<InnerData = Config> + Send + Sync + 'a>> {
+        let name = |args: &'a ArgMatches| {
+            args.value_of("NAME")
+                .ok_or(Error::Argument("missing workspace name", "[NAME]"))
+        };
+        let action = match args.subcommand() {
+            Some(("create", args)) => Self::Create {
+                name: name(args)?,
+                topic: args.value_of("topic"),
+            },
+            Some(("join", args)) => Self::Join(
+                args.value_of("TOKEN")
+                    .ok_or(Error::Argument("missing join token", "[TOKEN]"))?,
+            ),
+            Some(("ls", _)) => Self::Ls,
+            Some(("use", args)) => Self::Use(name(args)?),
+            Some(("leave", args)) => Self::Leave(name(args)?),
+            _ => return Err(Error::Argument("missing subcommand", "workspace")),
+        };
+        Ok(Box::new(action))
+    }
+}
+
+pub struct Config;
+
+#[async_trait]
+impl<'a> Dispatch for Action<'a> {
+    type InnerData = Config;
+
+    async fn prepare(&self) -> Result<Self::InnerData> {
+        if let Self::Create { name, .. } | Self::Use(name) | Self::Leave(name) = self {
+            // Same rules as snippet names
+            check::snippet(name).map_err(|_| {
+                Error::Argument(
+                    "invalid workspace name, expected letters, digits, '-', '_' or '.'",
+                    "[NAME]",
+                )
+            })?;
+        }
+        if let Self::Create {
+            topic: Some(topic), ..
+        } = self
+        {
+            check::topic(topic)?;
+        }
+        Ok(Config)
+    }
+
+    async fn dispatch(&self, _config: Self::InnerData) -> Result<()> {
+        match self {
+            Self::Create { name, topic } => {
+                if Workspace::load(name)?.is_some() {
+                    return Err(Error::Argument(
+                        "a workspace with this name is joined already, leave it first",
+                        "[NAME]",
+                    ));
+                }
+                let workspace = Workspace::new(name, *topic);
+                workspace.save()?;
+                select(Some(*name))?;
+
+                finish!(format!(
+                    "{}\n    token: '{}'\n\n    share the token privately, it holds the key\n\n",
+                    format_workspace(&workspace),
+                    style(workspace.token()).bold()
+                ));
+            }
+            Self::Join(token) => {
+                let workspace = Workspace::from_token(token)?;
+                workspace.save()?;
+                select(Some(workspace.name.as_str()))?;
+
+                finish!(format!("{}\n\n", format_workspace(&workspace)));
+            }
+            Self::Ls => {
+                let names = credentials::workspace_names()?;
+                if names.is_empty() {
+                    finish!(
+                        "\n    no workspaces, create one with `gistit workspace create <name>`\n\n"
+                    );
+                    return Ok(());
+                }
+
+                let active = get_runtime_settings()?.workspace;
+                let mut lines = String::new();
+                for name in names {
+                    let topic = Workspace::load(&name)?
+                        .map_or_else(|| style("missing").red().to_string(), |ws| ws.topic);
+                    let marker = if active.as_ref() == Some(&name) {
+                        style(" (in use)").green().to_string()
+                    } else {
+                        String::new()
+                    };
+                    lines.push_str(&format!(
+                        "\n    {}{}  {}",
+                        style(name).bold(),
+                        marker,
+                        style(topic).dim()
+                    ));
+                }
+                finish!(format!("{}\n\n", lines));
+            }
+            Self::Use(name) => {
+                if Workspace::load(name)?.is_none() {
+                    return Err(Error::Argument(
+                        "no workspace joined with this name, see `gistit workspace ls`",
+                        "[NAME]",
+                    ));
+                }
+                select(Some(*name))?;
+                finish!(format!(
+                    "\n    workspace: '{}' in use\n\n",
+                    style(name).bold()
+                ));
+            }
+            Self::Leave(name) => {
+                let left = Credential::Workspace((*name).to_owned()).delete()?;
+                let settings = Settings::load()?;
+                if settings.workspace.as_deref() == Some(*name) {
+                    select(None)?;
+                }
+
+                let state = if left { "left" } else { "wasn't joined" };
+                finish!(format!(
+                    "\n    workspace '{}': {}\n\n",
+                    style(name).bold(),
+                    state
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Puts the workspace `name` in use in the global settings, or none
+fn select(name: Option<&str>) -> Result<()> {
+    let mut settings = Settings::load()?;
+    settings.workspace = name.map(ToOwned::to_owned);
+    settings.save()
+}
+
+fn format_workspace(workspace: &Workspace) -> String {
+    format!(
+        "\n    workspace: '{}' (in use)\n    topic: '{}'",
+        style(&workspace.name).bold(),
+        workspace.topic
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn workspace_token_roundtrip() {
+        let workspace = Workspace::new("team", None);
+        assert!(workspace.topic.starts_with("workspace/team/"));
+        assert!(check::topic(&workspace.topic).is_ok());
+
+        let token = workspace.token();
+        assert!(token.starts_with(TOKEN_PREFIX));
+        assert!(!token.contains(&workspace.key));
+        assert_eq!(Workspace::from_token(&token).unwrap(), workspace);
+        assert_eq!(
+            Workspace::from_token(&format!(" {}\n", token)).unwrap(),
+            workspace
+        );
+
+        let custom = Workspace::new("team", Some("rust/snippets"));
+        assert_eq!(custom.topic, "rust/snippets");
+    }
+
+    #[test]
+    fn workspace_invalid_tokens() {
+        let encode = |content: &str| {
+            format!(
+                "{}{}",
+                TOKEN_PREFIX,
+                base64::encode_config(content, base64::URL_SAFE_NO_PAD)
+            )
+        };
+
+        assert!(Workspace::from_token("").is_err());
+        assert!(Workspace::from_token("gistit-workspace:!!!").is_err());
+        assert!(Workspace::from_token(&encode("team\ntopic\nkey12")[1..]).is_err());
+        assert!(Workspace::from_token(&encode("team\ntopic")).is_err());
+        assert!(Workspace::from_token(&encode("team\nthe topic\nkey12")).is_err());
+        assert!(Workspace::from_token(&encode("team\ntopic\nkey")).is_err());
+        assert!(Workspace::from_token(&encode("team\ntopic\nkey12\nmore")).is_err());
+        assert!(Workspace::from_token(&encode("team\ntopic\nkey12")).is_ok());
+    }
+}
@@ -0,0 +1,82 @@
+//! The comment module
+//!
+//! Comment threads on gistits. Only the server keeps them, gistits hosted through the p2p network
+//! can't be commented on. Comments on private gistits need their owner token.
+
+use reqwest::StatusCode;
+use url::Url;
+
+use gistit_proto::prost::Message;
+use gistit_proto::{Comment, Comments, Gistit};
+
+use crate::{http_client, rate_limited, Error, Result};
+
+/// A comment by `author` on the gistit `hash`, the server sets it's timestamp
+#[must_use]
+pub fn new_comment(hash: &str, author: &str, body: &str, token: Option<&str>) -> Comment {
+    Comment {
+        hash: hash.to_owned(),
+        author: author.to_owned(),
+        body: body.to_owned(),
+        timestamp: String::new(),
+        token: token.map(ToOwned::to_owned),
+    }
+}
+
+/// Posts `comment` to the server at `url`
+///
+/// # Errors
+///
+/// Fails with [`Error::NotFound`] if the gistit isn't there or the token can't read it, or if the
+/// server can't be reached or rejects the comment
+#[tracing::instrument(level = "debug", skip(url, comment), fields(url = %url))]
+pub async fn comment_server(url: &Url, comment: &Comment) -> Result<()> {
+    let response = http_client()
+        .post(url.join("comment")?)
+        .header("content-type", "application/x-protobuf")
+        .body(comment.encode_to_vec())
+        .send()
+        .await?;
+    tracing::debug!(status = %response.status(), "server answered");
+
+    match response.status() {
+        StatusCode::OK => Ok(()),
+        StatusCode::NOT_FOUND => Err(Error::NotFound),
+        StatusCode::BAD_REQUEST => Err(Error::Server("invalid comment")),
+        StatusCode::TOO_MANY_REQUESTS => Err(rate_limited(&response)),
+        _ => Err(Error::Server("unexpected response")),
+    }
+}
+
+/// Asks the server at `url` for the comments on `hash`, oldest first. Private gistits are only
+/// answered with their owner `token`
+///
+/// # Errors
+///
+/// Fails with [`Error::NotFound`] if the gistit isn't there or the token can't read it, or if the
+/// server can't be reached or answers unexpectedly
+#[tracing::instrument(level = "debug", skip(url, token), fields(url = %url))]
+pub async fn comments_server(url: &Url, hash: &str, token: Option<&str>) -> Result<Vec<Comment>> {
+    let gistit = Gistit {
+        hash: hash.to_owned(),
+        token: token.map(ToOwned::to_owned),
+        ..Gistit::default()
+    };
+
+    let response = http_client()
+        .post(url.join("comments")?)
+        .header("content-type", "application/x-protobuf")
+        .body(gistit.encode_to_vec())
+        .send()
+        .await?;
+    tracing::debug!(status = %response.status(), "server answered");
+
+    match response.status() {
+        StatusCode::OK => Ok(Comments::decode(response.bytes().await?)
+            .map_err(gistit_proto::Error::from)?
+            .comments),
+        StatusCode::NOT_FOUND => Err(Error::NotFound),
+        StatusCode::TOO_MANY_REQUESTS => Err(rate_limited(&response)),
+        _ => Err(Error::Server("unexpected response")),
+    }
+}
@@ -0,0 +1,390 @@
+//! The fetch module
+//!
+//! Fetches a gistit from the server or the p2p network, trying each source in order, and decrypts
+//! it if it was sent with a secret. [`head`] only fetches it's metadata.
+
+#[cfg(feature = "daemon")]
+use std::path::{Path, PathBuf};
+#[cfg(feature = "daemon")]
+use std::time::Duration;
+
+use reqwest::StatusCode;
+#[cfg(feature = "daemon")]
+use tokio::time::timeout;
+use url::Url;
+
+use gistit_proto::prost::Message;
+#[cfg(feature = "daemon")]
+use gistit_proto::{ipc, Instruction};
+use gistit_proto::{Candidates, Gistit, Head, History, Inner};
+
+use crate::{default_server_url, http_client, rate_limited, secret, Error, Result};
+
+/// How long to wait for `gistit-daemon` to report progress before trying the next source
+#[cfg(feature = "daemon")]
+const P2P_FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Where to look for a gistit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    /// The gistit server, at this base url
+    Server(Url),
+    /// The p2p network, through the `gistit-daemon` listening in this runtime directory
+    #[cfg(feature = "daemon")]
+    Daemon(PathBuf),
+}
+
+impl Default for Source {
+    fn default() -> Self {
+        Self::Server(default_server_url())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetchRequest {
+    pub hash: String,
+    /// Decrypts the gistit with this secret, if it was sent encrypted
+    pub secret: Option<String>,
+    /// Owner token of a private gistit, only sent to the server
+    pub token: Option<String>,
+    /// Agrees to fetch gistits deleted after the first successful fetch, they fail with
+    /// [`Error::BurnAfterRead`] otherwise
+    pub burn_after_read: bool,
+    /// Tried in order until one has the gistit
+    pub sources: Vec<Source>,
+}
+
+impl FetchRequest {
+    /// Fetches `hash` from the default server
+    #[must_use]
+    pub fn new(hash: &str) -> Self {
+        Self {
+            hash: hash.to_owned(),
+            secret: None,
+            token: None,
+            burn_after_read: false,
+            sources: vec![Source::default()],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FetchedGistit {
+    pub gistit: Gistit,
+    /// Where it was found
+    pub source: Source,
+}
+
+impl FetchedGistit {
+    /// Every file, names are relative paths when there's more than one
+    #[must_use]
+    pub fn files(&self) -> &[Inner] {
+        &self.gistit.inner
+    }
+
+    // NOTE: The ones below are about the first file
+    #[must_use]
+    pub fn name(&self) -> &str {
+        self.gistit.inner.first().map_or("", |inner| &inner.name)
+    }
+
+    #[must_use]
+    pub fn lang(&self) -> &str {
+        self.gistit.inner.first().map_or("", |inner| &inner.lang)
+    }
+
+    #[must_use]
+    pub fn data(&self) -> &str {
+        self.gistit.inner.first().map_or("", |inner| &inner.data)
+    }
+
+    #[must_use]
+    pub fn author(&self) -> &str {
+        &self.gistit.author
+    }
+}
+
+/// Fetches a gistit from the first source that has it
+///
+/// # Errors
+///
+/// Fails with [`Error::NotFound`] if no source has it, with the last source error if one failed,
+/// if it's encrypted and the secret is missing or wrong, or with [`Error::BurnAfterRead`] if it's
+/// deleted once read and the request didn't agree to it
+pub async fn fetch(request: FetchRequest) -> Result<FetchedGistit> {
+    let mut last_err = Error::NotFound;
+
+    for source in request.sources {
+        let fetched = match source {
+            Source::Server(ref url) => {
+                fetch_server(
+                    url,
+                    &request.hash,
+                    request.token.as_deref(),
+                    request.burn_after_read,
+                )
+                .await
+            }
+            #[cfg(feature = "daemon")]
+            Source::Daemon(ref runtime_path) => {
+                fetch_daemon(runtime_path, &request.hash, request.burn_after_read).await
+            }
+        };
+
+        match fetched {
+            Ok(Some(mut gistit)) => {
+                if secret::is_encrypted(&gistit) {
+                    let secret = request
+                        .secret
+                        .as_deref()
+                        .ok_or(Error::Secret("gistit is encrypted, a secret is needed"))?;
+                    secret::open(&mut gistit, secret)?;
+                }
+                return Ok(FetchedGistit { gistit, source });
+            }
+            // Found, the other sources won't have it either
+            Err(Error::BurnAfterRead) => return Err(Error::BurnAfterRead),
+            Ok(None) => (),
+            Err(err) => last_err = err,
+        }
+    }
+
+    Err(last_err)
+}
+
+/// Fetches the metadata of a gistit from the first source that has it, without it's content
+///
+/// # Errors
+///
+/// Fails with [`Error::NotFound`] if no source has it, or with the last source error if one failed
+pub async fn head(request: FetchRequest) -> Result<(Head, Source)> {
+    let mut last_err = Error::NotFound;
+
+    for source in request.sources {
+        let head = match source {
+            Source::Server(ref url) => {
+                head_server(url, &request.hash, request.token.as_deref()).await
+            }
+            #[cfg(feature = "daemon")]
+            Source::Daemon(ref runtime_path) => head_daemon(runtime_path, &request.hash).await,
+        };
+
+        match head {
+            Ok(Some(head)) => return Ok((head, source)),
+            Ok(None) => (),
+            Err(err) => last_err = err,
+        }
+    }
+
+    Err(last_err)
+}
+
+/// Asks the server at `url` for `hash`, `None` if it doesn't have it. Private gistits are only
+/// answered with their owner `token`, gistits deleted once read only if `burn_after_read`
+///
+/// # Errors
+///
+/// Fails if the server can't be reached or answers unexpectedly, or with
+/// [`Error::BurnAfterRead`] if it's deleted once read and `burn_after_read` is unset
+#[tracing::instrument(level = "debug", skip(url, token), fields(url = %url))]
+pub async fn fetch_server(
+    url: &Url,
+    hash: &str,
+    token: Option<&str>,
+    burn_after_read: bool,
+) -> Result<Option<Gistit>> {
+    let gistit = Gistit {
+        hash: hash.to_owned(),
+        token: token.map(ToOwned::to_owned),
+        burn_after_read,
+        ..Gistit::default()
+    };
+
+    let response = http_client()
+        .post(url.join("get")?)
+        .header("content-type", "application/x-protobuf")
+        .body(gistit.encode_to_vec())
+        .send()
+        .await?;
+    tracing::debug!(status = %response.status(), "server answered");
+
+    match response.status() {
+        StatusCode::OK => Ok(Some(Gistit::from_bytes(response.bytes().await?)?)),
+        StatusCode::NOT_FOUND => Ok(None),
+        StatusCode::CONFLICT => Err(Error::BurnAfterRead),
+        StatusCode::TOO_MANY_REQUESTS => Err(rate_limited(&response)),
+        _ => Err(Error::Server("unexpected response")),
+    }
+}
+
+/// Asks the server at `url` for the metadata of `hash`, `None` if it doesn't have it. Private
+/// gistits are only answered with their owner `token`
+///
+/// # Errors
+///
+/// Fails if the server can't be reached or answers unexpectedly
+#[tracing::instrument(level = "debug", skip(url, token), fields(url = %url))]
+pub async fn head_server(url: &Url, hash: &str, token: Option<&str>) -> Result<Option<Head>> {
+    let gistit = Gistit {
+        hash: hash.to_owned(),
+        token: token.map(ToOwned::to_owned),
+        ..Gistit::default()
+    };
+
+    let response = http_client()
+        .post(url.join("head")?)
+        .header("content-type", "application/x-protobuf")
+        .body(gistit.encode_to_vec())
+        .send()
+        .await?;
+    tracing::debug!(status = %response.status(), "server answered");
+
+    match response.status() {
+        StatusCode::OK => Ok(Some(
+            Head::decode(response.bytes().await?).map_err(gistit_proto::Error::from)?,
+        )),
+        StatusCode::NOT_FOUND => Ok(None),
+        StatusCode::TOO_MANY_REQUESTS => Err(rate_limited(&response)),
+        _ => Err(Error::Server("unexpected response")),
+    }
+}
+
+/// Asks the server at `url` for the revisions of `hash`, newest first, following their parents.
+/// `None` if it doesn't have it. Private revisions are only answered with their owner `token`
+///
+/// # Errors
+///
+/// Fails if the server can't be reached or answers unexpectedly
+#[tracing::instrument(level = "debug", skip(url, token), fields(url = %url))]
+pub async fn history_server(url: &Url, hash: &str, token: Option<&str>) -> Result<Option<History>> {
+    let gistit = Gistit {
+        hash: hash.to_owned(),
+        token: token.map(ToOwned::to_owned),
+        ..Gistit::default()
+    };
+
+    let response = http_client()
+        .post(url.join("history")?)
+        .header("content-type", "application/x-protobuf")
+        .body(gistit.encode_to_vec())
+        .send()
+        .await?;
+    tracing::debug!(status = %response.status(), "server answered");
+
+    match response.status() {
+        StatusCode::OK => Ok(Some(
+            History::decode(response.bytes().await?).map_err(gistit_proto::Error::from)?,
+        )),
+        StatusCode::NOT_FOUND => Ok(None),
+        StatusCode::TOO_MANY_REQUESTS => Err(rate_limited(&response)),
+        _ => Err(Error::Server("unexpected response")),
+    }
+}
+
+/// Asks the server at `url` for the hashes starting with `prefix`, to expand a short hash. Only
+/// public gistits are answered, hashes of unlisted and private ones are never listed
+///
+/// # Errors
+///
+/// Fails if the server can't be reached or answers unexpectedly
+#[tracing::instrument(level = "debug", skip(url), fields(url = %url))]
+pub async fn resolve_server(url: &Url, prefix: &str) -> Result<Vec<String>> {
+    let gistit = Gistit {
+        hash: prefix.to_owned(),
+        ..Gistit::default()
+    };
+
+    let response = http_client()
+        .post(url.join("resolve")?)
+        .header("content-type", "application/x-protobuf")
+        .body(gistit.encode_to_vec())
+        .send()
+        .await?;
+    tracing::debug!(status = %response.status(), "server answered");
+
+    match response.status() {
+        StatusCode::OK => Ok(Candidates::decode(response.bytes().await?)
+            .map_err(gistit_proto::Error::from)?
+            .hashes),
+        StatusCode::NOT_FOUND => Ok(Vec::new()),
+        StatusCode::TOO_MANY_REQUESTS => Err(rate_limited(&response)),
+        _ => Err(Error::Server("unexpected response")),
+    }
+}
+
+/// Asks `gistit-daemon` for the metadata of `hash`, `None` if no provider has it
+///
+/// # Errors
+///
+/// Fails if `gistit-daemon` isn't running or doesn't answer in time
+#[cfg(feature = "daemon")]
+pub async fn head_daemon(runtime_path: &Path, hash: &str) -> Result<Option<Head>> {
+    let mut bridge = gistit_ipc::client(runtime_path)?;
+    if !bridge.alive() {
+        return Err(Error::Daemon("gistit node is not running"));
+    }
+    bridge.connect_blocking()?;
+    bridge
+        .send(Instruction::request_head(hash.to_owned()))
+        .await?;
+
+    let instruction = timeout(P2P_FETCH_TIMEOUT, bridge.recv())
+        .await
+        .map_err(|_| Error::Daemon("timed out waiting for gistit-daemon"))??;
+
+    match instruction.expect_response()? {
+        ipc::instruction::Kind::HeadResponse(ipc::instruction::HeadResponse { head }) => Ok(head),
+        _ => Ok(None),
+    }
+}
+
+/// Asks `gistit-daemon` to find `hash` in the p2p network, `None` if no provider has it
+#[cfg(feature = "daemon")]
+async fn fetch_daemon(
+    runtime_path: &Path,
+    hash: &str,
+    burn_after_read: bool,
+) -> Result<Option<Gistit>> {
+    let mut bridge = gistit_ipc::client(runtime_path)?;
+    if !bridge.alive() {
+        return Err(Error::Daemon("gistit node is not running"));
+    }
+    bridge.connect_blocking()?;
+    bridge
+        .send(Instruction::request_fetch(hash.to_owned(), burn_after_read))
+        .await?;
+
+    // Progress is streamed before the final response
+    let fetch = async {
+        loop {
+            let instruction = timeout(P2P_FETCH_TIMEOUT, bridge.recv())
+                .await
+                .map_err(|_| Error::Daemon("timed out waiting for gistit-daemon"))??;
+
+            match instruction.expect_response()? {
+                ipc::instruction::Kind::FetchProgressResponse(_) => (),
+                ipc::instruction::Kind::FetchResponse(ipc::instruction::FetchResponse {
+                    gistit: Some(gistit),
+                    ..
+                }) => return Ok(Some(gistit)),
+                ipc::instruction::Kind::FetchResponse(ipc::instruction::FetchResponse {
+                    burn_after_read: true,
+                    ..
+                }) => return Err(Error::BurnAfterRead),
+                _ => return Ok(None),
+            }
+        }
+    };
+
+    if let Some(limit) = crate::timeout() {
+        if let Ok(fetched) = timeout(limit, fetch).await {
+            return fetched;
+        }
+        // The daemon keeps the chunks downloaded so far, fetching again resumes
+        bridge
+            .send(Instruction::request_cancel(hash.to_owned()))
+            .await?;
+        return Err(Error::Daemon("timed out fetching from the p2p network"));
+    }
+    fetch.await
+}
@@ -0,0 +1,236 @@
+//
+//   ________.__          __  .__  __
+//  /  _____/|__| _______/  |_|__|/  |_
+// /   \  ___|  |/  ___/\   __\  \   __\
+// \    \_\  \  |\___ \  |  | |  ||  |
+//  \______  /__/____  > |__| |__||__|
+//         \/        \/
+//
+#![warn(clippy::all, clippy::pedantic, clippy::nursery, clippy::cargo)]
+#![allow(clippy::multiple_crate_versions)]
+#![cfg_attr(
+    test,
+    allow(
+        unused,
+        clippy::all,
+        clippy::pedantic,
+        clippy::nursery,
+        clippy::dbg_macro,
+        clippy::unwrap_used,
+        clippy::missing_docs_in_private_items,
+    )
+)]
+
+//! Gistit send and fetch without the command line, for editor plugins, bots and other Rust tools.
+//! Nothing here prints or prompts, progress and failures are returned to the caller.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use url::Url;
+
+use gistit_project::var;
+use gistit_proto::payload::hash;
+use gistit_proto::Gistit;
+
+pub mod comment;
+pub mod fetch;
+pub mod secret;
+pub mod send;
+pub mod verify;
+#[cfg(target_arch = "wasm32")]
+pub mod wasm;
+
+pub use fetch::{fetch, head, FetchRequest, FetchedGistit, Source};
+pub use send::{send, Destination, SendReceipt, SendRequest};
+pub use verify::{verify, Integrity};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// The web page of gistits sent to the server, followed by their hash
+pub const SHARE_URL_BASE: &str = "https://gistit.vercel.app/h/";
+
+/// Milliseconds network operations are given, 0 to wait as long as they take
+static TIMEOUT_MILLIS: AtomicU64 = AtomicU64::new(0);
+
+/// Bounds every network operation of this process: server requests, `gistit-daemon` round trips
+/// and p2p fetches. They wait as long as they take when `None`, the default
+pub fn set_timeout(timeout: Option<Duration>) {
+    let millis = timeout.map_or(0, |timeout| {
+        u64::try_from(timeout.as_millis())
+            .unwrap_or(u64::MAX)
+            .max(1)
+    });
+    TIMEOUT_MILLIS.store(millis, Ordering::Relaxed);
+    #[cfg(feature = "daemon")]
+    gistit_ipc::set_timeout(timeout);
+}
+
+/// The timeout given with [`set_timeout`], if any
+#[must_use]
+pub fn timeout() -> Option<Duration> {
+    match TIMEOUT_MILLIS.load(Ordering::Relaxed) {
+        0 => None,
+        millis => Some(Duration::from_millis(millis)),
+    }
+}
+
+/// An HTTP client bound by [`timeout`]. The browser's fetch has no timeout, wasm requests aren't
+#[must_use]
+pub fn http_client() -> reqwest::Client {
+    let builder = reqwest::Client::builder();
+    #[cfg(not(target_arch = "wasm32"))]
+    let builder = match timeout() {
+        Some(timeout) => builder.timeout(timeout),
+        None => builder,
+    };
+    builder.build().unwrap_or_default()
+}
+
+/// The default gistit server
+///
+/// # Panics
+///
+/// If the bundled server url is invalid
+#[must_use]
+pub fn default_server_url() -> Url {
+    Url::parse(var::GISTIT_SERVER_URL_BASE).expect("bundled server url to be valid")
+}
+
+/// A single file gistit, hashed over it's data, author and description
+#[must_use]
+#[allow(clippy::cast_possible_truncation)]
+pub fn new_gistit(
+    name: &str,
+    lang: &str,
+    data: String,
+    author: String,
+    description: Option<String>,
+) -> Gistit {
+    let hash = hash(&author, description.as_deref(), &data);
+    let now = now_millis().to_string();
+    let inner = Gistit::new_inner(name.to_owned(), lang.to_owned(), data.len() as u32, data);
+
+    Gistit::new(hash, author, description, now, vec![inner])
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn now_millis() -> u128 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Check your system time")
+        .as_millis()
+}
+
+/// `SystemTime` isn't available in the browser
+#[cfg(target_arch = "wasm32")]
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn now_millis() -> u128 {
+    js_sys::Date::now() as u128
+}
+
+/// How long a `Retry-After` header asks to wait, either in seconds or until an HTTP date. Dates in
+/// the past don't ask to wait at all
+#[must_use]
+pub fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    let date = httpdate::parse_http_date(value).ok()?;
+    let now = std::time::UNIX_EPOCH + Duration::from_millis(now_millis().try_into().ok()?);
+    Some(date.duration_since(now).unwrap_or_default())
+}
+
+/// [`Error::RateLimited`] of a `429 Too Many Requests` response
+fn rate_limited(response: &reqwest::Response) -> Error {
+    Error::RateLimited(
+        response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_retry_after),
+    )
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("{0}")]
+    Request(#[from] reqwest::Error),
+
+    #[error("{0}")]
+    UrlParse(#[from] url::ParseError),
+
+    #[cfg(feature = "daemon")]
+    #[error("{0}")]
+    Ipc(#[from] gistit_ipc::Error),
+
+    #[error("{0}")]
+    Proto(#[from] gistit_proto::Error),
+
+    #[error("{0}")]
+    Server(&'static str),
+
+    #[error("{0}")]
+    Daemon(&'static str),
+
+    #[error("{0}")]
+    Secret(&'static str),
+
+    #[error("failed to decrypt gistit, is the secret right?")]
+    WrongSecret,
+
+    #[error("gistit not found")]
+    NotFound,
+
+    /// The server answered `429 Too Many Requests`, with how long it asked to wait if it did
+    #[error("rate limited by the server")]
+    RateLimited(Option<Duration>),
+
+    #[error("gistit is deleted once read, fetching it needs agreeing to that")]
+    BurnAfterRead,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn core_new_gistit_hash() {
+        let gistit = new_gistit(
+            "main.rs",
+            "rust",
+            "fn main() {}".to_owned(),
+            "me".to_owned(),
+            None,
+        );
+
+        assert_eq!(gistit.hash, hash("me", None, "fn main() {}"));
+        assert_eq!(gistit.inner[0].size, 12);
+        assert_eq!(default_server_url().as_str(), var::GISTIT_SERVER_URL_BASE);
+    }
+
+    #[test]
+    fn core_parse_retry_after() {
+        assert_eq!(parse_retry_after("120"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after(" 0 "), Some(Duration::ZERO));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon"), None);
+        assert_eq!(parse_retry_after("-1"), None);
+    }
+
+    #[test]
+    fn core_timeout() {
+        assert_eq!(timeout(), None);
+        set_timeout(Some(Duration::from_secs(5)));
+        assert_eq!(timeout(), Some(Duration::from_secs(5)));
+        set_timeout(None);
+        assert_eq!(timeout(), None);
+    }
+}
@@ -0,0 +1,474 @@
+//! The secret module
+//!
+//! Gistits can be encrypted with a secret before leaving this machine. The key is derived from the
+//! secret with argon2 and the data sealed with XChaCha20-Poly1305, it's nonce long enough to be
+//! picked at random. The inner `data` holds, base64 encoded, a header of [`FORMAT_VERSION`], the
+//! salt and the nonce followed by the ciphertext, the header authenticated along with it. The hash
+//! is computed over the encrypted data, so peers can still verify what they serve.
+//!
+//! Gistits encrypted before the format was versioned, with ChaCha20-Poly1305 and no header but
+//! the salt and nonce, are still decrypted.
+//!
+//! Neither the secret nor anything derived from it is ever sent, the server and peers only store
+//! the ciphertext and the `encrypted` flag of each file. The flag isn't authenticated, it only
+//! tells fetching to ask for the secret: a wrong secret and tampered data both fail the Poly1305
+//! tag, answered as [`Error::WrongSecret`].
+//!
+//! With [`seal_metadata`] the author, description, file names and languages are sealed too, into
+//! the `metadata` of the gistit, leaving [`METADATA_PLACEHOLDER`] in their place. The server and
+//! peers then only know it's an encrypted gistit and it's size.
+//!
+//! Files are encrypted in place, their plaintext buffer becomes the ciphertext, and the files of a
+//! gistit are sealed concurrently, the first one hashed while the others are still encrypted.
+
+use std::mem;
+
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, AeadInPlace, NewAead, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce, XChaCha20Poly1305, XNonce};
+use rand::rngs::OsRng;
+use rand::RngCore;
+
+use gistit_proto::payload::{hash, metadata};
+use gistit_proto::prost::Message;
+use gistit_proto::{Gistit, Inner, Metadata};
+
+use crate::{Error, Result};
+
+/// Stands in for the author and file names of gistits with sealed metadata
+pub const METADATA_PLACEHOLDER: &str = "encrypted";
+
+/// Stands in for the languages of files with sealed metadata
+const PLACEHOLDER_LANG: &str = "text";
+
+/// First byte of the encrypted data, bumped whenever the format changes
+pub const FORMAT_VERSION: u8 = 1;
+
+const SALT_LENGTH: usize = 16;
+
+const NONCE_LENGTH: usize = 24;
+
+/// Nonces of the unversioned format
+const LEGACY_NONCE_LENGTH: usize = 12;
+
+const TAG_LENGTH: usize = 16;
+
+const HEADER_LENGTH: usize = 1 + SALT_LENGTH + NONCE_LENGTH;
+
+fn derive_key(secret: &str, salt: &[u8]) -> Result<[u8; 32]> {
+    let mut key = [0_u8; 32];
+    Argon2::default()
+        .hash_password_into(secret.as_bytes(), salt, &mut key)
+        .map_err(|_| Error::Secret("failed to derive encryption key"))?;
+    Ok(key)
+}
+
+/// Encrypts `plaintext`, returning base64 of the version, salt, nonce and ciphertext
+///
+/// # Errors
+///
+/// Fails if the key can't be derived from `secret`
+pub fn encrypt(plaintext: &str, secret: &str) -> Result<String> {
+    encrypt_owned(plaintext.to_owned(), secret)
+}
+
+/// [`encrypt`], reusing the allocation of `plaintext` for the ciphertext
+fn encrypt_owned(plaintext: String, secret: &str) -> Result<String> {
+    encrypt_bytes(plaintext.into_bytes(), secret)
+}
+
+fn encrypt_bytes(plaintext: Vec<u8>, secret: &str) -> Result<String> {
+    let mut header = [0_u8; HEADER_LENGTH];
+    header[0] = FORMAT_VERSION;
+    OsRng.fill_bytes(&mut header[1..]);
+    let (salt, nonce) = header[1..].split_at(SALT_LENGTH);
+    let key = derive_key(secret, salt)?;
+
+    let mut buffer = plaintext;
+    buffer.reserve_exact(HEADER_LENGTH + TAG_LENGTH);
+    buffer.splice(0..0, header);
+
+    let tag = XChaCha20Poly1305::new(Key::from_slice(&key))
+        .encrypt_in_place_detached(
+            XNonce::from_slice(nonce),
+            &header,
+            &mut buffer[HEADER_LENGTH..],
+        )
+        .map_err(|_| Error::Secret("failed to encrypt gistit"))?;
+    buffer.extend_from_slice(&tag);
+
+    Ok(base64::encode(buffer))
+}
+
+/// Decrypts what [`encrypt`] returned, or an unversioned gistit
+///
+/// # Errors
+///
+/// Fails with [`Error::WrongSecret`] if `secret` doesn't decrypt it, or if `data` was tampered
+/// with
+pub fn decrypt(data: &str, secret: &str) -> Result<String> {
+    String::from_utf8(decrypt_bytes(data, secret)?)
+        .map_err(|_| Error::Secret("decrypted gistit is not UTF-8"))
+}
+
+fn decrypt_bytes(data: &str, secret: &str) -> Result<Vec<u8>> {
+    let bytes = base64::decode(data).map_err(|_| Error::Secret("corrupted encrypted gistit"))?;
+    if bytes.first() == Some(&FORMAT_VERSION) {
+        // One in 256 unversioned gistits start with `FORMAT_VERSION` by chance, as the first byte
+        // of their salt, so a failed versioned open may still be a legacy one
+        open_versioned(&bytes, secret).or_else(|_| open_legacy(&bytes, secret))
+    } else {
+        open_legacy(&bytes, secret)
+    }
+}
+
+fn open_versioned(bytes: &[u8], secret: &str) -> Result<Vec<u8>> {
+    if bytes.len() < HEADER_LENGTH + TAG_LENGTH {
+        return Err(Error::Secret("corrupted encrypted gistit"));
+    }
+    let (header, ciphertext) = bytes.split_at(HEADER_LENGTH);
+    let (salt, nonce) = header[1..].split_at(SALT_LENGTH);
+
+    let key = derive_key(secret, salt)?;
+    XChaCha20Poly1305::new(Key::from_slice(&key))
+        .decrypt(
+            XNonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: header,
+            },
+        )
+        .map_err(|_| Error::WrongSecret)
+}
+
+fn open_legacy(bytes: &[u8], secret: &str) -> Result<Vec<u8>> {
+    if bytes.len() < SALT_LENGTH + LEGACY_NONCE_LENGTH + TAG_LENGTH {
+        return Err(Error::Secret("corrupted encrypted gistit"));
+    }
+    let (salt, rest) = bytes.split_at(SALT_LENGTH);
+    let (nonce, ciphertext) = rest.split_at(LEGACY_NONCE_LENGTH);
+
+    let key = derive_key(secret, salt)?;
+    ChaCha20Poly1305::new(Key::from_slice(&key))
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| Error::WrongSecret)
+}
+
+/// Encrypts every inner file of `gistit` and rehashes it
+///
+/// # Errors
+///
+/// Inherits the errors of [`encrypt`]
+pub fn seal(gistit: &mut Gistit, secret: &str) -> Result<()> {
+    let Gistit {
+        hash: gistit_hash,
+        author,
+        description,
+        inner,
+        ..
+    } = gistit;
+    let (first, rest) = match inner.split_first_mut() {
+        Some(split) => split,
+        None => return Ok(()),
+    };
+    let mut seal_first = || -> Result<String> {
+        seal_inner(first, secret)?;
+        Ok(hash(author, description.as_deref(), &first.data))
+    };
+
+    #[cfg(not(target_arch = "wasm32"))]
+    let (first_hash, rest_sealed) = std::thread::scope(|scope| {
+        let rest_sealed = scope.spawn(|| seal_all(rest, secret));
+        let first_hash = seal_first();
+        (first_hash, join(rest_sealed))
+    });
+
+    // No threads in the browser
+    #[cfg(target_arch = "wasm32")]
+    let (first_hash, rest_sealed) = (
+        seal_first(),
+        rest.iter_mut()
+            .try_for_each(|inner| seal_inner(inner, secret)),
+    );
+
+    rest_sealed?;
+    *gistit_hash = first_hash?;
+    Ok(())
+}
+
+fn seal_inner(inner: &mut Inner, secret: &str) -> Result<()> {
+    inner.data = encrypt_owned(mem::take(&mut inner.data), secret)?;
+    inner.encrypted = true;
+    Ok(())
+}
+
+/// Seals `inners` split across the available cores
+#[cfg(not(target_arch = "wasm32"))]
+fn seal_all(inners: &mut [Inner], secret: &str) -> Result<()> {
+    if inners.is_empty() {
+        return Ok(());
+    }
+
+    let threads = std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get);
+    let per_thread = (inners.len() + threads - 1) / threads;
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = inners
+            .chunks_mut(per_thread)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter_mut()
+                        .try_for_each(|inner| seal_inner(inner, secret))
+                })
+            })
+            .collect();
+        handles.into_iter().try_for_each(join)
+    })
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn join(handle: std::thread::ScopedJoinHandle<'_, Result<()>>) -> Result<()> {
+    handle
+        .join()
+        .unwrap_or(Err(Error::Secret("encryption thread panicked")))
+}
+
+/// Seals the author, description, file names and languages of `gistit` into it's `metadata`
+///
+/// Placeholders are left in their place and it's rehashed. Meant for gistits [`seal`]ed already,
+/// the data would tell what the placeholders hide otherwise
+///
+/// # Errors
+///
+/// Inherits the errors of [`encrypt`]
+pub fn seal_metadata(gistit: &mut Gistit, secret: &str) -> Result<()> {
+    let metadata = Metadata {
+        author: gistit.author.clone(),
+        description: gistit.description.clone(),
+        files: gistit
+            .inner
+            .iter()
+            .map(|inner| metadata::File {
+                name: inner.name.clone(),
+                lang: inner.lang.clone(),
+            })
+            .collect(),
+    };
+    gistit.metadata = Some(encrypt_bytes(metadata.encode_to_vec(), secret)?);
+
+    gistit.author = METADATA_PLACEHOLDER.to_owned();
+    gistit.description = None;
+    for inner in &mut gistit.inner {
+        inner.name = METADATA_PLACEHOLDER.to_owned();
+        inner.lang = PLACEHOLDER_LANG.to_owned();
+    }
+    if let Some(first) = gistit.inner.first() {
+        gistit.hash = hash(&gistit.author, None, &first.data);
+    }
+    Ok(())
+}
+
+/// Puts the sealed metadata of `gistit` back in place of the placeholders. The hash is left as
+/// is, it's the one it's fetched by
+fn open_metadata(gistit: &mut Gistit, secret: &str) -> Result<()> {
+    let sealed = match gistit.metadata {
+        Some(ref sealed) => sealed,
+        None => return Ok(()),
+    };
+    let metadata = Metadata::decode(&*decrypt_bytes(sealed, secret)?)
+        .ok()
+        .filter(|metadata| metadata.files.len() == gistit.inner.len())
+        .ok_or(Error::Secret("corrupted encrypted metadata"))?;
+
+    gistit.author = metadata.author;
+    gistit.description = metadata.description;
+    for (inner, file) in gistit.inner.iter_mut().zip(metadata.files) {
+        inner.name = file.name;
+        inner.lang = file.lang;
+    }
+    gistit.metadata = None;
+    Ok(())
+}
+
+/// Decrypts the encrypted inner files of `gistit` and it's sealed metadata, in place
+///
+/// # Errors
+///
+/// Inherits the errors of [`decrypt`]
+pub fn open(gistit: &mut Gistit, secret: &str) -> Result<()> {
+    open_metadata(gistit, secret)?;
+    for inner in gistit.inner.iter_mut().filter(|inner| inner.encrypted) {
+        inner.data = decrypt(&inner.data, secret)?;
+        inner.encrypted = false;
+    }
+    Ok(())
+}
+
+#[must_use]
+pub fn is_encrypted(gistit: &Gistit) -> bool {
+    gistit.metadata.is_some() || gistit.inner.iter().any(|inner| inner.encrypted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secret_roundtrip() {
+        let sealed = encrypt("fn main() {}", "hunter2").unwrap();
+
+        assert_ne!(sealed, "fn main() {}");
+        assert_eq!(decrypt(&sealed, "hunter2").unwrap(), "fn main() {}");
+        assert!(matches!(
+            decrypt(&sealed, "hunter3"),
+            Err(Error::WrongSecret)
+        ));
+        assert!(decrypt("garbage", "hunter2").is_err());
+    }
+
+    /// Encrypted the way gistits were before the format was versioned
+    fn encrypt_legacy(plaintext: &str, secret: &str, salt: [u8; SALT_LENGTH]) -> String {
+        let nonce = [7_u8; LEGACY_NONCE_LENGTH];
+        let key = derive_key(secret, &salt).unwrap();
+        let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&key))
+            .encrypt(Nonce::from_slice(&nonce), plaintext.as_bytes())
+            .unwrap();
+        base64::encode([&salt[..], &nonce, &ciphertext].concat())
+    }
+
+    #[test]
+    fn secret_versioned() {
+        let bytes = base64::decode(encrypt("fn main() {}", "hunter2").unwrap()).unwrap();
+
+        assert_eq!(bytes[0], FORMAT_VERSION);
+        assert_eq!(
+            bytes.len(),
+            HEADER_LENGTH + "fn main() {}".len() + TAG_LENGTH
+        );
+    }
+
+    #[test]
+    fn secret_legacy() {
+        let sealed = encrypt_legacy("fn main() {}", "hunter2", [3; SALT_LENGTH]);
+        assert_eq!(decrypt(&sealed, "hunter2").unwrap(), "fn main() {}");
+        assert!(matches!(
+            decrypt(&sealed, "hunter3"),
+            Err(Error::WrongSecret)
+        ));
+
+        // Salt starting like the version byte
+        let sealed = encrypt_legacy("fn main() {}", "hunter2", [FORMAT_VERSION; SALT_LENGTH]);
+        assert_eq!(decrypt(&sealed, "hunter2").unwrap(), "fn main() {}");
+    }
+
+    #[test]
+    fn secret_seal_metadata() {
+        let mut gistit = Gistit::new(
+            "hash".to_owned(),
+            "me".to_owned(),
+            Some("Reads the settings".to_owned()),
+            String::new(),
+            vec![Gistit::new_inner(
+                "settings.rs".to_owned(),
+                "rust".to_owned(),
+                12,
+                "fn main() {}".to_owned(),
+            )],
+        );
+        seal(&mut gistit, "hunter2").unwrap();
+        seal_metadata(&mut gistit, "hunter2").unwrap();
+
+        assert!(gistit.metadata.is_some());
+        assert_eq!(gistit.author, METADATA_PLACEHOLDER);
+        assert_eq!(gistit.description, None);
+        assert_eq!(gistit.inner[0].name, METADATA_PLACEHOLDER);
+        assert_eq!(
+            gistit.hash,
+            hash(METADATA_PLACEHOLDER, None, &gistit.inner[0].data)
+        );
+
+        let sealed = gistit.clone();
+        assert!(matches!(
+            open(&mut gistit.clone(), "hunter3"),
+            Err(Error::WrongSecret)
+        ));
+        open(&mut gistit, "hunter2").unwrap();
+        assert!(!is_encrypted(&gistit));
+        assert_eq!(gistit.author, "me");
+        assert_eq!(gistit.description.as_deref(), Some("Reads the settings"));
+        assert_eq!(gistit.inner[0].name, "settings.rs");
+        assert_eq!(gistit.inner[0].lang, "rust");
+        assert_eq!(gistit.inner[0].data, "fn main() {}");
+        assert_eq!(gistit.hash, sealed.hash);
+    }
+
+    #[test]
+    fn secret_tampered() {
+        let mut bytes = base64::decode(encrypt("fn main() {}", "hunter2").unwrap()).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+
+        assert!(matches!(
+            decrypt(&base64::encode(&bytes), "hunter2"),
+            Err(Error::WrongSecret)
+        ));
+
+        // The header is authenticated too
+        bytes[last] ^= 1;
+        bytes[HEADER_LENGTH - 1] ^= 1;
+        assert!(matches!(
+            decrypt(&base64::encode(&bytes), "hunter2"),
+            Err(Error::WrongSecret)
+        ));
+    }
+
+    #[test]
+    fn secret_seal_many_files() {
+        let inner: Vec<Inner> = (0..4)
+            .map(|i| {
+                let data = format!("fn main() {{ {} }}", i);
+                Gistit::new_inner(format!("{}.rs", i), "rust".to_owned(), 0, data)
+            })
+            .collect();
+        let mut gistit = Gistit::new(
+            "hash".to_owned(),
+            "me".to_owned(),
+            None,
+            String::new(),
+            inner,
+        );
+        seal(&mut gistit, "hunter2").unwrap();
+
+        assert!(gistit.inner.iter().all(|inner| inner.encrypted));
+        assert_eq!(gistit.hash, hash("me", None, &gistit.inner[0].data));
+
+        open(&mut gistit, "hunter2").unwrap();
+        for (i, inner) in gistit.inner.iter().enumerate() {
+            assert_eq!(inner.data, format!("fn main() {{ {} }}", i));
+        }
+    }
+
+    #[test]
+    fn secret_seal_rehashes() {
+        let mut gistit = Gistit::new(
+            "hash".to_owned(),
+            "me".to_owned(),
+            None,
+            String::new(),
+            vec![Gistit::new_inner(
+                "main.rs".to_owned(),
+                "rust".to_owned(),
+                12,
+                "fn main() {}".to_owned(),
+            )],
+        );
+        seal(&mut gistit, "hunter2").unwrap();
+
+        assert!(is_encrypted(&gistit));
+        assert_eq!(gistit.hash, hash("me", None, &gistit.inner[0].data));
+
+        open(&mut gistit, "hunter2").unwrap();
+        assert!(!is_encrypted(&gistit));
+        assert_eq!(gistit.inner[0].data, "fn main() {}");
+    }
+}
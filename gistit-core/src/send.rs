@@ -0,0 +1,189 @@
+//! The send module
+//!
+//! Sends a gistit to the server, or hosts it through a running `gistit-daemon`, optionally
+//! encrypted first.
+
+#[cfg(feature = "daemon")]
+use std::path::{Path, PathBuf};
+
+use reqwest::StatusCode;
+use url::Url;
+
+use gistit_proto::prost::Message;
+use gistit_proto::Gistit;
+#[cfg(feature = "daemon")]
+use gistit_proto::{ipc, Instruction};
+
+use crate::{
+    default_server_url, http_client, new_gistit, rate_limited, secret, Error, Result,
+    SHARE_URL_BASE,
+};
+
+/// Where to send a gistit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Destination {
+    /// The gistit server, at this base url
+    Server(Url),
+    /// The p2p network, through the `gistit-daemon` listening in this runtime directory
+    #[cfg(feature = "daemon")]
+    Daemon {
+        runtime_path: PathBuf,
+        /// Gossipsub topics to announce the gistit on
+        topics: Vec<String>,
+    },
+}
+
+impl Default for Destination {
+    fn default() -> Self {
+        Self::Server(default_server_url())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SendRequest {
+    pub name: String,
+    pub data: String,
+    pub author: String,
+    pub lang: String,
+    pub description: Option<String>,
+    /// Encrypts the gistit with this secret before it's sent
+    pub secret: Option<String>,
+    pub destination: Destination,
+}
+
+impl SendRequest {
+    /// A plain text gistit sent to the default server
+    #[must_use]
+    pub fn new(name: &str, data: &str, author: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            data: data.to_owned(),
+            author: author.to_owned(),
+            lang: "text".to_owned(),
+            description: None,
+            secret: None,
+            destination: Destination::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SendReceipt {
+    pub hash: String,
+    /// The web page of the gistit, only for the server
+    pub url: Option<String>,
+}
+
+/// Sends a gistit where the request says
+///
+/// # Errors
+///
+/// Fails if the gistit couldn't be encrypted, the server refused it or `gistit-daemon` isn't
+/// running or failed to provide it
+pub async fn send(request: SendRequest) -> Result<SendReceipt> {
+    let mut gistit = new_gistit(
+        &request.name,
+        &request.lang,
+        request.data,
+        request.author,
+        request.description,
+    );
+    if let Some(ref secret) = request.secret {
+        secret::seal(&mut gistit, secret)?;
+    }
+
+    match request.destination {
+        Destination::Server(url) => {
+            let hash = send_server(&url, &gistit).await?;
+            Ok(SendReceipt {
+                url: Some(format!("{}{}", SHARE_URL_BASE, hash)),
+                hash,
+            })
+        }
+        #[cfg(feature = "daemon")]
+        Destination::Daemon {
+            runtime_path,
+            topics,
+        } => {
+            let hash = provide(&runtime_path, gistit, topics).await?;
+            Ok(SendReceipt { hash, url: None })
+        }
+    }
+}
+
+/// Hosts `gistit` through the `gistit-daemon` listening in `runtime_path`, returning it's hash
+#[cfg(feature = "daemon")]
+async fn provide(runtime_path: &Path, gistit: Gistit, topics: Vec<String>) -> Result<String> {
+    let mut bridge = gistit_ipc::client(runtime_path)?;
+    if !bridge.alive() {
+        return Err(Error::Daemon("gistit node is not running"));
+    }
+    bridge.connect_blocking()?;
+    bridge
+        .send(Instruction::request_provide(gistit, topics))
+        .await?;
+
+    match bridge.recv().await?.expect_response()? {
+        ipc::instruction::Kind::ProvideResponse(ipc::instruction::ProvideResponse {
+            hash: Some(hash),
+        }) => Ok(hash),
+        _ => Err(Error::Daemon(
+            "failed to provide gistit, check gistit-daemon logs",
+        )),
+    }
+}
+
+/// Sends `gistit` to the server at `url`, returning the hash it was stored under
+///
+/// # Errors
+///
+/// Fails if the server can't be reached or refuses the gistit
+#[tracing::instrument(level = "debug", skip_all, fields(url = %url, hash = %gistit.hash))]
+pub async fn send_server(url: &Url, gistit: &Gistit) -> Result<String> {
+    let response = http_client()
+        .post(url.join("load")?)
+        .header("content-type", "application/x-protobuf")
+        .body(gistit.encode_to_vec())
+        .send()
+        .await?;
+    tracing::debug!(status = %response.status(), "server answered");
+
+    match response.status() {
+        StatusCode::OK => Ok(Gistit::from_bytes(response.bytes().await?)?.hash),
+        StatusCode::UNPROCESSABLE_ENTITY | StatusCode::BAD_REQUEST => {
+            Err(Error::Server("invalid gistit payload"))
+        }
+        StatusCode::TOO_MANY_REQUESTS => Err(rate_limited(&response)),
+        _ => Err(Error::Server("invalid server response")),
+    }
+}
+
+/// Whether the server at `url` already has `hash`, so it can be linked to instead of sent again.
+/// Private gistits and ones deleted once read never can, and servers without the `exists`
+/// endpoint don't have anything
+///
+/// # Errors
+///
+/// Fails if the server can't be reached or answers unexpectedly
+#[tracing::instrument(level = "debug", skip(url), fields(url = %url))]
+pub async fn exists_server(url: &Url, hash: &str) -> Result<bool> {
+    let gistit = Gistit {
+        hash: hash.to_owned(),
+        ..Gistit::default()
+    };
+
+    let response = http_client()
+        .post(url.join("exists")?)
+        .header("content-type", "application/x-protobuf")
+        .body(gistit.encode_to_vec())
+        .send()
+        .await?;
+    tracing::debug!(status = %response.status(), "server answered");
+
+    match response.status() {
+        StatusCode::OK | StatusCode::NO_CONTENT => Ok(true),
+        StatusCode::NOT_FOUND => Ok(false),
+        StatusCode::TOO_MANY_REQUESTS => Err(rate_limited(&response)),
+        _ => Err(Error::Server("unexpected response")),
+    }
+}
@@ -0,0 +1,113 @@
+//! The verify module
+//!
+//! Checks a fetched gistit is the one that was asked for, after it went through peers or relays
+//! nobody vouches for. Hashes are sha256 over the first file's data, the author and the
+//! description. Encrypted gistits are hashed over their encrypted data, so they're verified
+//! without the secret.
+
+use gistit_proto::payload::hash;
+use gistit_proto::Gistit;
+
+/// The outcome of [`verify`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Integrity {
+    /// The hash recomputed over the content
+    pub computed: String,
+    /// Whether the content matches the hash the gistit carries
+    pub hash: bool,
+    /// Whether the gistit is the one that was asked for
+    pub requested: bool,
+    /// Whether the declared file sizes match the content. Encrypted files are skipped, their size
+    /// is the one of the plaintext
+    pub sizes: bool,
+}
+
+impl Integrity {
+    /// Whether every check passed
+    #[must_use]
+    pub const fn ok(&self) -> bool {
+        self.hash && self.requested && self.sizes
+    }
+}
+
+/// Recomputes the hash of `gistit` and checks it against the one it carries and the `requested`
+/// one
+#[must_use]
+pub fn verify(gistit: &Gistit, requested: &str) -> Integrity {
+    let computed = gistit.inner.first().map_or_else(String::new, |inner| {
+        hash(&gistit.author, gistit.description.as_deref(), &inner.data)
+    });
+    let sizes = gistit
+        .inner
+        .iter()
+        .filter(|inner| !inner.encrypted)
+        .all(|inner| inner.size as usize == inner.data.len());
+
+    Integrity {
+        hash: !computed.is_empty() && computed == gistit.hash,
+        requested: gistit.hash == requested,
+        sizes,
+        computed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{new_gistit, secret};
+
+    #[test]
+    fn verify_untouched_gistit() {
+        let gistit = new_gistit(
+            "main.rs",
+            "rust",
+            "fn main() {}".to_owned(),
+            "me".to_owned(),
+            None,
+        );
+        let integrity = verify(&gistit, &gistit.hash);
+
+        assert!(integrity.ok());
+        assert_eq!(integrity.computed, gistit.hash);
+    }
+
+    #[test]
+    fn verify_tampered_gistit() {
+        let gistit = new_gistit(
+            "main.rs",
+            "rust",
+            "fn main() {}".to_owned(),
+            "me".to_owned(),
+            None,
+        );
+
+        let mut tampered = gistit.clone();
+        tampered.inner[0].data = "fn main() { evil() }".to_owned();
+        let integrity = verify(&tampered, &gistit.hash);
+        assert!(!integrity.hash);
+        assert!(!integrity.sizes);
+        assert!(!integrity.ok());
+
+        let mut rehashed = tampered.clone();
+        rehashed.inner[0].size = 20;
+        rehashed.hash = verify(&tampered, "").computed;
+        let integrity = verify(&rehashed, &gistit.hash);
+        assert!(integrity.hash);
+        assert!(!integrity.requested);
+        assert!(!integrity.ok());
+    }
+
+    #[test]
+    fn verify_encrypted_gistit() {
+        let mut gistit = new_gistit(
+            "main.rs",
+            "rust",
+            "fn main() {}".to_owned(),
+            "me".to_owned(),
+            None,
+        );
+        secret::seal(&mut gistit, "hunter2").unwrap();
+
+        assert!(verify(&gistit, &gistit.hash).ok());
+    }
+}
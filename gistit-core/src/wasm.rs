@@ -0,0 +1,66 @@
+//! The wasm module
+//!
+//! Bindings for the web frontend, so it hashes, encrypts and encodes gistits exactly like the CLI
+//! does. Sending and fetching go through [`crate::send`] and [`crate::fetch`] with reqwest's wasm
+//! backend, only the server is reachable from the browser.
+
+use wasm_bindgen::prelude::*;
+
+use gistit_proto::prost::Message;
+use gistit_proto::Gistit;
+
+use crate::{new_gistit, secret};
+
+/// The gistit hash of `data`, by `author` and with `description`
+#[wasm_bindgen]
+#[must_use]
+pub fn hash(author: &str, description: Option<String>, data: &str) -> String {
+    gistit_proto::payload::hash(author, description.as_deref(), data)
+}
+
+/// Protobuf encoded single file gistit, encrypted with `secret` if given
+///
+/// # Errors
+///
+/// Fails if it couldn't be encrypted
+#[wasm_bindgen]
+pub fn encode(
+    name: &str,
+    lang: &str,
+    data: String,
+    author: String,
+    description: Option<String>,
+    secret: Option<String>,
+) -> Result<Vec<u8>, JsValue> {
+    let mut gistit = new_gistit(name, lang, data, author, description);
+    if let Some(secret) = secret {
+        secret::seal(&mut gistit, &secret).map_err(|err| JsValue::from_str(&err.to_string()))?;
+    }
+    Ok(gistit.encode_to_vec())
+}
+
+/// The data of a protobuf encoded gistit, decrypted with `secret` if it was encrypted
+///
+/// # Errors
+///
+/// Fails if it can't be decoded, or is encrypted and the secret is missing or wrong
+#[wasm_bindgen]
+pub fn decode(bytes: &[u8], secret: Option<String>) -> Result<String, JsValue> {
+    let to_js = |err: crate::Error| JsValue::from_str(&err.to_string());
+    let mut gistit = Gistit::from_bytes(bytes).map_err(|err| to_js(err.into()))?;
+
+    if secret::is_encrypted(&gistit) {
+        let secret = secret.ok_or_else(|| {
+            to_js(crate::Error::Secret(
+                "gistit is encrypted, a secret is needed",
+            ))
+        })?;
+        secret::open(&mut gistit, &secret).map_err(to_js)?;
+    }
+    Ok(gistit
+        .inner
+        .into_iter()
+        .next()
+        .map(|inner| inner.data)
+        .unwrap_or_default())
+}
@@ -0,0 +1,218 @@
+//! The acl module
+//!
+//! Connection gating rules, written as `allow|deny <peer id|ip[/prefix]>`. Deny rules always win.
+//! Once any allow rule exists only matching peers are let in, so an allowlist also cuts the node
+//! off from bootstrap and DHT peers not listed in it.
+
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use libp2p::core::{Multiaddr, PeerId};
+use libp2p::multiaddr::Protocol;
+
+use crate::{Error, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Allow,
+    Deny,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Peer(PeerId),
+    Ip(IpRange),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rule {
+    pub action: Action,
+    pub target: Target,
+}
+
+/// An ip address with a prefix length, in CIDR notation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpRange {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpRange {
+    #[must_use]
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(range), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u32::from(range) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(range), IpAddr::V6(ip)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u128::from(range) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for IpRange {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (addr, prefix) = s.split_once('/').map_or((s, None), |(a, p)| (a, Some(p)));
+        let addr: IpAddr = addr
+            .parse()
+            .map_err(|_| Error::Parse("invalid acl ip address"))?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse()
+                .ok()
+                .filter(|prefix| *prefix <= max)
+                .ok_or(Error::Parse("invalid acl ip prefix"))?,
+            None => max,
+        };
+
+        Ok(Self { addr, prefix })
+    }
+}
+
+impl fmt::Display for IpRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+impl FromStr for Rule {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (action, target) = s
+            .trim()
+            .split_once(char::is_whitespace)
+            .ok_or(Error::Parse(
+                "acl rule must be 'allow|deny <peer id|ip[/prefix]>'",
+            ))?;
+
+        let action = match action {
+            "allow" => Action::Allow,
+            "deny" => Action::Deny,
+            _ => return Err(Error::Parse("acl rule must start with 'allow' or 'deny'")),
+        };
+
+        let target = target.trim();
+        let target = match PeerId::from_str(target) {
+            Ok(peer) => Target::Peer(peer),
+            Err(_) => Target::Ip(target.parse()?),
+        };
+
+        Ok(Self { action, target })
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let action = match self.action {
+            Action::Allow => "allow",
+            Action::Deny => "deny",
+        };
+
+        match self.target {
+            Target::Peer(peer) => write!(f, "{} {}", action, peer),
+            Target::Ip(range) => write!(f, "{} {}", action, range),
+        }
+    }
+}
+
+impl Rule {
+    fn matches(&self, peer: &PeerId, ip: Option<IpAddr>) -> bool {
+        match self.target {
+            Target::Peer(target) => target == *peer,
+            Target::Ip(range) => ip.map_or(false, |ip| range.contains(ip)),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Acl {
+    rules: Vec<Rule>,
+}
+
+impl Acl {
+    /// # Errors
+    ///
+    /// Fails if any rule can't be parsed
+    pub fn new(rules: &[String]) -> Result<Self> {
+        Ok(Self {
+            rules: rules
+                .iter()
+                .map(|rule| rule.parse())
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    /// Returns `false` if the rule was already present
+    pub fn add(&mut self, rule: Rule) -> bool {
+        if self.rules.contains(&rule) {
+            return false;
+        }
+        self.rules.push(rule);
+        true
+    }
+
+    /// Returns `false` if the rule wasn't present
+    pub fn remove(&mut self, rule: &Rule) -> bool {
+        let len = self.rules.len();
+        self.rules.retain(|r| r != rule);
+        self.rules.len() != len
+    }
+
+    pub fn rules(&self) -> impl Iterator<Item = &Rule> {
+        self.rules.iter()
+    }
+
+    /// Peers explicitly denied, banned from the swarm altogether
+    pub fn denied_peers(&self) -> impl Iterator<Item = PeerId> + '_ {
+        self.rules.iter().filter_map(|rule| match rule {
+            Rule {
+                action: Action::Deny,
+                target: Target::Peer(peer),
+            } => Some(*peer),
+            _ => None,
+        })
+    }
+
+    /// Whether `peer` is denied by id. Ip rules and allowlists are enforced once it connects
+    #[must_use]
+    pub fn is_denied(&self, peer: &PeerId) -> bool {
+        self.denied_peers().any(|denied| denied == *peer)
+    }
+
+    /// Whether `peer`, connected through `address`, may stay connected and fetch from us
+    #[must_use]
+    pub fn is_allowed(&self, peer: &PeerId, address: &Multiaddr) -> bool {
+        let ip = ip_of(address);
+        let (allow, deny): (Vec<&Rule>, Vec<&Rule>) = self
+            .rules
+            .iter()
+            .partition(|rule| rule.action == Action::Allow);
+
+        if deny.iter().any(|rule| rule.matches(peer, ip)) {
+            return false;
+        }
+
+        allow.is_empty() || allow.iter().any(|rule| rule.matches(peer, ip))
+    }
+}
+
+fn ip_of(address: &Multiaddr) -> Option<IpAddr> {
+    address.iter().find_map(|protocol| match protocol {
+        Protocol::Ip4(ip) => Some(IpAddr::V4(ip)),
+        Protocol::Ip6(ip) => Some(IpAddr::V6(ip)),
+        _ => None,
+    })
+}
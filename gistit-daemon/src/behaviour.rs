@@ -1,36 +1,49 @@
 use std::io;
 use std::iter::once;
 use std::str::{self, FromStr};
+use std::sync::Arc;
 use std::time::Duration;
 
 use gistit_project::var;
 use gistit_proto::bytes::BytesMut;
 
-use libp2p::core::upgrade::{read_length_prefixed, write_length_prefixed};
-use libp2p::core::ProtocolName;
-use libp2p::futures::{AsyncRead, AsyncWrite, AsyncWriteExt};
-use libp2p::{autonat, Multiaddr, NetworkBehaviour};
+use libp2p::futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use libp2p::swarm::NetworkBehaviour;
+use libp2p::{autonat, kad, Multiaddr, StreamProtocol};
 
+use libp2p::allow_block_list::{self, BlockedPeers};
 use libp2p::autonat::{Behaviour as Autonat, Event as AutonatEvent};
 use libp2p::core::PeerId;
-use libp2p::identify::{Identify, IdentifyConfig, IdentifyEvent};
-use libp2p::kad::record::store::MemoryStore;
-use libp2p::kad::{Kademlia, KademliaConfig, KademliaEvent};
-use libp2p::ping::{Behaviour as PingBehaviour, Config as PingConfig, Event as PingEvent, Ping};
-use libp2p::relay::v2::client::{self, Client, Event as ClientEvent};
-use libp2p::relay::v2::relay::{self, Event as RelayEvent, Relay};
+use libp2p::dcutr::{Behaviour as Dcutr, Event as DcutrEvent};
+use libp2p::gossipsub::{
+    Behaviour as Gossipsub, Config as GossipsubConfig, Event as GossipsubEvent, MessageAuthenticity,
+};
+use libp2p::identify::{Behaviour as Identify, Config as IdentifyConfig, Event as IdentifyEvent};
+use libp2p::kad::store::MemoryStore;
+use libp2p::kad::{
+    Behaviour as Kademlia, Config as KademliaConfig, Event as KademliaEvent,
+    StoreInserts as KademliaStoreInserts,
+};
+use libp2p::ping::{Behaviour as Ping, Config as PingConfig, Event as PingEvent};
+use libp2p::relay::client::{self, Event as ClientEvent};
+use libp2p::relay::{Behaviour as Relay, Event as RelayEvent};
 use libp2p::request_response::{
-    ProtocolSupport, RequestResponse, RequestResponseCodec, RequestResponseConfig,
-    RequestResponseEvent,
+    Behaviour as RequestResponse, Codec as RequestResponseCodec, Config as RequestResponseConfig,
+    Event as RequestResponseEvent, ProtocolSupport,
 };
+use libp2p::swarm::behaviour::toggle::Toggle;
 
 use async_trait::async_trait;
 
 use gistit_proto::prost::Message;
-use gistit_proto::Gistit;
+use gistit_proto::{Gistit, Head};
 
+use crate::bootstrap;
+use crate::chunk::{self, Digest, CHUNK_SIZE};
 use crate::config::Config;
-use crate::Result;
+use crate::limits::{Bandwidth, Limiter};
+use crate::name;
+use crate::Error;
 
 pub const BOOTNODES: [&str; 4] = [
     "QmNnooDu7bfjPFoTZYxMNLWUQJyrVwtbZg5gBMjTezGAJN",
@@ -41,36 +54,76 @@ pub const BOOTNODES: [&str; 4] = [
 
 pub const BOOTADDR: &str = "/dnsaddr/bootstrap.libp2p.io";
 
+/// How long other peers keep our provider records
+pub const PROVIDER_RECORD_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Provider records are republished at this interval, well before they expire
+pub const PROVIDER_PUBLICATION_INTERVAL: Duration = Duration::from_secs(12 * 60 * 60);
+
+/// Gistits are transferred in chunks of this size so bandwidth caps apply smoothly
+const TRANSFER_CHUNK_SIZE: usize = 8 * 1024;
+
 #[derive(NetworkBehaviour)]
-#[behaviour(out_event = "Event", event_process = false)]
+#[behaviour(to_swarm = "Event")]
 pub struct Behaviour {
     pub request_response: RequestResponse<ExchangeCodec>,
+    pub chunk: RequestResponse<ChunkCodec>,
+    pub push: RequestResponse<PushCodec>,
     pub kademlia: Kademlia<MemoryStore>,
     pub identify: Identify,
-    pub relay: Relay,
+    pub relay: Toggle<Relay>,
     pub autonat: Autonat,
     pub ping: Ping,
-    pub client: Client,
+    pub client: client::Behaviour,
+    pub dcutr: Dcutr,
+    pub gossipsub: Gossipsub,
+    /// Peers denied by the acl, their connections are refused
+    pub blocked: allow_block_list::Behaviour<BlockedPeers>,
 }
 
 impl Behaviour {
     pub fn new_behaviour_and_transport(
         config: &Config,
-    ) -> Result<(Self, client::transport::ClientTransport)> {
-        let request_response = RequestResponse::new(
-            ExchangeCodec,
-            once((ExchangeProtocol, ProtocolSupport::Full)),
+        bandwidth: Arc<Bandwidth>,
+    ) -> crate::Result<(Self, client::Transport)> {
+        let request_response = RequestResponse::with_codec(
+            ExchangeCodec(bandwidth.clone()),
+            once((EXCHANGE_PROTOCOL, ProtocolSupport::Full)),
+            RequestResponseConfig::default(),
+        );
+
+        let chunk = RequestResponse::with_codec(
+            ChunkCodec(bandwidth.clone()),
+            once((CHUNK_PROTOCOL, ProtocolSupport::Full)),
+            RequestResponseConfig::default(),
+        );
+
+        let push = RequestResponse::with_codec(
+            PushCodec(bandwidth),
+            once((PUSH_PROTOCOL, ProtocolSupport::Full)),
             RequestResponseConfig::default(),
         );
 
         let kademlia = {
-            let mut cfg = KademliaConfig::default();
+            let mut cfg = KademliaConfig::new(kad::PROTOCOL_NAME);
             cfg.set_query_timeout(Duration::from_secs(5 * 60));
+            cfg.set_provider_record_ttl(Some(PROVIDER_RECORD_TTL));
+            cfg.set_provider_publication_interval(Some(PROVIDER_PUBLICATION_INTERVAL));
+            // Name records are validated before being stored, and republished by the node
+            cfg.set_record_ttl(Some(name::NAME_RECORD_TTL));
+            cfg.set_publication_interval(None);
+            cfg.set_record_filtering(KademliaStoreInserts::FilterBoth);
             let store = MemoryStore::new(config.peer_id);
             let mut behaviour = Kademlia::with_config(config.peer_id, store, cfg);
 
             let bootaddr = Multiaddr::from_str(BOOTADDR)?;
-            if config.bootstrap {
+            if !config.settings.bootstrap_peers.is_empty() {
+                for address in &config.settings.bootstrap_peers {
+                    behaviour.add_address(&bootstrap::peer_id(address)?, address.clone());
+                }
+
+                behaviour.bootstrap().expect("to bootstrap");
+            } else if config.settings.bootstrap {
                 for peer in BOOTNODES {
                     behaviour.add_address(
                         &PeerId::from_str(peer).expect("peer id to be valid"),
@@ -88,20 +141,24 @@ impl Behaviour {
             config.keypair.public(),
         ));
 
-        let relay = relay::Relay::new(
-            PeerId::from(config.keypair.public()),
-            relay::Config::default(),
-        );
+        // Only public nodes opting in with `--relay-server` relay traffic for others
+        let relay = Toggle::from(config.settings.relay.map(|quota| {
+            log::info!("Relay server enabled: {:?}", quota);
+            Relay::new(PeerId::from(config.keypair.public()), quota.into())
+        }));
 
-        let (client_transport, client) =
-            client::Client::new_transport_and_behaviour(config.peer_id);
+        let (client_transport, client) = client::new(config.peer_id);
 
         let autonat = {
             let mut behaviour = autonat::Behaviour::new(
                 PeerId::from(config.keypair.public()),
                 autonat::Config::default(),
             );
-            if config.bootstrap {
+            if !config.settings.bootstrap_peers.is_empty() {
+                for address in &config.settings.bootstrap_peers {
+                    behaviour.add_server(bootstrap::peer_id(address)?, Some(address.clone()));
+                }
+            } else if config.settings.bootstrap {
                 for peer in BOOTNODES {
                     let bootaddr = Multiaddr::from_str(BOOTADDR)?;
                     behaviour.add_server(
@@ -114,17 +171,34 @@ impl Behaviour {
             behaviour
         };
 
-        let ping = PingBehaviour::new(PingConfig::new().with_keep_alive(true));
+        let ping = Ping::new(PingConfig::new());
+
+        // Upgrades relayed connections to direct ones by hole punching
+        let dcutr = Dcutr::new(config.peer_id);
+
+        // Signed so subscribers know which peer announced a gistit
+        let gossipsub = Gossipsub::new(
+            MessageAuthenticity::Signed(config.keypair.clone()),
+            GossipsubConfig::default(),
+        )
+        .map_err(Error::Gossipsub)?;
+
+        let blocked = allow_block_list::Behaviour::default();
 
         Ok((
             Self {
                 request_response,
+                chunk,
+                push,
                 kademlia,
                 identify,
                 relay,
                 autonat,
                 ping,
                 client,
+                dcutr,
+                gossipsub,
+                blocked,
             },
             client_transport,
         ))
@@ -134,12 +208,16 @@ impl Behaviour {
 #[derive(Debug)]
 pub enum Event {
     RequestResponse(RequestResponseEvent<Request, Response>),
+    Chunk(RequestResponseEvent<ChunkRequest, ChunkResponse>),
+    Push(RequestResponseEvent<PushRequest, PushResponse>),
     Kademlia(KademliaEvent),
     Identify(IdentifyEvent),
     Relay(RelayEvent),
     Autonat(AutonatEvent),
     Ping(PingEvent),
     Client(ClientEvent),
+    Dcutr(DcutrEvent),
+    Gossipsub(GossipsubEvent),
 }
 
 impl From<RequestResponseEvent<Request, Response>> for Event {
@@ -148,6 +226,18 @@ impl From<RequestResponseEvent<Request, Response>> for Event {
     }
 }
 
+impl From<RequestResponseEvent<ChunkRequest, ChunkResponse>> for Event {
+    fn from(event: RequestResponseEvent<ChunkRequest, ChunkResponse>) -> Self {
+        Self::Chunk(event)
+    }
+}
+
+impl From<RequestResponseEvent<PushRequest, PushResponse>> for Event {
+    fn from(event: RequestResponseEvent<PushRequest, PushResponse>) -> Self {
+        Self::Push(event)
+    }
+}
+
 impl From<KademliaEvent> for Event {
     fn from(event: KademliaEvent) -> Self {
         Self::Kademlia(event)
@@ -184,17 +274,29 @@ impl From<ClientEvent> for Event {
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct ExchangeProtocol;
+impl From<DcutrEvent> for Event {
+    fn from(event: DcutrEvent) -> Self {
+        Self::Dcutr(event)
+    }
+}
+
+impl From<GossipsubEvent> for Event {
+    fn from(event: GossipsubEvent) -> Self {
+        Self::Gossipsub(event)
+    }
+}
 
-impl ProtocolName for ExchangeProtocol {
-    fn protocol_name(&self) -> &[u8] {
-        b"/gistit/1"
+impl From<void::Void> for Event {
+    fn from(never: void::Void) -> Self {
+        void::unreachable(never)
     }
 }
 
+pub const EXCHANGE_PROTOCOL: StreamProtocol = StreamProtocol::new("/gistit/1");
+
+/// Shares the bandwidth caps with the node, so they can be changed at runtime
 #[derive(Clone)]
-pub struct ExchangeCodec;
+pub struct ExchangeCodec(Arc<Bandwidth>);
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Request(pub Vec<u8>);
@@ -216,7 +318,7 @@ impl std::error::Error for Response {
 
 #[async_trait]
 impl RequestResponseCodec for ExchangeCodec {
-    type Protocol = ExchangeProtocol;
+    type Protocol = StreamProtocol;
     type Request = Request;
     type Response = Response;
 
@@ -240,7 +342,7 @@ impl RequestResponseCodec for ExchangeCodec {
         _: &Self::Protocol,
         io: &mut T,
     ) -> io::Result<Self::Response> {
-        let bytes = read_length_prefixed(io, var::GISTIT_MAX_SIZE).await?;
+        let bytes = read_throttled(io, var::GISTIT_MAX_SIZE, &self.0.download).await?;
         let gistit = Gistit::decode(&*bytes).map_err(|_| io::ErrorKind::InvalidInput)?;
         log::debug!("Read response: {:?}", gistit);
 
@@ -278,9 +380,314 @@ impl RequestResponseCodec for ExchangeCodec {
             .map_err(|_| io::ErrorKind::InvalidInput)?;
         log::debug!("Write response {:?} bytes", buf.len());
 
-        write_length_prefixed(io, buf).await?;
+        write_throttled(io, &buf, &self.0.upload).await?;
+        io.close().await?;
+
+        Ok(())
+    }
+}
+
+/// Transfers a gistit in hash verified chunks, see [`crate::chunk`]
+pub const CHUNK_PROTOCOL: StreamProtocol = StreamProtocol::new("/gistit/chunk/1");
+
+#[derive(Clone)]
+pub struct ChunkCodec(Arc<Bandwidth>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChunkRequest {
+    /// The chunk digests of the gistit with this hash, and whether the requester agrees to it
+    /// being deleted once read
+    Manifest(Vec<u8>, bool),
+    /// A chunk of the gistit with this hash
+    Chunk(Vec<u8>, usize),
+    /// The metadata of the gistit with this hash, without it's content
+    Head(Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChunkResponse {
+    Manifest(Vec<Digest>),
+    Chunk(Vec<u8>),
+    Head(Head),
+    /// Not hosting the gistit or no such chunk
+    NotFound,
+    /// The gistit is deleted once read and the manifest request didn't agree to it
+    BurnAfterRead,
+}
+
+const CHUNK_KIND_MANIFEST: usize = 0;
+const CHUNK_KIND_CHUNK: usize = 1;
+const CHUNK_KIND_NOT_FOUND: usize = 2;
+const CHUNK_KIND_HEAD: usize = 3;
+/// Manifest requests agreeing to burn the gistit, and the answer to the ones that didn't
+const CHUNK_KIND_BURN_AFTER_READ: usize = 4;
+
+/// Encoded heads are a few hundred bytes, author, description and name are bounded
+const MAX_HEAD_SIZE: usize = 4 * 1024;
+
+#[async_trait]
+impl RequestResponseCodec for ChunkCodec {
+    type Protocol = StreamProtocol;
+    type Request = ChunkRequest;
+    type Response = ChunkResponse;
+
+    async fn read_request<T: Send + Unpin + AsyncRead>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+    ) -> io::Result<Self::Request> {
+        let kind = read_varint(io).await?;
+        let hash = read_length_prefixed(io, var::GISTIT_HASH_LENGTH).await?;
+        if hash.is_empty() {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        match kind {
+            CHUNK_KIND_MANIFEST => Ok(ChunkRequest::Manifest(hash, false)),
+            CHUNK_KIND_BURN_AFTER_READ => Ok(ChunkRequest::Manifest(hash, true)),
+            CHUNK_KIND_CHUNK => Ok(ChunkRequest::Chunk(hash, read_varint(io).await?)),
+            CHUNK_KIND_HEAD => Ok(ChunkRequest::Head(hash)),
+            _ => Err(io::ErrorKind::InvalidData.into()),
+        }
+    }
+
+    async fn read_response<T: Send + Unpin + AsyncRead>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+    ) -> io::Result<Self::Response> {
+        match read_varint(io).await? {
+            CHUNK_KIND_MANIFEST => {
+                let bytes = read_length_prefixed(io, chunk::MAX_CHUNKS * 32).await?;
+                if bytes.is_empty() || bytes.len() % 32 != 0 {
+                    return Err(io::ErrorKind::InvalidData.into());
+                }
+
+                Ok(ChunkResponse::Manifest(
+                    bytes
+                        .chunks(32)
+                        .map(|digest| digest.try_into().expect("digest to be 32 bytes"))
+                        .collect(),
+                ))
+            }
+            CHUNK_KIND_CHUNK => Ok(ChunkResponse::Chunk(
+                read_throttled(io, CHUNK_SIZE, &self.0.download).await?,
+            )),
+            CHUNK_KIND_HEAD => {
+                let bytes = read_length_prefixed(io, MAX_HEAD_SIZE).await?;
+                let head = Head::decode(&*bytes).map_err(|_| io::ErrorKind::InvalidData)?;
+                Ok(ChunkResponse::Head(head))
+            }
+            CHUNK_KIND_NOT_FOUND => Ok(ChunkResponse::NotFound),
+            CHUNK_KIND_BURN_AFTER_READ => Ok(ChunkResponse::BurnAfterRead),
+            _ => Err(io::ErrorKind::InvalidData.into()),
+        }
+    }
+
+    async fn write_request<T: Send + Unpin + AsyncWrite>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+        request: Self::Request,
+    ) -> io::Result<()> {
+        match request {
+            ChunkRequest::Manifest(hash, burn_after_read) => {
+                // Plain manifest requests stay readable by peers that don't know about burning
+                let kind = if burn_after_read {
+                    CHUNK_KIND_BURN_AFTER_READ
+                } else {
+                    CHUNK_KIND_MANIFEST
+                };
+                write_varint(io, kind).await?;
+                write_length_prefixed(io, hash).await?;
+            }
+            ChunkRequest::Chunk(hash, index) => {
+                write_varint(io, CHUNK_KIND_CHUNK).await?;
+                write_length_prefixed(io, hash).await?;
+                write_varint(io, index).await?;
+            }
+            ChunkRequest::Head(hash) => {
+                write_varint(io, CHUNK_KIND_HEAD).await?;
+                write_length_prefixed(io, hash).await?;
+            }
+        }
+        io.close().await?;
+        Ok(())
+    }
+
+    async fn write_response<T: Send + Unpin + AsyncWrite>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+        response: Self::Response,
+    ) -> io::Result<()> {
+        match response {
+            ChunkResponse::Manifest(digests) => {
+                write_varint(io, CHUNK_KIND_MANIFEST).await?;
+                write_length_prefixed(io, digests.concat()).await?;
+            }
+            ChunkResponse::Chunk(data) => {
+                write_varint(io, CHUNK_KIND_CHUNK).await?;
+                write_throttled(io, &data, &self.0.upload).await?;
+            }
+            ChunkResponse::Head(head) => {
+                write_varint(io, CHUNK_KIND_HEAD).await?;
+                write_length_prefixed(io, head.encode_to_vec()).await?;
+            }
+            ChunkResponse::NotFound => write_varint(io, CHUNK_KIND_NOT_FOUND).await?,
+            ChunkResponse::BurnAfterRead => write_varint(io, CHUNK_KIND_BURN_AFTER_READ).await?,
+        }
         io.close().await?;
+        Ok(())
+    }
+}
+
+/// Pushes a gistit straight to a peer, answered with whether it was accepted
+pub const PUSH_PROTOCOL: StreamProtocol = StreamProtocol::new("/gistit/push/1");
+
+#[derive(Clone)]
+pub struct PushCodec(Arc<Bandwidth>);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PushRequest(pub Gistit);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PushResponse(pub bool);
 
+#[async_trait]
+impl RequestResponseCodec for PushCodec {
+    type Protocol = StreamProtocol;
+    type Request = PushRequest;
+    type Response = PushResponse;
+
+    async fn read_request<T: Send + Unpin + AsyncRead>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+    ) -> io::Result<Self::Request> {
+        let bytes = read_throttled(io, var::GISTIT_MAX_SIZE, &self.0.download).await?;
+        if bytes.is_empty() {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        let gistit = Gistit::decode(&*bytes).map_err(|_| io::ErrorKind::InvalidInput)?;
+        log::debug!("Read push request {:?}", gistit.hash);
+        Ok(PushRequest(gistit))
+    }
+
+    async fn read_response<T: Send + Unpin + AsyncRead>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+    ) -> io::Result<Self::Response> {
+        let bytes = read_length_prefixed(io, 1).await?;
+        match bytes.as_slice() {
+            [accepted] => Ok(PushResponse(*accepted == 1)),
+            _ => Err(io::ErrorKind::UnexpectedEof.into()),
+        }
+    }
+
+    async fn write_request<T: Send + Unpin + AsyncWrite>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+        PushRequest(gistit): Self::Request,
+    ) -> io::Result<()> {
+        let mut buf = BytesMut::with_capacity(var::GISTIT_MAX_SIZE);
+        gistit
+            .encode(&mut buf)
+            .map_err(|_| io::ErrorKind::InvalidInput)?;
+        log::debug!("Write push request {:?} bytes", buf.len());
+
+        write_throttled(io, &buf, &self.0.upload).await?;
+        io.close().await?;
+        Ok(())
+    }
+
+    async fn write_response<T: Send + Unpin + AsyncWrite>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+        PushResponse(accepted): Self::Response,
+    ) -> io::Result<()> {
+        write_length_prefixed(io, [u8::from(accepted)]).await?;
+        io.close().await?;
         Ok(())
     }
 }
+
+/// Writes a length prefixed buffer in chunks, each waiting for `limiter`
+async fn write_throttled<T: AsyncWrite + Unpin + Send>(
+    io: &mut T,
+    buf: &[u8],
+    limiter: &Limiter,
+) -> io::Result<()> {
+    write_varint(io, buf.len()).await?;
+    for chunk in buf.chunks(TRANSFER_CHUNK_SIZE) {
+        limiter.throttle(chunk.len()).await;
+        io.write_all(chunk).await?;
+    }
+    Ok(())
+}
+
+/// Reads a length prefixed buffer of up to `max_size` bytes in chunks, each waiting for `limiter`
+async fn read_throttled<T: AsyncRead + Unpin + Send>(
+    io: &mut T,
+    max_size: usize,
+    limiter: &Limiter,
+) -> io::Result<Vec<u8>> {
+    let len = read_varint(io).await?;
+    if len > max_size {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "gistit exceeds the max size",
+        ));
+    }
+
+    let mut buf = vec![0_u8; len];
+    for chunk in buf.chunks_mut(TRANSFER_CHUNK_SIZE) {
+        limiter.throttle(chunk.len()).await;
+        io.read_exact(chunk).await?;
+    }
+    Ok(buf)
+}
+
+/// Writes `value` as an unsigned LEB128 varint
+async fn write_varint<T: AsyncWrite + Unpin + Send>(io: &mut T, value: usize) -> io::Result<()> {
+    let mut buf = unsigned_varint::encode::usize_buffer();
+    io.write_all(unsigned_varint::encode::usize(value, &mut buf))
+        .await
+}
+
+async fn read_varint<T: AsyncRead + Unpin + Send>(io: &mut T) -> io::Result<usize> {
+    unsigned_varint::aio::read_usize(io)
+        .await
+        .map_err(Into::into)
+}
+
+/// Writes `data` prefixed by it's length
+async fn write_length_prefixed<T: AsyncWrite + Unpin + Send>(
+    io: &mut T,
+    data: impl AsRef<[u8]>,
+) -> io::Result<()> {
+    write_varint(io, data.as_ref().len()).await?;
+    io.write_all(data.as_ref()).await
+}
+
+/// Reads a length prefixed buffer of up to `max_size` bytes
+async fn read_length_prefixed<T: AsyncRead + Unpin + Send>(
+    io: &mut T,
+    max_size: usize,
+) -> io::Result<Vec<u8>> {
+    let len = read_varint(io).await?;
+    if len > max_size {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "length prefixed data exceeds the max size",
+        ));
+    }
+
+    let mut buf = vec![0_u8; len];
+    io.read_exact(&mut buf).await?;
+    Ok(buf)
+}
@@ -0,0 +1,118 @@
+//! The bootstrap module
+//!
+//! Keeps track of user configured bootstrap peers (`--bootstrap-peer`), redialing the ones that
+//! failed with an exponential backoff until they connect or run out of attempts.
+
+use std::cmp::min;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use libp2p::core::{Multiaddr, PeerId};
+use libp2p::multiaddr::Protocol;
+
+use crate::{Error, Result};
+
+/// First retry delay, doubled on each failed attempt
+const RETRY_BASE: Duration = Duration::from_secs(1);
+
+/// Upper bound for the retry delay
+const RETRY_MAX: Duration = Duration::from_secs(5 * 60);
+
+/// Give up on a bootstrap peer after this many failed dials
+const RETRY_MAX_ATTEMPTS: u32 = 10;
+
+#[derive(Debug, Clone, Copy)]
+struct Retry {
+    attempts: u32,
+    due: Instant,
+}
+
+#[derive(Debug, Default)]
+pub struct Bootstrap {
+    peers: HashMap<PeerId, Multiaddr>,
+    retries: HashMap<PeerId, Retry>,
+}
+
+impl Bootstrap {
+    /// # Errors
+    ///
+    /// Fails if an address doesn't end with a `/p2p/<peer id>` component
+    pub fn new(addresses: &[Multiaddr]) -> Result<Self> {
+        let mut peers = HashMap::new();
+        for address in addresses {
+            peers.insert(peer_id(address)?, address.clone());
+        }
+
+        Ok(Self {
+            peers,
+            retries: HashMap::default(),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.peers.is_empty()
+    }
+
+    pub fn peers(&self) -> impl Iterator<Item = (&PeerId, &Multiaddr)> {
+        self.peers.iter()
+    }
+
+    /// Records a connection to `peer`, cancelling its pending retries
+    pub fn connected(&mut self, peer: &PeerId) {
+        self.retries.remove(peer);
+    }
+
+    /// Records a failed dial to `peer`, scheduling a retry if it's a bootstrap peer with attempts
+    /// left
+    pub fn failed(&mut self, peer: &PeerId, now: Instant) {
+        if !self.peers.contains_key(peer) {
+            return;
+        }
+
+        let attempts = self.retries.get(peer).map_or(1, |retry| retry.attempts + 1);
+        if attempts > RETRY_MAX_ATTEMPTS {
+            log::warn!("Giving up on bootstrap peer {:?}", peer);
+            self.retries.remove(peer);
+            return;
+        }
+
+        let delay = backoff(attempts);
+        log::info!("Retrying bootstrap peer {:?} in {:?}", peer, delay);
+        self.retries.insert(
+            *peer,
+            Retry {
+                attempts,
+                due: now + delay,
+            },
+        );
+    }
+
+    /// Addresses whose retry is due. Their retries stay recorded until they connect or fail again
+    pub fn due(&mut self, now: Instant) -> Vec<(PeerId, Multiaddr)> {
+        let peers = &self.peers;
+        self.retries
+            .iter_mut()
+            .filter(|(_, retry)| retry.due <= now)
+            .filter_map(|(peer, retry)| {
+                // Don't redial again until this attempt fails
+                retry.due = now + RETRY_MAX;
+                peers.get(peer).map(|address| (*peer, address.clone()))
+            })
+            .collect()
+    }
+}
+
+fn backoff(attempts: u32) -> Duration {
+    let factor = 2_u32.saturating_pow(attempts.saturating_sub(1));
+    min(RETRY_BASE.saturating_mul(factor), RETRY_MAX)
+}
+
+/// Extracts the peer id from an address ending with `/p2p/<peer id>`
+pub fn peer_id(address: &Multiaddr) -> Result<PeerId> {
+    match address.iter().last() {
+        Some(Protocol::P2p(peer)) => Ok(peer),
+        _ => Err(Error::Parse(
+            "bootstrap address must end with '/p2p/<peer id>'",
+        )),
+    }
+}
@@ -0,0 +1,251 @@
+//! The chunk module
+//!
+//! Gistits are fetched in hash verified chunks. The fetching node first asks a provider for the
+//! manifest, the sha256 of every chunk of the encoded gistit, then downloads chunks from every
+//! provider in parallel, BitTorrent style. Downloaded chunks outlive failed requests, so a fetch
+//! that ran out of providers resumes where it stopped the next time it's requested.
+//!
+//! Providers are picked by their score, see [`crate::reputation`], a failing one is dropped and
+//! the next best takes over.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+use libp2p::core::PeerId;
+use libp2p::request_response::OutboundRequestId;
+use sha2::{Digest as _, Sha256};
+
+use gistit_project::var;
+
+/// Size of every chunk but the last one
+pub const CHUNK_SIZE: usize = 16 * 1024;
+
+/// Most chunks a gistit can be split into
+pub const MAX_CHUNKS: usize = (var::GISTIT_MAX_SIZE + CHUNK_SIZE - 1) / CHUNK_SIZE;
+
+/// Chunk requests a single provider serves at the same time
+const MAX_IN_FLIGHT_PER_PEER: usize = 2;
+
+pub type Digest = [u8; 32];
+
+#[must_use]
+pub fn digest(chunk: &[u8]) -> Digest {
+    Sha256::digest(chunk).into()
+}
+
+/// The digest of every chunk of `bytes`
+#[must_use]
+pub fn manifest(bytes: &[u8]) -> Vec<Digest> {
+    bytes.chunks(CHUNK_SIZE).map(digest).collect()
+}
+
+/// The `index`th chunk of `bytes`, if there's one
+#[must_use]
+pub fn chunk(bytes: &[u8], index: usize) -> Option<&[u8]> {
+    bytes.chunks(CHUNK_SIZE).nth(index)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pending {
+    Manifest,
+    Chunk(usize),
+}
+
+/// A gistit being downloaded from one or more providers
+#[derive(Debug, Default)]
+pub struct Download {
+    manifest: Option<Vec<Digest>>,
+    chunks: Vec<Option<Vec<u8>>>,
+    /// Providers yet to be asked for the manifest
+    manifest_queue: VecDeque<PeerId>,
+    providers: HashSet<PeerId>,
+    in_flight: HashMap<OutboundRequestId, (PeerId, Pending, Instant)>,
+}
+
+impl Download {
+    /// Adds providers found in the DHT, skipping known ones
+    pub fn add_providers(&mut self, providers: impl IntoIterator<Item = PeerId>) {
+        for peer in providers {
+            if self.providers.insert(peer) {
+                self.manifest_queue.push_back(peer);
+            }
+        }
+    }
+
+    /// The best scored provider to ask for the manifest, if it's still missing and not being
+    /// asked for
+    pub fn manifest_peer(&mut self, score: impl Fn(&PeerId) -> f64) -> Option<PeerId> {
+        let asking = self
+            .in_flight
+            .values()
+            .any(|(_, pending, _)| *pending == Pending::Manifest);
+        if self.manifest.is_some() || asking {
+            return None;
+        }
+
+        let providers = &self.providers;
+        self.manifest_queue.retain(|peer| providers.contains(peer));
+        let best = self
+            .manifest_queue
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| score(a).total_cmp(&score(b)))?
+            .0;
+        self.manifest_queue.remove(best)
+    }
+
+    pub fn manifest_requested(&mut self, request_id: OutboundRequestId, peer: PeerId) {
+        self.in_flight
+            .insert(request_id, (peer, Pending::Manifest, Instant::now()));
+    }
+
+    pub fn chunk_requested(&mut self, request_id: OutboundRequestId, peer: PeerId, index: usize) {
+        self.in_flight
+            .insert(request_id, (peer, Pending::Chunk(index), Instant::now()));
+    }
+
+    /// The provider asked with `request_id` and how long ago
+    pub fn requested(&self, request_id: OutboundRequestId) -> Option<(PeerId, Duration)> {
+        self.in_flight
+            .get(&request_id)
+            .map(|(peer, _, at)| (*peer, at.elapsed()))
+    }
+
+    /// Records the manifest answered to `request_id`, returns `false` if it's invalid. A manifest
+    /// already in place (kept from an interrupted download) wins over a new one
+    pub fn manifest_received(
+        &mut self,
+        request_id: OutboundRequestId,
+        manifest: Vec<Digest>,
+    ) -> bool {
+        let peer = match self.in_flight.remove(&request_id) {
+            Some((peer, Pending::Manifest, _)) => peer,
+            _ => return false,
+        };
+
+        if manifest.is_empty() || manifest.len() > MAX_CHUNKS {
+            self.providers.remove(&peer);
+            return false;
+        }
+
+        if self.manifest.is_none() {
+            self.chunks = vec![None; manifest.len()];
+            self.manifest = Some(manifest);
+        }
+        true
+    }
+
+    /// Stores the chunk answered to `request_id` if it matches the manifest. Providers sending
+    /// a bad chunk aren't asked again
+    pub fn chunk_received(
+        &mut self,
+        request_id: OutboundRequestId,
+        data: Vec<u8>,
+    ) -> Option<PeerId> {
+        let (peer, index) = match self.in_flight.remove(&request_id) {
+            Some((peer, Pending::Chunk(index), _)) => (peer, index),
+            _ => return None,
+        };
+
+        let expected = self
+            .manifest
+            .as_ref()
+            .and_then(|manifest| manifest.get(index));
+        if expected != Some(&digest(&data)) {
+            log::warn!("Chunk {} from {:?} doesn't match the manifest", index, peer);
+            self.providers.remove(&peer);
+            return None;
+        }
+
+        self.chunks[index] = Some(data);
+        Some(peer)
+    }
+
+    /// Drops a request that failed, the provider isn't asked again
+    pub fn request_failed(&mut self, request_id: OutboundRequestId) {
+        if let Some((peer, _, _)) = self.in_flight.remove(&request_id) {
+            self.providers.remove(&peer);
+        }
+    }
+
+    /// Missing chunks assigned to the best scored providers with free request slots, the least
+    /// loaded one if scores are even
+    pub fn next_requests(&self, score: impl Fn(&PeerId) -> f64) -> Vec<(PeerId, usize)> {
+        let mut load: HashMap<PeerId, usize> =
+            self.providers.iter().map(|peer| (*peer, 0)).collect();
+        let mut busy = HashSet::new();
+        for (peer, pending, _) in self.in_flight.values() {
+            if let Some(count) = load.get_mut(peer) {
+                *count += 1;
+            }
+            if let Pending::Chunk(index) = pending {
+                busy.insert(*index);
+            }
+        }
+
+        let mut requests = Vec::new();
+        for (index, chunk) in self.chunks.iter().enumerate() {
+            if chunk.is_some() || busy.contains(&index) {
+                continue;
+            }
+
+            let best = load
+                .iter_mut()
+                .filter(|(_, count)| **count < MAX_IN_FLIGHT_PER_PEER)
+                .max_by(|(a, a_count), (b, b_count)| {
+                    score(a)
+                        .total_cmp(&score(b))
+                        .then_with(|| b_count.cmp(a_count))
+                });
+            match best {
+                Some((peer, count)) => {
+                    *count += 1;
+                    requests.push((*peer, index));
+                }
+                None => break,
+            }
+        }
+        requests
+    }
+
+    /// Downloaded and total chunks, zero total while the manifest is missing
+    pub fn progress(&self) -> (usize, usize) {
+        let done = self.chunks.iter().filter(|chunk| chunk.is_some()).count();
+        (done, self.chunks.len())
+    }
+
+    /// Size of the downloaded chunks
+    pub fn bytes_done(&self) -> usize {
+        self.chunks.iter().flatten().map(Vec::len).sum()
+    }
+
+    /// Providers not yet dropped for failing or misbehaving
+    pub fn provider_count(&self) -> usize {
+        self.providers.len()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.manifest.is_some() && self.chunks.iter().all(Option::is_some)
+    }
+
+    /// Nothing in flight and no provider left to ask
+    pub fn is_stalled(&self) -> bool {
+        self.in_flight.is_empty() && (self.providers.is_empty() || self.manifest.is_none())
+    }
+
+    /// Forgets every provider, keeping downloaded chunks for a later resume
+    pub fn interrupt(&mut self) {
+        self.providers.clear();
+        self.manifest_queue.clear();
+        self.in_flight.clear();
+    }
+
+    /// The encoded gistit, once complete
+    pub fn assemble(&self) -> Option<Vec<u8>> {
+        self.chunks
+            .iter()
+            .map(Option::as_deref)
+            .collect::<Option<Vec<&[u8]>>>()
+            .map(|chunks| chunks.concat())
+    }
+}
@@ -1,84 +1,348 @@
-use std::fmt::Debug;
+//! The config module
+//!
+//! Everything the node runs with comes from the command line or from [`CONFIG_FILE_NAME`] inside
+//! the config directory, the command line winning. The file is read again on SIGHUP or a
+//! `ReloadConfigRequest`, see `Node::reload_config`.
+
+use std::fmt::{Debug, Display};
 use std::fs;
+use std::io;
 use std::net::Ipv4Addr;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::Duration;
 
 use libp2p::core::{Multiaddr, PeerId};
-use libp2p::identity::{self, ed25519, Keypair};
+use libp2p::identity::Keypair;
 use libp2p::multiaddr::multiaddr;
+use libp2p::relay;
 
-use log::{debug, info};
+use log::{debug, info, LevelFilter};
 use serde::{Deserialize, Serialize};
 use zeroize::{Zeroize, Zeroizing};
 
+use crate::acl::Rule;
+use crate::storage::DEFAULT_STORAGE_QUOTA;
+use crate::{bootstrap, identity};
 use crate::{Error, Result};
 
+/// The config file name inside the config directory
+pub const CONFIG_FILE_NAME: &str = "daemon.toml";
+
 pub struct Config {
     pub peer_id: PeerId,
     pub keypair: Keypair,
     pub runtime_path: PathBuf,
     pub config_path: PathBuf,
+    /// Where fetched and pinned gistits are stored
+    pub data_path: PathBuf,
     pub multiaddr: Multiaddr,
+    /// The QUIC listener of `--host` and `--port`
+    pub quic_multiaddr: Multiaddr,
+    pub settings: Settings,
+    /// What the command line set, it keeps winning over the config file when it's reloaded
+    pub overrides: FileConfig,
+}
+
+/// Transfer limits, zero means unlimited
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// Bytes per second
+    pub upload_rate: u64,
+    pub download_rate: u64,
+    /// Requests per minute a single peer can make
+    pub peer_request_rate: u32,
+}
+
+/// What TCP and WebSocket connections are secured with, QUIC always uses TLS 1.3. Both peers need
+/// the same one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Security {
+    #[default]
+    Noise,
+    Tls,
+}
+
+impl FromStr for Security {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "noise" => Ok(Self::Noise),
+            "tls" => Ok(Self::Tls),
+            _ => Err("expected 'noise' or 'tls'"),
+        }
+    }
+}
+
+/// Relay server limits, unset fields use libp2p defaults
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RelayQuota {
+    pub max_reservations: Option<usize>,
+    pub max_circuits: Option<usize>,
+    /// Seconds
+    pub max_circuit_duration: Option<u64>,
+    pub max_circuit_bytes: Option<u64>,
+}
+
+impl From<RelayQuota> for relay::Config {
+    fn from(quota: RelayQuota) -> Self {
+        let default = Self::default();
+
+        Self {
+            max_reservations: quota.max_reservations.unwrap_or(default.max_reservations),
+            max_circuits: quota.max_circuits.unwrap_or(default.max_circuits),
+            max_circuit_duration: quota
+                .max_circuit_duration
+                .map_or(default.max_circuit_duration, Duration::from_secs),
+            max_circuit_bytes: quota.max_circuit_bytes.unwrap_or(default.max_circuit_bytes),
+            ..default
+        }
+    }
+}
+
+/// The settings of the command line or the config file, checked and with defaults filled in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Settings {
+    /// Addresses listened on besides `--host` and `--port`
+    pub listen: Vec<Multiaddr>,
+    pub security: Security,
     pub bootstrap: bool,
+    /// User provided bootstrap peers, replacing the default libp2p ones when not empty
+    pub bootstrap_peers: Vec<Multiaddr>,
+    /// Connection gating rules, parsed by [`crate::acl::Acl`]
+    pub acl: Vec<String>,
+    /// Relay server quotas, `None` if not acting as a relay server
+    pub relay: Option<RelayQuota>,
+    pub limits: Limits,
+    /// Overrides the data directory
+    pub storage_path: Option<PathBuf>,
+    /// Bytes of fetched and pinned gistits kept on disk
+    pub storage_quota: u64,
+    /// Desktop notifications on node events
+    pub notify: bool,
+    /// Overrides the default log level, unless `RUST_LOG` is set
+    pub log_level: Option<LevelFilter>,
+}
+
+/// Transfer limits of [`FileConfig`], unset ones are unlimited
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FileLimits {
+    pub upload_rate: Option<u64>,
+    pub download_rate: Option<u64>,
+    pub peer_request_rate: Option<u32>,
+}
+
+/// The contents of [`CONFIG_FILE_NAME`], all optional. The command line arguments are read into
+/// one too, so both merge with [`FileConfig::or`]
+///
+/// ```toml
+/// listen = ["/ip4/0.0.0.0/tcp/4001", "/ip4/0.0.0.0/udp/4001/quic-v1"]
+/// security = "noise"
+/// bootstrap_peers = ["/ip4/10.0.0.1/tcp/4001/p2p/12D3KooW..."]
+/// acl = ["deny 10.0.0.0/8"]
+/// storage_quota = 100000000
+/// log_level = "debug"
+///
+/// [limits]
+/// upload_rate = 500000
+///
+/// # Acts as a relay server, with libp2p's default quotas if none are given
+/// [relay]
+/// max_circuits = 16
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FileConfig {
+    pub listen: Vec<String>,
+    /// `noise` or `tls`
+    pub security: Option<String>,
+    pub bootstrap: Option<bool>,
+    pub bootstrap_peers: Vec<String>,
+    pub acl: Vec<String>,
+    pub relay: Option<RelayQuota>,
+    pub limits: FileLimits,
+    pub storage_path: Option<PathBuf>,
+    pub storage_quota: Option<u64>,
+    pub notify: Option<bool>,
+    /// `off`, `error`, `warn`, `info`, `debug` or `trace`
+    pub log_level: Option<String>,
+}
+
+impl FileConfig {
+    /// Reads [`CONFIG_FILE_NAME`] inside `config_path`, empty if there's none
+    pub fn load(config_path: &Path) -> Result<Self> {
+        match fs::read_to_string(config_path.join(CONFIG_FILE_NAME)) {
+            Ok(content) => {
+                toml::from_str(&content).map_err(|err| Error::Config(vec![err.to_string()]))
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Takes what `self` leaves unset from `other`. Acl rules of both are kept
+    #[must_use]
+    pub fn or(self, other: Self) -> Self {
+        let or_list = |list: Vec<String>, other: Vec<String>| {
+            if list.is_empty() {
+                other
+            } else {
+                list
+            }
+        };
+
+        Self {
+            listen: or_list(self.listen, other.listen),
+            security: self.security.or(other.security),
+            bootstrap: self.bootstrap.or(other.bootstrap),
+            bootstrap_peers: or_list(self.bootstrap_peers, other.bootstrap_peers),
+            acl: other.acl.into_iter().chain(self.acl).collect(),
+            relay: self.relay.or(other.relay),
+            limits: FileLimits {
+                upload_rate: self.limits.upload_rate.or(other.limits.upload_rate),
+                download_rate: self.limits.download_rate.or(other.limits.download_rate),
+                peer_request_rate: self
+                    .limits
+                    .peer_request_rate
+                    .or(other.limits.peer_request_rate),
+            },
+            storage_path: self.storage_path.or(other.storage_path),
+            storage_quota: self.storage_quota.or(other.storage_quota),
+            notify: self.notify.or(other.notify),
+            log_level: self.log_level.or(other.log_level),
+        }
+    }
+
+    /// Checks every setting, failing with all the invalid ones
+    pub fn check(&self) -> Result<Settings> {
+        let mut errors = Vec::new();
+
+        let listen: Vec<Multiaddr> = parse_all(&self.listen, "listen address", &mut errors);
+        let bootstrap_peers: Vec<Multiaddr> =
+            parse_all(&self.bootstrap_peers, "bootstrap peer", &mut errors);
+        for address in &bootstrap_peers {
+            if bootstrap::peer_id(address).is_err() {
+                errors.push(format!(
+                    "bootstrap peer '{}' doesn't end with a /p2p/ peer id",
+                    address
+                ));
+            }
+        }
+        parse_all::<Rule>(&self.acl, "acl rule", &mut errors);
+        let security = self.security.as_ref().and_then(|security| {
+            security
+                .parse()
+                .map_err(|err| errors.push(format!("invalid security '{}', {}", security, err)))
+                .ok()
+        });
+        let log_level: Option<LevelFilter> = self.log_level.as_ref().and_then(|level| {
+            level
+                .parse()
+                .map_err(|_| errors.push(format!("invalid log level '{}'", level)))
+                .ok()
+        });
+
+        if !errors.is_empty() {
+            return Err(Error::Config(errors));
+        }
+
+        Ok(Settings {
+            listen,
+            security: security.unwrap_or_default(),
+            bootstrap: self.bootstrap.unwrap_or(false),
+            bootstrap_peers,
+            acl: self.acl.clone(),
+            relay: self.relay,
+            limits: Limits {
+                upload_rate: self.limits.upload_rate.unwrap_or(0),
+                download_rate: self.limits.download_rate.unwrap_or(0),
+                peer_request_rate: self.limits.peer_request_rate.unwrap_or(0),
+            },
+            storage_path: self.storage_path.clone(),
+            storage_quota: self.storage_quota.unwrap_or(DEFAULT_STORAGE_QUOTA),
+            notify: self.notify.unwrap_or(false),
+            log_level,
+        })
+    }
+}
+
+/// Parses every value, adding an error naming `what` for the invalid ones
+fn parse_all<T>(values: &[String], what: &str, errors: &mut Vec<String>) -> Vec<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    values
+        .iter()
+        .filter_map(|value| match value.parse() {
+            Ok(parsed) => Some(parsed),
+            Err(err) => {
+                errors.push(format!("invalid {} '{}', {}", what, value, err));
+                None
+            }
+        })
+        .collect()
 }
 
 impl Debug for Config {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "{:?} {:?} {:?} {:?}",
-            self.peer_id, self.runtime_path, self.config_path, self.multiaddr,
+            "{:?} {:?} {:?} {:?} {:?} {:?}",
+            self.peer_id,
+            self.runtime_path,
+            self.config_path,
+            self.data_path,
+            self.multiaddr,
+            self.settings,
         )
     }
 }
 
 impl Config {
-    pub fn from_args(
+    /// The config of the command line `overrides` and the config file
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`Error::Config`] if the config file or the command line have invalid settings
+    pub fn new(
         runtime_path: Option<PathBuf>,
         config_path: Option<PathBuf>,
         config_file: Option<PathBuf>,
         host: Option<Ipv4Addr>,
         port: Option<u16>,
-        bootstrap: bool,
+        overrides: FileConfig,
     ) -> Result<Self> {
         gistit_project::path::init()?;
 
         let host = host.unwrap_or_else(|| Ipv4Addr::new(0, 0, 0, 0));
         let port = port.unwrap_or(0_u16);
         let multiaddr = multiaddr!(Ip4(host), Tcp(port));
+        let quic_multiaddr = multiaddr!(Ip4(host), Udp(port), QuicV1);
 
-        let runtime_path = runtime_path.unwrap_or(gistit_project::path::runtime()?);
+        let runtime_path = runtime_path.unwrap_or(gistit_project::path::runtime_dir()?);
         let config_path = config_path.unwrap_or(gistit_project::path::config()?);
-        let node_config = config_file.unwrap_or_else(|| config_path.join("node-config"));
-
-        let (peer_id, keypair) = if fs::metadata(&node_config).is_ok() {
-            debug!("Using existing node config file");
-            let config = Zeroizing::new(NodeKey::from_file(&node_config)?);
-
-            let keypair = identity::Keypair::from_protobuf_encoding(&Zeroizing::new(
-                base64::decode(config.identity.priv_key.as_bytes())?,
-            ))?;
-
-            let peer_id = keypair.public().into();
-            assert_eq!(
-                    PeerId::from_str(&config.identity.peer_id)
-                    .map_err(|_| Error::Parse("failed to parse config peer id"))?,
-                    peer_id,
-                    "Expect peer id derived from private key and peer id retrieved from config to match."
-                );
-            (peer_id, keypair)
-        } else {
-            debug!("Generating new node key material");
-            let keypair = identity::Keypair::Ed25519(ed25519::Keypair::generate());
-            let peer_id = PeerId::from(keypair.public());
-
-            // Storing generated key material
-            let key_material = NodeKey::from_key_material(peer_id, &keypair)?;
-            fs::write(node_config, serde_json::to_string(&key_material)?)?;
-
-            (peer_id, keypair)
+        let settings = overrides
+            .clone()
+            .or(FileConfig::load(&config_path)?)
+            .check()?;
+        let data_path = match settings.storage_path {
+            Some(ref storage_path) => storage_path.clone(),
+            None => gistit_project::path::data()?,
+        };
+
+        // An explicit key file (e.g. an IPFS config) wins over the stored identity
+        let keypair = match config_file {
+            Some(config_file) => {
+                debug!("Using key material from {:?}", config_file);
+                identity::from_node_key(&Zeroizing::new(NodeKey::from_file(&config_file)?))?
+            }
+            None => identity::load_or_generate(&config_path)?,
         };
+        let peer_id = PeerId::from(keypair.public());
         info!("{:?}", peer_id);
 
         Ok(Self {
@@ -86,8 +350,11 @@ impl Config {
             keypair,
             runtime_path,
             config_path,
+            data_path,
             multiaddr,
-            bootstrap,
+            quic_multiaddr,
+            settings,
+            overrides,
         })
     }
 }
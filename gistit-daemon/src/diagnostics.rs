@@ -0,0 +1,192 @@
+//! The diagnostics module
+//!
+//! Reachability checks run for `gistit daemon doctor`. Bootstrap peers are dialed when the checks
+//! are requested, everything else is read from the swarm state once the dials complete or
+//! [`DIAGNOSTICS_TIMEOUT`] runs out.
+
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use libp2p::autonat::NatStatus;
+use libp2p::core::PeerId;
+
+use gistit_proto::ipc::instruction::diagnostic_check::Verdict;
+use gistit_proto::ipc::instruction::DiagnosticCheck;
+
+/// How long bootstrap peer dials are waited for
+pub const DIAGNOSTICS_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn check(name: &str, verdict: Verdict, detail: String, hint: &str) -> DiagnosticCheck {
+    let mut check = DiagnosticCheck {
+        name: name.to_owned(),
+        detail,
+        hint: hint.to_owned(),
+        ..DiagnosticCheck::default()
+    };
+    check.set_verdict(verdict);
+    check
+}
+
+/// Bootstrap peer dials of a diagnostics run, `None` while pending
+#[derive(Debug)]
+pub struct Diagnostics {
+    started: Instant,
+    dials: HashMap<PeerId, Option<bool>>,
+}
+
+impl Diagnostics {
+    pub fn new(peers: impl IntoIterator<Item = PeerId>, now: Instant) -> Self {
+        Self {
+            started: now,
+            dials: peers.into_iter().map(|peer| (peer, None)).collect(),
+        }
+    }
+
+    pub fn connected(&mut self, peer: &PeerId) {
+        if let Some(dial) = self.dials.get_mut(peer) {
+            *dial = Some(true);
+        }
+    }
+
+    /// Records a failed dial, unless the peer connected already
+    pub fn failed(&mut self, peer: &PeerId) {
+        if let Some(dial @ None) = self.dials.get_mut(peer) {
+            *dial = Some(false);
+        }
+    }
+
+    pub fn is_done(&self, now: Instant) -> bool {
+        self.dials.values().all(Option::is_some)
+            || now.duration_since(self.started) >= DIAGNOSTICS_TIMEOUT
+    }
+
+    /// Whether bootstrap peers could be reached, falling back to any connected peer when none are
+    /// configured
+    pub fn bootstrap_check(&self, connected_peers: usize) -> DiagnosticCheck {
+        const NAME: &str = "bootstrap peers";
+
+        if self.dials.is_empty() {
+            return if connected_peers > 0 {
+                check(
+                    NAME,
+                    Verdict::Pass,
+                    format!("none configured, {} peers connected", connected_peers),
+                    "",
+                )
+            } else {
+                check(
+                    NAME,
+                    Verdict::Fail,
+                    "none configured and no peers connected".to_owned(),
+                    "add one with `gistit daemon bootstrap add <multiaddr>`",
+                )
+            };
+        }
+
+        let reached = self
+            .dials
+            .values()
+            .filter(|dial| **dial == Some(true))
+            .count();
+        let detail = format!("{}/{} reachable", reached, self.dials.len());
+        if reached > 0 {
+            check(NAME, Verdict::Pass, detail, "")
+        } else {
+            check(
+                NAME,
+                Verdict::Fail,
+                detail,
+                "check your connection and the addresses in `gistit daemon bootstrap ls`",
+            )
+        }
+    }
+}
+
+pub fn autonat_check(status: &NatStatus) -> DiagnosticCheck {
+    const NAME: &str = "autonat";
+
+    match status {
+        NatStatus::Public(address) => check(
+            NAME,
+            Verdict::Pass,
+            format!("publicly reachable at {}", address),
+            "",
+        ),
+        NatStatus::Private => check(
+            NAME,
+            Verdict::Fail,
+            "behind a NAT".to_owned(),
+            "peers reach you through relays, forward the listen port to be reachable directly",
+        ),
+        NatStatus::Unknown => check(
+            NAME,
+            Verdict::Fail,
+            "reachability unknown".to_owned(),
+            "not enough peers probed this node yet, try again in a few minutes",
+        ),
+    }
+}
+
+/// `gistit-daemon` doesn't map ports itself (UPnP, NAT-PMP), so this only tells whether it's needed
+pub fn port_mapping_check(status: &NatStatus) -> DiagnosticCheck {
+    const NAME: &str = "port mapping";
+
+    match status {
+        NatStatus::Public(_) => check(
+            NAME,
+            Verdict::Pass,
+            "not needed, publicly reachable".to_owned(),
+            "",
+        ),
+        _ => check(
+            NAME,
+            Verdict::Skip,
+            "not supported, ports aren't mapped automatically".to_owned(),
+            "forward the listen port (`--port`) on your router to be reachable without relays",
+        ),
+    }
+}
+
+pub fn relay_check(status: &NatStatus, reservations: &HashSet<PeerId>) -> DiagnosticCheck {
+    const NAME: &str = "relay reservations";
+
+    if let NatStatus::Public(_) = status {
+        return check(
+            NAME,
+            Verdict::Skip,
+            "not needed, publicly reachable".to_owned(),
+            "",
+        );
+    }
+
+    if reservations.is_empty() {
+        check(
+            NAME,
+            Verdict::Fail,
+            "no relay accepted a reservation".to_owned(),
+            "peers can't reach you while behind a NAT, make sure you're connected to relay peers",
+        )
+    } else {
+        check(
+            NAME,
+            Verdict::Pass,
+            format!("{} active", reservations.len()),
+            "",
+        )
+    }
+}
+
+pub fn routing_table_check(size: usize) -> DiagnosticCheck {
+    const NAME: &str = "dht routing table";
+
+    if size > 0 {
+        check(NAME, Verdict::Pass, format!("{} peers", size), "")
+    } else {
+        check(
+            NAME,
+            Verdict::Fail,
+            "empty".to_owned(),
+            "gistits can't be found or provided, check the bootstrap peers",
+        )
+    }
+}
@@ -10,7 +10,7 @@ pub enum Error {
     Json(#[from] serde_json::Error),
 
     #[error("decode error, {0}")]
-    Identity(#[from] libp2p::identity::error::DecodingError),
+    Identity(#[from] libp2p::identity::DecodingError),
     #[error("decode error, {0}")]
     Base64(#[from] base64::DecodeError),
     #[error("decode error, {0}")]
@@ -31,6 +31,21 @@ pub enum Error {
     #[error("request response codec error, {0}")]
     Codec(#[from] crate::behaviour::Response),
 
+    #[error("gossipsub error, {0}")]
+    Gossipsub(&'static str),
+
+    #[error("identity error, {0}")]
+    Keystore(&'static str),
+
+    #[error("storage error, {0}")]
+    Storage(&'static str),
+
     #[error("parse error, {0}")]
     Parse(&'static str),
+
+    #[error("outbox error, {0}")]
+    Outbox(&'static str),
+
+    #[error("invalid config, {}", .0.join("; "))]
+    Config(Vec<String>),
 }
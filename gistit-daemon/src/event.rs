@@ -1,15 +1,33 @@
+use std::collections::HashMap;
 use std::str;
+use std::time::{Duration, Instant};
 
-use libp2p::identify::{IdentifyEvent, IdentifyInfo};
-use libp2p::kad::record::Key;
-use libp2p::kad::{GetProvidersError, GetProvidersOk, KademliaEvent, QueryResult};
+use libp2p::autonat::Event as AutonatEvent;
+use libp2p::dcutr::Event as DcutrEvent;
+use libp2p::gossipsub::Event as GossipsubEvent;
+use libp2p::identify::{Event as IdentifyEvent, Info as IdentifyInfo};
+use libp2p::kad::store::RecordStore;
+use libp2p::kad::{
+    self, Event as KademliaEvent, GetProvidersError, GetProvidersOk, GetRecordOk, InboundRequest,
+    QueryResult, RecordKey as Key,
+};
 use libp2p::multiaddr::Protocol;
-use libp2p::request_response::{RequestResponseEvent, RequestResponseMessage};
+use libp2p::Multiaddr;
+use libp2p::relay::client::Event as ClientEvent;
+use libp2p::relay::HOP_PROTOCOL_NAME as RELAY_HOP_PROTO;
+use libp2p::request_response::{Event as RequestResponseEvent, Message as RequestResponseMessage};
 
-use gistit_proto::Instruction;
-use log::{debug, error, info};
+use gistit_proto::ipc::instruction::fetch_progress_response::Stage;
+use gistit_proto::prost::Message;
+use gistit_proto::{Announcement, Gistit, Head, Instruction};
+use log::{debug, error, info, warn};
 
-use crate::behaviour::{Request, Response};
+use crate::behaviour::{
+    ChunkRequest, ChunkResponse, PushRequest, PushResponse, Request, Response,
+};
+use crate::chunk;
+use crate::metrics::{Metrics, METRICS};
+use crate::name;
 use crate::node::Node;
 use crate::Result;
 
@@ -18,17 +36,30 @@ pub async fn handle_request_response(
     event: RequestResponseEvent<Request, Response>,
 ) -> Result<()> {
     match event {
-        RequestResponseEvent::Message { message, .. } => match message {
+        RequestResponseEvent::Message { peer, message } => match message {
             RequestResponseMessage::Request {
                 request, channel, ..
             } => {
+                if node.acl.is_denied(&peer) {
+                    warn!("Refusing request from {:?}, denied by acl", peer);
+                    return Ok(());
+                }
+                if !node.peer_rate.allow(peer, Instant::now()) {
+                    warn!("Refusing request from {:?}, over the request rate", peer);
+                    Metrics::inc(&METRICS.rate_limited);
+                    return Ok(());
+                }
+
                 let key = Key::new(&request.0);
                 info!("Request response 'Message::Request' for {:?}", key);
-                let file = node
-                    .to_provide
-                    .get(&key)
-                    .expect("to be providing {key}")
-                    .clone();
+                // Dropping the channel fails the request on the other end
+                let file = match requested(&node.to_provide, &key) {
+                    Some(file) => file.clone(),
+                    None => return Ok(()),
+                };
+                node.storage.touch(&file.hash)?;
+                node.notifier.fetched(&file.hash, &peer, Instant::now());
+                Metrics::add(&METRICS.bytes_served, file.encoded_len() as u64);
 
                 node.swarm
                     .behaviour_mut()
@@ -41,11 +72,13 @@ pub async fn handle_request_response(
             } => {
                 info!("Request response 'Message::Response'");
                 let gistit = response.0;
+                Metrics::add(&METRICS.bytes_received, gistit.encoded_len() as u64);
                 let key = Key::new(&gistit.hash.as_bytes());
 
+                node.answer_gateway_fetch(&key, Some(&gistit));
                 if node.pending_receive_file.remove(&key) {
-                    node.bridge.connect_blocking()?;
-                    node.bridge
+                    Metrics::inc(&METRICS.fetch_success);
+                    node.outbox
                         .send(Instruction::respond_fetch(Some(gistit)))
                         .await?;
                 }
@@ -56,9 +89,9 @@ pub async fn handle_request_response(
             request_id, error, ..
         } => {
             error!("Request response outbound failure {:?}", error);
+            Metrics::inc(&METRICS.fetch_failure);
             node.pending_request_file.remove(&request_id);
-            node.bridge.connect_blocking()?;
-            node.bridge.send(Instruction::respond_fetch(None)).await?;
+            node.outbox.send(Instruction::respond_fetch(None)).await?;
         }
         RequestResponseEvent::InboundFailure { error, .. } => {
             error!("Request response inbound failure {:?}", error);
@@ -68,63 +101,507 @@ pub async fn handle_request_response(
     Ok(())
 }
 
+/// The gistit a plain request for `key` is answered with, if any. Peers can still find gistits we
+/// revoked, burnt or evicted through their provider records until those expire
+fn requested<'a>(to_provide: &'a HashMap<Key, Gistit>, key: &Key) -> Option<&'a Gistit> {
+    let file = match to_provide.get(key) {
+        Some(file) => file,
+        None => {
+            warn!("Refusing request for {:?}, it's not provided", key);
+            return None;
+        }
+    };
+    // Only served through the chunk protocol, where reads are counted and the requester agrees to
+    // burn it
+    if file.read_limit().is_some() {
+        warn!("Refusing request for {:?}, it has a read limit", key);
+        return None;
+    }
+    Some(file)
+}
+
+#[allow(clippy::cast_possible_truncation)]
+pub async fn handle_chunk(
+    node: &mut Node,
+    event: RequestResponseEvent<ChunkRequest, ChunkResponse>,
+) -> Result<()> {
+    match event {
+        RequestResponseEvent::Message { peer, message } => match message {
+            RequestResponseMessage::Request {
+                request, channel, ..
+            } => {
+                if node.acl.is_denied(&peer) {
+                    warn!("Refusing chunk request from {:?}, denied by acl", peer);
+                    return Ok(());
+                }
+
+                let response = match request {
+                    ChunkRequest::Manifest(hash, burn_after_read) => {
+                        // A fetch is rated by it's manifest request, not by each chunk
+                        if !node.peer_rate.allow(peer, Instant::now()) {
+                            warn!("Refusing request from {:?}, over the request rate", peer);
+                            Metrics::inc(&METRICS.rate_limited);
+                            return Ok(());
+                        }
+
+                        if let Ok(hash) = str::from_utf8(&hash) {
+                            node.storage.touch(hash)?;
+                        }
+                        let key = Key::new(&hash);
+                        match node.to_provide.get(&key) {
+                            Some(gistit) if gistit.burn_after_read && !burn_after_read => {
+                                ChunkResponse::BurnAfterRead
+                            }
+                            Some(gistit) => {
+                                if gistit.read_limit().is_some() {
+                                    node.reading.entry((key, peer)).or_default();
+                                }
+                                node.notifier.fetched(&gistit.hash, &peer, Instant::now());
+                                ChunkResponse::Manifest(chunk::manifest(&gistit.encode_to_vec()))
+                            }
+                            None => ChunkResponse::NotFound,
+                        }
+                    }
+                    ChunkRequest::Chunk(hash, index) => {
+                        let key = Key::new(&hash);
+                        let reader = (key.clone(), peer);
+                        // Gistits with a read limit are only served after a manifest request,
+                        // agreeing to burn it if it's deleted once read
+                        let gistit = node.to_provide.get(&key).filter(|gistit| {
+                            gistit.read_limit().is_none() || node.reading.contains_key(&reader)
+                        });
+                        let read_limit = gistit.and_then(Gistit::read_limit);
+                        let bytes = gistit.map(Message::encode_to_vec).unwrap_or_default();
+
+                        match chunk::chunk(&bytes, index) {
+                            Some(data) => {
+                                Metrics::add(&METRICS.bytes_served, data.len() as u64);
+                                let response = ChunkResponse::Chunk(data.to_vec());
+                                if let (Some(limit), Some(served)) =
+                                    (read_limit, node.reading.get_mut(&reader))
+                                {
+                                    served.insert(index);
+                                    if served.len() == bytes.chunks(chunk::CHUNK_SIZE).count() {
+                                        info!("Served every chunk of {:?} to {:?}", key, peer);
+                                        node.reading.remove(&reader);
+                                        node.count_read(&key, limit);
+                                    }
+                                }
+                                response
+                            }
+                            None => ChunkResponse::NotFound,
+                        }
+                    }
+                    ChunkRequest::Head(hash) => {
+                        if !node.peer_rate.allow(peer, Instant::now()) {
+                            warn!("Refusing request from {:?}, over the request rate", peer);
+                            Metrics::inc(&METRICS.rate_limited);
+                            return Ok(());
+                        }
+
+                        let key = Key::new(&hash);
+                        node.to_provide
+                            .get(&key)
+                            .map_or(ChunkResponse::NotFound, |gistit| {
+                                let mut head = Head::from_gistit(gistit, None);
+                                head.reads_left = gistit.read_limit().map(|limit| {
+                                    limit.saturating_sub(
+                                        node.reads.get(&key).copied().unwrap_or_default(),
+                                    )
+                                });
+                                ChunkResponse::Head(head)
+                            })
+                    }
+                };
+
+                if node
+                    .swarm
+                    .behaviour_mut()
+                    .chunk
+                    .send_response(channel, response)
+                    .is_err()
+                {
+                    warn!("Failed to answer chunk request from {:?}", peer);
+                }
+            }
+            RequestResponseMessage::Response {
+                request_id,
+                response,
+            } => {
+                if let Some(key) = node.pending_head_request.remove(&request_id) {
+                    let head = match response {
+                        ChunkResponse::Head(head) => Some(head),
+                        _ => None,
+                    };
+                    return node.head_received(&key, head).await;
+                }
+
+                let key = match node.pending_chunk.remove(&request_id) {
+                    Some(key) => key,
+                    None => return Ok(()),
+                };
+                let download = match node.downloads.get_mut(&key) {
+                    Some(download) => download,
+                    None => return Ok(()),
+                };
+                let elapsed = download
+                    .requested(request_id)
+                    .map_or(Duration::ZERO, |(_, elapsed)| elapsed);
+
+                match response {
+                    ChunkResponse::Manifest(manifest) => {
+                        debug!("Manifest of {} chunks from {:?}", manifest.len(), peer);
+                        if download.manifest_received(request_id, manifest) {
+                            node.reputation.answered(peer, elapsed);
+                        } else {
+                            warn!("Invalid manifest from {:?}", peer);
+                            node.reputation.failed(peer);
+                        }
+                    }
+                    ChunkResponse::Chunk(data) => {
+                        let size = data.len();
+                        Metrics::add(&METRICS.bytes_received, size as u64);
+                        if download.chunk_received(request_id, data).is_some() {
+                            let (done, total) = download.progress();
+                            debug!("Chunk {}/{} of {:?} from {:?}", done, total, key, peer);
+                            node.reputation.transferred(peer, size, elapsed);
+                            node.report_progress(&key, Stage::Downloading, Some(peer));
+                        } else {
+                            node.reputation.failed(peer);
+                        }
+                    }
+                    ChunkResponse::NotFound | ChunkResponse::Head(_) => {
+                        debug!("{:?} is not providing {:?}", peer, key);
+                        download.request_failed(request_id);
+                        node.reputation.failed(peer);
+                    }
+                    ChunkResponse::BurnAfterRead => return node.burn_refused(&key).await,
+                }
+
+                node.schedule_download(&key).await?;
+            }
+        },
+        RequestResponseEvent::OutboundFailure {
+            peer,
+            request_id,
+            error,
+        } => {
+            warn!("Chunk request to {:?} failed {:?}", peer, error);
+            if let Some(key) = node.pending_head_request.remove(&request_id) {
+                return node.head_received(&key, None).await;
+            }
+            if let Some(key) = node.pending_chunk.remove(&request_id) {
+                if let Some(download) = node.downloads.get_mut(&key) {
+                    download.request_failed(request_id);
+                }
+                node.reputation.failed(peer);
+                node.schedule_download(&key).await?;
+            }
+        }
+        RequestResponseEvent::InboundFailure { error, .. } => {
+            error!("Chunk inbound failure {:?}", error);
+        }
+        RequestResponseEvent::ResponseSent { .. } => (),
+    }
+    Ok(())
+}
+
+pub async fn handle_push(
+    node: &mut Node,
+    event: RequestResponseEvent<PushRequest, PushResponse>,
+) -> Result<()> {
+    match event {
+        RequestResponseEvent::Message { peer, message } => match message {
+            RequestResponseMessage::Request {
+                request: PushRequest(gistit),
+                channel,
+                ..
+            } => {
+                if node.acl.is_denied(&peer) {
+                    warn!("Refusing push from {:?}, denied by acl", peer);
+                    return Ok(());
+                }
+                if !node.peer_rate.allow(peer, Instant::now()) {
+                    warn!("Refusing push from {:?}, over the request rate", peer);
+                    Metrics::inc(&METRICS.rate_limited);
+                    return Ok(());
+                }
+                Metrics::add(&METRICS.bytes_received, gistit.encoded_len() as u64);
+
+                // Held in the inbox until the user accepts it
+                let inbox_id = node.inbox.insert(peer, gistit.clone());
+                match inbox_id {
+                    Some(id) => {
+                        info!(
+                            "Received gistit {} pushed by {:?}, inbox id {}",
+                            gistit.hash, peer, id
+                        );
+                        node.notifier.pushed(&gistit.hash, &peer, id);
+                    }
+                    None => warn!("Refusing push from {:?}, inbox is full", peer),
+                }
+                if node
+                    .swarm
+                    .behaviour_mut()
+                    .push
+                    .send_response(channel, PushResponse(inbox_id.is_some()))
+                    .is_err()
+                {
+                    warn!("Failed to acknowledge push from {:?}", peer);
+                }
+
+                // Notify the client if there's one listening
+                if let (Some(id), true) = (inbox_id, node.bridge.alive()) {
+                    node.outbox.push_event(Instruction::respond_incoming(
+                        gistit,
+                        peer.to_string(),
+                        id,
+                    ));
+                }
+            }
+            RequestResponseMessage::Response {
+                request_id,
+                response: PushResponse(delivered),
+            } => {
+                info!("Push to {:?} answered, delivered: {}", peer, delivered);
+                if node.pending_push.remove(&request_id) {
+                    node.outbox
+                        .send(Instruction::respond_push(delivered))
+                        .await?;
+                }
+            }
+        },
+        RequestResponseEvent::OutboundFailure {
+            request_id, error, ..
+        } => {
+            error!("Push outbound failure {:?}", error);
+            if node.pending_push.remove(&request_id) {
+                node.outbox.send(Instruction::respond_push(false)).await?;
+            }
+        }
+        RequestResponseEvent::InboundFailure { error, .. } => {
+            error!("Push inbound failure {:?}", error);
+        }
+        RequestResponseEvent::ResponseSent { .. } => (),
+    }
+    Ok(())
+}
+
 pub async fn handle_kademlia(node: &mut Node, event: KademliaEvent) -> Result<()> {
     match event {
-        KademliaEvent::OutboundQueryCompleted {
+        KademliaEvent::OutboundQueryProgressed {
             id,
             result: QueryResult::StartProviding(maybe_provided),
             ..
         } => {
-            node.pending_start_providing.remove(&id);
-            node.bridge.connect_blocking()?;
+            if let Some(tx) = node.pending_gateway_provide.remove(&id) {
+                let provided = match maybe_provided {
+                    Ok(provider) => {
+                        Metrics::inc(&METRICS.provide_success);
+                        node.to_provide.get(&provider.key).cloned()
+                    }
+                    Err(provider) => {
+                        error!("Kademlia start providing failed: {:?}", provider);
+                        Metrics::inc(&METRICS.provide_failure);
+                        node.to_provide.remove(provider.key());
+                        None
+                    }
+                };
+                let _ = tx.send(provided);
+                return Ok(());
+            }
+
+            // Stored gistits are provided on start, with no client waiting
+            if !node.pending_start_providing.remove(&id) {
+                if let Err(err) = maybe_provided {
+                    warn!("Kademlia start providing failed: {:?}", err);
+                }
+                return Ok(());
+            }
 
             match maybe_provided {
                 Ok(provider) => {
                     info!("Kademlia start providing: {:?}", provider);
+                    Metrics::inc(&METRICS.provide_success);
+                    node.announce(&provider.key);
                     let hash = str::from_utf8(&provider.key.to_vec())
                         .expect("hash format to be valid utf8")
                         .to_owned();
-                    node.bridge
+                    node.outbox
                         .send(Instruction::respond_provide(Some(hash)))
                         .await?;
                 }
                 Err(provider) => {
                     error!("Kademlia start providing failed: {:?}", provider);
+                    Metrics::inc(&METRICS.provide_failure);
                     node.to_provide.remove(provider.key());
-                    node.bridge.send(Instruction::respond_provide(None)).await?;
+                    node.to_announce.remove(provider.key());
+                    node.outbox.send(Instruction::respond_provide(None)).await?;
                 }
             }
             Ok(())
         }
-        KademliaEvent::OutboundQueryCompleted {
+        KademliaEvent::OutboundQueryProgressed {
             id,
             result: QueryResult::GetProviders(maybe_providers),
             ..
         } => {
             info!("Kademlia get providers: {:?}", maybe_providers);
-            node.pending_get_providers.remove(&id);
-            let mut failed = false;
+            // Results come in steps, only the first one finding providers is used
+            let key = match node.pending_get_providers.remove(&id) {
+                Some(key) => key,
+                None => return Ok(()),
+            };
+            if let Some(mut query) = node.swarm.behaviour_mut().kademlia.query_mut(&id) {
+                query.finish();
+            }
 
-            match maybe_providers {
-                Ok(GetProvidersOk { key, providers, .. }) => {
+            let failed = match maybe_providers {
+                Ok(GetProvidersOk::FoundProviders { providers, .. }) => {
                     // Finding zero providers is also an error
                     if providers.is_empty() {
-                        failed = true;
+                        Some(key)
                     } else {
-                        node.to_request.push((key, providers));
+                        // Heads are asked for directly, no need to download the content. Nobody
+                        // waits for it anymore if the client canceled
+                        if node.pending_head.contains_key(&key) {
+                            node.request_head(&key, &providers);
+                        }
+                        if node.wants_content(&key) {
+                            node.to_request.push((key, providers));
+                        }
+                        None
                     }
                 }
-                Err(GetProvidersError::Timeout { key, .. }) => {
-                    failed = true;
+                Ok(GetProvidersOk::FinishedWithNoAdditionalRecord { .. })
+                | Err(GetProvidersError::Timeout { .. }) => {
                     error!("No providers for {:?}", key);
+                    Some(key)
+                }
+            };
+
+            if let Some(key) = failed {
+                Metrics::inc(&METRICS.fetch_failure);
+                node.answer_gateway_fetch(&key, None);
+                if node.pending_head.remove(&key).is_some() {
+                    node.outbox.send(Instruction::respond_head(None)).await?;
+                }
+                if node.pending_receive_file.remove(&key) {
+                    node.outbox.send(Instruction::respond_fetch(None)).await?;
+                }
+                if node.pending_pin.remove(&key) {
+                    node.outbox.send(Instruction::respond_pin(false)).await?;
+                }
+            }
+
+            Ok(())
+        }
+        KademliaEvent::OutboundQueryProgressed {
+            id,
+            result: QueryResult::PutRecord(maybe_put),
+            ..
+        } => {
+            // Republished names have no client waiting
+            if node.pending_publish_name.remove(&id) {
+                if let Err(ref err) = maybe_put {
+                    error!("Kademlia put record failed: {:?}", err);
                 }
+                node.outbox
+                    .send(Instruction::respond_publish_name(maybe_put.is_ok()))
+                    .await?;
+            }
+            Ok(())
+        }
+        KademliaEvent::OutboundQueryProgressed {
+            id,
+            result: QueryResult::GetRecord(maybe_record),
+            ..
+        } => {
+            if !node.pending_resolve_name.contains(&id) {
+                return Ok(());
             }
 
-            if failed {
-                node.bridge.connect_blocking()?;
-                node.bridge.send(Instruction::respond_fetch(None)).await?;
+            let newest = match maybe_record {
+                // Invalid records are skipped, the query goes on until it finds a valid one
+                Ok(GetRecordOk::FoundRecord(peer_record)) => {
+                    match name::verify(&peer_record.record.key, &peer_record.record.value) {
+                        Some(found) => Some(found),
+                        None => return Ok(()),
+                    }
+                }
+                Ok(GetRecordOk::FinishedWithNoAdditionalRecord { .. }) => None,
+                Err(err) => {
+                    error!("Kademlia get record failed: {:?}", err);
+                    None
+                }
+            };
+            node.pending_resolve_name.remove(&id);
+            if let Some(mut query) = node.swarm.behaviour_mut().kademlia.query_mut(&id) {
+                query.finish();
             }
 
+            let response = match newest {
+                Some((record, publisher)) => {
+                    Instruction::respond_resolve_name(Some(record.hash), publisher.to_string())
+                }
+                None => Instruction::respond_resolve_name(None, String::new()),
+            };
+            node.outbox.send(response).await?;
+            Ok(())
+        }
+        KademliaEvent::InboundRequest {
+            request:
+                InboundRequest::AddProvider {
+                    record: Some(record),
+                },
+        } => {
+            // Inserts are filtered for name records, provider records are always kept
+            if let Err(err) = node
+                .swarm
+                .behaviour_mut()
+                .kademlia
+                .store_mut()
+                .add_provider(record)
+            {
+                warn!("Failed to store provider record: {:?}", err);
+            }
+            Ok(())
+        }
+        KademliaEvent::InboundRequest {
+            request:
+                InboundRequest::PutRecord {
+                    source,
+                    record: Some(record),
+                    ..
+                },
+        } => {
+            // Only gistit names are stored, and only by whoever published them first
+            let new = match name::verify(&record.key, &record.value) {
+                Some((new, _)) if name::is_name_key(&record.key) => new,
+                _ => {
+                    warn!("Refusing invalid record from {:?}", source);
+                    return Ok(());
+                }
+            };
+
+            let store = node.swarm.behaviour_mut().kademlia.store_mut();
+            let accepted = match store.get(&record.key) {
+                Some(existing) => name::verify(&existing.key, &existing.value)
+                    .map_or(true, |(existing, _)| name::supersedes(&new, &existing)),
+                None => true,
+            };
+
+            if accepted {
+                debug!("Storing name record {} from {:?}", new.name, source);
+                if let Err(err) = store.put(record) {
+                    warn!("Failed to store name record: {:?}", err);
+                }
+            } else {
+                warn!(
+                    "Refusing stale or foreign record for {} from {:?}",
+                    new.name, source
+                );
+            }
             Ok(())
         }
         _ => Ok(()),
@@ -140,28 +617,28 @@ pub fn handle_identify(node: &mut Node, event: IdentifyEvent) -> Result<()> {
                 protocols,
                 ..
             },
+        ..
     } = event
     {
         debug!("Identify: {:?}, protocols: {:?}", listen_addrs, protocols);
-        if protocols.iter().any(|p| p.as_bytes() == KADEMLIA_PROTO) {
-            for addr in &listen_addrs {
+        if protocols.contains(&kad::PROTOCOL_NAME) {
+            // Dialed in this order, QUIC connects in fewer round trips
+            for addr in quic_first(&listen_addrs) {
                 node.swarm
                     .behaviour_mut()
                     .kademlia
-                    .add_address(&peer_id, addr.clone());
+                    .add_address(&peer_id, addr);
             }
         }
 
-        if protocols.iter().any(|p| p.as_bytes() == RELAY_HOP_PROTO) {
+        if protocols.contains(&RELAY_HOP_PROTO) {
             for addr in listen_addrs {
                 // Don't attempt to relay over the relay
                 if addr.iter().any(|t| matches!(t, Protocol::P2pCircuit)) {
                     continue;
                 }
 
-                let peer = addr
-                    .with(Protocol::P2p(peer_id.into()))
-                    .with(Protocol::P2pCircuit);
+                let peer = addr.with(Protocol::P2p(peer_id)).with(Protocol::P2pCircuit);
 
                 if node.relays.contains(&peer) {
                     continue;
@@ -176,6 +653,141 @@ pub fn handle_identify(node: &mut Node, event: IdentifyEvent) -> Result<()> {
     Ok(())
 }
 
-const KADEMLIA_PROTO: &[u8] = b"/ipfs/kad/1.0.0";
-const RELAY_HOP_PROTO: &[u8] = b"/libp2p/circuit/relay/0.2.0/hop";
-// const RELAY_STOP_PROTO: &[u8] = b"/libp2p/circuit/relay/0.2.0/stop";
+/// `addresses` with the QUIC ones first, keeping their order otherwise
+fn quic_first(addresses: &[Multiaddr]) -> Vec<Multiaddr> {
+    let (mut quic, other): (Vec<_>, Vec<_>) = addresses
+        .iter()
+        .cloned()
+        .partition(|address| address.iter().any(|p| p == Protocol::QuicV1));
+    quic.extend(other);
+    quic
+}
+
+pub async fn handle_gossipsub(node: &mut Node, event: GossipsubEvent) -> Result<()> {
+    match event {
+        GossipsubEvent::Message { message, .. } => {
+            if !node.subscriptions.contains(&message.topic) {
+                return Ok(());
+            }
+
+            let announcement = match Announcement::decode(&*message.data) {
+                Ok(announcement) => announcement,
+                Err(err) => {
+                    warn!("Invalid announcement on {}: {:?}", message.topic, err);
+                    return Ok(());
+                }
+            };
+
+            // The subscribed client is gone, stop listening for it
+            if !node.bridge.alive() {
+                node.unsubscribe_all();
+                return Ok(());
+            }
+
+            node.outbox.push_event(Instruction::respond_event(
+                message.topic.into_string(),
+                announcement,
+            ));
+        }
+        event => debug!("Gossipsub: {:?}", event),
+    }
+    Ok(())
+}
+
+pub fn handle_autonat(node: &mut Node, event: AutonatEvent) {
+    match event {
+        AutonatEvent::StatusChanged { old, new } => {
+            info!("Reachability changed from {:?} to {:?}", old, new);
+            node.notifier.reachability_changed(&old, &new);
+        }
+        event => debug!("Autonat: {:?}", event),
+    }
+}
+
+pub fn handle_relay_client(node: &mut Node, event: ClientEvent) {
+    match event {
+        ClientEvent::ReservationReqAccepted {
+            relay_peer_id,
+            renewal,
+            ..
+        } => {
+            let action = if renewal { "renewed" } else { "accepted" };
+            info!("Relay reservation {} by {:?}", action, relay_peer_id);
+            node.relay_reservations.insert(relay_peer_id);
+        }
+        event => debug!("Relay client: {:?}", event),
+    }
+}
+
+pub fn handle_dcutr(event: DcutrEvent) {
+    match event.result {
+        Ok(_) => {
+            info!(
+                "Hole punch succeeded, direct connection to {:?}",
+                event.remote_peer_id
+            );
+        }
+        Err(error) => warn!(
+            "Hole punch to {:?} failed: {:?}",
+            event.remote_peer_id, error
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_after_revoke() {
+        let key = Key::new(&vec![b'a'; 64]);
+        let mut to_provide = HashMap::new();
+        to_provide.insert(key.clone(), Gistit::default());
+        assert!(requested(&to_provide, &key).is_some());
+
+        // What `Node::revoke` leaves behind, while the provider record outlives it
+        to_provide.remove(&key);
+        assert!(requested(&to_provide, &key).is_none());
+    }
+
+    #[test]
+    fn quic_addresses_first() {
+        let addresses: Vec<Multiaddr> = [
+            "/ip4/10.0.0.1/tcp/4001",
+            "/ip4/10.0.0.1/udp/4001/quic-v1",
+            "/ip4/10.0.0.1/tcp/4002/ws",
+            "/ip6/::1/udp/4001/quic-v1",
+        ]
+        .iter()
+        .map(|address| address.parse().unwrap())
+        .collect();
+
+        let sorted: Vec<String> = quic_first(&addresses)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            sorted,
+            vec![
+                "/ip4/10.0.0.1/udp/4001/quic-v1",
+                "/ip6/::1/udp/4001/quic-v1",
+                "/ip4/10.0.0.1/tcp/4001",
+                "/ip4/10.0.0.1/tcp/4002/ws",
+            ]
+        );
+    }
+
+    #[test]
+    fn request_with_read_limit() {
+        let key = Key::new(&vec![b'b'; 64]);
+        let mut to_provide = HashMap::new();
+        to_provide.insert(
+            key.clone(),
+            Gistit {
+                burn_after_read: true,
+                ..Gistit::default()
+            },
+        );
+        assert!(requested(&to_provide, &key).is_none());
+    }
+}
@@ -0,0 +1,238 @@
+//! The gateway module
+//!
+//! Opt-in HTTP gateway (`--http <addr>`) for web frontends and scripts that can't speak the IPC
+//! protocol. Gistits travel protobuf encoded, like they do to and from the server:
+//!
+//! - `GET /gistit/<hash>` fetches a gistit, from this node or the network
+//! - `GET /raw/<hash>` fetches it the same way, answering with just the file content as plain
+//!   text, for `curl .../raw/<hash> | sh`
+//! - `POST /gistit` hosts the gistit in the body, answering with it once it's provided
+//! - `GET /status` answers with the node status as json
+//!
+//! Requests are handed to the event loop through a channel, this module only speaks HTTP. Like
+//! the metrics endpoint it's deliberately minimal, put a reverse proxy in front of it for anything
+//! else, including TLS.
+#![allow(clippy::missing_errors_doc)]
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use log::{debug, info};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, oneshot};
+
+use gistit_proto::prost::Message;
+use gistit_proto::Gistit;
+
+use crate::Result;
+
+/// How long a request waits on the network before giving up
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Request heads bigger than this are refused
+const MAX_HEAD_SIZE: usize = 8 * 1024;
+
+/// Gistit bodies bigger than this are refused, files are capped at 50kb
+const MAX_BODY_SIZE: usize = 128 * 1024;
+
+/// A gateway request, answered by the event loop through it's sender
+#[derive(Debug)]
+pub enum Request {
+    Status(oneshot::Sender<serde_json::Value>),
+    /// Answered with `None` if it's invalid or couldn't be provided
+    Provide(Gistit, oneshot::Sender<Option<Gistit>>),
+    /// Answered with `None` if no provider was found
+    Fetch(String, oneshot::Sender<Option<Gistit>>),
+}
+
+/// Serves the gateway on `addr` forever, handing requests to `to_node`
+pub async fn serve(addr: SocketAddr, to_node: mpsc::Sender<Request>) -> Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    info!("Serving http gateway on http://{}", addr);
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let to_node = to_node.clone();
+        tokio::spawn(async move {
+            if let Err(err) = respond(stream, &to_node).await {
+                debug!("Gateway request from {} failed: {:?}", peer, err);
+            }
+        });
+    }
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    const fn empty(status: &'static str) -> Self {
+        Self {
+            status,
+            content_type: "text/plain",
+            body: Vec::new(),
+        }
+    }
+
+    fn gistit(gistit: &Gistit) -> Self {
+        Self {
+            status: "200 OK",
+            content_type: "application/x-protobuf",
+            body: gistit.encode_to_vec(),
+        }
+    }
+
+    /// Content of the first inner file. Encrypted ones are refused, there's nothing useful to pipe
+    fn raw(gistit: Gistit) -> Self {
+        match gistit.inner.into_iter().next() {
+            Some(inner) if !inner.encrypted => Self {
+                status: "200 OK",
+                content_type: "text/plain; charset=utf-8",
+                body: inner.data.into_bytes(),
+            },
+            Some(_) => Self::empty("403 Forbidden"),
+            None => Self::empty("404 Not Found"),
+        }
+    }
+
+    fn json(value: &serde_json::Value) -> Self {
+        Self {
+            status: "200 OK",
+            content_type: "application/json",
+            body: value.to_string().into_bytes(),
+        }
+    }
+}
+
+async fn respond(mut stream: TcpStream, to_node: &mpsc::Sender<Request>) -> Result<()> {
+    let response = match read_request(&mut stream).await? {
+        Some((method, path, body)) => route(&method, &path, body, to_node).await,
+        None => Response::empty("400 Bad Request"),
+    };
+
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nAccess-Control-Allow-Origin: *\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&response.body).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+async fn route(
+    method: &str,
+    path: &str,
+    body: Vec<u8>,
+    to_node: &mpsc::Sender<Request>,
+) -> Response {
+    match (method, path) {
+        ("GET", "/status") => match ask(to_node, Request::Status).await {
+            Some(status) => Response::json(&status),
+            None => Response::empty("503 Service Unavailable"),
+        },
+        ("POST", "/gistit") => match Gistit::from_bytes(&body) {
+            Ok(gistit) => match ask(to_node, |tx| Request::Provide(gistit, tx)).await {
+                Some(Some(gistit)) => Response::gistit(&gistit),
+                Some(None) => Response::empty("422 Unprocessable Entity"),
+                None => Response::empty("504 Gateway Timeout"),
+            },
+            Err(_) => Response::empty("400 Bad Request"),
+        },
+        ("GET", path) if path.starts_with("/gistit/") || path.starts_with("/raw/") => {
+            let (raw, hash) = match path.strip_prefix("/raw/") {
+                Some(hash) => (true, hash.to_owned()),
+                None => (false, path.trim_start_matches("/gistit/").to_owned()),
+            };
+            if !is_hash(&hash) {
+                return Response::empty("400 Bad Request");
+            }
+            match ask(to_node, |tx| Request::Fetch(hash, tx)).await {
+                Some(Some(gistit)) if raw => Response::raw(gistit),
+                Some(Some(gistit)) => Response::gistit(&gistit),
+                Some(None) => Response::empty("404 Not Found"),
+                None => Response::empty("504 Gateway Timeout"),
+            }
+        }
+        (_, "/status" | "/gistit") => Response::empty("405 Method Not Allowed"),
+        _ => Response::empty("404 Not Found"),
+    }
+}
+
+/// Hands a request to the event loop and waits for the answer. `None` if the node went away or
+/// took too long
+async fn ask<T>(
+    to_node: &mpsc::Sender<Request>,
+    request: impl FnOnce(oneshot::Sender<T>) -> Request,
+) -> Option<T> {
+    let (tx, rx) = oneshot::channel();
+    to_node.send(request(tx)).await.ok()?;
+
+    tokio::time::timeout(REQUEST_TIMEOUT, rx).await.ok()?.ok()
+}
+
+/// Reads the request line and body, `None` if the request is malformed or too big
+async fn read_request(stream: &mut TcpStream) -> Result<Option<(String, String, Vec<u8>)>> {
+    let mut buf = Vec::with_capacity(1024);
+    let mut chunk = [0_u8; 1024];
+
+    let head_end = loop {
+        if let Some(end) = find_head_end(&buf) {
+            break end;
+        }
+        if buf.len() > MAX_HEAD_SIZE {
+            return Ok(None);
+        }
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            return Ok(None);
+        }
+        buf.extend_from_slice(&chunk[..read]);
+    };
+
+    let head = match std::str::from_utf8(&buf[..head_end]) {
+        Ok(head) => head,
+        Err(_) => return Ok(None),
+    };
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let (method, path) = match (request_line.next(), request_line.next()) {
+        (Some(method), Some(path)) => (method.to_owned(), path.to_owned()),
+        _ => return Ok(None),
+    };
+    let content_length = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .map_or(Some(0), |(_, value)| value.trim().parse::<usize>().ok());
+
+    let content_length = match content_length {
+        Some(length) if length <= MAX_BODY_SIZE => length,
+        _ => return Ok(None),
+    };
+
+    let mut body = buf.split_off(head_end + 4);
+    while body.len() < content_length {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            return Ok(None);
+        }
+        body.extend_from_slice(&chunk[..read]);
+    }
+    body.truncate(content_length);
+
+    Ok(Some((method, path, body)))
+}
+
+fn find_head_end(buf: &[u8]) -> Option<usize> {
+    buf.windows(4).position(|window| window == b"\r\n\r\n")
+}
+
+/// Hashes are lowercase hex sha256 digests
+fn is_hash(hash: &str) -> bool {
+    hash.len() == 64 && hash.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
@@ -0,0 +1,234 @@
+//! The identity module
+//!
+//! The node keypair is generated on first run and kept encrypted at rest in [`IDENTITY_FILE_NAME`]
+//! inside the config directory, so the peer id stays the same across restarts. The encryption key
+//! is derived from [`GISTIT_IDENTITY_PASSPHRASE_VAR`] when set, otherwise it's a random key kept
+//! next to the identity with owner only permissions.
+//!
+//! Exports use the IPFS `config` key format, the same read by `--config-file`, so an identity can be
+//! moved between machines or to and from an IPFS node.
+#![allow(clippy::missing_errors_doc)]
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, NewAead};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use libp2p::core::PeerId;
+use libp2p::identity::Keypair;
+use log::{debug, info};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use gistit_project::env::GISTIT_IDENTITY_PASSPHRASE_VAR;
+
+use crate::config::NodeKey;
+use crate::{Error, Result};
+
+/// The encrypted identity file name inside the config directory
+pub const IDENTITY_FILE_NAME: &str = "identity";
+
+/// Random encryption key used when no passphrase is set
+pub const IDENTITY_KEY_FILE_NAME: &str = "identity.key";
+
+/// Plaintext key file written by older versions, migrated on first load
+const LEGACY_FILE_NAME: &str = "node-config";
+
+const IDENTITY_VERSION: u32 = 1;
+
+const SALT_LENGTH: usize = 16;
+
+const NONCE_LENGTH: usize = 12;
+
+#[derive(Serialize, Deserialize)]
+struct Sealed {
+    version: u32,
+    /// Whether the key is derived from a passphrase or read from [`IDENTITY_KEY_FILE_NAME`]
+    passphrase: bool,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// Loads the node keypair, generating and storing a new one on first run
+pub fn load_or_generate(config_path: &Path) -> Result<Keypair> {
+    let path = config_path.join(IDENTITY_FILE_NAME);
+    if fs::metadata(&path).is_ok() {
+        debug!("Using existing identity");
+        return load(config_path);
+    }
+
+    let legacy = config_path.join(LEGACY_FILE_NAME);
+    let keypair = if fs::metadata(&legacy).is_ok() {
+        info!("Migrating plaintext identity to an encrypted one");
+        let keypair = from_node_key(&Zeroizing::new(NodeKey::from_file(&legacy)?))?;
+        store(config_path, &keypair)?;
+        fs::remove_file(legacy)?;
+        keypair
+    } else {
+        debug!("Generating new node key material");
+        let keypair = generate();
+        store(config_path, &keypair)?;
+        keypair
+    };
+
+    Ok(keypair)
+}
+
+#[must_use]
+pub fn generate() -> Keypair {
+    Keypair::generate_ed25519()
+}
+
+/// Decrypts the stored identity
+pub fn load(config_path: &Path) -> Result<Keypair> {
+    let sealed: Sealed =
+        serde_json::from_str(&fs::read_to_string(config_path.join(IDENTITY_FILE_NAME))?)?;
+    if sealed.version != IDENTITY_VERSION {
+        return Err(Error::Keystore("unsupported identity file version"));
+    }
+
+    let salt = base64::decode(&sealed.salt)?;
+    let nonce = base64::decode(&sealed.nonce)?;
+    if nonce.len() != NONCE_LENGTH {
+        return Err(Error::Keystore("corrupted identity file"));
+    }
+
+    let key = encryption_key(config_path, sealed.passphrase, &salt)?;
+    let plaintext = Zeroizing::new(
+        ChaCha20Poly1305::new(Key::from_slice(&*key))
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                &*base64::decode(&sealed.ciphertext)?,
+            )
+            .map_err(|_| {
+                Error::Keystore("failed to decrypt identity, is the passphrase right?")
+            })?,
+    );
+
+    Ok(Keypair::from_protobuf_encoding(&plaintext)?)
+}
+
+/// Encrypts and stores `keypair`, replacing the current identity
+pub fn store(config_path: &Path, keypair: &Keypair) -> Result<()> {
+    let passphrase = std::env::var(GISTIT_IDENTITY_PASSPHRASE_VAR).is_ok();
+    let mut salt = [0_u8; SALT_LENGTH];
+    let mut nonce = [0_u8; NONCE_LENGTH];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut nonce);
+
+    let key = encryption_key(config_path, passphrase, &salt)?;
+    let plaintext = Zeroizing::new(keypair.to_protobuf_encoding()?);
+    let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&*key))
+        .encrypt(Nonce::from_slice(&nonce), plaintext.as_slice())
+        .map_err(|_| Error::Keystore("failed to encrypt identity"))?;
+
+    let sealed = Sealed {
+        version: IDENTITY_VERSION,
+        passphrase,
+        salt: base64::encode(salt),
+        nonce: base64::encode(nonce),
+        ciphertext: base64::encode(ciphertext),
+    };
+    write_private(
+        &config_path.join(IDENTITY_FILE_NAME),
+        serde_json::to_string(&sealed)?.as_bytes(),
+    )
+}
+
+/// Replaces the stored identity with a new one, returning the new peer id
+pub fn rotate(config_path: &Path) -> Result<PeerId> {
+    let keypair = generate();
+    store(config_path, &keypair)?;
+    Ok(keypair.public().into())
+}
+
+/// The stored identity in the IPFS `config` key format
+pub fn export(config_path: &Path) -> Result<Zeroizing<String>> {
+    let keypair = load_or_generate(config_path)?;
+    let node_key = Zeroizing::new(NodeKey::from_key_material(
+        keypair.public().into(),
+        &keypair,
+    )?);
+    Ok(Zeroizing::new(serde_json::to_string_pretty(&*node_key)?))
+}
+
+/// Replaces the stored identity with the one in `file`, in the IPFS `config` key format
+pub fn import(config_path: &Path, file: &Path) -> Result<PeerId> {
+    let keypair = from_node_key(&Zeroizing::new(NodeKey::from_file(file)?))?;
+    store(config_path, &keypair)?;
+    Ok(keypair.public().into())
+}
+
+/// Decodes the key material of `node_key`, checking it matches it's peer id
+pub fn from_node_key(node_key: &NodeKey) -> Result<Keypair> {
+    let keypair = Keypair::from_protobuf_encoding(&Zeroizing::new(base64::decode(
+        node_key.identity.priv_key.as_bytes(),
+    )?))?;
+
+    let peer_id: PeerId = node_key
+        .identity
+        .peer_id
+        .parse()
+        .map_err(|_| Error::Parse("failed to parse config peer id"))?;
+    if peer_id != PeerId::from(keypair.public()) {
+        return Err(Error::Keystore(
+            "peer id doesn't match the one derived from the private key",
+        ));
+    }
+
+    Ok(keypair)
+}
+
+fn encryption_key(
+    config_path: &Path,
+    passphrase: bool,
+    salt: &[u8],
+) -> Result<Zeroizing<[u8; 32]>> {
+    let mut key = Zeroizing::new([0_u8; 32]);
+
+    if passphrase {
+        let passphrase = Zeroizing::new(std::env::var(GISTIT_IDENTITY_PASSPHRASE_VAR).map_err(
+            |_| Error::Keystore("identity is passphrase protected, set GISTIT_IDENTITY_PASSPHRASE"),
+        )?);
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), salt, &mut *key)
+            .map_err(|_| Error::Keystore("failed to derive identity key"))?;
+    } else {
+        let path = key_path(config_path);
+        if fs::metadata(&path).is_ok() {
+            let stored = Zeroizing::new(fs::read(&path)?);
+            if stored.len() != key.len() {
+                return Err(Error::Keystore("corrupted identity key file"));
+            }
+            key.copy_from_slice(&stored);
+        } else {
+            OsRng.fill_bytes(&mut *key);
+            write_private(&path, &*key)?;
+        }
+    }
+
+    Ok(key)
+}
+
+fn key_path(config_path: &Path) -> PathBuf {
+    config_path.join(IDENTITY_KEY_FILE_NAME)
+}
+
+/// Writes a file only the owner can read
+fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    options.open(path)?.write_all(contents)?;
+    Ok(())
+}
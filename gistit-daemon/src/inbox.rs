@@ -0,0 +1,82 @@
+//! The inbox module
+//!
+//! Gistits other peers push to us wait here, in memory, until accepted or rejected with
+//! `gistit inbox`. Nothing pushed is written to disk or served before it's accepted.
+
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use libp2p::core::PeerId;
+
+use gistit_proto::ipc::instruction::InboxItem;
+use gistit_proto::prost::Message;
+use gistit_proto::Gistit;
+
+/// Pushes waiting for approval, further ones are refused
+pub const INBOX_CAPACITY: usize = 32;
+
+#[derive(Debug, Clone)]
+pub struct Item {
+    pub peer: PeerId,
+    pub gistit: Gistit,
+    /// Unix timestamp, in seconds
+    pub received_at: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct Inbox {
+    next_id: u32,
+    items: BTreeMap<u32, Item>,
+}
+
+impl Inbox {
+    /// Adds a pushed gistit, returning it's id. The same gistit pushed twice keeps the first id.
+    /// `None` if the inbox is full
+    pub fn insert(&mut self, peer: PeerId, gistit: Gistit) -> Option<u32> {
+        if let Some((id, _)) = self
+            .items
+            .iter()
+            .find(|(_, item)| item.gistit.hash == gistit.hash)
+        {
+            return Some(*id);
+        }
+        if self.items.len() >= INBOX_CAPACITY {
+            return None;
+        }
+
+        self.next_id += 1;
+        self.items.insert(
+            self.next_id,
+            Item {
+                peer,
+                gistit,
+                received_at: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .expect("Check your system time")
+                    .as_secs(),
+            },
+        );
+        Some(self.next_id)
+    }
+
+    /// Removes a gistit, once accepted or rejected
+    pub fn take(&mut self, id: u32) -> Option<Item> {
+        self.items.remove(&id)
+    }
+
+    /// Waiting gistits, oldest first
+    pub fn items(&self) -> Vec<InboxItem> {
+        self.items
+            .iter()
+            .map(|(id, item)| InboxItem {
+                id: *id,
+                hash: item.gistit.hash.clone(),
+                author: item.gistit.author.clone(),
+                description: item.gistit.description.clone(),
+                peer_id: item.peer.to_string(),
+                size: item.gistit.encoded_len() as u64,
+                received_at: item.received_at,
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,133 @@
+//! The limits module
+//!
+//! Bandwidth caps applied by the transfer protocol codecs and per peer request rate limiting.
+//! Every limit can be changed at runtime, zero means unlimited.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use libp2p::core::PeerId;
+
+use crate::config::Limits;
+
+/// Peers tracked before idle ones are pruned
+const PEER_BUCKETS_MAX: usize = 1024;
+
+/// Upload and download caps, shared between the node and the codecs
+#[derive(Debug)]
+pub struct Bandwidth {
+    pub upload: Limiter,
+    pub download: Limiter,
+}
+
+impl Bandwidth {
+    #[must_use]
+    pub fn new(limits: &Limits) -> Self {
+        Self {
+            upload: Limiter::new(limits.upload_rate),
+            download: Limiter::new(limits.download_rate),
+        }
+    }
+}
+
+/// Spaces out transfers so they average `rate` bytes per second
+#[derive(Debug)]
+pub struct Limiter {
+    rate: AtomicU64,
+    /// When the next transfer may start
+    next: Mutex<Instant>,
+}
+
+impl Limiter {
+    #[must_use]
+    pub fn new(rate: u64) -> Self {
+        Self {
+            rate: AtomicU64::new(rate),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub fn rate(&self) -> u64 {
+        self.rate.load(Ordering::Relaxed)
+    }
+
+    pub fn set_rate(&self, rate: u64) {
+        self.rate.store(rate, Ordering::Relaxed);
+    }
+
+    /// Waits for a slot to transfer `bytes` within the configured rate
+    #[allow(clippy::cast_precision_loss)]
+    pub async fn throttle(&self, bytes: usize) {
+        let rate = self.rate();
+        if rate == 0 {
+            return;
+        }
+
+        let wait = {
+            let mut next = self.next.lock().expect("limiter lock not to be poisoned");
+            let now = Instant::now();
+            let start = (*next).max(now);
+            *next = start + Duration::from_secs_f64(bytes as f64 / rate as f64);
+            start - now
+        };
+
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Token bucket of requests per minute for each peer
+#[derive(Debug, Default)]
+pub struct PeerRate {
+    per_minute: u32,
+    buckets: HashMap<PeerId, (f64, Instant)>,
+}
+
+impl PeerRate {
+    #[must_use]
+    pub fn new(per_minute: u32) -> Self {
+        Self {
+            per_minute,
+            buckets: HashMap::default(),
+        }
+    }
+
+    pub const fn rate(&self) -> u32 {
+        self.per_minute
+    }
+
+    pub fn set_rate(&mut self, per_minute: u32) {
+        self.per_minute = per_minute;
+        self.buckets.clear();
+    }
+
+    /// Takes a token for a request from `peer`, returns `false` if it's over the rate
+    pub fn allow(&mut self, peer: PeerId, now: Instant) -> bool {
+        if self.per_minute == 0 {
+            return true;
+        }
+
+        let capacity = f64::from(self.per_minute);
+        if self.buckets.len() >= PEER_BUCKETS_MAX {
+            let refill = capacity / 60.0;
+            self.buckets.retain(|_, (tokens, last)| {
+                *tokens + now.duration_since(*last).as_secs_f64() * refill < capacity
+            });
+        }
+
+        let (tokens, last) = self.buckets.entry(peer).or_insert((capacity, now));
+        *tokens =
+            (*tokens + now.duration_since(*last).as_secs_f64() * capacity / 60.0).min(capacity);
+        *last = now;
+
+        if *tokens >= 1.0 {
+            *tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
@@ -0,0 +1,208 @@
+//! The logger module
+//!
+//! Every record goes to stderr (through `env_logger`) and, as a JSON line, to [`LOG_FILE_NAME`]
+//! inside the runtime directory. The file is rotated once it reaches [`LOG_MAX_SIZE`], keeping
+//! [`LOG_ROTATE_KEEP`] old files around. It's the file `gistit daemon logs` reads from.
+//!
+//! `RUST_LOG` sets the level when given, otherwise it's [`DEFAULT_LOG_LEVEL`] or the one in the
+//! config file, changed with [`set_level`] as the file is reloaded or through a `LogLevelRequest`.
+#![allow(clippy::missing_errors_doc)]
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::{LevelFilter, Log, Metadata, Record};
+use serde::Serialize;
+
+use crate::Result;
+
+/// The structured log file name inside the runtime directory
+pub const LOG_FILE_NAME: &str = "gistit-daemon.log";
+
+/// Rotate the log file past this size in bytes
+pub const LOG_MAX_SIZE: u64 = 1_000_000;
+
+/// Amount of rotated files kept (`.1`, `.2`, ...)
+pub const LOG_ROTATE_KEEP: usize = 3;
+
+/// Biggest chunk sent in a single `LogChunkResponse`, must fit a datagram
+pub const LOG_CHUNK_MAX: u64 = 32_000;
+
+/// Level without `RUST_LOG` or a level in the config file
+pub const DEFAULT_LOG_LEVEL: LevelFilter = LevelFilter::Info;
+
+/// Whether `RUST_LOG` set the level, the config file doesn't change it then
+static LEVEL_FROM_ENV: AtomicBool = AtomicBool::new(false);
+
+#[derive(Serialize)]
+struct Entry<'a> {
+    /// Milliseconds since unix epoch
+    ts: u64,
+    level: &'a str,
+    target: &'a str,
+    msg: String,
+}
+
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+impl LogFile {
+    fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            size,
+        })
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn write(&mut self, record: &Record) -> Result<()> {
+        let ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Check your system time")
+            .as_millis() as u64;
+        let mut line = serde_json::to_vec(&Entry {
+            ts,
+            level: record.level().as_str(),
+            target: record.target(),
+            msg: record.args().to_string(),
+        })?;
+        line.push(b'\n');
+
+        if self.size + line.len() as u64 > LOG_MAX_SIZE {
+            rotate(&self.path)?;
+            *self = Self::open(&self.path)?;
+        }
+
+        self.file.write_all(&line)?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+}
+
+/// Shifts `gistit-daemon.log` to `gistit-daemon.log.1`, `.1` to `.2` and so on, dropping the
+/// oldest
+fn rotate(path: &Path) -> Result<()> {
+    let rotated = |n: usize| PathBuf::from(format!("{}.{}", path.to_string_lossy(), n));
+
+    for n in (1..LOG_ROTATE_KEEP).rev() {
+        if fs::metadata(rotated(n)).is_ok() {
+            fs::rename(rotated(n), rotated(n + 1))?;
+        }
+    }
+    fs::rename(path, rotated(1))?;
+    Ok(())
+}
+
+pub struct Logger {
+    stderr: env_logger::Logger,
+    file: Mutex<Option<LogFile>>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.stderr.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        self.stderr.log(record);
+
+        if let Some(file) = self.file.lock().unwrap().as_mut() {
+            // Nowhere to report this, stderr already got the record
+            file.write(record).ok();
+        }
+    }
+
+    fn flush(&self) {
+        self.stderr.flush();
+    }
+}
+
+/// Installs the global logger. If the log file can't be opened only stderr is used
+pub fn init(runtime_path: &Path) {
+    let from_env = std::env::var_os(env_logger::DEFAULT_FILTER_ENV).is_some();
+    // Without `RUST_LOG` records are only filtered by the max level, see `set_level`
+    let stderr =
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("trace"))
+            .write_style(env_logger::WriteStyle::Always)
+            .build();
+    let file = LogFile::open(&runtime_path.join(LOG_FILE_NAME)).ok();
+
+    LEVEL_FROM_ENV.store(from_env, Ordering::Relaxed);
+    log::set_max_level(if from_env {
+        stderr.filter()
+    } else {
+        DEFAULT_LOG_LEVEL
+    });
+    log::set_boxed_logger(Box::new(Logger {
+        stderr,
+        file: Mutex::new(file),
+    }))
+    .expect("logger to be initialized once");
+}
+
+/// Logs records up to `level` from now on, unless `RUST_LOG` set it
+pub fn set_level(level: LevelFilter) {
+    if !level_from_env() {
+        log::set_max_level(level);
+    }
+}
+
+/// The level records are logged up to
+pub fn level() -> LevelFilter {
+    log::max_level()
+}
+
+/// Whether `RUST_LOG` set the level, [`set_level`] does nothing then
+pub fn level_from_env() -> bool {
+    LEVEL_FROM_ENV.load(Ordering::Relaxed)
+}
+
+/// Reads up to [`LOG_CHUNK_MAX`] bytes of the log file from `offset`. Without an offset the end
+/// of the file is returned, starting at a line boundary. Returns the data and the offset to
+/// continue from
+pub fn read_chunk(runtime_path: &Path, offset: Option<u64>) -> Result<(Vec<u8>, u64)> {
+    let mut file = match File::open(runtime_path.join(LOG_FILE_NAME)) {
+        Ok(file) => file,
+        Err(_) => return Ok((Vec::new(), 0)),
+    };
+    let len = file.metadata()?.len();
+
+    let start = match offset {
+        // The file was rotated since the last read, start over
+        Some(offset) if offset > len => 0,
+        Some(offset) => offset,
+        None => len.saturating_sub(LOG_CHUNK_MAX),
+    };
+
+    let mut data = Vec::new();
+    file.seek(SeekFrom::Start(start))?;
+    file.take(LOG_CHUNK_MAX).read_to_end(&mut data)?;
+
+    // Only send whole lines, the rest is picked up by the next request
+    if let Some(last_newline) = data.iter().rposition(|b| *b == b'\n') {
+        data.truncate(last_newline + 1);
+    }
+    let next = start + data.len() as u64;
+
+    if offset.is_none() && start > 0 {
+        // Drop the partial first line
+        let first_newline = data.iter().position(|b| *b == b'\n').map_or(0, |n| n + 1);
+        data.drain(..first_newline);
+    }
+
+    Ok((data, next))
+}
@@ -20,27 +20,46 @@
     )
 )]
 
+mod acl;
 mod behaviour;
+mod bootstrap;
+mod chunk;
 mod config;
+mod diagnostics;
 mod error;
 mod event;
+mod gateway;
+mod identity;
+mod inbox;
+mod limits;
+mod logger;
+mod metrics;
+mod name;
 mod node;
+mod notify;
+mod outbox;
+mod reputation;
+mod storage;
 
 pub type Error = crate::error::Error;
 pub type Result<T> = std::result::Result<T, Error>;
 
-use std::net::Ipv4Addr;
+use std::net::{Ipv4Addr, SocketAddr};
 use std::path::PathBuf;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use libp2p::Multiaddr;
 
-use config::Config;
+use config::{Config, FileConfig, FileLimits, RelayQuota};
 use node::Node;
 
-/// Gistit p2p node
-#[derive(Parser, PartialEq, Debug)]
+/// Gistit p2p node. Settings left out are read from `daemon.toml` in the config directory
+#[derive(Parser, PartialEq, Debug, Clone)]
 #[clap(author, version, about, long_about = None)]
 struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
     #[clap(long)]
     /// Override runtime directory
     runtime_path: Option<PathBuf>,
@@ -49,6 +68,10 @@ struct Args {
     /// Override config directory
     config_path: Option<PathBuf>,
 
+    #[clap(long)]
+    /// Override data directory, where fetched and pinned gistits are stored
+    data_path: Option<PathBuf>,
+
     #[clap(long)]
     /// IPFS config file to extract key material
     config_file: Option<PathBuf>,
@@ -66,44 +89,202 @@ struct Args {
     dial: Vec<String>,
 
     #[clap(long)]
-    /// Listen to these addresses, useful for relays
+    /// Listen to these addresses, useful for relays. QUIC ones end with `/udp/<port>/quic-v1`
     listen: Vec<String>,
 
+    #[clap(long, possible_values = ["noise", "tls"])]
+    /// What TCP and WebSocket connections are secured with, `noise` by default. QUIC always uses
+    /// TLS
+    security: Option<String>,
+
     #[clap(long)]
-    /// Bootstrap this node
+    /// Bootstrap this node using the default libp2p bootstrap nodes
     bootstrap: bool,
+
+    #[clap(long)]
+    /// Bootstrap from these `/p2p/` addresses instead, redialing them with backoff on failure
+    bootstrap_peer: Vec<Multiaddr>,
+
+    #[clap(long)]
+    /// Connection gating rule, `allow|deny <peer id|ip[/prefix]>`. Deny rules always win, any allow
+    /// rule turns the list into an allowlist
+    acl: Vec<String>,
+
+    #[clap(long)]
+    /// Upload bandwidth cap in bytes per second, 0 is unlimited
+    upload_rate: Option<u64>,
+
+    #[clap(long)]
+    /// Download bandwidth cap in bytes per second, 0 is unlimited
+    download_rate: Option<u64>,
+
+    #[clap(long)]
+    /// Requests per minute a single peer can make, 0 is unlimited
+    peer_request_rate: Option<u32>,
+
+    #[clap(long)]
+    /// Bytes of fetched and pinned gistits kept on disk, 256MiB by default. Least recently used
+    /// unpinned ones are evicted first
+    storage_quota: Option<u64>,
+
+    #[clap(long)]
+    /// Show desktop notifications when a hosted gistit is fetched, a push arrives or the node
+    /// loses reachability
+    notify: bool,
+
+    #[clap(long)]
+    /// Serve Prometheus metrics at `http://<addr>/metrics`
+    metrics: Option<SocketAddr>,
+
+    #[clap(long)]
+    /// Serve an http gateway at `http://<addr>`, with `GET /gistit/<hash>`, `POST /gistit` and
+    /// `GET /status`
+    http: Option<SocketAddr>,
+
+    #[clap(long)]
+    /// Act as a circuit relay v2 server, helping peers behind NATs. Needs a public address
+    relay_server: bool,
+
+    #[clap(long, requires = "relay-server")]
+    /// Max amount of relay reservations
+    relay_max_reservations: Option<usize>,
+
+    #[clap(long, requires = "relay-server")]
+    /// Max amount of relayed connections
+    relay_max_circuits: Option<usize>,
+
+    #[clap(long, requires = "relay-server")]
+    /// Max duration of a relayed connection, in seconds
+    relay_max_circuit_duration: Option<u64>,
+
+    #[clap(long, requires = "relay-server")]
+    /// Max bytes transferred over a relayed connection
+    relay_max_circuit_bytes: Option<u64>,
+}
+
+#[derive(Subcommand, PartialEq, Debug, Clone)]
+enum Command {
+    /// Manage the node identity, without starting the node
+    #[clap(subcommand)]
+    Identity(IdentityCommand),
+}
+
+#[derive(Subcommand, PartialEq, Debug, Clone)]
+enum IdentityCommand {
+    /// Print the peer id, generating the identity if there's none
+    Show,
+    /// Replace the keypair with a new one, changing the peer id
+    Rotate,
+    /// Print the keypair in the IPFS config key format. Keep it secret
+    Export,
+    /// Replace the keypair with an exported one, or the one of an IPFS config file
+    Import {
+        /// The file to import from
+        file: PathBuf,
+    },
 }
 
-async fn run() -> Result<()> {
+fn run_identity(command: &IdentityCommand, config_path: Option<PathBuf>) -> Result<()> {
+    gistit_project::path::init()?;
+    let config_path = config_path.unwrap_or(gistit_project::path::config()?);
+
+    match command {
+        IdentityCommand::Show => {
+            let keypair = identity::load_or_generate(&config_path)?;
+            println!("{}", libp2p::PeerId::from(keypair.public()));
+        }
+        IdentityCommand::Rotate => println!("{}", identity::rotate(&config_path)?),
+        IdentityCommand::Export => println!("{}", *identity::export(&config_path)?),
+        IdentityCommand::Import { file } => println!("{}", identity::import(&config_path, file)?),
+    }
+    Ok(())
+}
+
+async fn run(args: Args) -> Result<()> {
     let Args {
+        command: _,
         runtime_path,
         config_path,
+        data_path,
         config_file,
         host,
         port,
         bootstrap,
+        bootstrap_peer,
         dial,
         listen,
-    } = Args::parse();
+        security,
+        acl,
+        upload_rate,
+        download_rate,
+        peer_request_rate,
+        storage_quota,
+        notify,
+        metrics: metrics_addr,
+        http: http_addr,
+        relay_server,
+        relay_max_reservations,
+        relay_max_circuits,
+        relay_max_circuit_duration,
+        relay_max_circuit_bytes,
+    } = args;
 
-    let config = Config::from_args(
+    // Win over the config file
+    let overrides = FileConfig {
+        listen,
+        security,
+        bootstrap: bootstrap.then_some(true),
+        bootstrap_peers: bootstrap_peer.iter().map(ToString::to_string).collect(),
+        acl,
+        relay: relay_server.then(|| RelayQuota {
+            max_reservations: relay_max_reservations,
+            max_circuits: relay_max_circuits,
+            max_circuit_duration: relay_max_circuit_duration,
+            max_circuit_bytes: relay_max_circuit_bytes,
+        }),
+        limits: FileLimits {
+            upload_rate,
+            download_rate,
+            peer_request_rate,
+        },
+        storage_path: data_path,
+        storage_quota,
+        notify: notify.then_some(true),
+        log_level: None,
+    };
+
+    let config = Config::new(
         runtime_path,
         config_path,
         config_file,
         host,
         port,
-        bootstrap,
+        overrides,
     )?;
     log::debug!("Running config: {:?}", config);
+    if let Some(level) = config.settings.log_level {
+        logger::set_level(level);
+    }
 
     let mut node = Node::new(config).await?;
 
-    for addr in dial {
-        node.dial_on_init(&addr)?;
+    if let Some(addr) = metrics_addr {
+        tokio::spawn(async move {
+            if let Err(err) = metrics::serve(addr).await {
+                log::error!("Metrics endpoint stopped: {:?}", err);
+            }
+        });
+    }
+
+    if let Some(addr) = http_addr {
+        node.serve_gateway(addr);
     }
 
-    for addr in listen {
-        node.listen_on_init(&addr)?;
+    node.dial_bootstrap_peers();
+    node.provide_stored();
+
+    for addr in dial {
+        node.dial_on_init(&addr)?;
     }
 
     node.run().await?;
@@ -113,11 +294,27 @@ async fn run() -> Result<()> {
 
 #[tokio::main]
 async fn main() {
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"))
-        .write_style(env_logger::WriteStyle::Always)
-        .init();
+    let args = Args::parse();
+    if let Some(Command::Identity(command)) = &args.command {
+        if let Err(err) = run_identity(command, args.config_path.clone()) {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let runtime_path = args
+        .runtime_path
+        .clone()
+        .or_else(|| gistit_project::path::runtime_dir().ok())
+        .unwrap_or_else(std::env::temp_dir);
+    logger::init(&runtime_path);
 
-    while let Err(err) = run().await {
+    while let Err(err) = run(args.clone()).await {
         log::error!("{:?}", err);
+        // Running again won't fix the settings
+        if matches!(err, Error::Config(_)) {
+            std::process::exit(1);
+        }
     }
 }
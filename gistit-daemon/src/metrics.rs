@@ -0,0 +1,279 @@
+//! The metrics module
+//!
+//! Process wide counters exposed in the Prometheus text format. The HTTP endpoint is opt in
+//! (`--metrics <addr>`) and only answers `GET /metrics`, anything fancier belongs to a reverse
+//! proxy.
+#![allow(clippy::missing_errors_doc)]
+
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use log::{debug, info};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::Result;
+
+pub static METRICS: Metrics = Metrics::new();
+
+/// Instruction kinds counted in `gistit_ipc_instructions_total`
+#[derive(Debug, Clone, Copy)]
+pub enum InstructionKind {
+    Provide = 0,
+    Fetch,
+    Status,
+    Shutdown,
+    Dial,
+    TailLogs,
+    Revoke,
+    Subscribe,
+    Push,
+    Acl,
+    Limits,
+    PublishName,
+    ResolveName,
+    Pin,
+    ListPins,
+    Diagnostics,
+    Inbox,
+    InboxDecision,
+    Head,
+    Cancel,
+    Exists,
+    ResolvePrefix,
+    ReloadConfig,
+    LogLevel,
+}
+
+const INSTRUCTION_LABELS: [&str; 24] = [
+    "provide",
+    "fetch",
+    "status",
+    "shutdown",
+    "dial",
+    "tail_logs",
+    "revoke",
+    "subscribe",
+    "push",
+    "acl",
+    "limits",
+    "publish_name",
+    "resolve_name",
+    "pin",
+    "list_pins",
+    "diagnostics",
+    "inbox",
+    "inbox_decision",
+    "head",
+    "cancel",
+    "exists",
+    "resolve_prefix",
+    "reload_config",
+    "log_level",
+];
+
+pub struct Metrics {
+    pub peers_connected: AtomicU64,
+    pub dht_queries: AtomicU64,
+    pub bytes_served: AtomicU64,
+    pub bytes_received: AtomicU64,
+    pub rate_limited: AtomicU64,
+    pub provide_success: AtomicU64,
+    pub provide_failure: AtomicU64,
+    pub fetch_success: AtomicU64,
+    pub fetch_failure: AtomicU64,
+    pub outbox_depth: AtomicU64,
+    pub events_dropped: AtomicU64,
+    ipc_instructions: [AtomicU64; 24],
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Self {
+            peers_connected: AtomicU64::new(0),
+            dht_queries: AtomicU64::new(0),
+            bytes_served: AtomicU64::new(0),
+            bytes_received: AtomicU64::new(0),
+            rate_limited: AtomicU64::new(0),
+            provide_success: AtomicU64::new(0),
+            provide_failure: AtomicU64::new(0),
+            fetch_success: AtomicU64::new(0),
+            fetch_failure: AtomicU64::new(0),
+            outbox_depth: AtomicU64::new(0),
+            events_dropped: AtomicU64::new(0),
+            ipc_instructions: [
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+            ],
+        }
+    }
+
+    pub fn inc(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn dec(gauge: &AtomicU64) {
+        gauge.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn add(counter: &AtomicU64, value: u64) {
+        counter.fetch_add(value, Ordering::Relaxed);
+    }
+
+    pub fn set(gauge: &AtomicU64, value: u64) {
+        gauge.store(value, Ordering::Relaxed);
+    }
+
+    pub fn instruction(&self, kind: InstructionKind) {
+        Self::inc(&self.ipc_instructions[kind as usize]);
+    }
+
+    /// Renders every metric in the Prometheus text exposition format
+    #[must_use]
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: &AtomicU64| {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            let _ = writeln!(out, "{} {}", name, value.load(Ordering::Relaxed));
+        };
+
+        metric(
+            "gistit_peers_connected",
+            "gauge",
+            "Peers currently connected",
+            &self.peers_connected,
+        );
+        metric(
+            "gistit_dht_queries_total",
+            "counter",
+            "Kademlia queries started",
+            &self.dht_queries,
+        );
+        metric(
+            "gistit_bytes_served_total",
+            "counter",
+            "Gistit bytes sent to other peers",
+            &self.bytes_served,
+        );
+        metric(
+            "gistit_bytes_received_total",
+            "counter",
+            "Gistit bytes received from other peers",
+            &self.bytes_received,
+        );
+        metric(
+            "gistit_rate_limited_total",
+            "counter",
+            "Requests refused for exceeding the per peer rate",
+            &self.rate_limited,
+        );
+        metric(
+            "gistit_provide_success_total",
+            "counter",
+            "Gistits successfully provided",
+            &self.provide_success,
+        );
+        metric(
+            "gistit_provide_failure_total",
+            "counter",
+            "Gistits that failed to be provided",
+            &self.provide_failure,
+        );
+        metric(
+            "gistit_fetch_success_total",
+            "counter",
+            "Gistits successfully fetched",
+            &self.fetch_success,
+        );
+        metric(
+            "gistit_fetch_failure_total",
+            "counter",
+            "Gistits that failed to be fetched",
+            &self.fetch_failure,
+        );
+        metric(
+            "gistit_outbox_depth",
+            "gauge",
+            "Instructions waiting to be written to the client",
+            &self.outbox_depth,
+        );
+        metric(
+            "gistit_events_dropped_total",
+            "counter",
+            "Events for the client dropped because it fell behind",
+            &self.events_dropped,
+        );
+
+        out.push_str("# HELP gistit_ipc_instructions_total IPC instructions received\n");
+        out.push_str("# TYPE gistit_ipc_instructions_total counter\n");
+        for (label, value) in INSTRUCTION_LABELS.iter().zip(&self.ipc_instructions) {
+            let _ = writeln!(
+                out,
+                "gistit_ipc_instructions_total{{kind=\"{}\"}} {}",
+                label,
+                value.load(Ordering::Relaxed)
+            );
+        }
+
+        out
+    }
+}
+
+/// Serves `GET /metrics` on `addr` forever
+pub async fn serve(addr: SocketAddr) -> Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    info!("Serving metrics on http://{}/metrics", addr);
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        tokio::spawn(async move {
+            if let Err(err) = respond(stream).await {
+                debug!("Metrics request from {} failed: {:?}", peer, err);
+            }
+        });
+    }
+}
+
+async fn respond(mut stream: TcpStream) -> Result<()> {
+    let mut buf = [0_u8; 1024];
+    let read = stream.read(&mut buf).await?;
+
+    let response = if buf[..read].starts_with(b"GET /metrics ") {
+        let body = METRICS.render();
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )
+    } else {
+        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_owned()
+    };
+
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
@@ -0,0 +1,130 @@
+//! The name module
+//!
+//! Human readable names, `@user/name`, pointing to the latest hash of a gistit. They're published
+//! to the DHT as signed [`NameRecord`]s, the newest sequence number wins. Peers storing a record
+//! only accept updates signed by the key that first published it, so a name belongs to whoever
+//! claimed it first on that part of the network.
+//!
+//! Names published by this node are kept in [`NAMES_FILE_NAME`] and republished periodically,
+//! since the DHT only keeps records for [`NAME_RECORD_TTL`].
+#![allow(clippy::missing_errors_doc)]
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use libp2p::core::PeerId;
+use libp2p::identity::{Keypair, PublicKey};
+use libp2p::kad::RecordKey as Key;
+
+use gistit_proto::payload::NameRecord;
+use gistit_proto::prost::Message;
+
+use crate::{Error, Result};
+
+/// Published names and their hash, inside the config directory
+pub const NAMES_FILE_NAME: &str = "names.json";
+
+/// How long other peers keep our name records
+pub const NAME_RECORD_TTL: Duration = Duration::from_secs(36 * 60 * 60);
+
+/// Our names are republished at this interval, well before they expire
+pub const NAME_REPUBLISH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+const NAME_KEY_PREFIX: &str = "/gistit/name/";
+
+const NAME_PART_MAX_LENGTH: usize = 32;
+
+/// Checks `name` is written as `@user/name`
+pub fn validate(name: &str) -> Result<()> {
+    let valid_part = |part: &str| {
+        !part.is_empty()
+            && part.len() <= NAME_PART_MAX_LENGTH
+            && part
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    };
+
+    match name.strip_prefix('@').and_then(|name| name.split_once('/')) {
+        Some((user, name)) if valid_part(user) && valid_part(name) => Ok(()),
+        _ => Err(Error::Parse("name must be '@user/name'")),
+    }
+}
+
+/// The DHT key of `name`
+#[must_use]
+pub fn key(name: &str) -> Key {
+    Key::new(&format!("{}{}", NAME_KEY_PREFIX, name))
+}
+
+#[must_use]
+pub fn is_name_key(key: &Key) -> bool {
+    key.to_vec().starts_with(NAME_KEY_PREFIX.as_bytes())
+}
+
+fn signed_bytes(name: &str, hash: &str, sequence: u64) -> Vec<u8> {
+    format!("{}\n{}\n{}", name, hash, sequence).into_bytes()
+}
+
+/// A record pointing `name` to `hash`, signed by `keypair`. The sequence is the current time, so
+/// later records always win
+pub fn sign(keypair: &Keypair, name: &str, hash: &str) -> Result<NameRecord> {
+    #[allow(clippy::cast_possible_truncation)]
+    let sequence = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Check your system time")
+        .as_millis() as u64;
+    let signature = keypair
+        .sign(&signed_bytes(name, hash, sequence))
+        .map_err(|_| Error::Parse("failed to sign name record"))?;
+
+    Ok(NameRecord {
+        name: name.to_owned(),
+        hash: hash.to_owned(),
+        sequence,
+        public_key: keypair.public().encode_protobuf(),
+        signature,
+    })
+}
+
+/// Decodes a DHT record value stored under `key`, returning it with it's publisher if the
+/// signature checks out
+#[must_use]
+pub fn verify(key: &Key, value: &[u8]) -> Option<(NameRecord, PeerId)> {
+    let record = NameRecord::decode(value).ok()?;
+    if validate(&record.name).is_err() || self::key(&record.name) != *key {
+        return None;
+    }
+
+    let public_key = PublicKey::try_decode_protobuf(&record.public_key).ok()?;
+    let signed = signed_bytes(&record.name, &record.hash, record.sequence);
+    if !public_key.verify(&signed, &record.signature) {
+        return None;
+    }
+
+    Some((record, public_key.to_peer_id()))
+}
+
+/// Whether `new` may replace `existing`, same publisher and a newer sequence
+#[must_use]
+pub fn supersedes(new: &NameRecord, existing: &NameRecord) -> bool {
+    new.public_key == existing.public_key && new.sequence > existing.sequence
+}
+
+/// Names published by this node, read from [`NAMES_FILE_NAME`]
+pub fn load(config_path: &Path) -> Result<BTreeMap<String, String>> {
+    let path = config_path.join(NAMES_FILE_NAME);
+    if fs::metadata(&path).is_err() {
+        return Ok(BTreeMap::new());
+    }
+    Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+}
+
+pub fn save(config_path: &Path, names: &BTreeMap<String, String>) -> Result<()> {
+    fs::write(
+        config_path.join(NAMES_FILE_NAME),
+        serde_json::to_string_pretty(names)?,
+    )?;
+    Ok(())
+}
@@ -1,105 +1,341 @@
 //! The network module
 #![allow(clippy::missing_errors_doc)]
 
-use std::collections::{HashMap, HashSet};
-use std::io;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
 use std::string::ToString;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
 use std::task::Poll;
+use std::time::{Duration, Instant};
 
-use either::Either;
-use log::{debug, error, info, warn};
+use log::{debug, error, info, warn, LevelFilter};
+use tokio::sync::{mpsc, oneshot};
 
 use gistit_ipc::{Bridge, Server};
-use gistit_proto::{ipc, Gistit, Instruction};
+use gistit_proto::ipc::instruction::fetch_progress_response::Stage;
+use gistit_proto::ipc::instruction::FetchProgressResponse;
+use gistit_proto::prost::Message;
+use gistit_proto::{ipc, payload, Announcement, Gistit, Head, Instruction};
 
-use libp2p::core::either::EitherError;
+use libp2p::core::muxing::StreamMuxerBox;
+use libp2p::core::transport::ListenerId;
 use libp2p::core::{self, Multiaddr, PeerId};
-use libp2p::futures::future::poll_fn;
+use libp2p::futures::future::{poll_fn, Either};
 use libp2p::futures::StreamExt;
 use libp2p::multiaddr::Protocol;
-use libp2p::swarm::{ProtocolsHandlerUpgrErr, SwarmBuilder, SwarmEvent};
-use libp2p::{dns, mplex, noise, tcp, websocket, yamux, Swarm, Transport};
+use libp2p::swarm::{self, SwarmEvent};
+use libp2p::{dns, noise, quic, tcp, tls, websocket, yamux, Swarm, Transport};
 
-use libp2p::kad::{record::Key, QueryId};
-use libp2p::ping::Failure;
-use libp2p::request_response::RequestId;
+use libp2p::autonat::NatStatus;
+use libp2p::gossipsub::{IdentTopic, TopicHash};
+use libp2p::identity::Keypair;
+use libp2p::kad::{QueryId, Quorum, Record, RecordKey as Key};
+use libp2p::request_response::OutboundRequestId;
 
-use crate::behaviour::{Behaviour, Event, Request};
-use crate::config::Config;
-use crate::event::{handle_identify, handle_kademlia, handle_request_response};
-use crate::Result;
+use crate::acl::{Acl, Action as AclAction, Rule, Target};
+use crate::behaviour::{Behaviour, ChunkRequest, Event, PushRequest};
+use crate::bootstrap::Bootstrap;
+use crate::chunk::Download;
+use crate::config::{Config, FileConfig, Security, Settings};
+use crate::diagnostics::{self, Diagnostics};
+use crate::event::{
+    handle_autonat, handle_chunk, handle_dcutr, handle_gossipsub, handle_identify, handle_kademlia,
+    handle_push, handle_relay_client, handle_request_response,
+};
+use crate::gateway::{self, Request as GatewayRequest};
+use crate::inbox::Inbox;
+use crate::limits::{Bandwidth, PeerRate};
+use crate::logger;
+use crate::metrics::{InstructionKind, Metrics, METRICS};
+use crate::name::{self, NAME_REPUBLISH_INTERVAL};
+use crate::notify::Notifier;
+use crate::outbox::{Outbox, OUTBOX_SIZE};
+use crate::reputation::Reputation;
+use crate::storage::Storage;
+use crate::{Error, Result};
+
+/// How often due bootstrap peer retries are checked
+const BOOTSTRAP_TICK: Duration = Duration::from_secs(1);
+
+/// Names are first republished once the node had time to join the DHT
+const NAME_REPUBLISH_DELAY: Duration = Duration::from_secs(30);
+
+/// How often a running diagnostics is checked for completion
+const DIAGNOSTICS_TICK: Duration = Duration::from_millis(500);
+
+/// Most peer scores sent in a status, keeping it within a single datagram
+const STATUS_PEERS_MAX: usize = 64;
+
+/// Gateway requests waiting for the event loop, the gateway waits when it's full
+const GATEWAY_QUEUE_SIZE: usize = 64;
+
+/// Connections without open streams are closed after this long
+const IDLE_CONNECTION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// TCP and WebSocket connections not secured and multiplexed by then are dropped
+const TRANSPORT_TIMEOUT: Duration = Duration::from_secs(20);
 
 /// The main event loop
 pub struct Node {
     pub swarm: Swarm<Behaviour>,
-    pub bridge: Bridge<Server>,
+
+    /// Instructions from the client are received here, shared with the outbox
+    pub bridge: Arc<Bridge<Server>>,
+
+    /// Instructions to the client are queued here
+    pub outbox: Outbox,
 
     pub pending_dial: HashSet<PeerId>,
 
-    /// Pending kademlia queries to get providers
-    pub pending_get_providers: HashSet<QueryId>,
+    /// Pending kademlia queries to get providers, with the key they look for
+    pub pending_get_providers: HashMap<QueryId, Key>,
 
     pub pending_start_providing: HashSet<QueryId>,
     pub to_provide: HashMap<Key, Gistit>,
 
-    pub pending_request_file: HashSet<RequestId>,
+    /// Gossipsub topics to announce a gistit on, once it's provided
+    pub to_announce: HashMap<Key, Vec<String>>,
+
+    /// Topics whose announcements are streamed to the client
+    pub subscriptions: HashSet<TopicHash>,
+
+    pub pending_request_file: HashSet<OutboundRequestId>,
 
     /// Stack of request file (`key`) events
     pub to_request: Vec<(Key, HashSet<PeerId>)>,
     pub pending_receive_file: HashSet<Key>,
 
+    /// Chunked downloads, kept after running out of providers so they can be resumed
+    pub downloads: HashMap<Key, Download>,
+
+    /// Fetches whose client agreed to the gistit being deleted once read, if it is
+    pub burn_agreed: HashSet<Key>,
+
+    /// Hosted gistits with a read limit a peer is fetching, and the chunks served to it so far
+    pub reading: HashMap<(Key, PeerId), HashSet<usize>>,
+
+    /// Complete fetches of hosted gistits with a read limit
+    pub reads: HashMap<Key, u32>,
+
+    /// Which download a manifest or chunk request belongs to
+    pub pending_chunk: HashMap<OutboundRequestId, Key>,
+
+    /// Heads the client is waiting for, and how many providers were asked and didn't answer yet
+    pub pending_head: HashMap<Key, usize>,
+
+    /// Which head a head request belongs to
+    pub pending_head_request: HashMap<OutboundRequestId, Key>,
+
+    /// Outgoing pushes waiting for the peer to answer
+    pub pending_push: HashSet<OutboundRequestId>,
+
+    /// Pushed gistits waiting for approval
+    pub inbox: Inbox,
+
+    /// Gistits pushed to us by other peers and accepted, and who pushed them
+    pub received: HashMap<Key, (PeerId, Gistit)>,
+
     /// Addresses that can be used as relay
     pub relays: HashSet<Multiaddr>,
+
+    /// Relays that accepted our reservation
+    pub relay_reservations: HashSet<PeerId>,
+
+    /// Connection gating rules
+    pub acl: Acl,
+
+    /// Bandwidth caps, shared with the transfer codecs
+    pub bandwidth: Arc<Bandwidth>,
+
+    /// Per peer request rate limiting
+    pub peer_rate: PeerRate,
+
+    /// User provided bootstrap peers and their pending redials
+    pub bootstrap: Bootstrap,
+
+    /// Names published by this node and the hash they point to
+    pub names: BTreeMap<String, String>,
+
+    /// Name publications the client is waiting for
+    pub pending_publish_name: HashSet<QueryId>,
+
+    /// Name resolutions the client is waiting for
+    pub pending_resolve_name: HashSet<QueryId>,
+
+    /// Fetched and pinned gistits kept on disk
+    pub storage: Storage,
+
+    /// Gistits being fetched to be pinned
+    pub pending_pin: HashSet<Key>,
+
+    /// Provider scores, deciding who downloads are asked first
+    pub reputation: Reputation,
+
+    /// Diagnostics the client is waiting for
+    pub diagnostics: Option<Diagnostics>,
+
+    /// Desktop notifications, if enabled
+    pub notifier: Notifier,
+
+    /// Requests from the http gateway, if it's served
+    pub gateway: Option<mpsc::Receiver<GatewayRequest>>,
+
+    /// Gateway requests waiting for a gistit to be provided
+    pub pending_gateway_provide: HashMap<QueryId, oneshot::Sender<Option<Gistit>>>,
+
+    /// Gateway requests waiting for a gistit to be fetched
+    pub pending_gateway_fetch: HashMap<Key, Vec<oneshot::Sender<Option<Gistit>>>>,
+
+    /// Signs name records
+    pub keypair: Keypair,
+
+    /// Where the socket and log files live
+    pub runtime_path: PathBuf,
+
+    /// Where the identity, published names and config file live
+    pub config_path: PathBuf,
+
+    /// What the node runs with, changed as the config file is reloaded
+    pub settings: Settings,
+
+    /// Command line settings, winning over the config file
+    pub overrides: FileConfig,
+
+    /// Listeners of the addresses in the settings
+    pub listeners: HashMap<Multiaddr, ListenerId>,
 }
 
 impl Node {
     pub async fn new(config: Config) -> Result<Self> {
-        let (behaviour, client_transport) = Behaviour::new_behaviour_and_transport(&config)?;
-
-        let noise_keys = noise::Keypair::<noise::X25519Spec>::new()
-            .into_authentic(&config.keypair)
-            .expect("Signing libp2p-noise static DH keypair failed.");
+        let bandwidth = Arc::new(Bandwidth::new(&config.settings.limits));
+        let (behaviour, client_transport) =
+            Behaviour::new_behaviour_and_transport(&config, bandwidth.clone())?;
 
         let transport = {
-            let tcp = tcp::TokioTcpConfig::new().nodelay(true);
-            let dns_tcp = dns::TokioDnsConfig::system(tcp.clone())?;
-            let ws_dns_tcp = websocket::WsConfig::new(tcp.clone());
+            let tcp_config = tcp::Config::default().nodelay(true);
+            let tcp = tcp::tokio::Transport::new(tcp_config.clone());
+            let dns_tcp =
+                dns::tokio::Transport::system(tcp::tokio::Transport::new(tcp_config.clone()))?;
+            let ws_dns_tcp = websocket::WsConfig::new(tcp::tokio::Transport::new(tcp_config));
 
-            tcp.or_transport(client_transport)
+            let tcp = tcp
+                .or_transport(client_transport)
                 .or_transport(dns_tcp)
                 .or_transport(ws_dns_tcp)
-                .upgrade(core::upgrade::Version::V1)
-                .authenticate(noise::NoiseConfig::xx(noise_keys).into_authenticated())
-                .multiplex(core::upgrade::SelectUpgrade::new(
-                    yamux::YamuxConfig::default(),
-                    mplex::MplexConfig::default(),
-                ))
-                .timeout(std::time::Duration::from_secs(20))
+                .upgrade(core::upgrade::Version::V1);
+            let tcp = match config.settings.security {
+                Security::Noise => tcp
+                    .authenticate(
+                        noise::Config::new(&config.keypair)
+                            .expect("Signing libp2p-noise static DH keypair failed."),
+                    )
+                    .multiplex(yamux::Config::default())
+                    .timeout(TRANSPORT_TIMEOUT)
+                    .boxed(),
+                Security::Tls => tcp
+                    .authenticate(
+                        tls::Config::new(&config.keypair)
+                            .expect("Generating libp2p-tls certificate failed."),
+                    )
+                    .multiplex(yamux::Config::default())
+                    .timeout(TRANSPORT_TIMEOUT)
+                    .boxed(),
+            };
+            // Secured and multiplexed by QUIC itself
+            let quic = quic::tokio::Transport::new(quic::Config::new(&config.keypair));
+
+            quic.or_transport(tcp)
+                .map(|either, _| match either {
+                    Either::Left((peer_id, connection)) => {
+                        (peer_id, StreamMuxerBox::new(connection))
+                    }
+                    Either::Right((peer_id, muxer)) => (peer_id, muxer),
+                })
                 .boxed()
         };
 
-        let mut swarm = SwarmBuilder::new(transport, behaviour, config.peer_id)
-            .executor(Box::new(|fut| {
-                tokio::task::spawn(fut);
-            }))
-            .build();
+        let mut swarm = Swarm::new(
+            transport,
+            behaviour,
+            config.peer_id,
+            swarm::Config::with_tokio_executor()
+                .with_idle_connection_timeout(IDLE_CONNECTION_TIMEOUT),
+        );
         swarm.listen_on(config.multiaddr)?;
+        swarm.listen_on(config.quic_multiaddr)?;
+        let mut listeners = HashMap::default();
+        for address in &config.settings.listen {
+            listeners.insert(address.clone(), swarm.listen_on(address.clone())?);
+        }
 
-        let bridge = gistit_ipc::server(&config.runtime_path)?;
+        let bridge = Arc::new(gistit_ipc::server(&config.runtime_path)?);
+        let outbox = Outbox::spawn(bridge.clone());
+        let bootstrap = Bootstrap::new(&config.settings.bootstrap_peers)?;
+        let acl = Acl::new(&config.settings.acl)?;
+        let names = name::load(&config.config_path)?;
+        let storage = Storage::open(&config.data_path, config.settings.storage_quota)?;
+        let reputation = Reputation::open(&config.data_path)?;
+        for peer in acl.denied_peers() {
+            swarm.behaviour_mut().blocked.block_peer(peer);
+        }
 
         Ok(Self {
             swarm,
             bridge,
+            outbox,
             pending_dial: HashSet::default(),
             pending_start_providing: HashSet::default(),
-            pending_get_providers: HashSet::default(),
+            pending_get_providers: HashMap::default(),
             pending_request_file: HashSet::default(),
             pending_receive_file: HashSet::default(),
+            downloads: HashMap::default(),
+            burn_agreed: HashSet::default(),
+            reading: HashMap::default(),
+            reads: HashMap::default(),
+            pending_chunk: HashMap::default(),
+            pending_head: HashMap::default(),
+            pending_head_request: HashMap::default(),
+            pending_push: HashSet::default(),
+            inbox: Inbox::default(),
+            received: HashMap::default(),
 
             to_provide: HashMap::default(),
+            to_announce: HashMap::default(),
+            subscriptions: HashSet::default(),
             to_request: Vec::default(),
 
             relays: HashSet::default(),
+            relay_reservations: HashSet::default(),
+
+            acl,
+            bandwidth,
+            peer_rate: PeerRate::new(config.settings.limits.peer_request_rate),
+            bootstrap,
+
+            names,
+            pending_publish_name: HashSet::default(),
+            pending_resolve_name: HashSet::default(),
+
+            storage,
+            pending_pin: HashSet::default(),
+            reputation,
+
+            diagnostics: None,
+            notifier: Notifier::new(config.settings.notify),
+
+            gateway: None,
+            pending_gateway_provide: HashMap::default(),
+            pending_gateway_fetch: HashMap::default(),
+
+            keypair: config.keypair,
+            runtime_path: config.runtime_path,
+            config_path: config.config_path,
+            settings: config.settings,
+            overrides: config.overrides,
+            listeners,
         })
     }
 
@@ -107,12 +343,317 @@ impl Node {
         Ok(self.swarm.dial(address.parse::<Multiaddr>()?)?)
     }
 
-    pub fn listen_on_init(&mut self, address: &str) -> Result<()> {
-        self.swarm.listen_on(address.parse::<Multiaddr>()?)?;
+    /// Dials every bootstrap peer, failures are retried from the event loop
+    pub fn dial_bootstrap_peers(&mut self) {
+        let now = Instant::now();
+        let peers: Vec<(PeerId, Multiaddr)> = self
+            .bootstrap
+            .peers()
+            .map(|(peer, address)| (*peer, address.clone()))
+            .collect();
+
+        for (peer, address) in peers {
+            info!("Dialing bootstrap peer {:?}", address);
+            if let Err(err) = self.swarm.dial(address) {
+                warn!("Failed to dial bootstrap peer {:?}: {:?}", peer, err);
+                self.bootstrap.failed(&peer, now);
+            }
+        }
+    }
+
+    fn redial_bootstrap_peers(&mut self) {
+        let now = Instant::now();
+        for (peer, address) in self.bootstrap.due(now) {
+            debug!("Redialing bootstrap peer {:?}", address);
+            if let Err(err) = self.swarm.dial(address) {
+                warn!("Failed to redial bootstrap peer {:?}: {:?}", peer, err);
+                self.bootstrap.failed(&peer, now);
+            }
+        }
+    }
+
+    /// Publishes the announcement of a provided gistit to the topics requested with it
+    pub fn announce(&mut self, key: &Key) {
+        let topics = self.to_announce.remove(key).unwrap_or_default();
+        let gistit = match self.to_provide.get(key) {
+            Some(gistit) if !topics.is_empty() => gistit,
+            _ => return,
+        };
+
+        let peer_id = self.swarm.local_peer_id().to_string();
+        let data = Announcement::from_gistit(gistit, peer_id).encode_to_vec();
+
+        for topic in topics {
+            info!("Announcing {} on {}", gistit.hash, topic);
+            if let Err(err) = self
+                .swarm
+                .behaviour_mut()
+                .gossipsub
+                .publish(IdentTopic::new(topic), data.clone())
+            {
+                warn!("Failed to announce {}: {:?}", gistit.hash, err);
+            }
+        }
+    }
+
+    /// Streams announcements of `topics` to the client, replacing the previous subscriptions
+    pub fn subscribe(&mut self, topics: &[String]) {
+        self.unsubscribe_all();
+
+        for topic in topics {
+            let topic = IdentTopic::new(topic);
+            match self.swarm.behaviour_mut().gossipsub.subscribe(&topic) {
+                Ok(_) => {
+                    self.subscriptions.insert(topic.hash());
+                }
+                Err(err) => warn!("Failed to subscribe to {}: {:?}", topic, err),
+            }
+        }
+    }
+
+    /// Drops every client subscription, once the client goes away
+    pub fn unsubscribe_all(&mut self) {
+        for hash in self.subscriptions.drain() {
+            let topic = IdentTopic::new(hash.into_string());
+            if let Err(err) = self.swarm.behaviour_mut().gossipsub.unsubscribe(&topic) {
+                warn!("Failed to unsubscribe from {}: {:?}", topic, err);
+            }
+        }
+    }
+
+    /// Adds or removes an acl rule, (un)banning peers denied by id. Ip rules and allowlists only
+    /// apply to new connections
+    pub fn update_acl(&mut self, add: bool, rule: Rule) -> bool {
+        let changed = if add {
+            self.acl.add(rule)
+        } else {
+            self.acl.remove(&rule)
+        };
+
+        if let Rule {
+            action: AclAction::Deny,
+            target: Target::Peer(peer),
+        } = rule
+        {
+            if changed && add {
+                self.swarm.behaviour_mut().blocked.block_peer(peer);
+            } else if changed {
+                self.swarm.behaviour_mut().blocked.unblock_peer(peer);
+            }
+        }
+
+        changed
+    }
+
+    /// Reads the config file again and applies what changed, unless the command line set it.
+    /// Runtime changes, like acl rules added through `AclRequest`, are kept. Returns the settings
+    /// that changed but only apply after a restart
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`Error::Config`] if the file is invalid, nothing is applied then
+    pub fn reload_config(&mut self) -> Result<Vec<String>> {
+        let new = self
+            .overrides
+            .clone()
+            .or(FileConfig::load(&self.config_path)?)
+            .check()?;
+        let old = std::mem::replace(&mut self.settings, new.clone());
+
+        for address in old.listen.iter().filter(|a| !new.listen.contains(a)) {
+            if let Some(id) = self.listeners.remove(address) {
+                info!("No longer listening on {:?}", address);
+                let _ = self.swarm.remove_listener(id);
+            }
+        }
+        for address in new.listen.iter().filter(|a| !old.listen.contains(a)) {
+            match self.swarm.listen_on(address.clone()) {
+                Ok(id) => {
+                    self.listeners.insert(address.clone(), id);
+                }
+                Err(err) => warn!("Failed to listen on {:?}: {:?}", address, err),
+            }
+        }
+
+        if new.bootstrap_peers != old.bootstrap_peers {
+            self.bootstrap = Bootstrap::new(&new.bootstrap_peers)?;
+            for (peer, address) in self.bootstrap.peers() {
+                self.swarm
+                    .behaviour_mut()
+                    .kademlia
+                    .add_address(peer, address.clone());
+            }
+            self.dial_bootstrap_peers();
+        }
+
+        // Rules added or removed since it was last read, same as `AclRequest`
+        for rule in old.acl.iter().filter(|r| !new.acl.contains(r)) {
+            self.update_acl(false, rule.parse()?);
+        }
+        for rule in new.acl.iter().filter(|r| !old.acl.contains(r)) {
+            self.update_acl(true, rule.parse()?);
+        }
+
+        if new.limits.upload_rate != old.limits.upload_rate {
+            self.bandwidth.upload.set_rate(new.limits.upload_rate);
+        }
+        if new.limits.download_rate != old.limits.download_rate {
+            self.bandwidth.download.set_rate(new.limits.download_rate);
+        }
+        if new.limits.peer_request_rate != old.limits.peer_request_rate {
+            self.peer_rate.set_rate(new.limits.peer_request_rate);
+        }
+
+        if new.storage_quota != old.storage_quota {
+            self.storage.set_quota(new.storage_quota);
+            for hash in self.storage.evict()? {
+                self.revoke(&Key::new(&hash));
+            }
+        }
+        self.notifier.set_enabled(new.notify);
+        // Only when it changed, not to undo a `LogLevelRequest`
+        if new.log_level != old.log_level {
+            logger::set_level(new.log_level.unwrap_or(logger::DEFAULT_LOG_LEVEL));
+        }
+
+        let needs_restart: Vec<String> = [
+            ("bootstrap", new.bootstrap != old.bootstrap),
+            ("security", new.security != old.security),
+            ("relay", new.relay != old.relay),
+            ("storage_path", new.storage_path != old.storage_path),
+        ]
+        .into_iter()
+        .filter_map(|(name, changed)| changed.then(|| name.to_owned()))
+        .collect();
+
+        info!("Config reloaded: {:?}", self.settings);
+        if !needs_restart.is_empty() {
+            warn!("Restart to apply {}", needs_restart.join(", "));
+        }
+        Ok(needs_restart)
+    }
+
+    /// The transfer limits in effect
+    pub fn limits(&self) -> ipc::instruction::Limits {
+        ipc::instruction::Limits {
+            upload_rate: self.bandwidth.upload.rate(),
+            download_rate: self.bandwidth.download.rate(),
+            peer_request_rate: self.peer_rate.rate(),
+        }
+    }
+
+    /// Stops providing `key`. Our local record is dropped right away, remote peers forget it once
+    /// it expires since it won't be republished. Returns `false` if it wasn't being provided
+    pub fn revoke(&mut self, key: &Key) -> bool {
+        self.swarm.behaviour_mut().kademlia.stop_providing(key);
+        self.to_provide.remove(key).is_some()
+    }
+
+    /// Counts a complete fetch of the hosted gistit `key`, revoking it once it's been read `limit`
+    /// times
+    pub fn count_read(&mut self, key: &Key, limit: u32) {
+        let reads = self.reads.entry(key.clone()).or_default();
+        *reads += 1;
+        if *reads >= limit {
+            info!("{:?} was read {} times, revoking it", key, reads);
+            self.reads.remove(key);
+            self.reading.retain(|(other, _), _| other != key);
+            self.revoke(key);
+        }
+    }
+
+    /// Provides every stored gistit, on start
+    pub fn provide_stored(&mut self) {
+        let hashes: Vec<String> = self.storage.hashes().cloned().collect();
+        for hash in hashes {
+            match self.storage.read(&hash) {
+                Ok(Some(gistit)) => self.provide_stored_gistit(gistit),
+                Ok(None) => (),
+                Err(err) => warn!("Failed to read stored gistit {}: {:?}", hash, err),
+            }
+        }
+    }
+
+    fn provide_stored_gistit(&mut self, gistit: Gistit) {
+        let key = Key::new(&gistit.hash);
+        if self.to_provide.contains_key(&key) {
+            return;
+        }
+
+        Metrics::inc(&METRICS.dht_queries);
+        if let Err(err) = self
+            .swarm
+            .behaviour_mut()
+            .kademlia
+            .start_providing(key.clone())
+        {
+            warn!("Failed to provide stored gistit {}: {:?}", gistit.hash, err);
+            return;
+        }
+        self.to_provide.insert(key, gistit);
+    }
+
+    /// Stores and provides `gistit`, revoking whatever gets evicted to make room for it
+    pub fn store(&mut self, gistit: Gistit, pinned: bool) -> Result<()> {
+        for hash in self.storage.insert(&gistit, pinned)? {
+            debug!("Evicting {}", hash);
+            self.revoke(&Key::new(&hash));
+        }
+        self.provide_stored_gistit(gistit);
         Ok(())
     }
 
+    /// Signs and puts a record pointing `name` to `hash` in the DHT
+    pub fn publish_name(&mut self, name: &str, hash: &str) -> Result<QueryId> {
+        let record = name::sign(&self.keypair, name, hash)?;
+        Metrics::inc(&METRICS.dht_queries);
+
+        self.swarm
+            .behaviour_mut()
+            .kademlia
+            .put_record(
+                Record::new(name::key(name), record.encode_to_vec()),
+                Quorum::One,
+            )
+            .map_err(|_| Error::Parse("failed to store name record"))
+    }
+
+    fn republish_names(&mut self) {
+        let names: Vec<(String, String)> = self
+            .names
+            .iter()
+            .map(|(name, hash)| (name.clone(), hash.clone()))
+            .collect();
+
+        for (name, hash) in names {
+            debug!("Republishing {}", name);
+            if let Err(err) = self.publish_name(&name, &hash) {
+                warn!("Failed to republish {}: {:?}", name, err);
+            }
+        }
+    }
+
+    /// Serves the http gateway on `addr`, it's requests are handled by the event loop
+    pub fn serve_gateway(&mut self, addr: SocketAddr) {
+        let (tx, rx) = mpsc::channel(GATEWAY_QUEUE_SIZE);
+        self.gateway = Some(rx);
+
+        tokio::spawn(async move {
+            if let Err(err) = gateway::serve(addr, tx).await {
+                error!("Http gateway stopped: {:?}", err);
+            }
+        });
+    }
+
     pub async fn run(&mut self) -> Result<()> {
+        let mut bootstrap_tick = tokio::time::interval(BOOTSTRAP_TICK);
+        let mut republish_tick = tokio::time::interval_at(
+            tokio::time::Instant::now() + NAME_REPUBLISH_DELAY,
+            NAME_REPUBLISH_INTERVAL,
+        );
+        let mut diagnostics_tick = tokio::time::interval(DIAGNOSTICS_TICK);
+        let mut hangup = Hangup::new()?;
+
         loop {
             tokio::select! {
                 swarm_event = self.swarm.next() => self.handle_swarm_event(
@@ -123,87 +664,395 @@ impl Node {
                 request_event = poll_fn(|_| {
                     self.to_request.pop().map_or(Poll::Pending, Poll::Ready)
                 }) => self.handle_request_event(request_event).await?,
+
+                gateway_request = poll_fn(|cx| match self.gateway.as_mut() {
+                    Some(rx) => rx.poll_recv(cx),
+                    None => Poll::Pending,
+                }) => match gateway_request {
+                    Some(request) => self.handle_gateway_request(request),
+                    // The gateway stopped, don't poll it again
+                    None => self.gateway = None,
+                },
+
+                _ = bootstrap_tick.tick(), if !self.bootstrap.is_empty() => {
+                    self.redial_bootstrap_peers();
+                }
+
+                _ = republish_tick.tick(), if !self.names.is_empty() => self.republish_names(),
+
+                _ = diagnostics_tick.tick(), if self.diagnostics.is_some() => {
+                    self.finish_diagnostics().await?;
+                }
+
+                _ = hangup.recv() => {
+                    if let Err(err) = self.reload_config() {
+                        warn!("Config not reloaded: {}", err);
+                    }
+                }
             }
         }
     }
 
+    /// Answers the client once the diagnostics bootstrap dials completed or timed out
+    async fn finish_diagnostics(&mut self) -> Result<()> {
+        let bootstrap = match self.diagnostics.take() {
+            Some(diagnostics) if diagnostics.is_done(Instant::now()) => {
+                diagnostics.bootstrap_check(self.swarm.network_info().num_peers())
+            }
+            diagnostics => {
+                self.diagnostics = diagnostics;
+                return Ok(());
+            }
+        };
+
+        let nat_status = self.swarm.behaviour().autonat.nat_status();
+        let routing_table_size = self
+            .swarm
+            .behaviour_mut()
+            .kademlia
+            .kbuckets()
+            .map(|bucket| bucket.num_entries())
+            .sum();
+        let checks = vec![
+            bootstrap,
+            diagnostics::autonat_check(&nat_status),
+            diagnostics::port_mapping_check(&nat_status),
+            diagnostics::relay_check(&nat_status, &self.relay_reservations),
+            diagnostics::routing_table_check(routing_table_size),
+        ];
+
+        self.outbox
+            .send(Instruction::respond_diagnostics(checks))
+            .await?;
+        Ok(())
+    }
+
     async fn handle_request_event(&mut self, event: (Key, HashSet<PeerId>)) -> Result<()> {
         let (key, providers) = event;
 
-        self.pending_receive_file.insert(key.clone());
-        for peer in providers {
+        for peer in &providers {
             for relay in &self.relays {
                 // Skip if we are trying to relay over the destination peer itself
                 if relay
                     .iter()
-                    .any(|protocol| protocol == Protocol::P2p(peer.into()))
+                    .any(|protocol| protocol == Protocol::P2p(*peer))
                 {
                     continue;
                 }
 
-                self.swarm
-                    .behaviour_mut()
-                    .request_response
-                    .add_address(&peer, relay.clone());
-                // let relayed_addr = relay.clone().with(Protocol::P2p(peer.into()));
-                // self.swarm.dial(relayed_addr)?;
+                self.swarm.add_peer_address(*peer, relay.clone());
             }
+        }
 
+        let download = self.downloads.entry(key.clone()).or_default();
+        if download.progress().0 > 0 {
+            info!("Resuming download of {:?}", key);
+        }
+        download.add_providers(providers);
+
+        self.report_progress(&key, Stage::ProvidersFound, None);
+        self.schedule_download(&key).await
+    }
+
+    /// Streams the progress of downloading `key` to the client, if it's waiting for it. Progress
+    /// is dropped rather than waited on when the client is behind
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn report_progress(&self, key: &Key, stage: Stage, peer: Option<PeerId>) {
+        let download = match self.downloads.get(key) {
+            Some(download) if self.pending_receive_file.contains(key) && self.bridge.alive() => {
+                download
+            }
+            _ => return,
+        };
+
+        let (done, total) = download.progress();
+        let mut progress = FetchProgressResponse {
+            hash: String::from_utf8_lossy(&key.to_vec()).into_owned(),
+            chunks_done: done as u32,
+            chunks_total: total as u32,
+            peer_id: peer.map(|peer| peer.to_string()).unwrap_or_default(),
+            providers: download.provider_count() as u32,
+            bytes_done: download.bytes_done() as u64,
+            ..FetchProgressResponse::default()
+        };
+        progress.set_stage(stage);
+
+        self.outbox
+            .push_event(Instruction::respond_fetch_progress(progress));
+    }
+
+    /// Requests the manifest or missing chunks of a download from it's providers. Completes the
+    /// fetch once every chunk is in, or fails it if no provider is left
+    pub async fn schedule_download(&mut self, key: &Key) -> Result<()> {
+        let download = match self.downloads.get_mut(key) {
+            Some(download) => download,
+            None => return Ok(()),
+        };
+
+        if download.is_complete() {
+            let bytes = download.assemble().expect("download to be complete");
+            self.downloads.remove(key);
+            self.save_reputation();
+            return self.finish_download(key, &bytes).await;
+        }
+
+        let burn_after_read = self.burn_agreed.contains(key);
+        let chunk = &mut self.swarm.behaviour_mut().chunk;
+        let reputation = &self.reputation;
+        let manifest_peer = download.manifest_peer(|peer| reputation.score(peer));
+        if let Some(peer) = manifest_peer {
+            debug!("Requesting manifest of {:?} from {:?}", key, peer);
+            let request_id =
+                chunk.send_request(&peer, ChunkRequest::Manifest(key.to_vec(), burn_after_read));
+            download.manifest_requested(request_id, peer);
+            self.pending_chunk.insert(request_id, key.clone());
+        }
+
+        for (peer, index) in download.next_requests(|peer| reputation.score(peer)) {
+            debug!("Requesting chunk {} of {:?} from {:?}", index, key, peer);
+            let request_id = chunk.send_request(&peer, ChunkRequest::Chunk(key.to_vec(), index));
+            download.chunk_requested(request_id, peer, index);
+            self.pending_chunk.insert(request_id, key.clone());
+        }
+
+        if download.is_stalled() {
+            let (done, total) = download.progress();
+            warn!(
+                "Out of providers for {:?}, keeping {}/{} chunks",
+                key, done, total
+            );
+            download.interrupt();
+            Metrics::inc(&METRICS.fetch_failure);
+            self.save_reputation();
+            self.burn_agreed.remove(key);
+            self.answer_gateway_fetch(key, None);
+
+            if self.pending_receive_file.remove(key) {
+                self.outbox.send(Instruction::respond_fetch(None)).await?;
+            }
+            if self.pending_pin.remove(key) {
+                self.outbox.send(Instruction::respond_pin(false)).await?;
+            }
+            return Ok(());
+        }
+
+        if manifest_peer.is_some() {
+            self.report_progress(key, Stage::Connecting, manifest_peer);
+        }
+        Ok(())
+    }
+
+    /// Saves provider scores, a download just ended
+    fn save_reputation(&mut self) {
+        if let Err(err) = self.reputation.save() {
+            warn!("Failed to save peer scores: {:?}", err);
+        }
+    }
+
+    /// Decodes a fully downloaded gistit and answers the client, if it's hash checks out
+    async fn finish_download(&mut self, key: &Key, bytes: &[u8]) -> Result<()> {
+        let gistit = Gistit::decode(bytes)
+            .ok()
+            .filter(|gistit| key.to_vec() == gistit.hash.as_bytes() && verify(gistit));
+
+        let pin = self.pending_pin.remove(key);
+        self.burn_agreed.remove(key);
+        let mut stored = false;
+        if let Some(ref gistit) = gistit {
+            Metrics::inc(&METRICS.fetch_success);
+            // Fetched gistits are cached and served, pinned ones are kept. Gistits with a read limit
+            // are only handed to the client, serving them again would get around it
+            if gistit.read_limit().is_some() {
+                debug!("Not storing {}, it has a read limit", gistit.hash);
+            } else {
+                match self.store(gistit.clone(), pin) {
+                    Ok(()) => stored = true,
+                    Err(err) => warn!("Failed to store {}: {:?}", gistit.hash, err),
+                }
+            }
+        } else {
+            error!("Downloaded gistit {:?} doesn't match it's hash", key);
+            Metrics::inc(&METRICS.fetch_failure);
+        }
+
+        self.answer_gateway_fetch(key, gistit.as_ref());
+        if self.pending_receive_file.remove(key) {
+            self.outbox.send(Instruction::respond_fetch(gistit)).await?;
+        }
+        if pin {
+            self.outbox.send(Instruction::respond_pin(stored)).await?;
+        }
+        Ok(())
+    }
+
+    /// Fails the fetch of `key`, it's provider deletes it once read and the client didn't agree to
+    /// that
+    pub async fn burn_refused(&mut self, key: &Key) -> Result<()> {
+        info!("{:?} burns after reading, not fetching it", key);
+        self.downloads.remove(key);
+        self.burn_agreed.remove(key);
+        Metrics::inc(&METRICS.fetch_failure);
+        self.answer_gateway_fetch(key, None);
+
+        if self.pending_receive_file.remove(key) {
+            self.outbox
+                .send(Instruction::respond_fetch_burn_after_read())
+                .await?;
+        }
+        if self.pending_pin.remove(key) {
+            self.outbox.send(Instruction::respond_pin(false)).await?;
+        }
+        Ok(())
+    }
+
+    /// Whether a client, a pin or the gateway waits for the content of `key`
+    pub fn wants_content(&self, key: &Key) -> bool {
+        self.pending_receive_file.contains(key)
+            || self.pending_pin.contains(key)
+            || self.pending_gateway_fetch.contains_key(key)
+    }
+
+    /// Asks every provider of `key` for it's head, the first answer wins
+    pub fn request_head(&mut self, key: &Key, providers: &HashSet<PeerId>) {
+        for peer in providers {
+            debug!("Requesting head of {:?} from {:?}", key, peer);
             let request_id = self
                 .swarm
                 .behaviour_mut()
-                .request_response
-                .send_request(&peer, Request(key.to_vec()));
-            info!("Requesting gistit from {:?}", peer);
-
-            self.pending_request_file.insert(request_id);
+                .chunk
+                .send_request(peer, ChunkRequest::Head(key.to_vec()));
+            self.pending_head_request.insert(request_id, key.clone());
+            *self.pending_head.entry(key.clone()).or_default() += 1;
         }
+    }
 
+    /// Answers the client with the head of `key` once a provider sends it, or with nothing once
+    /// every provider failed to
+    pub async fn head_received(&mut self, key: &Key, head: Option<Head>) -> Result<()> {
+        let head = head.filter(|head| key.to_vec() == head.hash.as_bytes());
+        let answer = match (head, self.pending_head.get_mut(key)) {
+            (_, None) => return Ok(()),
+            (Some(head), Some(_)) => Some(head),
+            (None, Some(waiting)) => {
+                *waiting = waiting.saturating_sub(1);
+                if *waiting > 0 {
+                    return Ok(());
+                }
+                None
+            }
+        };
+
+        self.pending_head.remove(key);
+        self.outbox.send(Instruction::respond_head(answer)).await?;
         Ok(())
     }
 
-    #[allow(clippy::type_complexity)]
-    async fn handle_swarm_event(
-        &mut self,
-        event: SwarmEvent<
-            Event,
-            EitherError<
-                EitherError<
-                    EitherError<
-                        EitherError<
-                            EitherError<
-                                EitherError<ProtocolsHandlerUpgrErr<io::Error>, io::Error>,
-                                io::Error,
-                            >,
-                            Either<
-                                ProtocolsHandlerUpgrErr<
-                                    EitherError<
-                                        impl std::error::Error + Send,
-                                        impl std::error::Error + Send,
-                                    >,
-                                >,
-                                void::Void,
-                            >,
-                        >,
-                        ProtocolsHandlerUpgrErr<io::Error>,
-                    >,
-                    Failure,
-                >,
-                Either<
-                    ProtocolsHandlerUpgrErr<
-                        EitherError<impl std::error::Error + Send, impl std::error::Error + Send>,
-                    >,
-                    void::Void,
-                >,
-            >,
-        >,
-    ) -> Result<()> {
+    /// Answers gateway requests waiting for `key` to be fetched
+    pub fn answer_gateway_fetch(&mut self, key: &Key, gistit: Option<&Gistit>) {
+        for tx in self.pending_gateway_fetch.remove(key).unwrap_or_default() {
+            // The request may have timed out already
+            let _ = tx.send(gistit.cloned());
+        }
+    }
+
+    /// Translates gateway requests to the same machinery ipc instructions use. Gateway requests
+    /// never touch the bridge, a connected client isn't answered for them
+    fn handle_gateway_request(&mut self, request: GatewayRequest) {
+        match request {
+            GatewayRequest::Status(tx) => {
+                debug!("Gateway: Status");
+                let network_info = self.swarm.network_info();
+                let status = serde_json::json!({
+                    "peer_id": self.swarm.local_peer_id().to_string(),
+                    "peer_count": network_info.num_peers(),
+                    "pending_connections": network_info.connection_counters().num_pending(),
+                    "hosting": self.to_provide.len(),
+                    "reachability": reachability(self.swarm.behaviour().autonat.nat_status()),
+                    "bytes_sent": METRICS.bytes_served.load(Ordering::Relaxed),
+                    "bytes_received": METRICS.bytes_received.load(Ordering::Relaxed),
+                });
+                let _ = tx.send(status);
+            }
+
+            GatewayRequest::Provide(gistit, tx) => {
+                debug!("Gateway: Provide gistit {}", gistit.hash);
+                if !verify(&gistit) {
+                    let _ = tx.send(None);
+                    return;
+                }
+                let key = Key::new(&gistit.hash);
+                if self.to_provide.contains_key(&key) {
+                    let _ = tx.send(Some(gistit));
+                    return;
+                }
+
+                Metrics::inc(&METRICS.dht_queries);
+                match self
+                    .swarm
+                    .behaviour_mut()
+                    .kademlia
+                    .start_providing(key.clone())
+                {
+                    Ok(query_id) => {
+                        self.pending_gateway_provide.insert(query_id, tx);
+                        self.to_provide.insert(key, gistit);
+                    }
+                    Err(err) => {
+                        warn!("Failed to provide {}: {:?}", gistit.hash, err);
+                        let _ = tx.send(None);
+                    }
+                }
+            }
+
+            GatewayRequest::Fetch(hash, tx) => {
+                debug!("Gateway: Fetch {}", hash);
+                let key = Key::new(&hash);
+                let local = self
+                    .received
+                    .get(&key)
+                    .map(|(_, gistit)| gistit)
+                    .or_else(|| self.to_provide.get(&key))
+                    .cloned();
+                if let Some(gistit) = local {
+                    if let Err(err) = self.storage.touch(&hash) {
+                        warn!("Failed to touch {}: {:?}", hash, err);
+                    }
+                    Metrics::inc(&METRICS.fetch_success);
+                    let _ = tx.send(Some(gistit));
+                    return;
+                }
+
+                let waiting = self.pending_gateway_fetch.entry(key.clone()).or_default();
+                waiting.push(tx);
+                // Someone is already fetching it, wait along
+                if waiting.len() > 1
+                    || self.pending_receive_file.contains(&key)
+                    || self.pending_pin.contains(&key)
+                {
+                    return;
+                }
+
+                Metrics::inc(&METRICS.dht_queries);
+                let query_id = self
+                    .swarm
+                    .behaviour_mut()
+                    .kademlia
+                    .get_providers(key.clone());
+                self.pending_get_providers.insert(query_id, key);
+            }
+        }
+    }
+
+    async fn handle_swarm_event(&mut self, event: SwarmEvent<Event>) -> Result<()> {
         match event {
             SwarmEvent::Behaviour(Event::Identify(event)) => handle_identify(self, event)?,
             SwarmEvent::Behaviour(Event::Kademlia(event)) => handle_kademlia(self, event).await?,
             SwarmEvent::Behaviour(Event::RequestResponse(event)) => {
                 handle_request_response(self, event).await?;
             }
+            SwarmEvent::Behaviour(Event::Chunk(event)) => handle_chunk(self, event).await?,
+            SwarmEvent::Behaviour(Event::Push(event)) => handle_push(self, event).await?,
+            SwarmEvent::Behaviour(Event::Gossipsub(event)) => handle_gossipsub(self, event).await?,
 
             SwarmEvent::NewListenAddr { address, .. } => {
                 let peer_id = self.swarm.local_peer_id().to_string();
@@ -213,9 +1062,47 @@ impl Node {
                 peer_id, endpoint, ..
             } => {
                 info!("Connection established {:?}", peer_id);
+                if !self.acl.is_allowed(&peer_id, endpoint.get_remote_address()) {
+                    warn!("Disconnecting {:?}, denied by acl", peer_id);
+                    let _ = self.swarm.disconnect_peer_id(peer_id);
+                    return Ok(());
+                }
+                Metrics::set(
+                    &METRICS.peers_connected,
+                    self.swarm.network_info().num_peers() as u64,
+                );
                 if endpoint.is_dialer() {
                     self.pending_dial.remove(&peer_id);
                 }
+                self.bootstrap.connected(&peer_id);
+                if let Some(diagnostics) = &mut self.diagnostics {
+                    diagnostics.connected(&peer_id);
+                }
+            }
+            SwarmEvent::ConnectionClosed { peer_id, .. } => {
+                debug!("Connection closed {:?}", peer_id);
+                Metrics::set(
+                    &METRICS.peers_connected,
+                    self.swarm.network_info().num_peers() as u64,
+                );
+            }
+            SwarmEvent::ListenerClosed {
+                addresses, reason, ..
+            } => {
+                // Failed relay reservations close the relayed listener
+                let relayed = addresses
+                    .iter()
+                    .filter(|address| address.iter().any(|p| p == Protocol::P2pCircuit));
+                for address in relayed {
+                    let relay = address
+                        .iter()
+                        .take_while(|protocol| *protocol != Protocol::P2pCircuit)
+                        .last();
+                    if let Some(Protocol::P2p(relay)) = relay {
+                        warn!("Relay listener on {:?} closed: {:?}", relay, reason);
+                        self.relay_reservations.remove(&relay);
+                    }
+                }
             }
             SwarmEvent::OutgoingConnectionError {
                 peer_id: maybe_peer_id,
@@ -225,11 +1112,17 @@ impl Node {
                 error!("Outgoing connection error: {:?}", error);
                 if let Some(peer_id) = maybe_peer_id {
                     self.pending_dial.remove(&peer_id);
+                    self.bootstrap.failed(&peer_id, Instant::now());
+                    if let Some(diagnostics) = &mut self.diagnostics {
+                        diagnostics.failed(&peer_id);
+                    }
                 }
             }
             SwarmEvent::Behaviour(Event::Relay(e)) => warn!("{:?}", e),
-            SwarmEvent::Behaviour(Event::Ping(_)) => {}
-            // SwarmEvent::Behaviour(Event::Autonat(e)) => warn!("{:?}", e),
+            SwarmEvent::Behaviour(Event::Ping(event)) => log::trace!("Ping: {:?}", event),
+            SwarmEvent::Behaviour(Event::Autonat(event)) => handle_autonat(self, event),
+            SwarmEvent::Behaviour(Event::Client(event)) => handle_relay_client(self, event),
+            SwarmEvent::Behaviour(Event::Dcutr(event)) => handle_dcutr(event),
             ev => {
                 debug!("other event: {:?}", ev);
             }
@@ -243,8 +1136,12 @@ impl Node {
         match instruction.expect_request()? {
             ipc::instruction::Kind::ProvideRequest(ipc::instruction::ProvideRequest {
                 gistit: Some(gistit),
+                topics,
+                ..
             }) => {
                 warn!("Instruction: Provide gistit {}", &gistit.hash);
+                METRICS.instruction(InstructionKind::Provide);
+                Metrics::inc(&METRICS.dht_queries);
                 let key = Key::new(&gistit.hash);
 
                 let query_id = self
@@ -255,21 +1152,133 @@ impl Node {
                     .expect("to start providing");
 
                 self.pending_start_providing.insert(query_id);
+                if !topics.is_empty() {
+                    self.to_announce.insert(key.clone(), topics);
+                }
                 self.to_provide.insert(key, gistit);
             }
 
-            ipc::instruction::Kind::FetchRequest(ipc::instruction::FetchRequest { hash }) => {
+            ipc::instruction::Kind::FetchRequest(ipc::instruction::FetchRequest {
+                hash,
+                burn_after_read,
+            }) => {
                 warn!("Instruction: Get providers for {}", hash);
+                METRICS.instruction(InstructionKind::Fetch);
+
+                // Pushed to us or hosted by us, no need to ask the network
+                let key = Key::new(&hash);
+                let local = self
+                    .received
+                    .get(&key)
+                    .map(|(_, gistit)| gistit)
+                    .or_else(|| self.to_provide.get(&key))
+                    .cloned();
+                if let Some(gistit) = local {
+                    self.storage.touch(&hash)?;
+                    Metrics::inc(&METRICS.fetch_success);
+                    self.outbox
+                        .send(Instruction::respond_fetch(Some(gistit)))
+                        .await?;
+                    return Ok(());
+                }
+
+                Metrics::inc(&METRICS.dht_queries);
                 let query_id = self
                     .swarm
                     .behaviour_mut()
                     .kademlia
-                    .get_providers(Key::new(&hash));
-                self.pending_get_providers.insert(query_id);
+                    .get_providers(key.clone());
+                self.pending_get_providers.insert(query_id, key.clone());
+                if burn_after_read {
+                    self.burn_agreed.insert(key.clone());
+                }
+                self.pending_receive_file.insert(key);
+
+                self.outbox
+                    .send(Instruction::respond_fetch_progress(FetchProgressResponse {
+                        hash,
+                        ..FetchProgressResponse::default()
+                    }))
+                    .await?;
             }
 
-            ipc::instruction::Kind::StatusRequest(ipc::instruction::StatusRequest {}) => {
+            ipc::instruction::Kind::HeadRequest(ipc::instruction::HeadRequest { hash }) => {
+                warn!("Instruction: Head {}", hash);
+                METRICS.instruction(InstructionKind::Head);
+
+                let key = Key::new(&hash);
+                let local = self
+                    .received
+                    .get(&key)
+                    .map(|(_, gistit)| gistit)
+                    .or_else(|| self.to_provide.get(&key))
+                    .map(|gistit| Head::from_gistit(gistit, None));
+                if local.is_some() {
+                    self.outbox.send(Instruction::respond_head(local)).await?;
+                    return Ok(());
+                }
+
+                Metrics::inc(&METRICS.dht_queries);
+                let query_id = self
+                    .swarm
+                    .behaviour_mut()
+                    .kademlia
+                    .get_providers(key.clone());
+                self.pending_get_providers.insert(query_id, key.clone());
+                self.pending_head.insert(key, 0);
+            }
+
+            ipc::instruction::Kind::ExistsRequest(ipc::instruction::ExistsRequest { hash }) => {
+                warn!("Instruction: Exists {}", hash);
+                METRICS.instruction(InstructionKind::Exists);
+
+                let exists = self.to_provide.contains_key(&Key::new(&hash));
+                self.outbox
+                    .send(Instruction::respond_exists(exists))
+                    .await?;
+            }
+
+            ipc::instruction::Kind::ResolvePrefixRequest(
+                ipc::instruction::ResolvePrefixRequest { prefix },
+            ) => {
+                warn!("Instruction: Resolve prefix {}", prefix);
+                METRICS.instruction(InstructionKind::ResolvePrefix);
+
+                // Only what we hold, the DHT can't be searched by prefix
+                let mut hashes: Vec<String> = self
+                    .to_provide
+                    .values()
+                    .chain(self.received.values().map(|(_, gistit)| gistit))
+                    .map(|gistit| &gistit.hash)
+                    .filter(|hash| hash.starts_with(&prefix))
+                    .cloned()
+                    .collect();
+                hashes.sort_unstable();
+                hashes.dedup();
+                self.outbox
+                    .send(Instruction::respond_resolve_prefix(hashes))
+                    .await?;
+            }
+
+            ipc::instruction::Kind::CancelRequest(ipc::instruction::CancelRequest { hash }) => {
+                warn!("Instruction: Cancel {}", hash);
+                METRICS.instruction(InstructionKind::Cancel);
+
+                let key = Key::new(&hash);
+                self.pending_head.remove(&key);
+                self.pending_receive_file.remove(&key);
+                // A pin or the gateway may still want it
+                if !self.wants_content(&key) {
+                    self.burn_agreed.remove(&key);
+                    if let Some(download) = self.downloads.get_mut(&key) {
+                        download.interrupt();
+                    }
+                }
+            }
+
+            ipc::instruction::Kind::StatusRequest(ipc::instruction::StatusRequest { peers }) => {
                 warn!("Instruction: Status");
+                METRICS.instruction(InstructionKind::Status);
 
                 let network_info = self.swarm.network_info();
 
@@ -277,26 +1286,381 @@ impl Node {
                 let peer_count = network_info.num_peers() as u32;
                 let pending_connections = network_info.connection_counters().num_pending();
                 let hosting = self.to_provide.len() as u32;
+                let reachability = reachability(self.swarm.behaviour().autonat.nat_status());
+                let acl = self.acl.rules().map(ToString::to_string).collect();
+                let peers = if peers {
+                    self.reputation
+                        .best(STATUS_PEERS_MAX)
+                        .into_iter()
+                        .map(|(peer, score)| ipc::instruction::PeerScore {
+                            peer_id: peer.to_string(),
+                            score: score.score(),
+                            successes: score.successes,
+                            failures: score.failures,
+                            latency: score.latency.unwrap_or(0),
+                            bandwidth: score.bandwidth.unwrap_or(0),
+                        })
+                        .collect()
+                } else {
+                    Vec::new()
+                };
 
-                self.bridge.connect_blocking()?;
-                self.bridge
+                self.outbox
                     .send(Instruction::respond_status(
-                        peer_id,
-                        peer_count,
-                        pending_connections,
-                        hosting,
+                        ipc::instruction::StatusResponse {
+                            peer_id,
+                            peer_count,
+                            pending_connections,
+                            hosting,
+                            reachability,
+                            acl,
+                            limits: Some(self.limits()),
+                            bytes_sent: METRICS.bytes_served.load(Ordering::Relaxed),
+                            bytes_received: METRICS.bytes_received.load(Ordering::Relaxed),
+                            rate_limited: METRICS.rate_limited.load(Ordering::Relaxed),
+                            outbox_depth: METRICS.outbox_depth.load(Ordering::Relaxed) as u32,
+                            outbox_capacity: OUTBOX_SIZE as u32,
+                            events_dropped: METRICS.events_dropped.load(Ordering::Relaxed),
+                            peers,
+                        },
                     ))
                     .await?;
             }
 
             ipc::instruction::Kind::DialRequest(ipc::instruction::DialRequest { address }) => {
                 warn!("Instruction: Dial");
+                METRICS.instruction(InstructionKind::Dial);
                 let multiaddr: Multiaddr = address.parse()?;
                 self.swarm.dial(multiaddr)?;
             }
 
+            ipc::instruction::Kind::TailLogsRequest(ipc::instruction::TailLogsRequest {
+                offset,
+            }) => {
+                debug!("Instruction: Tail logs");
+                METRICS.instruction(InstructionKind::TailLogs);
+                let (data, next) = logger::read_chunk(&self.runtime_path, offset)?;
+
+                self.outbox
+                    .send(Instruction::respond_log_chunk(data, next))
+                    .await?;
+            }
+
+            ipc::instruction::Kind::RevokeRequest(ipc::instruction::RevokeRequest { hash }) => {
+                warn!("Instruction: Revoke gistit {}", hash);
+                METRICS.instruction(InstructionKind::Revoke);
+                let revoked = self.revoke(&Key::new(&hash));
+
+                self.outbox
+                    .send(Instruction::respond_revoke(revoked))
+                    .await?;
+            }
+
+            ipc::instruction::Kind::PushRequest(ipc::instruction::PushRequest {
+                gistit: Some(gistit),
+                peer_id,
+            }) => {
+                warn!("Instruction: Push gistit {} to {}", gistit.hash, peer_id);
+                METRICS.instruction(InstructionKind::Push);
+                Metrics::add(&METRICS.bytes_served, gistit.encoded_len() as u64);
+                let peer = PeerId::from_str(&peer_id)
+                    .map_err(|_| Error::Parse("failed to parse push peer id"))?;
+
+                // Reach the peer through our relays too, in case it's behind a NAT
+                for relay in &self.relays {
+                    if relay.iter().any(|protocol| protocol == Protocol::P2p(peer)) {
+                        continue;
+                    }
+
+                    self.swarm
+                        .add_peer_address(peer, relay.clone().with(Protocol::P2p(peer)));
+                }
+
+                let request_id = self
+                    .swarm
+                    .behaviour_mut()
+                    .push
+                    .send_request(&peer, PushRequest(gistit));
+                self.pending_push.insert(request_id);
+            }
+
+            ipc::instruction::Kind::AclRequest(ipc::instruction::AclRequest { add, rule }) => {
+                warn!(
+                    "Instruction: Acl {} '{}'",
+                    if add { "add" } else { "remove" },
+                    rule
+                );
+                METRICS.instruction(InstructionKind::Acl);
+                let changed = match rule.parse::<Rule>() {
+                    Ok(rule) => self.update_acl(add, rule),
+                    Err(err) => {
+                        warn!("Invalid acl rule '{}': {}", rule, err);
+                        false
+                    }
+                };
+
+                self.outbox.send(Instruction::respond_acl(changed)).await?;
+            }
+
+            ipc::instruction::Kind::LimitsRequest(ipc::instruction::LimitsRequest {
+                upload_rate,
+                download_rate,
+                peer_request_rate,
+            }) => {
+                warn!("Instruction: Limits");
+                METRICS.instruction(InstructionKind::Limits);
+                if let Some(rate) = upload_rate {
+                    self.bandwidth.upload.set_rate(rate);
+                }
+                if let Some(rate) = download_rate {
+                    self.bandwidth.download.set_rate(rate);
+                }
+                if let Some(rate) = peer_request_rate {
+                    self.peer_rate.set_rate(rate);
+                }
+                info!("Limits changed to {:?}", self.limits());
+
+                self.outbox
+                    .send(Instruction::respond_limits(self.limits()))
+                    .await?;
+            }
+
+            ipc::instruction::Kind::PublishNameRequest(ipc::instruction::PublishNameRequest {
+                name,
+                hash,
+            }) => {
+                warn!("Instruction: Publish {} as {}", hash, name);
+                METRICS.instruction(InstructionKind::PublishName);
+
+                let published = name::validate(&name).and_then(|_| self.publish_name(&name, &hash));
+                match published {
+                    Ok(query_id) => {
+                        self.pending_publish_name.insert(query_id);
+                        self.names.insert(name, hash);
+                        name::save(&self.config_path, &self.names)?;
+                    }
+                    Err(err) => {
+                        warn!("Failed to publish {}: {:?}", name, err);
+                        self.outbox
+                            .send(Instruction::respond_publish_name(false))
+                            .await?;
+                    }
+                }
+            }
+
+            ipc::instruction::Kind::ResolveNameRequest(ipc::instruction::ResolveNameRequest {
+                name,
+            }) => {
+                warn!("Instruction: Resolve {}", name);
+                METRICS.instruction(InstructionKind::ResolveName);
+
+                if name::validate(&name).is_err() {
+                    self.outbox
+                        .send(Instruction::respond_resolve_name(None, String::new()))
+                        .await?;
+                    return Ok(());
+                }
+
+                Metrics::inc(&METRICS.dht_queries);
+                let query_id = self
+                    .swarm
+                    .behaviour_mut()
+                    .kademlia
+                    .get_record(name::key(&name));
+                self.pending_resolve_name.insert(query_id);
+            }
+
+            ipc::instruction::Kind::PinRequest(ipc::instruction::PinRequest { hash, pin }) => {
+                warn!(
+                    "Instruction: {} {}",
+                    if pin { "Pin" } else { "Unpin" },
+                    hash
+                );
+                METRICS.instruction(InstructionKind::Pin);
+                let key = Key::new(&hash);
+
+                let changed = if !pin {
+                    let changed = self.storage.set_pinned(&hash, false)?;
+                    for hash in self.storage.evict()? {
+                        self.revoke(&Key::new(&hash));
+                    }
+                    changed
+                } else if self.storage.contains(&hash) {
+                    self.storage.set_pinned(&hash, true).unwrap_or_else(|err| {
+                        warn!("Failed to pin {}: {:?}", hash, err);
+                        false
+                    })
+                } else if let Some(gistit) = self
+                    .received
+                    .get(&key)
+                    .map(|(_, gistit)| gistit)
+                    .or_else(|| self.to_provide.get(&key))
+                    .cloned()
+                {
+                    match self.store(gistit, true) {
+                        Ok(()) => true,
+                        Err(err) => {
+                            warn!("Failed to pin {}: {:?}", hash, err);
+                            false
+                        }
+                    }
+                } else {
+                    // Answered once the download completes or fails
+                    Metrics::inc(&METRICS.dht_queries);
+                    let query_id = self
+                        .swarm
+                        .behaviour_mut()
+                        .kademlia
+                        .get_providers(key.clone());
+                    self.pending_get_providers.insert(query_id, key.clone());
+                    self.pending_pin.insert(key);
+                    return Ok(());
+                };
+
+                self.outbox.send(Instruction::respond_pin(changed)).await?;
+            }
+
+            ipc::instruction::Kind::ListPinsRequest(ipc::instruction::ListPinsRequest {}) => {
+                warn!("Instruction: List pins");
+                METRICS.instruction(InstructionKind::ListPins);
+
+                let pins = self
+                    .storage
+                    .pinned()
+                    .into_iter()
+                    .map(|(hash, entry)| ipc::instruction::Pin {
+                        hash: hash.clone(),
+                        size: entry.size,
+                        last_access: entry.last_access,
+                    })
+                    .collect();
+
+                self.outbox
+                    .send(Instruction::respond_list_pins(
+                        pins,
+                        self.storage.used(),
+                        self.storage.quota(),
+                    ))
+                    .await?;
+            }
+
+            ipc::instruction::Kind::DiagnosticsRequest(ipc::instruction::DiagnosticsRequest {}) => {
+                warn!("Instruction: Diagnostics");
+                METRICS.instruction(InstructionKind::Diagnostics);
+
+                // Answered from the event loop, once the dials complete or time out
+                let peers: Vec<(PeerId, Multiaddr)> = self
+                    .bootstrap
+                    .peers()
+                    .map(|(peer, address)| (*peer, address.clone()))
+                    .collect();
+                let mut diagnostics =
+                    Diagnostics::new(peers.iter().map(|(peer, _)| *peer), Instant::now());
+
+                for (peer, address) in peers {
+                    if self.swarm.is_connected(&peer) {
+                        diagnostics.connected(&peer);
+                    } else if let Err(err) = self.swarm.dial(address) {
+                        warn!("Failed to dial bootstrap peer {:?}: {:?}", peer, err);
+                        diagnostics.failed(&peer);
+                    }
+                }
+                self.diagnostics = Some(diagnostics);
+            }
+
+            ipc::instruction::Kind::InboxRequest(ipc::instruction::InboxRequest {}) => {
+                warn!("Instruction: List inbox");
+                METRICS.instruction(InstructionKind::Inbox);
+
+                self.outbox
+                    .send(Instruction::respond_inbox(self.inbox.items()))
+                    .await?;
+            }
+
+            ipc::instruction::Kind::InboxDecisionRequest(
+                ipc::instruction::InboxDecisionRequest { id, accept },
+            ) => {
+                warn!("Instruction: Inbox decision {}, accept: {}", id, accept);
+                METRICS.instruction(InstructionKind::InboxDecision);
+
+                let item = self.inbox.take(id);
+                let hash = item.as_ref().map(|item| item.gistit.hash.clone());
+                if let (Some(item), true) = (item, accept) {
+                    info!("Accepted {} pushed by {:?}", item.gistit.hash, item.peer);
+                    self.received.insert(
+                        Key::new(&item.gistit.hash),
+                        (item.peer, item.gistit.clone()),
+                    );
+                    if let Err(err) = self.store(item.gistit, false) {
+                        warn!("Failed to store accepted gistit: {:?}", err);
+                    }
+                }
+
+                self.outbox
+                    .send(Instruction::respond_inbox_decision(hash))
+                    .await?;
+            }
+
+            ipc::instruction::Kind::SubscribeRequest(ipc::instruction::SubscribeRequest {
+                topics,
+            }) => {
+                warn!("Instruction: Subscribe to {:?}", topics);
+                METRICS.instruction(InstructionKind::Subscribe);
+                self.subscribe(&topics);
+            }
+
+            ipc::instruction::Kind::ReloadConfigRequest(
+                ipc::instruction::ReloadConfigRequest {},
+            ) => {
+                warn!("Instruction: Reload config");
+                METRICS.instruction(InstructionKind::ReloadConfig);
+
+                let response = match self.reload_config() {
+                    Ok(needs_restart) => Instruction::respond_reload_config(vec![], needs_restart),
+                    Err(err) => {
+                        warn!("Config not reloaded: {}", err);
+                        let errors = match err {
+                            Error::Config(errors) => errors,
+                            err => vec![err.to_string()],
+                        };
+                        Instruction::respond_reload_config(errors, vec![])
+                    }
+                };
+                self.outbox.send(response).await?;
+            }
+
+            ipc::instruction::Kind::LogLevelRequest(ipc::instruction::LogLevelRequest {
+                level,
+            }) => {
+                warn!("Instruction: Log level {:?}", level);
+                METRICS.instruction(InstructionKind::LogLevel);
+
+                match level.as_deref().map(str::parse::<LevelFilter>) {
+                    Some(Ok(level)) => {
+                        logger::set_level(level);
+                        info!("Log level changed to {}", logger::level());
+                    }
+                    Some(Err(_)) => warn!("Invalid log level {:?}", level),
+                    None => (),
+                }
+
+                self.outbox
+                    .send(Instruction::respond_log_level(
+                        logger::level().to_string().to_lowercase(),
+                        logger::level_from_env(),
+                    ))
+                    .await?;
+            }
+
             ipc::instruction::Kind::ShutdownRequest(ipc::instruction::ShutdownRequest {}) => {
                 warn!("Exiting...");
+                METRICS.instruction(InstructionKind::Shutdown);
+
+                let keys: Vec<Key> = self.to_provide.keys().cloned().collect();
+                for key in &keys {
+                    self.revoke(key);
+                }
+                self.save_reputation();
                 std::process::exit(0);
             }
 
@@ -305,3 +1669,41 @@ impl Node {
         Ok(())
     }
 }
+
+/// SIGHUP, asking to reload the config file. Never received outside unix
+struct Hangup {
+    #[cfg(unix)]
+    signal: tokio::signal::unix::Signal,
+}
+
+impl Hangup {
+    fn new() -> Result<Self> {
+        Ok(Self {
+            #[cfg(unix)]
+            signal: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?,
+        })
+    }
+
+    async fn recv(&mut self) {
+        #[cfg(unix)]
+        self.signal.recv().await;
+        #[cfg(not(unix))]
+        std::future::pending::<()>().await;
+    }
+}
+
+/// Whether the gistit hash matches it's content
+fn verify(gistit: &Gistit) -> bool {
+    gistit.inner.first().map_or(false, |inner| {
+        payload::hash(&gistit.author, gistit.description.as_deref(), &inner.data) == gistit.hash
+    })
+}
+
+/// Human readable AutoNAT status, reported through `StatusResponse`
+fn reachability(status: NatStatus) -> String {
+    match status {
+        NatStatus::Public(address) => format!("public ({})", address),
+        NatStatus::Private => "private (relayed)".to_owned(),
+        NatStatus::Unknown => "unknown".to_owned(),
+    }
+}
@@ -0,0 +1,100 @@
+//! The notify module
+//!
+//! Opt-in desktop notifications, enabled with `--notify`, when a hosted gistit is fetched, a push
+//! lands in the inbox or the node stops being publicly reachable. Notifications are shown off the
+//! event loop, failures (e.g. no notification server) are only logged.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use libp2p::autonat::NatStatus;
+use libp2p::core::PeerId;
+use log::warn;
+use notify_rust::Notification;
+
+/// A hosted gistit fetched again within this interval isn't notified twice
+const FETCHED_NOTIFY_INTERVAL: Duration = Duration::from_secs(60);
+
+const APP_NAME: &str = "gistit";
+
+#[derive(Debug, Default)]
+pub struct Notifier {
+    enabled: bool,
+    /// When each hosted gistit was last notified as fetched
+    last_fetched: HashMap<String, Instant>,
+}
+
+impl Notifier {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            last_fetched: HashMap::new(),
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn fetched(&mut self, hash: &str, peer: &PeerId, now: Instant) {
+        if !self.enabled {
+            return;
+        }
+        self.last_fetched
+            .retain(|_, at| now.duration_since(*at) < FETCHED_NOTIFY_INTERVAL);
+        if self.last_fetched.contains_key(hash) {
+            return;
+        }
+        self.last_fetched.insert(hash.to_owned(), now);
+
+        self.show(
+            "Gistit fetched".to_owned(),
+            format!("{} was fetched by {}", short(hash), peer),
+        );
+    }
+
+    pub fn pushed(&self, hash: &str, peer: &PeerId, inbox_id: u32) {
+        self.show(
+            "Gistit pushed to your inbox".to_owned(),
+            format!(
+                "{} pushed by {}, accept it with `gistit inbox accept {}`",
+                short(hash),
+                peer,
+                inbox_id
+            ),
+        );
+    }
+
+    /// Notifies when the node goes from public to private or unknown reachability
+    pub fn reachability_changed(&self, old: &NatStatus, new: &NatStatus) {
+        if let (NatStatus::Public(_), NatStatus::Private | NatStatus::Unknown) = (old, new) {
+            self.show(
+                "Gistit node lost reachability".to_owned(),
+                "Peers can only reach you through relays now, run `gistit daemon doctor`"
+                    .to_owned(),
+            );
+        }
+    }
+
+    fn show(&self, summary: String, body: String) {
+        if !self.enabled {
+            return;
+        }
+
+        // Showing a notification may block on the notification server
+        tokio::task::spawn_blocking(move || {
+            if let Err(err) = Notification::new()
+                .appname(APP_NAME)
+                .summary(&summary)
+                .body(&body)
+                .show()
+            {
+                warn!("Failed to show notification: {:?}", err);
+            }
+        });
+    }
+}
+
+fn short(hash: &str) -> &str {
+    hash.get(..12).unwrap_or(hash)
+}
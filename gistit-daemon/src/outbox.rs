@@ -0,0 +1,81 @@
+//! The outbox module
+//!
+//! Instructions for the client are written by a task of their own, fed by the event loop through
+//! a bounded queue. Responses wait for room in the queue, holding the event loop back until the
+//! client catches up, while events nobody waits an answer for (fetch progress, announcements,
+//! incoming pushes) are dropped once it's full. A client that stops reading can neither make the
+//! daemon buffer without bound nor stall it for longer than [`SEND_TIMEOUT`] per instruction.
+#![allow(clippy::missing_errors_doc)]
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::{debug, warn};
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+use gistit_ipc::{Bridge, Server};
+use gistit_proto::Instruction;
+
+use crate::metrics::{Metrics, METRICS};
+use crate::{Error, Result};
+
+/// Instructions waiting to be written to the client
+pub const OUTBOX_SIZE: usize = 256;
+
+/// Longest a single write to the client may take before the instruction is dropped
+const SEND_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone)]
+pub struct Outbox {
+    tx: mpsc::Sender<Instruction>,
+}
+
+impl Outbox {
+    /// Spawns the task writing to the client on the other end of `bridge`
+    pub fn spawn(bridge: Arc<Bridge<Server>>) -> Self {
+        let (tx, mut rx) = mpsc::channel::<Instruction>(OUTBOX_SIZE);
+
+        tokio::spawn(async move {
+            while let Some(instruction) = rx.recv().await {
+                Metrics::dec(&METRICS.outbox_depth);
+                match tokio::time::timeout(SEND_TIMEOUT, write(&bridge, instruction)).await {
+                    Ok(Ok(())) => (),
+                    Ok(Err(err)) => warn!("Failed to write to the client: {:?}", err),
+                    Err(_) => warn!("Client isn't reading, instruction dropped"),
+                }
+            }
+        });
+
+        Self { tx }
+    }
+
+    /// Queues a response, waiting for room while the client is behind
+    pub async fn send(&self, instruction: Instruction) -> Result<()> {
+        Metrics::inc(&METRICS.outbox_depth);
+        if self.tx.send(instruction).await.is_err() {
+            Metrics::dec(&METRICS.outbox_depth);
+            return Err(Error::Outbox("writer task stopped"));
+        }
+        Ok(())
+    }
+
+    /// Queues an event, dropping it if the queue is full
+    pub fn push_event(&self, instruction: Instruction) {
+        Metrics::inc(&METRICS.outbox_depth);
+        match self.tx.try_send(instruction) {
+            Ok(()) => (),
+            Err(TrySendError::Full(_)) => {
+                Metrics::dec(&METRICS.outbox_depth);
+                Metrics::inc(&METRICS.events_dropped);
+                debug!("Outbox full, event dropped");
+            }
+            Err(TrySendError::Closed(_)) => Metrics::dec(&METRICS.outbox_depth),
+        }
+    }
+}
+
+async fn write(bridge: &Bridge<Server>, instruction: Instruction) -> Result<()> {
+    bridge.connect_blocking()?;
+    bridge.send(instruction).await?;
+    Ok(())
+}
@@ -0,0 +1,176 @@
+//! The reputation module
+//!
+//! Providers are scored on past downloads: the share of requests they answered right, how fast
+//! they answered a manifest request and the bandwidth chunks came in at. Downloads ask the best
+//! scored providers first and fall back to the others as they fail. Scores are kept in
+//! [`PEERS_FILE_NAME`] inside the data directory, so they outlive restarts.
+#![allow(clippy::missing_errors_doc)]
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use libp2p::core::PeerId;
+use serde::{Deserialize, Serialize};
+
+use crate::chunk::CHUNK_SIZE;
+use crate::Result;
+
+/// Provider scores, inside the data directory
+pub const PEERS_FILE_NAME: &str = "peers.json";
+
+/// Peers kept, the ones seen least recently are forgotten past it
+const MAX_PEERS: usize = 1024;
+
+/// Latency of a peer never measured, in milliseconds
+const DEFAULT_LATENCY: u64 = 500;
+
+/// Bandwidth of a peer never measured, in bytes per second
+const DEFAULT_BANDWIDTH: u64 = 64 * 1024;
+
+/// Weight, out of 4, of the last measurement in the averages
+const LAST_WEIGHT: u64 = 1;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerScore {
+    pub successes: u64,
+    pub failures: u64,
+    /// Milliseconds to answer a manifest request, moving average
+    pub latency: Option<u64>,
+    /// Bytes per second chunks came in at, moving average
+    pub bandwidth: Option<u64>,
+    /// Unix timestamp, in seconds
+    pub last_seen: u64,
+}
+
+impl PeerScore {
+    /// Chunks a second the peer is expected to deliver, the time to get one being it's latency
+    /// plus the chunk size over it's bandwidth, weighted by the odds a request succeeds. Unknown
+    /// peers are neither good nor bad, so they still get asked
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn score(&self) -> f64 {
+        let reliability = (self.successes + 1) as f64 / (self.successes + self.failures + 2) as f64;
+        let latency = self.latency.unwrap_or(DEFAULT_LATENCY) as f64 / 1000.0;
+        let bandwidth = self.bandwidth.unwrap_or(DEFAULT_BANDWIDTH).max(1) as f64;
+
+        reliability / (latency + CHUNK_SIZE as f64 / bandwidth)
+    }
+}
+
+/// `average` moved towards `value`
+fn smooth(average: Option<u64>, value: u64) -> u64 {
+    average.map_or(value, |average| {
+        (average * (4 - LAST_WEIGHT) + value * LAST_WEIGHT) / 4
+    })
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Check your system time")
+        .as_secs()
+}
+
+#[derive(Debug)]
+pub struct Reputation {
+    path: PathBuf,
+    peers: HashMap<PeerId, PeerScore>,
+    /// Scores changed since they were last saved
+    changed: bool,
+}
+
+impl Reputation {
+    /// Reads the scores saved in `data_path`, if any
+    pub fn open(data_path: &Path) -> Result<Self> {
+        let path = data_path.join(PEERS_FILE_NAME);
+        let peers = if fs::metadata(&path).is_ok() {
+            let saved: HashMap<String, PeerScore> =
+                serde_json::from_str(&fs::read_to_string(&path)?)?;
+            saved
+                .into_iter()
+                .filter_map(|(peer, score)| Some((peer.parse().ok()?, score)))
+                .collect()
+        } else {
+            HashMap::new()
+        };
+
+        Ok(Self {
+            path,
+            peers,
+            changed: false,
+        })
+    }
+
+    /// The score of `peer`, see [`PeerScore::score`]
+    pub fn score(&self, peer: &PeerId) -> f64 {
+        self.peers.get(peer).copied().unwrap_or_default().score()
+    }
+
+    fn entry(&mut self, peer: PeerId) -> &mut PeerScore {
+        self.changed = true;
+        let score = self.peers.entry(peer).or_default();
+        score.last_seen = now();
+        score
+    }
+
+    /// `peer` answered a manifest request after `elapsed`
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn answered(&mut self, peer: PeerId, elapsed: Duration) {
+        let score = self.entry(peer);
+        score.successes += 1;
+        score.latency = Some(smooth(score.latency, elapsed.as_millis() as u64));
+    }
+
+    /// `peer` sent a valid chunk of `bytes` after `elapsed`
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn transferred(&mut self, peer: PeerId, bytes: usize, elapsed: Duration) {
+        let rate = bytes as u64 * 1000 / (elapsed.as_millis() as u64).max(1);
+        let score = self.entry(peer);
+        score.successes += 1;
+        score.bandwidth = Some(smooth(score.bandwidth, rate));
+    }
+
+    /// `peer` didn't answer, didn't have what it provides or sent something invalid
+    pub fn failed(&mut self, peer: PeerId) {
+        self.entry(peer).failures += 1;
+    }
+
+    /// Up to `count` peers, best scored first
+    pub fn best(&self, count: usize) -> Vec<(PeerId, PeerScore)> {
+        let mut peers: Vec<(PeerId, PeerScore)> = self
+            .peers
+            .iter()
+            .map(|(peer, score)| (*peer, *score))
+            .collect();
+        peers.sort_by(|(_, a), (_, b)| b.score().total_cmp(&a.score()));
+        peers.truncate(count);
+        peers
+    }
+
+    /// Writes the scores if they changed, forgetting the peers seen least recently past
+    /// [`MAX_PEERS`]
+    pub fn save(&mut self) -> Result<()> {
+        if !self.changed {
+            return Ok(());
+        }
+
+        if self.peers.len() > MAX_PEERS {
+            let mut last_seen: Vec<u64> =
+                self.peers.values().map(|score| score.last_seen).collect();
+            last_seen.sort_unstable_by(|a, b| b.cmp(a));
+            let oldest_kept = last_seen[MAX_PEERS - 1];
+            self.peers.retain(|_, score| score.last_seen >= oldest_kept);
+        }
+
+        let saved: HashMap<String, PeerScore> = self
+            .peers
+            .iter()
+            .map(|(peer, score)| (peer.to_base58(), *score))
+            .collect();
+        fs::write(&self.path, serde_json::to_string(&saved)?)?;
+        self.changed = false;
+        Ok(())
+    }
+}
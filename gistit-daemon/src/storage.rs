@@ -0,0 +1,208 @@
+//! The storage module
+//!
+//! Fetched and pinned gistits are kept on disk, inside [`STORAGE_DIR_NAME`] in the data directory,
+//! and served again after a restart. Pinned gistits stay until unpinned, the rest is a cache: once
+//! the stored bytes go over the quota the least recently accessed unpinned gistits are evicted.
+#![allow(clippy::missing_errors_doc)]
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use gistit_proto::prost::Message;
+use gistit_proto::Gistit;
+
+use crate::{Error, Result};
+
+/// Stored gistits, inside the data directory
+pub const STORAGE_DIR_NAME: &str = "store";
+
+/// Quota used when none is given, in bytes
+pub const DEFAULT_STORAGE_QUOTA: u64 = 256 * 1024 * 1024;
+
+const INDEX_FILE_NAME: &str = "index.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    /// Bytes
+    pub size: u64,
+    pub pinned: bool,
+    /// Unix timestamp, in seconds
+    pub last_access: u64,
+}
+
+#[derive(Debug)]
+pub struct Storage {
+    path: PathBuf,
+    quota: u64,
+    entries: HashMap<String, Entry>,
+}
+
+impl Storage {
+    /// Opens the storage inside `data_path`, creating it on first run
+    pub fn open(data_path: &Path, quota: u64) -> Result<Self> {
+        let path = data_path.join(STORAGE_DIR_NAME);
+        fs::create_dir_all(&path)?;
+
+        let index = path.join(INDEX_FILE_NAME);
+        let entries = if fs::metadata(&index).is_ok() {
+            serde_json::from_str(&fs::read_to_string(index)?)?
+        } else {
+            HashMap::new()
+        };
+
+        Ok(Self {
+            path,
+            quota,
+            entries,
+        })
+    }
+
+    pub fn contains(&self, hash: &str) -> bool {
+        self.entries.contains_key(hash)
+    }
+
+    pub fn hashes(&self) -> impl Iterator<Item = &String> {
+        self.entries.keys()
+    }
+
+    /// Reads a stored gistit, without counting it as an access
+    pub fn read(&self, hash: &str) -> Result<Option<Gistit>> {
+        if !self.contains(hash) {
+            return Ok(None);
+        }
+        Ok(Some(Gistit::from_bytes(fs::read(self.path.join(hash))?)?))
+    }
+
+    /// Marks `hash` as just accessed, pushing it back in the eviction order
+    pub fn touch(&mut self, hash: &str) -> Result<()> {
+        if let Some(entry) = self.entries.get_mut(hash) {
+            entry.last_access = now();
+            self.save_index()?;
+        }
+        Ok(())
+    }
+
+    /// Stores `gistit`, keeping it pinned if it already was. Returns the hashes evicted to make
+    /// room for it
+    pub fn insert(&mut self, gistit: &Gistit, pinned: bool) -> Result<Vec<String>> {
+        let bytes = gistit.encode_to_vec();
+        let existing = self.entries.get(&gistit.hash).filter(|e| e.pinned);
+        let pinned = pinned || existing.is_some();
+        let other_pins = self.pinned_size() - existing.map_or(0, |e| e.size);
+        if pinned && other_pins + bytes.len() as u64 > self.quota {
+            return Err(Error::Storage(
+                "pinned gistits would go over the storage quota",
+            ));
+        }
+
+        fs::write(self.path.join(&gistit.hash), &bytes)?;
+        self.entries.insert(
+            gistit.hash.clone(),
+            Entry {
+                size: bytes.len() as u64,
+                pinned,
+                last_access: now(),
+            },
+        );
+
+        let evicted = self.evict()?;
+        self.save_index()?;
+        Ok(evicted)
+    }
+
+    /// Pins or unpins a stored gistit, returning `false` if it's missing or already in that state
+    pub fn set_pinned(&mut self, hash: &str, pinned: bool) -> Result<bool> {
+        let pinned_size = self.pinned_size();
+        match self.entries.get_mut(hash) {
+            Some(entry) if entry.pinned != pinned => {
+                if pinned && pinned_size + entry.size > self.quota {
+                    return Err(Error::Storage(
+                        "pinned gistits would go over the storage quota",
+                    ));
+                }
+                entry.pinned = pinned;
+            }
+            _ => return Ok(false),
+        }
+
+        self.save_index()?;
+        Ok(true)
+    }
+
+    /// Pinned gistits, most recently accessed first
+    pub fn pinned(&self) -> Vec<(&String, &Entry)> {
+        let mut pinned: Vec<(&String, &Entry)> =
+            self.entries.iter().filter(|(_, e)| e.pinned).collect();
+        pinned.sort_by(|(_, a), (_, b)| b.last_access.cmp(&a.last_access));
+        pinned
+    }
+
+    /// Stored bytes, pinned and cached
+    pub fn used(&self) -> u64 {
+        self.entries.values().map(|e| e.size).sum()
+    }
+
+    pub const fn quota(&self) -> u64 {
+        self.quota
+    }
+
+    /// Takes effect on the next [`Self::evict`]
+    pub fn set_quota(&mut self, quota: u64) {
+        self.quota = quota;
+    }
+
+    /// Removes the least recently accessed unpinned gistits until the quota is met
+    pub fn evict(&mut self) -> Result<Vec<String>> {
+        let mut cached: Vec<(String, Entry)> = self
+            .entries
+            .iter()
+            .filter(|(_, e)| !e.pinned)
+            .map(|(hash, e)| (hash.clone(), *e))
+            .collect();
+        cached.sort_by_key(|(_, e)| e.last_access);
+
+        let mut used = self.used();
+        let mut evicted = Vec::new();
+        for (hash, entry) in cached {
+            if used <= self.quota {
+                break;
+            }
+            fs::remove_file(self.path.join(&hash))?;
+            self.entries.remove(&hash);
+            used -= entry.size;
+            evicted.push(hash);
+        }
+
+        if !evicted.is_empty() {
+            self.save_index()?;
+        }
+        Ok(evicted)
+    }
+
+    fn pinned_size(&self) -> u64 {
+        self.entries
+            .values()
+            .filter(|e| e.pinned)
+            .map(|e| e.size)
+            .sum()
+    }
+
+    fn save_index(&self) -> Result<()> {
+        fs::write(
+            self.path.join(INDEX_FILE_NAME),
+            serde_json::to_string(&self.entries)?,
+        )?;
+        Ok(())
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Check your system time")
+        .as_secs()
+}
@@ -0,0 +1,11 @@
+fn main() {
+    let crate_dir = std::env::var("CARGO_MANIFEST_DIR").expect("cargo to set the manifest dir");
+    let config = cbindgen::Config::from_file(format!("{}/cbindgen.toml", crate_dir))
+        .expect("valid cbindgen.toml");
+
+    cbindgen::generate_with_config(&crate_dir, config)
+        .expect("to generate C bindings")
+        .write_to_file(format!("{}/include/gistit.h", crate_dir));
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+}
@@ -0,0 +1,48 @@
+/* Generated by cbindgen from gistit-ffi, do not edit */
+
+#ifndef GISTIT_H
+#define GISTIT_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Sends a gistit to the default server, returning it's hash.
+//
+// `secret` may be `NULL`, otherwise the gistit is encrypted with it before it's sent.
+// Returns `NULL` on failure.
+//
+// # Safety
+//
+// `name`, `data`, `author` and `secret` must be `NULL` or valid nul terminated strings.
+char *gistit_send(const char *name, const char *data, const char *author, const char *secret);
+
+// Fetches a gistit from the default server, returning it's contents.
+//
+// `secret` may be `NULL`, it's needed for gistits sent encrypted. Returns `NULL` on failure.
+//
+// # Safety
+//
+// `hash` and `secret` must be `NULL` or valid nul terminated strings.
+char *gistit_fetch(const char *hash, const char *secret);
+
+// Releases a string returned by `gistit_send` or `gistit_fetch`. `NULL` is ignored.
+//
+// # Safety
+//
+// `value` must be `NULL` or returned by this library, and not freed before.
+void gistit_free(char *value);
+
+// The last error on this thread, `NULL` if none. Owned by the library, valid until the next call.
+const char *gistit_last_error(void);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* GISTIT_H */
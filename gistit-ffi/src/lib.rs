@@ -0,0 +1,153 @@
+//
+//   ________.__          __  .__  __
+//  /  _____/|__| _______/  |_|__|/  |_
+// /   \  ___|  |/  ___/\   __\  \   __\
+// \    \_\  \  |\___ \  |  | |  ||  |
+//  \______  /__/____  > |__| |__||__|
+//         \/        \/
+//
+#![warn(clippy::all, clippy::pedantic, clippy::nursery, clippy::cargo)]
+#![allow(clippy::multiple_crate_versions)]
+
+//! C bindings to `gistit-core`, the header is generated by cbindgen at `include/gistit.h`.
+//!
+//! Strings returned by these functions are owned by the caller and released with `gistit_free`.
+//! On failure they return `NULL` and `gistit_last_error` describes what went wrong.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::future::Future;
+use std::os::raw::c_char;
+use std::ptr;
+
+use gistit_core::{FetchRequest, SendRequest};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+fn set_last_error(err: impl ToString) {
+    let message =
+        CString::new(err.to_string().replace('\0', "")).expect("interior nul bytes to be removed");
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+/// Borrows a C string argument, `None` if it's `NULL` or not UTF-8
+unsafe fn str_arg<'a>(ptr: *const c_char) -> Option<&'a str> {
+    if ptr.is_null() {
+        None
+    } else {
+        CStr::from_ptr(ptr).to_str().ok()
+    }
+}
+
+/// Hands a string over to the caller, `NULL` if it contains a nul byte
+fn into_raw(value: String) -> *mut c_char {
+    CString::new(value).map_or_else(
+        |err| {
+            set_last_error(err);
+            ptr::null_mut()
+        },
+        CString::into_raw,
+    )
+}
+
+/// Calls are blocking, each one runs on it's own single threaded runtime
+fn block_on<F: Future>(future: F) -> Option<F::Output> {
+    match tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+    {
+        Ok(runtime) => Some(runtime.block_on(future)),
+        Err(err) => {
+            set_last_error(err);
+            None
+        }
+    }
+}
+
+/// Sends a gistit to the default server, returning it's hash.
+///
+/// `secret` may be `NULL`, otherwise the gistit is encrypted with it before it's sent.
+/// Returns `NULL` on failure.
+///
+/// # Safety
+///
+/// `name`, `data`, `author` and `secret` must be `NULL` or valid nul terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn gistit_send(
+    name: *const c_char,
+    data: *const c_char,
+    author: *const c_char,
+    secret: *const c_char,
+) -> *mut c_char {
+    let (name, data, author) = match (str_arg(name), str_arg(data), str_arg(author)) {
+        (Some(name), Some(data), Some(author)) => (name, data, author),
+        _ => {
+            set_last_error("name, data and author must be valid UTF-8 strings");
+            return ptr::null_mut();
+        }
+    };
+    let mut request = SendRequest::new(name, data, author);
+    request.secret = str_arg(secret).map(ToOwned::to_owned);
+
+    match block_on(gistit_core::send(request)) {
+        Some(Ok(receipt)) => into_raw(receipt.hash),
+        Some(Err(err)) => {
+            set_last_error(err);
+            ptr::null_mut()
+        }
+        None => ptr::null_mut(),
+    }
+}
+
+/// Fetches a gistit from the default server, returning it's contents.
+///
+/// `secret` may be `NULL`, it's needed for gistits sent encrypted. Returns `NULL` on failure.
+///
+/// # Safety
+///
+/// `hash` and `secret` must be `NULL` or valid nul terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn gistit_fetch(hash: *const c_char, secret: *const c_char) -> *mut c_char {
+    let hash = match str_arg(hash) {
+        Some(hash) => hash,
+        None => {
+            set_last_error("hash must be a valid UTF-8 string");
+            return ptr::null_mut();
+        }
+    };
+    let mut request = FetchRequest::new(hash);
+    request.secret = str_arg(secret).map(ToOwned::to_owned);
+
+    match block_on(gistit_core::fetch(request)) {
+        Some(Ok(fetched)) => into_raw(fetched.data().to_owned()),
+        Some(Err(err)) => {
+            set_last_error(err);
+            ptr::null_mut()
+        }
+        None => ptr::null_mut(),
+    }
+}
+
+/// Releases a string returned by `gistit_send` or `gistit_fetch`. `NULL` is ignored.
+///
+/// # Safety
+///
+/// `value` must be `NULL` or returned by this library, and not freed before.
+#[no_mangle]
+pub unsafe extern "C" fn gistit_free(value: *mut c_char) {
+    if !value.is_null() {
+        drop(CString::from_raw(value));
+    }
+}
+
+/// The last error on this thread, `NULL` if none. Owned by the library, valid until the next call.
+#[no_mangle]
+pub extern "C" fn gistit_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |err| err.as_ptr())
+    })
+}
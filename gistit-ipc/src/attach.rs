@@ -0,0 +1,256 @@
+//! Gistits handed over as file descriptors
+//!
+//! Both ends of the unix sockets live on the same machine, so instead of copying big gistits
+//! through the datagram the sender writes them to an anonymous file and passes it's descriptor
+//! along with the frame (`SCM_RIGHTS`). Only `ProvideRequest`s and `FetchResponse`s are attached,
+//! the receiving end reads the gistit back in before the instruction is handed out. Whenever a
+//! file can't be created or passed the gistit is sent inline, as it always is on Windows.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::mem;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::ptr;
+
+use gistit_proto::ipc::instruction::{FetchResponse, Kind, ProvideRequest};
+use gistit_proto::prost::Message;
+use gistit_proto::{Gistit, Instruction};
+
+use crate::frame::Malformed;
+
+/// Gistits encoded smaller than this are sent inline, a file isn't worth it
+pub const MIN_ATTACHED_SIZE: usize = 16 * 1024;
+
+/// Biggest attached gistit read back
+pub const MAX_ATTACHED_SIZE: usize = 64 * 1024 * 1024;
+
+/// Room for the control message of a single descriptor, as `u64`s to keep it aligned
+const CONTROL_LEN: usize = 4;
+
+/// The gistit `instruction` may carry and it's attached flag
+fn gistit_mut(instruction: &mut Instruction) -> Option<(&mut Option<Gistit>, &mut bool)> {
+    match instruction.kind.as_mut()? {
+        Kind::ProvideRequest(ProvideRequest {
+            gistit,
+            gistit_attached,
+            ..
+        })
+        | Kind::FetchResponse(FetchResponse {
+            gistit,
+            gistit_attached,
+            ..
+        }) => Some((gistit, gistit_attached)),
+        _ => None,
+    }
+}
+
+/// Moves the gistit `instruction` carries into an anonymous file, if it's big enough to be worth
+/// it. `None` leaves the instruction as it was
+pub fn detach(instruction: &mut Instruction) -> Option<File> {
+    let (gistit, attached) = gistit_mut(instruction)?;
+    let encoded = gistit
+        .as_ref()
+        .filter(|gistit| gistit.encoded_len() >= MIN_ATTACHED_SIZE)?
+        .encode_to_vec();
+
+    match anonymous_file(&encoded) {
+        Ok(file) => {
+            *gistit = None;
+            *attached = true;
+            Some(file)
+        }
+        Err(err) => {
+            tracing::debug!("Sending gistit inline, can't create a file for it: {}", err);
+            None
+        }
+    }
+}
+
+fn anonymous_file(bytes: &[u8]) -> io::Result<File> {
+    let mut file = tempfile::tempfile()?;
+    file.write_all(bytes)?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(file)
+}
+
+/// Reads the gistit `instruction` announces as attached back in from `file`. Instructions without
+/// one are left untouched
+///
+/// # Errors
+///
+/// Fails if the gistit is announced but `file` is missing, can't be read or doesn't hold a gistit
+pub fn reattach(instruction: &mut Instruction, file: Option<File>) -> Result<(), Malformed> {
+    let (gistit, attached) = match gistit_mut(instruction) {
+        Some((gistit, attached)) if *attached => (gistit, attached),
+        _ => return Ok(()),
+    };
+
+    let mut file = file.ok_or(Malformed::Unattached)?;
+    file.seek(SeekFrom::Start(0))?;
+    let mut buf = Vec::new();
+    file.take(MAX_ATTACHED_SIZE as u64 + 1)
+        .read_to_end(&mut buf)?;
+    if buf.len() > MAX_ATTACHED_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "attached gistit too big").into());
+    }
+
+    *gistit = Some(Gistit::decode(&*buf)?);
+    *attached = false;
+    Ok(())
+}
+
+/// Sends `buf` through the connected datagram `socket`, passing `file` along
+///
+/// # Errors
+///
+/// Fails with the `sendmsg(2)` error, [`io::ErrorKind::WouldBlock`] included
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub fn send_with(socket: RawFd, buf: &[u8], file: &File) -> io::Result<usize> {
+    let mut iov = libc::iovec {
+        iov_base: ptr::NonNull::from(buf).as_ptr().cast(),
+        iov_len: buf.len(),
+    };
+    let mut control = [0_u64; CONTROL_LEN];
+
+    // SAFETY: `msg` points to `iov` and `control` which outlive the call, the control buffer fits
+    // the header and one descriptor, checked against `CMSG_SPACE`
+    let sent = unsafe {
+        let space = libc::CMSG_SPACE(mem::size_of::<RawFd>() as u32) as usize;
+        debug_assert!(space <= mem::size_of_val(&control));
+
+        let mut msg: libc::msghdr = mem::zeroed();
+        msg.msg_iov = ptr::addr_of_mut!(iov);
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr().cast();
+        msg.msg_controllen = space as _;
+
+        let cmsg = libc::CMSG_FIRSTHDR(ptr::addr_of!(msg));
+        (*cmsg).cmsg_level = libc::SOL_SOCKET;
+        (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of::<RawFd>() as u32) as _;
+        ptr::write_unaligned(libc::CMSG_DATA(cmsg).cast::<RawFd>(), file.as_raw_fd());
+
+        libc::sendmsg(socket, ptr::addr_of!(msg), 0)
+    };
+
+    if sent < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(sent as usize)
+}
+
+/// Receives a datagram from `socket` into `buf`, along with the file passed with it if any
+///
+/// # Errors
+///
+/// Fails with the `recvmsg(2)` error, [`io::ErrorKind::WouldBlock`] included
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_ptr_alignment
+)]
+pub fn recv_with(socket: RawFd, buf: &mut [u8]) -> io::Result<(usize, Option<File>)> {
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr().cast(),
+        iov_len: buf.len(),
+    };
+    let mut control = [0_u64; CONTROL_LEN];
+
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+    let flags = libc::MSG_CMSG_CLOEXEC;
+    #[cfg(not(any(target_os = "linux", target_os = "android", target_os = "freebsd")))]
+    let flags = 0;
+
+    // SAFETY: `msg` points to `iov` and `control` which outlive the call. Descriptors are only
+    // read from `SCM_RIGHTS` messages the kernel wrote, each is owned by exactly one `File`
+    unsafe {
+        let mut msg: libc::msghdr = mem::zeroed();
+        msg.msg_iov = ptr::addr_of_mut!(iov);
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr().cast();
+        msg.msg_controllen = mem::size_of_val(&control) as _;
+
+        let read = libc::recvmsg(socket, ptr::addr_of_mut!(msg), flags);
+        if read < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut file = None;
+        let mut cmsg = libc::CMSG_FIRSTHDR(ptr::addr_of!(msg));
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                let data = libc::CMSG_DATA(cmsg).cast::<RawFd>();
+                let count = ((*cmsg).cmsg_len as usize - libc::CMSG_LEN(0) as usize)
+                    / mem::size_of::<RawFd>();
+
+                // Any descriptor past the first is closed right away
+                for i in 0..count {
+                    let passed = File::from_raw_fd(ptr::read_unaligned(data.add(i)));
+                    file.get_or_insert(passed);
+                }
+            }
+            cmsg = libc::CMSG_NXTHDR(ptr::addr_of!(msg), cmsg);
+        }
+
+        Ok((read as usize, file))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gistit(size: usize) -> Gistit {
+        Gistit::new(
+            "hash".to_owned(),
+            "author".to_owned(),
+            None,
+            "0".to_owned(),
+            vec![Gistit::new_inner(
+                "big.txt".to_owned(),
+                "text".to_owned(),
+                size as u32,
+                "a".repeat(size),
+            )],
+        )
+    }
+
+    #[test]
+    fn attach_round_trip() {
+        let instruction = Instruction::request_provide(gistit(MIN_ATTACHED_SIZE), vec![]);
+        let mut detached = instruction.clone();
+        let file = detach(&mut detached).unwrap();
+        assert!(matches!(
+            detached.kind,
+            Some(Kind::ProvideRequest(ProvideRequest {
+                gistit: None,
+                gistit_attached: true,
+                ..
+            }))
+        ));
+
+        reattach(&mut detached, Some(file)).unwrap();
+        assert_eq!(detached, instruction);
+    }
+
+    #[test]
+    fn attach_small_gistits_inline() {
+        let mut instruction = Instruction::respond_fetch(Some(gistit(16)));
+        assert!(detach(&mut instruction).is_none());
+        assert_eq!(instruction, Instruction::respond_fetch(Some(gistit(16))));
+
+        let mut status = Instruction::request_status();
+        assert!(detach(&mut status).is_none());
+        reattach(&mut status, None).unwrap();
+    }
+
+    #[test]
+    fn attach_missing_file() {
+        let mut instruction = Instruction::respond_fetch(Some(gistit(MIN_ATTACHED_SIZE)));
+        let _file = detach(&mut instruction).unwrap();
+        assert!(matches!(
+            reattach(&mut instruction, None),
+            Err(Malformed::Unattached)
+        ));
+    }
+}
@@ -0,0 +1,357 @@
+//! Framing of instructions on the wire
+//!
+//! Every instruction is sent as `magic | payload length | crc32 of the payload | payload`, the
+//! numbers little endian. Anything else written to our end, e.g. by a stray process or an older
+//! gistit, is recognized as malformed instead of being decoded into garbage.
+//!
+//! Instructions are encoded straight behind their header, and decoded out of the received frame
+//! so `bytes` fields borrow from it rather than being copied out.
+
+use gistit_proto::bytes::Bytes;
+use gistit_proto::prost::{self, Message};
+use gistit_proto::Instruction;
+
+use crate::{Result, READBUF_SIZE};
+
+/// Marks the start of a frame
+pub const MAGIC: [u8; 4] = *b"GSIT";
+
+/// Magic, payload length and checksum
+pub const HEADER_SIZE: usize = 12;
+
+/// Biggest frame read or sent, payloads are at most [`READBUF_SIZE`]
+pub const MAX_FRAME_SIZE: usize = HEADER_SIZE + READBUF_SIZE;
+
+/// Why a frame was rejected
+#[derive(thiserror::Error, Debug)]
+pub enum Malformed {
+    #[error("frame shorter than it's header")]
+    Truncated,
+
+    #[error("frame doesn't start with the magic header")]
+    Magic,
+
+    #[error("frame length doesn't match it's header")]
+    Length,
+
+    #[error("frame payload doesn't match it's checksum")]
+    Checksum,
+
+    #[error("frame payload is not an instruction: {0}")]
+    Decode(#[from] prost::DecodeError),
+
+    #[error("instruction too big to be framed, {0} bytes")]
+    Oversized(usize),
+
+    #[error("frame announces an attached gistit but no file descriptor came with it")]
+    Unattached,
+
+    #[error("attached gistit can't be read: {0}")]
+    Attachment(#[from] std::io::Error),
+}
+
+/// The header and payload of `instruction`, in a single allocation
+///
+/// # Errors
+///
+/// Fails with [`Malformed::Oversized`] if it's payload is bigger than [`READBUF_SIZE`]
+pub fn encode(instruction: &Instruction) -> Result<Vec<u8>> {
+    let payload_len = instruction.encoded_len();
+    if payload_len > READBUF_SIZE {
+        return Err(Malformed::Oversized(payload_len).into());
+    }
+
+    let len = u32::try_from(payload_len).map_err(|_| Malformed::Oversized(payload_len))?;
+    let mut frame = Vec::with_capacity(HEADER_SIZE + payload_len);
+    frame.extend_from_slice(&MAGIC);
+    frame.extend_from_slice(&len.to_le_bytes());
+    // Checksum is filled in once the payload is written
+    frame.extend_from_slice(&[0; 4]);
+    instruction
+        .encode(&mut frame)
+        .map_err(|_| Malformed::Oversized(payload_len))?;
+
+    let checksum = crc32fast::hash(&frame[HEADER_SIZE..]);
+    frame[8..HEADER_SIZE].copy_from_slice(&checksum.to_le_bytes());
+    Ok(frame)
+}
+
+/// The payload length a frame starting with `header` announces, checking the magic
+///
+/// # Errors
+///
+/// Fails if `header` is shorter than [`HEADER_SIZE`], doesn't start with [`MAGIC`] or announces
+/// more than [`READBUF_SIZE`]
+pub fn payload_len(header: &[u8]) -> std::result::Result<usize, Malformed> {
+    if header.len() < HEADER_SIZE {
+        return Err(Malformed::Truncated);
+    }
+    if header[..4] != MAGIC {
+        return Err(Malformed::Magic);
+    }
+
+    let len = read_u32(&header[4..8]) as usize;
+    if len > READBUF_SIZE {
+        return Err(Malformed::Length);
+    }
+    Ok(len)
+}
+
+/// The instruction in `frame`, which must hold exactly one. It's `bytes` fields are slices of
+/// `frame`, not copies
+///
+/// # Errors
+///
+/// Fails if the header is off, the length doesn't match or the payload doesn't check out
+pub fn decode(frame: Bytes) -> std::result::Result<Instruction, Malformed> {
+    let len = payload_len(&frame)?;
+    let mut payload = frame;
+    let header = payload.split_to(HEADER_SIZE);
+    if payload.len() != len {
+        return Err(Malformed::Length);
+    }
+
+    if crc32fast::hash(&payload) != read_u32(&header[8..12]) {
+        return Err(Malformed::Checksum);
+    }
+    Ok(Instruction::decode(payload)?)
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gistit_proto::ipc::instruction::{
+        DiagnosticCheck, FetchProgressResponse, InboxItem, Limits, PeerScore, Pin, StatusResponse,
+    };
+    use gistit_proto::{Announcement, Gistit, Head};
+    use proptest::prelude::*;
+
+    /// One kind for every instruction constructor, covering every `instruction::Kind`, and one for
+    /// an empty instruction
+    const KINDS: u8 = 49;
+
+    fn gistit(text: &str, number: u32) -> Gistit {
+        Gistit::new(
+            text.to_owned(),
+            text.to_owned(),
+            Some(text.to_owned()),
+            number.to_string(),
+            vec![Gistit::new_inner(
+                text.to_owned(),
+                text.to_owned(),
+                number,
+                text.to_owned(),
+            )],
+        )
+    }
+
+    /// An instruction of `kind`, it's fields filled from `text`, `number` and `flag`
+    #[allow(clippy::too_many_lines)]
+    fn instruction(kind: u8, text: String, number: u32, flag: bool) -> Instruction {
+        let big = u64::from(number) << 16;
+        let maybe = flag.then(|| text.clone());
+        match kind {
+            0 if flag => Instruction::request_status_peers(),
+            0 => Instruction::request_status(),
+            1 => Instruction::request_fetch(text, flag),
+            2 => Instruction::request_head(text),
+            3 => Instruction::request_cancel(text),
+            4 => Instruction::request_provide(gistit(&text, number), vec![text]),
+            5 => Instruction::request_shutdown(),
+            6 => Instruction::request_dial(text),
+            7 => Instruction::request_tail_logs(flag.then(|| big)),
+            8 => Instruction::request_revoke(text),
+            9 => Instruction::request_subscribe(vec![text.clone(), text]),
+            10 => Instruction::request_push(gistit(&text, number), text),
+            11 => Instruction::request_acl(flag, text),
+            12 => Instruction::request_limits(Some(big), None, Some(number)),
+            13 => Instruction::request_publish_name(text.clone(), text),
+            14 => Instruction::request_resolve_name(text),
+            15 => Instruction::request_pin(text, flag),
+            16 => Instruction::request_list_pins(),
+            17 => Instruction::request_diagnostics(),
+            18 => Instruction::request_inbox(),
+            19 => Instruction::request_inbox_decision(number, flag),
+            20 => Instruction::respond_status(StatusResponse {
+                peer_id: text.clone(),
+                peer_count: number,
+                reachability: text.clone(),
+                acl: vec![text.clone()],
+                bytes_sent: big,
+                peers: vec![PeerScore {
+                    peer_id: text,
+                    score: f64::from(number),
+                    successes: big,
+                    ..PeerScore::default()
+                }],
+                ..StatusResponse::default()
+            }),
+            21 => Instruction::respond_fetch(flag.then(|| gistit(&text, number))),
+            22 => Instruction::respond_fetch_burn_after_read(),
+            23 => Instruction::respond_head(flag.then(|| Head {
+                hash: text.clone(),
+                description: Some(text.clone()),
+                size: number,
+                expires_at: Some(big),
+                parent: Some(text),
+                ..Head::default()
+            })),
+            24 => Instruction::respond_fetch_progress(FetchProgressResponse {
+                hash: text.clone(),
+                chunks_done: number,
+                peer_id: text,
+                bytes_done: big,
+                ..FetchProgressResponse::default()
+            }),
+            25 => Instruction::respond_provide(maybe),
+            26 => Instruction::respond_log_chunk(text.into_bytes(), big),
+            27 => Instruction::respond_revoke(flag),
+            28 => Instruction::respond_event(
+                text.clone(),
+                Announcement {
+                    hash: text.clone(),
+                    description: maybe,
+                    size: number,
+                    peer_id: text,
+                    ..Announcement::default()
+                },
+            ),
+            29 => Instruction::respond_push(flag),
+            30 => Instruction::respond_incoming(gistit(&text, number), text, number),
+            31 => Instruction::respond_acl(flag),
+            32 => Instruction::respond_limits(Limits {
+                upload_rate: big,
+                download_rate: big,
+                peer_request_rate: number,
+            }),
+            33 => Instruction::respond_publish_name(flag),
+            34 => Instruction::respond_resolve_name(maybe, text),
+            35 => Instruction::respond_pin(flag),
+            36 => Instruction::respond_list_pins(
+                vec![Pin {
+                    hash: text,
+                    size: big,
+                    last_access: big,
+                }],
+                big,
+                big,
+            ),
+            37 => Instruction::respond_diagnostics(vec![DiagnosticCheck {
+                name: text.clone(),
+                detail: text.clone(),
+                hint: text,
+                ..DiagnosticCheck::default()
+            }]),
+            38 => Instruction::respond_inbox(vec![InboxItem {
+                id: number,
+                hash: text.clone(),
+                description: maybe,
+                peer_id: text,
+                received_at: big,
+                ..InboxItem::default()
+            }]),
+            39 => Instruction::respond_inbox_decision(maybe),
+            40 => Instruction::request_exists(text),
+            41 => Instruction::respond_exists(flag),
+            42 => Instruction::request_resolve_prefix(text),
+            43 => Instruction::respond_resolve_prefix(vec![text]),
+            44 => Instruction::request_reload_config(),
+            45 => Instruction::respond_reload_config(vec![text.clone()], vec![text]),
+            46 => Instruction::request_log_level(maybe),
+            47 => Instruction::respond_log_level(text, flag),
+            _ => Instruction { kind: None },
+        }
+    }
+
+    fn any_instruction() -> impl Strategy<Value = Instruction> {
+        (0..KINDS, ".{0,64}", any::<u32>(), any::<bool>())
+            .prop_map(|(kind, text, number, flag)| instruction(kind, text, number, flag))
+    }
+
+    proptest! {
+        #[test]
+        fn frame_round_trip(instruction in any_instruction()) {
+            let frame = encode(&instruction).unwrap();
+            prop_assert_eq!(frame.len(), HEADER_SIZE + instruction.encoded_len());
+            prop_assert_eq!(decode(frame.into()).unwrap(), instruction);
+        }
+
+        #[test]
+        fn frame_corruption_is_detected(
+            instruction in any_instruction(),
+            index in any::<prop::sample::Index>(),
+            flip in 1..=u8::MAX,
+        ) {
+            let mut frame = encode(&instruction).unwrap();
+            let at = index.index(frame.len());
+            frame[at] ^= flip;
+            prop_assert!(decode(frame.into()).is_err());
+        }
+
+        #[test]
+        fn frame_truncation_is_detected(instruction in any_instruction(), cut in 1_usize..16) {
+            let frame = encode(&instruction).unwrap();
+            let cut = cut.min(frame.len());
+            prop_assert!(decode(Bytes::copy_from_slice(&frame[..frame.len() - cut])).is_err());
+        }
+
+        #[test]
+        fn frame_decode_never_panics(bytes in prop::collection::vec(any::<u8>(), 0..256)) {
+            let _ = decode(bytes.into());
+        }
+
+        #[test]
+        fn frame_decode_checked_garbage(payload in prop::collection::vec(any::<u8>(), 0..256)) {
+            // A well formed header around garbage gets past the checksum, the payload must still
+            // decode, or be rejected without panicking
+            let mut frame = MAGIC.to_vec();
+            frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+            frame.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+            frame.extend_from_slice(&payload);
+            let _ = decode(frame.into());
+        }
+    }
+
+    #[test]
+    fn frame_oversized() {
+        let instruction = Instruction::request_dial("a".repeat(READBUF_SIZE));
+        assert!(matches!(
+            encode(&instruction),
+            Err(crate::Error::Frame(Malformed::Oversized(_)))
+        ));
+    }
+
+    #[test]
+    fn frame_header() {
+        let frame = encode(&Instruction::request_status()).unwrap();
+        assert_eq!(&frame[..4], &MAGIC);
+        assert_eq!(payload_len(&frame).unwrap(), frame.len() - HEADER_SIZE);
+        assert!(matches!(
+            payload_len(&frame[..3]),
+            Err(Malformed::Truncated)
+        ));
+        assert!(matches!(
+            decode(Bytes::from_static(b"not a frame!")),
+            Err(Malformed::Magic)
+        ));
+    }
+
+    #[test]
+    fn frame_decode_borrows_bytes() {
+        let instruction = Instruction::respond_log_chunk(vec![7_u8; 1024], 0);
+        let frame = Bytes::from(encode(&instruction).unwrap());
+        let range = frame.as_ptr_range();
+
+        match decode(frame.clone()).unwrap().kind {
+            Some(gistit_proto::ipc::instruction::Kind::LogChunkResponse(chunk)) => {
+                assert_eq!(chunk.data.len(), 1024);
+                assert!(range.contains(&chunk.data.as_ptr()));
+            }
+            other => panic!("unexpected instruction {:?}", other),
+        }
+    }
+}
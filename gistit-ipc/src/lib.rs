@@ -21,17 +21,16 @@
     )
 )]
 //! This is a simple crate to handle the inter process comms for gistit-daemon and gistit-cli
+//! Both ends live in the runtime directory, as unix datagram sockets or, on Windows, as named
+//! pipes derived from it's path. Instructions are framed with a magic header and checksum, see
+//! [`frame`]. Over unix sockets big gistits are passed as file descriptors, see `attach`
 //! TODO: Missing TCP socket implementation
 
-use std::fs::{metadata, remove_file};
-use std::marker::PhantomData;
-use std::path::{Path, PathBuf};
-use std::time::Instant;
-use tokio::net::UnixDatagram;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
 
-use gistit_proto::bytes::BytesMut;
-use gistit_proto::prost::{self, Message};
-use gistit_proto::Instruction;
+use gistit_proto::{prost, Instruction};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -41,6 +40,14 @@ const NAMED_SOCKET_1: &str = "gistit-1";
 const READBUF_SIZE: usize = 60_000; // A bit bigger than 50kb because encoding
 const CONNECT_TIMEOUT_SECS: u64 = 3;
 
+/// Milliseconds the client end waits for an answer, 0 to wait as long as it takes
+static CLIENT_TIMEOUT_MILLIS: AtomicU64 = AtomicU64::new(0);
+
+pub mod frame;
+
+#[cfg(unix)]
+pub mod attach;
+
 pub trait SockEnd {}
 
 #[derive(Debug)]
@@ -51,176 +58,70 @@ impl SockEnd for Server {}
 pub struct Client;
 impl SockEnd for Client {}
 
-#[derive(Debug)]
-pub struct Bridge<T: SockEnd> {
-    pub sock_0: UnixDatagram,
-    pub sock_1: UnixDatagram,
-    base: PathBuf,
-    __marker_t: PhantomData<T>,
-}
+#[cfg(unix)]
+mod unix;
+#[cfg(unix)]
+pub use unix::{client, server, Bridge};
 
-/// Recv from [`NAMED_SOCKET_0`] and send to [`NAMED_SOCKET_1`]
-/// The owner of `sock_0`
-///
-/// # Errors
-///
-/// Fails if can't spawn a named socket
-pub fn server(base: &Path) -> Result<Bridge<Server>> {
-    let sockpath_0 = &base.join(NAMED_SOCKET_0);
-
-    if metadata(sockpath_0).is_ok() {
-        remove_file(sockpath_0)?;
-    }
+#[cfg(windows)]
+mod windows;
+#[cfg(windows)]
+pub use windows::{client, server, Bridge};
 
-    log::trace!("Bind sock_0 (server) at {:?}", sockpath_0);
-    let sock_0 = UnixDatagram::bind(sockpath_0)?;
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("io error {0}")]
+    IO(#[from] std::io::Error),
 
-    Ok(Bridge {
-        sock_0,
-        sock_1: UnixDatagram::unbound()?,
-        base: base.to_path_buf(),
-        __marker_t: PhantomData,
-    })
-}
+    #[error("decode error {0}")]
+    Decode(#[from] prost::DecodeError),
 
-/// Recv from [`NAMED_SOCKET_1`] and send to [`NAMED_SOCKET_0`]
-/// The owner of `sock_1`
-///
-/// # Errors
-///
-/// Fails if can't spawn a named socket
-pub fn client(base: &Path) -> Result<Bridge<Client>> {
-    let sockpath_1 = &base.join(NAMED_SOCKET_1);
-
-    if metadata(sockpath_1).is_ok() {
-        remove_file(sockpath_1)?;
-    }
+    #[error("encode error {0}")]
+    Encode(#[from] prost::EncodeError),
 
-    log::trace!("Bind sock_1 (client) at {:?}", sockpath_1);
-    let sock_1 = UnixDatagram::bind(sockpath_1)?;
+    #[error("malformed frame, {0}")]
+    Frame(#[from] frame::Malformed),
 
-    Ok(Bridge {
-        sock_0: UnixDatagram::unbound()?,
-        sock_1,
-        base: base.to_path_buf(),
-        __marker_t: PhantomData,
-    })
+    #[error("gistit-daemon didn't answer within {}s", .0.as_secs_f32())]
+    Timeout(Duration),
 }
 
-fn __alive(base: &Path, dgram: &UnixDatagram, sock_name: &str) -> bool {
-    !matches!(dgram.connect(base.join(sock_name)), Err(_))
+/// Makes the client end give up on an answer after `timeout`, see `Bridge::recv`. It waits as long
+/// as it takes when `None`, the default. Applies to every client bridge of this process
+pub fn set_timeout(timeout: Option<Duration>) {
+    let millis = timeout.map_or(0, |timeout| {
+        u64::try_from(timeout.as_millis())
+            .unwrap_or(u64::MAX)
+            .max(1)
+    });
+    CLIENT_TIMEOUT_MILLIS.store(millis, Ordering::Relaxed);
 }
 
-fn __connect_blocking(base: &Path, dgram: &UnixDatagram, sock_name: &str) -> Result<()> {
-    let earlier = Instant::now();
-    while let Err(err) = dgram.connect(base.join(sock_name)) {
-        if Instant::now().duration_since(earlier).as_secs() > CONNECT_TIMEOUT_SECS {
-            return Err(err.into());
-        }
+fn client_timeout() -> Option<Duration> {
+    match CLIENT_TIMEOUT_MILLIS.load(Ordering::Relaxed) {
+        0 => None,
+        millis => Some(Duration::from_millis(millis)),
     }
-
-    log::trace!("Connecting to {:?}", sock_name);
-    Ok(())
 }
 
-impl Bridge<Server> {
-    pub fn alive(&self) -> bool {
-        __alive(&self.base, &self.sock_1, NAMED_SOCKET_1)
-    }
-
-    /// Connect to the other end
-    ///
-    /// # Errors
-    ///
-    /// Inherits errors of [`__connect_blocking`]
-    pub fn connect_blocking(&mut self) -> Result<()> {
-        __connect_blocking(&self.base, &self.sock_1, NAMED_SOCKET_1)
-    }
-
-    /// Send bincode serialized data through the pipe
-    ///
-    /// # Errors
-    ///
-    /// Fails if the socket is not alive
-    pub async fn send(&self, instruction: Instruction) -> Result<()> {
-        let mut buf = BytesMut::with_capacity(READBUF_SIZE);
-        instruction.encode(&mut buf)?;
-        log::trace!("Sending to client {} bytes", buf.len());
-        self.sock_1.send(&buf).await?;
-        Ok(())
-    }
-
-    /// Attempts to receive serialized data from the pipe
-    ///
-    /// # Errors
-    ///
-    /// Fails if the socket is not alive
-    pub async fn recv(&self) -> Result<Instruction> {
-        let mut buf = vec![0u8; READBUF_SIZE];
-        let read = self.sock_0.recv(&mut buf).await?;
-        buf.truncate(read);
-        let target = Instruction::decode(&*buf)?;
-        Ok(target)
-    }
-}
-
-impl Bridge<Client> {
-    pub fn alive(&self) -> bool {
-        __alive(&self.base, &self.sock_0, NAMED_SOCKET_0)
-    }
-
-    /// Connect to the other end
-    ///
-    /// # Errors
-    ///
-    /// Inherits errors of [`__connect_blocking`]
-    pub fn connect_blocking(&mut self) -> Result<()> {
-        __connect_blocking(&self.base, &self.sock_0, NAMED_SOCKET_0)
-    }
-
-    /// Send bincode serialized data through the pipe
-    ///
-    /// # Errors
-    ///
-    /// Fails if the socket is not alive
-    pub async fn send(&self, instruction: Instruction) -> Result<()> {
-        let mut buf = BytesMut::with_capacity(READBUF_SIZE);
-        instruction.encode(&mut buf)?;
-        log::trace!("Sending to server {} bytes", buf.len());
-        self.sock_0.send(&*buf).await?;
-        Ok(())
-    }
-
-    /// Attempts to receive serialized data from the pipe
-    ///
-    /// # Errors
-    ///
-    /// Fails if the socket is not alive
-    pub async fn recv(&self) -> Result<Instruction> {
-        let mut buf = vec![0u8; READBUF_SIZE];
-        let read = self.sock_1.recv(&mut buf).await?;
-        buf.truncate(read);
-        let target = Instruction::decode(&*buf)?;
-        Ok(target)
+/// Waits for `recv` up to `timeout`
+async fn within(
+    timeout: Option<Duration>,
+    recv: impl Future<Output = Result<Instruction>>,
+) -> Result<Instruction> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, recv)
+            .await
+            .map_err(|_| Error::Timeout(timeout))?,
+        None => recv.await,
     }
 }
 
-#[derive(thiserror::Error, Debug)]
-pub enum Error {
-    #[error("io error {0}")]
-    IO(#[from] std::io::Error),
-
-    #[error("decode error {0}")]
-    Decode(#[from] prost::DecodeError),
-
-    #[error("encode error {0}")]
-    Encode(#[from] prost::EncodeError),
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
     use assert_fs::prelude::*;
+    use gistit_proto::Instruction;
     use std::sync::Arc;
 
     pub fn test_instruction_1() -> Instruction {
@@ -231,6 +132,28 @@ mod tests {
         Instruction::request_shutdown()
     }
 
+    #[tokio::test]
+    async fn ipc_recv_within_timeout() {
+        let timeout = Some(Duration::from_millis(10));
+        assert!(matches!(
+            within(timeout, std::future::pending()).await,
+            Err(Error::Timeout(_))
+        ));
+        assert_eq!(
+            within(timeout, async { Ok(test_instruction_1()) })
+                .await
+                .unwrap(),
+            test_instruction_1()
+        );
+        assert_eq!(
+            within(None, async { Ok(test_instruction_2()) })
+                .await
+                .unwrap(),
+            test_instruction_2()
+        );
+    }
+
+    #[cfg(unix)]
     #[tokio::test]
     async fn ipc_named_socket_spawn() {
         let tmp = assert_fs::TempDir::new().unwrap();
@@ -334,6 +257,62 @@ mod tests {
         assert_eq!(server.recv().await.unwrap(), test_instruction_2());
     }
 
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn ipc_socket_skips_malformed_frames() {
+        let tmp = assert_fs::TempDir::new().unwrap();
+        let server = server(&tmp).unwrap();
+        let mut client = client(&tmp).unwrap();
+
+        let stray = tokio::net::UnixDatagram::unbound().unwrap();
+        stray.connect(tmp.child("gistit-0").path()).unwrap();
+        stray.send(b"not an instruction").await.unwrap();
+
+        let mut corrupted = frame::encode(&test_instruction_2()).unwrap();
+        *corrupted.last_mut().unwrap() ^= 0xff;
+        stray.send(&corrupted).await.unwrap();
+
+        client.connect_blocking().unwrap();
+        client.send(test_instruction_1()).await.unwrap();
+
+        assert_eq!(server.recv().await.unwrap(), test_instruction_1());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn ipc_socket_attaches_big_gistits() {
+        use gistit_proto::Gistit;
+
+        let tmp = assert_fs::TempDir::new().unwrap();
+        let mut server = server(&tmp).unwrap();
+        let mut client = client(&tmp).unwrap();
+
+        // Too big for a datagram, only gets through as a file descriptor
+        let data = "a".repeat(READBUF_SIZE * 2);
+        let gistit = Gistit::new(
+            "hash".to_owned(),
+            "author".to_owned(),
+            None,
+            "0".to_owned(),
+            vec![Gistit::new_inner(
+                "big.txt".to_owned(),
+                "text".to_owned(),
+                data.len() as u32,
+                data,
+            )],
+        );
+
+        client.connect_blocking().unwrap();
+        let provide = Instruction::request_provide(gistit.clone(), vec!["topic".to_owned()]);
+        client.send(provide.clone()).await.unwrap();
+        assert_eq!(server.recv().await.unwrap(), provide);
+
+        server.connect_blocking().unwrap();
+        let fetched = Instruction::respond_fetch(Some(gistit));
+        server.send(fetched.clone()).await.unwrap();
+        assert_eq!(client.recv().await.unwrap(), fetched);
+    }
+
     #[tokio::test]
     async fn ipc_socket_traffic_under_load() {
         let tmp = assert_fs::TempDir::new().unwrap();
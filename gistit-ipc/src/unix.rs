@@ -0,0 +1,231 @@
+//! Unix datagram sockets, one bound by each end inside the runtime directory
+
+use std::fs::{metadata, remove_file, File};
+use std::io;
+use std::marker::PhantomData;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use tokio::io::Interest;
+use tokio::net::UnixDatagram;
+
+use gistit_proto::bytes::BytesMut;
+use gistit_proto::Instruction;
+
+use crate::attach;
+use crate::frame::{self, MAX_FRAME_SIZE};
+use crate::{client_timeout, within, Client, Result, Server, SockEnd};
+use crate::{CONNECT_TIMEOUT_SECS, NAMED_SOCKET_0, NAMED_SOCKET_1};
+
+#[derive(Debug)]
+pub struct Bridge<T: SockEnd> {
+    pub sock_0: UnixDatagram,
+    pub sock_1: UnixDatagram,
+    base: PathBuf,
+    __marker_t: PhantomData<T>,
+}
+
+/// Recv from [`NAMED_SOCKET_0`] and send to [`NAMED_SOCKET_1`]
+/// The owner of `sock_0`
+///
+/// # Errors
+///
+/// Fails if can't spawn a named socket
+pub fn server(base: &Path) -> Result<Bridge<Server>> {
+    let sockpath_0 = &base.join(NAMED_SOCKET_0);
+
+    if metadata(sockpath_0).is_ok() {
+        remove_file(sockpath_0)?;
+    }
+
+    tracing::trace!("Bind sock_0 (server) at {:?}", sockpath_0);
+    let sock_0 = UnixDatagram::bind(sockpath_0)?;
+
+    Ok(Bridge {
+        sock_0,
+        sock_1: UnixDatagram::unbound()?,
+        base: base.to_path_buf(),
+        __marker_t: PhantomData,
+    })
+}
+
+/// Recv from [`NAMED_SOCKET_1`] and send to [`NAMED_SOCKET_0`]
+/// The owner of `sock_1`
+///
+/// # Errors
+///
+/// Fails if can't spawn a named socket
+pub fn client(base: &Path) -> Result<Bridge<Client>> {
+    let sockpath_1 = &base.join(NAMED_SOCKET_1);
+
+    if metadata(sockpath_1).is_ok() {
+        remove_file(sockpath_1)?;
+    }
+
+    tracing::trace!("Bind sock_1 (client) at {:?}", sockpath_1);
+    let sock_1 = UnixDatagram::bind(sockpath_1)?;
+
+    Ok(Bridge {
+        sock_0: UnixDatagram::unbound()?,
+        sock_1,
+        base: base.to_path_buf(),
+        __marker_t: PhantomData,
+    })
+}
+
+fn __alive(base: &Path, dgram: &UnixDatagram, sock_name: &str) -> bool {
+    !matches!(dgram.connect(base.join(sock_name)), Err(_))
+}
+
+fn __connect_blocking(base: &Path, dgram: &UnixDatagram, sock_name: &str) -> Result<()> {
+    let earlier = Instant::now();
+    while let Err(err) = dgram.connect(base.join(sock_name)) {
+        if Instant::now().duration_since(earlier).as_secs() > CONNECT_TIMEOUT_SECS {
+            return Err(err.into());
+        }
+    }
+
+    tracing::trace!("Connecting to {:?}", sock_name);
+    Ok(())
+}
+
+/// Sends `instruction` on `dgram`, passing the gistit it carries as a file descriptor if it's
+/// big, or inline if that fails
+async fn __send(dgram: &UnixDatagram, mut instruction: Instruction) -> Result<()> {
+    if let Some(file) = attach::detach(&mut instruction) {
+        let buf = frame::encode(&instruction)?;
+        tracing::trace!("Sending {} bytes with an attached gistit", buf.len());
+        match __send_with(dgram, &buf, &file).await {
+            Ok(_) => return Ok(()),
+            Err(err) => {
+                tracing::debug!("Can't pass the gistit file, sending it inline: {}", err);
+                attach::reattach(&mut instruction, Some(file))?;
+            }
+        }
+    }
+
+    let buf = frame::encode(&instruction)?;
+    tracing::trace!("Sending {} bytes", buf.len());
+    dgram.send(&buf).await?;
+    Ok(())
+}
+
+async fn __send_with(dgram: &UnixDatagram, buf: &[u8], file: &File) -> io::Result<usize> {
+    loop {
+        dgram.writable().await?;
+        match dgram.try_io(Interest::WRITABLE, || {
+            attach::send_with(dgram.as_raw_fd(), buf, file)
+        }) {
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {}
+            result => return result,
+        }
+    }
+}
+
+/// Receives on `dgram` until a well formed frame comes in, anything else is dropped with a warning
+/// so a stray writer can't wedge the other end. Attached gistits are read back in
+async fn __recv(dgram: &UnixDatagram) -> Result<Instruction> {
+    loop {
+        let mut buf = BytesMut::with_capacity(MAX_FRAME_SIZE);
+        buf.resize(MAX_FRAME_SIZE, 0);
+        dgram.readable().await?;
+        let (read, file) = match dgram.try_io(Interest::READABLE, || {
+            attach::recv_with(dgram.as_raw_fd(), &mut buf)
+        }) {
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => continue,
+            result => result?,
+        };
+        tracing::trace!("Received {} bytes", read);
+        buf.truncate(read);
+
+        let instruction = frame::decode(buf.freeze()).and_then(|mut instruction| {
+            attach::reattach(&mut instruction, file)?;
+            Ok(instruction)
+        });
+        match instruction {
+            Ok(instruction) => return Ok(instruction),
+            Err(err) => tracing::warn!("Ignoring {} bytes datagram: {}", read, err),
+        }
+    }
+}
+
+impl Bridge<Server> {
+    pub fn alive(&self) -> bool {
+        __alive(&self.base, &self.sock_1, NAMED_SOCKET_1)
+    }
+
+    /// Connect to the other end
+    ///
+    /// # Errors
+    ///
+    /// Inherits errors of [`__connect_blocking`]
+    pub fn connect_blocking(&self) -> Result<()> {
+        __connect_blocking(&self.base, &self.sock_1, NAMED_SOCKET_1)
+    }
+
+    /// Send a framed instruction through the pipe
+    ///
+    /// # Errors
+    ///
+    /// Fails if the socket is not alive or the instruction is too big to be sent inline
+    pub async fn send(&self, instruction: Instruction) -> Result<()> {
+        tracing::trace!("Sending to client");
+        __send(&self.sock_1, instruction).await
+    }
+
+    /// Attempts to receive a framed instruction from the pipe, skipping malformed ones
+    ///
+    /// # Errors
+    ///
+    /// Fails if the socket is not alive
+    pub async fn recv(&self) -> Result<Instruction> {
+        __recv(&self.sock_0).await
+    }
+}
+
+impl Bridge<Client> {
+    pub fn alive(&self) -> bool {
+        __alive(&self.base, &self.sock_0, NAMED_SOCKET_0)
+    }
+
+    /// Connect to the other end
+    ///
+    /// # Errors
+    ///
+    /// Inherits errors of [`__connect_blocking`]
+    #[tracing::instrument(level = "debug", name = "ipc_connect", skip_all)]
+    pub fn connect_blocking(&mut self) -> Result<()> {
+        __connect_blocking(&self.base, &self.sock_0, NAMED_SOCKET_0)
+    }
+
+    /// Send a framed instruction through the pipe
+    ///
+    /// # Errors
+    ///
+    /// Fails if the socket is not alive or the instruction is too big to be sent inline
+    #[tracing::instrument(level = "debug", name = "ipc_send", skip_all)]
+    pub async fn send(&self, instruction: Instruction) -> Result<()> {
+        tracing::trace!("Sending to server");
+        __send(&self.sock_0, instruction).await
+    }
+
+    /// Attempts to receive a framed instruction from the pipe, skipping malformed ones. Gives up
+    /// after the timeout set with [`crate::set_timeout`]
+    ///
+    /// # Errors
+    ///
+    /// Fails if the socket is not alive or nothing came in time
+    #[tracing::instrument(level = "debug", name = "ipc_recv", skip_all)]
+    pub async fn recv(&self) -> Result<Instruction> {
+        within(client_timeout(), __recv(&self.sock_1)).await
+    }
+
+    /// Same as [`Self::recv`] but waits as long as it takes, for streams like subscriptions
+    ///
+    /// # Errors
+    ///
+    /// Fails if the socket is not alive
+    pub async fn listen(&self) -> Result<Instruction> {
+        __recv(&self.sock_1).await
+    }
+}
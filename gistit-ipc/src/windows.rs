@@ -0,0 +1,264 @@
+//! Named pipes standing in for the unix datagram sockets. Each end serves it's own pipe, reading
+//! frames from whoever connects to it, and connects to the other end's pipe to
+//! send. Pipe names are derived from the runtime directory, so `GISTIT_RUNTIME` still separates
+//! instances
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::windows::named_pipe::{
+    ClientOptions, NamedPipeClient, NamedPipeServer, ServerOptions,
+};
+use tokio::sync::{mpsc, Mutex};
+
+use gistit_proto::bytes::Bytes;
+use gistit_proto::Instruction;
+
+use crate::frame::{self, HEADER_SIZE};
+use crate::{client_timeout, within, Client, Result, Server, SockEnd};
+use crate::{CONNECT_TIMEOUT_SECS, NAMED_SOCKET_0, NAMED_SOCKET_1};
+
+/// Messages read but not received yet, past this readers wait
+const INBOX_CAPACITY: usize = 64;
+
+/// Windows error of a pipe whose instances are all connected, so someone is serving it
+const ERROR_PIPE_BUSY: i32 = 231;
+
+#[derive(Debug)]
+pub struct Bridge<T: SockEnd> {
+    /// Messages read from our own pipe
+    inbox: Mutex<mpsc::Receiver<Bytes>>,
+    /// Connection to the other end's pipe
+    outbox: Mutex<Option<NamedPipeClient>>,
+    base: PathBuf,
+    __marker_t: PhantomData<T>,
+}
+
+/// Serves [`NAMED_SOCKET_0`] and sends to [`NAMED_SOCKET_1`]
+///
+/// # Errors
+///
+/// Fails if can't create the named pipe
+pub fn server(base: &Path) -> Result<Bridge<Server>> {
+    tracing::trace!("Serve pipe_0 (server) of {:?}", base);
+    Ok(Bridge {
+        inbox: Mutex::new(serve(&pipe_name(base, NAMED_SOCKET_0))?),
+        outbox: Mutex::new(None),
+        base: base.to_path_buf(),
+        __marker_t: PhantomData,
+    })
+}
+
+/// Serves [`NAMED_SOCKET_1`] and sends to [`NAMED_SOCKET_0`]
+///
+/// # Errors
+///
+/// Fails if can't create the named pipe
+pub fn client(base: &Path) -> Result<Bridge<Client>> {
+    tracing::trace!("Serve pipe_1 (client) of {:?}", base);
+    Ok(Bridge {
+        inbox: Mutex::new(serve(&pipe_name(base, NAMED_SOCKET_1))?),
+        outbox: Mutex::new(None),
+        base: base.to_path_buf(),
+        __marker_t: PhantomData,
+    })
+}
+
+/// `\\.\pipe\gistit-0-<hash of base>`. Both ends are built together, so they hash alike
+fn pipe_name(base: &Path, sock_name: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    base.hash(&mut hasher);
+    format!(r"\\.\pipe\{}-{:016x}", sock_name, hasher.finish())
+}
+
+/// Accepts connections to the pipe `name` for as long as the returned receiver lives, every
+/// connection is read on it's own task
+fn serve(name: &str) -> Result<mpsc::Receiver<Bytes>> {
+    let mut pipe = ServerOptions::new().create(name)?;
+    let (tx, rx) = mpsc::channel(INBOX_CAPACITY);
+    let name = name.to_owned();
+
+    tokio::spawn(async move {
+        while pipe.connect().await.is_ok() && !tx.is_closed() {
+            // A new instance is created before handing this one over, so connecting never fails
+            // in between
+            let connected = pipe;
+            pipe = match ServerOptions::new().create(&name) {
+                Ok(pipe) => pipe,
+                Err(err) => {
+                    tracing::warn!("Failed to serve {}: {}", name, err);
+                    break;
+                }
+            };
+            tokio::spawn(read_messages(connected, tx.clone()));
+        }
+    });
+    Ok(rx)
+}
+
+/// Reads whole frames off `pipe`. A stream can't be resynchronized after a bad header, so the
+/// connection is dropped then, frames with a bad checksum are only skipped by [`__recv`]
+async fn read_messages(mut pipe: NamedPipeServer, tx: mpsc::Sender<Bytes>) {
+    loop {
+        let mut buf = vec![0_u8; HEADER_SIZE];
+        if pipe.read_exact(&mut buf).await.is_err() {
+            break;
+        }
+        let len = match frame::payload_len(&buf) {
+            Ok(len) => len,
+            Err(err) => {
+                tracing::warn!("Dropping connection sending a malformed frame: {}", err);
+                break;
+            }
+        };
+
+        buf.resize(HEADER_SIZE + len, 0);
+        if pipe.read_exact(&mut buf[HEADER_SIZE..]).await.is_err()
+            || tx.send(Bytes::from(buf)).await.is_err()
+        {
+            break;
+        }
+    }
+}
+
+fn __alive(base: &Path, sock_name: &str) -> bool {
+    match ClientOptions::new().open(pipe_name(base, sock_name)) {
+        Ok(_) => true,
+        Err(err) => err.raw_os_error() == Some(ERROR_PIPE_BUSY),
+    }
+}
+
+fn __connect_blocking(base: &Path, sock_name: &str) -> Result<NamedPipeClient> {
+    let name = pipe_name(base, sock_name);
+    let earlier = Instant::now();
+    loop {
+        match ClientOptions::new().open(&name) {
+            Ok(pipe) => {
+                tracing::trace!("Connecting to {:?}", sock_name);
+                return Ok(pipe);
+            }
+            Err(err) if Instant::now().duration_since(earlier).as_secs() > CONNECT_TIMEOUT_SECS => {
+                return Err(err.into());
+            }
+            Err(_) => {}
+        }
+    }
+}
+
+async fn __send(outbox: &Mutex<Option<NamedPipeClient>>, instruction: Instruction) -> Result<()> {
+    let buf = frame::encode(&instruction)?;
+
+    // Held for the whole frame, concurrent sends would interleave otherwise
+    let mut outbox = outbox.lock().await;
+    let pipe = outbox
+        .as_mut()
+        .ok_or_else(|| io::Error::from(io::ErrorKind::NotConnected))?;
+    pipe.write_all(&buf).await?;
+    Ok(())
+}
+
+/// Receives until a frame checks out, anything else is dropped with a warning
+async fn __recv(inbox: &Mutex<mpsc::Receiver<Bytes>>) -> Result<Instruction> {
+    let mut inbox = inbox.lock().await;
+    loop {
+        let buf = inbox
+            .recv()
+            .await
+            .ok_or_else(|| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        let len = buf.len();
+        match frame::decode(buf) {
+            Ok(instruction) => return Ok(instruction),
+            Err(err) => tracing::warn!("Ignoring {} bytes frame: {}", len, err),
+        }
+    }
+}
+
+impl Bridge<Server> {
+    pub fn alive(&self) -> bool {
+        __alive(&self.base, NAMED_SOCKET_1)
+    }
+
+    /// Connect to the other end
+    ///
+    /// # Errors
+    ///
+    /// Inherits errors of [`__connect_blocking`], or fails if a send is in flight
+    pub fn connect_blocking(&self) -> Result<()> {
+        let mut outbox = self
+            .outbox
+            .try_lock()
+            .map_err(|_| io::Error::from(io::ErrorKind::WouldBlock))?;
+        *outbox = Some(__connect_blocking(&self.base, NAMED_SOCKET_1)?);
+        Ok(())
+    }
+
+    /// Send a framed instruction through the pipe
+    ///
+    /// # Errors
+    ///
+    /// Fails if the pipe is not connected
+    pub async fn send(&self, instruction: Instruction) -> Result<()> {
+        __send(&self.outbox, instruction).await
+    }
+
+    /// Attempts to receive a framed instruction from the pipe, skipping malformed ones
+    ///
+    /// # Errors
+    ///
+    /// Fails if the pipe is not served anymore
+    pub async fn recv(&self) -> Result<Instruction> {
+        __recv(&self.inbox).await
+    }
+}
+
+impl Bridge<Client> {
+    pub fn alive(&self) -> bool {
+        __alive(&self.base, NAMED_SOCKET_0)
+    }
+
+    /// Connect to the other end
+    ///
+    /// # Errors
+    ///
+    /// Inherits errors of [`__connect_blocking`]
+    #[tracing::instrument(level = "debug", name = "ipc_connect", skip_all)]
+    pub fn connect_blocking(&mut self) -> Result<()> {
+        *self.outbox.get_mut() = Some(__connect_blocking(&self.base, NAMED_SOCKET_0)?);
+        Ok(())
+    }
+
+    /// Send a framed instruction through the pipe
+    ///
+    /// # Errors
+    ///
+    /// Fails if the pipe is not connected
+    #[tracing::instrument(level = "debug", name = "ipc_send", skip_all)]
+    pub async fn send(&self, instruction: Instruction) -> Result<()> {
+        __send(&self.outbox, instruction).await
+    }
+
+    /// Attempts to receive a framed instruction from the pipe, skipping malformed ones. Gives up
+    /// after the timeout set with [`crate::set_timeout`]
+    ///
+    /// # Errors
+    ///
+    /// Fails if the pipe is not served anymore or nothing came in time
+    #[tracing::instrument(level = "debug", name = "ipc_recv", skip_all)]
+    pub async fn recv(&self) -> Result<Instruction> {
+        within(client_timeout(), __recv(&self.inbox)).await
+    }
+
+    /// Same as [`Self::recv`] but waits as long as it takes, for streams like subscriptions
+    ///
+    /// # Errors
+    ///
+    /// Fails if the pipe is not served anymore
+    pub async fn listen(&self) -> Result<Instruction> {
+        __recv(&self.inbox).await
+    }
+}
@@ -28,14 +28,18 @@ pub const QUALIFIER: &str = "io";
 
 pub mod path {
     use std::fs;
-    use std::path::{Path, PathBuf};
+    use std::path::PathBuf;
 
-    use directories::{BaseDirs, ProjectDirs};
+    use directories::ProjectDirs;
 
     use super::{env, Error, Result};
 
     use super::{APPLICATION, ORGANIZATION, QUALIFIER};
 
+    /// Runtime directory name where there's no system one
+    #[cfg(any(target_os = "macos", windows))]
+    const RUNTIME_DIR_NAME: &str = "runtime";
+
     /// Initialize needed project directories if not present
     ///
     /// # Errors
@@ -47,7 +51,7 @@ pub mod path {
             fs::create_dir_all(&config)?;
         }
 
-        let runtime = runtime()?;
+        let runtime = runtime_dir()?;
         if fs::metadata(&runtime).is_err() {
             fs::create_dir_all(&runtime)?;
         }
@@ -60,18 +64,47 @@ pub mod path {
         Ok(())
     }
 
-    /// Returns the runtime path of this program
-    /// Fallbacks to a temporary folder
+    /// Returns the runtime path of this program, where `gistit-daemon` keeps it's sockets, pid and
+    /// log files: `$XDG_RUNTIME_DIR` on Linux, `~/Library/Application Support/<project>/runtime`
+    /// on macOS and `%APPDATA%\<organization>\<application>\runtime` on Windows, where the
+    /// sockets are named pipes derived from it
     ///
     /// # Errors
     ///
     /// Fails if the system doesn't have a HOME directory
-    pub fn runtime() -> Result<PathBuf> {
-        let default = BaseDirs::new()
+    pub fn runtime_dir() -> Result<PathBuf> {
+        Ok(env::var_or_default(
+            env::GISTIT_RUNTIME_VAR,
+            default_runtime_dir()?,
+        ))
+    }
+
+    /// Fallbacks to a temporary folder without `$XDG_RUNTIME_DIR`
+    #[cfg(not(any(target_os = "macos", windows)))]
+    fn default_runtime_dir() -> Result<PathBuf> {
+        Ok(directories::BaseDirs::new()
             .ok_or(Error::Directory("can't open home directory"))?
             .runtime_dir()
-            .map_or_else(std::env::temp_dir, Path::to_path_buf);
-        Ok(env::var_or_default(env::GISTIT_RUNTIME_VAR, default))
+            .map_or_else(std::env::temp_dir, std::path::Path::to_path_buf))
+    }
+
+    /// There's no `$XDG_RUNTIME_DIR` on macOS
+    #[cfg(target_os = "macos")]
+    fn default_runtime_dir() -> Result<PathBuf> {
+        Ok(ProjectDirs::from(QUALIFIER, ORGANIZATION, APPLICATION)
+            .ok_or(Error::Directory("can't open home directory"))?
+            .data_dir()
+            .join(RUNTIME_DIR_NAME))
+    }
+
+    #[cfg(windows)]
+    fn default_runtime_dir() -> Result<PathBuf> {
+        Ok(directories::BaseDirs::new()
+            .ok_or(Error::Directory("can't open home directory"))?
+            .data_dir()
+            .join(ORGANIZATION)
+            .join(APPLICATION)
+            .join(RUNTIME_DIR_NAME))
     }
 
     /// Returns the config path of this program
@@ -113,6 +146,13 @@ pub mod env {
 
     pub const GISTIT_SERVER_URL: &str = "GISTIT_SERVER_URL";
 
+    pub const GISTIT_PROFILE_VAR: &str = "GISTIT_PROFILE";
+
+    pub const GISTIT_IDENTITY_PASSPHRASE_VAR: &str = "GISTIT_IDENTITY_PASSPHRASE";
+
+    /// Secret of `gistit host` and `gistit fetch` when none is passed
+    pub const GISTIT_SECRET_VAR: &str = "GISTIT_SECRET";
+
     #[must_use]
     pub fn var_or_default(var: &str, default: PathBuf) -> PathBuf {
         env::var_os(var)
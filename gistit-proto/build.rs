@@ -1,4 +1,7 @@
 fn main() -> std::io::Result<()> {
-    prost_build::compile_protos(&["src/payload.proto", "src/ipc.proto"], &["src"])?;
+    // Log chunks are sliced out of the received frame instead of copied
+    prost_build::Config::new()
+        .bytes([".gistit.ipc.Instruction.LogChunkResponse.data"])
+        .compile_protos(&["src/payload.proto", "src/ipc.proto"], &["src"])?;
     Ok(())
 }
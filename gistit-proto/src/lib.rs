@@ -24,7 +24,9 @@ pub use bytes;
 pub use prost;
 
 pub use ipc::Instruction;
-pub use payload::{gistit::Inner, Gistit};
+pub use payload::{
+    gistit::Inner, Announcement, Candidates, Comment, Comments, Gistit, Head, History, Metadata,
+};
 
 pub mod payload {
     use super::prost::Message;
@@ -57,6 +59,14 @@ pub mod payload {
                 description,
                 timestamp,
                 inner,
+                lifespan: None,
+                visibility: gistit::Visibility::Public as i32,
+                token: None,
+                burn_after_read: false,
+                max_reads: None,
+                reads_left: None,
+                parent: None,
+                metadata: None,
             }
         }
 
@@ -72,9 +82,23 @@ pub mod payload {
                 lang,
                 size,
                 data,
+                encrypted: false,
+                binary: false,
             }
         }
 
+        /// The file named `name`
+        #[must_use]
+        pub fn file(&self, name: &str) -> Option<&gistit::Inner> {
+            self.inner.iter().find(|inner| inner.name == name)
+        }
+
+        /// How many fetches it's deleted after, if limited. Burning after reading is the same as one
+        #[must_use]
+        pub fn read_limit(&self) -> Option<u32> {
+            self.max_reads.or_else(|| self.burn_after_read.then(|| 1))
+        }
+
         /// Decodes a buffer into [`Self`]
         ///
         /// # Errors
@@ -84,11 +108,67 @@ pub mod payload {
             Ok(Self::decode(bytes.as_ref())?)
         }
     }
+
+    impl Announcement {
+        /// Announcement of `gistit` provided by `peer_id`, without it's content
+        #[must_use]
+        pub fn from_gistit(gistit: &Gistit, peer_id: String) -> Self {
+            let (name, lang, size) = gistit.inner.first().map_or_else(
+                || (String::new(), String::new(), 0),
+                |inner| (inner.name.clone(), inner.lang.clone(), inner.size),
+            );
+
+            Self {
+                hash: gistit.hash.clone(),
+                author: gistit.author.clone(),
+                description: gistit.description.clone(),
+                timestamp: gistit.timestamp.clone(),
+                name,
+                lang,
+                size,
+                peer_id,
+            }
+        }
+    }
+
+    impl Head {
+        /// Metadata of `gistit`. Gistits on the p2p network don't expire
+        #[must_use]
+        pub fn from_gistit(gistit: &Gistit, expires_at: Option<u64>) -> Self {
+            let (name, lang, size, encrypted) = gistit.inner.first().map_or_else(
+                || (String::new(), String::new(), 0, false),
+                |inner| {
+                    (
+                        inner.name.clone(),
+                        inner.lang.clone(),
+                        inner.size,
+                        inner.encrypted,
+                    )
+                },
+            );
+
+            Self {
+                hash: gistit.hash.clone(),
+                author: gistit.author.clone(),
+                description: gistit.description.clone(),
+                timestamp: gistit.timestamp.clone(),
+                name,
+                lang,
+                size,
+                encrypted,
+                expires_at,
+                burn_after_read: gistit.burn_after_read,
+                reads_left: gistit.reads_left,
+                parent: gistit.parent.clone(),
+            }
+        }
+    }
 }
 
 pub mod ipc {
-    use super::Gistit;
+    use super::{Announcement, Gistit, Head};
     use super::{Error, Result};
+    use bytes::Bytes;
 
     include!(concat!(env!("OUT_DIR"), "/gistit.ipc.rs"));
 
@@ -97,26 +177,95 @@ pub mod ipc {
         pub const fn request_status() -> Self {
             Self {
                 kind: Some(instruction::Kind::StatusRequest(
-                    instruction::StatusRequest {},
+                    instruction::StatusRequest { peers: false },
+                )),
+            }
+        }
+
+        /// Asks the status along with the scores of the best providers
+        #[must_use]
+        pub const fn request_status_peers() -> Self {
+            Self {
+                kind: Some(instruction::Kind::StatusRequest(
+                    instruction::StatusRequest { peers: true },
                 )),
             }
         }
 
+        /// Fetches `hash`, gistits deleted once read are only fetched if `burn_after_read`
         #[must_use]
-        pub const fn request_fetch(hash: String) -> Self {
+        pub const fn request_fetch(hash: String, burn_after_read: bool) -> Self {
             Self {
                 kind: Some(instruction::Kind::FetchRequest(instruction::FetchRequest {
                     hash,
+                    burn_after_read,
+                })),
+            }
+        }
+
+        #[must_use]
+        pub const fn request_head(hash: String) -> Self {
+            Self {
+                kind: Some(instruction::Kind::HeadRequest(instruction::HeadRequest {
+                    hash,
                 })),
             }
         }
 
         #[must_use]
-        pub const fn request_provide(gistit: Gistit) -> Self {
+        pub const fn request_cancel(hash: String) -> Self {
+            Self {
+                kind: Some(instruction::Kind::CancelRequest(
+                    instruction::CancelRequest { hash },
+                )),
+            }
+        }
+
+        #[must_use]
+        pub const fn request_exists(hash: String) -> Self {
+            Self {
+                kind: Some(instruction::Kind::ExistsRequest(
+                    instruction::ExistsRequest { hash },
+                )),
+            }
+        }
+
+        #[must_use]
+        pub const fn request_resolve_prefix(prefix: String) -> Self {
+            Self {
+                kind: Some(instruction::Kind::ResolvePrefixRequest(
+                    instruction::ResolvePrefixRequest { prefix },
+                )),
+            }
+        }
+
+        #[must_use]
+        pub const fn request_reload_config() -> Self {
+            Self {
+                kind: Some(instruction::Kind::ReloadConfigRequest(
+                    instruction::ReloadConfigRequest {},
+                )),
+            }
+        }
+
+        /// Sets the daemon log level, or only asks it if `level` is `None`
+        #[must_use]
+        pub const fn request_log_level(level: Option<String>) -> Self {
+            Self {
+                kind: Some(instruction::Kind::LogLevelRequest(
+                    instruction::LogLevelRequest { level },
+                )),
+            }
+        }
+
+        #[must_use]
+        pub const fn request_provide(gistit: Gistit, topics: Vec<String>) -> Self {
             Self {
                 kind: Some(instruction::Kind::ProvideRequest(
                     instruction::ProvideRequest {
                         gistit: Some(gistit),
+                        topics,
+                        gistit_attached: false,
                     },
                 )),
             }
@@ -141,33 +290,225 @@ pub mod ipc {
         }
 
         #[must_use]
-        pub const fn respond_status(
-            peer_id: String,
-            peer_count: u32,
-            pending_connections: u32,
-            hosting: u32,
+        pub const fn request_tail_logs(offset: Option<u64>) -> Self {
+            Self {
+                kind: Some(instruction::Kind::TailLogsRequest(
+                    instruction::TailLogsRequest { offset },
+                )),
+            }
+        }
+
+        #[must_use]
+        pub const fn request_revoke(hash: String) -> Self {
+            Self {
+                kind: Some(instruction::Kind::RevokeRequest(
+                    instruction::RevokeRequest { hash },
+                )),
+            }
+        }
+
+        #[must_use]
+        pub const fn request_subscribe(topics: Vec<String>) -> Self {
+            Self {
+                kind: Some(instruction::Kind::SubscribeRequest(
+                    instruction::SubscribeRequest { topics },
+                )),
+            }
+        }
+
+        #[must_use]
+        pub const fn request_push(gistit: Gistit, peer_id: String) -> Self {
+            Self {
+                kind: Some(instruction::Kind::PushRequest(instruction::PushRequest {
+                    gistit: Some(gistit),
+                    peer_id,
+                })),
+            }
+        }
+
+        #[must_use]
+        pub const fn request_acl(add: bool, rule: String) -> Self {
+            Self {
+                kind: Some(instruction::Kind::AclRequest(instruction::AclRequest {
+                    add,
+                    rule,
+                })),
+            }
+        }
+
+        #[must_use]
+        pub const fn request_limits(
+            upload_rate: Option<u64>,
+            download_rate: Option<u64>,
+            peer_request_rate: Option<u32>,
         ) -> Self {
             Self {
-                kind: Some(instruction::Kind::StatusResponse(
-                    instruction::StatusResponse {
-                        peer_id,
-                        peer_count,
-                        pending_connections,
-                        hosting,
+                kind: Some(instruction::Kind::LimitsRequest(
+                    instruction::LimitsRequest {
+                        upload_rate,
+                        download_rate,
+                        peer_request_rate,
                     },
                 )),
             }
         }
 
+        #[must_use]
+        pub const fn request_publish_name(name: String, hash: String) -> Self {
+            Self {
+                kind: Some(instruction::Kind::PublishNameRequest(
+                    instruction::PublishNameRequest { name, hash },
+                )),
+            }
+        }
+
+        #[must_use]
+        pub const fn request_resolve_name(name: String) -> Self {
+            Self {
+                kind: Some(instruction::Kind::ResolveNameRequest(
+                    instruction::ResolveNameRequest { name },
+                )),
+            }
+        }
+
+        #[must_use]
+        pub const fn request_pin(hash: String, pin: bool) -> Self {
+            Self {
+                kind: Some(instruction::Kind::PinRequest(instruction::PinRequest {
+                    hash,
+                    pin,
+                })),
+            }
+        }
+
+        #[must_use]
+        pub const fn request_list_pins() -> Self {
+            Self {
+                kind: Some(instruction::Kind::ListPinsRequest(
+                    instruction::ListPinsRequest {},
+                )),
+            }
+        }
+
+        #[must_use]
+        pub const fn request_diagnostics() -> Self {
+            Self {
+                kind: Some(instruction::Kind::DiagnosticsRequest(
+                    instruction::DiagnosticsRequest {},
+                )),
+            }
+        }
+
+        #[must_use]
+        pub const fn request_inbox() -> Self {
+            Self {
+                kind: Some(instruction::Kind::InboxRequest(
+                    instruction::InboxRequest {},
+                )),
+            }
+        }
+
+        #[must_use]
+        pub const fn request_inbox_decision(id: u32, accept: bool) -> Self {
+            Self {
+                kind: Some(instruction::Kind::InboxDecisionRequest(
+                    instruction::InboxDecisionRequest { id, accept },
+                )),
+            }
+        }
+
+        #[must_use]
+        pub const fn respond_status(status: instruction::StatusResponse) -> Self {
+            Self {
+                kind: Some(instruction::Kind::StatusResponse(status)),
+            }
+        }
+
         #[must_use]
         pub const fn respond_fetch(gistit: Option<Gistit>) -> Self {
             Self {
                 kind: Some(instruction::Kind::FetchResponse(
-                    instruction::FetchResponse { gistit },
+                    instruction::FetchResponse {
+                        gistit,
+                        burn_after_read: false,
+                        gistit_attached: false,
+                    },
+                )),
+            }
+        }
+
+        /// The gistit is deleted once read and the request didn't agree to it
+        #[must_use]
+        pub const fn respond_fetch_burn_after_read() -> Self {
+            Self {
+                kind: Some(instruction::Kind::FetchResponse(
+                    instruction::FetchResponse {
+                        gistit: None,
+                        burn_after_read: true,
+                        gistit_attached: false,
+                    },
                 )),
             }
         }
 
+        #[must_use]
+        pub const fn respond_head(head: Option<Head>) -> Self {
+            Self {
+                kind: Some(instruction::Kind::HeadResponse(instruction::HeadResponse {
+                    head,
+                })),
+            }
+        }
+
+        #[must_use]
+        pub const fn respond_exists(exists: bool) -> Self {
+            Self {
+                kind: Some(instruction::Kind::ExistsResponse(
+                    instruction::ExistsResponse { exists },
+                )),
+            }
+        }
+
+        #[must_use]
+        pub const fn respond_resolve_prefix(hashes: Vec<String>) -> Self {
+            Self {
+                kind: Some(instruction::Kind::ResolvePrefixResponse(
+                    instruction::ResolvePrefixResponse { hashes },
+                )),
+            }
+        }
+
+        #[must_use]
+        pub const fn respond_reload_config(
+            errors: Vec<String>,
+            needs_restart: Vec<String>,
+        ) -> Self {
+            Self {
+                kind: Some(instruction::Kind::ReloadConfigResponse(
+                    instruction::ReloadConfigResponse {
+                        errors,
+                        needs_restart,
+                    },
+                )),
+            }
+        }
+
+        #[must_use]
+        pub const fn respond_log_level(level: String, from_env: bool) -> Self {
+            Self {
+                kind: Some(instruction::Kind::LogLevelResponse(
+                    instruction::LogLevelResponse { level, from_env },
+                )),
+            }
+        }
+
+        #[must_use]
+        pub const fn respond_fetch_progress(progress: instruction::FetchProgressResponse) -> Self {
+            Self {
+                kind: Some(instruction::Kind::FetchProgressResponse(progress)),
+            }
+        }
+
         #[must_use]
         pub const fn respond_provide(maybe_hash: Option<String>) -> Self {
             Self {
@@ -177,6 +518,144 @@ pub mod ipc {
             }
         }
 
+        #[must_use]
+        pub fn respond_log_chunk(data: impl Into<Bytes>, offset: u64) -> Self {
+            Self {
+                kind: Some(instruction::Kind::LogChunkResponse(
+                    instruction::LogChunkResponse {
+                        data: data.into(),
+                        offset,
+                    },
+                )),
+            }
+        }
+
+        #[must_use]
+        pub const fn respond_revoke(revoked: bool) -> Self {
+            Self {
+                kind: Some(instruction::Kind::RevokeResponse(
+                    instruction::RevokeResponse { revoked },
+                )),
+            }
+        }
+
+        #[must_use]
+        pub const fn respond_event(topic: String, announcement: Announcement) -> Self {
+            Self {
+                kind: Some(instruction::Kind::EventResponse(
+                    instruction::EventResponse {
+                        topic,
+                        announcement: Some(announcement),
+                    },
+                )),
+            }
+        }
+
+        #[must_use]
+        pub const fn respond_push(delivered: bool) -> Self {
+            Self {
+                kind: Some(instruction::Kind::PushResponse(instruction::PushResponse {
+                    delivered,
+                })),
+            }
+        }
+
+        #[must_use]
+        pub const fn respond_incoming(gistit: Gistit, peer_id: String, inbox_id: u32) -> Self {
+            Self {
+                kind: Some(instruction::Kind::IncomingResponse(
+                    instruction::IncomingResponse {
+                        gistit: Some(gistit),
+                        peer_id,
+                        inbox_id,
+                    },
+                )),
+            }
+        }
+
+        #[must_use]
+        pub const fn respond_acl(changed: bool) -> Self {
+            Self {
+                kind: Some(instruction::Kind::AclResponse(instruction::AclResponse {
+                    changed,
+                })),
+            }
+        }
+
+        #[must_use]
+        pub const fn respond_limits(limits: instruction::Limits) -> Self {
+            Self {
+                kind: Some(instruction::Kind::LimitsResponse(
+                    instruction::LimitsResponse {
+                        limits: Some(limits),
+                    },
+                )),
+            }
+        }
+
+        #[must_use]
+        pub const fn respond_publish_name(published: bool) -> Self {
+            Self {
+                kind: Some(instruction::Kind::PublishNameResponse(
+                    instruction::PublishNameResponse { published },
+                )),
+            }
+        }
+
+        #[must_use]
+        pub const fn respond_resolve_name(hash: Option<String>, peer_id: String) -> Self {
+            Self {
+                kind: Some(instruction::Kind::ResolveNameResponse(
+                    instruction::ResolveNameResponse { hash, peer_id },
+                )),
+            }
+        }
+
+        #[must_use]
+        pub const fn respond_pin(changed: bool) -> Self {
+            Self {
+                kind: Some(instruction::Kind::PinResponse(instruction::PinResponse {
+                    changed,
+                })),
+            }
+        }
+
+        #[must_use]
+        pub const fn respond_list_pins(pins: Vec<instruction::Pin>, used: u64, quota: u64) -> Self {
+            Self {
+                kind: Some(instruction::Kind::ListPinsResponse(
+                    instruction::ListPinsResponse { pins, used, quota },
+                )),
+            }
+        }
+
+        #[must_use]
+        pub const fn respond_diagnostics(checks: Vec<instruction::DiagnosticCheck>) -> Self {
+            Self {
+                kind: Some(instruction::Kind::DiagnosticsResponse(
+                    instruction::DiagnosticsResponse { checks },
+                )),
+            }
+        }
+
+        #[must_use]
+        pub const fn respond_inbox(items: Vec<instruction::InboxItem>) -> Self {
+            Self {
+                kind: Some(instruction::Kind::InboxResponse(
+                    instruction::InboxResponse { items },
+                )),
+            }
+        }
+
+        #[must_use]
+        pub const fn respond_inbox_decision(hash: Option<String>) -> Self {
+            Self {
+                kind: Some(instruction::Kind::InboxDecisionResponse(
+                    instruction::InboxDecisionResponse { hash },
+                )),
+            }
+        }
+
         /// Unwraps [`Self`] expecting a request kind
         ///
         /// # Errors
@@ -190,7 +669,27 @@ pub mod ipc {
                         Some(
                             instruction::Kind::FetchResponse(_)
                             | instruction::Kind::ProvideResponse(_)
-                            | instruction::Kind::StatusResponse(_),
+                            | instruction::Kind::StatusResponse(_)
+                            | instruction::Kind::LogChunkResponse(_)
+                            | instruction::Kind::RevokeResponse(_)
+                            | instruction::Kind::EventResponse(_)
+                            | instruction::Kind::PushResponse(_)
+                            | instruction::Kind::IncomingResponse(_)
+                            | instruction::Kind::AclResponse(_)
+                            | instruction::Kind::LimitsResponse(_)
+                            | instruction::Kind::FetchProgressResponse(_)
+                            | instruction::Kind::PublishNameResponse(_)
+                            | instruction::Kind::ResolveNameResponse(_)
+                            | instruction::Kind::PinResponse(_)
+                            | instruction::Kind::ListPinsResponse(_)
+                            | instruction::Kind::DiagnosticsResponse(_)
+                            | instruction::Kind::InboxResponse(_)
+                            | instruction::Kind::InboxDecisionResponse(_)
+                            | instruction::Kind::HeadResponse(_)
+                            | instruction::Kind::ExistsResponse(_)
+                            | instruction::Kind::ResolvePrefixResponse(_)
+                            | instruction::Kind::ReloadConfigResponse(_)
+                            | instruction::Kind::LogLevelResponse(_),
                         )
                         | None,
                 } => Err(Error::Other("instruction is not a request")),
@@ -214,7 +713,27 @@ pub mod ipc {
                             instruction::Kind::FetchRequest(_)
                             | instruction::Kind::StatusRequest(_)
                             | instruction::Kind::ShutdownRequest(_)
-                            | instruction::Kind::ProvideRequest(_),
+                            | instruction::Kind::ProvideRequest(_)
+                            | instruction::Kind::DialRequest(_)
+                            | instruction::Kind::TailLogsRequest(_)
+                            | instruction::Kind::RevokeRequest(_)
+                            | instruction::Kind::SubscribeRequest(_)
+                            | instruction::Kind::PushRequest(_)
+                            | instruction::Kind::AclRequest(_)
+                            | instruction::Kind::LimitsRequest(_)
+                            | instruction::Kind::PublishNameRequest(_)
+                            | instruction::Kind::ResolveNameRequest(_)
+                            | instruction::Kind::PinRequest(_)
+                            | instruction::Kind::ListPinsRequest(_)
+                            | instruction::Kind::DiagnosticsRequest(_)
+                            | instruction::Kind::InboxRequest(_)
+                            | instruction::Kind::InboxDecisionRequest(_)
+                            | instruction::Kind::HeadRequest(_)
+                            | instruction::Kind::CancelRequest(_)
+                            | instruction::Kind::ExistsRequest(_)
+                            | instruction::Kind::ResolvePrefixRequest(_)
+                            | instruction::Kind::ReloadConfigRequest(_)
+                            | instruction::Kind::LogLevelRequest(_),
                         )
                         | None,
                 } => Err(Error::Other("instruction is not a response")),
@@ -262,11 +781,11 @@ mod tests {
     #[test]
     fn test_ipc_unwrap_methods() {
         let req1 = Instruction::request_shutdown().expect_request().unwrap();
-        let req2 = Instruction::request_provide(Gistit::default())
+        let req2 = Instruction::request_provide(Gistit::default(), Vec::new())
             .expect_request()
             .unwrap();
         let req3 = Instruction::request_status().expect_request().unwrap();
-        let req4 = Instruction::request_fetch(String::new())
+        let req4 = Instruction::request_fetch(String::new(), false)
             .expect_request()
             .unwrap();
 
@@ -276,10 +795,173 @@ mod tests {
         let res2 = Instruction::respond_provide(None)
             .expect_response()
             .unwrap();
-        let res3 = Instruction::respond_status(String::new(), 0, 0, 0)
+        let res3 = Instruction::respond_status(ipc::instruction::StatusResponse::default())
+            .expect_response()
+            .unwrap();
+        let res4 = Instruction::respond_log_chunk(Vec::new(), 0)
             .expect_response()
             .unwrap();
 
+        assert!(Instruction::request_revoke(String::new())
+            .expect_response()
+            .is_err());
+        assert!(Instruction::respond_revoke(true).expect_request().is_err());
+        assert!(Instruction::request_tail_logs(None)
+            .expect_response()
+            .is_err());
+        assert!(Instruction::respond_log_chunk(Vec::new(), 0)
+            .expect_request()
+            .is_err());
+
+        assert!(
+            Instruction::request_subscribe(vec!["gistit/rust".to_owned()])
+                .expect_response()
+                .is_err()
+        );
+        assert!(
+            Instruction::respond_event(String::new(), Announcement::default())
+                .expect_request()
+                .is_err()
+        );
+
+        assert!(Instruction::request_push(Gistit::default(), String::new())
+            .expect_response()
+            .is_err());
+        assert!(Instruction::respond_push(true).expect_request().is_err());
+        assert!(Instruction::request_acl(true, String::new())
+            .expect_response()
+            .is_err());
+        assert!(Instruction::respond_acl(true).expect_request().is_err());
+        assert!(Instruction::request_limits(Some(1024), None, None)
+            .expect_response()
+            .is_err());
+        assert!(
+            Instruction::respond_limits(ipc::instruction::Limits::default())
+                .expect_request()
+                .is_err()
+        );
+        assert!(
+            Instruction::respond_incoming(Gistit::default(), String::new(), 0)
+                .expect_request()
+                .is_err()
+        );
+        assert!(Instruction::respond_fetch_progress(
+            ipc::instruction::FetchProgressResponse::default()
+        )
+        .expect_request()
+        .is_err());
+        assert!(
+            Instruction::request_publish_name("@alice/dotfiles".to_owned(), String::new())
+                .expect_response()
+                .is_err()
+        );
+        assert!(
+            Instruction::request_resolve_name("@alice/dotfiles".to_owned())
+                .expect_response()
+                .is_err()
+        );
+        assert!(Instruction::respond_publish_name(true)
+            .expect_request()
+            .is_err());
+        assert!(Instruction::respond_resolve_name(None, String::new())
+            .expect_request()
+            .is_err());
+        assert!(Instruction::request_pin(String::new(), true)
+            .expect_response()
+            .is_err());
+        assert!(Instruction::request_list_pins().expect_response().is_err());
+        assert!(Instruction::respond_pin(true).expect_request().is_err());
+        assert!(Instruction::respond_list_pins(Vec::new(), 0, 0)
+            .expect_request()
+            .is_err());
+        assert!(Instruction::request_diagnostics()
+            .expect_response()
+            .is_err());
+        assert!(Instruction::respond_diagnostics(Vec::new())
+            .expect_request()
+            .is_err());
+        assert!(Instruction::request_inbox().expect_response().is_err());
+        assert!(Instruction::request_inbox_decision(1, true)
+            .expect_response()
+            .is_err());
+        assert!(Instruction::respond_inbox(Vec::new())
+            .expect_request()
+            .is_err());
+        assert!(Instruction::respond_inbox_decision(None)
+            .expect_request()
+            .is_err());
+        assert!(Instruction::request_head(String::new())
+            .expect_response()
+            .is_err());
+        assert!(Instruction::respond_head(None).expect_request().is_err());
+        assert!(Instruction::request_exists(String::new())
+            .expect_response()
+            .is_err());
+        assert!(Instruction::respond_exists(true).expect_request().is_err());
+        assert!(Instruction::request_resolve_prefix(String::new())
+            .expect_response()
+            .is_err());
+        assert!(Instruction::respond_resolve_prefix(Vec::new())
+            .expect_request()
+            .is_err());
+        assert!(Instruction::request_reload_config()
+            .expect_response()
+            .is_err());
+        assert!(Instruction::respond_reload_config(Vec::new(), Vec::new())
+            .expect_request()
+            .is_err());
+        assert!(Instruction::request_log_level(None)
+            .expect_response()
+            .is_err());
+        assert!(Instruction::respond_log_level(String::new(), false)
+            .expect_request()
+            .is_err());
+
         assert!(true);
     }
+
+    #[test]
+    fn test_announcement_from_gistit() {
+        let mut gistit = Gistit::default();
+        gistit.hash = "hash".to_owned();
+        gistit.inner.push(Gistit::new_inner(
+            "main.rs".to_owned(),
+            "rust".to_owned(),
+            42,
+            "fn main() {}".to_owned(),
+        ));
+
+        let announcement = Announcement::from_gistit(&gistit, "peer".to_owned());
+        assert_eq!(announcement.hash, "hash");
+        assert_eq!(announcement.name, "main.rs");
+        assert_eq!(announcement.size, 42);
+        assert_eq!(announcement.peer_id, "peer");
+    }
+
+    #[test]
+    fn test_head_from_gistit() {
+        let mut gistit = Gistit::default();
+        gistit.hash = "hash".to_owned();
+        gistit.author = "alice".to_owned();
+        let mut inner = Gistit::new_inner(
+            "main.rs".to_owned(),
+            "rust".to_owned(),
+            42,
+            "fn main() {}".to_owned(),
+        );
+        inner.encrypted = true;
+        gistit.inner.push(inner);
+        gistit.burn_after_read = true;
+
+        let head = Head::from_gistit(&gistit, Some(1000));
+        assert_eq!(head.hash, "hash");
+        assert_eq!(head.author, "alice");
+        assert_eq!(head.name, "main.rs");
+        assert_eq!(head.lang, "rust");
+        assert_eq!(head.size, 42);
+        assert!(head.encrypted);
+        assert_eq!(head.expires_at, Some(1000));
+        assert!(head.burn_after_read);
+        assert_eq!(Head::decode(&*head.encode_to_vec()).unwrap(), head);
+    }
 }
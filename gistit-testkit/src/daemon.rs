@@ -0,0 +1,486 @@
+//! The daemon module
+//!
+//! [`FakeDaemon`] answers the IPC instructions of gistit-cli and gistit-core in a runtime
+//! directory, like `gistit-daemon` does, without ever reaching the p2p network. The gistits it was
+//! given, through provides, [`FakeDaemon::insert`] or accepted pushes, are the only ones found.
+//! Pushes are always delivered and names always published, the rest is answered as a node without
+//! peers would. Cancels and dials aren't answered, like the real one, and a shutdown stops it.
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use tokio::task::JoinHandle;
+
+use gistit_ipc::{Bridge, Server};
+use gistit_proto::ipc::instruction::{self, Kind};
+use gistit_proto::{Announcement, Gistit, Head, Instruction};
+
+use crate::{lock, Result};
+
+/// Peer id of the fake node, answered to status and name requests
+pub const PEER_ID: &str = "12D3KooWGzh5Wxb3ErFCxB2Hp8P8W4R1rYqPgGz2eHhL3pP7uYxq";
+
+/// Bytes it can store, the default of `gistit-daemon`
+const STORAGE_QUOTA: u64 = 256 * 1024 * 1024;
+
+#[derive(Debug)]
+struct State {
+    gistits: HashMap<String, Gistit>,
+    pins: BTreeSet<String>,
+    /// Published names, `@user/name` to hash
+    names: HashMap<String, String>,
+    acl: Vec<String>,
+    limits: instruction::Limits,
+    /// Subscribed gossipsub topics
+    topics: Vec<String>,
+    inbox: Vec<(instruction::InboxItem, Gistit)>,
+    next_inbox_id: u32,
+    log_level: String,
+    /// Every instruction received, in the order they came in
+    requests: Vec<Instruction>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            gistits: HashMap::new(),
+            pins: BTreeSet::new(),
+            names: HashMap::new(),
+            acl: Vec::new(),
+            limits: instruction::Limits::default(),
+            topics: Vec::new(),
+            inbox: Vec::new(),
+            next_inbox_id: 0,
+            log_level: "info".to_owned(),
+            requests: Vec::new(),
+        }
+    }
+}
+
+/// `gistit-daemon`, listening in a runtime directory
+#[derive(Debug)]
+pub struct FakeDaemon {
+    bridge: Arc<Bridge<Server>>,
+    state: Arc<Mutex<State>>,
+    task: JoinHandle<()>,
+}
+
+impl FakeDaemon {
+    /// Starts answering instructions in `runtime_path`, until it's dropped or asked to shutdown.
+    /// Has to be called inside a tokio runtime
+    ///
+    /// # Errors
+    ///
+    /// Fails if the socket can't be bound in `runtime_path`
+    pub fn start(runtime_path: &Path) -> Result<Self> {
+        let bridge = Arc::new(gistit_ipc::server(runtime_path)?);
+        let state = Arc::new(Mutex::new(State::default()));
+
+        let (answering, serving) = (bridge.clone(), state.clone());
+        let runtime_path = runtime_path.to_path_buf();
+        let task = tokio::spawn(async move {
+            while let Ok(instruction) = answering.recv().await {
+                lock(&serving).requests.push(instruction.clone());
+                let request = match instruction.expect_request() {
+                    Ok(Kind::ShutdownRequest(_)) => break,
+                    Ok(request) => request,
+                    Err(_) => continue,
+                };
+
+                let answer = handle(request, &mut lock(&serving));
+                if let Some(answer) = answer {
+                    let _ = send(&answering, answer).await;
+                }
+            }
+            stop(&runtime_path);
+        });
+
+        Ok(Self {
+            bridge,
+            state,
+            task,
+        })
+    }
+
+    /// Stores `gistit` as if it was provided
+    pub fn insert(&self, gistit: Gistit) {
+        lock(&self.state)
+            .gistits
+            .insert(gistit.hash.clone(), gistit);
+    }
+
+    /// The stored gistit `hash`
+    #[must_use]
+    pub fn gistit(&self, hash: &str) -> Option<Gistit> {
+        lock(&self.state).gistits.get(hash).cloned()
+    }
+
+    /// Every instruction received so far
+    #[must_use]
+    pub fn requests(&self) -> Vec<Instruction> {
+        lock(&self.state).requests.clone()
+    }
+
+    /// Streams an announcement of `gistit` on `topic` to the client, as if another peer provided
+    /// it. `false` if the client isn't subscribed to `topic`
+    ///
+    /// # Errors
+    ///
+    /// Fails if the client went away
+    pub async fn announce(&self, topic: &str, gistit: &Gistit) -> Result<bool> {
+        let subscribed = lock(&self.state).topics.iter().any(|t| t == topic);
+        if !subscribed {
+            return Ok(false);
+        }
+
+        let announcement = Announcement::from_gistit(gistit, PEER_ID.to_owned());
+        send(
+            &self.bridge,
+            Instruction::respond_event(topic.to_owned(), announcement),
+        )
+        .await?;
+        Ok(true)
+    }
+
+    /// Puts `gistit` in the inbox, as if `peer_id` pushed it, and tells the client if it's
+    /// listening. Returns it's inbox id
+    ///
+    /// # Errors
+    ///
+    /// Fails if the client is listening but couldn't be told
+    pub async fn deliver(&self, gistit: Gistit, peer_id: &str) -> Result<u32> {
+        let id = {
+            let mut state = lock(&self.state);
+            state.next_inbox_id += 1;
+            let item = instruction::InboxItem {
+                id: state.next_inbox_id,
+                hash: gistit.hash.clone(),
+                author: gistit.author.clone(),
+                description: gistit.description.clone(),
+                peer_id: peer_id.to_owned(),
+                size: size(&gistit),
+                // Unix epoch, so output doesn't depend on when tests run
+                received_at: 0,
+            };
+            state.inbox.push((item, gistit.clone()));
+            state.next_inbox_id
+        };
+
+        if self.bridge.alive() {
+            send(
+                &self.bridge,
+                Instruction::respond_incoming(gistit, peer_id.to_owned(), id),
+            )
+            .await?;
+        }
+        Ok(id)
+    }
+}
+
+impl Drop for FakeDaemon {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// The answer to `request`, `None` if it isn't answered
+#[allow(clippy::too_many_lines)]
+fn handle(request: Kind, state: &mut State) -> Option<Instruction> {
+    let answer = match request {
+        Kind::ProvideRequest(instruction::ProvideRequest { gistit, .. }) => {
+            let hash = gistit.map(|gistit| {
+                let hash = gistit.hash.clone();
+                state.gistits.insert(hash.clone(), gistit);
+                hash
+            });
+            Instruction::respond_provide(hash)
+        }
+        Kind::FetchRequest(instruction::FetchRequest {
+            hash,
+            burn_after_read,
+        }) => match state.gistits.get(&hash) {
+            Some(gistit) if gistit.burn_after_read && !burn_after_read => {
+                Instruction::respond_fetch_burn_after_read()
+            }
+            Some(gistit) if gistit.burn_after_read => {
+                Instruction::respond_fetch(state.gistits.remove(&hash))
+            }
+            gistit => Instruction::respond_fetch(gistit.cloned()),
+        },
+        Kind::HeadRequest(instruction::HeadRequest { hash }) => Instruction::respond_head(
+            state
+                .gistits
+                .get(&hash)
+                .map(|gistit| Head::from_gistit(gistit, None)),
+        ),
+        Kind::ExistsRequest(instruction::ExistsRequest { hash }) => {
+            Instruction::respond_exists(state.gistits.contains_key(&hash))
+        }
+        Kind::ResolvePrefixRequest(instruction::ResolvePrefixRequest { prefix }) => {
+            let mut hashes: Vec<String> = state
+                .gistits
+                .keys()
+                .filter(|hash| hash.starts_with(&prefix))
+                .cloned()
+                .collect();
+            hashes.sort();
+            Instruction::respond_resolve_prefix(hashes)
+        }
+        Kind::ReloadConfigRequest(_) => Instruction::respond_reload_config(Vec::new(), Vec::new()),
+        Kind::LogLevelRequest(instruction::LogLevelRequest { level }) => {
+            if let Some(level) = level {
+                state.log_level = level;
+            }
+            Instruction::respond_log_level(state.log_level.clone(), false)
+        }
+        Kind::StatusRequest(_) => Instruction::respond_status(instruction::StatusResponse {
+            peer_id: PEER_ID.to_owned(),
+            hosting: u32::try_from(state.gistits.len()).unwrap_or(u32::MAX),
+            reachability: "unknown".to_owned(),
+            acl: state.acl.clone(),
+            limits: Some(state.limits.clone()),
+            ..instruction::StatusResponse::default()
+        }),
+        Kind::RevokeRequest(instruction::RevokeRequest { hash }) => {
+            state.pins.remove(&hash);
+            Instruction::respond_revoke(state.gistits.remove(&hash).is_some())
+        }
+        Kind::TailLogsRequest(instruction::TailLogsRequest { offset }) => {
+            Instruction::respond_log_chunk(Vec::new(), offset.unwrap_or_default())
+        }
+        Kind::SubscribeRequest(instruction::SubscribeRequest { topics }) => {
+            state.topics = topics;
+            return None;
+        }
+        Kind::PushRequest(_) => Instruction::respond_push(true),
+        Kind::AclRequest(instruction::AclRequest { add, rule }) => {
+            let valid = rule.starts_with("allow ") || rule.starts_with("deny ");
+            let present = state.acl.contains(&rule);
+            if valid && add && !present {
+                state.acl.push(rule);
+            } else if valid && !add && present {
+                state.acl.retain(|other| *other != rule);
+            }
+            Instruction::respond_acl(valid && add != present)
+        }
+        Kind::LimitsRequest(instruction::LimitsRequest {
+            upload_rate,
+            download_rate,
+            peer_request_rate,
+        }) => {
+            let limits = &mut state.limits;
+            limits.upload_rate = upload_rate.unwrap_or(limits.upload_rate);
+            limits.download_rate = download_rate.unwrap_or(limits.download_rate);
+            limits.peer_request_rate = peer_request_rate.unwrap_or(limits.peer_request_rate);
+            Instruction::respond_limits(limits.clone())
+        }
+        Kind::PublishNameRequest(instruction::PublishNameRequest { name, hash }) => {
+            state.names.insert(name, hash);
+            Instruction::respond_publish_name(true)
+        }
+        Kind::ResolveNameRequest(instruction::ResolveNameRequest { name }) => {
+            Instruction::respond_resolve_name(state.names.get(&name).cloned(), PEER_ID.to_owned())
+        }
+        Kind::PinRequest(instruction::PinRequest { hash, pin }) => {
+            let changed = if pin {
+                state.gistits.contains_key(&hash) && state.pins.insert(hash)
+            } else {
+                state.pins.remove(&hash)
+            };
+            Instruction::respond_pin(changed)
+        }
+        Kind::ListPinsRequest(_) => {
+            let pins = state
+                .pins
+                .iter()
+                .map(|hash| instruction::Pin {
+                    hash: hash.clone(),
+                    size: state.gistits.get(hash).map_or(0, size),
+                    last_access: 0,
+                })
+                .collect();
+            let used = state.gistits.values().map(size).sum();
+            Instruction::respond_list_pins(pins, used, STORAGE_QUOTA)
+        }
+        Kind::DiagnosticsRequest(_) => Instruction::respond_diagnostics(Vec::new()),
+        Kind::InboxRequest(_) => {
+            Instruction::respond_inbox(state.inbox.iter().map(|(item, _)| item.clone()).collect())
+        }
+        Kind::InboxDecisionRequest(instruction::InboxDecisionRequest { id, accept }) => {
+            let position = state.inbox.iter().position(|(item, _)| item.id == id);
+            let hash = position.map(|position| {
+                let (item, gistit) = state.inbox.remove(position);
+                if accept {
+                    state.gistits.insert(item.hash.clone(), gistit);
+                }
+                item.hash
+            });
+            Instruction::respond_inbox_decision(hash)
+        }
+        // Cancels, dials and responses aren't answered
+        _ => return None,
+    };
+    Some(answer)
+}
+
+/// Bytes of all the files of `gistit`
+fn size(gistit: &Gistit) -> u64 {
+    gistit.inner.iter().map(|inner| u64::from(inner.size)).sum()
+}
+
+async fn send(bridge: &Bridge<Server>, instruction: Instruction) -> Result<()> {
+    bridge.connect_blocking()?;
+    bridge.send(instruction).await?;
+    Ok(())
+}
+
+/// Removes the socket of a stopped daemon, so clients see it isn't running
+fn stop(runtime_path: &Path) {
+    let _ = std::fs::remove_file(runtime_path.join("gistit-0"));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gistit_core::{Destination, FetchRequest, SendRequest, Source};
+
+    async fn ask(runtime_path: &Path, request: Instruction) -> Kind {
+        let mut client = gistit_ipc::client(runtime_path).unwrap();
+        client.connect_blocking().unwrap();
+        client.send(request).await.unwrap();
+        client.recv().await.unwrap().expect_response().unwrap()
+    }
+
+    #[tokio::test]
+    async fn daemon_provide_and_fetch() {
+        let tmp = assert_fs::TempDir::new().unwrap();
+        let daemon = FakeDaemon::start(&tmp).unwrap();
+
+        let receipt = gistit_core::send(SendRequest {
+            destination: Destination::Daemon {
+                runtime_path: tmp.to_path_buf(),
+                topics: Vec::new(),
+            },
+            ..SendRequest::new("main.rs", "fn main() {}", "author")
+        })
+        .await
+        .unwrap();
+        assert!(daemon.gistit(&receipt.hash).is_some());
+
+        let fetched = gistit_core::fetch(FetchRequest {
+            sources: vec![Source::Daemon(tmp.to_path_buf())],
+            ..FetchRequest::new(&receipt.hash)
+        })
+        .await
+        .unwrap();
+        assert_eq!(fetched.data(), "fn main() {}");
+
+        let head = gistit_core::fetch::head_daemon(&tmp, &receipt.hash)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(head.name, "main.rs");
+
+        assert!(matches!(
+            ask(&tmp, Instruction::request_resolve_prefix(receipt.hash[..7].to_owned())).await,
+            Kind::ResolvePrefixResponse(instruction::ResolvePrefixResponse { hashes })
+                if hashes == [receipt.hash.clone()]
+        ));
+        assert!(matches!(
+            ask(&tmp, Instruction::request_revoke(receipt.hash.clone())).await,
+            Kind::RevokeResponse(instruction::RevokeResponse { revoked: true })
+        ));
+        assert!(matches!(
+            ask(
+                &tmp,
+                Instruction::request_fetch(receipt.hash.clone(), false)
+            )
+            .await,
+            Kind::FetchResponse(instruction::FetchResponse { gistit: None, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn daemon_answers_requests() {
+        let tmp = assert_fs::TempDir::new().unwrap();
+        let daemon = FakeDaemon::start(&tmp).unwrap();
+        let gistit = gistit_core::new_gistit(
+            "main.rs",
+            "rust",
+            "fn main() {}".to_owned(),
+            "author".to_owned(),
+            None,
+        );
+        daemon.insert(gistit.clone());
+
+        assert!(matches!(
+            ask(&tmp, Instruction::request_status()).await,
+            Kind::StatusResponse(instruction::StatusResponse { hosting: 1, .. })
+        ));
+        assert!(matches!(
+            ask(&tmp, Instruction::request_pin(gistit.hash.clone(), true)).await,
+            Kind::PinResponse(instruction::PinResponse { changed: true })
+        ));
+        assert!(matches!(
+            ask(&tmp, Instruction::request_list_pins()).await,
+            Kind::ListPinsResponse(instruction::ListPinsResponse { pins, used: 12, .. })
+                if pins.len() == 1
+        ));
+        assert!(matches!(
+            ask(
+                &tmp,
+                Instruction::request_acl(true, "deny 10.0.0.0/8".to_owned())
+            )
+            .await,
+            Kind::AclResponse(instruction::AclResponse { changed: true })
+        ));
+        assert!(matches!(
+            ask(&tmp, Instruction::request_acl(true, "nonsense".to_owned())).await,
+            Kind::AclResponse(instruction::AclResponse { changed: false })
+        ));
+        assert!(matches!(
+            ask(
+                &tmp,
+                Instruction::request_publish_name("@me/main".to_owned(), gistit.hash.clone())
+            )
+            .await,
+            Kind::PublishNameResponse(instruction::PublishNameResponse { published: true })
+        ));
+        assert!(matches!(
+            ask(&tmp, Instruction::request_resolve_name("@me/main".to_owned())).await,
+            Kind::ResolveNameResponse(instruction::ResolveNameResponse { hash: Some(hash), .. })
+                if hash == gistit.hash
+        ));
+
+        let pushed = gistit_core::new_gistit(
+            "lib.rs",
+            "rust",
+            "pub fn lib() {}".to_owned(),
+            "peer".to_owned(),
+            None,
+        );
+        let id = daemon.deliver(pushed.clone(), PEER_ID).await.unwrap();
+        let mut client = gistit_ipc::client(&tmp).unwrap();
+        client.connect_blocking().unwrap();
+        client.send(Instruction::request_inbox()).await.unwrap();
+        assert!(matches!(
+            client.recv().await.unwrap().expect_response().unwrap(),
+            Kind::InboxResponse(instruction::InboxResponse { items }) if items[0].id == id
+        ));
+        assert!(matches!(
+            ask(&tmp, Instruction::request_inbox_decision(id, true)).await,
+            Kind::InboxDecisionResponse(instruction::InboxDecisionResponse { hash: Some(_) })
+        ));
+        assert!(daemon.gistit(&pushed.hash).is_some());
+
+        let mut client = gistit_ipc::client(&tmp).unwrap();
+        client.connect_blocking().unwrap();
+        client.send(Instruction::request_shutdown()).await.unwrap();
+        tokio::task::yield_now().await;
+        while client.alive() {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(daemon.requests().len(), 10);
+    }
+}
@@ -0,0 +1,54 @@
+//
+//   ________.__          __  .__  __
+//  /  _____/|__| _______/  |_|__|/  |_
+// /   \  ___|  |/  ___/\   __\  \   __\
+// \    \_\  \  |\___ \  |  | |  ||  |
+//  \______  /__/____  > |__| |__||__|
+//         \/        \/
+//
+#![warn(clippy::all, clippy::pedantic, clippy::nursery, clippy::cargo)]
+#![allow(clippy::module_name_repetitions, clippy::multiple_crate_versions)]
+#![cfg_attr(
+    test,
+    allow(
+        unused,
+        clippy::all,
+        clippy::pedantic,
+        clippy::nursery,
+        clippy::dbg_macro,
+        clippy::unwrap_used,
+        clippy::missing_docs_in_private_items,
+    )
+)]
+//! Test doubles of the gistit server and `gistit-daemon`, so tests of gistit-cli and of tools built
+//! on gistit-core run offline and give the same results every time. [`MockServer`] answers the
+//! server endpoints over HTTP on a local port, [`FakeDaemon`] answers IPC instructions in a
+//! runtime directory. Both keep their gistits in memory and run on the tokio runtime they're
+//! started in, until they're dropped.
+
+use std::sync::{Mutex, MutexGuard};
+
+pub mod daemon;
+pub mod server;
+
+pub use daemon::FakeDaemon;
+pub use server::MockServer;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("io error {0}")]
+    IO(#[from] std::io::Error),
+
+    #[error("ipc error {0}")]
+    Ipc(#[from] gistit_ipc::Error),
+}
+
+/// Locks state shared with a serving task. A test that panicked while holding it doesn't poison it
+/// for the assertions that follow
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
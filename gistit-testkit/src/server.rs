@@ -0,0 +1,567 @@
+//! The server module
+//!
+//! [`MockServer`] speaks the endpoints of the gistit server (`server/functions`) that gistit-cli
+//! and gistit-core use, validating and answering with the same status codes:
+//!
+//! - `POST /load` stores the gistit in the body, answering with it without it's data. 400 if it's
+//!   invalid
+//! - `POST /get` answers with the gistit asked for. 404 if it isn't stored or it's private and the
+//!   owner token doesn't match, 409 if it's deleted once read and the request didn't agree to that
+//! - `POST /head` answers with it's metadata and `POST /exists` with 204 or 404
+//! - `POST /resolve` answers with the public hashes starting with a prefix
+//! - `GET /limits` and `GET /capabilities` answer like the bundled server
+//!
+//! Routes can be made to fail with [`MockServer::fail`], e.g. to test rate limiting.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+use gistit_proto::payload::gistit::Visibility;
+use gistit_proto::prost::Message;
+use gistit_proto::{Candidates, Gistit, Head};
+
+use crate::{lock, Result};
+
+const HASH_LENGTH: usize = 64;
+
+const AUTHOR_LENGTH: std::ops::RangeInclusive<usize> = 3..=50;
+
+const DESCRIPTION_LENGTH: std::ops::RangeInclusive<usize> = 10..=100;
+
+/// Bytes of a file
+const FILE_SIZE: std::ops::RangeInclusive<usize> = 20..=MAX_SIZE;
+
+const MAX_SIZE: usize = 50_000;
+
+const TOKEN_LENGTH: std::ops::RangeInclusive<usize> = 32..=128;
+
+const MAX_READS: std::ops::RangeInclusive<u32> = 1..=1000;
+
+/// Seconds
+const MIN_LIFESPAN: u64 = 60;
+
+const MAX_LIFESPAN: u64 = 7 * 24 * 60 * 60;
+
+const DEFAULT_LIFESPAN: u64 = 30 * 60;
+
+const MIN_PREFIX_LENGTH: usize = 4;
+
+const MAX_CANDIDATES: usize = 10;
+
+const FEATURES: [&str; 6] = [
+    "private",
+    "read-limit",
+    "revisions",
+    "comments",
+    "resolve",
+    "binary",
+];
+
+/// Request heads bigger than this are refused
+const MAX_HEAD_SIZE: usize = 8 * 1024;
+
+#[derive(Debug, Default)]
+struct State {
+    /// Stored as they were loaded, owner tokens included
+    gistits: HashMap<String, Gistit>,
+    /// `<method> <path>` of every request, in the order they came in
+    requests: Vec<String>,
+    /// Status lines routes fail with, by path
+    failures: HashMap<String, &'static str>,
+}
+
+/// The gistit server, listening on a local port
+#[derive(Debug)]
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    task: JoinHandle<()>,
+}
+
+impl MockServer {
+    /// Starts serving on a free local port, until it's dropped
+    ///
+    /// # Errors
+    ///
+    /// Fails if no local port can be bound
+    pub async fn start() -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(State::default()));
+
+        let serving = state.clone();
+        let task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let state = serving.clone();
+                tokio::spawn(async move {
+                    let _ = respond(stream, &state).await;
+                });
+            }
+        });
+
+        Ok(Self { addr, state, task })
+    }
+
+    /// Base url to send requests to, for `GISTIT_SERVER_URL` or `Destination::Server`
+    #[must_use]
+    pub fn url(&self) -> String {
+        format!("http://{}/", self.addr)
+    }
+
+    /// Stores `gistit` as if it was loaded, without validating it
+    pub fn insert(&self, mut gistit: Gistit) {
+        gistit.reads_left = gistit.max_reads;
+        lock(&self.state)
+            .gistits
+            .insert(gistit.hash.clone(), gistit);
+    }
+
+    /// The stored gistit `hash`, `None` once it's deleted after being read
+    #[must_use]
+    pub fn gistit(&self, hash: &str) -> Option<Gistit> {
+        lock(&self.state).gistits.get(hash).cloned()
+    }
+
+    /// `<method> <path>` of every request so far, e.g. `POST /load`
+    #[must_use]
+    pub fn requests(&self) -> Vec<String> {
+        lock(&self.state).requests.clone()
+    }
+
+    /// Answers requests to `path` with `status`, e.g. `429 Too Many Requests`, until
+    /// [`Self::recover`]
+    pub fn fail(&self, path: &str, status: &'static str) {
+        lock(&self.state).failures.insert(path.to_owned(), status);
+    }
+
+    /// Answers requests to `path` normally again
+    pub fn recover(&self, path: &str) {
+        lock(&self.state).failures.remove(path);
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    const fn empty(status: &'static str) -> Self {
+        Self {
+            status,
+            content_type: "text/plain",
+            body: Vec::new(),
+        }
+    }
+
+    fn protobuf(message: &impl Message) -> Self {
+        Self {
+            status: "200 OK",
+            content_type: "application/x-protobuf",
+            body: message.encode_to_vec(),
+        }
+    }
+
+    fn json(value: &serde_json::Value) -> Self {
+        Self {
+            status: "200 OK",
+            content_type: "application/json",
+            body: value.to_string().into_bytes(),
+        }
+    }
+}
+
+async fn respond(mut stream: TcpStream, state: &Mutex<State>) -> Result<()> {
+    let response = match read_request(&mut stream).await? {
+        Some((method, path, body)) => route(&method, &path, &body, &mut lock(state)),
+        None => Response::empty("400 Bad Request"),
+    };
+
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&response.body).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+fn route(method: &str, path: &str, body: &[u8], state: &mut State) -> Response {
+    state.requests.push(format!("{} {}", method, path));
+    if let Some(&status) = state.failures.get(path) {
+        return Response::empty(status);
+    }
+
+    let request = Gistit::from_bytes(body);
+    match (method, path, request) {
+        ("GET", "/limits", _) => Response::json(&limits()),
+        ("GET", "/capabilities", _) => {
+            let mut capabilities = limits();
+            capabilities["maxSize"] = MAX_SIZE.into();
+            capabilities["hashAlgorithms"] = serde_json::json!(["sha256"]);
+            capabilities["features"] = FEATURES.as_slice().into();
+            Response::json(&capabilities)
+        }
+        ("POST", "/load", Ok(gistit)) => load(gistit, state),
+        ("POST", "/resolve", Ok(Gistit { hash: prefix, .. })) => resolve(&prefix, state),
+        ("POST", "/get" | "/head" | "/exists", Ok(request))
+            if request.hash.len() != HASH_LENGTH =>
+        {
+            Response::empty("400 Bad Request")
+        }
+        ("POST", "/get", Ok(request)) => get(&request, state),
+        ("POST", "/head", Ok(request)) => match state.gistits.get(&request.hash) {
+            Some(gistit) if can_read(gistit, request.token.as_deref()) => {
+                Response::protobuf(&Head::from_gistit(gistit, None))
+            }
+            _ => Response::empty("404 Not Found"),
+        },
+        ("POST", "/exists", Ok(request)) => match state.gistits.get(&request.hash) {
+            Some(gistit)
+                if can_read(gistit, None)
+                    && !gistit.burn_after_read
+                    && gistit.reads_left.is_none() =>
+            {
+                Response::empty("204 No Content")
+            }
+            _ => Response::empty("404 Not Found"),
+        },
+        ("POST", "/load" | "/get" | "/head" | "/exists" | "/resolve", Err(_)) => {
+            Response::empty("400 Bad Request")
+        }
+        (
+            _,
+            "/load" | "/get" | "/head" | "/exists" | "/resolve" | "/limits" | "/capabilities",
+            _,
+        ) => Response::empty("405 Method Not Allowed"),
+        _ => Response::empty("404 Not Found"),
+    }
+}
+
+/// What `load` accepts, like the bundled server
+fn limits() -> serde_json::Value {
+    serde_json::json!({
+        "minLifespan": MIN_LIFESPAN,
+        "maxLifespan": MAX_LIFESPAN,
+        "defaultLifespan": DEFAULT_LIFESPAN,
+        "neverExpire": false,
+    })
+}
+
+fn load(mut gistit: Gistit, state: &mut State) -> Response {
+    if !is_valid(&gistit) {
+        return Response::empty("400 Bad Request");
+    }
+    gistit.reads_left = gistit.max_reads;
+
+    let mut response = Gistit {
+        token: None,
+        lifespan: None,
+        parent: None,
+        metadata: None,
+        reads_left: None,
+        ..gistit.clone()
+    };
+    for inner in &mut response.inner {
+        inner.data.clear();
+    }
+    state.gistits.insert(gistit.hash.clone(), gistit);
+    Response::protobuf(&response)
+}
+
+/// Whether `load` accepts `gistit`
+fn is_valid(gistit: &Gistit) -> bool {
+    let in_range = |value: Option<usize>, range: std::ops::RangeInclusive<usize>| {
+        value.map_or(true, |value| range.contains(&value))
+    };
+    let lifespan_ok = gistit.lifespan.map_or(true, |lifespan| {
+        (MIN_LIFESPAN..=MAX_LIFESPAN).contains(&lifespan)
+    });
+    let token_ok = gistit.visibility != Visibility::Private as i32
+        || in_range(
+            Some(gistit.token.as_ref().map_or(0, String::len)),
+            TOKEN_LENGTH,
+        );
+
+    gistit.hash.len() == HASH_LENGTH
+        && (gistit.author.is_empty() || AUTHOR_LENGTH.contains(&gistit.author.len()))
+        && in_range(
+            gistit.description.as_ref().map(String::len),
+            DESCRIPTION_LENGTH,
+        )
+        && gistit
+            .inner
+            .first()
+            .map_or(false, |inner| FILE_SIZE.contains(&inner.data.len()))
+        && lifespan_ok
+        && Visibility::from_i32(gistit.visibility).is_some()
+        && token_ok
+        && gistit
+            .max_reads
+            .map_or(true, |max_reads| MAX_READS.contains(&max_reads))
+        && in_range(
+            gistit.parent.as_ref().map(String::len),
+            HASH_LENGTH..=HASH_LENGTH,
+        )
+}
+
+/// Private gistits are only read with their owner token
+fn can_read(gistit: &Gistit, token: Option<&str>) -> bool {
+    gistit.visibility != Visibility::Private as i32
+        || (gistit.token.is_some() && gistit.token.as_deref() == token)
+}
+
+/// Reads a gistit, deleting it if it burns after reading or it was it's last read
+fn get(request: &Gistit, state: &mut State) -> Response {
+    let gistit = match state.gistits.get_mut(&request.hash) {
+        Some(gistit) if can_read(gistit, request.token.as_deref()) => gistit,
+        _ => return Response::empty("404 Not Found"),
+    };
+
+    let burn = if gistit.burn_after_read {
+        if !request.burn_after_read {
+            return Response::empty("409 Conflict");
+        }
+        true
+    } else if let Some(reads_left) = gistit.reads_left {
+        gistit.reads_left = Some(reads_left.saturating_sub(1));
+        reads_left <= 1
+    } else {
+        false
+    };
+
+    let found = Gistit {
+        token: None,
+        ..gistit.clone()
+    };
+    if burn {
+        state.gistits.remove(&request.hash);
+    }
+    Response::protobuf(&found)
+}
+
+fn resolve(prefix: &str, state: &State) -> Response {
+    if !(MIN_PREFIX_LENGTH..HASH_LENGTH).contains(&prefix.len()) {
+        return Response::empty("400 Bad Request");
+    }
+
+    let mut hashes: Vec<String> = state
+        .gistits
+        .values()
+        .filter(|gistit| {
+            gistit.visibility == Visibility::Public as i32 && gistit.hash.starts_with(prefix)
+        })
+        .map(|gistit| gistit.hash.clone())
+        .collect();
+    if hashes.is_empty() {
+        return Response::empty("404 Not Found");
+    }
+    hashes.sort();
+    hashes.truncate(MAX_CANDIDATES);
+    Response::protobuf(&Candidates { hashes })
+}
+
+/// Reads the request line and body, `None` if the request is malformed or too big
+async fn read_request(stream: &mut TcpStream) -> Result<Option<(String, String, Vec<u8>)>> {
+    let mut buf = Vec::with_capacity(1024);
+    let mut chunk = [0_u8; 1024];
+
+    let head_end = loop {
+        if let Some(end) = find_head_end(&buf) {
+            break end;
+        }
+        if buf.len() > MAX_HEAD_SIZE {
+            return Ok(None);
+        }
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            return Ok(None);
+        }
+        buf.extend_from_slice(&chunk[..read]);
+    };
+
+    let head = match std::str::from_utf8(&buf[..head_end]) {
+        Ok(head) => head,
+        Err(_) => return Ok(None),
+    };
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let (method, path) = match (request_line.next(), request_line.next()) {
+        // The query is left out, no endpoint takes one
+        (Some(method), Some(path)) => (
+            method.to_owned(),
+            path.split('?').next().unwrap_or_default().to_owned(),
+        ),
+        _ => return Ok(None),
+    };
+    let content_length = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .map_or(Some(0), |(_, value)| value.trim().parse::<usize>().ok());
+
+    let content_length = match content_length {
+        Some(length) if length <= MAX_SIZE * 4 => length,
+        _ => return Ok(None),
+    };
+
+    let mut body = buf.split_off(head_end + 4);
+    while body.len() < content_length {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            return Ok(None);
+        }
+        body.extend_from_slice(&chunk[..read]);
+    }
+    body.truncate(content_length);
+
+    Ok(Some((method, path, body)))
+}
+
+fn find_head_end(buf: &[u8]) -> Option<usize> {
+    buf.windows(4).position(|window| window == b"\r\n\r\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gistit_core::fetch::{fetch_server, head_server, resolve_server};
+    use gistit_core::send::{exists_server, send_server};
+    use gistit_core::{new_gistit, Error};
+    use url::Url;
+
+    fn test_gistit() -> Gistit {
+        new_gistit(
+            "main.rs",
+            "rust",
+            "fn main() { println!(\"hello\") }".to_owned(),
+            "author".to_owned(),
+            None,
+        )
+    }
+
+    #[tokio::test]
+    async fn server_load_and_get() {
+        let server = MockServer::start().await.unwrap();
+        let url = Url::parse(&server.url()).unwrap();
+        let gistit = test_gistit();
+
+        assert_eq!(send_server(&url, &gistit).await.unwrap(), gistit.hash);
+        assert_eq!(server.gistit(&gistit.hash).unwrap(), gistit);
+        assert!(exists_server(&url, &gistit.hash).await.unwrap());
+
+        let fetched = fetch_server(&url, &gistit.hash, None, false).await.unwrap();
+        assert_eq!(fetched.unwrap(), gistit);
+        let head = head_server(&url, &gistit.hash, None).await.unwrap();
+        assert_eq!(head.unwrap(), Head::from_gistit(&gistit, None));
+        assert_eq!(
+            resolve_server(&url, &gistit.hash[..7]).await.unwrap(),
+            vec![gistit.hash.clone()]
+        );
+
+        let missing = "0".repeat(HASH_LENGTH);
+        assert_eq!(
+            fetch_server(&url, &missing, None, false).await.unwrap(),
+            None
+        );
+        assert!(!exists_server(&url, &missing).await.unwrap());
+
+        let mut tiny = test_gistit();
+        tiny.inner[0].data = "tiny".to_owned();
+        assert!(send_server(&url, &tiny).await.is_err());
+
+        assert_eq!(
+            server.requests(),
+            [
+                "POST /load",
+                "POST /exists",
+                "POST /get",
+                "POST /head",
+                "POST /resolve",
+                "POST /get",
+                "POST /exists",
+                "POST /load",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn server_read_once() {
+        let server = MockServer::start().await.unwrap();
+        let url = Url::parse(&server.url()).unwrap();
+
+        let mut burnt = test_gistit();
+        burnt.burn_after_read = true;
+        server.insert(burnt.clone());
+        assert!(matches!(
+            fetch_server(&url, &burnt.hash, None, false).await,
+            Err(Error::BurnAfterRead)
+        ));
+        assert!(fetch_server(&url, &burnt.hash, None, true)
+            .await
+            .unwrap()
+            .is_some());
+        assert_eq!(server.gistit(&burnt.hash), None);
+
+        let mut private = test_gistit();
+        private.visibility = Visibility::Private as i32;
+        private.token = Some("t".repeat(32));
+        send_server(&url, &private).await.unwrap();
+        assert_eq!(
+            fetch_server(&url, &private.hash, None, false)
+                .await
+                .unwrap(),
+            None
+        );
+        let fetched = fetch_server(&url, &private.hash, Some(&"t".repeat(32)), false)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(fetched.token, None);
+    }
+
+    #[tokio::test]
+    async fn server_failures_and_limits() {
+        let server = MockServer::start().await.unwrap();
+        let url = Url::parse(&server.url()).unwrap();
+
+        server.fail("/load", "429 Too Many Requests");
+        assert!(matches!(
+            send_server(&url, &test_gistit()).await,
+            Err(Error::RateLimited(_))
+        ));
+        server.recover("/load");
+        assert!(send_server(&url, &test_gistit()).await.is_ok());
+
+        let capabilities = get_json(&url.join("capabilities").unwrap()).await;
+        assert_eq!(capabilities["maxSize"], MAX_SIZE);
+        assert_eq!(capabilities["minLifespan"], MIN_LIFESPAN);
+        assert_eq!(
+            capabilities["features"].as_array().unwrap().len(),
+            FEATURES.len()
+        );
+    }
+
+    async fn get_json(url: &Url) -> serde_json::Value {
+        let response = gistit_core::http_client()
+            .get(url.clone())
+            .send()
+            .await
+            .unwrap();
+        serde_json::from_str(&response.text().await.unwrap()).unwrap()
+    }
+}
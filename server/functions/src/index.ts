@@ -1,5 +1,6 @@
 import * as functions from "firebase-functions";
 import * as admin from "firebase-admin";
+import { createHash, timingSafeEqual } from "crypto";
 import protobuf from "protobufjs";
 
 export { auth, token, tokenScheduledCleanup } from "./auth";
@@ -24,6 +25,49 @@ const GISTIT_DESCRIPTION_MIN_CHAR_LENGTH = 10;
 const GISTIT_FILE_MAX_SIZE = 50_000_000; // 50kb
 const GISTIT_FILE_MIN_SIZE = 20; // 20 bytes
 
+const GISTIT_MIN_LIFESPAN = 60; // 1 minute
+// Self-hosted backends can keep gistits longer with `GISTIT_MAX_LIFESPAN`, in seconds, and forever
+// with `GISTIT_NEVER_EXPIRE=true`
+const GISTIT_MAX_LIFESPAN =
+  Number(process.env.GISTIT_MAX_LIFESPAN) || 7 * 24 * 60 * 60; // 7 days
+const GISTIT_NEVER_EXPIRE = process.env.GISTIT_NEVER_EXPIRE === "true";
+const GISTIT_DEFAULT_LIFESPAN = 30 * 60; // 30 minutes, see `reserved.ts`
+// Lifespan of gistits kept forever
+const LIFESPAN_NEVER = 0;
+
+const GISTIT_TOKEN_MIN_CHAR_LENGTH = 32;
+const GISTIT_TOKEN_MAX_CHAR_LENGTH = 128;
+
+const GISTIT_MAX_READS = 1000;
+
+// Revisions answered by `history`, older ones are left out
+const GISTIT_MAX_REVISIONS = 50;
+
+const GISTIT_COMMENT_MAX_CHAR_LENGTH = 500;
+// Comments answered by `comments`, older ones are left out
+const GISTIT_MAX_COMMENTS = 200;
+
+// Short hashes answered by `resolve`, like git short SHAs
+const GISTIT_MIN_PREFIX_LENGTH = 4;
+// Candidates answered by `resolve`, enough to tell a prefix is ambiguous
+const GISTIT_MAX_CANDIDATES = 10;
+
+// Algorithms gistit hashes are accepted in, sha256 of the data, author and description
+const GISTIT_HASH_ALGORITHMS = ["sha256"];
+// Optional features, clients check the ones they need before sending or fetching anything
+const GISTIT_FEATURES = [
+  "private",
+  "read-limit",
+  "revisions",
+  "comments",
+  "resolve",
+  "binary",
+];
+
+// `Gistit.Visibility`
+const VISIBILITY_PUBLIC = 0;
+const VISIBILITY_PRIVATE = 2;
+
 export type GistitPayload = {
   hash: string;
   author: string;
@@ -34,9 +78,109 @@ export type GistitPayload = {
     lang: string;
     data: string;
     size: number;
+    encrypted?: boolean;
+    // `data` is base64 encoded bytes
+    binary?: boolean;
   }[];
+  // Seconds
+  lifespan?: number;
+  visibility?: number;
+  // Owner token of private gistits, only it's hash is stored
+  token?: string;
+  tokenHash?: string;
+  // Deleted after the first successful `get`, which must set it too to agree to that
+  burnAfterRead?: boolean;
+  // Deleted after this many successful reads, only what's left of them is stored
+  maxReads?: number;
+  readsLeft?: number;
+  // Hash of the revision it replaces
+  parent?: string;
+  // Author, description and file names sealed with the secret, opaque to the server
+  metadata?: string;
+};
+
+export type CommentPayload = {
+  hash: string;
+  author: string;
+  body: string;
+  timestamp: string;
+  token?: string;
+};
+
+const hashToken = (token: string) =>
+  createHash("sha256").update(token).digest("hex");
+
+// Private gistits are only readable with their owner token, anything else reads as missing
+const canRead = (gistit: GistitPayload, token?: string | null) => {
+  if (gistit.visibility !== VISIBILITY_PRIVATE) return true;
+  if (!token || !gistit.tokenHash) return false;
+
+  const expected = Buffer.from(gistit.tokenHash, "hex");
+  const actual = Buffer.from(hashToken(token), "hex");
+  return timingSafeEqual(expected, actual);
+};
+
+// Reads a gistit, deleting it in the same transaction if it burns after reading so it's only ever
+// answered once, or counting the read down if it has a read limit. Answers the status code to fail
+// with instead: 404 if it can't be read, 409 if it burns after reading and `burn` doesn't agree to
+// that
+const readOnce = async (
+  hash: string,
+  token: string | null | undefined,
+  burn: boolean
+): Promise<GistitPayload | number> => {
+  const gistitRef = db.collection("gistits").doc(hash);
+
+  return db.runTransaction(async (tx) => {
+    const snapshot = await tx.get(gistitRef);
+    const gistit = snapshot.data() as GistitPayload | undefined;
+
+    if (!gistit || !canRead(gistit, token)) return 404;
+    if (gistit.burnAfterRead) {
+      if (!burn) return 409;
+      tx.delete(gistitRef);
+      tx.delete(db.collection("reserved").doc(hash));
+    } else if (gistit.readsLeft !== undefined) {
+      const readsLeft = gistit.readsLeft - 1;
+      if (readsLeft > 0) {
+        tx.update(gistitRef, { readsLeft });
+      } else {
+        tx.delete(gistitRef);
+        tx.delete(db.collection("reserved").doc(hash));
+      }
+      return { ...gistit, readsLeft };
+    }
+    return gistit;
+  });
+};
+
+// Lifespans `load` accepts
+const GISTIT_LIMITS = {
+  minLifespan: GISTIT_MIN_LIFESPAN,
+  maxLifespan: GISTIT_MAX_LIFESPAN,
+  defaultLifespan: GISTIT_DEFAULT_LIFESPAN,
+  neverExpire: GISTIT_NEVER_EXPIRE,
+};
+
+// Everything clients can check upfront, `GISTIT_LIMITS` included
+const GISTIT_CAPABILITIES = {
+  ...GISTIT_LIMITS,
+  maxSize: GISTIT_FILE_MAX_SIZE,
+  hashAlgorithms: GISTIT_HASH_ALGORITHMS,
+  features: GISTIT_FEATURES,
 };
 
+// Only asked by clients from before `capabilities`
+export const limits = functions.https.onRequest(async (_req, res) => {
+  res.setHeader("Access-Control-Allow-Origin", "*");
+  res.status(200).json(GISTIT_LIMITS);
+});
+
+export const capabilities = functions.https.onRequest(async (_req, res) => {
+  res.setHeader("Access-Control-Allow-Origin", "*");
+  res.status(200).json(GISTIT_CAPABILITIES);
+});
+
 export const load = functions.https.onRequest(async (req, res) => {
   const proto = await protobuf.load("payload.proto");
   const Gistit = proto.lookupType("gistit.payload.Gistit");
@@ -48,7 +192,14 @@ export const load = functions.https.onRequest(async (req, res) => {
       author,
       description,
       timestamp,
-      inner: [{ name, lang, size, data }],
+      inner: [{ name, lang, size, data, encrypted, binary }],
+      lifespan,
+      visibility = VISIBILITY_PUBLIC,
+      token,
+      burnAfterRead,
+      maxReads,
+      parent,
+      metadata,
     } = payload as unknown as GistitPayload;
     functions.logger.log(payload);
 
@@ -78,6 +229,42 @@ export const load = functions.https.onRequest(async (req, res) => {
       throw Error("File size is not allowed");
     }
 
+    // uint64 may be decoded as a `Long`
+    const lifespanSecs =
+      lifespan !== undefined && lifespan !== null ? Number(lifespan) : undefined;
+    if (lifespanSecs === LIFESPAN_NEVER && !GISTIT_NEVER_EXPIRE) {
+      throw Error("Gistits can't be kept forever");
+    }
+    if (
+      lifespanSecs &&
+      (lifespanSecs > GISTIT_MAX_LIFESPAN || lifespanSecs < GISTIT_MIN_LIFESPAN)
+    ) {
+      throw Error("Invalid lifespan");
+    }
+
+    if (visibility < VISIBILITY_PUBLIC || visibility > VISIBILITY_PRIVATE)
+      throw Error("Invalid visibility");
+
+    if (
+      visibility === VISIBILITY_PRIVATE &&
+      (!token ||
+        token.length < GISTIT_TOKEN_MIN_CHAR_LENGTH ||
+        token.length > GISTIT_TOKEN_MAX_CHAR_LENGTH)
+    ) {
+      throw Error("Private gistits need an owner token");
+    }
+
+    if (
+      maxReads !== undefined &&
+      maxReads !== null &&
+      (maxReads < 1 || maxReads > GISTIT_MAX_READS)
+    ) {
+      throw Error("Invalid max reads");
+    }
+
+    if (parent && parent.length !== GISTIT_HASH_LENGTH)
+      throw Error("Invalid parent hash format");
+
     await db
       .collection("gistits")
       .doc(hash)
@@ -85,7 +272,18 @@ export const load = functions.https.onRequest(async (req, res) => {
         author,
         description,
         timestamp: timestamp.toString(),
-        inner: [{ name, lang, data, size }],
+        inner: [
+          { name, lang, data, size, encrypted: !!encrypted, binary: !!binary },
+        ],
+        ...(lifespanSecs !== undefined ? { lifespan: lifespanSecs } : {}),
+        visibility,
+        ...(visibility === VISIBILITY_PRIVATE && token
+          ? { tokenHash: hashToken(token) }
+          : {}),
+        burnAfterRead: !!burnAfterRead,
+        ...(maxReads ? { readsLeft: maxReads } : {}),
+        ...(parent ? { parent } : {}),
+        ...(metadata ? { metadata } : {}),
       });
 
     functions.logger.info("added gistit: ", hash);
@@ -95,6 +293,9 @@ export const load = functions.https.onRequest(async (req, res) => {
       description,
       timestamp,
       inner: [{ name, lang, data: "", size }],
+      visibility,
+      burnAfterRead,
+      maxReads,
     }).finish();
 
     res.send(response);
@@ -129,25 +330,407 @@ export const get = functions.https.onRequest(async (req, res) => {
   const payload = Gistit.decode(data);
 
   try {
-    const { hash } = payload as unknown as GistitPayload;
+    const { hash, token, burnAfterRead } = payload as unknown as GistitPayload;
 
     functions.logger.debug(hash);
 
     if (hash?.length !== GISTIT_HASH_LENGTH)
       throw Error("Invalid gistit hash format");
 
-    const gistitRef = await db.collection("gistits").doc(hash).get();
+    const found = await readOnce(hash, token, !!burnAfterRead);
+    if (typeof found === "number") {
+      res.status(found).end();
+      return;
+    }
+
+    const { tokenHash, ...gistit } = found;
+    const response = Gistit.encode({ ...gistit, hash }).finish();
+    res.status(200).send(response);
+  } catch (err) {
+    res.status(400).end();
+  }
+});
+
+// Metadata of a stored gistit, as a `Head`. `removeAt` is null for gistits kept forever
+const toHead = (hash: string, gistit: GistitPayload, removeAt?: number | null) => {
+  const {
+    author,
+    description,
+    timestamp,
+    inner: [{ name, lang, size, encrypted }],
+    burnAfterRead,
+    readsLeft,
+    parent,
+  } = gistit;
+
+  return {
+    hash,
+    author,
+    description,
+    timestamp,
+    name,
+    lang,
+    size,
+    encrypted,
+    expiresAt: removeAt ?? undefined,
+    burnAfterRead,
+    readsLeft,
+    parent,
+  };
+};
+
+// Metadata of a gistit without it's content, for `gistit fetch --head`. Same request as `get`
+export const head = functions.https.onRequest(async (req, res) => {
+  res.setHeader("Access-Control-Allow-Origin", "*");
+
+  const proto = await protobuf.load("payload.proto");
+  const Gistit = proto.lookupType("gistit.payload.Gistit");
+  const Head = proto.lookupType("gistit.payload.Head");
+
+  try {
+    const { hash, token } = Gistit.decode(
+      req.body
+    ) as unknown as GistitPayload;
 
-    if (!gistitRef.exists) {
+    if (hash?.length !== GISTIT_HASH_LENGTH)
+      throw Error("Invalid gistit hash format");
+
+    const [gistitRef, reservedRef] = await Promise.all([
+      db.collection("gistits").doc(hash).get(),
+      db.collection("reserved").doc(hash).get(),
+    ]);
+
+    const gistit = gistitRef.data() as GistitPayload;
+    if (!gistitRef.exists || !canRead(gistit, token)) {
       res.status(404).end();
       return;
     }
 
-    const gistit = gistitRef.data();
-    console.log(gistit);
-    const response = Gistit.encode({ ...gistit, hash }).finish();
+    const response = Head.encode(
+      toHead(hash, gistit, reservedRef.data()?.removeAt)
+    ).finish();
+
     res.status(200).send(response);
   } catch (err) {
     res.status(400).end();
   }
 });
+
+// Whether a gistit can be linked to instead of being sent again, for `gistit` to skip the upload.
+// Same request as `head`. Private gistits and ones deleted once read are never shared, 404
+export const exists = functions.https.onRequest(async (req, res) => {
+  const proto = await protobuf.load("payload.proto");
+  const Gistit = proto.lookupType("gistit.payload.Gistit");
+
+  try {
+    const { hash } = Gistit.decode(req.body) as unknown as GistitPayload;
+
+    if (hash?.length !== GISTIT_HASH_LENGTH)
+      throw Error("Invalid gistit hash format");
+
+    const gistitRef = await db.collection("gistits").doc(hash).get();
+    const gistit = gistitRef.data() as GistitPayload;
+    if (
+      !gistitRef.exists ||
+      !canRead(gistit) ||
+      gistit.burnAfterRead ||
+      gistit.readsLeft
+    ) {
+      res.status(404).end();
+      return;
+    }
+
+    res.status(204).end();
+  } catch (err) {
+    res.status(400).end();
+  }
+});
+
+// Hashes of public gistits starting with a prefix, for `gistit fetch` to expand a short hash.
+// Same request as `head` with the prefix as the hash. Unlisted and private gistits are never
+// listed, their hash is what keeps them out of sight. 404 if none starts with it
+export const resolve = functions.https.onRequest(async (req, res) => {
+  const proto = await protobuf.load("payload.proto");
+  const Gistit = proto.lookupType("gistit.payload.Gistit");
+  const Candidates = proto.lookupType("gistit.payload.Candidates");
+
+  try {
+    const { hash: prefix } = Gistit.decode(
+      req.body
+    ) as unknown as GistitPayload;
+
+    if (
+      !prefix ||
+      prefix.length < GISTIT_MIN_PREFIX_LENGTH ||
+      prefix.length >= GISTIT_HASH_LENGTH ||
+      !/^[0-9a-f]+$/i.test(prefix)
+    )
+      throw Error("Invalid gistit hash prefix format");
+
+    const id = admin.firestore.FieldPath.documentId();
+    const matching = await db
+      .collection("gistits")
+      .where(id, ">=", prefix)
+      .where(id, "<", prefix + "\uf8ff")
+      .select("visibility")
+      .limit(GISTIT_MAX_CANDIDATES)
+      .get();
+    const hashes = matching.docs
+      .filter(
+        (doc) =>
+          (doc.data().visibility ?? VISIBILITY_PUBLIC) === VISIBILITY_PUBLIC
+      )
+      .map((doc) => doc.id);
+
+    if (hashes.length === 0) {
+      res.status(404).end();
+      return;
+    }
+    res.status(200).send(Candidates.encode({ hashes }).finish());
+  } catch (err) {
+    res.status(400).end();
+  }
+});
+
+// Revisions of a gistit, following `parent` from the one asked for, newest first, for
+// `gistit history`. Same request as `head`, a revision the token can't read ends the history
+export const history = functions.https.onRequest(async (req, res) => {
+  res.setHeader("Access-Control-Allow-Origin", "*");
+
+  const proto = await protobuf.load("payload.proto");
+  const Gistit = proto.lookupType("gistit.payload.Gistit");
+  const History = proto.lookupType("gistit.payload.History");
+
+  try {
+    const { hash, token } = Gistit.decode(
+      req.body
+    ) as unknown as GistitPayload;
+
+    if (hash?.length !== GISTIT_HASH_LENGTH)
+      throw Error("Invalid gistit hash format");
+
+    const revisions = [];
+    const seen = new Set<string>();
+    let next: string | undefined = hash;
+    while (
+      next &&
+      !seen.has(next) &&
+      revisions.length < GISTIT_MAX_REVISIONS
+    ) {
+      seen.add(next);
+      const [gistitRef, reservedRef] = await Promise.all([
+        db.collection("gistits").doc(next).get(),
+        db.collection("reserved").doc(next).get(),
+      ]);
+
+      const gistit = gistitRef.data() as GistitPayload | undefined;
+      if (!gistit || !canRead(gistit, token)) break;
+
+      revisions.push(toHead(next, gistit, reservedRef.data()?.removeAt));
+      next = gistit.parent;
+    }
+
+    if (revisions.length === 0) {
+      res.status(404).end();
+      return;
+    }
+    res.status(200).send(History.encode({ revisions }).finish());
+  } catch (err) {
+    res.status(400).end();
+  }
+});
+
+// Adds a comment to the thread of a gistit, kept in it's `comments` subcollection. Comments on
+// private gistits need their owner token, the timestamp is set here
+export const comment = functions.https.onRequest(async (req, res) => {
+  res.setHeader("Access-Control-Allow-Origin", "*");
+
+  const proto = await protobuf.load("payload.proto");
+  const Comment = proto.lookupType("gistit.payload.Comment");
+
+  try {
+    const { hash, author, body, token } = Comment.decode(
+      req.body
+    ) as unknown as CommentPayload;
+
+    if (hash?.length !== GISTIT_HASH_LENGTH)
+      throw Error("Invalid gistit hash format");
+
+    if (
+      author?.length > GISTIT_AUTHOR_MAX_CHAR_LENGTH ||
+      author?.length < GISTIT_AUTHOR_MIN_CHAR_LENGTH
+    )
+      throw Error("Invalid comment author");
+
+    if (!body?.trim() || body.length > GISTIT_COMMENT_MAX_CHAR_LENGTH)
+      throw Error("Invalid comment body");
+
+    const gistitRef = db.collection("gistits").doc(hash);
+    const gistit = (await gistitRef.get()).data() as GistitPayload | undefined;
+    if (!gistit || !canRead(gistit, token)) {
+      res.status(404).end();
+      return;
+    }
+
+    await gistitRef.collection("comments").add({
+      author,
+      body,
+      timestamp: Date.now().toString(),
+    });
+    res.status(200).end();
+  } catch (err) {
+    res.status(400).end();
+  }
+});
+
+// Comment thread of a gistit, oldest first, for `gistit comment` and `gistit fetch --comments`.
+// Same request as `head`
+export const comments = functions.https.onRequest(async (req, res) => {
+  res.setHeader("Access-Control-Allow-Origin", "*");
+
+  const proto = await protobuf.load("payload.proto");
+  const Gistit = proto.lookupType("gistit.payload.Gistit");
+  const Comments = proto.lookupType("gistit.payload.Comments");
+
+  try {
+    const { hash, token } = Gistit.decode(
+      req.body
+    ) as unknown as GistitPayload;
+
+    if (hash?.length !== GISTIT_HASH_LENGTH)
+      throw Error("Invalid gistit hash format");
+
+    const gistitRef = db.collection("gistits").doc(hash);
+    const gistit = (await gistitRef.get()).data() as GistitPayload | undefined;
+    if (!gistit || !canRead(gistit, token)) {
+      res.status(404).end();
+      return;
+    }
+
+    const thread = await gistitRef
+      .collection("comments")
+      .orderBy("timestamp", "desc")
+      .limit(GISTIT_MAX_COMMENTS)
+      .get();
+    const comments = thread.docs
+      .map((doc) => ({ ...(doc.data() as CommentPayload), hash }))
+      .reverse();
+
+    res.status(200).send(Comments.encode({ comments }).finish());
+  } catch (err) {
+    res.status(400).end();
+  }
+});
+
+// Plain text content of a gistit, for `curl .../raw/<hash> | sh` style consumption, the bytes of
+// binary ones. Private
+// gistits need their owner token as `?token=<token>`, gistits deleted once read `?burn=true`.
+// Gistits with a read limit answer what's left of it in `X-Gistit-Reads-Left`
+export const raw = functions.https.onRequest(async (req, res) => {
+  res.setHeader("Access-Control-Allow-Origin", "*");
+
+  if (req.method !== "GET") {
+    res.status(405).end();
+    return;
+  }
+
+  const hash = req.path.replace(/^\/+/, "");
+  if (hash.length !== GISTIT_HASH_LENGTH) {
+    res.status(400).end();
+    return;
+  }
+
+  try {
+    const token = typeof req.query.token === "string" ? req.query.token : null;
+    const found = await readOnce(hash, token, req.query.burn === "true");
+    if (typeof found === "number") {
+      res.status(found).end();
+      return;
+    }
+
+    const { inner, readsLeft } = found;
+    if (readsLeft !== undefined) {
+      res.setHeader("X-Gistit-Reads-Left", readsLeft.toString());
+    }
+    if (inner[0]?.binary && !inner[0]?.encrypted) {
+      res
+        .status(200)
+        .setHeader("Content-Type", "application/octet-stream")
+        .send(Buffer.from(inner[0].data, "base64"));
+      return;
+    }
+    res
+      .status(200)
+      .setHeader("Content-Type", "text/plain; charset=utf-8")
+      .send(inner[0]?.data ?? "");
+  } catch (err) {
+    functions.logger.error(err);
+    res.status(500).end();
+  }
+});
+
+// Keys of the counts `telemetry` accepts, command names, error codes and OSes
+const TELEMETRY_KEY = /^[a-z][a-z0-9-]{0,31}$/;
+const TELEMETRY_VERSION = /^[0-9a-z.+-]{1,32}$/i;
+const TELEMETRY_MAX_KEYS = 64;
+
+type TelemetryReport = {
+  version: string;
+  os: string;
+  commands: Record<string, number>;
+  errors: Record<string, number>;
+};
+
+const increments = (entries: Record<string, number> = {}) =>
+  Object.fromEntries(
+    Object.entries(entries)
+      .slice(0, TELEMETRY_MAX_KEYS)
+      .filter(
+        ([key, count]) =>
+          TELEMETRY_KEY.test(key) && Number.isInteger(count) && count > 0
+      )
+      .map(([key, count]) => [
+        key,
+        admin.firestore.FieldValue.increment(count),
+      ])
+  );
+
+// Anonymous usage counts sent by clients that opted in, added up per day. Nothing identifying the
+// client is stored
+export const telemetry = functions.https.onRequest(async (req, res) => {
+  if (req.method !== "POST") {
+    res.status(405).end();
+    return;
+  }
+
+  const { version, os, commands, errors } = req.body as TelemetryReport;
+  if (
+    !TELEMETRY_KEY.test(os ?? "") ||
+    !TELEMETRY_VERSION.test(version ?? "")
+  ) {
+    res.status(400).end();
+    return;
+  }
+
+  try {
+    const one = admin.firestore.FieldValue.increment(1);
+    const day = new Date().toISOString().slice(0, 10);
+    await db
+      .collection("telemetry")
+      .doc(day)
+      .set(
+        {
+          commands: increments(commands),
+          errors: increments(errors),
+          os: { [os]: one },
+          // Dots would nest
+          versions: { [version.replace(/\./g, "_")]: one },
+        },
+        { merge: true }
+      );
+    res.status(204).end();
+  } catch (err) {
+    functions.logger.error(err);
+    res.status(500).end();
+  }
+});
@@ -9,30 +9,47 @@ interface onChangeContext extends functions.EventContext {
   };
 }
 
+// Lifespan of gistits sent without one, in milliseconds
+const DEFAULT_LIFESPAN = 300 * 60 * 100;
+
+// When a gistit sent with `lifespan`, in seconds, is removed. Never if it's 0, which `load` only
+// accepts on servers that allow it. A null `removeAt` is never matched by the cleanup
+const removeAt = (lifespan: number | undefined) => {
+  if (lifespan === 0) return null;
+  return Date.now() + (lifespan ? lifespan * 1000 : DEFAULT_LIFESPAN);
+};
+
 export const createReservedData = functions.firestore
   .document("gistits/{hash}")
-  .onCreate(async (_, context) => {
+  .onCreate(async (snapshot, context) => {
     const hash = (context as onChangeContext).params.hash;
+    // Validated by `load`, in seconds
+    const lifespan: number | undefined = snapshot.data()?.lifespan;
 
     return db
       .collection("reserved")
       .doc(hash)
       .set({
-        removeAt: Date.now() + 300 * 60 * 100,
+        removeAt: removeAt(lifespan),
         reuploaded: 0,
       });
   });
 
 export const updateReservedData = functions.firestore
   .document("gistits/{hash}")
-  .onUpdate(async (_, context) => {
+  .onUpdate(async (change, context) => {
     const hash = (context as onChangeContext).params.hash;
+    // Counting reads down isn't a reupload
+    if (change.before.data()?.timestamp === change.after.data()?.timestamp) {
+      return null;
+    }
+    const lifespan: number | undefined = change.after.data()?.lifespan;
 
     return db
       .collection("reserved")
       .doc(hash)
       .update({
-        removeAt: Date.now() + 300 * 60 * 100,
+        removeAt: removeAt(lifespan),
         reuploaded: admin.firestore.FieldValue.increment(1),
       });
   });
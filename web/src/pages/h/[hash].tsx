@@ -14,7 +14,10 @@ export type GistitPayload = {
     lang: string
     data: string
     size: number
+    encrypted?: boolean
+    binary?: boolean
   }[]
+  metadata?: string
 }
 
 const SnippetPage = () => {
@@ -60,6 +63,14 @@ const SnippetPage = () => {
           <span className="flex items-center justify-center w-full mb-12 font-light">
             Gistit not found
           </span>
+        ) : gistit && (gistit.metadata || gistit.inner[0]?.encrypted) ? (
+          <span className="flex items-center justify-center w-full mb-12 font-light">
+            Encrypted gistit, open it with `gistit fetch`
+          </span>
+        ) : gistit && gistit.inner[0]?.binary ? (
+          <span className="flex items-center justify-center w-full mb-12 font-light">
+            Binary gistit, save it with `gistit fetch --save`
+          </span>
         ) : gistit ? (
           <>
             <h1 className="text-xl font-bold text-blue-500">{gistit.author}</h1>